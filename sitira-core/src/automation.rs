@@ -0,0 +1,194 @@
+use crate::mapping::{Parameter, ParameterValues, PARAMETER_COUNT};
+
+/// Change of a parameter within one control cycle which touches it while recording
+const TOUCH_THRESHOLD: f32 = 0.01;
+/// Change of the blend per menu step
+const BLEND_STEP: f32 = 0.1;
+
+/// Movements of the knobs, recorded into a lane per parameter which loops along with the take.
+///
+/// The lanes hold one value per control cycle and are as long as the take. While recording, a
+/// parameter whose knob gets turned is touched and writes its knob value into its lane until the
+/// recording stops, the other lanes stay as they are. The lanes which hold a recording play over
+/// the knob values, as far as the blend sets. They start over whenever a take ends.
+pub struct Automation {
+    /// Values of all parameters, one control cycle after the other
+    lanes: &'static mut [f32],
+    length: usize,
+    position: usize,
+    recording: bool,
+    /// Parameters which write into their lane, one bit each
+    touched: u32,
+    /// Parameters whose lane holds a recording, one bit each
+    automated: u32,
+    blend: f32,
+    last: Option<ParameterValues>,
+    take_running: bool,
+}
+
+impl Automation {
+    pub fn new(lanes: &'static mut [f32]) -> Self {
+        Automation {
+            lanes,
+            length: 1,
+            position: 0,
+            recording: false,
+            touched: 0,
+            automated: 0,
+            blend: 1.0,
+            last: None,
+            take_running: false,
+        }
+    }
+
+    /// Sets the length of the loop in control cycles, as far as the lanes reach.
+    pub fn set_length(&mut self, ticks: usize) {
+        self.length = ticks.clamp(1, (self.lanes.len() / PARAMETER_COUNT).max(1));
+
+        if self.position >= self.length {
+            self.position = 0;
+        }
+    }
+
+    /// Follows the recording of the audio, the lanes start over when a take ends.
+    pub fn sync(&mut self, take_running: bool) {
+        if self.take_running && !take_running {
+            self.position = 0;
+        }
+
+        self.take_running = take_running;
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Starts or stops recording, a new recording waits for the knobs to be turned.
+    pub fn set_recording(&mut self, recording: bool) {
+        self.recording = recording;
+        self.touched = 0;
+    }
+
+    pub fn get_blend(&self) -> f32 {
+        self.blend
+    }
+
+    pub fn step_blend(&mut self, steps: i32) {
+        self.blend = (self.blend + steps as f32 * BLEND_STEP).clamp(0.0, 1.0);
+    }
+
+    /// Forgets all recorded movements.
+    pub fn clear(&mut self) {
+        self.touched = 0;
+        self.automated = 0;
+    }
+
+    /// Returns the parameters whose lane holds a recording, one bit each.
+    pub fn get_automated(&self) -> u32 {
+        self.automated
+    }
+
+    /// Records or plays back one control cycle and returns the knob values with the lanes over
+    /// them.
+    pub fn apply(&mut self, knobs: &ParameterValues) -> ParameterValues {
+        let last = self.last.replace(*knobs).unwrap_or(*knobs);
+        let mut values = *knobs;
+
+        for index in 0..PARAMETER_COUNT {
+            let parameter = Parameter::from_index(index);
+            let knob = knobs.get(parameter);
+            let bit = 1 << index;
+
+            if self.recording && (knob - last.get(parameter)).abs() > TOUCH_THRESHOLD {
+                // a lane recorded for the first time holds the knob value all the way
+                if self.automated & bit == 0 {
+                    for tick in 0..self.length {
+                        self.lanes[tick * PARAMETER_COUNT + index] = knob;
+                    }
+                }
+
+                self.touched |= bit;
+                self.automated |= bit;
+            }
+
+            let lane = &mut self.lanes[self.position * PARAMETER_COUNT + index];
+
+            if self.touched & bit != 0 {
+                *lane = knob;
+            } else if self.automated & bit != 0 {
+                values.set(parameter, knob + (*lane - knob) * self.blend);
+            }
+        }
+
+        self.position = (self.position + 1) % self.length;
+
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_automation(ticks: usize) -> Automation {
+        let lanes = vec![0.0; ticks * PARAMETER_COUNT].into_boxed_slice();
+
+        Automation::new(Box::leak(lanes))
+    }
+
+    fn knobs(pitch: f32) -> ParameterValues {
+        let mut values = ParameterValues::default();
+        values.set(Parameter::Pitch, pitch);
+        values
+    }
+
+    #[test]
+    fn touched_knobs_loop_over_the_others() {
+        let mut automation = new_automation(8);
+        automation.set_length(4);
+        automation.apply(&knobs(0.5));
+        automation.set_recording(true);
+
+        // a sweep over the last three cycles of the loop, the first one keeps where it started
+        for pitch in [0.6, 0.7, 0.8] {
+            automation.apply(&knobs(pitch));
+        }
+
+        automation.set_recording(false);
+        assert_eq!(automation.get_automated(), 1 << Parameter::Pitch as usize);
+
+        let played: Vec<f32> = (0..4)
+            .map(|_| automation.apply(&knobs(0.0)).get(Parameter::Pitch))
+            .collect();
+
+        assert_eq!(played, [0.6, 0.6, 0.7, 0.8]);
+        assert_eq!(automation.apply(&knobs(0.0)).get(Parameter::Offset), 0.0);
+
+        // half the blend meets the knob halfway
+        automation.step_blend(-5);
+        assert!((automation.apply(&knobs(0.0)).get(Parameter::Pitch) - 0.3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn lanes_start_over_when_a_take_ends() {
+        let mut automation = new_automation(8);
+        automation.set_length(4);
+        automation.apply(&knobs(0.0));
+        automation.set_recording(true);
+
+        for pitch in [0.2, 0.3, 0.4, 0.1] {
+            automation.apply(&knobs(pitch));
+        }
+
+        automation.set_recording(false);
+        automation.sync(true);
+        automation.apply(&knobs(0.0));
+        automation.sync(false);
+
+        // the loop plays from its first cycle again
+        assert_eq!(automation.apply(&knobs(0.0)).get(Parameter::Pitch), 0.1);
+
+        automation.clear();
+        assert_eq!(automation.apply(&knobs(0.0)).get(Parameter::Pitch), 0.0);
+    }
+}
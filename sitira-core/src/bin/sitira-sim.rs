@@ -0,0 +1,414 @@
+//! Runs the interface and the engine of Sitira on the host.
+//!
+//! The screen is drawn into a window by embedded-graphics-simulator, the keyboard stands in for
+//! the panel and everything the engine plays is written to a WAV file when the window closes.
+//!
+//! ```text
+//! cargo run -p sitira-core --features simulator --target x86_64-unknown-linux-gnu -- \
+//!     source.wav [output.wav]
+//! ```
+//!
+//! | Key         | Panel                                          |
+//! |-------------|------------------------------------------------|
+//! | Left, Right | Turn the encoder                               |
+//! | Return      | Click the encoder                              |
+//! | Backspace   | Double click the encoder                       |
+//! | 1 to 8      | Pick a knob: offset, size, pitch, grains, delay, velocity, window, shape |
+//! | Up, Down    | Turn the picked knob                           |
+//! | Tab         | Select the next slice                          |
+//! | Escape      | Quit and write the WAV file                    |
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use embedded_graphics::{pixelcolor::Rgb565, prelude::*, primitives::Rectangle};
+use embedded_graphics_simulator::{
+    sdl2::Keycode, OutputSettingsBuilder, SimulatorDisplay, SimulatorEvent, Window,
+};
+use hound::{SampleFormat, WavReader, WavSpec, WavWriter};
+
+use sitira_core::curve::CurveSet;
+use sitira_core::event::{Event, Input};
+use sitira_core::grain_stats::{self, GRAIN_SNAPSHOT, GRAIN_STATS};
+use sitira_core::grains::{self, GrainCloud};
+use sitira_core::mapping::Parameter;
+use sitira_core::menu::{Menu, MenuAction, MenuItem};
+use sitira_core::meter::{BlockMeter, OUTPUT_METER};
+use sitira_core::normalize;
+use sitira_core::onset::OnsetDetector;
+use sitira_core::output::OutputStage;
+use sitira_core::pages;
+use sitira_core::quantizer::Quantizer;
+use sitira_core::screen::{Screen, HEIGHT, WIDTH};
+use sitira_core::settings::UserSettings;
+use sitira_core::slices::SliceMarkers;
+use sitira_core::spectrum::{SpectrumAnalyzer, SPECTRUM};
+use sitira_core::stretch::StretchRanges;
+use sitira_core::strings::{self, UiText};
+use sitira_core::theme::THEME;
+use sitira_core::timecode::{self, TimeFormat};
+use sitira_core::waveform::{Peak, PeakPyramid};
+
+/// Interval of the control and display updates, as on the hardware
+const CONTROL_RATE_IN_MS: u64 = 30;
+/// Frames the engine renders per call, as the audio task does
+const BLOCK_SIZE: usize = 32;
+/// Level of the granulator in front of the output stage
+const GRANULATOR_LEVEL: f32 = 0.5;
+/// Change of a knob per key press
+const KNOB_STEP: f32 = 0.02;
+/// Knobs which can be turned with the keyboard
+const KNOBS: [Parameter; 8] = [
+    Parameter::Offset,
+    Parameter::GrainSize,
+    Parameter::Pitch,
+    Parameter::ActiveGrains,
+    Parameter::Delay,
+    Parameter::Velocity,
+    Parameter::Envelope,
+    Parameter::WaveSelect,
+];
+/// Grain and pitch ranges of the grain engine, as configured in the firmware
+const STRETCH_RANGES: StretchRanges = StretchRanges {
+    min_grain_in_ms: 10.0,
+    max_grain_in_ms: 1000.0,
+    pitch_range_in_semitones: 24.0,
+};
+const GRAIN_DELAY_RANGE_IN_MS: (f32, f32) = (0.0, 1000.0);
+/// Window pixels per display pixel
+const WINDOW_SCALE: u32 = 2;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+
+    let (source_path, output_path) = match (args.next(), args.next()) {
+        (Some(source), output) => (source, output.unwrap_or_else(|| "sitira-sim.wav".into())),
+        (None, _) => {
+            eprintln!("Usage: sitira-sim <source.wav> [output.wav]");
+            process::exit(1);
+        }
+    };
+
+    let (source, sample_rate) = match read_source(&source_path) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("Could not read {}: {}", source_path, error);
+            process::exit(1);
+        }
+    };
+
+    let source = source.as_slice();
+
+    // created with the settings of the first update
+    let mut granulator: Option<GrainCloud> = None;
+
+    let mut output = OutputStage::new(sample_rate as f32, 10.0, -60.0, 0.0);
+    output.set_volume(1.0);
+    output.set_muted(false);
+
+    let mut output_meter = BlockMeter::new();
+    let mut rendered: Vec<(f32, f32)> = Vec::new();
+    let mut spectrum_analyzer = SpectrumAnalyzer::new();
+
+    let mut slices = SliceMarkers::new();
+    let mut detector = OnsetDetector::new();
+
+    detector.start(source.len(), &mut slices);
+    while !detector.process(source, &mut slices, usize::MAX) {}
+
+    // the source never changes, so its pyramid is built once
+    let pyramid = PeakPyramid::new(source.len());
+    let mut peaks = vec![Peak::EMPTY; pyramid.len()];
+    pyramid.update(&mut peaks, source, 0..source.len());
+    let mut columns = [Peak::EMPTY; WIDTH];
+
+    let mut menu = Menu::new();
+    let mut curves = CurveSet::new();
+    let mut quantizer = Quantizer::default();
+    let mut knobs = [0.5, 0.5, 0.5, 0.1, 0.0, 1.0, 0.0, 0.5];
+    let mut knob = 0;
+
+    let mut display = SimulatorDisplay::<Rgb565>::new(Size::new(WIDTH as u32, HEIGHT as u32));
+    let mut window = Window::new(
+        "Sitira",
+        &OutputSettingsBuilder::new().scale(WINDOW_SCALE).build(),
+    );
+
+    let interval = Duration::from_millis(CONTROL_RATE_IN_MS);
+    let frames_per_update = sample_rate as usize * CONTROL_RATE_IN_MS as usize / 1000;
+
+    'running: loop {
+        let update_start = Instant::now();
+
+        // panel
+        let mut events = Vec::new();
+
+        for event in window.events() {
+            match event {
+                SimulatorEvent::Quit => break 'running,
+                SimulatorEvent::KeyDown { keycode, .. } => match keycode {
+                    Keycode::Escape => break 'running,
+                    Keycode::Left | Keycode::Right => {
+                        let detents = if keycode == Keycode::Left { -1 } else { 1 };
+
+                        events.push(Event::EncoderTurned {
+                            detents,
+                            accelerated: detents,
+                        });
+                    }
+                    Keycode::Return => events.push(Event::Click(Input::EncoderSwitch)),
+                    Keycode::Backspace => events.push(Event::DoubleClick(Input::EncoderSwitch)),
+                    Keycode::Up => knobs[knob] = (knobs[knob] + KNOB_STEP).min(1.0),
+                    Keycode::Down => knobs[knob] = (knobs[knob] - KNOB_STEP).max(0.0),
+                    Keycode::Tab => slices.select_next(),
+                    Keycode::Num1 => knob = 0,
+                    Keycode::Num2 => knob = 1,
+                    Keycode::Num3 => knob = 2,
+                    Keycode::Num4 => knob = 3,
+                    Keycode::Num5 => knob = 4,
+                    Keycode::Num6 => knob = 5,
+                    Keycode::Num7 => knob = 6,
+                    Keycode::Num8 => knob = 7,
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+
+        for event in events.iter() {
+            match menu.handle(event) {
+                Some(MenuAction::Adjust(MenuItem::CurveChannel, steps)) => {
+                    curves.select_channel(steps)
+                }
+                Some(MenuAction::Adjust(MenuItem::CurvePoints, steps)) => {
+                    curves.change_point_count(steps)
+                }
+                Some(MenuAction::Adjust(MenuItem::CurvePoint, steps)) => curves.select_point(steps),
+                Some(MenuAction::Adjust(MenuItem::CurveInput, steps)) => {
+                    curves.move_point(steps, 0)
+                }
+                Some(MenuAction::Adjust(MenuItem::CurveOutput, steps)) => {
+                    curves.move_point(0, steps)
+                }
+                Some(MenuAction::Execute(MenuItem::CurveReset)) => curves.reset(),
+                Some(MenuAction::Adjust(MenuItem::Scale, steps)) => {
+                    quantizer.step_scale(steps);
+                    println!("Scale {}", quantizer.get_scale().name());
+                }
+                Some(MenuAction::Adjust(MenuItem::Mode, steps)) => {
+                    quantizer.step_mode(steps);
+                    println!("Mode {}", quantizer.get_mode().name());
+                }
+                Some(MenuAction::Adjust(MenuItem::Root, steps)) => {
+                    quantizer.step_root(steps);
+                    println!("Root {}", quantizer.get_root_name());
+                }
+                Some(MenuAction::Adjust(MenuItem::FineTune, steps)) => {
+                    quantizer.step_fine_tune(steps);
+                    println!("Fine tune {} cents", quantizer.get_fine_tune());
+                }
+                Some(MenuAction::Adjust(MenuItem::Theme, steps)) => {
+                    THEME.select(THEME.get().step(steps));
+                    println!("Theme {}", THEME.get().name());
+                }
+                // everything else is owned by the firmware
+                Some(action) => println!("{:?}", action),
+                None => {}
+            }
+        }
+
+        // knobs are shaped by their response curves, just as the multiplexed channels are
+        let shaped: Vec<f32> = knobs
+            .iter()
+            .enumerate()
+            .map(|(channel, value)| curves.apply(channel, *value))
+            .collect();
+
+        let settings = UserSettings {
+            master_volume: GRANULATOR_LEVEL,
+            active_grains: shaped[3],
+            offset: slices.apply(shaped[0]),
+            grain_size: shaped[1],
+            pitch: STRETCH_RANGES.pitch(quantizer.tune(STRETCH_RANGES.semitones(shaped[2]))),
+            delay: shaped[4],
+            velocity: shaped[5],
+            sp_offset: 0.0,
+            sp_grain_size: 0.0,
+            sp_pitch: 0.0,
+            sp_delay: 0.0,
+            sp_velocity: 0.0,
+            window_function: grains::Window::from_parameter(shaped[6]) as u8,
+            window_param: shaped[7],
+            tuning: quantizer,
+        };
+
+        let granulator = granulator.get_or_insert_with(|| {
+            GrainCloud::new(
+                sample_rate as usize,
+                STRETCH_RANGES,
+                GRAIN_DELAY_RANGE_IN_MS,
+                &settings,
+            )
+        });
+        granulator.set_settings(&settings);
+
+        // engine
+        let engine_start = Instant::now();
+        let update_start = rendered.len();
+
+        for _ in 0..frames_per_update / BLOCK_SIZE {
+            let mut block = [0.0; BLOCK_SIZE];
+            granulator.render(source, &mut block);
+
+            for sample in block {
+                let sample = sample * normalize::get_gain();
+                let (left, right) = output.process(sample, sample);
+
+                output_meter.accumulate(left);
+                output_meter.accumulate(right);
+                rendered.push((left, right));
+            }
+        }
+
+        output_meter.publish(&OUTPUT_METER);
+        GRAIN_SNAPSHOT.publish(granulator.get_dots(source.len()));
+
+        // the frames of this update, in mono like the tap of the audio task
+        let tap: Vec<f32> = rendered[update_start..]
+            .iter()
+            .map(|(left, right)| (left + right) * 0.5)
+            .collect();
+
+        SPECTRUM.set_enabled(menu.get_selected_item() == MenuItem::Spectrum);
+        SPECTRUM.push(&tap);
+        spectrum_analyzer.step(&SPECTRUM);
+
+        let engine_time = frames_per_update as f32 / sample_rate as f32;
+        GRAIN_STATS.publish(
+            Some(&settings),
+            engine_start.elapsed().as_secs_f32() / engine_time,
+        );
+        GRAIN_STATS.publish_counts(granulator.get_requested(), granulator.get_playing());
+
+        // display
+        display.clear(THEME.get_palette().background).unwrap();
+
+        if menu.get_selected_item().is_curve() {
+            display.draw_curve(curves.get_curve(), curves.get_point(), curves.get_channel());
+        } else if menu.get_selected_item() == MenuItem::GrainView {
+            display.draw_grain_view(GRAIN_SNAPSHOT.get_dots());
+        } else if menu.get_selected_item() == MenuItem::Spectrum {
+            display.draw_spectrum(&SPECTRUM.get_bands());
+        } else if menu.get_selected_item() == MenuItem::GrainCount {
+            let (requested, playing) = GRAIN_STATS.get_counts();
+            let name = MenuItem::GrainCount.name();
+
+            display.draw_message(&format!("{}\n{}/{}", name, playing, requested));
+        } else {
+            // not enough samples to fill the screen width
+            if source.len() >= WIDTH {
+                pyramid.query(&peaks, source, 0..source.len(), &mut columns);
+                display.draw_waveform(&columns);
+            }
+            display.draw_slice_markers(
+                slices.as_slice(),
+                slices.get_buffer_length(),
+                slices.get_selected(),
+            );
+        }
+
+        let offset = (settings.offset * source.len() as f32) as usize;
+
+        display.draw_time_readout(
+            format_time(offset, sample_rate).as_str(),
+            format_time(source.len(), sample_rate).as_str(),
+            normalize::format_gain(normalize::get_gain()).as_str(),
+        );
+
+        display.draw_cloud_readout(
+            grain_stats::format_percent(GRAIN_STATS.get_density()).as_str(),
+            grain_stats::format_percent(GRAIN_STATS.get_pitch()).as_str(),
+            grain_stats::format_percent(GRAIN_STATS.get_load()).as_str(),
+        );
+
+        let item = menu.get_selected_item().name();
+        let mode = if menu.is_editing() { "edit" } else { "menu" };
+        let knob_text = format!("{} {:.2}", pages::label(KNOBS[knob]), knobs[knob]);
+
+        display.clear_subsection(Rectangle::new(Point::new(0, 183), Size::new(320, 14)));
+        display.print_on_screen(4, 192, &format!("{}: {}", mode, item));
+        display.print_on_screen(180, 192, &knob_text);
+
+        display.draw_meter(
+            Point::new(0, 215),
+            strings::get(UiText::OutputMeter),
+            OUTPUT_METER.get_rms(),
+            OUTPUT_METER.get_peak(),
+        );
+
+        window.update(&display);
+
+        if let Some(remaining) = interval.checked_sub(update_start.elapsed()) {
+            thread::sleep(remaining);
+        }
+    }
+
+    if let Err(error) = write_output(&output_path, &rendered, sample_rate) {
+        eprintln!("Could not write {}: {}", output_path, error);
+        process::exit(1);
+    }
+
+    println!(
+        "Wrote {:.1} s to {}",
+        rendered.len() as f32 / sample_rate as f32,
+        output_path
+    );
+}
+
+/// Reads a WAV file of any format and mixes it down to mono.
+fn read_source(path: &str) -> Result<(Vec<f32>, u32), hound::Error> {
+    let mut reader = WavReader::open(path)?;
+    let spec = reader.spec();
+
+    let samples = match spec.sample_format {
+        SampleFormat::Float => reader.samples::<f32>().collect::<Result<Vec<_>, _>>()?,
+        SampleFormat::Int => {
+            let scale = 1.0 / (1u32 << (spec.bits_per_sample - 1)) as f32;
+
+            reader
+                .samples::<i32>()
+                .map(|sample| sample.map(|sample| sample as f32 * scale))
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    };
+
+    let channels = spec.channels as usize;
+    let mono = samples
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+
+    Ok((mono, spec.sample_rate))
+}
+
+fn write_output(path: &str, frames: &[(f32, f32)], sample_rate: u32) -> Result<(), hound::Error> {
+    let spec = WavSpec {
+        channels: 2,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+
+    let mut writer = WavWriter::create(path, spec)?;
+
+    for (left, right) in frames {
+        writer.write_sample(*left)?;
+        writer.write_sample(*right)?;
+    }
+
+    writer.finalize()
+}
+
+fn format_time(samples: usize, sample_rate: u32) -> timecode::TimeText {
+    timecode::format_position(samples, sample_rate, TimeFormat::Seconds)
+}
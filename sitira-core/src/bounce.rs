@@ -0,0 +1,108 @@
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Number of audio blocks rendered per audio callback, makes bouncing faster than real time
+pub const BOUNCE_BLOCKS_PER_CALLBACK: usize = 4;
+
+/// Requests and progress of a bounce, shared between the control and the audio task.
+///
+/// A bounce renders the granulator output into another slot, so previously granulated
+/// material can be granulated again.
+pub struct Bounce {
+    requested: AtomicBool,
+    target_slot: AtomicUsize,
+    length: AtomicUsize,
+    rendered: AtomicUsize,
+    running: AtomicBool,
+}
+
+impl Bounce {
+    pub const fn new() -> Self {
+        Bounce {
+            requested: AtomicBool::new(false),
+            target_slot: AtomicUsize::new(0),
+            length: AtomicUsize::new(0),
+            rendered: AtomicUsize::new(0),
+            running: AtomicBool::new(false),
+        }
+    }
+
+    /// Requests rendering `length` samples into `target_slot`. Ignored while a bounce is running.
+    pub fn request(&self, target_slot: usize, length: usize) -> bool {
+        if self.is_running() {
+            return false;
+        }
+
+        self.target_slot.store(target_slot, Ordering::Relaxed);
+        self.length.store(length, Ordering::Relaxed);
+        self.rendered.store(0, Ordering::Relaxed);
+        self.requested.store(true, Ordering::Release);
+
+        true
+    }
+
+    /// Takes a pending request and marks the bounce as running. Returns target slot and length.
+    pub fn take_request(&self) -> Option<(usize, usize)> {
+        if self.requested.swap(false, Ordering::Acquire) {
+            self.running.store(true, Ordering::Relaxed);
+
+            Some((
+                self.target_slot.load(Ordering::Relaxed),
+                self.length.load(Ordering::Relaxed),
+            ))
+        } else {
+            None
+        }
+    }
+
+    pub fn set_rendered(&self, rendered: usize) {
+        self.rendered.store(rendered, Ordering::Relaxed);
+    }
+
+    pub fn finish(&self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed) || self.requested.load(Ordering::Relaxed)
+    }
+
+    /// Returns the progress in percent.
+    pub fn get_progress(&self) -> u32 {
+        let length = self.length.load(Ordering::Relaxed);
+
+        if length == 0 {
+            return 0;
+        }
+
+        ((self.rendered.load(Ordering::Relaxed) * 100) / length) as u32
+    }
+}
+
+impl Default for Bounce {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// State of the bounce which is currently rendered by the audio task.
+pub struct BounceJob {
+    pub target_slot: usize,
+    pub length: usize,
+    pub rendered: usize,
+}
+
+impl BounceJob {
+    pub fn new(target_slot: usize, length: usize) -> Self {
+        BounceJob {
+            target_slot,
+            length,
+            rendered: 0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.rendered >= self.length
+    }
+}
+
+pub static BOUNCE: Bounce = Bounce::new();
@@ -0,0 +1,179 @@
+use crate::mapping::DIRECT_CV_COUNT;
+
+/// The 16 multiplexed channels, the master volume pot and the direct CV inputs
+pub const CALIBRATION_CHANNELS: usize = 17 + DIRECT_CV_COUNT;
+/// Index of the master volume pot
+pub const MASTER_VOLUME_CHANNEL: usize = 16;
+/// Index of the first direct CV input
+pub const DIRECT_CV_CHANNEL: usize = 17;
+
+/// Smallest span between minimum and maximum which is accepted as a measurement
+const MIN_RANGE: f32 = 0.5;
+/// Smoothing of the center measurement
+const CENTER_SMOOTHING: f32 = 0.9;
+
+/// Bytes needed to store the calibration
+pub const CALIBRATION_SIZE: usize = CALIBRATION_CHANNELS * 6;
+
+/// Measured minimum, center and maximum reading of one ADC channel.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ChannelCalibration {
+    pub min: f32,
+    pub center: f32,
+    pub max: f32,
+}
+
+impl ChannelCalibration {
+    pub const IDENTITY: ChannelCalibration = ChannelCalibration {
+        min: 0.0,
+        center: 0.5,
+        max: 1.0,
+    };
+
+    /// Scales a raw reading, so that min, center and max end up at `0.0`, `0.5` and `1.0`.
+    pub fn apply(&self, value: f32) -> f32 {
+        let scaled = if value < self.center {
+            0.5 * (value - self.min) / (self.center - self.min)
+        } else {
+            0.5 + 0.5 * (value - self.center) / (self.max - self.center)
+        };
+
+        scaled.clamp(0.0, 1.0)
+    }
+
+    fn is_valid(&self) -> bool {
+        self.max - self.min >= MIN_RANGE && self.min < self.center && self.center < self.max
+    }
+}
+
+/// Current step of the calibration routine.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CalibrationStage {
+    Inactive,
+    /// All pots centered and all CV inputs unplugged
+    Center,
+    /// Every pot gets turned through its full range
+    Range,
+}
+
+/// Calibration of all pots and CV inputs.
+///
+/// The routine first measures the center of every channel, then tracks minimum and maximum
+/// while the pots get swept. Channels which were not swept far enough keep their previous
+/// calibration.
+pub struct Calibration {
+    channels: [ChannelCalibration; CALIBRATION_CHANNELS],
+    measured: [ChannelCalibration; CALIBRATION_CHANNELS],
+    stage: CalibrationStage,
+}
+
+impl Calibration {
+    pub fn new() -> Self {
+        Calibration {
+            channels: [ChannelCalibration::IDENTITY; CALIBRATION_CHANNELS],
+            measured: [ChannelCalibration::IDENTITY; CALIBRATION_CHANNELS],
+            stage: CalibrationStage::Inactive,
+        }
+    }
+
+    pub fn get_stage(&self) -> CalibrationStage {
+        self.stage
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.stage != CalibrationStage::Inactive
+    }
+
+    pub fn start(&mut self) {
+        self.measured = self.channels;
+        self.stage = CalibrationStage::Center;
+    }
+
+    /// Moves on to the next stage. Leaving the last stage stores the measurement.
+    pub fn advance(&mut self) {
+        self.stage = match self.stage {
+            CalibrationStage::Inactive => CalibrationStage::Inactive,
+            CalibrationStage::Center => {
+                for channel in self.measured.iter_mut() {
+                    channel.min = channel.center;
+                    channel.max = channel.center;
+                }
+
+                CalibrationStage::Range
+            }
+            CalibrationStage::Range => {
+                for (channel, measured) in self.channels.iter_mut().zip(self.measured.iter()) {
+                    if measured.is_valid() {
+                        *channel = *measured;
+                    }
+                }
+
+                CalibrationStage::Inactive
+            }
+        };
+    }
+
+    /// Feeds the raw readings of one control cycle into the running measurement.
+    pub fn measure(&mut self, raw: &[f32; CALIBRATION_CHANNELS]) {
+        for (channel, value) in self.measured.iter_mut().zip(raw.iter()) {
+            match self.stage {
+                CalibrationStage::Center => {
+                    channel.center =
+                        channel.center * CENTER_SMOOTHING + value * (1.0 - CENTER_SMOOTHING);
+                }
+                CalibrationStage::Range => {
+                    channel.min = channel.min.min(*value);
+                    channel.max = channel.max.max(*value);
+                }
+                CalibrationStage::Inactive => (),
+            }
+        }
+    }
+
+    /// Scales a raw reading of a channel with its calibration.
+    pub fn apply(&self, channel: usize, value: f32) -> f32 {
+        match self.channels.get(channel) {
+            Some(calibration) => calibration.apply(value),
+            None => value,
+        }
+    }
+
+    /// Writes the calibration into `bytes`, which needs to hold at least `CALIBRATION_SIZE`
+    /// bytes.
+    pub fn write_bytes(&self, bytes: &mut [u8]) {
+        for (channel, chunk) in self.channels.iter().zip(bytes.chunks_exact_mut(6)) {
+            chunk[0..2].copy_from_slice(&quantize(channel.min).to_le_bytes());
+            chunk[2..4].copy_from_slice(&quantize(channel.center).to_le_bytes());
+            chunk[4..6].copy_from_slice(&quantize(channel.max).to_le_bytes());
+        }
+    }
+
+    /// Restores a calibration written by `write_bytes()`. Invalid channels are left untouched.
+    pub fn read_bytes(&mut self, bytes: &[u8]) {
+        for (channel, chunk) in self.channels.iter_mut().zip(bytes.chunks_exact(6)) {
+            let calibration = ChannelCalibration {
+                min: dequantize([chunk[0], chunk[1]]),
+                center: dequantize([chunk[2], chunk[3]]),
+                max: dequantize([chunk[4], chunk[5]]),
+            };
+
+            if calibration.is_valid() {
+                *channel = calibration;
+            }
+        }
+    }
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn quantize(value: f32) -> u16 {
+    (value.clamp(0.0, 1.0) * u16::MAX as f32 + 0.5) as u16
+}
+
+fn dequantize(bytes: [u8; 2]) -> f32 {
+    u16::from_le_bytes(bytes) as f32 / u16::MAX as f32
+}
@@ -0,0 +1,128 @@
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+/// Why the SD card can not be used.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CardError {
+    /// No card answered at the start or at the last retry
+    Missing,
+    /// The card answered, but holds no readable FAT file system
+    Unmountable,
+    /// Reading or writing a file failed while the card was in use
+    Failed,
+}
+
+impl CardError {
+    /// Every error, indexed by its discriminant
+    const ALL: [CardError; 3] = [
+        CardError::Missing,
+        CardError::Unmountable,
+        CardError::Failed,
+    ];
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            CardError::Missing => "No SD card",
+            CardError::Unmountable => "No FAT file system",
+            CardError::Failed => "SD card failed",
+        }
+    }
+}
+
+/// Stored instead of an error while the card is usable
+const READY: u8 = u8::MAX;
+
+/// State of the SD card, set by the idle task which owns the card and read by the UI.
+///
+/// Without a usable card Sitira keeps recording and playing, everything which reads or writes
+/// files checks the state first and gets the error instead. A retry mounts the card again.
+pub struct CardStatus {
+    error: AtomicU8,
+    retry: AtomicBool,
+}
+
+impl CardStatus {
+    pub const fn new() -> Self {
+        CardStatus {
+            // nothing is usable until the card has been mounted
+            error: AtomicU8::new(CardError::Missing as u8),
+            retry: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the error which keeps the card from being used.
+    pub fn check(&self) -> Result<(), CardError> {
+        match self.error.load(Ordering::Relaxed) {
+            READY => Ok(()),
+            error => Err(CardError::ALL[error as usize]),
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.check().is_ok()
+    }
+
+    /// Sets the state after mounting the card or after a file could not be read or written.
+    pub fn set(&self, state: Result<(), CardError>) {
+        let error = match state {
+            Ok(()) => READY,
+            Err(error) => error as u8,
+        };
+
+        self.error.store(error, Ordering::Relaxed);
+    }
+
+    /// Requests mounting the card again, ignored while it is usable.
+    pub fn request_retry(&self) -> bool {
+        if self.is_ready() {
+            return false;
+        }
+
+        self.retry.store(true, Ordering::Relaxed);
+        true
+    }
+
+    pub fn take_retry(&self) -> bool {
+        self.retry.swap(false, Ordering::Relaxed)
+    }
+}
+
+impl Default for CardStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// State of the SD card of the panel
+pub static CARD: CardStatus = CardStatus::new();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn card_is_missing_until_mounted() {
+        let status = CardStatus::new();
+
+        assert_eq!(status.check(), Err(CardError::Missing));
+
+        status.set(Ok(()));
+        assert!(status.is_ready());
+
+        status.set(Err(CardError::Failed));
+        assert_eq!(status.check(), Err(CardError::Failed));
+    }
+
+    #[test]
+    fn retries_are_only_taken_without_a_usable_card() {
+        let status = CardStatus::new();
+
+        assert!(status.request_retry());
+        assert!(status.take_retry());
+        assert!(!status.take_retry());
+
+        status.set(Ok(()));
+
+        assert!(!status.request_retry());
+        assert!(!status.take_retry());
+    }
+}
@@ -0,0 +1,81 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Shortest clock period which is accepted, in seconds
+const MIN_PERIOD_IN_S: f32 = 0.01;
+/// Longest clock period which is accepted, in seconds. Without an edge for that long, the clock
+/// counts as absent.
+const MAX_PERIOD_IN_S: f32 = 2.0;
+/// Relative deviation up to which a new period gets smoothed instead of taken over directly
+const SMOOTHING_TOLERANCE: f32 = 0.1;
+
+/// Measures the period of an external clock in samples.
+///
+/// Edges arrive with the frame of the block they happened at, so periods are accurate to a few
+/// samples. Stable periods get averaged to take out the remaining jitter.
+pub struct ClockFollower {
+    min_period: usize,
+    max_period: usize,
+    since_edge: usize,
+    period: usize,
+}
+
+impl ClockFollower {
+    pub fn new(sample_rate: f32) -> Self {
+        ClockFollower {
+            min_period: (MIN_PERIOD_IN_S * sample_rate) as usize,
+            max_period: (MAX_PERIOD_IN_S * sample_rate) as usize,
+            since_edge: 0,
+            period: 0,
+        }
+    }
+
+    /// Advances the follower by one block of `frames` samples, with a rising edge at frame
+    /// `edge`, and publishes the period.
+    pub fn process(&mut self, edge: Option<usize>, frames: usize) {
+        match edge {
+            // edges faster than the fastest clock are bounce and get ignored
+            Some(frame) if self.since_edge + frame >= self.min_period => {
+                let measured = self.since_edge + frame;
+                self.since_edge = frames - frame;
+
+                if measured <= self.max_period {
+                    let deviation = measured.abs_diff(self.period) as f32;
+
+                    self.period = if deviation <= self.period as f32 * SMOOTHING_TOLERANCE {
+                        (self.period * 3 + measured) / 4
+                    } else {
+                        measured
+                    };
+                }
+            }
+            _ => self.since_edge += frames,
+        }
+
+        if self.since_edge > self.max_period {
+            self.period = 0;
+        }
+
+        CLOCK_PERIOD.store(self.period, Ordering::Relaxed);
+    }
+}
+
+/// Returns the period of the external clock in samples, `None` if there is no clock.
+pub fn get_period() -> Option<usize> {
+    match CLOCK_PERIOD.load(Ordering::Relaxed) {
+        0 => None,
+        period => Some(period),
+    }
+}
+
+/// Rounds a loop length to the nearest whole number of clock periods which fits in `max`.
+pub fn quantize_length(length: usize, period: usize, max: usize) -> usize {
+    if period == 0 || period > max {
+        return length;
+    }
+
+    let periods = ((length + period / 2) / period).clamp(1, max / period);
+    periods * period
+}
+
+/// Period of the external clock, written by the audio task
+static CLOCK_PERIOD: AtomicUsize = AtomicUsize::new(0);
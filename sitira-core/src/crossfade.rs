@@ -0,0 +1,91 @@
+use micromath::F32Ext;
+
+/// Crossfade between the monitored input and the played output, stepped once per frame.
+///
+/// When recording starts or stops, the source which is heard glides over to the other one
+/// instead of being swapped at once, so the change never pops. Like the mixer, the gains keep the
+/// power constant over the fade.
+pub struct Crossfade {
+    /// Position of the fade, `0.0` plays and `1.0` monitors
+    position: f32,
+    monitoring: bool,
+    step: f32,
+}
+
+impl Crossfade {
+    /// Creates a crossfade which plays, a full fade takes `fade_in_ms`.
+    pub fn new(sample_rate: f32, fade_in_ms: f32) -> Self {
+        Crossfade {
+            position: 0.0,
+            monitoring: false,
+            step: 1.0 / (fade_in_ms * sample_rate / 1000.0).max(1.0),
+        }
+    }
+
+    /// Starts fading over to the input or back to the output, a fade which is still running turns
+    /// around where it is.
+    pub fn set_monitoring(&mut self, monitoring: bool) {
+        self.monitoring = monitoring;
+    }
+
+    /// Returns `true` as long as the input can be heard.
+    pub fn is_monitor_heard(&self) -> bool {
+        self.monitoring || self.position > 0.0
+    }
+
+    /// Returns `true` as long as the output can be heard.
+    pub fn is_output_heard(&self) -> bool {
+        !self.monitoring || self.position < 1.0
+    }
+
+    /// Moves the fade on by one frame and returns the gains of the input and the output.
+    pub fn next_gains(&mut self) -> (f32, f32) {
+        self.position = if self.monitoring {
+            (self.position + self.step).min(1.0)
+        } else {
+            (self.position - self.step).max(0.0)
+        };
+
+        (self.position.sqrt(), (1.0 - self.position).sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fade_glides_between_the_sources() {
+        // ten frames per fade
+        let mut crossfade = Crossfade::new(1000.0, 10.0);
+
+        assert!(!crossfade.is_monitor_heard());
+        assert_eq!(crossfade.next_gains(), (0.0, 1.0));
+
+        crossfade.set_monitoring(true);
+
+        let (monitor, output) = crossfade.next_gains();
+        assert!(monitor > 0.0 && output < 1.0);
+        assert!(crossfade.is_monitor_heard() && crossfade.is_output_heard());
+
+        for _ in 0..4 {
+            crossfade.next_gains();
+        }
+
+        // the power stays the same halfway through
+        let (monitor, output) = crossfade.next_gains();
+        assert!((monitor * monitor + output * output - 1.0).abs() < 1e-3);
+
+        for _ in 0..4 {
+            crossfade.next_gains();
+        }
+
+        assert_eq!(crossfade.next_gains(), (1.0, 0.0));
+        assert!(!crossfade.is_output_heard());
+
+        // a fade back turns around right away
+        crossfade.set_monitoring(false);
+        assert!(crossfade.is_output_heard());
+        assert!(crossfade.next_gains().1 > 0.0);
+    }
+}
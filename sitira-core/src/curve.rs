@@ -0,0 +1,325 @@
+/// Maximum amount of breakpoints of a curve
+pub const MAX_POINTS: usize = 5;
+/// Minimum amount of breakpoints of a curve, first and last point included
+pub const MIN_POINTS: usize = 3;
+/// One curve per multiplexed ADC channel
+pub const CURVE_CHANNELS: usize = 16;
+
+/// Smallest horizontal distance between two breakpoints
+const MIN_GAP: f32 = 0.02;
+/// Change of a breakpoint coordinate per encoder detent
+const POINT_STEP: f32 = 0.01;
+
+/// Bytes needed to store one curve
+pub const CURVE_SIZE: usize = 1 + MAX_POINTS * 4;
+/// Bytes needed to store all curves
+pub const CURVE_SET_SIZE: usize = CURVE_CHANNELS * CURVE_SIZE;
+
+/// Breakpoint with normalized input (`x`) and output (`y`) value.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// Maps a normalized control value onto a normalized parameter value.
+///
+/// The curve runs through 3 to 5 breakpoints and is interpolated with a monotone cubic spline,
+/// so it never overshoots between two points. The first and last point always sit on the left
+/// and right edge, only their output value can be moved.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ResponseCurve {
+    points: [Point; MAX_POINTS],
+    len: usize,
+}
+
+impl ResponseCurve {
+    /// Creates the identity curve.
+    pub fn linear() -> Self {
+        let mut curve = ResponseCurve {
+            points: [Point { x: 0.0, y: 0.0 }; MAX_POINTS],
+            len: MIN_POINTS,
+        };
+
+        curve.distribute(|x| x);
+        curve
+    }
+
+    pub fn point_count(&self) -> usize {
+        self.len
+    }
+
+    pub fn get_points(&self) -> &[Point] {
+        &self.points[..self.len]
+    }
+
+    /// Changes the amount of breakpoints. The points get spread evenly and keep the current shape
+    /// as good as possible.
+    pub fn set_point_count(&mut self, count: usize) {
+        let count = count.clamp(MIN_POINTS, MAX_POINTS);
+
+        if count != self.len {
+            let old = *self;
+            self.len = count;
+            self.distribute(|x| old.apply(x));
+        }
+    }
+
+    /// Moves a breakpoint by the given amount of steps. The input value stays in between the
+    /// neighbouring points.
+    pub fn move_point(&mut self, index: usize, x_steps: i32, y_steps: i32) {
+        if index >= self.len {
+            return;
+        }
+
+        let last = self.len - 1;
+
+        if index != 0 && index != last {
+            let min = self.points[index - 1].x + MIN_GAP;
+            let max = self.points[index + 1].x - MIN_GAP;
+            let x = self.points[index].x + x_steps as f32 * POINT_STEP;
+            self.points[index].x = x.clamp(min, max);
+        }
+
+        let y = self.points[index].y + y_steps as f32 * POINT_STEP;
+        self.points[index].y = y.clamp(0.0, 1.0);
+    }
+
+    /// Returns the shaped value of a normalized input.
+    pub fn apply(&self, value: f32) -> f32 {
+        let x = value.clamp(0.0, 1.0);
+        let last = self.len - 1;
+
+        // find the segment the value lies in
+        let mut segment = 0;
+        while segment < last - 1 && x > self.points[segment + 1].x {
+            segment += 1;
+        }
+
+        let start = self.points[segment];
+        let end = self.points[segment + 1];
+        let width = end.x - start.x;
+        let t = (x - start.x) / width;
+
+        let t2 = t * t;
+        let t3 = t2 * t;
+
+        // cubic hermite basis
+        let h00 = 2.0 * t3 - 3.0 * t2 + 1.0;
+        let h10 = t3 - 2.0 * t2 + t;
+        let h01 = -2.0 * t3 + 3.0 * t2;
+        let h11 = t3 - t2;
+
+        let y = h00 * start.y
+            + h10 * width * self.tangent(segment)
+            + h01 * end.y
+            + h11 * width * self.tangent(segment + 1);
+
+        y.clamp(0.0, 1.0)
+    }
+
+    /// Writes the curve into `bytes`, which needs to hold at least `CURVE_SIZE` bytes.
+    pub fn write_bytes(&self, bytes: &mut [u8]) {
+        bytes[0] = self.len as u8;
+
+        for (point, chunk) in self
+            .points
+            .iter()
+            .zip(bytes[1..CURVE_SIZE].chunks_exact_mut(4))
+        {
+            chunk[..2].copy_from_slice(&quantize(point.x).to_le_bytes());
+            chunk[2..].copy_from_slice(&quantize(point.y).to_le_bytes());
+        }
+    }
+
+    /// Reads a curve written by `write_bytes()`. Returns `None` if the data is not a valid curve.
+    pub fn read_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < CURVE_SIZE {
+            return None;
+        }
+
+        let len = bytes[0] as usize;
+
+        if !(MIN_POINTS..=MAX_POINTS).contains(&len) {
+            return None;
+        }
+
+        let mut curve = ResponseCurve::linear();
+        curve.len = len;
+
+        for (point, chunk) in curve
+            .points
+            .iter_mut()
+            .zip(bytes[1..CURVE_SIZE].chunks_exact(4))
+        {
+            point.x = dequantize(u16::from_le_bytes([chunk[0], chunk[1]]));
+            point.y = dequantize(u16::from_le_bytes([chunk[2], chunk[3]]));
+        }
+
+        let points = curve.get_points();
+        let edges_fixed = points[0].x == 0.0 && points[len - 1].x == 1.0;
+        let ascending = points.windows(2).all(|pair| pair[0].x < pair[1].x);
+
+        if edges_fixed && ascending {
+            Some(curve)
+        } else {
+            None
+        }
+    }
+
+    /// Spreads all points evenly and sets their output to `shape(x)`.
+    fn distribute<F: Fn(f32) -> f32>(&mut self, shape: F) {
+        let last = self.len - 1;
+
+        for (index, point) in self.points[..self.len].iter_mut().enumerate() {
+            let x = index as f32 / last as f32;
+            *point = Point { x, y: shape(x) };
+        }
+    }
+
+    fn slope(&self, segment: usize) -> f32 {
+        let start = self.points[segment];
+        let end = self.points[segment + 1];
+        (end.y - start.y) / (end.x - start.x)
+    }
+
+    /// Tangent at a breakpoint after Fritsch-Carlson, which keeps every segment monotone.
+    fn tangent(&self, index: usize) -> f32 {
+        let last = self.len - 1;
+
+        if index == 0 {
+            return self.slope(0);
+        }
+
+        if index == last {
+            return self.slope(last - 1);
+        }
+
+        let left = self.slope(index - 1);
+        let right = self.slope(index);
+
+        // local extremum stays flat
+        if left * right <= 0.0 {
+            return 0.0;
+        }
+
+        2.0 * left * right / (left + right)
+    }
+}
+
+impl Default for ResponseCurve {
+    fn default() -> Self {
+        Self::linear()
+    }
+}
+
+/// Response curves of all multiplexed channels together with the editing position.
+pub struct CurveSet {
+    curves: [ResponseCurve; CURVE_CHANNELS],
+    channel: usize,
+    point: usize,
+    changed: bool,
+}
+
+impl CurveSet {
+    pub fn new() -> Self {
+        CurveSet {
+            curves: [ResponseCurve::linear(); CURVE_CHANNELS],
+            channel: 0,
+            point: 0,
+            changed: true,
+        }
+    }
+
+    /// Shapes the value of an ADC channel with its curve.
+    pub fn apply(&self, channel: usize, value: f32) -> f32 {
+        match self.curves.get(channel) {
+            Some(curve) => curve.apply(value),
+            None => value,
+        }
+    }
+
+    pub fn get_channel(&self) -> usize {
+        self.channel
+    }
+
+    pub fn get_point(&self) -> usize {
+        self.point
+    }
+
+    /// Curve of the channel which is currently edited.
+    pub fn get_curve(&self) -> &ResponseCurve {
+        &self.curves[self.channel]
+    }
+
+    pub fn select_channel(&mut self, steps: i32) {
+        let channel = self.channel as i32 + steps;
+        self.channel = channel.rem_euclid(CURVE_CHANNELS as i32) as usize;
+        self.point = self.point.min(self.get_curve().point_count() - 1);
+        self.changed = true;
+    }
+
+    pub fn select_point(&mut self, steps: i32) {
+        let last = self.get_curve().point_count() as i32 - 1;
+        self.point = (self.point as i32 + steps).clamp(0, last) as usize;
+        self.changed = true;
+    }
+
+    pub fn change_point_count(&mut self, steps: i32) {
+        let curve = &mut self.curves[self.channel];
+        let count = (curve.point_count() as i32 + steps).max(0) as usize;
+
+        curve.set_point_count(count);
+        self.point = self.point.min(curve.point_count() - 1);
+        self.changed = true;
+    }
+
+    pub fn move_point(&mut self, x_steps: i32, y_steps: i32) {
+        self.curves[self.channel].move_point(self.point, x_steps, y_steps);
+        self.changed = true;
+    }
+
+    /// Resets the edited curve to the identity.
+    pub fn reset(&mut self) {
+        self.curves[self.channel] = ResponseCurve::linear();
+        self.point = 0;
+        self.changed = true;
+    }
+
+    /// Returns `true` once after the edited curve has changed, so the display knows when to redraw.
+    pub fn take_changed(&mut self) -> bool {
+        let changed = self.changed;
+        self.changed = false;
+        changed
+    }
+
+    /// Writes all curves into `bytes`, which needs to hold at least `CURVE_SET_SIZE` bytes.
+    pub fn write_bytes(&self, bytes: &mut [u8]) {
+        for (curve, chunk) in self.curves.iter().zip(bytes.chunks_exact_mut(CURVE_SIZE)) {
+            curve.write_bytes(chunk);
+        }
+    }
+
+    /// Restores curves written by `write_bytes()`. Invalid curves are reset to the identity.
+    pub fn read_bytes(&mut self, bytes: &[u8]) {
+        for (curve, chunk) in self.curves.iter_mut().zip(bytes.chunks(CURVE_SIZE)) {
+            *curve = ResponseCurve::read_bytes(chunk).unwrap_or_default();
+        }
+
+        self.changed = true;
+    }
+}
+
+impl Default for CurveSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn quantize(value: f32) -> u16 {
+    (value.clamp(0.0, 1.0) * u16::MAX as f32 + 0.5) as u16
+}
+
+fn dequantize(value: u16) -> f32 {
+    value as f32 / u16::MAX as f32
+}
@@ -0,0 +1,84 @@
+/// Smoothing of echo time changes, which bend the pitch like a tape delay instead of clicking
+const TIME_SMOOTHING: f32 = 0.0005;
+/// Highest feedback, keeps the echoes from building up forever
+const MAX_FEEDBACK: f32 = 0.95;
+
+/// Stereo ping-pong delay after the engines.
+///
+/// The mono input enters the left line, each line feeds back into the other one, so the echoes
+/// alternate between the sides. Not to be confused with the grain `delay`, which only schedules
+/// grains.
+pub struct Echo {
+    /// Interleaved left and right lines
+    buffer: &'static mut [f32],
+    length: usize,
+    write: usize,
+    time: f32,
+    target_time: f32,
+    feedback: f32,
+    mix: f32,
+}
+
+impl Echo {
+    /// Creates the echo on top of `buffer`, which holds both lines interleaved.
+    pub fn new(buffer: &'static mut [f32]) -> Self {
+        buffer.fill(0.0);
+
+        let length = buffer.len() / 2;
+
+        Echo {
+            buffer,
+            length,
+            write: 0,
+            time: 1.0,
+            target_time: 1.0,
+            feedback: 0.0,
+            mix: 0.0,
+        }
+    }
+
+    /// Sets the echo time in frames.
+    pub fn set_time(&mut self, frames: f32) {
+        self.target_time = frames.clamp(1.0, (self.length - 2) as f32);
+    }
+
+    pub fn set_feedback(&mut self, feedback: f32) {
+        self.feedback = feedback.clamp(0.0, MAX_FEEDBACK);
+    }
+
+    /// Sets the crossfade between the dry (`0.0`) and the echoed (`1.0`) signal.
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix = mix.clamp(0.0, 1.0);
+    }
+
+    pub fn process(&mut self, input: f32) -> (f32, f32) {
+        self.time += (self.target_time - self.time) * TIME_SMOOTHING;
+
+        let left = self.read(0);
+        let right = self.read(1);
+
+        self.buffer[self.write * 2] = input + right * self.feedback;
+        self.buffer[self.write * 2 + 1] = left * self.feedback;
+        self.write = (self.write + 1) % self.length;
+
+        let dry = input * (1.0 - self.mix);
+        (dry + left * self.mix, dry + right * self.mix)
+    }
+
+    /// Reads a line at the current echo time with linear interpolation.
+    fn read(&self, channel: usize) -> f32 {
+        let mut position = self.write as f32 - self.time;
+
+        if position < 0.0 {
+            position += self.length as f32;
+        }
+
+        let index = position as usize % self.length;
+        let next = (index + 1) % self.length;
+        let fraction = position - (position as usize) as f32;
+
+        let a = self.buffer[index * 2 + channel];
+        let b = self.buffer[next * 2 + channel];
+        a + (b - a) * fraction
+    }
+}
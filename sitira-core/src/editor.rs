@@ -0,0 +1,161 @@
+use core::ops::Range;
+
+/// Samples the view can be zoomed in to at most, a few per column of the screen
+pub const MIN_VIEW_LENGTH: usize = 1024;
+/// Detents a trim point takes to cross the view
+const TRIM_STEPS_PER_VIEW: usize = 160;
+/// Detents the view takes to scroll by its own length
+const SCROLL_STEPS_PER_VIEW: usize = 16;
+
+/// Zoomable view of the active slot with trim points, shared between the control and the display
+/// task.
+///
+/// The view is the part of the buffer the waveform shows, the trim is the region grains play
+/// from. Trim points move by a fraction of the view, so zooming in makes them finer. The trim
+/// confines the grain offset like a selected slice does and is set back to the whole buffer
+/// whenever another slot is played or the buffer changes its length.
+pub struct WaveformEditor {
+    slot: usize,
+    buffer_length: usize,
+    view: Range<usize>,
+    trim: Range<usize>,
+    changed: bool,
+}
+
+impl WaveformEditor {
+    pub fn new() -> Self {
+        WaveformEditor {
+            slot: 0,
+            buffer_length: 0,
+            view: 0..0,
+            trim: 0..0,
+            changed: true,
+        }
+    }
+
+    /// Follows the buffer the grains are played from, a new one resets the view and the trim.
+    pub fn follow(&mut self, slot: usize, buffer_length: usize) {
+        if slot != self.slot || buffer_length != self.buffer_length {
+            self.slot = slot;
+            self.buffer_length = buffer_length;
+            self.view = 0..buffer_length;
+            self.trim = 0..buffer_length;
+            self.changed = true;
+        }
+    }
+
+    pub fn get_buffer_length(&self) -> usize {
+        self.buffer_length
+    }
+
+    /// Returns the samples the waveform shows.
+    pub fn get_view(&self) -> Range<usize> {
+        self.view.clone()
+    }
+
+    /// Returns the samples the grains are played from.
+    pub fn get_trim(&self) -> Range<usize> {
+        self.trim.clone()
+    }
+
+    pub fn is_trimmed(&self) -> bool {
+        self.trim.len() < self.buffer_length
+    }
+
+    /// Halves the view per step inwards and doubles it per step outwards, around its center.
+    pub fn zoom(&mut self, steps: i32) {
+        let min = MIN_VIEW_LENGTH.min(self.buffer_length);
+        let factor = 1 << steps.unsigned_abs().min(31);
+        let length = if steps > 0 {
+            self.view.len() / factor
+        } else {
+            self.view.len().saturating_mul(factor)
+        };
+        let length = length.clamp(min, self.buffer_length);
+        let center = self.view.start + self.view.len() / 2;
+
+        self.show(center.saturating_sub(length / 2), length);
+    }
+
+    /// Moves the view by a sixteenth of its length per step.
+    pub fn scroll(&mut self, steps: i32) {
+        let step = (self.view.len() / SCROLL_STEPS_PER_VIEW).max(1) as i64;
+        let start = (self.view.start as i64 + steps as i64 * step).max(0) as usize;
+
+        self.show(start, self.view.len());
+    }
+
+    /// Moves the start of the trim, it stays in front of the end.
+    pub fn step_start(&mut self, steps: i32) {
+        let start = self.step_point(self.trim.start, steps, 0, self.trim.end.saturating_sub(1));
+
+        self.trim.start = start;
+        self.reveal(start);
+    }
+
+    /// Moves the end of the trim, it stays behind the start.
+    pub fn step_end(&mut self, steps: i32) {
+        let min = (self.trim.start + 1).min(self.buffer_length);
+        let end = self.step_point(self.trim.end, steps, min, self.buffer_length);
+
+        self.trim.end = end;
+        self.reveal(end);
+    }
+
+    /// Plays the whole buffer again.
+    pub fn reset_trim(&mut self) {
+        self.trim = 0..self.buffer_length;
+        self.changed = true;
+    }
+
+    /// Maps a normalized offset (`0.0` to `1.0`) into the trim.
+    pub fn apply(&self, offset: f32) -> f32 {
+        if self.buffer_length == 0 {
+            return offset;
+        }
+
+        let length = self.buffer_length as f32;
+
+        (self.trim.start as f32 + offset * self.trim.len() as f32) / length
+    }
+
+    /// Returns `true` once after the view or the trim have changed, so the display knows when to
+    /// redraw.
+    pub fn take_changed(&mut self) -> bool {
+        core::mem::replace(&mut self.changed, false)
+    }
+
+    fn step_point(&self, point: usize, steps: i32, min: usize, max: usize) -> usize {
+        let step = (self.view.len() / TRIM_STEPS_PER_VIEW).max(1) as i64;
+        let point = point as i64 + steps as i64 * step;
+
+        point.clamp(min as i64, max as i64) as usize
+    }
+
+    /// Scrolls the view just as far as needed to show `position`.
+    fn reveal(&mut self, position: usize) {
+        let length = self.view.len();
+
+        if position < self.view.start {
+            self.show(position, length);
+        } else if position > self.view.end {
+            self.show(position - length, length);
+        } else {
+            self.changed = true;
+        }
+    }
+
+    /// Shows `length` samples from `start`, kept within the buffer.
+    fn show(&mut self, start: usize, length: usize) {
+        let start = start.min(self.buffer_length - length);
+
+        self.view = start..start + length;
+        self.changed = true;
+    }
+}
+
+impl Default for WaveformEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,126 @@
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Confirmation, requests and progress of an erase, shared between the control, the display
+/// and the idle task.
+///
+/// An erase fills an SDRAM buffer with silence. It can not be undone, so it has to be confirmed
+/// first. The buffer is zeroed in chunks by the idle task, which keeps the audio running.
+pub struct Erase {
+    confirming: AtomicBool,
+    requested: AtomicBool,
+    buffer: AtomicUsize,
+    length: AtomicUsize,
+    erased: AtomicUsize,
+    running: AtomicBool,
+}
+
+impl Erase {
+    pub const fn new() -> Self {
+        Erase {
+            confirming: AtomicBool::new(false),
+            requested: AtomicBool::new(false),
+            buffer: AtomicUsize::new(0),
+            length: AtomicUsize::new(0),
+            erased: AtomicUsize::new(0),
+            running: AtomicBool::new(false),
+        }
+    }
+
+    /// Asks for a confirmation, which the display shows. Ignored while an erase is running.
+    pub fn ask(&self) -> bool {
+        if self.is_running() {
+            return false;
+        }
+
+        self.confirming.store(true, Ordering::Relaxed);
+        true
+    }
+
+    pub fn is_confirming(&self) -> bool {
+        self.confirming.load(Ordering::Relaxed)
+    }
+
+    pub fn cancel(&self) {
+        self.confirming.store(false, Ordering::Relaxed);
+    }
+
+    /// Requests zeroing the first `length` samples of `buffer`. Ignored unless confirming.
+    pub fn confirm(&self, buffer: usize, length: usize) -> bool {
+        if !self.confirming.swap(false, Ordering::Relaxed) {
+            return false;
+        }
+
+        self.buffer.store(buffer, Ordering::Relaxed);
+        self.length.store(length, Ordering::Relaxed);
+        self.erased.store(0, Ordering::Relaxed);
+        self.requested.store(true, Ordering::Release);
+
+        true
+    }
+
+    /// Takes a pending request and marks the erase as running. Returns buffer and length.
+    pub fn take_request(&self) -> Option<(usize, usize)> {
+        if self.requested.swap(false, Ordering::Acquire) {
+            self.running.store(true, Ordering::Relaxed);
+
+            Some((
+                self.buffer.load(Ordering::Relaxed),
+                self.length.load(Ordering::Relaxed),
+            ))
+        } else {
+            None
+        }
+    }
+
+    pub fn set_erased(&self, erased: usize) {
+        self.erased.store(erased, Ordering::Relaxed);
+    }
+
+    pub fn finish(&self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed) || self.requested.load(Ordering::Relaxed)
+    }
+
+    /// Returns the progress in percent.
+    pub fn get_progress(&self) -> u32 {
+        let length = self.length.load(Ordering::Relaxed);
+
+        if length == 0 {
+            return 0;
+        }
+
+        ((self.erased.load(Ordering::Relaxed) * 100) / length) as u32
+    }
+}
+
+impl Default for Erase {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub static ERASE: Erase = Erase::new();
+
+/// State of the erase which is currently carried out by the idle task.
+pub struct EraseJob {
+    pub buffer: usize,
+    pub length: usize,
+    pub erased: usize,
+}
+
+impl EraseJob {
+    pub fn new(buffer: usize, length: usize) -> Self {
+        EraseJob {
+            buffer,
+            length,
+            erased: 0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.erased >= self.length
+    }
+}
@@ -0,0 +1,176 @@
+use crate::spsc::{Consumer, Producer, SpscQueue};
+
+/// Number of events which can be queued during one control cycle
+pub const EVENT_QUEUE_SIZE: usize = 32;
+
+/// Every physical or virtual input which is able to produce events.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Input {
+    Button,
+    EncoderSwitch,
+    /// Gate inputs 1 to 4 (zero indexed)
+    Gate(u8),
+    KillGate,
+    /// Buttons of an expansion panel (zero indexed)
+    ExpanderButton(u8),
+}
+
+/// Commands which are not bound to a physical input, e.g. sent by a console or a remote.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Command {
+    ToggleRecording,
+    StartRecording,
+    StopRecording,
+    RotateBuffer,
+    NextSlice,
+    JumpToSlice(u8),
+    UndoTake,
+    RecallScene(u8),
+}
+
+/// A normalized input event.
+///
+/// Hardware drivers only translate their state changes into events, so consumers never have to
+/// know where an event originates from.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Event {
+    Pressed(Input),
+    Released(Input),
+    Click(Input),
+    DoubleClick(Input),
+    Hold(Input),
+    /// Signed amount of detents the encoder moved since the last cycle, plain and accelerated
+    EncoderTurned {
+        detents: i32,
+        accelerated: i32,
+    },
+    NoteOn {
+        note: u8,
+        velocity: u8,
+    },
+    NoteOff {
+        note: u8,
+    },
+    ControlChange {
+        controller: u8,
+        value: u8,
+    },
+    Command(Command),
+}
+
+impl Event {
+    /// Returns `true` for events caused by someone operating the panel controls, gates and
+    /// remote messages do not count.
+    pub fn is_user_activity(&self) -> bool {
+        match self {
+            Event::Pressed(input)
+            | Event::Released(input)
+            | Event::Click(input)
+            | Event::DoubleClick(input)
+            | Event::Hold(input) => matches!(
+                input,
+                Input::Button | Input::EncoderSwitch | Input::ExpanderButton(_)
+            ),
+            Event::EncoderTurned { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+/// An event with the value of the cycle counter when it happened.
+///
+/// Lets a task which runs in blocks, like the audio task, apply the event at the right place of
+/// a block instead of at its start.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TimedEvent {
+    pub event: Event,
+    pub timestamp: u32,
+}
+
+impl TimedEvent {
+    /// Returns the frame of a block of `frames` frames at which the event happened. The block
+    /// started to arrive at the cycle count `block_start`.
+    pub fn get_frame(&self, block_start: u32, cycles_per_frame: u32, frames: usize) -> usize {
+        let elapsed = self.timestamp.wrapping_sub(block_start);
+
+        // events from before the block have been late and are applied at its start
+        if elapsed > i32::MAX as u32 {
+            return 0;
+        }
+
+        ((elapsed / cycles_per_frame) as usize).min(frames.saturating_sub(1))
+    }
+}
+
+/// End of an event queue in a static which pushes
+pub type EventProducer = Producer<'static, TimedEvent, EVENT_QUEUE_SIZE>;
+/// End of an event queue in a static which pops
+pub type EventConsumer = Consumer<'static, TimedEvent, EVENT_QUEUE_SIZE>;
+
+/// Timestamped gate edges from the interrupts to the audio task
+pub static AUDIO_EVENTS: SpscQueue<TimedEvent, EVENT_QUEUE_SIZE> = SpscQueue::new();
+/// Timestamped panel events from the control task to the audio task
+pub static PANEL_EVENTS: SpscQueue<TimedEvent, EVENT_QUEUE_SIZE> = SpscQueue::new();
+
+/// Fixed size FIFO of timestamped events which gets filled and drained once per control cycle.
+///
+/// When the queue is full, newly pushed events are dropped.
+pub struct EventQueue {
+    events: [Option<TimedEvent>; EVENT_QUEUE_SIZE],
+    read: usize,
+    len: usize,
+}
+
+impl EventQueue {
+    pub fn new() -> Self {
+        EventQueue {
+            events: [None; EVENT_QUEUE_SIZE],
+            read: 0,
+            len: 0,
+        }
+    }
+
+    /// Appends an event, returns `false` if the queue was full.
+    pub fn push(&mut self, event: TimedEvent) -> bool {
+        if self.len == EVENT_QUEUE_SIZE {
+            return false;
+        }
+
+        let write = (self.read + self.len) % EVENT_QUEUE_SIZE;
+        self.events[write] = Some(event);
+        self.len += 1;
+
+        true
+    }
+
+    /// Removes the oldest event.
+    pub fn pop(&mut self) -> Option<TimedEvent> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let event = self.events[self.read].take();
+        self.read = (self.read + 1) % EVENT_QUEUE_SIZE;
+        self.len -= 1;
+
+        event
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+impl Default for EventQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
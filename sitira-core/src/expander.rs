@@ -0,0 +1,151 @@
+use crate::event::{Event, Input};
+
+/// Buttons on the 16 pins of the MCP23017
+pub const EXPANDER_BUTTONS: usize = 16;
+/// Knobs on the 4 inputs of the ADS1115
+pub const EXPANDER_KNOBS: usize = 4;
+
+/// I2C address of the MCP23017 with A0 to A2 on ground
+pub const MCP23017_ADDRESS: u8 = 0x20;
+/// I2C address of the ADS1115 with ADDR on ground
+pub const ADS1115_ADDRESS: u8 = 0x48;
+
+/// Write which enables the pull-ups of port A and B, their registers follow each other
+pub const MCP23017_PULL_UPS: [u8; 3] = [0x0c, 0xff, 0xff];
+/// Input register of port A, the one of port B follows
+pub const MCP23017_GPIO: u8 = 0x12;
+
+/// Register with the result of the last conversion
+pub const ADS1115_CONVERSION: u8 = 0x00;
+/// Register which starts a conversion
+const ADS1115_CONFIG: u8 = 0x01;
+/// Reading of a knob at 3.3V, with the ±4.096V range of the ADS1115
+const ADS1115_FULL_SCALE: f32 = 32768.0 * 3.3 / 4.096;
+
+/// Returns the write which starts a single conversion of the knob on input `channel`.
+pub fn ads1115_start(channel: usize) -> [u8; 3] {
+    // against ground, ±4.096V, single shot at 860 samples per second, comparator off
+    let input = 0b100 | (channel as u16 & 0b11);
+    let config: u16 = 1 << 15 | input << 12 | 0b001 << 9 | 1 << 8 | 0b111 << 5 | 0b11;
+    let [high, low] = config.to_be_bytes();
+
+    [ADS1115_CONFIG, high, low]
+}
+
+/// Scales a conversion read from the ADS1115 to `0.0..=1.0`.
+pub fn ads1115_value(bytes: [u8; 2]) -> f32 {
+    (i16::from_be_bytes(bytes) as f32 / ADS1115_FULL_SCALE).clamp(0.0, 1.0)
+}
+
+/// State of an expansion panel with extra buttons on an MCP23017 and knobs on an ADS1115.
+///
+/// Turns what the expanders report into events and knob values, the driver only moves the
+/// bytes. The knobs get converted one after the other, one per poll, since a conversion takes
+/// longer than reading the buttons.
+pub struct ExpansionPanel {
+    /// One bit per pressed button
+    pressed: u16,
+    knobs: [f32; EXPANDER_KNOBS],
+    /// Knob whose conversion is running
+    knob: usize,
+}
+
+impl ExpansionPanel {
+    pub const fn new() -> Self {
+        ExpansionPanel {
+            pressed: 0,
+            knobs: [0.0; EXPANDER_KNOBS],
+            knob: 0,
+        }
+    }
+
+    /// Takes the levels of the ports, port A in the low byte, and reports every button which
+    /// changed. The pins are pulled up, so a pressed button reads low.
+    pub fn update_buttons<F>(&mut self, levels: u16, mut on_event: F)
+    where
+        F: FnMut(Event),
+    {
+        let pressed = !levels;
+        let changed = pressed ^ self.pressed;
+
+        for button in 0..EXPANDER_BUTTONS {
+            if changed & (1 << button) == 0 {
+                continue;
+            }
+
+            let input = Input::ExpanderButton(button as u8);
+
+            on_event(if pressed & (1 << button) != 0 {
+                Event::Pressed(input)
+            } else {
+                Event::Released(input)
+            });
+        }
+
+        self.pressed = pressed;
+    }
+
+    /// Takes the finished conversion of the running knob, returns the knob to convert next.
+    pub fn update_knob(&mut self, bytes: [u8; 2]) -> usize {
+        self.knobs[self.knob] = ads1115_value(bytes);
+        self.knob = (self.knob + 1) % EXPANDER_KNOBS;
+
+        self.knob
+    }
+
+    pub fn get_knobs(&self) -> [f32; EXPANDER_KNOBS] {
+        self.knobs
+    }
+}
+
+impl Default for ExpansionPanel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buttons_report_their_edges() {
+        let mut panel = ExpansionPanel::new();
+        let mut events = Vec::new();
+
+        panel.update_buttons(0xffff, |event| events.push(event));
+        assert!(events.is_empty());
+
+        panel.update_buttons(!0b1001, |event| events.push(event));
+        panel.update_buttons(!0b1000, |event| events.push(event));
+
+        assert_eq!(
+            events,
+            [
+                Event::Pressed(Input::ExpanderButton(0)),
+                Event::Pressed(Input::ExpanderButton(3)),
+                Event::Released(Input::ExpanderButton(0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn knobs_get_converted_in_turn() {
+        let mut panel = ExpansionPanel::new();
+
+        assert_eq!(ads1115_start(2), [0x01, 0xe3, 0xe3]);
+
+        assert_eq!(panel.update_knob([0x00, 0x00]), 1);
+        assert_eq!(panel.update_knob([0x80, 0x00]), 2);
+        assert_eq!(panel.update_knob([0x7f, 0xff]), 3);
+
+        let knobs = panel.get_knobs();
+        assert_eq!(knobs[0], 0.0);
+        assert_eq!(knobs[1], 0.0);
+        assert_eq!(knobs[2], 1.0);
+
+        panel.update_knob([0x33, 0x00]);
+        assert!((panel.get_knobs()[3] - 0.4945).abs() < 0.001);
+        assert_eq!(panel.update_knob([0, 0]), 1);
+    }
+}
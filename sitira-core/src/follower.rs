@@ -0,0 +1,43 @@
+use micromath::F32Ext;
+
+/// Follows the amplitude of a signal, rising with the attack time and falling with the release
+/// time.
+pub struct EnvelopeFollower {
+    attack: f32,
+    release: f32,
+    level: f32,
+}
+
+impl EnvelopeFollower {
+    pub fn new(sample_rate: f32, attack_in_ms: f32, release_in_ms: f32) -> Self {
+        EnvelopeFollower {
+            attack: coefficient(sample_rate, attack_in_ms),
+            release: coefficient(sample_rate, release_in_ms),
+            level: 0.0,
+        }
+    }
+
+    /// Feeds one sample and returns the new envelope level.
+    pub fn process(&mut self, sample: f32) -> f32 {
+        let input = sample.abs();
+        let coefficient = if input > self.level {
+            self.attack
+        } else {
+            self.release
+        };
+
+        self.level += (input - self.level) * coefficient;
+        self.level
+    }
+
+    pub fn get_level(&self) -> f32 {
+        self.level
+    }
+}
+
+/// One pole smoothing coefficient which reaches about 63% of a step within `time_in_ms`.
+fn coefficient(sample_rate: f32, time_in_ms: f32) -> f32 {
+    let samples = (time_in_ms * 0.001 * sample_rate).max(1.0);
+
+    1.0 - (-1.0 / samples).exp()
+}
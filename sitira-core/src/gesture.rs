@@ -0,0 +1,185 @@
+use crate::event::{Event, Input};
+
+/// Control cycles after which a press counts as hold
+pub const HOLD_TICKS: u32 = 20;
+/// Control cycles in which a second press counts as double click
+pub const DOUBLE_CLICK_TICKS: u32 = 10;
+
+/// Gestures recognized on a switch or button.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Gesture {
+    Click,
+    DoubleClick,
+    Hold,
+}
+
+impl Gesture {
+    /// Returns the event of this gesture on `input`.
+    pub fn to_event(self, input: Input) -> Event {
+        match self {
+            Gesture::Click => Event::Click(input),
+            Gesture::DoubleClick => Event::DoubleClick(input),
+            Gesture::Hold => Event::Hold(input),
+        }
+    }
+}
+
+/// Recognizes clicks, double clicks and holds from the edges of a switch.
+///
+/// Has to be ticked once per control cycle. A single click is only reported once no second
+/// press followed within the double click window, and a hold never turns into a click.
+pub struct GestureDetector {
+    hold_ticks: u32,
+    double_click_ticks: u32,
+    pressed_ticks: Option<u32>,
+    release_ticks: Option<u32>,
+    hold_reported: bool,
+}
+
+impl GestureDetector {
+    pub fn new(hold_ticks: u32, double_click_ticks: u32) -> Self {
+        GestureDetector {
+            hold_ticks,
+            double_click_ticks,
+            pressed_ticks: None,
+            release_ticks: None,
+            hold_reported: false,
+        }
+    }
+
+    /// Returns `true` while the switch is held down long enough to count as hold.
+    pub fn is_held(&self) -> bool {
+        self.hold_reported
+    }
+
+    /// Advances by one control cycle with the edges the switch had during it.
+    pub fn tick(&mut self, rising: bool, falling: bool) -> Option<Gesture> {
+        let mut gesture = None;
+
+        if rising {
+            // second press within the window
+            if self.release_ticks.take().is_some() {
+                gesture = Some(Gesture::DoubleClick);
+                self.pressed_ticks = None;
+            } else {
+                self.pressed_ticks = Some(0);
+            }
+        }
+
+        if let Some(ticks) = self.pressed_ticks.as_mut() {
+            *ticks += 1;
+
+            if *ticks >= self.hold_ticks && !self.hold_reported {
+                self.hold_reported = true;
+                gesture = Some(Gesture::Hold);
+            }
+        }
+
+        if falling {
+            // a hold never turns into a click
+            if self.pressed_ticks.take().is_some() && !self.hold_reported {
+                self.release_ticks = Some(0);
+            }
+
+            self.hold_reported = false;
+        }
+
+        // a single click is only certain when no second press followed
+        if let Some(ticks) = self.release_ticks.as_mut() {
+            *ticks += 1;
+
+            if *ticks > self.double_click_ticks {
+                self.release_ticks = None;
+                gesture = Some(Gesture::Click);
+            }
+        }
+
+        gesture
+    }
+}
+
+impl Default for GestureDetector {
+    fn default() -> Self {
+        Self::new(HOLD_TICKS, DOUBLE_CLICK_TICKS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ticks once per edge list entry and collects the gestures with their tick.
+    fn run(detector: &mut GestureDetector, edges: &[(bool, bool)]) -> Vec<(usize, Gesture)> {
+        edges
+            .iter()
+            .enumerate()
+            .filter_map(|(tick, &(rising, falling))| {
+                detector
+                    .tick(rising, falling)
+                    .map(|gesture| (tick, gesture))
+            })
+            .collect()
+    }
+
+    /// A press at tick 0 which is released after `held` ticks, followed by `idle` quiet ticks.
+    fn press(held: usize, idle: usize) -> Vec<(bool, bool)> {
+        let mut edges = vec![(false, false); held + idle + 1];
+        edges[0].0 = true;
+        edges[held].1 = true;
+        edges
+    }
+
+    #[test]
+    fn click_is_reported_after_the_double_click_window() {
+        let mut detector = GestureDetector::new(20, 10);
+        let gestures = run(&mut detector, &press(3, 20));
+
+        assert_eq!(gestures, vec![(3 + 10, Gesture::Click)]);
+    }
+
+    #[test]
+    fn second_press_in_the_window_is_a_double_click() {
+        let mut detector = GestureDetector::new(20, 10);
+        let mut edges = press(3, 5);
+        edges.extend(press(2, 20));
+
+        assert_eq!(run(&mut detector, &edges), vec![(9, Gesture::DoubleClick)]);
+    }
+
+    #[test]
+    fn presses_apart_are_two_clicks() {
+        let mut detector = GestureDetector::new(20, 10);
+        let mut edges = press(3, 12);
+        edges.extend(press(3, 12));
+
+        assert_eq!(
+            run(&mut detector, &edges),
+            vec![(13, Gesture::Click), (16 + 13, Gesture::Click)]
+        );
+    }
+
+    #[test]
+    fn long_press_is_a_hold_and_no_click() {
+        let mut detector = GestureDetector::new(20, 10);
+        let gestures = run(&mut detector, &press(30, 20));
+
+        assert_eq!(gestures, vec![(19, Gesture::Hold)]);
+    }
+
+    #[test]
+    fn hold_is_reported_while_held() {
+        let mut detector = GestureDetector::new(20, 10);
+
+        detector.tick(true, false);
+
+        for _ in 1..19 {
+            assert_eq!(detector.tick(false, false), None);
+            assert!(!detector.is_held());
+        }
+
+        assert_eq!(detector.tick(false, false), Some(Gesture::Hold));
+        assert!(detector.is_held());
+        assert_eq!(detector.tick(false, true), None);
+        assert!(!detector.is_held());
+    }
+}
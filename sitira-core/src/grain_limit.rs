@@ -0,0 +1,84 @@
+use crate::grains::MAX_GRAINS;
+
+/// Grains the limit never drops below, so a busy audio task still plays a thin cloud
+const MIN_LIMIT: f32 = 8.0;
+/// Grains the limit grows by per block while the load stays below the target
+const RECOVERY: f32 = 0.02;
+
+/// Cap on the grains a grain cloud may play, which follows the load of the audio task.
+///
+/// Once the load rises above the ceiling, the cap drops to the share of the playing grains which
+/// brings it back to the target. Below the target it grows back slowly, in between it holds. The
+/// grains which play already are never cut, the clouds only start fewer new ones.
+pub struct GrainLimit {
+    target: f32,
+    ceiling: f32,
+    limit: f32,
+}
+
+impl GrainLimit {
+    /// Creates a limit which lets all grains play, `target` and `ceiling` are shares of the block
+    /// time.
+    pub const fn new(target: f32, ceiling: f32) -> Self {
+        GrainLimit {
+            target,
+            ceiling,
+            limit: MAX_GRAINS as f32,
+        }
+    }
+
+    /// Takes the load of the last block and the most grains one cloud played during it, returns
+    /// the grains each cloud may play from now on.
+    pub fn update(&mut self, load: f32, playing: usize) -> usize {
+        if load > self.ceiling {
+            let fitting = playing as f32 * self.target / load;
+
+            self.limit = self.limit.min(fitting).max(MIN_LIMIT);
+        } else if load < self.target {
+            self.limit = (self.limit + RECOVERY).min(MAX_GRAINS as f32);
+        }
+
+        self.get()
+    }
+
+    pub fn get(&self) -> usize {
+        self.limit as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limit_follows_the_headroom() {
+        let mut limit = GrainLimit::new(0.75, 0.9);
+
+        assert_eq!(limit.update(0.5, 40), MAX_GRAINS);
+
+        // 100 grains take the whole block, 75 of them fit
+        assert_eq!(limit.update(1.0, 100), 75);
+        assert_eq!(limit.update(1.0, 100), 75);
+        assert_eq!(limit.update(0.8, 75), 75);
+
+        // two grains per 100 blocks
+        for _ in 0..110 {
+            limit.update(0.7, 75);
+        }
+
+        assert_eq!(limit.get(), 77);
+
+        for _ in 0..10000 {
+            limit.update(0.7, 75);
+        }
+
+        assert_eq!(limit.get(), MAX_GRAINS);
+    }
+
+    #[test]
+    fn limit_keeps_a_thin_cloud() {
+        let mut limit = GrainLimit::new(0.75, 0.9);
+
+        assert_eq!(limit.update(2.0, 4), MIN_LIMIT as usize);
+    }
+}
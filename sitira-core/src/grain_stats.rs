@@ -0,0 +1,198 @@
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::grains::{GrainDot, MAX_GRAINS};
+use crate::settings::UserSettings;
+use crate::timecode::TimeText;
+
+/// Statistics of the grain cloud, published by the audio task once per block and read by the
+/// control and display tasks.
+///
+/// Density, offset and pitch are the normalized values the grain cloud got driven with during
+/// the block. The load is measured, it is the share of the
+/// block time the audio task needed. Values are stored as raw `f32` bits in atomics, just like
+/// the level meters. The grain counts tell how many grains the density asks for and how many
+/// play, which the load limit may keep lower.
+pub struct GrainStats {
+    density: AtomicU32,
+    offset: AtomicU32,
+    pitch: AtomicU32,
+    load: AtomicU32,
+    requested: AtomicU32,
+    playing: AtomicU32,
+}
+
+impl GrainStats {
+    pub const fn new() -> Self {
+        GrainStats {
+            density: AtomicU32::new(0),
+            offset: AtomicU32::new(0),
+            pitch: AtomicU32::new(0),
+            load: AtomicU32::new(0),
+            requested: AtomicU32::new(0),
+            playing: AtomicU32::new(0),
+        }
+    }
+
+    /// Share of the grains which are active, `0.0` while the granulator is not playing.
+    pub fn get_density(&self) -> f32 {
+        f32::from_bits(self.density.load(Ordering::Relaxed))
+    }
+
+    /// Offset the grains get spawned at, normalized to the buffer length.
+    pub fn get_offset(&self) -> f32 {
+        f32::from_bits(self.offset.load(Ordering::Relaxed))
+    }
+
+    pub fn get_pitch(&self) -> f32 {
+        f32::from_bits(self.pitch.load(Ordering::Relaxed))
+    }
+
+    /// Share of the block time the audio task needed, above `1.0` it is overloaded.
+    pub fn get_load(&self) -> f32 {
+        f32::from_bits(self.load.load(Ordering::Relaxed))
+    }
+
+    /// Returns the grains the density asks for and the grains which play.
+    pub fn get_counts(&self) -> (usize, usize) {
+        (
+            self.requested.load(Ordering::Relaxed) as usize,
+            self.playing.load(Ordering::Relaxed) as usize,
+        )
+    }
+
+    pub fn publish_counts(&self, requested: usize, playing: usize) {
+        self.requested.store(requested as u32, Ordering::Relaxed);
+        self.playing.store(playing as u32, Ordering::Relaxed);
+    }
+
+    /// Publishes the settings the granulator played the last block with, `None` if it did not
+    /// play at all.
+    pub fn publish(&self, settings: Option<&UserSettings>, load: f32) {
+        let (density, offset, pitch) = match settings {
+            Some(settings) => (settings.active_grains, settings.offset, settings.pitch),
+            None => (0.0, self.get_offset(), self.get_pitch()),
+        };
+
+        self.density.store(density.to_bits(), Ordering::Relaxed);
+        self.offset.store(offset.to_bits(), Ordering::Relaxed);
+        self.pitch.store(pitch.to_bits(), Ordering::Relaxed);
+        self.load.store(load.to_bits(), Ordering::Relaxed);
+    }
+}
+
+impl Default for GrainStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Places of the grains which played at the end of the last block, published by the audio task
+/// and plotted by the grain view.
+///
+/// Every place is packed into one atomic, the position in the upper 16 bits and the pitch and
+/// the amplitude in a byte each. An amplitude of zero marks a place without a playing grain, so
+/// playing grains keep at least the lowest step.
+pub struct GrainSnapshot {
+    dots: [AtomicU32; MAX_GRAINS],
+}
+
+impl GrainSnapshot {
+    pub const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const EMPTY: AtomicU32 = AtomicU32::new(0);
+
+        GrainSnapshot {
+            dots: [EMPTY; MAX_GRAINS],
+        }
+    }
+
+    /// Publishes the dots of `GrainCloud::get_dots()`.
+    pub fn publish(&self, dots: impl Iterator<Item = Option<GrainDot>>) {
+        for (packed, dot) in self.dots.iter().zip(dots) {
+            packed.store(dot.map_or(0, pack), Ordering::Relaxed);
+        }
+    }
+
+    /// Empties the snapshot, e.g. while the granulator does not play.
+    pub fn clear(&self) {
+        for packed in self.dots.iter() {
+            packed.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the dots of the playing grains.
+    pub fn get_dots(&self) -> impl Iterator<Item = GrainDot> + '_ {
+        self.dots
+            .iter()
+            .filter_map(|packed| unpack(packed.load(Ordering::Relaxed)))
+    }
+}
+
+impl Default for GrainSnapshot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn pack(dot: GrainDot) -> u32 {
+    let position = (dot.position.clamp(0.0, 1.0) * u16::MAX as f32) as u32;
+    let pitch = (dot.pitch.clamp(0.0, 1.0) * u8::MAX as f32) as u32;
+    let amplitude = ((dot.amplitude.clamp(0.0, 1.0) * u8::MAX as f32) as u32).max(1);
+
+    position << 16 | pitch << 8 | amplitude
+}
+
+fn unpack(packed: u32) -> Option<GrainDot> {
+    let amplitude = packed & 0xff;
+
+    (amplitude != 0).then(|| GrainDot {
+        position: (packed >> 16) as f32 / u16::MAX as f32,
+        pitch: (packed >> 8 & 0xff) as f32 / u8::MAX as f32,
+        amplitude: amplitude as f32 / u8::MAX as f32,
+    })
+}
+
+/// Formats a normalized value as whole percent.
+pub fn format_percent(value: f32) -> TimeText {
+    let mut text = TimeText::new();
+
+    // the text always fits
+    let _ = write!(text, "{}%", (value.max(0.0) * 100.0 + 0.5) as u32);
+
+    text
+}
+
+/// Statistics of the granulator which plays the active buffer
+pub static GRAIN_STATS: GrainStats = GrainStats::new();
+
+/// Grains of the granulator which plays the active buffer
+pub static GRAIN_SNAPSHOT: GrainSnapshot = GrainSnapshot::new();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_keeps_the_playing_grains() {
+        let snapshot = GrainSnapshot::new();
+        let dot = GrainDot {
+            position: 0.25,
+            pitch: 1.0,
+            amplitude: 0.001,
+        };
+
+        snapshot.publish([None, Some(dot), None].into_iter());
+
+        let dots: Vec<GrainDot> = snapshot.get_dots().collect();
+
+        assert_eq!(dots.len(), 1);
+        assert!((dots[0].position - 0.25).abs() < 1e-4);
+        assert_eq!(dots[0].pitch, 1.0);
+        // quiet grains do not vanish
+        assert!(dots[0].amplitude > 0.0);
+
+        snapshot.clear();
+        assert_eq!(snapshot.get_dots().count(), 0);
+    }
+}
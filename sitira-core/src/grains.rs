@@ -0,0 +1,1024 @@
+use core::f32::consts::PI;
+
+use micromath::F32Ext;
+
+use crate::rng::{Rng, DEFAULT_SEED};
+use crate::settings::UserSettings;
+use crate::stretch::{self, StretchRanges};
+
+/// Grains which can play at once, the active grains parameter spans up to it
+pub const MAX_GRAINS: usize = 128;
+/// Share of the delay by which full swing lengthens the first and shortens the second of two
+/// grains, which splits their time 75 to 25
+const MAX_SWING: f32 = 0.5;
+/// Share of the delay by which full humanize moves a grain start at most, in either direction
+const MAX_JITTER: f32 = 0.25;
+/// Points of the window tables, the gains in between get interpolated
+const WINDOW_POINTS: usize = 256;
+/// Frames the grains get mixed in at once
+const RENDER_CHUNK: usize = 32;
+/// Frames over which a grain of the rectangle fades out, so it does not end with a click
+const RECTANGLE_FADE_FRAMES: usize = 16;
+/// Change of the window shape after which the window table gets computed again, so a noisy
+/// knob does not recompute it every block
+const SHAPE_RESOLUTION: f32 = 1.0 / 256.0;
+
+/// Shapes which fade a grain in and out.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Window {
+    Sine,
+    Hann,
+    Triangle,
+    Trapezoid,
+    Tukey,
+    Gaussian,
+    Rectangle,
+}
+
+impl Window {
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            0 => Window::Sine,
+            1 => Window::Hann,
+            2 => Window::Triangle,
+            3 => Window::Trapezoid,
+            4 => Window::Tukey,
+            5 => Window::Gaussian,
+            _ => Window::Rectangle,
+        }
+    }
+
+    /// Picks the window a knob points at, the rectangle only at its end.
+    pub fn from_parameter(value: f32) -> Self {
+        Window::from_u8((value.clamp(0.0, 1.0) * 6.0) as u8)
+    }
+
+    /// Returns `true` for the windows which `shape` changes.
+    pub fn is_shaped(&self) -> bool {
+        matches!(self, Window::Trapezoid | Window::Tukey | Window::Gaussian)
+    }
+
+    /// Gain at `phase`, which runs from `0.0` to `1.0` over the grain. `shape` sets the ramps of
+    /// the trapezoid and the Tukey window and the width of the Gaussian one. All windows but the
+    /// rectangle start and end at zero.
+    pub fn gain(&self, phase: f32, shape: f32) -> f32 {
+        let phase = phase.clamp(0.0, 1.0);
+        let shape = shape.clamp(0.01, 1.0);
+
+        match self {
+            Window::Sine => (PI * phase).sin(),
+            Window::Hann => 0.5 - 0.5 * (2.0 * PI * phase).cos(),
+            Window::Triangle => 1.0 - (2.0 * phase - 1.0).abs(),
+            Window::Trapezoid => (phase.min(1.0 - phase) * 2.0 / shape).min(1.0),
+            Window::Tukey => {
+                let edge = phase.min(1.0 - phase);
+
+                if edge < shape * 0.5 {
+                    0.5 - 0.5 * (2.0 * PI * edge / shape).cos()
+                } else {
+                    1.0
+                }
+            }
+            Window::Gaussian => {
+                let gaussian = |phase: f32| {
+                    let x = (phase - 0.5) / (shape * 0.5);
+
+                    (-0.5 * x * x).exp()
+                };
+                let edge = gaussian(0.0);
+
+                // lowered by what is left at the edges, so a wide one ends at zero as well
+                ((gaussian(phase) - edge) / (1.0 - edge)).max(0.0)
+            }
+            Window::Rectangle => 1.0,
+        }
+    }
+}
+
+/// Window sampled at `WINDOW_POINTS` steps, so the gains of a grain cost a lookup per frame
+/// instead of a sine or an exponential.
+struct WindowTable {
+    window: Window,
+    shape: f32,
+    gains: [f32; WINDOW_POINTS + 1],
+}
+
+impl WindowTable {
+    fn new(window: Window, shape: f32) -> Self {
+        let mut gains = [0.0; WINDOW_POINTS + 1];
+
+        for (point, gain) in gains.iter_mut().enumerate() {
+            *gain = window.gain(point as f32 / WINDOW_POINTS as f32, shape);
+        }
+
+        WindowTable {
+            window,
+            shape,
+            gains,
+        }
+    }
+
+    /// Returns the gain `elapsed` frames into a grain of `length` frames.
+    fn gain(&self, elapsed: usize, length: usize) -> f32 {
+        let mut gain = [0.0];
+        self.fill(&mut gain, elapsed, length);
+
+        gain[0]
+    }
+
+    /// Fills `gains` with the gains of a grain of `length` frames from `elapsed` frames on. The
+    /// last frame of the grain gets the gain of the end of the window.
+    fn fill(&self, gains: &mut [f32], elapsed: usize, length: usize) {
+        // the rectangle needs no lookups, only a fade at its end
+        if self.window == Window::Rectangle {
+            let fade = RECTANGLE_FADE_FRAMES.min(length / 2).max(1);
+
+            for (frame, gain) in gains.iter_mut().enumerate() {
+                let left = length.saturating_sub(elapsed + frame + 1);
+
+                *gain = (left as f32 / fade as f32).min(1.0);
+            }
+
+            return;
+        }
+
+        let step = WINDOW_POINTS as f32 / (length.max(2) - 1) as f32;
+        let mut position = elapsed as f32 * step;
+
+        for gain in gains.iter_mut() {
+            let point = (position as usize).min(WINDOW_POINTS - 1);
+            let fraction = (position - point as f32).min(1.0);
+
+            *gain = self.gains[point] + (self.gains[point + 1] - self.gains[point]) * fraction;
+            position += step;
+        }
+    }
+}
+
+/// Grains of a cloud, one array per property, so mixing a grain streams through its own values.
+struct Grains {
+    /// Sample each grain reads next and the fraction towards the one after it
+    index: [usize; MAX_GRAINS],
+    phase: [f32; MAX_GRAINS],
+    speed: [f32; MAX_GRAINS],
+    /// Normalized pitch the speed was set from
+    pitch: [f32; MAX_GRAINS],
+    amplitude: [f32; MAX_GRAINS],
+    length: [usize; MAX_GRAINS],
+    elapsed: [usize; MAX_GRAINS],
+    /// Frames of the envelope ramps on top of the window, `0` leaves the grain to its window
+    attack: [usize; MAX_GRAINS],
+    decay: [usize; MAX_GRAINS],
+}
+
+impl Grains {
+    const IDLE: Grains = Grains {
+        index: [0; MAX_GRAINS],
+        phase: [0.0; MAX_GRAINS],
+        speed: [1.0; MAX_GRAINS],
+        pitch: [0.5; MAX_GRAINS],
+        amplitude: [0.0; MAX_GRAINS],
+        length: [0; MAX_GRAINS],
+        elapsed: [0; MAX_GRAINS],
+        attack: [0; MAX_GRAINS],
+        decay: [0; MAX_GRAINS],
+    };
+
+    fn is_playing(&self, grain: usize) -> bool {
+        self.elapsed[grain] < self.length[grain]
+    }
+
+    fn remaining(&self, grain: usize) -> usize {
+        self.length[grain].saturating_sub(self.elapsed[grain])
+    }
+
+    fn is_shaped(&self, grain: usize) -> bool {
+        self.attack[grain] > 0 || self.decay[grain] > 0
+    }
+
+    /// Returns the gain of the envelope `elapsed` frames into the grain, linear ramps up over the
+    /// attack and down over the decay.
+    fn envelope(&self, grain: usize, elapsed: usize) -> f32 {
+        let (attack, decay) = (self.attack[grain], self.decay[grain]);
+        let left = self.length[grain].saturating_sub(elapsed);
+
+        let rise = if elapsed < attack {
+            elapsed as f32 / attack as f32
+        } else {
+            1.0
+        };
+        let fall = if left < decay {
+            left as f32 / decay as f32
+        } else {
+            1.0
+        };
+
+        rise * fall
+    }
+}
+
+/// Cloud of grains played from one buffer, driven by the `UserSettings`.
+///
+/// A new grain starts every `delay` as long as fewer than `active_grains` of `MAX_GRAINS` play,
+/// each with its own offset, length, pitch and velocity spread around the settings. Grains read
+/// the buffer like the varispeed, as sample index and phase, and wrap around its end. Every grain
+/// which played to its end gets counted, so the gate output can follow the actual grains. The
+/// spreads are drawn from a seeded `Rng`, so the same seed scatters the grains the same way again.
+/// Swing and humanize shift the starts of the grains off the even grid of the delay. An attack
+/// and a decay shape every grain on top of its window, each with a spread of its own. The pitch
+/// of every grain gets snapped to the scale and mode of the tuning in the settings.
+pub struct GrainCloud {
+    sample_rate: f32,
+    ranges: StretchRanges,
+    delay_range_in_ms: (f32, f32),
+    settings: UserSettings,
+    window: WindowTable,
+    swing: f32,
+    humanize: f32,
+    /// Shares of the grain length the envelope rises and falls over
+    attack: f32,
+    decay: f32,
+    attack_spread: f32,
+    decay_spread: f32,
+    /// Set while the next grain is the second of a swung pair
+    offbeat: bool,
+    grains: Grains,
+    /// Grains which may play at most, whatever the active grains ask for
+    limit: usize,
+    /// Frames until the next grain may start
+    countdown: usize,
+    finished: usize,
+    rng: Rng,
+}
+
+impl GrainCloud {
+    pub fn new(
+        sample_rate: usize,
+        ranges: StretchRanges,
+        delay_range_in_ms: (f32, f32),
+        settings: &UserSettings,
+    ) -> Self {
+        GrainCloud {
+            sample_rate: sample_rate as f32,
+            ranges,
+            delay_range_in_ms,
+            settings: *settings,
+            window: WindowTable::new(
+                Window::from_u8(settings.window_function),
+                settings.window_param,
+            ),
+            swing: 0.0,
+            humanize: 0.0,
+            attack: 0.0,
+            decay: 0.0,
+            attack_spread: 0.0,
+            decay_spread: 0.0,
+            offbeat: false,
+            grains: Grains::IDLE,
+            limit: MAX_GRAINS,
+            countdown: 0,
+            finished: 0,
+            rng: Rng::new(DEFAULT_SEED),
+        }
+    }
+
+    /// Takes over the settings for the grains which start from now on, playing ones keep theirs.
+    pub fn set_settings(&mut self, settings: &UserSettings) {
+        let window = Window::from_u8(settings.window_function);
+        let reshaped = (settings.window_param - self.window.shape).abs() > SHAPE_RESOLUTION;
+
+        if window != self.window.window || (window.is_shaped() && reshaped) {
+            self.window = WindowTable::new(window, settings.window_param);
+        }
+
+        self.settings = *settings;
+    }
+
+    /// Sets how far the grain starts get shifted, both from `0.0` to `1.0`. `swing` delays every
+    /// other grain and lets the next one follow sooner by as much, `humanize` moves every start by
+    /// a random amount.
+    pub fn set_timing(&mut self, swing: f32, humanize: f32) {
+        self.swing = swing.clamp(0.0, 1.0);
+        self.humanize = humanize.clamp(0.0, 1.0);
+    }
+
+    /// Sets the envelope of the grains which start from now on, `attack` and `decay` as shares of
+    /// the grain length from `0.0`, which leaves the grains to their window, to `1.0`, which ramps
+    /// over the whole grain. Short attacks with long decays sound percussive, long attacks swell.
+    pub fn set_envelope(&mut self, attack: f32, decay: f32) {
+        self.attack = attack.clamp(0.0, 1.0);
+        self.decay = decay.clamp(0.0, 1.0);
+    }
+
+    /// Sets by how much the attack and the decay of every grain may differ from the envelope.
+    pub fn set_envelope_spread(&mut self, attack: f32, decay: f32) {
+        self.attack_spread = attack.clamp(0.0, 1.0);
+        self.decay_spread = decay.clamp(0.0, 1.0);
+    }
+
+    /// Starts the randomization over from `seed` and lets the next grain start right away, so the
+    /// grains follow the same pattern as the last time with this seed and these settings.
+    pub fn reseed(&mut self, seed: u32) {
+        self.rng = Rng::new(seed);
+        self.countdown = 0;
+        self.offbeat = false;
+    }
+
+    /// Caps the grains which may play below the active grains, e.g. while the audio task runs out
+    /// of time. Grains which play already are not cut, just no new ones start.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit.min(MAX_GRAINS);
+    }
+
+    /// Returns the grains the active grains ask for.
+    pub fn get_requested(&self) -> usize {
+        (self.settings.active_grains.clamp(0.0, 1.0) * MAX_GRAINS as f32 + 0.5) as usize
+    }
+
+    /// Returns the grains which play right now.
+    pub fn get_playing(&self) -> usize {
+        (0..MAX_GRAINS)
+            .filter(|grain| self.grains.is_playing(*grain))
+            .count()
+    }
+
+    /// Returns a dot for every place of a grain, `None` for the ones which do not play.
+    pub fn get_dots(&self, buffer_length: usize) -> impl Iterator<Item = Option<GrainDot>> + '_ {
+        let grains = &self.grains;
+        let length = buffer_length.max(1) as f32;
+
+        (0..MAX_GRAINS).map(move |grain| {
+            grains.is_playing(grain).then(|| {
+                let elapsed = grains.elapsed[grain];
+                let window = self.window.gain(elapsed, grains.length[grain]);
+
+                GrainDot {
+                    position: (grains.index[grain] as f32 / length).min(1.0),
+                    pitch: grains.pitch[grain],
+                    amplitude: grains.amplitude[grain] * window * grains.envelope(grain, elapsed),
+                }
+            })
+        })
+    }
+
+    /// Returns how many grains played to their end since the last call.
+    pub fn take_finished(&mut self) -> usize {
+        core::mem::replace(&mut self.finished, 0)
+    }
+
+    /// Silences all grains at once, e.g. when their buffer is going to be recorded into.
+    pub fn stop(&mut self) {
+        self.grains = Grains::IDLE;
+    }
+
+    /// Returns the next sample of all grains played from `buffer`, see `render()`.
+    pub fn get_next_sample(&mut self, buffer: &[f32]) -> f32 {
+        let mut sample = [0.0];
+        self.render(buffer, &mut sample);
+
+        sample[0]
+    }
+
+    /// Adds the next `output.len()` samples of all grains played from `buffer` to `output`,
+    /// starting a grain whenever one is due.
+    ///
+    /// The block gets split where grains start. In between, every grain is mixed on its own, with
+    /// its window gains filled in one go, which sounds the same as mixing frame by frame.
+    pub fn render(&mut self, buffer: &[f32], output: &mut [f32]) {
+        let length = buffer.len();
+
+        if length < 2 {
+            return;
+        }
+
+        for output in output.chunks_mut(RENDER_CHUNK) {
+            let mut mix = [0.0; RENDER_CHUNK];
+            let mix = &mut mix[..output.len()];
+            let mut start = 0;
+
+            while start < mix.len() {
+                self.countdown = self.countdown.saturating_sub(1);
+
+                if self.countdown == 0 {
+                    self.spawn(length);
+                }
+
+                let frames = self.get_frames_to_spawn().min(mix.len() - start);
+
+                self.mix_grains(buffer, &mut mix[start..start + frames]);
+                self.countdown = self.countdown.saturating_sub(frames - 1);
+                start += frames;
+            }
+
+            let volume = self.settings.master_volume;
+
+            for (value, sum) in output.iter_mut().zip(mix.iter()) {
+                *value += sum * volume;
+            }
+        }
+    }
+
+    /// Returns the frames until a grain may start. While all active grains play, that is when the
+    /// first of them ends.
+    fn get_frames_to_spawn(&self) -> usize {
+        if self.countdown > 0 {
+            return self.countdown;
+        }
+
+        (0..MAX_GRAINS)
+            .filter(|grain| self.grains.is_playing(*grain))
+            .map(|grain| self.grains.remaining(grain))
+            .min()
+            .unwrap_or(RENDER_CHUNK)
+    }
+
+    /// Adds the playing grains to `mix`, within which no grain starts.
+    fn mix_grains(&mut self, buffer: &[f32], mix: &mut [f32]) {
+        let length = buffer.len();
+        let grains = &mut self.grains;
+        let mut gains = [0.0; RENDER_CHUNK];
+
+        for grain in 0..MAX_GRAINS {
+            if !grains.is_playing(grain) {
+                continue;
+            }
+
+            let elapsed = grains.elapsed[grain];
+            let gains = &mut gains[..grains.remaining(grain).min(mix.len())];
+            self.window.fill(gains, elapsed, grains.length[grain]);
+
+            if grains.is_shaped(grain) {
+                for (frame, gain) in gains.iter_mut().enumerate() {
+                    *gain *= grains.envelope(grain, elapsed + frame);
+                }
+            }
+
+            // buffer might have shrunk since the grain started
+            let start = grains.index[grain] % length;
+            let phase = grains.phase[grain];
+            let speed = grains.speed[grain];
+            let amplitude = grains.amplitude[grain];
+
+            // the position counts from the start of the stretch, so only an addition carries over
+            // from one frame to the next
+            let read = |position: f32| {
+                let whole = position as usize;
+                let mut index = start + whole;
+
+                if index >= length {
+                    index %= length;
+                }
+
+                (index, position - whole as f32)
+            };
+            let mut position = phase;
+
+            for (sum, gain) in mix.iter_mut().zip(gains.iter()) {
+                let (index, fraction) = read(position);
+                let next_index = if index + 1 < length { index + 1 } else { 0 };
+                let sample = buffer[index] + (buffer[next_index] - buffer[index]) * fraction;
+
+                *sum += sample * gain * amplitude;
+                position += speed;
+            }
+
+            let (index, fraction) = read(position);
+
+            grains.index[grain] = index;
+            grains.phase[grain] = fraction;
+            grains.elapsed[grain] += gains.len();
+
+            if !grains.is_playing(grain) {
+                self.finished = self.finished.saturating_add(1);
+            }
+        }
+    }
+
+    /// Starts a grain if fewer than the active grains play and sets the time until the next one.
+    fn spawn(&mut self, buffer_length: usize) {
+        let settings = self.settings;
+
+        if self.get_playing() >= self.get_requested().min(self.limit) {
+            return;
+        }
+
+        let rng = &mut self.rng;
+        let offset = spread(rng, settings.offset, settings.sp_offset);
+        let grain_size = spread(rng, settings.grain_size, settings.sp_grain_size);
+        let spread_pitch = spread(rng, settings.pitch, settings.sp_pitch);
+        let velocity = spread(rng, settings.velocity, settings.sp_velocity);
+        let delay = spread(rng, settings.delay, settings.sp_delay);
+        let attack = spread(rng, self.attack, self.attack_spread);
+        let decay = spread(rng, self.decay, self.decay_spread);
+
+        // a spread grain gets snapped to the tuning the pitch itself was tuned to
+        let semitones = settings.tuning.retune(self.ranges.semitones(spread_pitch));
+        let pitch = self.ranges.pitch(semitones);
+        let speed = stretch::speed_from_semitones(semitones);
+        let length = self.ms_to_frames(self.ranges.grain_in_ms(grain_size));
+
+        if let Some(free) = (0..MAX_GRAINS).find(|grain| !self.grains.is_playing(*grain)) {
+            let grains = &mut self.grains;
+
+            grains.index[free] = ((offset * buffer_length as f32) as usize).min(buffer_length - 1);
+            grains.phase[free] = 0.0;
+            grains.speed[free] = speed;
+            grains.pitch[free] = pitch;
+            grains.amplitude[free] = velocity;
+            grains.length[free] = length;
+            grains.elapsed[free] = 0;
+            grains.attack[free] = (attack * length as f32) as usize;
+            grains.decay[free] = (decay * length as f32) as usize;
+        }
+
+        let (min, max) = self.delay_range_in_ms;
+        let delay_in_ms = min + delay * (max - min);
+
+        // pairs of swung grains keep the time of two delays
+        let swing = if self.offbeat {
+            -self.swing
+        } else {
+            self.swing
+        } * MAX_SWING;
+        let jitter = if self.humanize > 0.0 {
+            self.rng.next_bipolar() * self.humanize * MAX_JITTER
+        } else {
+            0.0
+        };
+
+        self.offbeat = !self.offbeat;
+        self.countdown = self.ms_to_frames(delay_in_ms * (1.0 + swing + jitter));
+    }
+
+    fn ms_to_frames(&self, ms: f32) -> usize {
+        ((ms * self.sample_rate / 1000.0) as usize).max(1)
+    }
+}
+
+/// A playing grain as the grain view plots it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct GrainDot {
+    /// Sample the grain reads, normalized to the buffer length
+    pub position: f32,
+    /// Normalized pitch the grain was started with
+    pub pitch: f32,
+    /// Velocity of the grain times the gain of its window right now
+    pub amplitude: f32,
+}
+
+/// Moves `value` by a random amount of up to `amount` in either direction.
+fn spread(rng: &mut Rng, value: f32, amount: f32) -> f32 {
+    if amount <= 0.0 {
+        return value.clamp(0.0, 1.0);
+    }
+
+    (value + rng.next_bipolar() * amount).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quantizer::{Mode, Quantizer, Scale};
+
+    const RANGES: StretchRanges = StretchRanges {
+        min_grain_in_ms: 10.0,
+        max_grain_in_ms: 1000.0,
+        pitch_range_in_semitones: 24.0,
+    };
+
+    fn settings(active_grains: f32, grain_size: f32, delay: f32) -> UserSettings {
+        UserSettings {
+            master_volume: 1.0,
+            active_grains,
+            offset: 0.0,
+            grain_size,
+            pitch: 0.5,
+            delay,
+            velocity: 1.0,
+            sp_offset: 0.0,
+            sp_grain_size: 0.0,
+            sp_pitch: 0.0,
+            sp_delay: 0.0,
+            sp_velocity: 0.0,
+            window_function: Window::Rectangle as u8,
+            window_param: 0.5,
+            tuning: Quantizer::default(),
+        }
+    }
+
+    #[test]
+    fn windows_fade_in_and_out() {
+        for value in 0..7 {
+            let window = Window::from_u8(value);
+
+            assert!((window.gain(0.5, 0.5) - 1.0).abs() < 1e-3, "{:?}", window);
+
+            if window != Window::Rectangle {
+                assert!(window.gain(0.0, 0.5).abs() < 1e-3, "{:?}", window);
+                assert!(window.gain(1.0, 0.5).abs() < 1e-3, "{:?}", window);
+            }
+        }
+    }
+
+    #[test]
+    fn windows_are_symmetric_and_hann_ones_overlap_to_one() {
+        for value in 0..7 {
+            let window = Window::from_u8(value);
+
+            for step in 0..=100 {
+                let phase = step as f32 / 100.0;
+                let gain = window.gain(phase, 0.5);
+                let mirrored = window.gain(1.0 - phase, 0.5);
+
+                // the approximated sine may miss the bounds by a rounding error
+                assert!((-1e-3..=1.0 + 1e-3).contains(&gain), "{:?}", window);
+                assert!((gain - mirrored).abs() < 1e-3, "{:?}", window);
+            }
+        }
+
+        // grains of the Hann window which start half a grain apart play at an even level
+        for step in 0..=50 {
+            let phase = step as f32 / 100.0;
+            let sum = Window::Hann.gain(phase, 0.0) + Window::Hann.gain(phase + 0.5, 0.0);
+
+            assert!((sum - 1.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn window_tables_follow_the_windows() {
+        for value in 0..7 {
+            let window = Window::from_u8(value);
+            let table = WindowTable::new(window, 0.5);
+
+            // the rectangle fades out over its last frames
+            for elapsed in 0..1000 - RECTANGLE_FADE_FRAMES {
+                let expected = window.gain(elapsed as f32 / 999.0, 0.5);
+
+                assert!(
+                    (table.gain(elapsed, 1000) - expected).abs() < 1e-3,
+                    "{:?}",
+                    window
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn grains_end_at_zero() {
+        let buffer = [1.0; 1000];
+
+        for value in 0..7 {
+            // one grain of 100 frames, a wide Gaussian keeps the most at its edges
+            let mut settings = settings(1.0 / MAX_GRAINS as f32, 0.0, 1.0);
+            settings.window_function = value;
+            settings.window_param = 1.0;
+            let mut cloud = GrainCloud::new(10000, RANGES, (0.0, 1000.0), &settings);
+
+            let played: Vec<f32> = (0..100).map(|_| cloud.get_next_sample(&buffer)).collect();
+
+            assert!(played[50] > 0.5, "{}", value);
+            assert!(played[99].abs() < 1e-3, "{} ends at {}", value, played[99]);
+            assert_eq!(cloud.take_finished(), 1);
+        }
+    }
+
+    #[test]
+    fn spread_pitches_stay_on_the_scale() {
+        let buffer = [1.0; 1000];
+        let tuning = Quantizer::new(Scale::Pentatonic, Mode::Ionian, 2);
+        let mut settings = settings(0.5, 1.0, 0.0);
+        settings.pitch = RANGES.pitch(tuning.tune(0.0));
+        // an octave either way, so no grain gets clamped at the ends of the range
+        settings.sp_pitch = 0.25;
+        settings.tuning = tuning;
+        let mut cloud = GrainCloud::new(1000, RANGES, (0.0, 1000.0), &settings);
+
+        for _ in 0..100 {
+            cloud.get_next_sample(&buffer);
+        }
+
+        let pitches: Vec<f32> = cloud
+            .get_dots(buffer.len())
+            .flatten()
+            .map(|dot| RANGES.semitones(dot.pitch))
+            .collect();
+
+        assert!(pitches.len() > 10);
+        assert!(pitches.iter().any(|semitones| semitones.abs() > 1.0));
+
+        for semitones in pitches {
+            assert!(
+                (tuning.quantize(semitones) - semitones).abs() < 1e-3,
+                "{}",
+                semitones
+            );
+        }
+    }
+
+    #[test]
+    fn shape_changes_the_window_of_the_grains() {
+        let mut settings = settings(1.0, 0.5, 0.0);
+        settings.window_function = Window::Tukey as u8;
+        settings.window_param = 0.2;
+        let mut cloud = GrainCloud::new(1000, RANGES, (0.0, 1000.0), &settings);
+        let narrow = cloud.window.gain(100, 1000);
+
+        // a knob which jitters keeps the table
+        settings.window_param = 0.201;
+        cloud.set_settings(&settings);
+        assert_eq!(cloud.window.shape, 0.2);
+
+        settings.window_param = 0.8;
+        cloud.set_settings(&settings);
+
+        assert!((narrow - 1.0).abs() < 1e-3);
+        assert!(cloud.window.gain(100, 1000) < 0.5);
+        assert_eq!(Window::from_parameter(0.5), Window::Trapezoid);
+        assert_eq!(Window::from_parameter(1.0), Window::Rectangle);
+    }
+
+    #[test]
+    fn grains_play_the_buffer_at_the_offset() {
+        let buffer: Vec<f32> = (0..1000).map(|n| n as f32).collect();
+        let mut settings = settings(1.0 / MAX_GRAINS as f32, 0.5, 1.0);
+        settings.offset = 0.25;
+        let mut cloud = GrainCloud::new(1000, RANGES, (0.0, 1000.0), &settings);
+
+        let played: Vec<f32> = (0..4).map(|_| cloud.get_next_sample(&buffer)).collect();
+
+        assert_eq!(played, [250.0, 251.0, 252.0, 253.0]);
+        assert_eq!(cloud.get_playing(), 1);
+    }
+
+    #[test]
+    fn grains_stay_within_a_shrinking_buffer() {
+        let buffer: Vec<f32> = (0..1000).map(|n| (n as f32 * 0.1).sin()).collect();
+        // long grains from the very end, two octaves up
+        let mut settings = settings(1.0, 1.0, 0.0);
+        settings.offset = 1.0;
+        settings.pitch = 1.0;
+        settings.sp_offset = 1.0;
+        let mut cloud = GrainCloud::new(1000, RANGES, (0.0, 10.0), &settings);
+        let mut output = [0.0; 200];
+
+        cloud.render(&buffer, &mut output);
+        assert!(cloud.get_playing() > 1);
+
+        // the grains which play on read a buffer cut down while they play
+        for length in [100, 10, 2, 1] {
+            cloud.render(&buffer[..length], &mut output);
+
+            assert!(output.iter().all(|sample| sample.is_finite()));
+        }
+    }
+
+    #[test]
+    fn spread_stays_within_the_amount() {
+        let mut rng = Rng::new(DEFAULT_SEED);
+        let values: Vec<f32> = (0..1000).map(|_| spread(&mut rng, 0.5, 0.2)).collect();
+
+        assert!(values.iter().all(|value| (0.3..=0.7).contains(value)));
+        assert!(values.iter().any(|value| *value < 0.35));
+        assert!(values.iter().any(|value| *value > 0.65));
+
+        // at the edge the spread gets clamped instead of wrapped
+        assert!((0..1000).all(|_| (0.4..=1.0).contains(&spread(&mut rng, 0.9, 0.5))));
+        assert_eq!(spread(&mut rng, 0.5, 0.0), 0.5);
+    }
+
+    #[test]
+    fn spread_grain_sizes_stay_within_the_range() {
+        let buffer = [1.0; 1000];
+        let mut settings = settings(1.0, 0.5, 0.0);
+        settings.sp_grain_size = 0.25;
+        let mut cloud = GrainCloud::new(1000, RANGES, (0.0, 1.0), &settings);
+        let min = cloud.ms_to_frames(RANGES.grain_in_ms(0.25));
+        let max = cloud.ms_to_frames(RANGES.grain_in_ms(0.75));
+
+        for _ in 0..100 {
+            cloud.get_next_sample(&buffer);
+        }
+
+        let lengths: Vec<usize> = (0..MAX_GRAINS)
+            .filter(|grain| cloud.grains.is_playing(*grain))
+            .map(|grain| cloud.grains.length[grain])
+            .collect();
+
+        assert!(lengths.len() > 1);
+        assert!(lengths.iter().all(|length| (min..=max).contains(length)));
+        assert!(lengths.iter().any(|length| *length != lengths[0]));
+    }
+
+    #[test]
+    fn grain_times_follow_the_sample_rate() {
+        // grains of 10 ms every 100 ms
+        let settings = settings(1.0, 0.0, 0.1);
+
+        for sample_rate in [1000, 48000, 96000] {
+            let mut cloud = GrainCloud::new(sample_rate, RANGES, (0.0, 1000.0), &settings);
+            let frames = sample_rate / 10;
+
+            assert_eq!(get_gaps(&mut cloud, 2), [frames, frames]);
+            assert_eq!(cloud.grains.length[0], sample_rate / 100);
+        }
+    }
+
+    #[test]
+    fn finished_grains_get_counted() {
+        let buffer = [1.0; 1000];
+        // grains of 10 ms, one at a time
+        let settings = settings(1.0 / MAX_GRAINS as f32, 0.0, 0.0);
+        let mut cloud = GrainCloud::new(1000, RANGES, (0.0, 1000.0), &settings);
+
+        for _ in 0..95 {
+            cloud.get_next_sample(&buffer);
+        }
+
+        assert_eq!(cloud.take_finished(), 9);
+        assert_eq!(cloud.take_finished(), 0);
+    }
+
+    #[test]
+    fn same_seed_scatters_the_grains_the_same_way() {
+        let buffer: Vec<f32> = (0..1000).map(|n| (n as f32 * 0.1).sin()).collect();
+        let mut settings = settings(0.5, 0.2, 0.0);
+        settings.sp_offset = 1.0;
+        settings.sp_pitch = 0.5;
+        let mut cloud = GrainCloud::new(1000, RANGES, (0.0, 10.0), &settings);
+
+        let play = |cloud: &mut GrainCloud| -> Vec<f32> {
+            (0..500).map(|_| cloud.get_next_sample(&buffer)).collect()
+        };
+
+        cloud.reseed(7);
+        let first = play(&mut cloud);
+
+        cloud.stop();
+        cloud.reseed(7);
+        assert_eq!(play(&mut cloud), first);
+
+        cloud.stop();
+        cloud.reseed(8);
+        assert_ne!(play(&mut cloud), first);
+    }
+
+    #[test]
+    fn envelope_shapes_the_grains() {
+        let buffer = [1.0; 1000];
+        // one grain of 100 frames
+        let settings = settings(1.0 / MAX_GRAINS as f32, 0.0, 1.0);
+        let mut cloud = GrainCloud::new(10000, RANGES, (0.0, 1000.0), &settings);
+        let play = |cloud: &mut GrainCloud| -> Vec<f32> {
+            (0..100).map(|_| cloud.get_next_sample(&buffer)).collect()
+        };
+
+        cloud.set_envelope(0.5, 0.0);
+        let swell = play(&mut cloud);
+
+        assert_eq!(swell[0], 0.0);
+        assert!((swell[25] - 0.5).abs() < 1e-6);
+        // up to where the rectangle fades out
+        assert!(swell[50..100 - RECTANGLE_FADE_FRAMES]
+            .iter()
+            .all(|sample| *sample == 1.0));
+
+        cloud.reseed(0);
+        cloud.set_envelope(0.0, 1.0);
+        let percussive = play(&mut cloud);
+
+        assert_eq!(percussive[0], 1.0);
+        assert!((percussive[75] - 0.25).abs() < 1e-6);
+        assert!(percussive.windows(2).all(|pair| pair[1] < pair[0]));
+    }
+
+    #[test]
+    fn limit_caps_the_active_grains() {
+        let buffer = [1.0; 1000];
+        let mut cloud = GrainCloud::new(1000, RANGES, (0.0, 1000.0), &settings(0.5, 1.0, 0.0));
+        cloud.set_limit(10);
+
+        for _ in 0..100 {
+            cloud.get_next_sample(&buffer);
+        }
+
+        assert_eq!(cloud.get_requested(), MAX_GRAINS / 2);
+        assert_eq!(cloud.get_playing(), 10);
+
+        // the playing grains stay, new ones start once the limit is lifted
+        cloud.set_limit(5);
+        cloud.get_next_sample(&buffer);
+        assert_eq!(cloud.get_playing(), 10);
+
+        cloud.set_limit(MAX_GRAINS);
+
+        for _ in 0..100 {
+            cloud.get_next_sample(&buffer);
+        }
+
+        assert_eq!(cloud.get_playing(), MAX_GRAINS / 2);
+    }
+
+    #[test]
+    fn blocks_sound_like_single_frames() {
+        let buffer: Vec<f32> = (0..1000).map(|n| (n as f32 * 0.1).sin()).collect();
+        let mut settings = settings(0.5, 0.1, 0.02);
+        settings.sp_offset = 1.0;
+        settings.sp_grain_size = 0.5;
+        settings.sp_pitch = 0.5;
+        settings.sp_velocity = 0.5;
+        settings.window_function = Window::Hann as u8;
+
+        let mut by_frame = GrainCloud::new(1000, RANGES, (0.0, 100.0), &settings);
+        let mut by_block = GrainCloud::new(1000, RANGES, (0.0, 100.0), &settings);
+        by_frame.set_timing(0.5, 0.5);
+        by_block.set_timing(0.5, 0.5);
+
+        let frames: Vec<f32> = (0..2000)
+            .map(|_| by_frame.get_next_sample(&buffer))
+            .collect();
+        let mut blocks = vec![0.0; 2000];
+
+        for block in blocks.chunks_mut(48) {
+            by_block.render(&buffer, block);
+        }
+
+        // the read positions get rounded differently, but not by an audible amount
+        for (block, frame) in blocks.iter().zip(frames.iter()) {
+            assert!((block - frame).abs() < 1e-3, "{} {}", block, frame);
+        }
+
+        assert!(frames.iter().any(|frame| frame.abs() > 1.0));
+        assert_eq!(by_block.take_finished(), by_frame.take_finished());
+    }
+
+    /// Returns the frames between the starts of the first grains.
+    fn get_gaps(cloud: &mut GrainCloud, count: usize) -> Vec<usize> {
+        let buffer = [1.0; 1000];
+        let mut starts = Vec::new();
+        let mut frame = 0;
+
+        while starts.len() <= count {
+            let playing = cloud.get_playing();
+            cloud.get_next_sample(&buffer);
+
+            if cloud.get_playing() > playing {
+                starts.push(frame);
+            }
+
+            frame += 1;
+        }
+
+        starts.windows(2).map(|pair| pair[1] - pair[0]).collect()
+    }
+
+    #[test]
+    fn swing_alternates_the_gaps_between_grains() {
+        // grains of 10 ms every 100 ms
+        let mut cloud = GrainCloud::new(1000, RANGES, (0.0, 1000.0), &settings(1.0, 0.0, 0.1));
+
+        assert_eq!(get_gaps(&mut cloud, 4), [100, 100, 100, 100]);
+
+        cloud.set_timing(1.0, 0.0);
+        cloud.reseed(1);
+
+        assert_eq!(get_gaps(&mut cloud, 4), [150, 50, 150, 50]);
+    }
+
+    #[test]
+    fn humanize_moves_the_grains_within_bounds() {
+        let mut cloud = GrainCloud::new(1000, RANGES, (0.0, 1000.0), &settings(1.0, 0.0, 0.1));
+        cloud.set_timing(0.0, 1.0);
+
+        let gaps = get_gaps(&mut cloud, 16);
+
+        assert!(
+            gaps.iter().all(|gap| (75..=125).contains(gap)),
+            "{:?}",
+            gaps
+        );
+        assert!(gaps.iter().any(|gap| *gap != 100));
+    }
+
+    #[test]
+    fn active_grains_cap_the_playing_ones() {
+        let buffer = [1.0; 1000];
+        let mut cloud = GrainCloud::new(1000, RANGES, (0.0, 1000.0), &settings(0.25, 1.0, 0.0));
+
+        for _ in 0..100 {
+            cloud.get_next_sample(&buffer);
+        }
+
+        assert_eq!(cloud.get_playing(), MAX_GRAINS / 4);
+
+        // playing grains fade out once no more may start
+        cloud.set_settings(&settings(0.0, 1.0, 0.0));
+
+        for _ in 0..1000 {
+            cloud.get_next_sample(&buffer);
+        }
+
+        assert_eq!(cloud.get_playing(), 0);
+        assert_eq!(cloud.get_next_sample(&buffer), 0.0);
+    }
+}
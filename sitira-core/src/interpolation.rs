@@ -0,0 +1,94 @@
+use crate::settings::UserSettings;
+
+/// Number of continuous values of `UserSettings`
+const CONTINUOUS_VALUES: usize = 13;
+
+/// Glides the granulator settings from one control update to the next.
+///
+/// The control task only updates the settings every control cycle, which makes fast CVs audible
+/// as steps. Every audio block moves the continuous values a bit further towards the latest
+/// update, so they arrive there right when the next one is due. Discrete values, like the window
+/// function, are taken over directly.
+pub struct SettingsInterpolator {
+    from: [f32; CONTINUOUS_VALUES],
+    to: [f32; CONTINUOUS_VALUES],
+    /// Blocks since the latest update
+    block: u32,
+    blocks_per_update: u32,
+}
+
+impl SettingsInterpolator {
+    pub fn new(settings: &UserSettings, blocks_per_update: u32) -> Self {
+        let values = get_continuous_values(settings);
+
+        SettingsInterpolator {
+            from: values,
+            to: values,
+            block: 0,
+            blocks_per_update: blocks_per_update.max(1),
+        }
+    }
+
+    /// Advances by one block towards `target` and writes the result into `output`.
+    pub fn process(&mut self, target: &UserSettings, output: &mut UserSettings) {
+        let values = get_continuous_values(target);
+
+        // a new update starts from wherever the glide currently is
+        if values != self.to {
+            self.from = self.get_current();
+            self.to = values;
+            self.block = 0;
+        }
+
+        self.block = (self.block + 1).min(self.blocks_per_update);
+
+        output.window_function = target.window_function;
+        output.tuning = target.tuning;
+        set_continuous_values(output, &self.get_current());
+    }
+
+    fn get_current(&self) -> [f32; CONTINUOUS_VALUES] {
+        let progress = self.block as f32 / self.blocks_per_update as f32;
+        let mut values = self.to;
+
+        for (value, from) in values.iter_mut().zip(self.from) {
+            *value = from + (*value - from) * progress;
+        }
+
+        values
+    }
+}
+
+fn get_continuous_values(settings: &UserSettings) -> [f32; CONTINUOUS_VALUES] {
+    [
+        settings.master_volume,
+        settings.active_grains,
+        settings.offset,
+        settings.grain_size,
+        settings.pitch,
+        settings.delay,
+        settings.velocity,
+        settings.sp_offset,
+        settings.sp_grain_size,
+        settings.sp_pitch,
+        settings.sp_delay,
+        settings.sp_velocity,
+        settings.window_param,
+    ]
+}
+
+fn set_continuous_values(settings: &mut UserSettings, values: &[f32; CONTINUOUS_VALUES]) {
+    settings.master_volume = values[0];
+    settings.active_grains = values[1];
+    settings.offset = values[2];
+    settings.grain_size = values[3];
+    settings.pitch = values[4];
+    settings.delay = values[5];
+    settings.velocity = values[6];
+    settings.sp_offset = values[7];
+    settings.sp_grain_size = values[8];
+    settings.sp_pitch = values[9];
+    settings.sp_delay = values[10];
+    settings.sp_velocity = values[11];
+    settings.window_param = values[12];
+}
@@ -0,0 +1,289 @@
+use crate::event::{Event, Input};
+use crate::grains::GrainCloud;
+use crate::quantizer::Quantizer;
+use crate::routing::GATE_COUNT;
+use crate::settings::UserSettings;
+
+/// Pads of the kit, each one plays a slot of its own
+pub const KIT_PADS: usize = 4;
+/// MIDI note of the first pad, the others follow chromatically
+const FIRST_NOTE: u8 = 36;
+/// Shortest and longest burst of grains
+pub const BURST_RANGE_IN_MS: (u32, u32) = (10, 2000);
+/// Change of the burst length per encoder detent
+const BURST_STEP_IN_MS: u32 = 10;
+const DEFAULT_BURST_IN_MS: u32 = 250;
+
+/// A sample of the kit and how it gets triggered and played.
+pub struct KitPad {
+    pub slot: usize,
+    pub note: u8,
+    /// Gate input which triggers the pad, `None` if only its note does
+    pub gate: Option<u8>,
+    /// Time the pad keeps starting grains once triggered
+    pub burst_in_ms: u32,
+    /// Granulator settings of the pad, stored from the knobs
+    pub settings: UserSettings,
+}
+
+impl KitPad {
+    fn new(index: usize, settings: &UserSettings) -> Self {
+        KitPad {
+            slot: index,
+            note: FIRST_NOTE + index as u8,
+            gate: Some(index as u8),
+            burst_in_ms: DEFAULT_BURST_IN_MS,
+            settings: *settings,
+        }
+    }
+}
+
+/// Maps samples in several slots to gate inputs and MIDI notes, shared between the control, the
+/// display and the audio task.
+///
+/// While the kit mode is on, the gates and notes of the pads trigger them instead of what they
+/// are routed to, and the continuous grain cloud is muted. The control task marks triggered
+/// pads, the audio task takes them and starts a burst of grains from the slot of each one.
+pub struct Kit {
+    enabled: bool,
+    pads: [KitPad; KIT_PADS],
+    selected: usize,
+    /// Pads triggered since the audio task took them last, one bit per pad
+    triggered: u8,
+    changed: bool,
+}
+
+impl Kit {
+    /// Creates the kit with pad `n` playing slot `n`, every pad starts with `settings`.
+    pub fn new(settings: &UserSettings) -> Self {
+        Kit {
+            enabled: false,
+            pads: [
+                KitPad::new(0, settings),
+                KitPad::new(1, settings),
+                KitPad::new(2, settings),
+                KitPad::new(3, settings),
+            ],
+            selected: 0,
+            triggered: 0,
+            changed: true,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.triggered = 0;
+        self.changed = true;
+    }
+
+    pub fn get_pads(&self) -> &[KitPad] {
+        &self.pads
+    }
+
+    /// Gives access to the pads, e.g. to restore them from a session.
+    pub fn get_pads_mut(&mut self) -> &mut [KitPad] {
+        self.changed = true;
+        &mut self.pads
+    }
+
+    /// Returns the pad which is being edited.
+    pub fn get_selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn select(&mut self, steps: i32) {
+        self.selected = (self.selected as i32 + steps).rem_euclid(KIT_PADS as i32) as usize;
+        self.changed = true;
+    }
+
+    /// Moves the selected pad to another of `slot_count` slots.
+    pub fn step_slot(&mut self, steps: i32, slot_count: usize) {
+        let pad = &mut self.pads[self.selected];
+        pad.slot = (pad.slot as i32 + steps).rem_euclid(slot_count as i32) as usize;
+        self.changed = true;
+    }
+
+    pub fn step_note(&mut self, steps: i32) {
+        let pad = &mut self.pads[self.selected];
+        pad.note = (pad.note as i32 + steps).clamp(0, 127) as u8;
+        self.changed = true;
+    }
+
+    /// Steps the gate of the selected pad through all inputs and none.
+    pub fn step_gate(&mut self, steps: i32) {
+        let pad = &mut self.pads[self.selected];
+        let position = pad.gate.map_or(GATE_COUNT, |gate| gate as usize);
+        let position = (position as i32 + steps).rem_euclid(GATE_COUNT as i32 + 1) as usize;
+
+        pad.gate = if position < GATE_COUNT {
+            Some(position as u8)
+        } else {
+            None
+        };
+        self.changed = true;
+    }
+
+    pub fn step_burst(&mut self, steps: i32) {
+        let pad = &mut self.pads[self.selected];
+        let (min, max) = BURST_RANGE_IN_MS;
+        let burst = pad.burst_in_ms as i32 + steps * BURST_STEP_IN_MS as i32;
+
+        pad.burst_in_ms = burst.clamp(min as i32, max as i32) as u32;
+        self.changed = true;
+    }
+
+    /// Stores the settings into the selected pad and triggers it, so it can be heard.
+    pub fn store(&mut self, settings: &UserSettings) {
+        self.pads[self.selected].settings = *settings;
+        self.trigger(self.selected);
+    }
+
+    /// Snaps the grains of every pad to `tuning`, so they play in the same tuning as the cloud.
+    pub fn set_tuning(&mut self, tuning: Quantizer) {
+        for pad in self.pads.iter_mut() {
+            pad.settings.tuning = tuning;
+        }
+    }
+
+    /// Triggers the pad an event is mapped to while the kit mode is on. Returns `true` if a pad
+    /// took the event, which then must not be handled any further.
+    pub fn claim(&mut self, event: &Event) -> bool {
+        if !self.enabled {
+            return false;
+        }
+
+        let pad = match *event {
+            Event::Pressed(Input::Gate(gate)) => {
+                self.pads.iter().position(|pad| pad.gate == Some(gate))
+            }
+            Event::NoteOn { note, .. } => self.pads.iter().position(|pad| pad.note == note),
+            _ => None,
+        };
+
+        if let Some(pad) = pad {
+            self.trigger(pad);
+        }
+
+        pad.is_some()
+    }
+
+    pub fn trigger(&mut self, pad: usize) {
+        if pad < KIT_PADS {
+            self.triggered |= 1 << pad;
+        }
+    }
+
+    /// Returns the pads which were triggered since the last call, one bit per pad.
+    pub fn take_triggered(&mut self) -> u8 {
+        core::mem::replace(&mut self.triggered, 0)
+    }
+
+    /// Returns `true` once after a pad or the mode has changed, so the display knows when to
+    /// redraw.
+    pub fn take_changed(&mut self) -> bool {
+        core::mem::replace(&mut self.changed, false)
+    }
+}
+
+/// Plays the bursts of one pad with a grain cloud of its own, run by the audio task.
+pub struct KitVoice {
+    granulator: GrainCloud,
+    settings: UserSettings,
+    slot: usize,
+    /// Frames in which the burst still starts grains
+    spawning: usize,
+    /// Frames until the last grains of the burst have faded out
+    remaining: usize,
+    /// Frames the longest grain lasts, which get added to every burst
+    tail: usize,
+}
+
+impl KitVoice {
+    /// Creates a voice which plays its bursts with `granulator`.
+    pub fn new(granulator: GrainCloud, settings: &UserSettings, tail: usize) -> Self {
+        KitVoice {
+            granulator,
+            settings: *settings,
+            slot: 0,
+            spawning: 0,
+            remaining: 0,
+            tail,
+        }
+    }
+
+    /// Starts a burst of the pad, a running one starts over with the new settings.
+    pub fn trigger(&mut self, pad: &KitPad, sample_rate: usize) {
+        self.settings = pad.settings;
+        self.granulator.set_settings(&self.settings);
+        self.slot = pad.slot;
+        self.spawning = pad.burst_in_ms as usize * sample_rate / 1000;
+        self.remaining = self.spawning + self.tail;
+    }
+
+    /// Sets how far the grain starts get shifted, see `GrainCloud::set_timing`.
+    pub fn set_timing(&mut self, swing: f32, humanize: f32) {
+        self.granulator.set_timing(swing, humanize);
+    }
+
+    /// Sets the envelope of the grains, see `GrainCloud::set_envelope`.
+    pub fn set_envelope(&mut self, attack: f32, decay: f32) {
+        self.granulator.set_envelope(attack, decay);
+    }
+
+    pub fn set_envelope_spread(&mut self, attack: f32, decay: f32) {
+        self.granulator.set_envelope_spread(attack, decay);
+    }
+
+    /// Starts the randomization of the grains over from `seed`.
+    pub fn reseed(&mut self, seed: u32) {
+        self.granulator.reseed(seed);
+    }
+
+    /// Caps the grains of the bursts, see `GrainCloud::set_limit`.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.granulator.set_limit(limit);
+    }
+
+    pub fn get_playing(&self) -> usize {
+        self.granulator.get_playing()
+    }
+
+    /// Cuts the burst off, e.g. when its slot gets recorded into.
+    pub fn stop(&mut self) {
+        self.granulator.stop();
+        self.spawning = 0;
+        self.remaining = 0;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.remaining > 0
+    }
+
+    /// Returns the slot the burst plays from.
+    pub fn get_slot(&self) -> usize {
+        self.slot
+    }
+
+    /// Adds a block played from `buffer` to `output`, the grains stop starting once the burst is
+    /// over.
+    pub fn render(&mut self, buffer: &[f32], output: &mut [f32]) {
+        self.granulator.render(buffer, output);
+
+        let frames = output.len();
+
+        if self.spawning > 0 {
+            self.spawning = self.spawning.saturating_sub(frames);
+
+            if self.spawning == 0 {
+                self.settings.active_grains = 0.0;
+                self.granulator.set_settings(&self.settings);
+            }
+        }
+
+        self.remaining = self.remaining.saturating_sub(frames);
+    }
+}
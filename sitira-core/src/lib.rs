@@ -0,0 +1,79 @@
+//! Everything of Sitira which does not touch the hardware: the grain engine and the ones around it,
+//! the parameter model, the UI logic and the buffer bookkeeping.
+//!
+//! Builds for the host as well, so all of it can be tested without a Daisy Seed. The `std`
+//! feature links the standard library for host builds, and the tests link it as well. The
+//! `simulator` feature adds the `sitira-sim` binary on top.
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+// with `std` the float methods of micromath are shadowed by the inherent ones
+#![cfg_attr(any(test, feature = "std"), allow(unused_imports))]
+
+pub mod automation;
+pub mod bounce;
+pub mod calibration;
+pub mod card;
+pub mod clock;
+pub mod crossfade;
+pub mod curve;
+pub mod echo;
+pub mod editor;
+pub mod erase;
+pub mod event;
+pub mod expander;
+pub mod follower;
+pub mod gesture;
+pub mod grain_limit;
+pub mod grain_stats;
+pub mod grains;
+pub mod interpolation;
+pub mod kit;
+pub mod looper;
+pub mod mapping;
+pub mod memtest;
+pub mod menu;
+pub mod meter;
+pub mod metronome;
+pub mod midi;
+pub mod midi_learn;
+pub mod mixer;
+pub mod modulation;
+pub mod normalize;
+pub mod onset;
+pub mod output;
+pub mod oversample;
+pub mod pages;
+pub mod pitch_shift;
+pub mod pre_roll;
+pub mod pulse;
+pub mod quantizer;
+pub mod record_sync;
+pub mod resample;
+pub mod reverb;
+pub mod ring;
+pub mod rng;
+pub mod rotation;
+pub mod routing;
+pub mod scene;
+pub mod screen;
+pub mod scrub;
+pub mod session;
+pub mod settings;
+pub mod shift;
+pub mod slew;
+pub mod slices;
+pub mod soak;
+pub mod spectrum;
+pub mod spsc;
+pub mod stream;
+pub mod stretch;
+pub mod strings;
+pub mod tempo;
+pub mod texture;
+pub mod theme;
+pub mod timecode;
+pub mod transport;
+pub mod trim;
+pub mod usb_audio;
+pub mod varispeed;
+pub mod waveform;
+pub mod work;
@@ -0,0 +1,142 @@
+use micromath::F32Ext;
+
+use crate::varispeed::Varispeed;
+
+/// Plain loop player, the engine which takes the place of the granulator in looper mode.
+///
+/// Plays the take straight through at a variable speed, with the play head of the varispeed
+/// engine. The end of the take gets crossfaded into its start, so the loop is shorter than the
+/// take by the crossfade and its seam never clicks. While overdubbing, the input is added to the
+/// take where the play head passes, on top of what the take held lowered by the feedback.
+pub struct Looper {
+    head: Varispeed,
+    /// Frames of the crossfade at the seam
+    seam: usize,
+    /// Share of the take an overdub keeps on every pass
+    feedback: f32,
+}
+
+impl Looper {
+    pub fn new(seam: usize) -> Self {
+        Looper {
+            head: Varispeed::new(),
+            seam,
+            feedback: 1.0,
+        }
+    }
+
+    /// Sets the share of the take an overdub keeps where it passes, `1.0` keeps all of it and
+    /// `0.0` replaces it with the input.
+    pub fn set_feedback(&mut self, feedback: f32) {
+        self.feedback = feedback.clamp(0.0, 1.0);
+    }
+
+    /// Returns `true` once after the play head wrapped around either end of the loop.
+    pub fn take_wrapped(&mut self) -> bool {
+        self.head.take_wrapped()
+    }
+
+    /// Plays the next frames of `take` into `output` and moves the play head by `speed` samples
+    /// per frame. With `overdub`, every frame of it gets added to the take at the play head, after
+    /// the take got lowered by the feedback there.
+    pub fn render(
+        &mut self,
+        take: &mut [f32],
+        speed: f32,
+        overdub: Option<&[f32]>,
+        output: &mut [f32],
+    ) {
+        let seam = self.seam.min(take.len() / 2);
+        let length = take.len() - seam;
+
+        if length < 2 {
+            return;
+        }
+
+        for (frame, sample) in output.iter_mut().enumerate() {
+            // standing still only moves the play head back to the start if the take got shorter
+            self.head.advance(length, 0.0);
+
+            let (index, phase) = self.head.get_position();
+            let next = if index + 1 < length { index + 1 } else { 0 };
+            let current = read(take, index, length, seam);
+
+            *sample = current + (read(take, next, length, seam) - current) * phase;
+
+            if let Some(input) = overdub {
+                take[index] = take[index] * self.feedback + input[frame];
+            }
+
+            self.head.advance(length, speed);
+        }
+    }
+}
+
+/// Sample `index` of a loop of `length` frames, the first `seam` frames of which fade in while
+/// the ones behind the loop fade out.
+fn read(take: &[f32], index: usize, length: usize, seam: usize) -> f32 {
+    if index >= seam {
+        return take[index];
+    }
+
+    let fade = index as f32 / seam as f32;
+
+    take[index] * fade.sqrt() + take[length + index] * (1.0 - fade).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seam_fades_the_end_into_the_start() {
+        let mut looper = Looper::new(4);
+        let mut take: Vec<f32> = (0..16).map(|n| n as f32).collect();
+        let mut output = [0.0; 16];
+
+        looper.render(&mut take, 1.0, None, &mut output);
+
+        // the loop is 12 frames long, its start is still the end of the take
+        assert_eq!(output[0], 12.0);
+        assert_eq!(output[4..12], [4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0]);
+        assert_eq!(output[12], 12.0);
+        assert!(looper.take_wrapped());
+
+        // halfway through the seam both sides play at equal power
+        let half = 0.5f32.sqrt();
+        assert!((output[2] - (2.0 * half + 14.0 * half)).abs() < 1e-2);
+    }
+
+    #[test]
+    fn overdub_adds_the_input_at_the_play_head() {
+        let mut looper = Looper::new(0);
+        let mut take = vec![1.0; 8];
+        let mut output = [0.0; 12];
+
+        looper.render(&mut take, 1.0, Some(&[0.5; 12]), &mut output);
+
+        // the first four frames got two passes
+        assert_eq!(take[..4], [2.0; 4]);
+        assert_eq!(take[4..], [1.5; 4]);
+        assert_eq!(output[8..], [1.5; 4]);
+    }
+
+    #[test]
+    fn feedback_fades_the_take_on_every_pass() {
+        let mut looper = Looper::new(0);
+        let mut take = vec![1.0; 8];
+        let mut output = [0.0; 16];
+
+        looper.set_feedback(0.5);
+        looper.render(&mut take, 1.0, Some(&[0.0; 16]), &mut output);
+
+        // two passes halve the take twice, the second one plays what the first left
+        assert_eq!(take, [0.25; 8]);
+        assert_eq!(output[..8], [1.0; 8]);
+        assert_eq!(output[8..], [0.5; 8]);
+
+        // playing without an overdub keeps the take
+        looper.render(&mut take, 1.0, None, &mut output);
+        assert_eq!(take, [0.25; 8]);
+    }
+}
@@ -0,0 +1,490 @@
+use core::fmt::Write;
+
+use crate::expander::EXPANDER_KNOBS;
+use crate::modulation::{ModMatrix, ModRoute};
+
+/// Number of channels which can be mapped, the multiplexed ones and the knobs of an expansion
+/// panel
+pub const MAPPED_CHANNELS: usize = EXPANDER_CHANNEL + EXPANDER_KNOBS;
+/// First channel of the knobs of an expansion panel
+pub const EXPANDER_CHANNEL: usize = 16;
+/// Number of parameters, without `Parameter::None`
+pub const PARAMETER_COUNT: usize = 29;
+/// Number of CV inputs which are read directly instead of through the multiplexers
+pub const DIRECT_CV_COUNT: usize = 2;
+
+/// File on the SD card which replaces the default mapping
+pub const MAPPING_NAME: &str = "MAPPING.TXT";
+/// Longest mapping file which gets read
+pub const MAX_FILE_LENGTH: usize = 2048;
+
+/// Multiplexed channel of every control on the panel.
+pub enum AdcMuxInputs {
+    Offset = 0,
+    GrainSize = 1,
+    Pitch = 2,
+    VarispeedSpeed = 3,
+    PitchSpread = 4,
+    OffsetSpread = 5,
+    EngineBlend = 6,
+    GrainSizeSpread = 7,
+    Delay = 8,
+    ActiveGrains = 9,
+    Envelope = 10,
+    Velocity = 12,
+    DelaySpread = 13,
+    WaveSelect = 14,
+    VelocitySpread = 15,
+}
+
+/// Everything a multiplexed channel can control.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Parameter {
+    Offset,
+    GrainSize,
+    Pitch,
+    VarispeedSpeed,
+    PitchSpread,
+    OffsetSpread,
+    EngineBlend,
+    GrainSizeSpread,
+    Delay,
+    ActiveGrains,
+    Envelope,
+    Velocity,
+    DelaySpread,
+    WaveSelect,
+    VelocitySpread,
+    EchoTime,
+    EchoFeedback,
+    EchoMix,
+    ReverbSize,
+    ReverbMix,
+    TextureCrush,
+    TextureDownsample,
+    /// Settings of the second grain stream, which takes everything else from the first one
+    SecondOffset,
+    SecondGrainSize,
+    SecondPitch,
+    SecondGrains,
+    /// Direct CV inputs, sources of the modulation matrix
+    DirectCv1,
+    DirectCv2,
+    /// Source of the modulation matrix
+    Macro,
+    /// The channel is not used
+    None,
+}
+
+/// Every parameter with its name in the mapping file.
+const PARAMETER_NAMES: [(Parameter, &str); PARAMETER_COUNT + 1] = [
+    (Parameter::Offset, "offset"),
+    (Parameter::GrainSize, "grain_size"),
+    (Parameter::Pitch, "pitch"),
+    (Parameter::VarispeedSpeed, "varispeed_speed"),
+    (Parameter::PitchSpread, "pitch_spread"),
+    (Parameter::OffsetSpread, "offset_spread"),
+    (Parameter::EngineBlend, "engine_blend"),
+    (Parameter::GrainSizeSpread, "grain_size_spread"),
+    (Parameter::Delay, "delay"),
+    (Parameter::ActiveGrains, "active_grains"),
+    (Parameter::Envelope, "envelope"),
+    (Parameter::Velocity, "velocity"),
+    (Parameter::DelaySpread, "delay_spread"),
+    (Parameter::WaveSelect, "wave_select"),
+    (Parameter::VelocitySpread, "velocity_spread"),
+    (Parameter::EchoTime, "echo_time"),
+    (Parameter::EchoFeedback, "echo_feedback"),
+    (Parameter::EchoMix, "echo_mix"),
+    (Parameter::ReverbSize, "reverb_size"),
+    (Parameter::ReverbMix, "reverb_mix"),
+    (Parameter::TextureCrush, "texture_crush"),
+    (Parameter::TextureDownsample, "texture_downsample"),
+    (Parameter::SecondOffset, "second_offset"),
+    (Parameter::SecondGrainSize, "second_grain_size"),
+    (Parameter::SecondPitch, "second_pitch"),
+    (Parameter::SecondGrains, "second_grains"),
+    (Parameter::DirectCv1, "cv_1"),
+    (Parameter::DirectCv2, "cv_2"),
+    (Parameter::Macro, "macro"),
+    (Parameter::None, "none"),
+];
+
+/// Parameters of the echo, reverb and texture stages, in the order of the default shift bank.
+pub const FX_PARAMETERS: [Parameter; 7] = [
+    Parameter::EchoTime,
+    Parameter::EchoFeedback,
+    Parameter::EchoMix,
+    Parameter::ReverbSize,
+    Parameter::ReverbMix,
+    Parameter::TextureCrush,
+    Parameter::TextureDownsample,
+];
+
+/// Knobs of the shift bank which control the second grain stream, the ones of the delay and the
+/// shape page, which the effects leave free.
+const SECOND_STREAM: [(AdcMuxInputs, Parameter); 4] = [
+    (AdcMuxInputs::Delay, Parameter::SecondOffset),
+    (AdcMuxInputs::ActiveGrains, Parameter::SecondGrains),
+    (AdcMuxInputs::Envelope, Parameter::SecondGrainSize),
+    (AdcMuxInputs::Velocity, Parameter::SecondPitch),
+];
+
+/// Parameters the direct CV inputs set, in the order of their channels.
+pub const DIRECT_CV_PARAMETERS: [Parameter; DIRECT_CV_COUNT] =
+    [Parameter::DirectCv1, Parameter::DirectCv2];
+
+/// Wiring of the Sitira panel.
+const PANEL: [(AdcMuxInputs, Parameter); 15] = [
+    (AdcMuxInputs::Offset, Parameter::Offset),
+    (AdcMuxInputs::GrainSize, Parameter::GrainSize),
+    (AdcMuxInputs::Pitch, Parameter::Pitch),
+    (AdcMuxInputs::VarispeedSpeed, Parameter::VarispeedSpeed),
+    (AdcMuxInputs::PitchSpread, Parameter::PitchSpread),
+    (AdcMuxInputs::OffsetSpread, Parameter::OffsetSpread),
+    (AdcMuxInputs::EngineBlend, Parameter::EngineBlend),
+    (AdcMuxInputs::GrainSizeSpread, Parameter::GrainSizeSpread),
+    (AdcMuxInputs::Delay, Parameter::Delay),
+    (AdcMuxInputs::ActiveGrains, Parameter::ActiveGrains),
+    (AdcMuxInputs::Envelope, Parameter::Envelope),
+    (AdcMuxInputs::Velocity, Parameter::Velocity),
+    (AdcMuxInputs::DelaySpread, Parameter::DelaySpread),
+    (AdcMuxInputs::WaveSelect, Parameter::WaveSelect),
+    (AdcMuxInputs::VelocitySpread, Parameter::VelocitySpread),
+];
+
+impl Parameter {
+    pub fn from_name(name: &str) -> Option<Self> {
+        PARAMETER_NAMES
+            .iter()
+            .find(|(_, parameter_name)| *parameter_name == name)
+            .map(|(parameter, _)| *parameter)
+    }
+
+    /// Parameter with the discriminant `index`, `Parameter::None` past the last one.
+    pub fn from_index(index: usize) -> Self {
+        PARAMETER_NAMES
+            .get(index)
+            .map_or(Parameter::None, |(parameter, _)| *parameter)
+    }
+
+    /// Name of the parameter in the mapping file.
+    pub fn name(&self) -> &'static str {
+        PARAMETER_NAMES
+            .iter()
+            .find(|(parameter, _)| parameter == self)
+            .map_or("none", |(_, name)| *name)
+    }
+
+    /// Value of a parameter which is not mapped to any channel.
+    pub fn default_value(&self) -> f32 {
+        match self {
+            Parameter::GrainSize
+            | Parameter::Pitch
+            | Parameter::VarispeedSpeed
+            | Parameter::SecondGrainSize
+            | Parameter::SecondPitch => 0.5,
+            Parameter::ActiveGrains | Parameter::Velocity => 1.0,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Role of a single channel.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ChannelMapping {
+    pub parameter: Parameter,
+    pub invert: bool,
+    /// Parameter value at the lowest channel value
+    pub min: f32,
+    /// Parameter value at the highest channel value
+    pub max: f32,
+}
+
+impl ChannelMapping {
+    pub const fn new(parameter: Parameter) -> Self {
+        ChannelMapping {
+            parameter,
+            invert: false,
+            min: 0.0,
+            max: 1.0,
+        }
+    }
+
+    pub fn apply(&self, value: f32) -> f32 {
+        let value = if self.invert { 1.0 - value } else { value };
+
+        self.min + (self.max - self.min) * value
+    }
+}
+
+#[derive(Debug)]
+pub enum MappingError {
+    TooLong,
+    NotText,
+    /// Line (counted from 1) which could not be read
+    Syntax(usize),
+}
+
+/// Values of all parameters after mapping.
+#[derive(Clone, Copy)]
+pub struct ParameterValues {
+    values: [f32; PARAMETER_COUNT],
+}
+
+impl ParameterValues {
+    /// Creates the values of parameters which are not mapped to any channel.
+    pub fn new() -> Self {
+        let mut parameters = ParameterValues {
+            values: [0.0; PARAMETER_COUNT],
+        };
+
+        for (parameter, _) in PARAMETER_NAMES.iter().take(PARAMETER_COUNT) {
+            parameters.values[*parameter as usize] = parameter.default_value();
+        }
+
+        parameters
+    }
+
+    pub fn get(&self, parameter: Parameter) -> f32 {
+        match parameter {
+            Parameter::None => 0.0,
+            _ => self.values[parameter as usize],
+        }
+    }
+
+    pub fn set(&mut self, parameter: Parameter, value: f32) {
+        if parameter != Parameter::None {
+            self.values[parameter as usize] = value;
+        }
+    }
+}
+
+impl Default for ParameterValues {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Assigns the multiplexed channels to parameters.
+///
+/// Comes after the board configuration, which already takes care of how a panel revision routes
+/// the channels to the multiplexers. The mapping decides what each channel controls, so a panel
+/// with another layout works without code changes. Every channel can be inverted and scaled to a
+/// range. When several channels control the same parameter, the last one wins.
+#[derive(Clone, Copy)]
+pub struct ControlMap {
+    channels: [ChannelMapping; MAPPED_CHANNELS],
+}
+
+impl ControlMap {
+    /// Creates the mapping of the Sitira panel.
+    pub fn new() -> Self {
+        let mut channels = [ChannelMapping::new(Parameter::None); MAPPED_CHANNELS];
+
+        for (input, parameter) in PANEL {
+            channels[input as usize] = ChannelMapping::new(parameter);
+        }
+
+        ControlMap { channels }
+    }
+
+    /// Creates the default shift bank, where the first knobs control the effects, the next one
+    /// the macro and the rest the second grain stream.
+    pub fn shift() -> Self {
+        let mut channels = [ChannelMapping::new(Parameter::None); MAPPED_CHANNELS];
+
+        for (channel, parameter) in FX_PARAMETERS.iter().enumerate() {
+            channels[channel] = ChannelMapping::new(*parameter);
+        }
+
+        channels[FX_PARAMETERS.len()] = ChannelMapping::new(Parameter::Macro);
+
+        for (input, parameter) in SECOND_STREAM {
+            channels[input as usize] = ChannelMapping::new(parameter);
+        }
+
+        ControlMap { channels }
+    }
+
+    /// Returns the first channel which controls `parameter`.
+    pub fn get_channel(&self, parameter: Parameter) -> Option<usize> {
+        self.channels
+            .iter()
+            .position(|mapping| mapping.parameter == parameter)
+    }
+
+    pub fn get_mapping(&self, channel: usize) -> ChannelMapping {
+        self.channels[channel]
+    }
+}
+
+impl Default for ControlMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parts of the mapping file.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Panel,
+    Shift,
+    Matrix,
+}
+
+/// Mappings of both knob banks, the panel bank and the one active while shift is held, and the
+/// modulation matrix on top of them.
+#[derive(Clone, Copy)]
+pub struct ControlMaps {
+    pub panel: ControlMap,
+    pub shift: ControlMap,
+    pub matrix: ModMatrix,
+}
+
+impl ControlMaps {
+    pub fn new() -> Self {
+        ControlMaps {
+            panel: ControlMap::new(),
+            shift: ControlMap::shift(),
+            matrix: ModMatrix::new(),
+        }
+    }
+
+    /// Reads a mapping, where every line assigns a channel and unlisted channels keep their
+    /// default mapping. Lines after `[shift]` assign the channels of the shift bank. Routes
+    /// after `[matrix]` replace the default routes of the macro:
+    ///
+    /// ```text
+    /// # channel parameter [invert] [min max]
+    /// 3 pitch invert
+    /// 11 delay 0.0 0.5
+    ///
+    /// [shift]
+    /// 7 echo_mix 0.0 0.5
+    ///
+    /// [matrix]
+    /// # source destination depth
+    /// macro grain_size_spread 0.5
+    /// macro pitch -0.2
+    /// ```
+    pub fn parse(text: &str) -> Result<Self, MappingError> {
+        let mut maps = Self::new();
+        let mut section = Section::Panel;
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            match line {
+                "[shift]" => {
+                    section = Section::Shift;
+                    continue;
+                }
+                "[matrix]" => {
+                    section = Section::Matrix;
+                    maps.matrix = ModMatrix::empty();
+                    continue;
+                }
+                _ => (),
+            }
+
+            let error = MappingError::Syntax(index + 1);
+
+            let map = match section {
+                Section::Panel => &mut maps.panel,
+                Section::Shift => &mut maps.shift,
+                Section::Matrix => match ModRoute::parse(line) {
+                    Some(route) if maps.matrix.add(route) => continue,
+                    _ => return Err(error),
+                },
+            };
+
+            let mut words = line.split_whitespace();
+
+            let channel = match words.next().and_then(|word| word.parse::<usize>().ok()) {
+                Some(channel) if channel < MAPPED_CHANNELS => channel,
+                _ => return Err(error),
+            };
+
+            let parameter = match words.next().and_then(Parameter::from_name) {
+                Some(parameter) => parameter,
+                None => return Err(error),
+            };
+
+            let mut mapping = ChannelMapping::new(parameter);
+            let mut words = words.peekable();
+
+            if words.peek() == Some(&"invert") {
+                mapping.invert = true;
+                words.next();
+            }
+
+            match (words.next(), words.next(), words.next()) {
+                (None, None, None) => (),
+                (Some(min), Some(max), None) => match (min.parse(), max.parse()) {
+                    (Ok(min), Ok(max)) => {
+                        mapping.min = min;
+                        mapping.max = max;
+                    }
+                    _ => return Err(error),
+                },
+                _ => return Err(error),
+            }
+
+            map.channels[channel] = mapping;
+        }
+
+        Ok(maps)
+    }
+
+    /// Writes the mappings in the syntax `parse()` reads, every channel of both banks and all
+    /// routes.
+    pub fn write<W: Write>(&self, out: &mut W) -> core::fmt::Result {
+        writeln!(out, "# channel parameter [invert] [min max]")?;
+
+        for (header, map) in [("", &self.panel), ("[shift]\n", &self.shift)] {
+            out.write_str(header)?;
+
+            for (channel, mapping) in map.channels.iter().enumerate() {
+                write!(out, "{} {}", channel, mapping.parameter.name())?;
+
+                if mapping.invert {
+                    out.write_str(" invert")?;
+                }
+
+                writeln!(out, " {:.3} {:.3}", mapping.min, mapping.max)?;
+            }
+        }
+
+        writeln!(out, "[matrix]")?;
+
+        for route in self.matrix.get_routes() {
+            writeln!(
+                out,
+                "{} {} {:.3}",
+                route.source.name(),
+                route.destination.name(),
+                route.depth
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the content of a mapping file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MappingError> {
+        let text = core::str::from_utf8(bytes).map_err(|_| MappingError::NotText)?;
+
+        Self::parse(text)
+    }
+}
+
+impl Default for ControlMaps {
+    fn default() -> Self {
+        Self::new()
+    }
+}
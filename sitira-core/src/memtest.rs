@@ -0,0 +1,136 @@
+use core::fmt;
+
+/// Pattern of the address bus test, its inverse marks the location under test
+const ADDRESS_PATTERN: u32 = 0xaaaa_aaaa;
+
+/// Wiring or memory fault found by `test()`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MemoryFault {
+    /// Data lines which do not follow a walking one, as bit mask
+    DataLines(u32),
+    /// Lowest word address line which is stuck or shorted to another one
+    AddressLine(u32),
+    /// Number of words which lost their pattern, while the lines seem fine
+    Cells(usize),
+}
+
+impl MemoryFault {
+    pub fn description(&self) -> &'static str {
+        match self {
+            MemoryFault::DataLines(_) => "Data lines are faulty",
+            MemoryFault::AddressLine(_) => "Address lines are faulty",
+            MemoryFault::Cells(_) => "Memory cells are faulty",
+        }
+    }
+}
+
+impl fmt::Display for MemoryFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MemoryFault::DataLines(bits) => write!(f, "data lines {:#010x} faulty", bits),
+            MemoryFault::AddressLine(line) => write!(f, "address line {} faulty", line),
+            MemoryFault::Cells(failed) => write!(f, "{} words faulty", failed),
+        }
+    }
+}
+
+/// Tests the wiring of a memory and the cells of its first `words` words.
+///
+/// A walking one checks the data lines, writes to every power of two address check the address
+/// lines of the whole memory. Then an address derived pattern and its inverse are written to the
+/// tested words, which catches cells that do not keep their value. Everything the test touches
+/// is cleared afterwards. Returns the number of tested words.
+pub fn test(memory: &mut [f32], words: usize) -> Result<usize, MemoryFault> {
+    let words = words.min(memory.len());
+    let result = test_words(memory, words);
+
+    memory[..words].fill(0.0);
+
+    // the address test also writes behind the tested words
+    let mut offset = 1;
+    while offset < memory.len() {
+        memory[offset] = 0.0;
+        offset <<= 1;
+    }
+
+    result
+}
+
+fn test_words(memory: &mut [f32], words: usize) -> Result<usize, MemoryFault> {
+    let base = memory.as_mut_ptr() as *mut u32;
+    let len = memory.len();
+
+    // SAFETY: all indices lie within the slice, which is only used through `base` in here
+    let write = |index: usize, value: u32| unsafe { base.add(index).write_volatile(value) };
+    let read = |index: usize| unsafe { base.add(index).read_volatile() };
+
+    if len == 0 {
+        return Ok(0);
+    }
+
+    // walking one over the data lines
+    let mut faulty_bits = 0;
+
+    for bit in 0..32 {
+        write(0, 1 << bit);
+        faulty_bits |= read(0) ^ (1 << bit);
+    }
+
+    if faulty_bits != 0 {
+        return Err(MemoryFault::DataLines(faulty_bits));
+    }
+
+    // address lines, a stuck or shorted line makes two power of two addresses alias
+    let offsets = || {
+        (0..usize::BITS)
+            .map(|line| 1 << line)
+            .take_while(|&o| o < len)
+    };
+
+    for offset in offsets() {
+        write(offset, ADDRESS_PATTERN);
+    }
+
+    write(0, !ADDRESS_PATTERN);
+
+    for (line, offset) in offsets().enumerate() {
+        if read(offset) != ADDRESS_PATTERN {
+            return Err(MemoryFault::AddressLine(line as u32));
+        }
+    }
+
+    write(0, ADDRESS_PATTERN);
+
+    for (line, tested) in offsets().enumerate() {
+        write(tested, !ADDRESS_PATTERN);
+
+        let aliased = read(0) != ADDRESS_PATTERN
+            || offsets().any(|offset| offset != tested && read(offset) != ADDRESS_PATTERN);
+
+        if aliased {
+            return Err(MemoryFault::AddressLine(line as u32));
+        }
+
+        write(tested, ADDRESS_PATTERN);
+    }
+
+    // every cell holds its address and then the inverse, so every bit is set once
+    let mut failed = 0;
+
+    for pattern in [|index: usize| index as u32, |index: usize| !(index as u32)] {
+        for index in 0..words {
+            write(index, pattern(index));
+        }
+
+        for index in 0..words {
+            if read(index) != pattern(index) {
+                failed += 1;
+            }
+        }
+    }
+
+    match failed {
+        0 => Ok(words),
+        _ => Err(MemoryFault::Cells(failed)),
+    }
+}
@@ -0,0 +1,348 @@
+use crate::event::{Event, Input};
+use crate::strings::{self, UiText};
+
+/// Every entry of the menu which can be adjusted with the encoder.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MenuItem {
+    OffsetFine,
+    Parameters,
+    GrainView,
+    Spectrum,
+    GrainCount,
+    RotationDivision,
+    Slot,
+    EraseSlot,
+    SecondSlot,
+    EditZoom,
+    EditScroll,
+    TrimStart,
+    TrimEnd,
+    TrimReset,
+    BounceLength,
+    Bounce,
+    CurveChannel,
+    CurvePoints,
+    CurvePoint,
+    CurveInput,
+    CurveOutput,
+    CurveReset,
+    Scene,
+    SceneStore,
+    MorphSceneA,
+    MorphSceneB,
+    MorphSource,
+    SceneGates,
+    SceneGlide,
+    Automate,
+    AutomationBlend,
+    AutomationClear,
+    InputTrim,
+    RecordSync,
+    LoopQuantize,
+    Metronome,
+    LiveGranulation,
+    PreRoll,
+    Looper,
+    LoopDecay,
+    PitchShift,
+    Scale,
+    Mode,
+    Root,
+    FineTune,
+    GrainSeed,
+    Reroll,
+    Swing,
+    Humanize,
+    GrainAttack,
+    GrainDecay,
+    AttackSpread,
+    DecaySpread,
+    DelaySync,
+    NoteValues,
+    EchoTime,
+    EchoSync,
+    EchoFeedback,
+    EchoMix,
+    ReverbSize,
+    ReverbMix,
+    TextureCrush,
+    TextureDownsample,
+    MacroRoute,
+    MacroDepth,
+    SlewParameter,
+    SlewRise,
+    SlewFall,
+    MidiLearn,
+    MidiClear,
+    ExportFormat,
+    Export,
+    Sample,
+    LoadQuality,
+    LoadSample,
+    KitMode,
+    KitPad,
+    KitSlot,
+    KitNote,
+    KitGate,
+    KitBurst,
+    KitStore,
+    SaveSession,
+    LoadSession,
+    RetryCard,
+    Theme,
+}
+
+impl MenuItem {
+    /// Name of the item in the selected language.
+    pub fn name(&self) -> &'static str {
+        strings::get(UiText::Menu(*self))
+    }
+
+    /// Action items get executed on a press instead of being edited.
+    pub fn is_action(&self) -> bool {
+        matches!(
+            self,
+            MenuItem::EraseSlot
+                | MenuItem::TrimReset
+                | MenuItem::Bounce
+                | MenuItem::CurveReset
+                | MenuItem::Reroll
+                | MenuItem::SceneStore
+                | MenuItem::MidiClear
+                | MenuItem::AutomationClear
+                | MenuItem::Export
+                | MenuItem::LoadSample
+                | MenuItem::KitStore
+                | MenuItem::SaveSession
+                | MenuItem::LoadSession
+                | MenuItem::RetryCard
+        )
+    }
+
+    /// Fine items get adjusted by single detents without acceleration.
+    pub fn is_fine(&self) -> bool {
+        matches!(
+            self,
+            MenuItem::OffsetFine
+                | MenuItem::EditZoom
+                | MenuItem::CurveInput
+                | MenuItem::CurveOutput
+                | MenuItem::FineTune
+                | MenuItem::InputTrim
+        )
+    }
+
+    /// Curve items show the response curve editor on the display.
+    pub fn is_curve(&self) -> bool {
+        matches!(
+            self,
+            MenuItem::CurveChannel
+                | MenuItem::CurvePoints
+                | MenuItem::CurvePoint
+                | MenuItem::CurveInput
+                | MenuItem::CurveOutput
+                | MenuItem::CurveReset
+        )
+    }
+
+    /// Editor items show the zoomable waveform with the trim on the display.
+    pub fn is_editor(&self) -> bool {
+        matches!(
+            self,
+            MenuItem::EditZoom
+                | MenuItem::EditScroll
+                | MenuItem::TrimStart
+                | MenuItem::TrimEnd
+                | MenuItem::TrimReset
+        )
+    }
+
+    /// Kit items show the kit editor on the display.
+    pub fn is_kit(&self) -> bool {
+        matches!(
+            self,
+            MenuItem::KitMode
+                | MenuItem::KitPad
+                | MenuItem::KitSlot
+                | MenuItem::KitNote
+                | MenuItem::KitGate
+                | MenuItem::KitBurst
+                | MenuItem::KitStore
+        )
+    }
+}
+
+pub const MENU_ITEMS: [MenuItem; 86] = [
+    MenuItem::OffsetFine,
+    MenuItem::Parameters,
+    MenuItem::GrainView,
+    MenuItem::Spectrum,
+    MenuItem::GrainCount,
+    MenuItem::RotationDivision,
+    MenuItem::Slot,
+    MenuItem::EraseSlot,
+    MenuItem::SecondSlot,
+    MenuItem::EditZoom,
+    MenuItem::EditScroll,
+    MenuItem::TrimStart,
+    MenuItem::TrimEnd,
+    MenuItem::TrimReset,
+    MenuItem::BounceLength,
+    MenuItem::Bounce,
+    MenuItem::CurveChannel,
+    MenuItem::CurvePoints,
+    MenuItem::CurvePoint,
+    MenuItem::CurveInput,
+    MenuItem::CurveOutput,
+    MenuItem::CurveReset,
+    MenuItem::Scene,
+    MenuItem::SceneStore,
+    MenuItem::MorphSceneA,
+    MenuItem::MorphSceneB,
+    MenuItem::MorphSource,
+    MenuItem::SceneGates,
+    MenuItem::SceneGlide,
+    MenuItem::Automate,
+    MenuItem::AutomationBlend,
+    MenuItem::AutomationClear,
+    MenuItem::InputTrim,
+    MenuItem::RecordSync,
+    MenuItem::LoopQuantize,
+    MenuItem::Metronome,
+    MenuItem::LiveGranulation,
+    MenuItem::PreRoll,
+    MenuItem::Looper,
+    MenuItem::LoopDecay,
+    MenuItem::PitchShift,
+    MenuItem::Scale,
+    MenuItem::Mode,
+    MenuItem::Root,
+    MenuItem::FineTune,
+    MenuItem::GrainSeed,
+    MenuItem::Reroll,
+    MenuItem::Swing,
+    MenuItem::Humanize,
+    MenuItem::GrainAttack,
+    MenuItem::GrainDecay,
+    MenuItem::AttackSpread,
+    MenuItem::DecaySpread,
+    MenuItem::DelaySync,
+    MenuItem::NoteValues,
+    MenuItem::EchoTime,
+    MenuItem::EchoSync,
+    MenuItem::EchoFeedback,
+    MenuItem::EchoMix,
+    MenuItem::ReverbSize,
+    MenuItem::ReverbMix,
+    MenuItem::TextureCrush,
+    MenuItem::TextureDownsample,
+    MenuItem::MacroRoute,
+    MenuItem::MacroDepth,
+    MenuItem::SlewParameter,
+    MenuItem::SlewRise,
+    MenuItem::SlewFall,
+    MenuItem::MidiLearn,
+    MenuItem::MidiClear,
+    MenuItem::ExportFormat,
+    MenuItem::Export,
+    MenuItem::Sample,
+    MenuItem::LoadQuality,
+    MenuItem::LoadSample,
+    MenuItem::KitMode,
+    MenuItem::KitPad,
+    MenuItem::KitSlot,
+    MenuItem::KitNote,
+    MenuItem::KitGate,
+    MenuItem::KitBurst,
+    MenuItem::KitStore,
+    MenuItem::SaveSession,
+    MenuItem::LoadSession,
+    MenuItem::RetryCard,
+    MenuItem::Theme,
+];
+
+/// Requests an adjustment of a menu item, which is executed by the item's owner.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MenuAction {
+    Adjust(MenuItem, i32),
+    Execute(MenuItem),
+}
+
+/// Encoder driven menu.
+///
+/// Turning the encoder moves the cursor, clicking the encoder switch toggles between navigating
+/// and editing. While editing, turns are reported as (accelerated) adjustments of the selected
+/// item. Action items are executed right away when clicked. A double click returns to the top.
+pub struct Menu {
+    cursor: usize,
+    editing: bool,
+    changed: bool,
+}
+
+impl Menu {
+    pub fn new() -> Self {
+        Menu {
+            cursor: 0,
+            editing: false,
+            changed: true,
+        }
+    }
+
+    pub fn get_selected_item(&self) -> MenuItem {
+        MENU_ITEMS[self.cursor]
+    }
+
+    pub fn is_editing(&self) -> bool {
+        self.editing
+    }
+
+    /// Returns `true` once after the menu has changed, so the display knows when to redraw.
+    pub fn take_changed(&mut self) -> bool {
+        let changed = self.changed;
+        self.changed = false;
+        changed
+    }
+
+    /// Consumes an event and returns the requested adjustment, if there is any.
+    pub fn handle(&mut self, event: &Event) -> Option<MenuAction> {
+        match *event {
+            Event::Click(Input::EncoderSwitch) if self.get_selected_item().is_action() => {
+                Some(MenuAction::Execute(self.get_selected_item()))
+            }
+            Event::Click(Input::EncoderSwitch) => {
+                self.editing = !self.editing;
+                self.changed = true;
+                None
+            }
+            Event::DoubleClick(Input::EncoderSwitch) => {
+                self.editing = false;
+                self.cursor = 0;
+                self.changed = true;
+                None
+            }
+            Event::EncoderTurned {
+                detents,
+                accelerated,
+            } if self.editing => {
+                let item = self.get_selected_item();
+                let steps = if item.is_fine() { detents } else { accelerated };
+
+                self.changed = true;
+                Some(MenuAction::Adjust(item, steps))
+            }
+            Event::EncoderTurned { detents, .. } => {
+                let last = MENU_ITEMS.len() as i32 - 1;
+                self.cursor = (self.cursor as i32 + detents).clamp(0, last) as usize;
+                self.changed = true;
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Default for Menu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,137 @@
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use micromath::F32Ext;
+
+/// Absolute sample value which counts as clipping
+pub const CLIP_LEVEL: f32 = 0.99;
+
+/// Number of control cycles the clip indicator keeps flashing
+const CLIP_HOLD_CYCLES: u8 = 16;
+
+/// Level meter which is written by the audio task and read by the control and display tasks.
+///
+/// Values are stored as raw `f32` bits in atomics, so no task ever has to wait for a lock.
+pub struct Meter {
+    rms: AtomicU32,
+    peak: AtomicU32,
+    clipped: AtomicBool,
+}
+
+impl Meter {
+    pub const fn new() -> Self {
+        Meter {
+            rms: AtomicU32::new(0),
+            peak: AtomicU32::new(0),
+            clipped: AtomicBool::new(false),
+        }
+    }
+
+    pub fn get_rms(&self) -> f32 {
+        f32::from_bits(self.rms.load(Ordering::Relaxed))
+    }
+
+    pub fn get_peak(&self) -> f32 {
+        f32::from_bits(self.peak.load(Ordering::Relaxed))
+    }
+
+    /// Returns `true` if the signal clipped since the last call.
+    pub fn take_clipped(&self) -> bool {
+        self.clipped.swap(false, Ordering::Relaxed)
+    }
+
+    fn publish(&self, rms: f32, peak: f32) {
+        self.rms.store(rms.to_bits(), Ordering::Relaxed);
+        self.peak.store(peak.to_bits(), Ordering::Relaxed);
+
+        if peak >= CLIP_LEVEL {
+            self.clipped.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Default for Meter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Accumulates RMS and peak of one audio block.
+pub struct BlockMeter {
+    sum_of_squares: f32,
+    peak: f32,
+    count: usize,
+}
+
+impl BlockMeter {
+    pub fn new() -> Self {
+        BlockMeter {
+            sum_of_squares: 0.0,
+            peak: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn accumulate(&mut self, sample: f32) {
+        let magnitude = sample.abs();
+
+        self.sum_of_squares += sample * sample;
+        self.peak = self.peak.max(magnitude);
+        self.count += 1;
+    }
+
+    /// Publishes the values of the current block into `meter` and starts a new block.
+    pub fn publish(&mut self, meter: &Meter) {
+        if self.count > 0 {
+            let rms = (self.sum_of_squares / self.count as f32).sqrt();
+            meter.publish(rms, self.peak);
+        }
+
+        *self = Self::new();
+    }
+}
+
+impl Default for BlockMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Keeps an indicator flashing for a while after clipping occured.
+pub struct ClipIndicator {
+    remaining: u8,
+}
+
+impl ClipIndicator {
+    pub fn new() -> Self {
+        ClipIndicator { remaining: 0 }
+    }
+
+    /// (Re)starts flashing.
+    pub fn trigger(&mut self) {
+        self.remaining = CLIP_HOLD_CYCLES;
+    }
+
+    /// Advances by one control cycle. Returns the indicator state while flashing, `None` otherwise.
+    pub fn tick(&mut self) -> Option<bool> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        self.remaining -= 1;
+
+        Some(self.remaining.is_multiple_of(2))
+    }
+}
+
+impl Default for ClipIndicator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Metered level of the incoming audio
+pub static INPUT_METER: Meter = Meter::new();
+/// Metered level of the incoming audio after the input trim, as it gets recorded
+pub static RECORD_METER: Meter = Meter::new();
+/// Metered level of the outgoing audio
+pub static OUTPUT_METER: Meter = Meter::new();
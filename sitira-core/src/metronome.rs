@@ -0,0 +1,119 @@
+use micromath::F32Ext;
+
+use crate::tempo::TempoSource;
+
+/// Pitch of the click on the first beat of a bar
+const ACCENT_FREQUENCY_IN_HZ: f32 = 2000.0;
+/// Pitch of the clicks on the other beats
+const BEAT_FREQUENCY_IN_HZ: f32 = 1000.0;
+/// Time in which a click decays to about a third
+const CLICK_DECAY_IN_MS: f32 = 8.0;
+/// Level below which a click counts as silent
+const SILENCE: f32 = 0.0001;
+
+/// Coefficients of the click resonator at one pitch.
+struct Oscillator {
+    coefficient: f32,
+    sine: f32,
+}
+
+impl Oscillator {
+    fn new(sample_rate: f32, frequency: f32) -> Self {
+        let omega = 2.0 * core::f32::consts::PI * frequency / sample_rate;
+
+        Oscillator {
+            coefficient: 2.0 * omega.cos(),
+            sine: omega.sin(),
+        }
+    }
+}
+
+/// Synthesizes a click on every beat of the tempo.
+///
+/// Clicks are decaying sine bursts, generated with a resonator, so no table or trigonometry is
+/// needed per sample. With the external clock its edges trigger the beats, a tapped tempo gets
+/// counted out.
+pub struct Metronome {
+    beat: Oscillator,
+    accent: Oscillator,
+    decay: f32,
+    beats_per_bar: usize,
+    /// Beat within the bar which is clicked next
+    next_beat: usize,
+    since_beat: usize,
+    downbeat_pending: bool,
+    coefficient: f32,
+    previous: f32,
+    current: f32,
+    level: f32,
+}
+
+impl Metronome {
+    pub fn new(sample_rate: f32, beats_per_bar: usize) -> Self {
+        Metronome {
+            beat: Oscillator::new(sample_rate, BEAT_FREQUENCY_IN_HZ),
+            accent: Oscillator::new(sample_rate, ACCENT_FREQUENCY_IN_HZ),
+            decay: (-1000.0 / (CLICK_DECAY_IN_MS * sample_rate)).exp(),
+            beats_per_bar: beats_per_bar.max(1),
+            next_beat: 0,
+            since_beat: 0,
+            downbeat_pending: false,
+            coefficient: 0.0,
+            previous: 0.0,
+            current: 0.0,
+            level: 0.0,
+        }
+    }
+
+    /// Clicks the first beat of a bar with the next sample, e.g. when a recording starts.
+    pub fn restart(&mut self) {
+        self.downbeat_pending = true;
+        self.next_beat = 0;
+    }
+
+    /// Renders one sample of the click track. `edge` marks an edge of the external clock.
+    pub fn process(&mut self, tempo: Option<(usize, TempoSource)>, edge: bool) -> f32 {
+        self.since_beat = self.since_beat.saturating_add(1);
+
+        let beat = self.downbeat_pending
+            || match tempo {
+                Some((_, TempoSource::Clock)) => edge,
+                Some((period, TempoSource::Tap)) => self.since_beat >= period,
+                None => false,
+            };
+
+        if beat {
+            self.click();
+        }
+
+        if self.level < SILENCE {
+            return 0.0;
+        }
+
+        let next = self.coefficient * self.current - self.previous;
+
+        self.previous = self.current;
+        self.current = next;
+        self.level *= self.decay;
+
+        self.previous * self.level
+    }
+
+    fn click(&mut self) {
+        let oscillator = if self.next_beat == 0 {
+            &self.accent
+        } else {
+            &self.beat
+        };
+
+        // the resonator continues from a zero crossing, so the click starts without a step
+        self.coefficient = oscillator.coefficient;
+        self.previous = -oscillator.sine;
+        self.current = 0.0;
+        self.level = 1.0;
+
+        self.next_beat = (self.next_beat + 1) % self.beats_per_bar;
+        self.since_beat = 0;
+        self.downbeat_pending = false;
+    }
+}
@@ -0,0 +1,191 @@
+use crate::event::Event;
+
+/// Baud rate of a MIDI DIN connection
+pub const MIDI_BAUD_RATE: u32 = 31_250;
+
+const NOTE_OFF: u8 = 0x80;
+const NOTE_ON: u8 = 0x90;
+const CONTROL_CHANGE: u8 = 0xb0;
+const SYSTEM_EXCLUSIVE: u8 = 0xf0;
+const REAL_TIME: u8 = 0xf8;
+
+/// Turns the bytes of a MIDI stream into note and control change events.
+///
+/// Follows running status, so messages without a status byte continue the last one. Real time
+/// messages like the clock may show up anywhere and get skipped, as do system messages and every
+/// channel message other than notes and control changes. A note on with velocity 0 is a note off.
+pub struct MidiParser {
+    /// Channel to listen to (zero indexed), `None` listens to all of them
+    channel: Option<u8>,
+    status: Option<u8>,
+    data: [u8; 2],
+    received: usize,
+}
+
+impl MidiParser {
+    pub fn new(channel: Option<u8>) -> Self {
+        MidiParser {
+            channel,
+            status: None,
+            data: [0; 2],
+            received: 0,
+        }
+    }
+
+    /// Consumes the next byte and returns the event of a message it completes.
+    pub fn parse(&mut self, byte: u8) -> Option<Event> {
+        if byte >= REAL_TIME {
+            return None;
+        }
+
+        if byte & 0x80 != 0 {
+            // system messages cancel the running status
+            self.status = (byte < SYSTEM_EXCLUSIVE).then_some(byte);
+            self.received = 0;
+            return None;
+        }
+
+        let status = self.status?;
+
+        self.data[self.received] = byte;
+        self.received += 1;
+
+        if self.received < get_data_length(status) {
+            return None;
+        }
+
+        self.received = 0;
+
+        if self.channel.is_some_and(|channel| channel != status & 0x0f) {
+            return None;
+        }
+
+        let [first, second] = self.data;
+
+        match status & 0xf0 {
+            NOTE_ON if second > 0 => Some(Event::NoteOn {
+                note: first,
+                velocity: second,
+            }),
+            NOTE_ON | NOTE_OFF => Some(Event::NoteOff { note: first }),
+            CONTROL_CHANGE => Some(Event::ControlChange {
+                controller: first,
+                value: second,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl Default for MidiParser {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+/// Data bytes of a channel message with `status`.
+fn get_data_length(status: u8) -> usize {
+    match status & 0xf0 {
+        // program change and channel pressure
+        0xc0 | 0xd0 => 1,
+        _ => 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_all(parser: &mut MidiParser, bytes: &[u8]) -> Vec<Event> {
+        bytes
+            .iter()
+            .filter_map(|byte| parser.parse(*byte))
+            .collect()
+    }
+
+    #[test]
+    fn parses_notes_and_control_changes() {
+        let mut parser = MidiParser::default();
+        let events = parse_all(&mut parser, &[0x90, 36, 100, 0x80, 36, 0, 0xb3, 7, 127]);
+
+        assert_eq!(
+            events,
+            [
+                Event::NoteOn {
+                    note: 36,
+                    velocity: 100
+                },
+                Event::NoteOff { note: 36 },
+                Event::ControlChange {
+                    controller: 7,
+                    value: 127
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn running_status_continues_the_last_message() {
+        let mut parser = MidiParser::default();
+        let events = parse_all(&mut parser, &[0x90, 36, 100, 38, 90, 36, 0]);
+
+        assert_eq!(
+            events,
+            [
+                Event::NoteOn {
+                    note: 36,
+                    velocity: 100
+                },
+                Event::NoteOn {
+                    note: 38,
+                    velocity: 90
+                },
+                Event::NoteOff { note: 36 },
+            ]
+        );
+    }
+
+    #[test]
+    fn real_time_messages_get_skipped_within_a_message() {
+        let mut parser = MidiParser::default();
+        let events = parse_all(&mut parser, &[0x90, 0xf8, 36, 0xfe, 100]);
+
+        assert_eq!(
+            events,
+            [Event::NoteOn {
+                note: 36,
+                velocity: 100
+            }]
+        );
+    }
+
+    #[test]
+    fn system_exclusive_and_other_messages_get_skipped() {
+        let mut parser = MidiParser::default();
+        let bytes = [
+            0xf0, 0x7e, 36, 100, 0xf7, 36, 100, 0xc0, 5, 0xe0, 0, 64, 0x90, 40, 1,
+        ];
+
+        assert_eq!(
+            parse_all(&mut parser, &bytes),
+            [Event::NoteOn {
+                note: 40,
+                velocity: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn other_channels_get_filtered() {
+        let mut parser = MidiParser::new(Some(1));
+        let events = parse_all(&mut parser, &[0x90, 36, 100, 0x91, 37, 100]);
+
+        assert_eq!(
+            events,
+            [Event::NoteOn {
+                note: 37,
+                velocity: 100
+            }]
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::event::Event;
+use crate::mapping::{Parameter, ParameterValues, PARAMETER_COUNT};
+
+/// Controllers of a MIDI channel
+pub const CONTROLLERS: usize = 128;
+/// Distance a knob has to move from where it was at the last control change to take over again
+const TAKEOVER_THRESHOLD: f32 = 0.05;
+/// Marks that no controller drives the selected parameter, in the published state
+const NO_CONTROLLER: u32 = 0xff;
+
+/// Controllers of incoming control changes mapped onto the parameters, learned at runtime.
+///
+/// Learning listens for the selected parameter and maps the controller of the next control
+/// change to it. A controller sets its parameter until the knob of the parameter gets turned, so
+/// whichever moved last wins. Several controllers may drive the same parameter.
+pub struct MidiLearn {
+    controllers: [Parameter; CONTROLLERS],
+    selected: Parameter,
+    learning: bool,
+    /// Last value of every parameter set by a controller, with its knob value at the first update
+    /// after it
+    values: [Option<(f32, Option<f32>)>; PARAMETER_COUNT],
+}
+
+impl MidiLearn {
+    pub const fn new() -> Self {
+        MidiLearn {
+            controllers: [Parameter::None; CONTROLLERS],
+            selected: Parameter::Offset,
+            learning: false,
+            values: [None; PARAMETER_COUNT],
+        }
+    }
+
+    pub fn get_selected(&self) -> Parameter {
+        self.selected
+    }
+
+    /// Selects the parameter which gets learned or cleared.
+    pub fn select(&mut self, steps: i32) {
+        let index = (self.selected as i32 + steps).rem_euclid(PARAMETER_COUNT as i32);
+
+        self.selected = Parameter::from_index(index as usize);
+        self.learning = false;
+    }
+
+    pub fn is_learning(&self) -> bool {
+        self.learning
+    }
+
+    /// Listens for the controller of the selected parameter, or stops listening.
+    pub fn set_learning(&mut self, learning: bool) {
+        self.learning = learning;
+    }
+
+    /// Returns the first controller which drives `parameter`.
+    pub fn get_controller(&self, parameter: Parameter) -> Option<u8> {
+        self.controllers
+            .iter()
+            .position(|mapped| *mapped == parameter && parameter != Parameter::None)
+            .map(|controller| controller as u8)
+    }
+
+    /// Unmaps every controller of the selected parameter, it follows its knob again.
+    pub fn clear(&mut self) {
+        for mapped in self.controllers.iter_mut() {
+            if *mapped == self.selected {
+                *mapped = Parameter::None;
+            }
+        }
+
+        self.values[self.selected as usize] = None;
+    }
+
+    /// Returns the parameter of every controller, e.g. for a session.
+    pub fn get_map(&self) -> [Parameter; CONTROLLERS] {
+        self.controllers
+    }
+
+    pub fn set_map(&mut self, controllers: &[Parameter; CONTROLLERS]) {
+        self.controllers = *controllers;
+        self.values = [None; PARAMETER_COUNT];
+    }
+
+    /// Learns or follows a control change, returns `false` for all other events.
+    pub fn handle(&mut self, event: &Event) -> bool {
+        let (controller, value) = match *event {
+            Event::ControlChange { controller, value } => (controller as usize, value),
+            _ => return false,
+        };
+
+        if controller >= CONTROLLERS {
+            return false;
+        }
+
+        if core::mem::replace(&mut self.learning, false) {
+            self.controllers[controller] = self.selected;
+        }
+
+        match self.controllers[controller] {
+            Parameter::None => false,
+            parameter => {
+                self.values[parameter as usize] = Some((value as f32 / 127.0, None));
+                true
+            }
+        }
+    }
+
+    /// Puts the values of the controllers over the knob values of their parameters.
+    pub fn apply(&mut self, parameters: &mut ParameterValues) {
+        for (index, entry) in self.values.iter_mut().enumerate() {
+            if let Some((value, anchor)) = entry {
+                let parameter = Parameter::from_index(index);
+                let knob = parameters.get(parameter);
+                let anchor = *anchor.get_or_insert(knob);
+
+                if (knob - anchor).abs() > TAKEOVER_THRESHOLD {
+                    *entry = None;
+                } else {
+                    parameters.set(parameter, *value);
+                }
+            }
+        }
+    }
+}
+
+impl Default for MidiLearn {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Selected parameter, its controller and whether it is being learned, as published by the
+/// control task for the display.
+pub struct LearnView {
+    state: AtomicU32,
+}
+
+impl LearnView {
+    pub const fn new() -> Self {
+        LearnView {
+            state: AtomicU32::new(NO_CONTROLLER << 8),
+        }
+    }
+
+    pub fn publish(&self, learn: &MidiLearn) {
+        let selected = learn.get_selected();
+        let controller = learn
+            .get_controller(selected)
+            .map_or(NO_CONTROLLER, u32::from);
+
+        self.state.store(
+            selected as u32 | controller << 8 | u32::from(learn.is_learning()) << 16,
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Returns the selected parameter, its controller and whether it is being learned.
+    pub fn get(&self) -> (Parameter, Option<u8>, bool) {
+        let state = self.state.load(Ordering::Relaxed);
+        let controller = (state >> 8) & 0xff;
+
+        (
+            Parameter::from_index((state & 0xff) as usize),
+            (controller != NO_CONTROLLER).then_some(controller as u8),
+            state & 1 << 16 != 0,
+        )
+    }
+}
+
+impl Default for LearnView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub static LEARN_VIEW: LearnView = LearnView::new();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn control_change(controller: u8, value: u8) -> Event {
+        Event::ControlChange { controller, value }
+    }
+
+    #[test]
+    fn learned_controller_drives_its_parameter() {
+        let mut learn = MidiLearn::new();
+        let mut parameters = ParameterValues::default();
+
+        // nothing is mapped before learning
+        assert!(!learn.handle(&control_change(74, 127)));
+
+        learn.select(2);
+        learn.set_learning(true);
+        assert!(learn.handle(&control_change(74, 127)));
+        assert!(!learn.is_learning());
+        assert_eq!(learn.get_controller(Parameter::Pitch), Some(74));
+
+        learn.apply(&mut parameters);
+        assert_eq!(parameters.get(Parameter::Pitch), 1.0);
+
+        // the knob takes over once it gets turned
+        parameters.set(Parameter::Pitch, 0.2);
+        learn.apply(&mut parameters);
+        assert_eq!(parameters.get(Parameter::Pitch), 0.2);
+
+        learn.clear();
+        assert!(!learn.handle(&control_change(74, 0)));
+    }
+
+    #[test]
+    fn view_reports_the_selection() {
+        let mut learn = MidiLearn::new();
+        let view = LearnView::new();
+
+        view.publish(&learn);
+        assert_eq!(view.get(), (Parameter::Offset, None, false));
+
+        learn.set_learning(true);
+        learn.handle(&control_change(1, 64));
+        learn.set_learning(true);
+        view.publish(&learn);
+        assert_eq!(view.get(), (Parameter::Offset, Some(1), true));
+    }
+}
@@ -0,0 +1,51 @@
+use micromath::F32Ext;
+
+/// Output mixer stage which blends the granular and the varispeed engine.
+///
+/// Uses an equal power crossfade, so the perceived loudness stays constant while blending.
+/// The gains are only computed when the blend changes, which is once per audio block at most.
+pub struct Mixer {
+    blend: f32,
+    granular_gain: f32,
+    varispeed_gain: f32,
+}
+
+impl Mixer {
+    pub fn new() -> Self {
+        Mixer {
+            blend: 0.0,
+            granular_gain: 1.0,
+            varispeed_gain: 0.0,
+        }
+    }
+
+    /// Sets the blend between the granular (`0.0`) and the varispeed (`1.0`) engine.
+    pub fn set_blend(&mut self, blend: f32) {
+        let blend = blend.clamp(0.0, 1.0);
+
+        if blend != self.blend {
+            self.blend = blend;
+            self.granular_gain = (1.0 - blend).sqrt();
+            self.varispeed_gain = blend.sqrt();
+        }
+    }
+
+    pub fn get_blend(&self) -> f32 {
+        self.blend
+    }
+
+    /// Returns `true` if the varispeed engine is audible at all.
+    pub fn is_varispeed_active(&self) -> bool {
+        self.varispeed_gain > 0.0
+    }
+
+    pub fn process(&self, granular: f32, varispeed: f32) -> f32 {
+        granular * self.granular_gain + varispeed * self.varispeed_gain
+    }
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
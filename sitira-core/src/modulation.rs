@@ -0,0 +1,141 @@
+use crate::mapping::{Parameter, ParameterValues};
+
+/// Largest number of routes in the matrix
+pub const MAX_ROUTES: usize = 8;
+
+/// What the macro knob does without a matrix in the mapping file: the higher it goes, the more
+/// the grains scatter.
+const DEFAULT_ROUTES: [(Parameter, f32); 5] = [
+    (Parameter::OffsetSpread, 0.4),
+    (Parameter::GrainSizeSpread, 0.5),
+    (Parameter::PitchSpread, 0.25),
+    (Parameter::DelaySpread, 0.5),
+    (Parameter::VelocitySpread, 0.3),
+];
+
+/// Lets one parameter modulate another.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ModRoute {
+    pub source: Parameter,
+    pub destination: Parameter,
+    /// Change of the destination at full source, negative values modulate downwards
+    pub depth: f32,
+}
+
+impl ModRoute {
+    /// Reads a route in the form `source destination depth`.
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut words = line.split_whitespace();
+
+        let source = Parameter::from_name(words.next()?)?;
+        let destination = Parameter::from_name(words.next()?)?;
+        let depth = words.next()?.parse::<f32>().ok()?;
+
+        if words.next().is_some() || source == Parameter::None || destination == Parameter::None {
+            return None;
+        }
+
+        Some(ModRoute {
+            source,
+            destination,
+            depth: depth.clamp(-1.0, 1.0),
+        })
+    }
+}
+
+/// Modulation matrix, applied to the parameters after the knobs have been mapped.
+///
+/// Every route adds its source, scaled by its depth, to its destination. Sources are always taken
+/// before any modulation, so routes can not feed back into each other. The macro parameter is
+/// meant as source, a single knob or CV which moves several parameters at once.
+#[derive(Clone, Copy)]
+pub struct ModMatrix {
+    routes: [Option<ModRoute>; MAX_ROUTES],
+    selected: usize,
+}
+
+impl ModMatrix {
+    /// Creates the matrix with the default routes of the macro.
+    pub fn new() -> Self {
+        let mut matrix = Self::empty();
+
+        for (destination, depth) in DEFAULT_ROUTES {
+            matrix.add(ModRoute {
+                source: Parameter::Macro,
+                destination,
+                depth,
+            });
+        }
+
+        matrix
+    }
+
+    pub fn empty() -> Self {
+        ModMatrix {
+            routes: [None; MAX_ROUTES],
+            selected: 0,
+        }
+    }
+
+    /// Adds a route, returns `false` if the matrix is full.
+    pub fn add(&mut self, route: ModRoute) -> bool {
+        match self.routes.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(route);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Selects the route whose depth gets adjusted, skipping empty slots.
+    pub fn select(&mut self, steps: i32) {
+        let count = self.routes.iter().flatten().count();
+
+        if count > 0 {
+            self.selected = (self.selected as i32 + steps).rem_euclid(count as i32) as usize;
+        }
+    }
+
+    pub fn get_routes(&self) -> impl Iterator<Item = &ModRoute> {
+        self.routes.iter().flatten()
+    }
+
+    pub fn get_selected(&self) -> Option<ModRoute> {
+        self.routes.iter().flatten().nth(self.selected).copied()
+    }
+
+    /// Changes the depth of the selected route by `change`.
+    pub fn adjust_depth(&mut self, change: f32) {
+        if let Some(route) = self.routes.iter_mut().flatten().nth(self.selected) {
+            route.depth = (route.depth + change).clamp(-1.0, 1.0);
+        }
+    }
+
+    /// Returns the parameters with all routes applied.
+    pub fn apply(&self, parameters: &ParameterValues) -> ParameterValues {
+        let mut modulated = *parameters;
+
+        for route in self.routes.iter().flatten() {
+            let value =
+                modulated.get(route.destination) + parameters.get(route.source) * route.depth;
+
+            modulated.set(route.destination, value);
+        }
+
+        // only the sum of all routes gets limited
+        for route in self.routes.iter().flatten() {
+            let value = modulated.get(route.destination);
+
+            modulated.set(route.destination, value.clamp(0.0, 1.0));
+        }
+
+        modulated
+    }
+}
+
+impl Default for ModMatrix {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,105 @@
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use micromath::F32Ext;
+
+use crate::timecode::TimeText;
+
+/// Gain applied to the playback, stored as `f32` bits
+static PLAYBACK_GAIN: AtomicU32 = AtomicU32::new(0x3F80_0000);
+
+/// Returns the normalization gain of the active buffer.
+pub fn get_gain() -> f32 {
+    f32::from_bits(PLAYBACK_GAIN.load(Ordering::Relaxed))
+}
+
+pub fn set_gain(gain: f32) {
+    PLAYBACK_GAIN.store(gain.to_bits(), Ordering::Relaxed);
+}
+
+/// Returns the gain which lifts `peak` to `target`. Loud material is never attenuated.
+pub fn gain_for_peak(peak: f32, target: f32, max_gain: f32) -> f32 {
+    if peak > 0.0 {
+        (target / peak).clamp(1.0, max_gain)
+    } else {
+        1.0
+    }
+}
+
+/// Formats a gain in decibels with one decimal.
+pub fn format_gain(gain: f32) -> TimeText {
+    let tenths = (200.0 * gain.max(1.0).log10()).round() as u32;
+    let mut text = TimeText::new();
+
+    // the text always fits
+    let _ = write!(text, "+{}.{} dB", tenths / 10, tenths % 10);
+
+    text
+}
+
+/// Finds the peak of a recorded buffer.
+///
+/// Like the onset detector, the scan works on a limited amount of samples per call, so it can
+/// run in `idle`.
+pub struct PeakScanner {
+    position: usize,
+    length: usize,
+    peak: f32,
+    running: bool,
+}
+
+impl PeakScanner {
+    pub fn new() -> Self {
+        PeakScanner {
+            position: 0,
+            length: 0,
+            peak: 0.0,
+            running: false,
+        }
+    }
+
+    /// Starts a new scan of a buffer with `length` samples.
+    pub fn start(&mut self, length: usize) {
+        self.position = 0;
+        self.length = length;
+        self.peak = 0.0;
+        self.running = true;
+    }
+
+    pub fn cancel(&mut self) {
+        self.running = false;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Scans up to `samples` further samples of `buffer`. Returns the peak once the whole
+    /// buffer has been scanned.
+    pub fn process(&mut self, buffer: &[f32], samples: usize) -> Option<f32> {
+        if !self.running {
+            return None;
+        }
+
+        let end = (self.position + samples).min(self.length).min(buffer.len());
+
+        for sample in &buffer[self.position..end] {
+            self.peak = self.peak.max(sample.abs());
+        }
+
+        self.position = end;
+
+        if end < self.length.min(buffer.len()) {
+            return None;
+        }
+
+        self.running = false;
+        Some(self.peak)
+    }
+}
+
+impl Default for PeakScanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
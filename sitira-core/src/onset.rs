@@ -0,0 +1,103 @@
+use crate::slices::SliceMarkers;
+
+/// Number of samples which are analyzed as one block
+pub const FRAME_SIZE: usize = 256;
+
+/// Energy ratio between a frame and the running average which counts as an onset
+const THRESHOLD: f32 = 4.0;
+/// Frames below this mean energy are never considered an onset
+const MIN_ENERGY: f32 = 0.0001;
+/// Minimum distance between two onsets in frames (about 43ms at 48kHz)
+const MIN_GAP_FRAMES: usize = 8;
+/// Smoothing factor of the running energy average
+const AVERAGE_SMOOTHING: f32 = 0.9;
+
+/// Energy based transient detector.
+///
+/// The analysis works incrementally on a few frames per call, so it can run in `idle` without
+/// ever blocking a task. Every detected onset gets stored as a slice marker.
+pub struct OnsetDetector {
+    position: usize,
+    length: usize,
+    average: f32,
+    frames_since_onset: usize,
+    running: bool,
+}
+
+impl OnsetDetector {
+    pub fn new() -> Self {
+        OnsetDetector {
+            position: 0,
+            length: 0,
+            average: 0.0,
+            frames_since_onset: 0,
+            running: false,
+        }
+    }
+
+    /// Starts a new analysis of a buffer with `length` samples.
+    pub fn start(&mut self, length: usize, markers: &mut SliceMarkers) {
+        self.position = 0;
+        self.length = length;
+        self.average = 0.0;
+        self.frames_since_onset = MIN_GAP_FRAMES;
+        self.running = length >= FRAME_SIZE;
+
+        markers.clear(length);
+        markers.push(0);
+    }
+
+    pub fn cancel(&mut self) {
+        self.running = false;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Analyzes up to `max_frames` frames. Returns `true` when the whole buffer has been analyzed.
+    pub fn process(
+        &mut self,
+        buffer: &[f32],
+        markers: &mut SliceMarkers,
+        max_frames: usize,
+    ) -> bool {
+        if !self.running {
+            return true;
+        }
+
+        let length = self.length.min(buffer.len());
+
+        for _ in 0..max_frames {
+            if self.position + FRAME_SIZE > length {
+                self.running = false;
+                return true;
+            }
+
+            let frame = &buffer[self.position..self.position + FRAME_SIZE];
+            let energy =
+                frame.iter().map(|sample| sample * sample).sum::<f32>() / FRAME_SIZE as f32;
+
+            if energy > MIN_ENERGY
+                && energy > self.average * THRESHOLD
+                && self.frames_since_onset >= MIN_GAP_FRAMES
+            {
+                markers.push(self.position);
+                self.frames_since_onset = 0;
+            } else {
+                self.frames_since_onset += 1;
+            }
+
+            self.average = self.average * AVERAGE_SMOOTHING + energy * (1.0 - AVERAGE_SMOOTHING);
+            self.position += FRAME_SIZE;
+        }
+
+        false
+    }
+}
+
+impl Default for OnsetDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
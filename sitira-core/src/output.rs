@@ -0,0 +1,75 @@
+use micromath::F32Ext;
+
+/// Knob positions below this are silent
+const SILENT_POSITION: f32 = 0.01;
+/// Time constant of the volume smoothing
+const VOLUME_SMOOTHING_IN_S: f32 = 0.01;
+
+/// Final gain stage in front of the codec.
+///
+/// The master volume knob sweeps evenly through the decibels between `min_db` and `max_db`, which
+/// sounds far more even than a linear gain. Muting and unmuting ramps the gain, so the kill gate
+/// never clicks. The stage starts muted and ramps up, which keeps the boot silent.
+pub struct OutputStage {
+    position: f32,
+    /// Gain the volume is smoothed towards
+    target: f32,
+    volume: f32,
+    volume_coefficient: f32,
+    muted: bool,
+    /// Position of the mute ramp, `0.0` is silent
+    ramp: f32,
+    ramp_step: f32,
+    min_db: f32,
+    max_db: f32,
+}
+
+impl OutputStage {
+    pub fn new(sample_rate: f32, ramp_in_ms: f32, min_db: f32, max_db: f32) -> Self {
+        OutputStage {
+            position: 0.0,
+            target: 0.0,
+            volume: 0.0,
+            volume_coefficient: 1.0 - (-1.0 / (VOLUME_SMOOTHING_IN_S * sample_rate)).exp(),
+            muted: false,
+            ramp: 0.0,
+            ramp_step: 1.0 / (ramp_in_ms * sample_rate / 1000.0).max(1.0),
+            min_db,
+            max_db,
+        }
+    }
+
+    /// Sets the master volume from the normalized knob position.
+    pub fn set_volume(&mut self, position: f32) {
+        let position = position.clamp(0.0, 1.0);
+
+        if position != self.position {
+            self.position = position;
+            self.target = if position < SILENT_POSITION {
+                0.0
+            } else {
+                let db = self.min_db + (self.max_db - self.min_db) * position;
+                10.0f32.powf(db / 20.0)
+            };
+        }
+    }
+
+    /// Ramps the output down or back up.
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    pub fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        self.volume += (self.target - self.volume) * self.volume_coefficient;
+
+        self.ramp = if self.muted {
+            (self.ramp - self.ramp_step).max(0.0)
+        } else {
+            (self.ramp + self.ramp_step).min(1.0)
+        };
+
+        let gain = self.volume * self.ramp;
+
+        (left * gain, right * gain)
+    }
+}
@@ -0,0 +1,36 @@
+/// Most conversions one reading of an input gets made of
+pub const MAX_OVERSAMPLING: usize = 16;
+
+/// Reduces several conversions of one input to a single reading.
+///
+/// The conversions get sorted and a quarter of them is dropped at either end before the rest is
+/// averaged, so single spikes of long panel wires are thrown away like by a median filter while
+/// the noise of the remaining ones is averaged out. Three conversions give their median, one or
+/// two their mean.
+pub fn decimate(samples: &mut [u32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+
+    samples.sort_unstable();
+
+    let trim = (samples.len() + 1) / 4;
+    let kept = &samples[trim..samples.len() - trim];
+    let sum: u32 = kept.iter().sum();
+
+    sum as f32 / kept.len() as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spikes_are_dropped_and_the_rest_is_averaged() {
+        assert_eq!(decimate(&mut [7]), 7.0);
+        assert_eq!(decimate(&mut [4, 6]), 5.0);
+        assert_eq!(decimate(&mut [100, 1, 2]), 2.0);
+        assert_eq!(decimate(&mut [10, 12, 11, 9, 0, 65535, 10, 11]), 10.5);
+        assert_eq!(decimate(&mut []), 0.0);
+    }
+}
@@ -0,0 +1,256 @@
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+use crate::mapping::{Parameter, ParameterValues, PARAMETER_COUNT};
+use crate::strings::{self, UiText};
+
+/// Change of a parameter since it was last focused which focuses it again, smaller ones are ADC
+/// noise
+const FOCUS_THRESHOLD: f32 = 0.02;
+/// Change of a shown value which redraws its bar, about a pixel
+const REDRAW_THRESHOLD: f32 = 0.004;
+/// Marks that no parameter is focused
+const NO_FOCUS: usize = usize::MAX;
+
+/// Most parameters a page holds
+pub const MAX_PAGE_ROWS: usize = 5;
+
+/// Parameters which are shown together on the display.
+pub struct ParameterPage {
+    pub title: UiText,
+    pub parameters: &'static [Parameter],
+}
+
+/// The parameters of the panel, grouped by what they shape
+pub const PAGES: [ParameterPage; 5] = [
+    ParameterPage {
+        title: UiText::GrainsPage,
+        parameters: &[
+            Parameter::Offset,
+            Parameter::GrainSize,
+            Parameter::Pitch,
+            Parameter::ActiveGrains,
+        ],
+    },
+    ParameterPage {
+        title: UiText::ShapePage,
+        parameters: &[
+            Parameter::Delay,
+            Parameter::Velocity,
+            Parameter::Envelope,
+            Parameter::WaveSelect,
+        ],
+    },
+    ParameterPage {
+        title: UiText::SpreadPage,
+        parameters: &[
+            Parameter::OffsetSpread,
+            Parameter::GrainSizeSpread,
+            Parameter::PitchSpread,
+            Parameter::DelaySpread,
+            Parameter::VelocitySpread,
+        ],
+    },
+    ParameterPage {
+        title: UiText::EnginePage,
+        parameters: &[Parameter::VarispeedSpeed, Parameter::EngineBlend],
+    },
+    ParameterPage {
+        title: UiText::SecondStreamPage,
+        parameters: &[
+            Parameter::SecondOffset,
+            Parameter::SecondGrainSize,
+            Parameter::SecondPitch,
+            Parameter::SecondGrains,
+        ],
+    },
+];
+
+/// Name of a parameter on the display, in the selected language.
+pub fn label(parameter: Parameter) -> &'static str {
+    strings::get(UiText::Parameter(parameter))
+}
+
+/// Returns the page a parameter is shown on, `None` if it is on none.
+fn find_page(parameter: Parameter) -> Option<usize> {
+    PAGES
+        .iter()
+        .position(|page| page.parameters.contains(&parameter))
+}
+
+/// Finds the parameter which is being changed, run by the control task.
+///
+/// Every parameter keeps the value it had when it was last focused, so slow turns add up until
+/// they count as a change while noise does not.
+pub struct ChangeDetector {
+    anchors: [f32; PARAMETER_COUNT],
+    started: bool,
+}
+
+impl ChangeDetector {
+    pub fn new() -> Self {
+        ChangeDetector {
+            anchors: [0.0; PARAMETER_COUNT],
+            started: false,
+        }
+    }
+
+    /// Returns the shown parameter which moved the furthest, if any moved far enough.
+    pub fn detect(&mut self, values: &ParameterValues) -> Option<Parameter> {
+        // the first values only set the anchors
+        let started = core::mem::replace(&mut self.started, true);
+        let mut changed = None;
+        let mut furthest = FOCUS_THRESHOLD;
+
+        for page in PAGES.iter() {
+            for parameter in page.parameters {
+                let value = values.get(*parameter);
+                let anchor = &mut self.anchors[*parameter as usize];
+                let distance = (value - *anchor).abs();
+
+                if !started || distance > FOCUS_THRESHOLD {
+                    *anchor = value;
+                }
+
+                if started && distance > furthest {
+                    furthest = distance;
+                    changed = Some(*parameter);
+                }
+            }
+        }
+
+        changed
+    }
+}
+
+impl Default for ChangeDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parameter values and the focus as published by the control task for the display.
+///
+/// Values are stored as raw `f32` bits in atomics. The focus marks the parameter which is being
+/// changed and runs out after a number of control cycles.
+pub struct ParameterView {
+    values: [AtomicU32; PARAMETER_COUNT],
+    /// Parameters played by their automation lane, one bit each
+    automated: AtomicU32,
+    page: AtomicUsize,
+    focus: AtomicUsize,
+    focus_ticks: AtomicU32,
+    changed: AtomicBool,
+}
+
+impl ParameterView {
+    pub const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicU32 = AtomicU32::new(0);
+
+        ParameterView {
+            values: [ZERO; PARAMETER_COUNT],
+            automated: AtomicU32::new(0),
+            page: AtomicUsize::new(0),
+            focus: AtomicUsize::new(NO_FOCUS),
+            focus_ticks: AtomicU32::new(0),
+            changed: AtomicBool::new(true),
+        }
+    }
+
+    /// Takes the values of a control cycle and counts down the focus.
+    pub fn publish(&self, values: &ParameterValues) {
+        for page in PAGES.iter() {
+            for parameter in page.parameters {
+                let value = values.get(*parameter);
+
+                if (value - self.get_value(*parameter)).abs() >= REDRAW_THRESHOLD {
+                    self.values[*parameter as usize].store(value.to_bits(), Ordering::Relaxed);
+                    self.changed.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+
+        let ticks = self.focus_ticks.load(Ordering::Relaxed);
+
+        if ticks > 0 {
+            self.focus_ticks.store(ticks - 1, Ordering::Relaxed);
+
+            if ticks == 1 {
+                self.changed.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Focuses a parameter for `ticks` control cycles, its page gets shown.
+    pub fn focus(&self, parameter: Parameter, ticks: u32) {
+        if let Some(page) = find_page(parameter) {
+            self.page.store(page, Ordering::Relaxed);
+            self.focus.store(parameter as usize, Ordering::Relaxed);
+            self.focus_ticks.store(ticks, Ordering::Relaxed);
+            self.changed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the page and the row within it of the focused parameter.
+    pub fn get_focus(&self) -> Option<(usize, usize)> {
+        if self.focus_ticks.load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+
+        let focus = self.focus.load(Ordering::Relaxed);
+
+        PAGES.iter().enumerate().find_map(|(index, page)| {
+            page.parameters
+                .iter()
+                .position(|parameter| *parameter as usize == focus)
+                .map(|row| (index, row))
+        })
+    }
+
+    pub fn get_page(&self) -> usize {
+        self.page.load(Ordering::Relaxed)
+    }
+
+    /// Steps through the pages, wrapping around at either end.
+    pub fn step_page(&self, steps: i32) {
+        let page = (self.get_page() as i32 + steps).rem_euclid(PAGES.len() as i32) as usize;
+
+        self.page.store(page, Ordering::Relaxed);
+        self.focus_ticks.store(0, Ordering::Relaxed);
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    pub fn get_value(&self, parameter: Parameter) -> f32 {
+        match parameter {
+            Parameter::None => 0.0,
+            _ => f32::from_bits(self.values[parameter as usize].load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Takes the parameters whose automation lane holds a recording, one bit each.
+    pub fn set_automated(&self, automated: u32) {
+        if self.automated.swap(automated, Ordering::Relaxed) != automated {
+            self.changed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    pub fn is_automated(&self, parameter: Parameter) -> bool {
+        match parameter {
+            Parameter::None => false,
+            _ => self.automated.load(Ordering::Relaxed) & 1 << parameter as usize != 0,
+        }
+    }
+
+    /// Returns `true` once after a value, the page, the focus or the automation changed.
+    pub fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::Relaxed)
+    }
+}
+
+impl Default for ParameterView {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub static PARAMETER_VIEW: ParameterView = ParameterView::new();
@@ -0,0 +1,113 @@
+use micromath::F32Ext;
+
+use crate::grains::Window;
+
+/// Shifts the pitch of the live input by granulating it as it comes in.
+///
+/// The input runs through a ring, which two grains of a fixed length read from half a grain
+/// apart. Each grain plays at the shifted speed and restarts behind the write head when it ends,
+/// their Hann windows add up to one, so the shifted input plays on without gaps or clicks.
+pub struct PitchShifter {
+    buffer: &'static mut [f32],
+    head: usize,
+    /// Position in the first grain, the second one is half a grain ahead
+    phase: f32,
+    /// Frames of one grain
+    grain: usize,
+}
+
+impl PitchShifter {
+    /// Creates a shifter with grains `grain` frames long, the buffer has to be longer than that.
+    pub fn new(buffer: &'static mut [f32], grain: usize) -> Self {
+        buffer.fill(0.0);
+
+        PitchShifter {
+            grain: grain.min(buffer.len().saturating_sub(2)),
+            buffer,
+            head: 0,
+            phase: 0.0,
+        }
+    }
+
+    /// Takes the next `input` sample and returns it shifted by `speed`, where `2.0` is an octave
+    /// up and `0.5` one down.
+    pub fn process(&mut self, input: f32, speed: f32) -> f32 {
+        let length = self.buffer.len();
+
+        if self.grain < 2 {
+            return input;
+        }
+
+        self.buffer[self.head] = input;
+
+        // the grains fall behind the write head when playing faster and catch up when slower
+        let mut output = 0.0;
+
+        for phase in [self.phase, (self.phase + 0.5).fract()] {
+            let delay = 1.0 + (1.0 - phase) * (self.grain - 1) as f32;
+            let position = (self.head + length) as f32 - delay;
+            let index = position as usize;
+            let fraction = position - index as f32;
+            let current = self.buffer[index % length];
+            let next = self.buffer[(index + 1) % length];
+            let window = Window::Hann.gain(phase, 0.0);
+
+            output += (current + (next - current) * fraction) * window;
+        }
+
+        self.head = (self.head + 1) % length;
+        self.phase = (self.phase + (speed - 1.0) / self.grain as f32).fract();
+
+        if self.phase < 0.0 {
+            self.phase += 1.0;
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::f32::consts::TAU;
+
+    use super::*;
+
+    fn new_shifter(grain: usize) -> PitchShifter {
+        PitchShifter::new(Box::leak(vec![0.0; grain * 2].into_boxed_slice()), grain)
+    }
+
+    #[test]
+    fn windows_keep_the_level() {
+        let mut shifter = new_shifter(64);
+
+        for _ in 0..1000 {
+            shifter.process(1.0, 1.5);
+        }
+
+        assert!((shifter.process(1.0, 1.5) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn octave_up_doubles_the_frequency() {
+        let mut shifter = new_shifter(960);
+        let period = 96.0;
+        let mut crossings = 0;
+        let mut last = 0.0;
+
+        for frame in 0..48000 {
+            let input = (frame as f32 / period * TAU).sin();
+            let output = shifter.process(input, 2.0);
+
+            if frame >= 4800 && last < 0.0 && output >= 0.0 {
+                crossings += 1;
+            }
+
+            last = output;
+        }
+
+        // 450 periods of the input, twice as many come out
+        let expected = 2.0 * (48000 - 4800) as f32 / period;
+
+        assert!((crossings as f32 - expected).abs() < expected * 0.05);
+    }
+}
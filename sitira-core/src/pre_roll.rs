@@ -0,0 +1,119 @@
+/// Kept samples copied into a take per audio callback, a whole pre-roll at once would overrun it
+pub const SPLICE_FRAMES_PER_CALLBACK: usize = 4096;
+
+/// Last moments of the input in front of a take.
+///
+/// While nothing is recorded, the input runs through the ring. When a take starts, the kept
+/// samples get reserved at its beginning and are copied there a few thousand per callback, the
+/// take itself is written behind them right away. Nothing new is kept until the splice is done.
+pub struct PreRoll {
+    buffer: &'static mut [f32],
+    head: usize,
+    kept: usize,
+    /// Kept samples which still have to be copied into the take
+    pending: usize,
+}
+
+impl PreRoll {
+    pub fn new(buffer: &'static mut [f32]) -> Self {
+        buffer.fill(0.0);
+
+        PreRoll {
+            buffer,
+            head: 0,
+            kept: 0,
+            pending: 0,
+        }
+    }
+
+    pub fn push(&mut self, sample: f32) {
+        if self.buffer.is_empty() || self.is_splicing() {
+            return;
+        }
+
+        self.buffer[self.head] = sample;
+        self.head = (self.head + 1) % self.buffer.len();
+        self.kept = (self.kept + 1).min(self.buffer.len());
+    }
+
+    /// Forgets the kept samples, unless they are being spliced.
+    pub fn clear(&mut self) {
+        if !self.is_splicing() {
+            self.kept = 0;
+        }
+    }
+
+    /// Starts copying the kept samples into a new take and returns how many of them it begins
+    /// with.
+    pub fn begin_splice(&mut self) -> usize {
+        self.pending = self.kept;
+        self.kept
+    }
+
+    pub fn is_splicing(&self) -> bool {
+        self.pending > 0
+    }
+
+    /// Copies the next `frames` kept samples to their place at the start of `take`, oldest first.
+    /// The memory of the take has to be longer than the pre-roll.
+    pub fn splice(&mut self, take: &mut [f32], frames: usize) {
+        let start = self.kept - self.pending;
+        let end = (start + frames).min(self.kept);
+        let oldest = (self.head + self.buffer.len() - self.kept) % self.buffer.len().max(1);
+
+        for (index, sample) in take[start..end].iter_mut().enumerate() {
+            *sample = self.buffer[(oldest + start + index) % self.buffer.len()];
+        }
+
+        self.pending -= end - start;
+
+        if self.pending == 0 {
+            self.kept = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_pre_roll(length: usize) -> PreRoll {
+        PreRoll::new(Box::leak(vec![0.0; length].into_boxed_slice()))
+    }
+
+    #[test]
+    fn take_begins_with_the_kept_samples() {
+        let mut pre_roll = new_pre_roll(4);
+        let mut take = [0.0; 8];
+
+        (1..=6).for_each(|n| pre_roll.push(n as f32));
+
+        // only the newest samples are kept
+        assert_eq!(pre_roll.begin_splice(), 4);
+
+        // nothing gets kept while splicing
+        pre_roll.push(9.0);
+
+        pre_roll.splice(&mut take, 3);
+        assert!(pre_roll.is_splicing());
+        pre_roll.splice(&mut take, 3);
+        assert!(!pre_roll.is_splicing());
+
+        assert_eq!(take[..5], [3.0, 4.0, 5.0, 6.0, 0.0]);
+
+        // the next take starts from what came after this one
+        pre_roll.push(7.0);
+        assert_eq!(pre_roll.begin_splice(), 1);
+    }
+
+    #[test]
+    fn clear_forgets_the_kept_samples() {
+        let mut pre_roll = new_pre_roll(4);
+
+        pre_roll.push(1.0);
+        pre_roll.clear();
+
+        assert_eq!(pre_roll.begin_splice(), 0);
+        assert!(!pre_roll.is_splicing());
+    }
+}
@@ -0,0 +1,59 @@
+/// Number of gate outputs
+pub const PULSE_OUTPUT_COUNT: usize = 2;
+
+/// Gate outputs which emit pulses to clock other modules.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PulseOutput {
+    /// Pulses whenever a grain has played to its end
+    Grain,
+    /// Pulses whenever the loop wraps around
+    Loop,
+}
+
+/// Times the pulses of the gate outputs.
+///
+/// Triggers are collected while a block gets rendered and the levels are written to the pins
+/// once per block, so a pulse starts at most one block late and always lasts its full width. A
+/// trigger while an output is still high pulls it low for one block first, so the receiving
+/// module sees a separate edge.
+pub struct PulseScheduler {
+    width_in_frames: usize,
+    remaining: [usize; PULSE_OUTPUT_COUNT],
+    pending: [bool; PULSE_OUTPUT_COUNT],
+}
+
+impl PulseScheduler {
+    pub fn new(width_in_frames: usize) -> Self {
+        PulseScheduler {
+            width_in_frames: width_in_frames.max(1),
+            remaining: [0; PULSE_OUTPUT_COUNT],
+            pending: [false; PULSE_OUTPUT_COUNT],
+        }
+    }
+
+    pub fn trigger(&mut self, output: PulseOutput) {
+        self.pending[output as usize] = true;
+    }
+
+    /// Advances by a block of `frames` and returns the level of every output during it.
+    pub fn advance(&mut self, frames: usize) -> [bool; PULSE_OUTPUT_COUNT] {
+        let mut levels = [false; PULSE_OUTPUT_COUNT];
+
+        for (index, level) in levels.iter_mut().enumerate() {
+            if self.pending[index] {
+                if self.remaining[index] > 0 {
+                    // the gap before the next pulse
+                    self.remaining[index] = 0;
+                } else {
+                    self.pending[index] = false;
+                    self.remaining[index] = self.width_in_frames;
+                }
+            }
+
+            *level = self.remaining[index] > 0;
+            self.remaining[index] = self.remaining[index].saturating_sub(frames);
+        }
+
+        levels
+    }
+}
@@ -0,0 +1,363 @@
+use micromath::F32Ext;
+
+/// Semitones per octave
+const OCTAVE: i32 = 12;
+/// Furthest the fine tune reaches in either direction
+const FINE_TUNE_RANGE_IN_CENTS: i32 = 100;
+
+/// Names of the pitch classes relative to the unshifted pitch
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// MIDI note which plays the grains unshifted, notes below the slice notes transpose them
+pub const MIDI_CENTER_NOTE: u8 = 24;
+
+/// Scales pitches can be snapped to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Scale {
+    /// Pitches are passed through untouched
+    Free,
+    Chromatic,
+    Diatonic,
+    Pentatonic,
+    WholeTone,
+}
+
+impl Scale {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scale::Free => "Free",
+            Scale::Chromatic => "Chromatic",
+            Scale::Diatonic => "Diatonic",
+            Scale::Pentatonic => "Pentatonic",
+            Scale::WholeTone => "Whole Tone",
+        }
+    }
+
+    /// Semitones between neighbouring degrees, starting at the first degree of the scale.
+    fn steps(&self) -> &'static [u8] {
+        match self {
+            Scale::Free | Scale::Chromatic => &[1; 12],
+            Scale::Diatonic => &[2, 2, 1, 2, 2, 2, 1],
+            Scale::Pentatonic => &[2, 2, 3, 2, 3],
+            Scale::WholeTone => &[2; 6],
+        }
+    }
+}
+
+/// Order in which the scales are stepped through
+const SCALES: [Scale; 5] = [
+    Scale::Free,
+    Scale::Chromatic,
+    Scale::Diatonic,
+    Scale::Pentatonic,
+    Scale::WholeTone,
+];
+
+/// Modes rotate the steps of a scale, so another degree becomes the root.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mode {
+    Ionian,
+    Dorian,
+    Phrygian,
+    Lydian,
+    Mixolydian,
+    Aeolian,
+    Locrian,
+}
+
+impl Mode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Ionian => "Ionian",
+            Mode::Dorian => "Dorian",
+            Mode::Phrygian => "Phrygian",
+            Mode::Lydian => "Lydian",
+            Mode::Mixolydian => "Mixolydian",
+            Mode::Aeolian => "Aeolian",
+            Mode::Locrian => "Locrian",
+        }
+    }
+}
+
+/// Order in which the modes are stepped through, the index is the rotation
+const MODES: [Mode; 7] = [
+    Mode::Ionian,
+    Mode::Dorian,
+    Mode::Phrygian,
+    Mode::Lydian,
+    Mode::Mixolydian,
+    Mode::Aeolian,
+    Mode::Locrian,
+];
+
+/// Snaps pitch shifts in semitones to the degrees of a scale.
+///
+/// Every pitch source converts to semitones first and goes through the same quantizer, so the
+/// knob, its CV input and MIDI notes always land on the same tuning.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Quantizer {
+    scale: Scale,
+    mode: Mode,
+    /// Pitch class of the first degree, `0` is the unshifted pitch
+    root: u8,
+    /// Set degrees as a bit mask of pitch classes relative to the root
+    degrees: u16,
+    /// Offset added after quantizing, to match other instruments
+    fine_tune_in_cents: i32,
+}
+
+impl Quantizer {
+    pub fn new(scale: Scale, mode: Mode, root: u8) -> Self {
+        let mut quantizer = Quantizer {
+            scale,
+            mode,
+            root: root % OCTAVE as u8,
+            degrees: 0,
+            fine_tune_in_cents: 0,
+        };
+
+        quantizer.update_degrees();
+        quantizer
+    }
+
+    pub fn get_scale(&self) -> Scale {
+        self.scale
+    }
+
+    pub fn get_mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn get_root(&self) -> u8 {
+        self.root
+    }
+
+    /// Name of the root, taking the unshifted pitch as C.
+    pub fn get_root_name(&self) -> &'static str {
+        NOTE_NAMES[self.root as usize]
+    }
+
+    pub fn get_fine_tune(&self) -> i32 {
+        self.fine_tune_in_cents
+    }
+
+    pub fn set_scale(&mut self, scale: Scale) {
+        self.scale = scale;
+        self.update_degrees();
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.update_degrees();
+    }
+
+    pub fn set_root(&mut self, root: u8) {
+        self.root = root % OCTAVE as u8;
+    }
+
+    /// Steps through the scales, wrapping around at either end.
+    pub fn step_scale(&mut self, steps: i32) {
+        let index = SCALES.iter().position(|s| *s == self.scale).unwrap_or(0) as i32;
+
+        self.set_scale(SCALES[(index + steps).rem_euclid(SCALES.len() as i32) as usize]);
+    }
+
+    /// Steps through the modes, wrapping around at either end.
+    pub fn step_mode(&mut self, steps: i32) {
+        self.set_mode(MODES[(self.mode as i32 + steps).rem_euclid(MODES.len() as i32) as usize]);
+    }
+
+    /// Steps the root by semitones, wrapping around within the octave.
+    pub fn step_root(&mut self, steps: i32) {
+        self.root = (self.root as i32 + steps).rem_euclid(OCTAVE) as u8;
+    }
+
+    /// Steps the fine tune by cents, it stops at either end of its range.
+    pub fn step_fine_tune(&mut self, steps: i32) {
+        self.fine_tune_in_cents = (self.fine_tune_in_cents + steps)
+            .clamp(-FINE_TUNE_RANGE_IN_CENTS, FINE_TUNE_RANGE_IN_CENTS);
+    }
+
+    /// Returns `true` if pitches get snapped at all.
+    pub fn is_active(&self) -> bool {
+        self.scale != Scale::Free
+    }
+
+    /// Snaps a pitch shift to the nearest degree, ties go to the lower one.
+    pub fn quantize(&self, semitones: f32) -> f32 {
+        if !self.is_active() {
+            return semitones;
+        }
+
+        let relative = semitones - self.root as f32;
+        let octave = (relative / OCTAVE as f32).floor();
+        let class = relative - octave * OCTAVE as f32;
+
+        // the root of the next octave catches everything above the last degree
+        let mut nearest = OCTAVE as f32;
+
+        for degree in (0..OCTAVE).rev() {
+            if self.degrees & (1 << degree) != 0
+                && (class - degree as f32).abs() <= (class - nearest).abs()
+            {
+                nearest = degree as f32;
+            }
+        }
+
+        self.root as f32 + octave * OCTAVE as f32 + nearest
+    }
+
+    /// Quantizes a pitch shift and adds the fine tune, which is what every pitch source plays.
+    pub fn tune(&self, semitones: f32) -> f32 {
+        self.quantize(semitones) + self.fine_tune_in_cents as f32 / 100.0
+    }
+
+    /// Tunes a pitch shift which carries the fine tune already, e.g. one spread around a tuned
+    /// pitch. Tuned pitches stay as they are.
+    pub fn retune(&self, semitones: f32) -> f32 {
+        self.tune(semitones - self.fine_tune_in_cents as f32 / 100.0)
+    }
+
+    fn update_degrees(&mut self) {
+        let steps = self.scale.steps();
+        let rotation = self.mode as usize;
+        let mut degree = 0;
+
+        self.degrees = 0;
+
+        for index in 0..steps.len() {
+            self.degrees |= 1 << degree;
+            degree += steps[(index + rotation) % steps.len()] as usize;
+        }
+    }
+}
+
+impl Default for Quantizer {
+    fn default() -> Self {
+        Self::new(Scale::Free, Mode::Ionian, 0)
+    }
+}
+
+/// Pitch shift a MIDI note asks for.
+pub fn note_to_semitones(note: u8) -> f32 {
+    note as f32 - MIDI_CENTER_NOTE as f32
+}
+
+/// Pitch shift of a 1V/oct control voltage.
+pub fn volts_to_semitones(volts: f32) -> f32 {
+    volts * OCTAVE as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn free_passes_pitches_through() {
+        let quantizer = Quantizer::default();
+
+        assert_eq!(quantizer.quantize(3.3), 3.3);
+        assert_eq!(quantizer.quantize(-7.8), -7.8);
+    }
+
+    #[test]
+    fn chromatic_rounds_to_semitones() {
+        let quantizer = Quantizer::new(Scale::Chromatic, Mode::Ionian, 0);
+
+        assert_eq!(quantizer.quantize(3.3), 3.0);
+        assert_eq!(quantizer.quantize(3.7), 4.0);
+        assert_eq!(quantizer.quantize(-0.4), 0.0);
+        assert_eq!(quantizer.quantize(-11.6), -12.0);
+    }
+
+    #[test]
+    fn ionian_snaps_to_the_major_scale() {
+        let quantizer = Quantizer::new(Scale::Diatonic, Mode::Ionian, 0);
+        let snapped: [f32; 12] = core::array::from_fn(|class| quantizer.quantize(class as f32));
+
+        assert_eq!(
+            snapped,
+            [0.0, 0.0, 2.0, 2.0, 4.0, 5.0, 5.0, 7.0, 7.0, 9.0, 9.0, 11.0]
+        );
+    }
+
+    #[test]
+    fn modes_rotate_the_scale() {
+        let aeolian = Quantizer::new(Scale::Diatonic, Mode::Aeolian, 0);
+
+        // minor third and sixth
+        assert_eq!(aeolian.quantize(3.0), 3.0);
+        assert_eq!(aeolian.quantize(8.0), 8.0);
+        assert_eq!(aeolian.quantize(4.0), 3.0);
+
+        let dorian = Quantizer::new(Scale::Diatonic, Mode::Dorian, 0);
+
+        assert_eq!(dorian.quantize(9.0), 9.0);
+        assert_eq!(dorian.quantize(8.0), 7.0);
+    }
+
+    #[test]
+    fn root_shifts_the_degrees() {
+        let quantizer = Quantizer::new(Scale::Pentatonic, Mode::Ionian, 2);
+
+        assert_eq!(quantizer.quantize(2.0), 2.0);
+        assert_eq!(quantizer.quantize(1.2), 2.0);
+        assert_eq!(quantizer.quantize(6.0), 6.0);
+        assert_eq!(quantizer.quantize(8.0), 9.0);
+        assert_eq!(quantizer.quantize(-10.0), -10.0);
+    }
+
+    #[test]
+    fn pitches_above_the_last_degree_snap_to_the_next_octave() {
+        let quantizer = Quantizer::new(Scale::WholeTone, Mode::Ionian, 0);
+
+        assert_eq!(quantizer.quantize(11.2), 12.0);
+        assert_eq!(quantizer.quantize(23.0), 22.0);
+        assert_eq!(quantizer.quantize(-1.2), -2.0);
+    }
+
+    #[test]
+    fn stepping_wraps_around() {
+        let mut quantizer = Quantizer::default();
+
+        quantizer.step_scale(-1);
+        assert_eq!(quantizer.get_scale(), Scale::WholeTone);
+
+        quantizer.step_mode(-1);
+        assert_eq!(quantizer.get_mode(), Mode::Locrian);
+
+        quantizer.step_root(13);
+        assert_eq!(quantizer.get_root(), 1);
+        assert_eq!(quantizer.get_root_name(), "C#");
+
+        quantizer.step_fine_tune(-250);
+        assert_eq!(quantizer.get_fine_tune(), -100);
+    }
+
+    #[test]
+    fn fine_tune_shifts_quantized_pitches() {
+        let mut quantizer = Quantizer::new(Scale::Chromatic, Mode::Ionian, 0);
+
+        quantizer.step_fine_tune(25);
+
+        assert_eq!(quantizer.tune(6.9), 7.25);
+        assert_eq!(quantizer.tune(-3.2), -2.75);
+
+        // spread around a tuned pitch, the fine tune does not get added twice
+        assert_eq!(quantizer.retune(7.25), 7.25);
+        assert_eq!(quantizer.retune(8.8), 9.25);
+    }
+
+    #[test]
+    fn all_sources_agree() {
+        let quantizer = Quantizer::new(Scale::Diatonic, Mode::Ionian, 0);
+
+        let note = quantizer.quantize(note_to_semitones(MIDI_CENTER_NOTE + 7));
+        let cv = quantizer.quantize(volts_to_semitones(7.0 / 12.0));
+
+        assert_eq!(note, 7.0);
+        assert_eq!(cv, 7.0);
+    }
+}
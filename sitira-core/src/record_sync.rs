@@ -0,0 +1,52 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Hands an armed recording toggle from the control to the audio task.
+///
+/// The control task arms it, the audio task executes the toggle on the next gate edge and
+/// reports back, so the control task can catch up with the new transport state.
+pub struct RecordSync {
+    armed: AtomicBool,
+    fired: AtomicBool,
+}
+
+impl RecordSync {
+    pub const fn new() -> Self {
+        RecordSync {
+            armed: AtomicBool::new(false),
+            fired: AtomicBool::new(false),
+        }
+    }
+
+    pub fn arm(&self) {
+        self.armed.store(true, Ordering::Relaxed);
+    }
+
+    pub fn disarm(&self) {
+        self.armed.store(false, Ordering::Relaxed);
+    }
+
+    /// Called by the audio task on a gate edge. Returns `true` if the toggle was armed and has
+    /// to be executed now.
+    pub fn fire(&self) -> bool {
+        let armed = self.armed.swap(false, Ordering::Relaxed);
+
+        if armed {
+            self.fired.store(true, Ordering::Relaxed);
+        }
+
+        armed
+    }
+
+    /// Returns `true` once after the audio task executed the toggle.
+    pub fn take_fired(&self) -> bool {
+        self.fired.swap(false, Ordering::Relaxed)
+    }
+}
+
+impl Default for RecordSync {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub static RECORD_SYNC: RecordSync = RecordSync::new();
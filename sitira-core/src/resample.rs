@@ -0,0 +1,286 @@
+use core::f32::consts::PI;
+use core::ops::Range;
+
+use micromath::F32Ext;
+
+/// Taps of the polyphase filter, half of them on either side of the position
+const TAPS: usize = 16;
+/// Fractions of a sample the polyphase filter has coefficients for
+const PHASES: usize = 64;
+/// Highest ratio of file to engine rate, e.g. 384 kHz into 48 kHz
+pub const MAX_RATE_RATIO: u32 = 8;
+
+/// How the samples of a file at another rate are interpolated.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ResampleQuality {
+    /// Straight lines between two samples, fast but dull and with some aliasing
+    Linear,
+    /// Windowed sinc, which also filters everything above the lower of both Nyquist frequencies
+    Polyphase,
+}
+
+impl ResampleQuality {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ResampleQuality::Linear => "linear",
+            ResampleQuality::Polyphase => "polyphase",
+        }
+    }
+
+    pub fn toggle(&self) -> Self {
+        match self {
+            ResampleQuality::Linear => ResampleQuality::Polyphase,
+            ResampleQuality::Polyphase => ResampleQuality::Linear,
+        }
+    }
+
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            0 => ResampleQuality::Linear,
+            _ => ResampleQuality::Polyphase,
+        }
+    }
+}
+
+/// Converts samples from the rate of a file to the rate of the engine.
+///
+/// Output sample `n` lies at input position `n * input_rate / output_rate`, which is kept as an
+/// integer ratio, so the positions stay exact over files of any length. The input is handed in
+/// chunks which only need to cover `get_input_range()` of the output, so a file can be converted
+/// while it is read.
+pub struct Resampler {
+    input_rate: u32,
+    output_rate: u32,
+    quality: ResampleQuality,
+    /// Coefficients of every phase, the extra row lets the last phase be interpolated as well
+    coefficients: [[f32; TAPS]; PHASES + 1],
+}
+
+impl Resampler {
+    /// Returns `None` for rates which are zero or too far apart.
+    pub fn new(input_rate: u32, output_rate: u32, quality: ResampleQuality) -> Option<Self> {
+        if input_rate == 0
+            || output_rate == 0
+            || input_rate > output_rate * MAX_RATE_RATIO
+            || output_rate > input_rate * MAX_RATE_RATIO
+        {
+            return None;
+        }
+
+        let mut resampler = Resampler {
+            input_rate,
+            output_rate,
+            quality,
+            coefficients: [[0.0; TAPS]; PHASES + 1],
+        };
+
+        if quality == ResampleQuality::Polyphase {
+            resampler.design_filter();
+        }
+
+        Some(resampler)
+    }
+
+    /// Returns the number of output samples of an input of `length` samples.
+    pub fn get_output_length(&self, length: usize) -> usize {
+        let scaled = length as u64 * self.output_rate as u64;
+
+        scaled.div_ceil(self.input_rate as u64) as usize
+    }
+
+    /// Returns the most output samples whose input fits into `input_length` samples.
+    pub fn get_output_chunk(&self, input_length: usize) -> usize {
+        let usable = input_length.saturating_sub(TAPS) as u64;
+
+        ((usable * self.output_rate as u64 / self.input_rate as u64) as usize).max(1)
+    }
+
+    /// Returns the input samples the output samples `output` are interpolated from.
+    pub fn get_input_range(&self, output: Range<usize>) -> Range<usize> {
+        let (before, after) = self.get_reach();
+        let first = self.get_position(output.start).0;
+        let last = self.get_position(output.end.max(output.start + 1) - 1).0;
+
+        first.saturating_sub(before)..last + after + 1
+    }
+
+    /// Fills `output`, which starts at output sample `output_start`, from `input`, which starts at
+    /// input sample `input_start`. Input samples outside of `input` count as silence.
+    pub fn process(
+        &self,
+        input: &[f32],
+        input_start: usize,
+        output_start: usize,
+        output: &mut [f32],
+    ) {
+        let sample = |index: usize| {
+            index
+                .checked_sub(input_start)
+                .and_then(|index| input.get(index))
+                .copied()
+                .unwrap_or(0.0)
+        };
+
+        for (offset, value) in output.iter_mut().enumerate() {
+            let (index, fraction) = self.get_position(output_start + offset);
+
+            *value = match self.quality {
+                ResampleQuality::Linear => {
+                    sample(index) * (1.0 - fraction) + sample(index + 1) * fraction
+                }
+                ResampleQuality::Polyphase => {
+                    let phase = fraction * PHASES as f32;
+                    let row = phase as usize;
+                    let blend = phase - row as f32;
+                    let first = index as isize - (TAPS / 2 - 1) as isize;
+
+                    (0..TAPS).fold(0.0, |sum, tap| {
+                        let coefficient = self.coefficients[row][tap] * (1.0 - blend)
+                            + self.coefficients[row + 1][tap] * blend;
+                        let index = first + tap as isize;
+
+                        match usize::try_from(index) {
+                            Ok(index) => sum + sample(index) * coefficient,
+                            Err(_) => sum,
+                        }
+                    })
+                }
+            };
+        }
+    }
+
+    /// Returns the input sample before output sample `n` and the fraction beyond it.
+    fn get_position(&self, n: usize) -> (usize, f32) {
+        let scaled = n as u64 * self.input_rate as u64;
+        let index = scaled / self.output_rate as u64;
+        let remainder = scaled % self.output_rate as u64;
+
+        (index as usize, remainder as f32 / self.output_rate as f32)
+    }
+
+    /// Returns the input samples needed before and after the position.
+    fn get_reach(&self) -> (usize, usize) {
+        match self.quality {
+            ResampleQuality::Linear => (0, 1),
+            ResampleQuality::Polyphase => (TAPS / 2 - 1, TAPS / 2),
+        }
+    }
+
+    /// Calculates a Blackman windowed sinc for every phase, cut off at the lower Nyquist frequency.
+    fn design_filter(&mut self) {
+        let cutoff = (self.output_rate as f32 / self.input_rate as f32).min(1.0);
+        let half = (TAPS / 2) as f32;
+
+        for (row, coefficients) in self.coefficients.iter_mut().enumerate() {
+            let fraction = row as f32 / PHASES as f32;
+
+            for (tap, coefficient) in coefficients.iter_mut().enumerate() {
+                let x = tap as f32 - (TAPS / 2 - 1) as f32 - fraction;
+                let y = PI * x * cutoff;
+                let sinc = if y.abs() < 1e-6 { 1.0 } else { y.sin() / y };
+                let window = if x.abs() < half {
+                    0.42 + 0.5 * (PI * x / half).cos() + 0.08 * (2.0 * PI * x / half).cos()
+                } else {
+                    0.0
+                };
+
+                *coefficient = sinc * window;
+            }
+
+            // every phase passes DC unchanged
+            let sum: f32 = coefficients.iter().sum();
+
+            if sum.abs() > 1e-6 {
+                coefficients
+                    .iter_mut()
+                    .for_each(|coefficient| *coefficient /= sum);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(resampler: &Resampler, input: &[f32]) -> Vec<f32> {
+        let mut output = vec![0.0; resampler.get_output_length(input.len())];
+        resampler.process(input, 0, 0, &mut output);
+        output
+    }
+
+    #[test]
+    fn rejects_rates_too_far_apart() {
+        assert!(Resampler::new(0, 48000, ResampleQuality::Linear).is_none());
+        assert!(Resampler::new(48000 * 9, 48000, ResampleQuality::Linear).is_none());
+        assert!(Resampler::new(48000, 48000 * 9, ResampleQuality::Linear).is_none());
+        assert!(Resampler::new(48000 * 8, 48000, ResampleQuality::Linear).is_some());
+    }
+
+    #[test]
+    fn output_length_rounds_up() {
+        let resampler = Resampler::new(44100, 48000, ResampleQuality::Linear).unwrap();
+
+        assert_eq!(resampler.get_output_length(44100), 48000);
+        assert_eq!(resampler.get_output_length(1), 2);
+        assert_eq!(resampler.get_output_length(0), 0);
+    }
+
+    #[test]
+    fn same_rate_passes_samples_through() {
+        let input: Vec<f32> = (0..32).map(|n| (n as f32 * 0.3).sin()).collect();
+
+        for quality in [ResampleQuality::Linear, ResampleQuality::Polyphase] {
+            let resampler = Resampler::new(48000, 48000, quality).unwrap();
+            let output = convert(&resampler, &input);
+
+            for (expected, actual) in input.iter().zip(output.iter()).skip(TAPS).take(8) {
+                assert!((expected - actual).abs() < 1e-3, "{:?}", quality);
+            }
+        }
+    }
+
+    #[test]
+    fn linear_interpolates_between_samples() {
+        let resampler = Resampler::new(24000, 48000, ResampleQuality::Linear).unwrap();
+        let output = convert(&resampler, &[0.0, 1.0, 0.0]);
+
+        assert_eq!(output, [0.0, 0.5, 1.0, 0.5, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn polyphase_passes_dc() {
+        let resampler = Resampler::new(44100, 48000, ResampleQuality::Polyphase).unwrap();
+        let output = convert(&resampler, &[1.0; 200]);
+
+        for value in &output[TAPS..output.len() - TAPS] {
+            assert!((value - 1.0).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn chunks_match_a_single_pass() {
+        let resampler = Resampler::new(44100, 48000, ResampleQuality::Polyphase).unwrap();
+        let input: Vec<f32> = (0..500).map(|n| (n as f32 * 0.05).sin()).collect();
+        let whole = convert(&resampler, &input);
+
+        let mut chunked = vec![0.0; whole.len()];
+        let chunk = resampler.get_output_chunk(64);
+
+        for start in (0..whole.len()).step_by(chunk) {
+            let end = (start + chunk).min(whole.len());
+            let range = resampler.get_input_range(start..end);
+            let range = range.start..range.end.min(input.len());
+
+            assert!(range.len() <= 64);
+            resampler.process(
+                &input[range.clone()],
+                range.start,
+                start,
+                &mut chunked[start..end],
+            );
+        }
+
+        assert_eq!(whole, chunked);
+    }
+}
@@ -0,0 +1,177 @@
+/// Comb filter lengths of Freeverb at 44.1kHz
+const COMB_LENGTHS: [usize; 4] = [1116, 1188, 1277, 1356];
+/// Allpass filter lengths of Freeverb at 44.1kHz
+const ALLPASS_LENGTHS: [usize; 2] = [556, 441];
+/// Extra length of the right lines, decorrelates both sides
+const STEREO_SPREAD: usize = 23;
+const REFERENCE_SAMPLE_RATE: usize = 44_100;
+
+const INPUT_GAIN: f32 = 0.05;
+const DAMPING: f32 = 0.2;
+const ALLPASS_FEEDBACK: f32 = 0.5;
+/// Comb feedback of the smallest and the largest room
+const MIN_ROOM: f32 = 0.7;
+const MAX_ROOM: f32 = 0.98;
+
+/// Samples needed for all lines of both channels at the given sample rate
+pub const fn get_buffer_length(sample_rate: usize) -> usize {
+    let mut length = 0;
+    let mut i = 0;
+
+    while i < COMB_LENGTHS.len() {
+        length += COMB_LENGTHS[i];
+        i += 1;
+    }
+
+    i = 0;
+    while i < ALLPASS_LENGTHS.len() {
+        length += ALLPASS_LENGTHS[i];
+        i += 1;
+    }
+
+    let lines = COMB_LENGTHS.len() + ALLPASS_LENGTHS.len();
+
+    // rounded up, so every line fits
+    2 * ((length + lines * STEREO_SPREAD) * sample_rate / REFERENCE_SAMPLE_RATE + lines)
+}
+
+/// Section of the reverb buffer which works as ring buffer.
+struct Line {
+    start: usize,
+    length: usize,
+    position: usize,
+}
+
+impl Line {
+    fn read(&self, buffer: &[f32]) -> f32 {
+        buffer[self.start + self.position]
+    }
+
+    fn write(&mut self, buffer: &mut [f32], value: f32) {
+        buffer[self.start + self.position] = value;
+        self.position = (self.position + 1) % self.length;
+    }
+}
+
+/// Lowpass feedback comb filter.
+struct Comb {
+    line: Line,
+    filter: f32,
+}
+
+impl Comb {
+    fn process(&mut self, buffer: &mut [f32], input: f32, feedback: f32) -> f32 {
+        let output = self.line.read(buffer);
+        self.filter = output * (1.0 - DAMPING) + self.filter * DAMPING;
+        self.line.write(buffer, input + self.filter * feedback);
+        output
+    }
+}
+
+struct Allpass {
+    line: Line,
+}
+
+impl Allpass {
+    fn process(&mut self, buffer: &mut [f32], input: f32) -> f32 {
+        let delayed = self.line.read(buffer);
+        self.line.write(buffer, input + delayed * ALLPASS_FEEDBACK);
+        delayed - input
+    }
+}
+
+/// The filters of one side.
+struct Channel {
+    combs: [Comb; 4],
+    allpasses: [Allpass; 2],
+}
+
+impl Channel {
+    fn process(&mut self, buffer: &mut [f32], input: f32, feedback: f32) -> f32 {
+        let mut output = 0.0;
+
+        for comb in self.combs.iter_mut() {
+            output += comb.process(buffer, input, feedback);
+        }
+
+        for allpass in self.allpasses.iter_mut() {
+            output = allpass.process(buffer, output);
+        }
+
+        output
+    }
+}
+
+/// Schroeder reverb in the style of Freeverb, with half the combs.
+///
+/// Works as a send on the mixed down signal, every line lives in SDRAM.
+pub struct Reverb {
+    buffer: &'static mut [f32],
+    left: Channel,
+    right: Channel,
+    feedback: f32,
+    mix: f32,
+}
+
+impl Reverb {
+    /// Creates the reverb on top of `buffer`, which needs to hold `get_buffer_length()` samples.
+    pub fn new(buffer: &'static mut [f32], sample_rate: f32) -> Self {
+        assert!(buffer.len() >= get_buffer_length(sample_rate as usize));
+
+        buffer.fill(0.0);
+
+        let mut next = 0;
+        let mut allocate = |length: usize, spread: usize| {
+            let length =
+                ((length + spread) as f32 * sample_rate / REFERENCE_SAMPLE_RATE as f32) as usize;
+            let line = Line {
+                start: next,
+                length: length.max(1),
+                position: 0,
+            };
+
+            next += line.length;
+            line
+        };
+
+        let mut channel = |spread: usize| Channel {
+            combs: COMB_LENGTHS.map(|length| Comb {
+                line: allocate(length, spread),
+                filter: 0.0,
+            }),
+            allpasses: ALLPASS_LENGTHS.map(|length| Allpass {
+                line: allocate(length, spread),
+            }),
+        };
+
+        let left = channel(0);
+        let right = channel(STEREO_SPREAD);
+
+        Reverb {
+            buffer,
+            left,
+            right,
+            feedback: MIN_ROOM,
+            mix: 0.0,
+        }
+    }
+
+    /// Sets the room size between `0.0` and `1.0`, larger rooms decay longer.
+    pub fn set_size(&mut self, size: f32) {
+        self.feedback = MIN_ROOM + (MAX_ROOM - MIN_ROOM) * size.clamp(0.0, 1.0);
+    }
+
+    /// Sets the level of the reverb which gets added to the dry signal.
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix = mix.clamp(0.0, 1.0);
+    }
+
+    pub fn process(&mut self, left: f32, right: f32) -> (f32, f32) {
+        let input = (left + right) * INPUT_GAIN;
+
+        let wet_left = self.left.process(self.buffer, input, self.feedback);
+        let wet_right = self.right.process(self.buffer, input, self.feedback);
+
+        (left + wet_left * self.mix, right + wet_right * self.mix)
+    }
+}
@@ -0,0 +1,255 @@
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Bookkeeping of the audio buffer which is recorded into and played back.
+///
+/// The ring only tracks how much of its memory is valid, the memory itself is handed in by
+/// whoever holds it. A `RingWriter` needs it mutably and a `RingReader` immutably, so the borrow
+/// checker keeps the record path and the engines from ever touching the same samples at once.
+/// Everything is stored in atomics, so every task can query the length without locking.
+pub struct AudioRing {
+    length: AtomicUsize,
+    wrapped: AtomicBool,
+}
+
+impl AudioRing {
+    pub const fn new() -> Self {
+        AudioRing {
+            length: AtomicUsize::new(0),
+            wrapped: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the amount of valid samples, which is also the position of the write head.
+    pub fn len(&self) -> usize {
+        self.length.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if the writer wrapped around since the ring was cleared, so the whole
+    /// memory holds recorded samples.
+    pub fn is_wrapped(&self) -> bool {
+        self.wrapped.load(Ordering::Relaxed)
+    }
+
+    /// Empties the ring for a new take.
+    pub fn clear(&self) {
+        self.set_len(0);
+    }
+
+    /// Sets the amount of valid samples, e.g. after switching to another buffer or changing the
+    /// loop length.
+    pub fn set_len(&self, length: usize) {
+        self.wrapped.store(false, Ordering::Relaxed);
+        self.length.store(length, Ordering::Relaxed);
+    }
+
+    /// Returns a writer which continues at the write head. The new length gets published when
+    /// the writer is dropped.
+    pub fn writer<'a>(&'a self, memory: &'a mut [f32]) -> RingWriter<'a> {
+        RingWriter {
+            ring: self,
+            head: self.len().min(memory.len()),
+            wrapped: self.is_wrapped(),
+            memory,
+        }
+    }
+
+    /// Returns a reader over the valid samples.
+    pub fn reader<'a>(&self, memory: &'a [f32]) -> RingReader<'a> {
+        let length = self.len().min(memory.len());
+
+        RingReader {
+            samples: &memory[..length],
+            oldest: 0,
+            readable: length,
+        }
+    }
+
+    /// Returns a reader which keeps `gap` samples away from the write head, for reading while
+    /// the ring is being written.
+    ///
+    /// The reader spans all of the memory once the writer has wrapped around. In that case the
+    /// samples right in front of the head are avoided as well, since they are the next ones to
+    /// be overwritten.
+    pub fn reader_behind<'a>(&self, memory: &'a [f32], gap: usize) -> RingReader<'a> {
+        let head = self.len().min(memory.len());
+
+        if self.is_wrapped() {
+            RingReader {
+                samples: memory,
+                oldest: (head + gap) % memory.len().max(1),
+                readable: memory.len().saturating_sub(2 * gap),
+            }
+        } else {
+            RingReader {
+                samples: &memory[..head],
+                oldest: 0,
+                readable: head.saturating_sub(gap),
+            }
+        }
+    }
+}
+
+impl Default for AudioRing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Appends samples at the write head of an `AudioRing`, wrapping around at the end of its memory.
+pub struct RingWriter<'a> {
+    ring: &'a AudioRing,
+    memory: &'a mut [f32],
+    head: usize,
+    wrapped: bool,
+}
+
+impl RingWriter<'_> {
+    pub fn push(&mut self, sample: f32) {
+        if self.memory.is_empty() {
+            return;
+        }
+
+        if self.head >= self.memory.len() {
+            self.head = 0;
+            self.wrapped = true;
+        }
+
+        self.memory[self.head] = sample;
+        self.head += 1;
+    }
+}
+
+impl Drop for RingWriter<'_> {
+    fn drop(&mut self) {
+        self.ring.length.store(self.head, Ordering::Relaxed);
+        self.ring.wrapped.store(self.wrapped, Ordering::Relaxed);
+    }
+}
+
+/// Readable part of an `AudioRing`.
+///
+/// Engines get the samples as one slice. The part of it which is safe to read starts at the
+/// oldest sample and may wrap around the end of the slice.
+#[derive(Clone, Copy)]
+pub struct RingReader<'a> {
+    samples: &'a [f32],
+    oldest: usize,
+    readable: usize,
+}
+
+impl<'a> RingReader<'a> {
+    pub fn as_slice(&self) -> &'a [f32] {
+        self.samples
+    }
+
+    /// Returns the amount of samples which are safe to read.
+    pub fn len(&self) -> usize {
+        self.readable
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.readable == 0
+    }
+
+    /// Maps a normalized offset from the oldest to the newest readable sample onto the slice.
+    pub fn map_offset(&self, offset: f32) -> f32 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+
+        let position = self.oldest + (offset.clamp(0.0, 1.0) * self.readable as f32) as usize;
+
+        (position % self.samples.len()) as f32 / self.samples.len() as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(ring: &AudioRing, memory: &mut [f32], samples: impl IntoIterator<Item = f32>) {
+        let mut writer = ring.writer(memory);
+        samples.into_iter().for_each(|sample| writer.push(sample));
+    }
+
+    #[test]
+    fn writer_publishes_its_length_when_dropped() {
+        let ring = AudioRing::new();
+        let mut memory = [0.0; 8];
+
+        record(&ring, &mut memory, [1.0, 2.0, 3.0]);
+        record(&ring, &mut memory, [4.0]);
+
+        assert_eq!(ring.len(), 4);
+        assert!(!ring.is_wrapped());
+        assert_eq!(memory[..4], [1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn writer_wraps_around_and_overwrites_the_oldest() {
+        let ring = AudioRing::new();
+        let mut memory = [0.0; 4];
+
+        record(&ring, &mut memory, (1..=6).map(|n| n as f32));
+
+        assert!(ring.is_wrapped());
+        assert_eq!(ring.len(), 2);
+        assert_eq!(memory, [5.0, 6.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn clear_starts_a_new_take() {
+        let ring = AudioRing::new();
+        let mut memory = [0.0; 4];
+
+        record(&ring, &mut memory, (1..=6).map(|n| n as f32));
+        ring.clear();
+
+        assert!(ring.is_empty());
+        assert!(!ring.is_wrapped());
+        assert!(ring.reader(&memory).is_empty());
+    }
+
+    #[test]
+    fn reader_lags_behind_the_head() {
+        let ring = AudioRing::new();
+        let mut memory = [0.0; 16];
+
+        record(&ring, &mut memory, (0..10).map(|n| n as f32));
+        let reader = ring.reader_behind(&memory, 4);
+
+        assert_eq!(reader.len(), 6);
+        assert_eq!(reader.as_slice().len(), 10);
+        assert_eq!(reader.map_offset(0.0), 0.0);
+        assert_eq!(reader.map_offset(1.0), 6.0 / 10.0);
+    }
+
+    #[test]
+    fn reader_behind_a_short_take_is_empty() {
+        let ring = AudioRing::new();
+        let mut memory = [0.0; 16];
+
+        record(&ring, &mut memory, [1.0, 2.0]);
+
+        assert!(ring.reader_behind(&memory, 4).is_empty());
+    }
+
+    #[test]
+    fn reader_avoids_both_sides_of_the_head_after_an_overrun() {
+        let ring = AudioRing::new();
+        let mut memory = [0.0; 16];
+
+        record(&ring, &mut memory, (0..20).map(|n| n as f32));
+        let reader = ring.reader_behind(&memory, 2);
+
+        // the head is at 4, the oldest safe sample two in front of it
+        assert_eq!(reader.len(), 12);
+        assert_eq!(reader.map_offset(0.0), 6.0 / 16.0);
+        // the newest safe sample wraps around the end of the memory
+        assert_eq!(reader.map_offset(1.0), 2.0 / 16.0);
+    }
+}
@@ -0,0 +1,165 @@
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Seed the grains start with, until another one gets picked
+pub const DEFAULT_SEED: u32 = 0x5175_1a5e;
+
+/// Deterministic random numbers, xoshiro128++.
+///
+/// The same seed always gives the same numbers, so whatever draws from it can be reproduced by
+/// starting over from the seed. Fast and small, but not meant for anything that needs to be
+/// unpredictable.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: [u32; 4],
+}
+
+impl Rng {
+    /// Starts the numbers of `seed`, every seed including `0` gives a usable state.
+    pub fn new(seed: u32) -> Self {
+        // splitmix32 spreads the seed over the whole state, which must not be all zero
+        let mut mix = seed;
+        let mut state = [0; 4];
+
+        for word in state.iter_mut() {
+            mix = mix.wrapping_add(0x9e37_79b9);
+
+            let mut z = mix;
+            z = (z ^ (z >> 16)).wrapping_mul(0x85eb_ca6b);
+            z = (z ^ (z >> 13)).wrapping_mul(0xc2b2_ae35);
+            *word = z ^ (z >> 16);
+        }
+
+        if state == [0; 4] {
+            state[0] = 1;
+        }
+
+        Rng { state }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let [s0, s1, s2, s3] = self.state;
+        let result = s0.wrapping_add(s3).rotate_left(7).wrapping_add(s0);
+        let t = s1 << 9;
+
+        self.state[2] = s2 ^ s0;
+        self.state[3] = s3 ^ s1;
+        self.state[1] = s1 ^ self.state[2];
+        self.state[0] = s0 ^ self.state[3];
+        self.state[2] ^= t;
+        self.state[3] = self.state[3].rotate_left(11);
+
+        result
+    }
+
+    /// Returns a number from `0.0` up to, but excluding, `1.0`.
+    pub fn next_unit(&mut self) -> f32 {
+        // the upper 24 bits fit the mantissa exactly
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    /// Returns a number from `-1.0` up to, but excluding, `1.0`.
+    pub fn next_bipolar(&mut self) -> f32 {
+        self.next_unit() * 2.0 - 1.0
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Self::new(DEFAULT_SEED)
+    }
+}
+
+/// Seed of the grain randomization, set by the control task and taken over by the audio task.
+pub struct SeedControl {
+    seed: AtomicU32,
+    changed: AtomicBool,
+}
+
+impl SeedControl {
+    pub const fn new() -> Self {
+        SeedControl {
+            seed: AtomicU32::new(DEFAULT_SEED),
+            // so every grain cloud gets seeded once at the start
+            changed: AtomicBool::new(true),
+        }
+    }
+
+    pub fn get(&self) -> u32 {
+        self.seed.load(Ordering::Relaxed)
+    }
+
+    /// Sets the seed, the randomization starts over from it even if it stays the same.
+    pub fn set(&self, seed: u32) {
+        self.seed.store(seed, Ordering::Relaxed);
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    /// Moves the seed by `steps`.
+    pub fn step(&self, steps: i32) {
+        self.set(self.get().wrapping_add(steps as u32));
+    }
+
+    /// Returns `true` once after the seed has been set.
+    pub fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::Relaxed)
+    }
+}
+
+impl Default for SeedControl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Seed the grain clouds scatter their grains with
+pub static GRAIN_SEED: SeedControl = SeedControl::new();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_gives_same_numbers() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let mut c = Rng::new(43);
+
+        let numbers: Vec<u32> = (0..16).map(|_| a.next_u32()).collect();
+
+        assert_eq!(numbers, (0..16).map(|_| b.next_u32()).collect::<Vec<_>>());
+        assert_ne!(numbers, (0..16).map(|_| c.next_u32()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn numbers_stay_in_their_range() {
+        let mut rng = Rng::new(0);
+        let mut sum = 0.0;
+
+        for _ in 0..10_000 {
+            let unit = rng.next_unit();
+            let bipolar = rng.next_bipolar();
+
+            assert!((0.0..1.0).contains(&unit));
+            assert!((-1.0..1.0).contains(&bipolar));
+
+            sum += bipolar;
+        }
+
+        // evenly spread around zero
+        assert!((sum / 10_000.0f32).abs() < 0.05);
+    }
+
+    #[test]
+    fn seed_changes_get_taken_once() {
+        let control = SeedControl::new();
+
+        assert!(control.take_changed());
+        assert!(!control.take_changed());
+
+        control.step(-1);
+
+        assert_eq!(control.get(), DEFAULT_SEED - 1);
+        assert!(control.take_changed());
+        assert!(!control.take_changed());
+    }
+}
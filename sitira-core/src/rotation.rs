@@ -0,0 +1,87 @@
+/// Amount by which the buffer start point gets shifted on every trigger.
+#[derive(Clone, Copy)]
+pub enum RotationAmount {
+    /// A musical division of the buffer length, e.g. `Division(8)` shifts by an eighth of the loop.
+    Division(u8),
+    /// A fraction of the buffer length between `0.0` and `1.0`.
+    Percentage(f32),
+}
+
+/// Selectable musical divisions of the buffer
+const DIVISIONS: [u8; 8] = [1, 2, 3, 4, 6, 8, 12, 16];
+
+/// Rotates the effective start point of the audio buffer.
+///
+/// Every trigger (usually a gate) moves the start point further by a fixed amount, wrapping
+/// around at the end of the buffer. The rotation is applied as a transform on the normalized
+/// grain offset, so the recorded material itself is never touched.
+pub struct BufferRotation {
+    amount: RotationAmount,
+    position: f32,
+}
+
+impl BufferRotation {
+    pub fn new(amount: RotationAmount) -> Self {
+        BufferRotation {
+            amount,
+            position: 0.0,
+        }
+    }
+
+    pub fn set_amount(&mut self, amount: RotationAmount) {
+        self.amount = amount;
+    }
+
+    /// Steps through the musical divisions. Switches to a division if a percentage was set.
+    pub fn step_division(&mut self, steps: i32) {
+        let current = match self.amount {
+            RotationAmount::Division(division) => DIVISIONS
+                .iter()
+                .position(|d| *d >= division)
+                .unwrap_or(DIVISIONS.len() - 1),
+            RotationAmount::Percentage(_) => 0,
+        };
+
+        let next = (current as i32 + steps).clamp(0, DIVISIONS.len() as i32 - 1) as usize;
+        self.amount = RotationAmount::Division(DIVISIONS[next]);
+    }
+
+    pub fn get_amount(&self) -> RotationAmount {
+        self.amount
+    }
+
+    /// Shifts the start point by one step.
+    pub fn trigger(&mut self) {
+        self.position = wrap(self.position + self.step());
+    }
+
+    /// Moves the start point back to the beginning of the buffer.
+    pub fn reset(&mut self) {
+        self.position = 0.0;
+    }
+
+    /// Returns the current rotation as a fraction of the buffer length.
+    pub fn get_position(&self) -> f32 {
+        self.position
+    }
+
+    /// Applies the rotation to a normalized offset (`0.0` to `1.0`).
+    pub fn apply(&self, offset: f32) -> f32 {
+        wrap(offset + self.position)
+    }
+
+    fn step(&self) -> f32 {
+        match self.amount {
+            RotationAmount::Division(division) => 1.0 / division.max(1) as f32,
+            RotationAmount::Percentage(percentage) => percentage.clamp(0.0, 1.0),
+        }
+    }
+}
+
+fn wrap(value: f32) -> f32 {
+    if value > 1.0 {
+        value - 1.0
+    } else {
+        value
+    }
+}
@@ -0,0 +1,106 @@
+use crate::event::{Command, Event, Input};
+use crate::expander::EXPANDER_BUTTONS;
+
+/// Number of assignable gate inputs
+pub const GATE_COUNT: usize = 4;
+
+/// MIDI note which plays the first slice, every note above selects the next slice
+pub const SLICE_BASE_NOTE: u8 = 36;
+
+/// Action which gets executed when a gate input or a button of an expansion panel fires.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TriggerAction {
+    None,
+    RotateBuffer,
+    ToggleRecording,
+    NextSlice,
+    RecallScene(u8),
+}
+
+impl TriggerAction {
+    /// Command the action sends, `None` for an unassigned input.
+    pub fn command(&self) -> Option<Command> {
+        match self {
+            TriggerAction::None => None,
+            TriggerAction::RotateBuffer => Some(Command::RotateBuffer),
+            TriggerAction::ToggleRecording => Some(Command::ToggleRecording),
+            TriggerAction::NextSlice => Some(Command::NextSlice),
+            TriggerAction::RecallScene(scene) => Some(Command::RecallScene(*scene)),
+        }
+    }
+}
+
+/// Routes gate triggers and the buttons of an expansion panel to their assigned actions.
+///
+/// Gate and button presses get translated into commands, so all consumers handle them exactly
+/// like any other command source. With scene gates on, every gate recalls the scene of its
+/// number instead of its own action.
+pub struct TriggerRouting {
+    actions: [TriggerAction; GATE_COUNT],
+    buttons: [TriggerAction; EXPANDER_BUTTONS],
+    scene_gates: bool,
+}
+
+impl TriggerRouting {
+    pub fn new(
+        actions: [TriggerAction; GATE_COUNT],
+        buttons: [TriggerAction; EXPANDER_BUTTONS],
+    ) -> Self {
+        TriggerRouting {
+            actions,
+            buttons,
+            scene_gates: false,
+        }
+    }
+
+    pub fn is_scene_gates(&self) -> bool {
+        self.scene_gates
+    }
+
+    pub fn set_scene_gates(&mut self, scene_gates: bool) {
+        self.scene_gates = scene_gates;
+    }
+
+    pub fn set_action(&mut self, gate: usize, action: TriggerAction) {
+        if let Some(slot) = self.actions.get_mut(gate) {
+            *slot = action;
+        }
+    }
+
+    pub fn get_action(&self, gate: usize) -> TriggerAction {
+        if self.scene_gates && gate < GATE_COUNT {
+            return TriggerAction::RecallScene(gate as u8);
+        }
+
+        self.actions
+            .get(gate)
+            .copied()
+            .unwrap_or(TriggerAction::None)
+    }
+
+    pub fn get_button_action(&self, button: usize) -> TriggerAction {
+        self.buttons
+            .get(button)
+            .copied()
+            .unwrap_or(TriggerAction::None)
+    }
+
+    /// Translates gate and button events into their assigned command and MIDI notes into slice
+    /// selections. All other events are passed through.
+    pub fn route(&self, event: Event) -> Event {
+        match event {
+            Event::Pressed(Input::Gate(gate)) => self
+                .get_action(gate as usize)
+                .command()
+                .map_or(event, Event::Command),
+            Event::Pressed(Input::ExpanderButton(button)) => self
+                .get_button_action(button as usize)
+                .command()
+                .map_or(event, Event::Command),
+            Event::NoteOn { note, .. } if note >= SLICE_BASE_NOTE => {
+                Event::Command(Command::JumpToSlice(note - SLICE_BASE_NOTE))
+            }
+            _ => event,
+        }
+    }
+}
@@ -0,0 +1,279 @@
+/// Amount of scenes which can be stored
+pub const SCENE_COUNT: usize = 8;
+/// Every scene holds a value for each multiplexed ADC channel
+pub const SCENE_CHANNELS: usize = 16;
+/// Longest glide into a recalled scene
+pub const MAX_GLIDE_IN_MS: u32 = 10_000;
+/// Change of the glide per menu step
+const GLIDE_STEP_IN_MS: i32 = 100;
+/// Distance a knob has to move from where it was at a recall to take its channel back
+const TAKEOVER_THRESHOLD: f32 = 0.05;
+
+/// Snapshot of all multiplexed parameter values.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Scene {
+    values: [f32; SCENE_CHANNELS],
+}
+
+/// Scene the channels glide into after it was recalled.
+struct Recall {
+    start: [f32; SCENE_CHANNELS],
+    target: [f32; SCENE_CHANNELS],
+    /// Knob values at the first update of the recall
+    knobs: Option<[f32; SCENE_CHANNELS]>,
+    position: f32,
+    /// Channels whose knob has been turned since, one bit each
+    released: u16,
+}
+
+/// Stores scenes and morphs between two of them.
+///
+/// A morph source is one of the multiplexed channels, usually a CV input. Its value moves
+/// continuously from scene A (`0.0`, 0V) to scene B (`1.0`, 5V). While a morph is active, the
+/// interpolated values replace all other channels, the source itself keeps its own value.
+/// Discrete channels, like selectors, are not interpolated but switch over halfway.
+///
+/// A scene can also be recalled, e.g. by a gate. The channels then glide from where they are to
+/// the scene and hold it, until their knob gets turned.
+pub struct SceneMorph {
+    scenes: [Option<Scene>; SCENE_COUNT],
+    discrete: u16,
+    selected: usize,
+    scene_a: usize,
+    scene_b: usize,
+    source: Option<usize>,
+    recall: Option<Recall>,
+    glide_in_ms: u32,
+    /// Values of the last update, where a recall starts from
+    current: [f32; SCENE_CHANNELS],
+}
+
+impl SceneMorph {
+    /// Creates the scene storage, `discrete` lists the channels which must not be interpolated.
+    pub fn new(discrete: &[usize]) -> Self {
+        SceneMorph {
+            scenes: [None; SCENE_COUNT],
+            discrete: discrete
+                .iter()
+                .filter(|channel| **channel < SCENE_CHANNELS)
+                .fold(0, |mask, channel| mask | 1 << channel),
+            selected: 0,
+            scene_a: 0,
+            scene_b: 1,
+            source: None,
+            recall: None,
+            glide_in_ms: 0,
+            current: [0.0; SCENE_CHANNELS],
+        }
+    }
+
+    pub fn select(&mut self, steps: i32) {
+        self.selected = step_scene(self.selected, steps);
+    }
+
+    /// Stores the values into the selected scene.
+    pub fn store(&mut self, values: &[f32; SCENE_CHANNELS]) {
+        self.scenes[self.selected] = Some(Scene { values: *values });
+    }
+
+    /// Returns the values of a scene, `None` if nothing has been stored into it.
+    pub fn get_scene(&self, index: usize) -> Option<[f32; SCENE_CHANNELS]> {
+        self.scenes
+            .get(index)
+            .copied()
+            .flatten()
+            .map(|scene| scene.values)
+    }
+
+    /// Puts back a scene, e.g. from a session.
+    pub fn restore_scene(&mut self, index: usize, values: &[f32; SCENE_CHANNELS]) {
+        if let Some(scene) = self.scenes.get_mut(index) {
+            *scene = Some(Scene { values: *values });
+        }
+    }
+
+    /// Returns scene A, scene B and the source of the morph.
+    pub fn get_morph(&self) -> (usize, usize, Option<usize>) {
+        (self.scene_a, self.scene_b, self.source)
+    }
+
+    pub fn set_morph(&mut self, scene_a: usize, scene_b: usize, source: Option<usize>) {
+        self.scene_a = scene_a % SCENE_COUNT;
+        self.scene_b = scene_b % SCENE_COUNT;
+        self.source = source.filter(|channel| *channel < SCENE_CHANNELS);
+    }
+
+    pub fn step_scene_a(&mut self, steps: i32) {
+        self.scene_a = step_scene(self.scene_a, steps);
+    }
+
+    pub fn step_scene_b(&mut self, steps: i32) {
+        self.scene_b = step_scene(self.scene_b, steps);
+    }
+
+    /// Channel which drives the morph, `None` if morphing is turned off.
+    pub fn get_source(&self) -> Option<usize> {
+        self.source
+    }
+
+    /// Steps through the channels, below the first channel the morph is turned off.
+    pub fn step_source(&mut self, steps: i32) {
+        let position = self.source.map_or(0, |channel| channel as i32 + 1) + steps;
+
+        self.source = match position.clamp(0, SCENE_CHANNELS as i32) {
+            0 => None,
+            position => Some(position as usize - 1),
+        };
+    }
+
+    pub fn get_glide(&self) -> u32 {
+        self.glide_in_ms
+    }
+
+    pub fn step_glide(&mut self, steps: i32) {
+        self.glide_in_ms = (self.glide_in_ms as i32 + steps * GLIDE_STEP_IN_MS)
+            .clamp(0, MAX_GLIDE_IN_MS as i32) as u32;
+    }
+
+    /// Starts gliding into a scene, an empty one is ignored.
+    pub fn recall(&mut self, index: usize) {
+        if let Some(target) = self.get_scene(index) {
+            self.recall = Some(Recall {
+                start: self.current,
+                target,
+                knobs: None,
+                position: 0.0,
+                released: 0,
+            });
+        }
+    }
+
+    /// Moves a recall on by one update of `update_in_ms` and puts it over `values`.
+    pub fn apply_recall(&mut self, values: &mut [f32; SCENE_CHANNELS], update_in_ms: u32) {
+        if let Some(recall) = &mut self.recall {
+            let knobs = *recall.knobs.get_or_insert(*values);
+
+            recall.position = match self.glide_in_ms {
+                0 => 1.0,
+                glide_in_ms => {
+                    (recall.position + update_in_ms as f32 / glide_in_ms as f32).min(1.0)
+                }
+            };
+
+            for (channel, value) in values.iter_mut().enumerate() {
+                let bit = 1 << channel;
+
+                if (*value - knobs[channel]).abs() > TAKEOVER_THRESHOLD {
+                    recall.released |= bit;
+                }
+
+                if recall.released & bit != 0 {
+                    continue;
+                }
+
+                let (start, target) = (recall.start[channel], recall.target[channel]);
+
+                *value = if self.discrete & bit != 0 {
+                    if recall.position < 0.5 {
+                        start
+                    } else {
+                        target
+                    }
+                } else {
+                    start + (target - start) * recall.position
+                };
+            }
+
+            // the recall is over once every knob has been turned
+            if recall.released == u16::MAX {
+                self.recall = None;
+            }
+        }
+
+        self.current = *values;
+    }
+
+    /// Replaces `values` with the interpolation between scene A and B at `position`. Does
+    /// nothing as long as no source is selected or one of the scenes is empty.
+    pub fn morph(&self, position: f32, values: &mut [f32; SCENE_CHANNELS]) {
+        let source = match self.source {
+            Some(source) => source,
+            None => return,
+        };
+
+        let (a, b) = match (self.scenes[self.scene_a], self.scenes[self.scene_b]) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return,
+        };
+
+        let position = position.clamp(0.0, 1.0);
+
+        for (channel, value) in values.iter_mut().enumerate() {
+            if channel == source {
+                continue;
+            }
+
+            *value = if self.discrete & 1 << channel != 0 {
+                if position < 0.5 {
+                    a.values[channel]
+                } else {
+                    b.values[channel]
+                }
+            } else {
+                a.values[channel] + (b.values[channel] - a.values[channel]) * position
+            };
+        }
+    }
+}
+
+impl Default for SceneMorph {
+    fn default() -> Self {
+        Self::new(&[])
+    }
+}
+
+fn step_scene(scene: usize, steps: i32) -> usize {
+    (scene as i32 + steps).rem_euclid(SCENE_COUNT as i32) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recall_glides_until_a_knob_turns() {
+        let mut scenes = SceneMorph::new(&[]);
+        let mut values = [0.0; SCENE_CHANNELS];
+
+        scenes.store(&[1.0; SCENE_CHANNELS]);
+        scenes.apply_recall(&mut values, 50);
+
+        // a glide over four updates
+        scenes.step_glide(2);
+        scenes.recall(0);
+
+        let mut values = [0.0; SCENE_CHANNELS];
+        scenes.apply_recall(&mut values, 50);
+        assert_eq!(values[0], 0.25);
+
+        for _ in 0..3 {
+            values = [0.0; SCENE_CHANNELS];
+            scenes.apply_recall(&mut values, 50);
+        }
+
+        assert_eq!(values, [1.0; SCENE_CHANNELS]);
+
+        // the turned knob takes its channel back, the others hold the scene
+        values = [0.0; SCENE_CHANNELS];
+        values[3] = 0.5;
+        scenes.apply_recall(&mut values, 50);
+        assert_eq!(values[3], 0.5);
+        assert_eq!(values[4], 1.0);
+
+        // an empty scene is no recall
+        scenes.recall(1);
+        values = [0.0; SCENE_CHANNELS];
+        scenes.apply_recall(&mut values, 50);
+        assert_eq!(values[4], 1.0);
+    }
+}
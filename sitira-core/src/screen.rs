@@ -0,0 +1,1156 @@
+use core::convert::Infallible;
+use core::fmt::Write;
+use core::ops::{Neg, Range};
+
+use embedded_graphics::{
+    mono_font::{ascii, MonoFont, MonoTextStyle},
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::{Line, Polyline, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
+    text::{Alignment, Text},
+};
+
+use micromath::F32Ext;
+
+use crate::curve::ResponseCurve;
+use crate::grains::{GrainDot, Window};
+use crate::kit::Kit;
+use crate::meter::CLIP_LEVEL;
+use crate::strings::{self, UiText};
+use crate::theme::THEME;
+use crate::timecode::TimeText;
+use crate::waveform::Peak;
+
+/// Width the interface is laid out for, the one of the ILI9341 in landscape orientation, and the
+/// widest screen it is drawn on
+pub const WIDTH: usize = 320;
+/// Height the interface is laid out for
+pub const HEIGHT: usize = 240;
+
+/// How a screen is turned.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Orientation {
+    Landscape,
+    Portrait,
+}
+
+/// Sizes of text on the display.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FontSize {
+    /// Lists, readouts and labels
+    Small,
+    /// Values which pop up while they are changed
+    Medium,
+    /// Messages and titles
+    Large,
+}
+
+/// Returns the font of a text size.
+pub fn font(size: FontSize) -> &'static MonoFont<'static> {
+    match size {
+        FontSize::Small => &ascii::FONT_6X9,
+        FontSize::Medium => &ascii::FONT_9X15,
+        FontSize::Large => &ascii::FONT_10X20,
+    }
+}
+
+/// Places the interface on a screen of any size.
+///
+/// Positions and lengths are given for `WIDTH` x `HEIGHT` and scaled to the screen. Compact
+/// screens, like the 128x64 of the SSD1306, are too small for that: they stack one row of readout
+/// values, the popup, the waveform, the progress and the meters, write every text in the smallest
+/// font and leave out the cloud readout, the stretch preview and the labels.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Layout {
+    width: u32,
+    height: u32,
+}
+
+impl Layout {
+    /// Height of a row of text on a compact screen
+    const COMPACT_ROW: i32 = 7;
+
+    pub fn new(size: Size) -> Self {
+        Layout {
+            width: size.width,
+            height: size.height,
+        }
+    }
+
+    /// Returns `true` for screens less than half as high as the layout.
+    pub fn is_compact(&self) -> bool {
+        self.height < HEIGHT as u32 / 2
+    }
+
+    pub fn x(&self, x: i32) -> i32 {
+        x * self.width as i32 / WIDTH as i32
+    }
+
+    pub fn y(&self, y: i32) -> i32 {
+        y * self.height as i32 / HEIGHT as i32
+    }
+
+    pub fn point(&self, x: i32, y: i32) -> Point {
+        Point::new(self.x(x), self.y(y))
+    }
+
+    /// Scales a size, which stays at least one pixel in both directions.
+    pub fn size(&self, width: u32, height: u32) -> Size {
+        Size::new(
+            (self.x(width as i32) as u32).max(1),
+            (self.y(height as i32) as u32).max(1),
+        )
+    }
+
+    pub fn area(&self, x: i32, y: i32, width: u32, height: u32) -> Rectangle {
+        Rectangle::new(self.point(x, y), self.size(width, height))
+    }
+
+    /// Returns the font of a text size on this screen.
+    pub fn font(&self, size: FontSize) -> &'static MonoFont<'static> {
+        match self.is_compact() {
+            true => &ascii::FONT_4X6,
+            false => font(size),
+        }
+    }
+
+    pub fn text_style(&self, size: FontSize, color: Rgb565) -> MonoTextStyle<'static, Rgb565> {
+        MonoTextStyle::new(self.font(size), color)
+    }
+
+    /// Returns the baseline of the time readout, the top row.
+    pub fn readout_y(&self) -> i32 {
+        match self.is_compact() {
+            true => Self::COMPACT_ROW - 2,
+            false => self.y(20),
+        }
+    }
+
+    /// Area of the shift indicator, at the end of the popup row.
+    pub fn shift_area(&self) -> Rectangle {
+        match self.is_compact() {
+            true => Rectangle::new(
+                Point::new(self.width as i32 - 22, Self::COMPACT_ROW),
+                Size::new(22, Self::COMPACT_ROW as u32),
+            ),
+            false => self.area(280, 28, 36, 12),
+        }
+    }
+
+    /// Area of the SD card error, in front of the shift indicator on a compact screen and at the
+    /// end of the grain statistics otherwise.
+    pub fn card_area(&self) -> Rectangle {
+        match self.is_compact() {
+            true => Rectangle::new(
+                Point::new(self.width as i32 - 36, Self::COMPACT_ROW),
+                Size::new(13, Self::COMPACT_ROW as u32),
+            ),
+            false => self.area(292, 41, 24, 12),
+        }
+    }
+
+    /// Area of the parameter popup, in front of the shift indicator and the SD card error.
+    pub fn popup_area(&self) -> Rectangle {
+        match self.is_compact() {
+            true => Rectangle::new(
+                Point::new(0, Self::COMPACT_ROW),
+                Size::new(self.width - 38, Self::COMPACT_ROW as u32),
+            ),
+            false => self.area(0, 24, 276, 17),
+        }
+    }
+
+    /// Area of the waveform.
+    pub fn wave_area(&self) -> Rectangle {
+        match self.is_compact() {
+            true => Rectangle::new(
+                Point::new(0, 2 * Self::COMPACT_ROW),
+                Size::new(self.width, 30),
+            ),
+            false => self.area(0, 59, WIDTH as u32, 123),
+        }
+    }
+
+    /// Returns the middle line of the waveform and how far it reaches up and down from it.
+    pub fn wave_axis(&self) -> (i32, i32) {
+        let wave = self.wave_area();
+        let half = wave.size.height as i32 / 2;
+
+        (wave.top_left.y + half, half - 1)
+    }
+
+    /// Returns the baselines of the title, of the first row and the distance of the rows of a
+    /// table in the overlay area.
+    pub fn overlay_rows(&self) -> (i32, i32, i32) {
+        let top = self.overlay_area().top_left.y;
+
+        match self.is_compact() {
+            true => (top + 5, top + 11, Self::COMPACT_ROW - 1),
+            false => (top + self.y(12), top + self.y(32), self.y(20)),
+        }
+    }
+
+    /// Area the editors, the pages and messages take over, the waveform and whatever is right
+    /// below it on a compact screen.
+    pub fn overlay_area(&self) -> Rectangle {
+        let wave = self.wave_area();
+
+        match self.is_compact() {
+            true => Rectangle::new(
+                wave.top_left,
+                wave.size + Size::new(0, Self::COMPACT_ROW as u32 - 1),
+            ),
+            false => wave,
+        }
+    }
+
+    /// Area of the progress bar, which covers the stretch preview and the meters, or the row
+    /// between the waveform and the meters of a compact screen.
+    pub fn progress_area(&self) -> Rectangle {
+        match self.is_compact() {
+            true => Rectangle::new(
+                Point::new(0, 44),
+                Size::new(self.width, Self::COMPACT_ROW as u32 - 1),
+            ),
+            false => self.area(0, 180, WIDTH as u32, 45),
+        }
+    }
+
+    /// Returns where the level meter with `index` goes, from the top.
+    pub fn meter_position(&self, index: usize) -> Point {
+        match self.is_compact() {
+            true => Point::new(0, 51 + 4 * index as i32),
+            false => self.point(0, 200 + 13 * index as i32),
+        }
+    }
+}
+
+/// Everything the interface draws, independent of where the pixels end up.
+///
+/// Implemented for every infallible `Rgb565` draw target, which is the framebuffer of the LCD on
+/// the hardware and the simulator window on the host. Besides drawing, the interface only asks
+/// the target for its size and orientation, so it does not depend on a particular panel.
+pub trait Screen: DrawTarget<Color = Rgb565, Error = Infallible> + Sized {
+    /// Size of the screen in pixels, as it is turned.
+    fn get_size(&self) -> Size {
+        self.bounding_box().size
+    }
+
+    /// Layout of the interface on this screen.
+    fn get_layout(&self) -> Layout {
+        Layout::new(self.get_size())
+    }
+
+    /// Orientation of the screen, landscape unless it is higher than wide.
+    fn get_orientation(&self) -> Orientation {
+        let size = self.get_size();
+
+        if size.height > size.width {
+            Orientation::Portrait
+        } else {
+            Orientation::Landscape
+        }
+    }
+
+    /// Shows the name of the instrument in the middle of the screen.
+    fn draw_splash(&mut self) {
+        let palette = THEME.get_palette();
+
+        self.clear(palette.background).unwrap();
+
+        let layout = self.get_layout();
+        let character_style = layout.text_style(FontSize::Large, palette.foreground);
+
+        let middle_x: i32 = (self.get_size().width / 2) as i32;
+        let middle_y: i32 = (self.get_size().height / 2) as i32;
+
+        let start_text = strings::get(UiText::Splash);
+        let position = Point::new(middle_x, middle_y - layout.y((4 * 22) / 2));
+
+        Text::with_alignment(start_text, position, character_style, Alignment::Center)
+            .draw(self)
+            .unwrap();
+    }
+
+    fn clear_subsection(&mut self, area: Rectangle) {
+        let palette = THEME.get_palette();
+
+        area.into_styled(PrimitiveStyle::with_fill(palette.background))
+            .draw(self)
+            .unwrap();
+    }
+
+    fn fill_subsection_with_corners(
+        &mut self,
+        top_left: Point,
+        bottom_right: Point,
+        color: Rgb565,
+    ) {
+        Rectangle::with_corners(top_left, bottom_right)
+            .into_styled(PrimitiveStyle::with_fill(color))
+            .draw(self)
+            .unwrap();
+    }
+
+    /// Draws the waveform from the peaks of as many stretches of the buffer as there are columns,
+    /// see `PeakPyramid::query()`.
+    fn draw_waveform(&mut self, columns: &[Peak]) {
+        let wave_width = (self.get_size().width as usize).min(WIDTH);
+        let (wave_y_offset, wave_height) = self.get_layout().wave_axis();
+
+        let palette = THEME.get_palette();
+        let count = columns.len().min(wave_width);
+
+        if count == 0 {
+            return;
+        }
+
+        let mut points_iter = columns.iter().take(count).enumerate().map(|(i, peak)| {
+            let x = i * wave_width / count;
+            let y = log_scale(log_scale(log_scale(peak.get_amplitude()))) * wave_height as f32;
+
+            Point::new(
+                x as i32,
+                y.clamp(wave_height.neg() as f32, wave_height as f32) as i32,
+            )
+        });
+
+        let mut inversed_points_iter = points_iter.clone();
+
+        let mut points: [Point; WIDTH] = [Point::new(0, 0); WIDTH];
+        let points = &mut points[..count];
+
+        for point in points.iter_mut() {
+            *point = points_iter.next().unwrap();
+            point.y += wave_y_offset;
+        }
+
+        let line_style = PrimitiveStyle::with_stroke(palette.accent, 1);
+
+        Polyline::new(points)
+            .into_styled(line_style)
+            .draw(self)
+            .unwrap();
+
+        for point in points.iter_mut() {
+            *point = inversed_points_iter.next().unwrap();
+            point.y = -point.y + wave_y_offset;
+        }
+
+        Polyline::new(points)
+            .into_styled(line_style)
+            .draw(self)
+            .unwrap();
+
+        let upper_bound = [
+            Point::new(0, wave_y_offset - wave_height),
+            Point::new(wave_width as i32, wave_y_offset - wave_height),
+        ];
+
+        let lower_bound = [
+            Point::new(0, wave_y_offset + wave_height),
+            Point::new(wave_width as i32, wave_y_offset + wave_height),
+        ];
+
+        let line_style = PrimitiveStyle::with_stroke(palette.muted, 1);
+
+        Polyline::new(&lower_bound)
+            .into_styled(line_style)
+            .draw(self)
+            .unwrap();
+
+        Polyline::new(&upper_bound)
+            .into_styled(line_style)
+            .draw(self)
+            .unwrap();
+    }
+
+    /// Draws slice markers as vertical lines across the waveform. The selected slice is highlighted.
+    fn draw_slice_markers(
+        &mut self,
+        markers: &[usize],
+        buffer_length: usize,
+        selected: Option<usize>,
+    ) {
+        let wave_width = self.get_size().width as i32;
+        let (wave_y_offset, wave_height) = self.get_layout().wave_axis();
+
+        let palette = THEME.get_palette();
+
+        if buffer_length == 0 {
+            return;
+        }
+
+        for (index, position) in markers.iter().enumerate() {
+            let x = ((*position as f32 / buffer_length as f32) * wave_width as f32) as i32;
+
+            let color = if selected == Some(index) {
+                palette.highlight
+            } else {
+                palette.muted
+            };
+
+            let marker = [
+                Point::new(x, wave_y_offset - wave_height),
+                Point::new(x, wave_y_offset + wave_height),
+            ];
+
+            Polyline::new(&marker)
+                .into_styled(PrimitiveStyle::with_stroke(color, 1))
+                .draw(self)
+                .unwrap();
+        }
+    }
+
+    /// Draws the trim points which lie within the view across the zoomed waveform, and an overview
+    /// of where the view and the trim lie within the whole buffer along its top.
+    fn draw_trim(&mut self, view: Range<usize>, trim: Range<usize>, buffer_length: usize) {
+        let layout = self.get_layout();
+        let wave_width = self.get_size().width as i32;
+        let (wave_y_offset, wave_height) = layout.wave_axis();
+        let overview_y = layout.wave_area().top_left.y + 4;
+
+        let palette = THEME.get_palette();
+
+        if view.is_empty() || buffer_length == 0 {
+            return;
+        }
+
+        for position in [trim.start, trim.end] {
+            if !view.contains(&position) && position != view.end {
+                continue;
+            }
+
+            let x = ((position - view.start) as f32 / view.len() as f32 * wave_width as f32) as i32;
+            let marker = [
+                Point::new(x.min(wave_width - 1), wave_y_offset - wave_height),
+                Point::new(x.min(wave_width - 1), wave_y_offset + wave_height),
+            ];
+
+            Polyline::new(&marker)
+                .into_styled(PrimitiveStyle::with_stroke(palette.highlight, 1))
+                .draw(self)
+                .unwrap();
+        }
+
+        let scale =
+            |position: usize| (position as f32 / buffer_length as f32 * wave_width as f32) as i32;
+
+        Rectangle::new(
+            Point::new(scale(view.start), overview_y - 1),
+            Size::new((scale(view.end) - scale(view.start)).max(1) as u32, 3),
+        )
+        .into_styled(PrimitiveStyle::with_fill(palette.muted))
+        .draw(self)
+        .unwrap();
+
+        let overview = [
+            Point::new(scale(trim.start), overview_y),
+            Point::new(scale(trim.end), overview_y),
+        ];
+
+        Polyline::new(&overview)
+            .into_styled(PrimitiveStyle::with_stroke(palette.highlight, 1))
+            .draw(self)
+            .unwrap();
+    }
+
+    /// Draws a response curve with its breakpoints in place of the waveform. The selected
+    /// breakpoint is highlighted.
+    fn draw_curve(&mut self, curve: &ResponseCurve, selected: usize, channel: usize) {
+        const RESOLUTION: usize = 60;
+        const HANDLE_SIZE: u32 = 5;
+
+        let palette = THEME.get_palette();
+        let layout = self.get_layout();
+        let area = layout.overlay_area();
+        // the curve stays square and fills the height of the area
+        let curve_x = layout.x(100);
+        let curve_y = area.top_left.y + 1;
+        let curve_size = area.size.height as i32 - 3;
+
+        self.clear_subsection(area);
+
+        let to_screen = |x: f32, y: f32| {
+            Point::new(
+                curve_x + (x * curve_size as f32) as i32,
+                curve_y + curve_size - (y * curve_size as f32) as i32,
+            )
+        };
+
+        Rectangle::new(
+            Point::new(curve_x, curve_y),
+            Size::new(curve_size as u32 + 1, curve_size as u32 + 1),
+        )
+        .into_styled(PrimitiveStyle::with_stroke(palette.muted, 1))
+        .draw(self)
+        .unwrap();
+
+        let mut points = [Point::zero(); RESOLUTION + 1];
+
+        for (index, point) in points.iter_mut().enumerate() {
+            let x = index as f32 / RESOLUTION as f32;
+            *point = to_screen(x, curve.apply(x));
+        }
+
+        Polyline::new(&points)
+            .into_styled(PrimitiveStyle::with_stroke(palette.accent, 1))
+            .draw(self)
+            .unwrap();
+
+        for (index, point) in curve.get_points().iter().enumerate() {
+            let color = if index == selected {
+                palette.highlight
+            } else {
+                palette.foreground
+            };
+
+            Rectangle::with_center(to_screen(point.x, point.y), Size::new_equal(HANDLE_SIZE))
+                .into_styled(PrimitiveStyle::with_fill(color))
+                .draw(self)
+                .unwrap();
+        }
+
+        let label_y = (area.top_left.y + 9) as usize;
+        let label =
+            self.print_on_screen(layout.x(4) as usize, label_y, strings::get(UiText::Curve));
+        let number = [
+            b'0' + (channel / 10 % 10) as u8,
+            b'0' + (channel % 10) as u8,
+        ];
+
+        if let Ok(number) = core::str::from_utf8(&number) {
+            let x = label.top_left.x + label.size.width as i32 + layout.x(6);
+            self.print_on_screen(x as usize, label_y, number);
+        }
+    }
+
+    /// Draws the pads of the kit as a table in place of the waveform. The selected pad is
+    /// highlighted.
+    fn draw_kit(&mut self, kit: &Kit) {
+        const COLUMNS: [i32; 5] = [4, 60, 120, 180, 240];
+
+        let palette = THEME.get_palette();
+        let layout = self.get_layout();
+        let (title_y, header_y, row_height) = layout.overlay_rows();
+
+        self.clear_subsection(layout.overlay_area());
+
+        let mode = if kit.is_enabled() {
+            UiText::KitOn
+        } else {
+            UiText::KitOff
+        };
+        let label_y = title_y as usize;
+        let label = self.print_on_screen(layout.x(4) as usize, label_y, strings::get(UiText::Kit));
+        let x = label.top_left.x + label.size.width as i32 + layout.x(6);
+        self.print_on_screen(x as usize, label_y, strings::get(mode));
+
+        let header = [
+            UiText::PadLabel,
+            UiText::SlotLabel,
+            UiText::NoteLabel,
+            UiText::GateLabel,
+            UiText::BurstLabel,
+        ];
+
+        for (x, label) in COLUMNS.iter().zip(header) {
+            Text::new(
+                strings::get(label),
+                Point::new(layout.x(*x), header_y),
+                layout.text_style(FontSize::Small, palette.muted),
+            )
+            .draw(self)
+            .unwrap();
+        }
+
+        for (index, pad) in kit.get_pads().iter().enumerate() {
+            let y = header_y + (index as i32 + 1) * row_height;
+            let color = if index == kit.get_selected() {
+                palette.highlight
+            } else {
+                palette.foreground
+            };
+
+            // the texts always fit, so errors are impossible
+            let mut cells = [TimeText::new(); 5];
+            write!(cells[0], "{}", index + 1).ok();
+            write!(cells[1], "{}", pad.slot + 1).ok();
+            write!(cells[2], "{}", pad.note).ok();
+            match pad.gate {
+                Some(gate) => write!(cells[3], "{}", gate + 1).ok(),
+                None => write!(cells[3], "-").ok(),
+            };
+            write!(cells[4], "{} ms", pad.burst_in_ms).ok();
+
+            for (x, cell) in COLUMNS.iter().zip(cells.iter()) {
+                Text::new(
+                    cell.as_str(),
+                    Point::new(layout.x(*x), y),
+                    layout.text_style(FontSize::Small, color),
+                )
+                .draw(self)
+                .unwrap();
+            }
+        }
+    }
+
+    /// Plots every playing grain as a dot in place of the waveform, across by its position in the
+    /// buffer and up by its pitch. Loud grains get the highlight color, quiet ones fade into the
+    /// muted one.
+    fn draw_grain_view(&mut self, dots: impl Iterator<Item = GrainDot>) {
+        const DOT_SIZE: u32 = 3;
+
+        let palette = THEME.get_palette();
+        let area = self.get_layout().overlay_area();
+        let width = area.size.width as i32 - 1;
+        let height = area.size.height as i32 - 1;
+
+        self.clear_subsection(area);
+
+        // grains on the line play at the pitch of the buffer
+        let middle = area.top_left.y + height / 2;
+
+        Line::new(
+            Point::new(area.top_left.x, middle),
+            Point::new(area.top_left.x + width, middle),
+        )
+        .into_styled(PrimitiveStyle::with_stroke(palette.muted, 1))
+        .draw(self)
+        .unwrap();
+
+        for dot in dots {
+            let center = area.top_left
+                + Point::new(
+                    (dot.position * width as f32) as i32,
+                    height - (dot.pitch * height as f32) as i32,
+                );
+            let color = blend(palette.muted, palette.highlight, dot.amplitude);
+
+            Rectangle::with_center(center, Size::new_equal(DOT_SIZE))
+                .intersection(&area)
+                .into_styled(PrimitiveStyle::with_fill(color))
+                .draw(self)
+                .unwrap();
+        }
+    }
+
+    /// Draws the bands of the output spectrum as bars in place of the waveform, the lowest on the
+    /// left.
+    fn draw_spectrum(&mut self, bands: &[f32]) {
+        let palette = THEME.get_palette();
+        let area = self.get_layout().overlay_area();
+        let bottom = area.top_left.y + area.size.height as i32;
+        let bar_width = (area.size.width / bands.len().max(1) as u32).max(1);
+        // a gap keeps the bars apart where there is room for it
+        let width = bar_width - (bar_width > 2) as u32;
+
+        self.clear_subsection(area);
+
+        for (index, band) in bands.iter().enumerate() {
+            let height = (band.clamp(0.0, 1.0) * area.size.height as f32) as u32;
+
+            if height == 0 {
+                continue;
+            }
+
+            Rectangle::new(
+                Point::new(
+                    area.top_left.x + (index as u32 * bar_width) as i32,
+                    bottom - height as i32,
+                ),
+                Size::new(width, height),
+            )
+            .into_styled(PrimitiveStyle::with_fill(palette.accent))
+            .draw(self)
+            .unwrap();
+        }
+    }
+
+    /// Shows a centered message in place of the waveform.
+    fn draw_message(&mut self, message: &str) {
+        let palette = THEME.get_palette();
+        let layout = self.get_layout();
+
+        let area = layout.overlay_area();
+
+        self.clear_subsection(area);
+
+        let character_style = layout.text_style(FontSize::Large, palette.foreground);
+        let position = Point::new(
+            self.get_size().width as i32 / 2,
+            area.top_left.y + area.size.height as i32 / 4 + 1,
+        );
+
+        Text::with_alignment(message, position, character_style, Alignment::Center)
+            .draw(self)
+            .unwrap();
+    }
+
+    /// Fills the whole screen with a title and some lines of text, e.g. for status and fault
+    /// reports.
+    fn draw_report(&mut self, background: Rgb565, title: &str, lines: &[&str]) {
+        self.clear(background).unwrap();
+
+        let layout = self.get_layout();
+        let title_style = layout.text_style(FontSize::Large, Rgb565::WHITE);
+        let line_style = layout.text_style(FontSize::Small, Rgb565::WHITE);
+
+        Text::new(title, layout.point(4, 20), title_style)
+            .draw(self)
+            .unwrap();
+
+        for (index, line) in lines.iter().enumerate() {
+            Text::new(line, layout.point(4, 44 + 12 * index as i32), line_style)
+                .draw(self)
+                .unwrap();
+        }
+    }
+
+    /// Draws a page of parameters in place of the waveform, every value as a bar. The focused
+    /// row is highlighted, the automated ones get the accent color.
+    fn draw_parameter_page(
+        &mut self,
+        title: &str,
+        parameters: &[(&str, f32, bool)],
+        focused: Option<usize>,
+    ) {
+        const BAR_X: i32 = 100;
+        const BAR_WIDTH: i32 = 200;
+
+        let palette = THEME.get_palette();
+        let layout = self.get_layout();
+        let (title_y, _, row_height) = layout.overlay_rows();
+
+        self.clear_subsection(layout.overlay_area());
+
+        Text::new(
+            title,
+            Point::new(layout.x(4), title_y),
+            layout.text_style(FontSize::Small, palette.muted),
+        )
+        .draw(self)
+        .unwrap();
+
+        for (row, (label, value, automated)) in parameters.iter().enumerate() {
+            // the rows follow the title, every bar is level with its label
+            let y = title_y + (row as i32 + 1) * row_height - row_height / 5;
+            let color = if focused == Some(row) {
+                palette.highlight
+            } else if *automated {
+                palette.accent
+            } else {
+                palette.foreground
+            };
+
+            Text::new(
+                label,
+                Point::new(layout.x(4), y),
+                layout.text_style(FontSize::Small, color),
+            )
+            .draw(self)
+            .unwrap();
+
+            let bar = Rectangle::new(
+                Point::new(layout.x(BAR_X), y - row_height * 2 / 5),
+                Size::new(layout.x(BAR_WIDTH) as u32, row_height as u32 / 2),
+            );
+
+            bar.into_styled(PrimitiveStyle::with_stroke(palette.muted, 1))
+                .draw(self)
+                .unwrap();
+
+            let width = (value.clamp(0.0, 1.0) * bar.size.width as f32) as u32;
+
+            Rectangle::new(bar.top_left, Size::new(width, bar.size.height))
+                .into_styled(PrimitiveStyle::with_fill(color))
+                .draw(self)
+                .unwrap();
+        }
+    }
+
+    /// Shows the parameter which is being changed below the readout, with its value as a bar.
+    fn draw_parameter_popup(&mut self, label: &str, value: f32) {
+        let palette = THEME.get_palette();
+        let bar = draw_popup_label(self, label);
+        let width = (value.clamp(0.0, 1.0) * bar.size.width as f32) as u32;
+
+        Rectangle::new(bar.top_left, Size::new(width, bar.size.height))
+            .into_styled(PrimitiveStyle::with_fill(palette.highlight))
+            .draw(self)
+            .unwrap();
+    }
+
+    /// Shows the window of the grains in place of the bar, while its kind or its shape changes.
+    fn draw_window_popup(&mut self, label: &str, window: Window, shape: f32) {
+        const RESOLUTION: usize = 48;
+
+        let palette = THEME.get_palette();
+        let area = self.get_layout().popup_area();
+        let bar = draw_popup_label(self, label);
+        // the curve takes the whole height of the popup
+        let bottom = area.top_left.y + area.size.height as i32 - 2;
+        let height = (area.size.height - 3) as f32;
+        let mut points = [Point::zero(); RESOLUTION + 1];
+
+        for (index, point) in points.iter_mut().enumerate() {
+            let phase = index as f32 / RESOLUTION as f32;
+
+            *point = Point::new(
+                bar.top_left.x + (phase * bar.size.width.saturating_sub(1) as f32) as i32,
+                bottom - (window.gain(phase, shape) * height + 0.5) as i32,
+            );
+        }
+
+        Polyline::new(&points)
+            .into_styled(PrimitiveStyle::with_stroke(palette.highlight, 1))
+            .draw(self)
+            .unwrap();
+    }
+
+    fn clear_parameter_popup(&mut self) {
+        let area = self.get_layout().popup_area();
+
+        self.clear_subsection(area);
+    }
+
+    /// Draws the playback offset, the buffer length and the normalization gain above the
+    /// waveform.
+    fn draw_time_readout(&mut self, offset: &str, length: &str, gain: &str) {
+        let palette = THEME.get_palette();
+        let y = self.get_layout().readout_y();
+
+        self.draw_readout(
+            y,
+            palette.foreground,
+            [
+                (UiText::PositionLabel, 28, offset),
+                (UiText::LengthLabel, 136, length),
+                (UiText::GainLabel, 250, gain),
+            ],
+        );
+    }
+
+    /// Draws the density, the pitch and the load of the grain cloud below the time readout. Left
+    /// out on compact screens.
+    fn draw_cloud_readout(&mut self, density: &str, pitch: &str, load: &str) {
+        let palette = THEME.get_palette();
+        let layout = self.get_layout();
+
+        if layout.is_compact() {
+            return;
+        }
+
+        self.draw_readout(
+            layout.y(50),
+            palette.muted,
+            [
+                (UiText::DensityLabel, 28, density),
+                (UiText::CloudPitchLabel, 136, pitch),
+                (UiText::LoadLabel, 250, load),
+            ],
+        );
+    }
+
+    /// Previews below the waveform how grain size and pitch stretch the buffer. While the grain
+    /// timing is given in note values, the `delay` takes the place of the speed. Left out on
+    /// compact screens.
+    fn draw_stretch_preview(&mut self, grain: &str, pitch: &str, speed: &str, delay: Option<&str>) {
+        let palette = THEME.get_palette();
+        let layout = self.get_layout();
+
+        if layout.is_compact() {
+            return;
+        }
+
+        self.draw_readout(
+            layout.y(192),
+            palette.foreground,
+            [
+                (UiText::GrainLabel, 40, grain),
+                (UiText::PitchLabel, 148, pitch),
+                match delay {
+                    Some(delay) => (UiText::DelayLabel, 256, delay),
+                    None => (UiText::SpeedLabel, 256, speed),
+                },
+            ],
+        );
+    }
+
+    fn clear_stretch_preview(&mut self) {
+        let layout = self.get_layout();
+
+        if !layout.is_compact() {
+            self.clear_subsection(layout.area(0, 184, WIDTH as u32, 10));
+        }
+    }
+
+    /// Draws a row of three labeled values with its baseline at `y`, every value at the given x
+    /// of the layout. Compact screens only show the values, evenly spread.
+    fn draw_readout(&mut self, y: i32, color: Rgb565, fields: [(UiText, i32, &str); 3]) {
+        const LABEL_X: [i32; 3] = [4, 112, 220];
+
+        let layout = self.get_layout();
+        let character_style = layout.text_style(FontSize::Small, color);
+        let width = self.get_size().width as i32;
+        let height = layout.font(FontSize::Small).character_size.height as i32;
+
+        self.clear_subsection(Rectangle::new(
+            Point::new(0, y + 1 - height),
+            Size::new(width as u32, height as u32 + 1),
+        ));
+
+        for (index, (label, value_x, value)) in fields.into_iter().enumerate() {
+            let value_x = if layout.is_compact() {
+                index as i32 * width / 3
+            } else {
+                Text::new(
+                    strings::get(label),
+                    Point::new(layout.x(LABEL_X[index]), y),
+                    character_style,
+                )
+                .draw(self)
+                .unwrap();
+
+                layout.x(value_x)
+            };
+
+            Text::new(value, Point::new(value_x, y), character_style)
+                .draw(self)
+                .unwrap();
+        }
+    }
+
+    /// Shows below the readout that the knobs control the shift bank.
+    fn draw_shift_indicator(&mut self, shifted: bool) {
+        let palette = THEME.get_palette();
+        let layout = self.get_layout();
+
+        let area = layout.shift_area();
+
+        self.clear_subsection(area);
+
+        if !shifted {
+            return;
+        }
+
+        area.into_styled(PrimitiveStyle::with_fill(palette.foreground))
+            .draw(self)
+            .unwrap();
+
+        Text::new(
+            strings::get(UiText::ShiftLabel),
+            Point::new(
+                area.top_left.x + layout.x(3).max(1),
+                area.top_left.y + area.size.height as i32 - layout.y(3).max(1),
+            ),
+            layout.text_style(FontSize::Small, palette.background),
+        )
+        .draw(self)
+        .unwrap();
+    }
+
+    /// Shows that the SD card can not be used, so loading and saving files is off.
+    fn draw_card_error(&mut self, failed: bool) {
+        let palette = THEME.get_palette();
+        let layout = self.get_layout();
+
+        let area = layout.card_area();
+
+        self.clear_subsection(area);
+
+        if !failed {
+            return;
+        }
+
+        area.into_styled(PrimitiveStyle::with_fill(palette.highlight))
+            .draw(self)
+            .unwrap();
+
+        Text::new(
+            strings::get(UiText::CardErrorLabel),
+            Point::new(
+                area.top_left.x + layout.x(3).max(1),
+                area.top_left.y + area.size.height as i32 - layout.y(3).max(1),
+            ),
+            layout.text_style(FontSize::Small, palette.background),
+        )
+        .draw(self)
+        .unwrap();
+    }
+
+    /// Draws a horizontal level meter with a label on the left. The RMS level is drawn as a bar,
+    /// the peak level as a thin marker. The bar turns red when the peak is clipping.
+    fn draw_meter(&mut self, position: Point, label: &str, rms: f32, peak: f32) {
+        let palette = THEME.get_palette();
+        let layout = self.get_layout();
+
+        // a compact screen has no room for the label, the meter spans its width
+        let (meter_position, meter_width, meter_height) = if layout.is_compact() {
+            (position, self.get_size().width, 3)
+        } else {
+            let character_style = layout.text_style(FontSize::Small, palette.foreground);
+
+            Text::new(label, position + layout.point(0, 7), character_style)
+                .draw(self)
+                .unwrap();
+
+            let size = layout.size(290, 8);
+
+            (position + layout.point(24, 0), size.width, size.height)
+        };
+
+        self.clear_subsection(Rectangle::new(
+            meter_position,
+            Size::new(meter_width, meter_height),
+        ));
+
+        let bar_color = if peak >= CLIP_LEVEL {
+            Rgb565::RED
+        } else {
+            Rgb565::GREEN
+        };
+
+        let rms_width = (rms.clamp(0.0, 1.0) * meter_width as f32) as u32;
+
+        Rectangle::new(meter_position, Size::new(rms_width, meter_height))
+            .into_styled(PrimitiveStyle::with_fill(bar_color))
+            .draw(self)
+            .unwrap();
+
+        let peak_x = (peak.clamp(0.0, 1.0) * (meter_width - 1) as f32) as i32;
+
+        Rectangle::new(
+            meter_position + Point::new(peak_x, 0),
+            Size::new(1, meter_height),
+        )
+        .into_styled(PrimitiveStyle::with_fill(palette.foreground))
+        .draw(self)
+        .unwrap();
+    }
+
+    fn draw_loading_bar(&mut self, percentage: u32, filename: &str) {
+        let palette = THEME.get_palette();
+        let layout = self.get_layout();
+
+        // a compact screen fits a thin bar into the progress area, without the name
+        let (border, stroke_width, bar) = if layout.is_compact() {
+            let area = layout.progress_area();
+            let bar = Rectangle::new(
+                area.top_left + Point::new(2, 2),
+                area.size - Size::new(4, 4),
+            );
+
+            (area, 1, bar)
+        } else {
+            (
+                layout.area(40, 200, 240, 20),
+                3,
+                layout.area(46, 206, 231, 8),
+            )
+        };
+
+        if percentage == 0 {
+            let border_style = PrimitiveStyleBuilder::new()
+                .stroke_color(palette.foreground)
+                .stroke_width(stroke_width)
+                .build();
+
+            border.into_styled(border_style).draw(self).unwrap();
+
+            if !layout.is_compact() {
+                let character_style = layout.text_style(FontSize::Small, palette.foreground);
+                let position = Point::new((self.get_size().width / 2) as i32, layout.y(190));
+
+                Text::with_alignment(filename, position, character_style, Alignment::Center)
+                    .draw(self)
+                    .unwrap();
+            }
+        }
+
+        let loading_bar_style = PrimitiveStyleBuilder::new()
+            .fill_color(palette.foreground)
+            .build();
+
+        if percentage <= 100 {
+            Rectangle::new(
+                bar.top_left,
+                Size {
+                    width: (bar.size.width * percentage) / 100,
+                    height: bar.size.height,
+                },
+            )
+            .into_styled(loading_bar_style)
+            .draw(self)
+            .unwrap();
+        }
+    }
+
+    fn print_on_screen(&mut self, x: usize, y: usize, message: &str) -> Rectangle {
+        let palette = THEME.get_palette();
+
+        let character_style = self
+            .get_layout()
+            .text_style(FontSize::Small, palette.foreground);
+
+        let position = Point::new(x as i32, y as i32);
+
+        let text = Text::new(message, position, character_style);
+
+        let bounding_box = text.bounding_box();
+
+        text.draw(self).unwrap();
+
+        bounding_box
+    }
+}
+
+impl<D> Screen for D where D: DrawTarget<Color = Rgb565, Error = Infallible> {}
+
+/// Clears the popup and draws the label of a parameter into it, returns the area of its bar.
+fn draw_popup_label<S: Screen>(screen: &mut S, label: &str) -> Rectangle {
+    const BAR_X: i32 = 130;
+    const BAR_WIDTH: u32 = 140;
+
+    let palette = THEME.get_palette();
+    let layout = screen.get_layout();
+
+    screen.clear_parameter_popup();
+
+    let area = layout.popup_area();
+    let bottom = area.top_left.y + area.size.height as i32;
+
+    Text::new(
+        label,
+        Point::new(layout.x(4), bottom - layout.y(5).max(1)),
+        layout.text_style(FontSize::Medium, palette.highlight),
+    )
+    .draw(screen)
+    .unwrap();
+
+    Rectangle::new(
+        Point::new(layout.x(BAR_X), area.top_left.y + layout.y(4).max(1)),
+        Size::new(
+            layout
+                .x(BAR_WIDTH as i32)
+                .min(area.size.width as i32 - layout.x(BAR_X)) as u32,
+            area.size.height / 2,
+        ),
+    )
+}
+
+/// Mixes two colors, `amount` runs from `from` at `0.0` to `to` at `1.0`.
+fn blend(from: Rgb565, to: Rgb565, amount: f32) -> Rgb565 {
+    let amount = amount.clamp(0.0, 1.0);
+    let mix = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * amount + 0.5) as u8;
+
+    Rgb565::new(
+        mix(from.r(), to.r()),
+        mix(from.g(), to.g()),
+        mix(from.b(), to.b()),
+    )
+}
+
+fn log_scale(value: f32) -> f32 {
+    (value + 1.0).log10() * (1.0 / 2.0.log10())
+}
@@ -0,0 +1,56 @@
+/// Combines the offset knob with fine scrubbing by the encoder.
+///
+/// The knob sets the coarse position, the encoder shifts it by a fixed time per detent. When the
+/// knob gets moved noticeably away from where it was while scrubbing, it picks the offset up
+/// again and the scrubbed amount is dropped.
+pub struct OffsetScrub {
+    step: i32,
+    range: i32,
+    pickup_threshold: f32,
+    /// Scrubbed distance in samples
+    fine: i32,
+    /// Knob position the scrubbing refers to
+    anchor: Option<f32>,
+    reanchor: bool,
+}
+
+impl OffsetScrub {
+    pub fn new(sample_rate: f32, step_in_ms: f32, range_in_s: f32, pickup_threshold: f32) -> Self {
+        OffsetScrub {
+            step: ((step_in_ms * sample_rate / 1000.0) as i32).max(1),
+            range: (range_in_s * sample_rate) as i32,
+            pickup_threshold,
+            fine: 0,
+            anchor: None,
+            reanchor: false,
+        }
+    }
+
+    /// Moves the offset by `steps` encoder detents.
+    pub fn scrub(&mut self, steps: i32) {
+        self.fine = (self.fine + steps * self.step).clamp(-self.range, self.range);
+        self.reanchor = true;
+    }
+
+    /// Combines the knob `position` with the scrubbed distance into a normalized offset of a
+    /// buffer with `length` samples.
+    pub fn apply(&mut self, position: f32, length: usize) -> f32 {
+        if self.reanchor {
+            self.anchor = Some(position);
+            self.reanchor = false;
+        }
+
+        if let Some(anchor) = self.anchor {
+            if (position - anchor).abs() > self.pickup_threshold {
+                self.fine = 0;
+                self.anchor = None;
+            }
+        }
+
+        if length == 0 {
+            return position;
+        }
+
+        (position + self.fine as f32 / length as f32).clamp(0.0, 1.0)
+    }
+}
@@ -0,0 +1,574 @@
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::kit::{Kit, KitPad, BURST_RANGE_IN_MS, KIT_PADS};
+use crate::mapping::{ControlMaps, MappingError, Parameter};
+use crate::midi_learn::{MidiLearn, CONTROLLERS};
+use crate::scene::{SceneMorph, SCENE_CHANNELS, SCENE_COUNT};
+
+/// File on the SD card which holds the session
+pub const SESSION_NAME: &str = "SESSION.TXT";
+/// Longest session file which gets read or written
+pub const MAX_SESSION_LENGTH: usize = 4096;
+/// Most slots a session can refer to
+pub const MAX_SESSION_SLOTS: usize = 8;
+/// Continuous granulator settings stored per pad
+const PAD_VALUES: usize = 12;
+
+#[derive(Debug)]
+pub enum SessionError {
+    TooLong,
+    NotText,
+    /// Line (counted from 1) which could not be read
+    Syntax(usize),
+}
+
+/// Parts of the session file.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Slots,
+    Kit,
+    Scenes,
+    Midi,
+}
+
+/// Settings of a kit pad as stored in a session.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PadPreset {
+    pub slot: usize,
+    pub note: u8,
+    pub gate: Option<u8>,
+    pub burst_in_ms: u32,
+    /// Grains, offset, size, pitch, delay, velocity, their spreads and the window parameter
+    pub values: [f32; PAD_VALUES],
+    pub window_function: u8,
+}
+
+impl PadPreset {
+    pub fn capture(pad: &KitPad) -> Self {
+        let settings = &pad.settings;
+
+        PadPreset {
+            slot: pad.slot,
+            note: pad.note,
+            gate: pad.gate,
+            burst_in_ms: pad.burst_in_ms,
+            values: [
+                settings.active_grains,
+                settings.offset,
+                settings.grain_size,
+                settings.pitch,
+                settings.delay,
+                settings.velocity,
+                settings.sp_offset,
+                settings.sp_grain_size,
+                settings.sp_pitch,
+                settings.sp_delay,
+                settings.sp_velocity,
+                settings.window_param,
+            ],
+            window_function: settings.window_function,
+        }
+    }
+
+    /// Puts the preset into a pad, the volume and the tuning of the pad stay as they are.
+    pub fn apply(&self, pad: &mut KitPad) {
+        let settings = &mut pad.settings;
+        let values = &self.values;
+
+        pad.slot = self.slot;
+        pad.note = self.note;
+        pad.gate = self.gate;
+        pad.burst_in_ms = self.burst_in_ms;
+        settings.active_grains = values[0];
+        settings.offset = values[1];
+        settings.grain_size = values[2];
+        settings.pitch = values[3];
+        settings.delay = values[4];
+        settings.velocity = values[5];
+        settings.sp_offset = values[6];
+        settings.sp_grain_size = values[7];
+        settings.sp_pitch = values[8];
+        settings.sp_delay = values[9];
+        settings.sp_velocity = values[10];
+        settings.window_param = values[11];
+        settings.window_function = self.window_function;
+    }
+}
+
+/// State of the whole instrument which is stored on the SD card: the sample file of every slot,
+/// the kit, the scenes, the learned MIDI controllers and the control mappings.
+///
+/// Recordings are not part of a session, only slots loaded from sample files refer to them.
+/// Everything which a session file does not list stays as it is when the session gets loaded.
+#[derive(Clone, Copy)]
+pub struct Session {
+    pub active_slot: usize,
+    /// Number of the sample file every slot was loaded from
+    pub samples: [Option<u32>; MAX_SESSION_SLOTS],
+    pub kit_enabled: bool,
+    pub pads: [Option<PadPreset>; KIT_PADS],
+    pub scenes: [Option<[f32; SCENE_CHANNELS]>; SCENE_COUNT],
+    /// Scene A, scene B and the source of the morph
+    pub morph: Option<(usize, usize, Option<usize>)>,
+    /// Parameter of every MIDI controller
+    pub controllers: Option<[Parameter; CONTROLLERS]>,
+    pub maps: Option<ControlMaps>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Session {
+            active_slot: 0,
+            samples: [None; MAX_SESSION_SLOTS],
+            kit_enabled: false,
+            pads: [None; KIT_PADS],
+            scenes: [None; SCENE_COUNT],
+            morph: None,
+            controllers: None,
+            maps: None,
+        }
+    }
+
+    /// Takes the kit, the scenes, the controllers and the mappings as they are now, the slots get
+    /// filled in by the caller.
+    pub fn capture(kit: &Kit, scenes: &SceneMorph, learn: &MidiLearn, maps: &ControlMaps) -> Self {
+        let mut session = Session::new();
+
+        session.kit_enabled = kit.is_enabled();
+
+        for (preset, pad) in session.pads.iter_mut().zip(kit.get_pads()) {
+            *preset = Some(PadPreset::capture(pad));
+        }
+
+        for (index, scene) in session.scenes.iter_mut().enumerate() {
+            *scene = scenes.get_scene(index);
+        }
+
+        session.morph = Some(scenes.get_morph());
+        session.controllers = Some(learn.get_map());
+        session.maps = Some(*maps);
+
+        session
+    }
+
+    /// Puts the kit, the scenes and the controllers of the session back, the slots and the
+    /// mappings are up to the caller.
+    pub fn apply(&self, kit: &mut Kit, scenes: &mut SceneMorph, learn: &mut MidiLearn) {
+        kit.set_enabled(self.kit_enabled);
+
+        for (preset, pad) in self.pads.iter().zip(kit.get_pads_mut()) {
+            if let Some(preset) = preset {
+                preset.apply(pad);
+            }
+        }
+
+        for (index, values) in self.scenes.iter().enumerate() {
+            if let Some(values) = values {
+                scenes.restore_scene(index, values);
+            }
+        }
+
+        if let Some((scene_a, scene_b, source)) = self.morph {
+            scenes.set_morph(scene_a, scene_b, source);
+        }
+
+        if let Some(controllers) = self.controllers.as_ref() {
+            learn.set_map(controllers);
+        }
+    }
+
+    /// Writes the session as text, one section after another. The mappings come last in the
+    /// syntax of the mapping file:
+    ///
+    /// ```text
+    /// [slots]
+    /// active 0
+    /// # slot sample
+    /// 1 SAMPLE03.WAV
+    ///
+    /// [kit]
+    /// enabled on
+    /// # pad slot note gate burst grains offset size pitch delay velocity ... window
+    /// 0 1 36 0 250 0.100 0.500 0.500 0.500 0.000 1.000 ... 2
+    ///
+    /// [scenes]
+    /// morph 0 1 none
+    /// # scene value of every channel
+    /// 0 0.500 0.120 ...
+    ///
+    /// [midi]
+    /// # controller parameter
+    /// 74 pitch
+    ///
+    /// [mapping]
+    /// 3 pitch invert 0.000 1.000
+    /// ```
+    pub fn write<W: Write>(&self, out: &mut W) -> core::fmt::Result {
+        writeln!(out, "# Sitira session")?;
+
+        writeln!(out, "[slots]")?;
+        writeln!(out, "active {}", self.active_slot)?;
+        writeln!(out, "# slot sample")?;
+
+        for (slot, sample) in self.samples.iter().enumerate() {
+            if let Some(number) = sample {
+                writeln!(out, "{} SAMPLE{:02}.WAV", slot, number)?;
+            }
+        }
+
+        writeln!(out, "\n[kit]")?;
+        writeln!(
+            out,
+            "enabled {}",
+            if self.kit_enabled { "on" } else { "off" }
+        )?;
+        writeln!(out, "# pad slot note gate burst values window")?;
+
+        for (index, pad) in self.pads.iter().enumerate() {
+            if let Some(pad) = pad {
+                write!(out, "{} {} {} ", index, pad.slot, pad.note)?;
+
+                match pad.gate {
+                    Some(gate) => write!(out, "{}", gate)?,
+                    None => write!(out, "none")?,
+                }
+
+                write!(out, " {}", pad.burst_in_ms)?;
+
+                for value in pad.values {
+                    write!(out, " {:.3}", value)?;
+                }
+
+                writeln!(out, " {}", pad.window_function)?;
+            }
+        }
+
+        writeln!(out, "\n[scenes]")?;
+
+        if let Some((scene_a, scene_b, source)) = self.morph {
+            write!(out, "morph {} {} ", scene_a, scene_b)?;
+
+            match source {
+                Some(source) => writeln!(out, "{}", source)?,
+                None => writeln!(out, "none")?,
+            }
+        }
+
+        writeln!(out, "# scene value of every channel")?;
+
+        for (index, values) in self.scenes.iter().enumerate() {
+            if let Some(values) = values {
+                write!(out, "{}", index)?;
+
+                for value in values {
+                    write!(out, " {:.3}", value)?;
+                }
+
+                writeln!(out)?;
+            }
+        }
+
+        if let Some(controllers) = self.controllers.as_ref() {
+            writeln!(out, "\n[midi]")?;
+            writeln!(out, "# controller parameter")?;
+
+            for (controller, parameter) in controllers.iter().enumerate() {
+                if *parameter != Parameter::None {
+                    writeln!(out, "{} {}", controller, parameter.name())?;
+                }
+            }
+        }
+
+        if let Some(maps) = self.maps.as_ref() {
+            writeln!(out, "\n[mapping]")?;
+            maps.write(out)?;
+        }
+
+        Ok(())
+    }
+
+    /// Takes the next sample file which still has to be loaded. The one of the active slot comes
+    /// last, so it is the one left streaming.
+    pub fn take_next_sample(&mut self) -> Option<(usize, u32)> {
+        let active = self.active_slot;
+        let slot = (0..MAX_SESSION_SLOTS)
+            .filter(|slot| *slot != active)
+            .chain(Some(active))
+            .find(|slot| self.samples[*slot].is_some())?;
+
+        self.samples[slot].take().map(|number| (slot, number))
+    }
+
+    /// Reads a session written by `write()`.
+    pub fn parse(text: &str) -> Result<Self, SessionError> {
+        let mut session = Session::new();
+        let mut section = None;
+        let mut offset = 0;
+
+        for (index, line) in text.split_inclusive('\n').enumerate() {
+            offset += line.len();
+
+            let line = line.split('#').next().unwrap_or("").trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            section = match line {
+                "[slots]" => Some(Section::Slots),
+                "[kit]" => Some(Section::Kit),
+                "[scenes]" => Some(Section::Scenes),
+                // a listed section replaces all controllers, also when it is empty
+                "[midi]" => {
+                    session.controllers = Some([Parameter::None; CONTROLLERS]);
+                    Some(Section::Midi)
+                }
+                // the rest of the file is read like a mapping file
+                "[mapping]" => {
+                    let maps =
+                        ControlMaps::parse(&text[offset..]).map_err(|error| match error {
+                            MappingError::Syntax(line) => SessionError::Syntax(index + 1 + line),
+                            _ => SessionError::Syntax(index + 1),
+                        })?;
+
+                    session.maps = Some(maps);
+                    break;
+                }
+                _ => {
+                    let parsed = match section {
+                        Some(Section::Slots) => session.parse_slot(line),
+                        Some(Section::Kit) => session.parse_pad(line),
+                        Some(Section::Scenes) => session.parse_scene(line),
+                        Some(Section::Midi) => session.parse_controller(line),
+                        None => None,
+                    };
+
+                    if parsed.is_none() {
+                        return Err(SessionError::Syntax(index + 1));
+                    }
+
+                    section
+                }
+            };
+        }
+
+        Ok(session)
+    }
+
+    /// Reads the content of a session file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SessionError> {
+        let text = core::str::from_utf8(bytes).map_err(|_| SessionError::NotText)?;
+
+        Self::parse(text)
+    }
+
+    fn parse_slot(&mut self, line: &str) -> Option<()> {
+        let mut words = line.split_whitespace();
+        let first = words.next()?;
+
+        if first == "active" {
+            self.active_slot = parse_index(words.next()?, MAX_SESSION_SLOTS)?;
+        } else {
+            let slot = parse_index(first, MAX_SESSION_SLOTS)?;
+            let name = words.next()?;
+            let number = name.strip_prefix("SAMPLE")?.strip_suffix(".WAV")?;
+
+            if number.len() != 2 {
+                return None;
+            }
+
+            self.samples[slot] = Some(number.parse().ok()?);
+        }
+
+        words.next().is_none().then_some(())
+    }
+
+    fn parse_pad(&mut self, line: &str) -> Option<()> {
+        let mut words = line.split_whitespace();
+        let first = words.next()?;
+
+        if first == "enabled" {
+            self.kit_enabled = match words.next()? {
+                "on" => true,
+                "off" => false,
+                _ => return None,
+            };
+
+            return words.next().is_none().then_some(());
+        }
+
+        let index = parse_index(first, KIT_PADS)?;
+        let slot = parse_index(words.next()?, MAX_SESSION_SLOTS)?;
+        let note = words
+            .next()?
+            .parse::<u8>()
+            .ok()
+            .filter(|note| *note < 128)?;
+        let gate = match words.next()? {
+            "none" => None,
+            gate => Some(gate.parse::<u8>().ok()?),
+        };
+        let (min, max) = BURST_RANGE_IN_MS;
+        let burst_in_ms = words.next()?.parse::<u32>().ok()?.clamp(min, max);
+
+        let mut values = [0.0; PAD_VALUES];
+
+        for value in values.iter_mut() {
+            *value = words.next()?.parse::<f32>().ok()?.clamp(0.0, 1.0);
+        }
+
+        let window_function = words.next()?.parse::<u8>().ok()?;
+
+        self.pads[index] = Some(PadPreset {
+            slot,
+            note,
+            gate,
+            burst_in_ms,
+            values,
+            window_function,
+        });
+
+        words.next().is_none().then_some(())
+    }
+
+    fn parse_scene(&mut self, line: &str) -> Option<()> {
+        let mut words = line.split_whitespace();
+        let first = words.next()?;
+
+        if first == "morph" {
+            let scene_a = parse_index(words.next()?, SCENE_COUNT)?;
+            let scene_b = parse_index(words.next()?, SCENE_COUNT)?;
+            let source = match words.next()? {
+                "none" => None,
+                source => Some(parse_index(source, SCENE_CHANNELS)?),
+            };
+
+            self.morph = Some((scene_a, scene_b, source));
+        } else {
+            let index = parse_index(first, SCENE_COUNT)?;
+            let mut values = [0.0; SCENE_CHANNELS];
+
+            for value in values.iter_mut() {
+                *value = words.next()?.parse::<f32>().ok()?.clamp(0.0, 1.0);
+            }
+
+            self.scenes[index] = Some(values);
+        }
+
+        words.next().is_none().then_some(())
+    }
+
+    fn parse_controller(&mut self, line: &str) -> Option<()> {
+        let mut words = line.split_whitespace();
+        let controller = parse_index(words.next()?, CONTROLLERS)?;
+        let parameter = Parameter::from_name(words.next()?)?;
+
+        if parameter == Parameter::None {
+            return None;
+        }
+
+        self.controllers.as_mut()?[controller] = parameter;
+
+        words.next().is_none().then_some(())
+    }
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn parse_index(word: &str, count: usize) -> Option<usize> {
+    word.parse::<usize>().ok().filter(|index| *index < count)
+}
+
+/// Text of a session which lives on the stack.
+pub struct SessionText {
+    bytes: [u8; MAX_SESSION_LENGTH],
+    len: usize,
+}
+
+impl SessionText {
+    pub const fn new() -> Self {
+        SessionText {
+            bytes: [0; MAX_SESSION_LENGTH],
+            len: 0,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl Default for SessionText {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for SessionText {
+    fn write_str(&mut self, text: &str) -> core::fmt::Result {
+        let end = self.len + text.len();
+
+        if end > MAX_SESSION_LENGTH {
+            return Err(core::fmt::Error);
+        }
+
+        self.bytes[self.len..end].copy_from_slice(text.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Save and load requests of the menu, executed by the idle task.
+pub struct SessionRequests {
+    save: AtomicBool,
+    load: AtomicBool,
+    loaded: AtomicBool,
+}
+
+impl SessionRequests {
+    pub const fn new() -> Self {
+        SessionRequests {
+            save: AtomicBool::new(false),
+            load: AtomicBool::new(false),
+            loaded: AtomicBool::new(false),
+        }
+    }
+
+    pub fn request_save(&self) {
+        self.save.store(true, Ordering::Release);
+    }
+
+    pub fn request_load(&self) {
+        self.load.store(true, Ordering::Release);
+    }
+
+    pub fn take_save(&self) -> bool {
+        self.save.swap(false, Ordering::Acquire)
+    }
+
+    pub fn take_load(&self) -> bool {
+        self.load.swap(false, Ordering::Acquire)
+    }
+
+    /// Tells the control task that a loaded session waits to be applied.
+    pub fn set_loaded(&self) {
+        self.loaded.store(true, Ordering::Release);
+    }
+
+    pub fn take_loaded(&self) -> bool {
+        self.loaded.swap(false, Ordering::Acquire)
+    }
+}
+
+impl Default for SessionRequests {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub static SESSION: SessionRequests = SessionRequests::new();
@@ -0,0 +1,143 @@
+use crate::mapping::Parameter;
+use crate::quantizer::Quantizer;
+
+/// Settings of the grain clouds, set from the knobs by the control task and glided towards by the
+/// audio task. All values but the window function and the tuning are normalized between `0.0` and
+/// `1.0`, the spreads move the values of every grain by up to as much in either direction.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct UserSettings {
+    pub master_volume: f32,
+    /// Grains which may play at once, as share of the most
+    pub active_grains: f32,
+    pub offset: f32,
+    pub grain_size: f32,
+    pub pitch: f32,
+    /// Time between the grain starts
+    pub delay: f32,
+    pub velocity: f32,
+    pub sp_offset: f32,
+    pub sp_grain_size: f32,
+    pub sp_pitch: f32,
+    pub sp_delay: f32,
+    pub sp_velocity: f32,
+    /// Window of the grains, see `grains::Window`
+    pub window_function: u8,
+    /// Shape of the window, for the windows which have one
+    pub window_param: f32,
+    /// Scale, mode, root and fine tune the pitch of every grain gets snapped to
+    pub tuning: Quantizer,
+}
+
+/// Settings of all processing stages besides the grain clouds.
+///
+/// Complements `UserSettings` and is shared between the control and the audio task
+/// in the same way. All values but the slot are normalized between `0.0` and `1.0`.
+#[derive(Clone, Copy)]
+pub struct EngineSettings {
+    /// Playback speed of the varispeed engine, `0.5` means standstill
+    pub varispeed_speed: f32,
+    /// Blend between the granular (`0.0`) and the varispeed (`1.0`) engine
+    pub engine_blend: f32,
+    /// Echo time as fraction of the longest echo
+    pub echo_time: f32,
+    pub echo_feedback: f32,
+    /// Crossfade between the dry (`0.0`) and the echoed (`1.0`) signal
+    pub echo_mix: f32,
+    /// Room size of the reverb
+    pub reverb_size: f32,
+    /// Level of the reverb which gets added to the dry signal
+    pub reverb_mix: f32,
+    /// Bit crushing of the texture stage, `0.0` is off
+    pub texture_crush: f32,
+    /// Sample rate reduction of the texture stage, `0.0` is off
+    pub texture_downsample: f32,
+    /// Position of the master volume knob, the output stage turns it into decibels
+    pub master_volume: f32,
+    /// Shift of every other grain start, `0.0` keeps the grains on the grid of the delay
+    pub grain_swing: f32,
+    /// Random shift of every grain start, `0.0` is off
+    pub grain_humanize: f32,
+    /// Share of every grain the envelope rises over on top of the window, `0.0` is off
+    pub grain_attack: f32,
+    /// Share of every grain the envelope falls over, `0.0` is off
+    pub grain_decay: f32,
+    /// Random change of the attack and the decay of every grain
+    pub grain_attack_spread: f32,
+    pub grain_decay_spread: f32,
+    /// Offset, grain size and pitch of the second grain stream, which shares all other settings
+    /// with the first one
+    pub second_offset: f32,
+    pub second_grain_size: f32,
+    pub second_pitch: f32,
+    /// Grains of the second stream as share of the most, `0.0` silences it
+    pub second_grains: f32,
+    /// Slot the second stream plays, `None` plays the active slot like the first one
+    pub second_slot: Option<usize>,
+    /// Share of the loop an overdub fades out on every pass, `0.0` keeps all of it
+    pub loop_decay: f32,
+}
+
+impl Default for EngineSettings {
+    fn default() -> Self {
+        EngineSettings {
+            varispeed_speed: 0.75,
+            engine_blend: 0.0,
+            echo_time: 0.25,
+            echo_feedback: 0.4,
+            echo_mix: 0.0,
+            reverb_size: 0.5,
+            reverb_mix: 0.0,
+            texture_crush: 0.0,
+            texture_downsample: 0.0,
+            master_volume: 0.0,
+            grain_swing: 0.0,
+            grain_humanize: 0.0,
+            grain_attack: 0.0,
+            grain_decay: 0.0,
+            grain_attack_spread: 0.0,
+            grain_decay_spread: 0.0,
+            second_offset: 0.0,
+            second_grain_size: 0.5,
+            second_pitch: 0.5,
+            second_grains: 0.0,
+            second_slot: None,
+            loop_decay: 0.0,
+        }
+    }
+}
+
+impl EngineSettings {
+    /// Returns the setting `parameter` controls, `None` for parameters of the granulator.
+    pub fn get(&self, parameter: Parameter) -> Option<f32> {
+        match parameter {
+            Parameter::VarispeedSpeed => Some(self.varispeed_speed),
+            Parameter::EngineBlend => Some(self.engine_blend),
+            Parameter::EchoTime => Some(self.echo_time),
+            Parameter::EchoFeedback => Some(self.echo_feedback),
+            Parameter::EchoMix => Some(self.echo_mix),
+            Parameter::ReverbSize => Some(self.reverb_size),
+            Parameter::ReverbMix => Some(self.reverb_mix),
+            Parameter::TextureCrush => Some(self.texture_crush),
+            Parameter::TextureDownsample => Some(self.texture_downsample),
+            _ => None,
+        }
+    }
+
+    /// Changes the setting `parameter` controls, parameters of the granulator are ignored.
+    pub fn set(&mut self, parameter: Parameter, value: f32) {
+        let setting = match parameter {
+            Parameter::VarispeedSpeed => &mut self.varispeed_speed,
+            Parameter::EngineBlend => &mut self.engine_blend,
+            Parameter::EchoTime => &mut self.echo_time,
+            Parameter::EchoFeedback => &mut self.echo_feedback,
+            Parameter::EchoMix => &mut self.echo_mix,
+            Parameter::ReverbSize => &mut self.reverb_size,
+            Parameter::ReverbMix => &mut self.reverb_mix,
+            Parameter::TextureCrush => &mut self.texture_crush,
+            Parameter::TextureDownsample => &mut self.texture_downsample,
+            _ => return,
+        };
+
+        *setting = value;
+    }
+}
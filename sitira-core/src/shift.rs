@@ -0,0 +1,151 @@
+use crate::mapping::{ControlMap, ControlMaps, Parameter, ParameterValues, MAPPED_CHANNELS};
+
+/// How a knob relates to the value of its parameter after a bank switch.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Pickup {
+    /// The knob controls its parameter
+    Active,
+    /// The bank has just been switched and the knob has not been read since
+    Pending,
+    /// The knob has to be turned up to the held value
+    Below,
+    /// The knob has to be turned down to the held value
+    Above,
+}
+
+/// Switches the knobs between the panel bank and the shift bank.
+///
+/// A parameter keeps its value while its knob controls the other bank. After every switch, a
+/// knob only takes over once it reaches the held value, so switching banks never makes a
+/// parameter jump.
+pub struct ShiftLayer {
+    maps: ControlMaps,
+    shifted: bool,
+    parameters: ParameterValues,
+    pickup: [Pickup; MAPPED_CHANNELS],
+    /// Knob positions when they were picked up
+    anchors: [f32; MAPPED_CHANNELS],
+    /// Set once a knob of the shift bank has been turned
+    used: bool,
+    pickup_threshold: f32,
+}
+
+impl ShiftLayer {
+    pub fn new(maps: ControlMaps, pickup_threshold: f32) -> Self {
+        ShiftLayer {
+            maps,
+            shifted: false,
+            parameters: ParameterValues::new(),
+            pickup: [Pickup::Active; MAPPED_CHANNELS],
+            anchors: [0.0; MAPPED_CHANNELS],
+            used: false,
+            pickup_threshold,
+        }
+    }
+
+    pub fn get_maps(&self) -> &ControlMaps {
+        &self.maps
+    }
+
+    /// Replaces the mappings of both banks, every knob then has to pick its parameter up again.
+    pub fn set_maps(&mut self, maps: ControlMaps) {
+        self.maps = maps;
+        self.pickup = [Pickup::Pending; MAPPED_CHANNELS];
+    }
+
+    pub fn is_shifted(&self) -> bool {
+        self.shifted
+    }
+
+    /// Switches the bank, every knob then has to pick its parameter up again.
+    pub fn set_shifted(&mut self, shifted: bool) {
+        if shifted == self.shifted {
+            return;
+        }
+
+        self.shifted = shifted;
+        self.pickup = [Pickup::Pending; MAPPED_CHANNELS];
+
+        if shifted {
+            self.used = false;
+        }
+    }
+
+    /// Returns `true` if a knob of the shift bank has been turned since it was last engaged.
+    pub fn is_used(&self) -> bool {
+        self.used
+    }
+
+    /// Counts the shift bank as used, e.g. when the held encoder served another gesture.
+    pub fn mark_used(&mut self) {
+        self.used = true;
+    }
+
+    /// Sets the held value of a parameter, e.g. after it has been changed in the menu.
+    pub fn set(&mut self, parameter: Parameter, value: f32) {
+        self.parameters.set(parameter, value);
+    }
+
+    /// Returns `true` if a knob of the active bank controls `parameter`.
+    pub fn is_mapped(&self, parameter: Parameter) -> bool {
+        self.get_map().get_channel(parameter).is_some()
+    }
+
+    /// Turns the channel values into parameter values.
+    pub fn apply(&mut self, values: &[f32; MAPPED_CHANNELS]) -> ParameterValues {
+        let threshold = self.pickup_threshold;
+
+        for (channel, value) in values.iter().enumerate() {
+            let mapping = self.get_map().get_mapping(channel);
+
+            if mapping.parameter == Parameter::None {
+                continue;
+            }
+
+            let position = mapping.apply(*value);
+            let held = self.parameters.get(mapping.parameter);
+
+            let pickup = match self.pickup[channel] {
+                Pickup::Pending if (position - held).abs() <= threshold => Pickup::Active,
+                Pickup::Pending if position < held => Pickup::Below,
+                Pickup::Pending => Pickup::Above,
+                // a knob which crosses the held value has obviously been turned
+                Pickup::Below if position >= held => {
+                    self.used |= self.shifted;
+                    Pickup::Active
+                }
+                Pickup::Above if position <= held => {
+                    self.used |= self.shifted;
+                    Pickup::Active
+                }
+                pickup => pickup,
+            };
+
+            if pickup != Pickup::Active {
+                self.pickup[channel] = pickup;
+                continue;
+            }
+
+            if self.pickup[channel] != Pickup::Active {
+                self.pickup[channel] = Pickup::Active;
+                self.anchors[channel] = position;
+            }
+
+            if self.shifted && (position - self.anchors[channel]).abs() > threshold {
+                self.used = true;
+            }
+
+            self.parameters.set(mapping.parameter, position);
+        }
+
+        self.parameters
+    }
+
+    fn get_map(&self) -> &ControlMap {
+        if self.shifted {
+            &self.maps.shift
+        } else {
+            &self.maps.panel
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use crate::mapping::{Parameter, ParameterValues, PARAMETER_COUNT};
+
+/// Longest rise or fall time which can be set
+pub const MAX_SLEW_IN_MS: u32 = 10_000;
+
+/// How fast one parameter may change, in ms for the full range upwards and downwards.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Slew {
+    pub rise_in_ms: u32,
+    pub fall_in_ms: u32,
+}
+
+impl Slew {
+    /// Follows every change at once
+    pub const OFF: Slew = Slew {
+        rise_in_ms: 0,
+        fall_in_ms: 0,
+    };
+}
+
+/// Limits how fast the parameters change, so a stepped CV, e.g. of a sequencer, glides from one
+/// step to the next.
+///
+/// Every parameter has a rise and a fall time of its own, which are all off at first. The values
+/// of the first update are taken over directly, from then on every update moves a slewed
+/// parameter towards its target by at most its share of the full range.
+pub struct SlewLimiter {
+    slews: [Slew; PARAMETER_COUNT],
+    current: Option<ParameterValues>,
+    /// Parameter whose slew the menu adjusts
+    selected: Parameter,
+    update_in_ms: f32,
+}
+
+impl SlewLimiter {
+    /// Creates a limiter which gets updated every `update_in_ms`.
+    pub fn new(update_in_ms: u32) -> Self {
+        SlewLimiter {
+            slews: [Slew::OFF; PARAMETER_COUNT],
+            current: None,
+            selected: Parameter::Offset,
+            update_in_ms: update_in_ms as f32,
+        }
+    }
+
+    pub fn get_selected(&self) -> Parameter {
+        self.selected
+    }
+
+    /// Selects the parameter whose slew gets adjusted.
+    pub fn select(&mut self, steps: i32) {
+        let index = (self.selected as i32 + steps).rem_euclid(PARAMETER_COUNT as i32);
+
+        self.selected = Parameter::from_index(index as usize);
+    }
+
+    pub fn get_slew(&self, parameter: Parameter) -> Slew {
+        match parameter {
+            Parameter::None => Slew::OFF,
+            _ => self.slews[parameter as usize],
+        }
+    }
+
+    /// Changes the rise time of the selected parameter by `change_in_ms`.
+    pub fn adjust_rise(&mut self, change_in_ms: i32) {
+        let slew = &mut self.slews[self.selected as usize];
+
+        slew.rise_in_ms = adjust_time(slew.rise_in_ms, change_in_ms);
+    }
+
+    /// Changes the fall time of the selected parameter by `change_in_ms`.
+    pub fn adjust_fall(&mut self, change_in_ms: i32) {
+        let slew = &mut self.slews[self.selected as usize];
+
+        slew.fall_in_ms = adjust_time(slew.fall_in_ms, change_in_ms);
+    }
+
+    /// Moves the parameters one update towards `target` and returns where they are.
+    pub fn apply(&mut self, target: &ParameterValues) -> ParameterValues {
+        let current = self.current.get_or_insert(*target);
+
+        for (index, slew) in self.slews.iter().enumerate() {
+            let parameter = Parameter::from_index(index);
+            let value = current.get(parameter);
+            let change = target.get(parameter) - value;
+
+            let time_in_ms = if change > 0.0 {
+                slew.rise_in_ms
+            } else {
+                slew.fall_in_ms
+            };
+
+            let limit = match time_in_ms {
+                0 => f32::MAX,
+                time_in_ms => self.update_in_ms / time_in_ms as f32,
+            };
+
+            current.set(parameter, value + change.clamp(-limit, limit));
+        }
+
+        *current
+    }
+}
+
+fn adjust_time(time_in_ms: u32, change_in_ms: i32) -> u32 {
+    (time_in_ms as i32 + change_in_ms).clamp(0, MAX_SLEW_IN_MS as i32) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(offset: f32, pitch: f32) -> ParameterValues {
+        let mut values = ParameterValues::new();
+        values.set(Parameter::Offset, offset);
+        values.set(Parameter::Pitch, pitch);
+        values
+    }
+
+    #[test]
+    fn parameters_without_slew_follow_at_once() {
+        let mut limiter = SlewLimiter::new(10);
+
+        limiter.apply(&values(0.0, 0.0));
+        let slewed = limiter.apply(&values(1.0, 1.0));
+
+        assert_eq!(slewed.get(Parameter::Offset), 1.0);
+        assert_eq!(slewed.get(Parameter::Pitch), 1.0);
+    }
+
+    #[test]
+    fn rise_and_fall_limit_the_change_per_update() {
+        let mut limiter = SlewLimiter::new(10);
+
+        limiter.select(2);
+        assert_eq!(limiter.get_selected(), Parameter::Pitch);
+
+        // the full range upwards in 100 ms and downwards in 50 ms
+        limiter.adjust_rise(100);
+        limiter.adjust_fall(50);
+
+        limiter.apply(&values(0.0, 0.0));
+
+        let rising: Vec<f32> = (0..3)
+            .map(|_| limiter.apply(&values(1.0, 0.5)).get(Parameter::Pitch))
+            .collect();
+        let falling = limiter.apply(&values(1.0, 0.0)).get(Parameter::Pitch);
+
+        assert!((rising[0] - 0.1).abs() < 1e-6);
+        assert!((rising[2] - 0.3).abs() < 1e-6);
+        assert!((falling - 0.1).abs() < 1e-6);
+        assert_eq!(limiter.get_slew(Parameter::Offset), Slew::OFF);
+    }
+
+    #[test]
+    fn selection_and_times_wrap_and_clamp() {
+        let mut limiter = SlewLimiter::new(10);
+
+        limiter.select(-1);
+        assert_eq!(limiter.get_selected(), Parameter::Macro);
+
+        limiter.adjust_rise(-10);
+        limiter.adjust_fall(MAX_SLEW_IN_MS as i32 * 2);
+
+        assert_eq!(
+            limiter.get_slew(Parameter::Macro),
+            Slew {
+                rise_in_ms: 0,
+                fall_in_ms: MAX_SLEW_IN_MS
+            }
+        );
+    }
+}
@@ -0,0 +1,138 @@
+/// Maximum number of slice markers per buffer
+pub const MAX_SLICES: usize = 32;
+
+/// Slice markers of the recorded buffer.
+///
+/// Markers are stored as sample positions in ascending order. Every slice spans from its marker
+/// to the next one (or the end of the buffer). One slice can be selected, which then confines the
+/// grain offset to its region.
+pub struct SliceMarkers {
+    positions: [usize; MAX_SLICES],
+    len: usize,
+    buffer_length: usize,
+    selected: Option<usize>,
+    changed: bool,
+}
+
+impl SliceMarkers {
+    pub fn new() -> Self {
+        SliceMarkers {
+            positions: [0; MAX_SLICES],
+            len: 0,
+            buffer_length: 0,
+            selected: None,
+            changed: false,
+        }
+    }
+
+    /// Removes all markers and sets the length of the buffer they refer to.
+    pub fn clear(&mut self, buffer_length: usize) {
+        self.len = 0;
+        self.buffer_length = buffer_length;
+        self.selected = None;
+        self.changed = true;
+    }
+
+    /// Appends a marker, returns `false` if it was dropped because the list is full or the
+    /// position is not behind the last marker.
+    pub fn push(&mut self, position: usize) -> bool {
+        if self.len == MAX_SLICES || position >= self.buffer_length {
+            return false;
+        }
+
+        if self.len > 0 && position <= self.positions[self.len - 1] {
+            return false;
+        }
+
+        self.positions[self.len] = position;
+        self.len += 1;
+        self.changed = true;
+
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get_buffer_length(&self) -> usize {
+        self.buffer_length
+    }
+
+    /// Returns all marker positions in samples.
+    pub fn as_slice(&self) -> &[usize] {
+        &self.positions[..self.len]
+    }
+
+    /// Returns start and end of a slice normalized to the buffer length.
+    pub fn get_region(&self, index: usize) -> Option<(f32, f32)> {
+        if index >= self.len || self.buffer_length == 0 {
+            return None;
+        }
+
+        let start = self.positions[index];
+        let end = if index + 1 < self.len {
+            self.positions[index + 1]
+        } else {
+            self.buffer_length
+        };
+
+        Some((
+            start as f32 / self.buffer_length as f32,
+            end as f32 / self.buffer_length as f32,
+        ))
+    }
+
+    /// Selects a slice, indices beyond the last slice wrap around.
+    pub fn select(&mut self, index: usize) {
+        if self.len > 0 {
+            self.selected = Some(index % self.len);
+            self.changed = true;
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        let next = match self.selected {
+            Some(index) => index + 1,
+            None => 0,
+        };
+
+        self.select(next);
+    }
+
+    pub fn deselect(&mut self) {
+        self.selected = None;
+        self.changed = true;
+    }
+
+    pub fn get_selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    /// Maps a normalized offset (`0.0` to `1.0`) into the region of the selected slice.
+    ///
+    /// Returns the offset unchanged when no slice is selected.
+    pub fn apply(&self, offset: f32) -> f32 {
+        match self.selected.and_then(|index| self.get_region(index)) {
+            Some((start, end)) => start + offset * (end - start),
+            None => offset,
+        }
+    }
+
+    /// Returns `true` once after the markers or the selection have changed.
+    pub fn take_changed(&mut self) -> bool {
+        let changed = self.changed;
+        self.changed = false;
+        changed
+    }
+}
+
+impl Default for SliceMarkers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
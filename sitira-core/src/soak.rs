@@ -0,0 +1,194 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use micromath::F32Ext;
+
+use crate::event::Command;
+
+/// Length of one sweep from the lowest to the highest frequency
+const SWEEP_SECONDS: f32 = 2.0;
+const SWEEP_START_FREQUENCY: f32 = 20.0;
+const SWEEP_END_FREQUENCY: f32 = 10_000.0;
+/// Noise is played in bursts of this length with twice the length of silence in between
+const BURST_SECONDS: f32 = 0.1;
+/// Total length of the noise burst section
+const NOISE_SECONDS: f32 = 2.0;
+/// Silence after every program, so the meters see both extremes
+const SILENCE_SECONDS: f32 = 1.0;
+const LEVEL: f32 = 0.5;
+
+/// Control cycles the output may stay clipping before it counts as anomaly
+const MAX_CLIPPING_TICKS: u32 = 100;
+
+/// Synthetic input signal for the soak test.
+///
+/// Loops through an exponential sine sweep, noise bursts and silence.
+pub struct SignalGenerator {
+    sample_rate: f32,
+    position: u32,
+    phase: f32,
+    noise: u32,
+}
+
+impl SignalGenerator {
+    pub fn new(sample_rate: f32) -> Self {
+        SignalGenerator {
+            sample_rate,
+            position: 0,
+            phase: 0.0,
+            noise: 0x1234_5678,
+        }
+    }
+
+    /// Replaces the incoming audio with the next block of the program.
+    pub fn fill(&mut self, buffer: &mut [(f32, f32)]) {
+        for frame in buffer.iter_mut() {
+            let sample = self.next_sample();
+            *frame = (sample, sample);
+        }
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        let sweep_end = (SWEEP_SECONDS * self.sample_rate) as u32;
+        let noise_end = sweep_end + (NOISE_SECONDS * self.sample_rate) as u32;
+        let program_end = noise_end + (SILENCE_SECONDS * self.sample_rate) as u32;
+
+        let position = self.position;
+        self.position = (self.position + 1) % program_end;
+
+        if position < sweep_end {
+            let progress = position as f32 / sweep_end as f32;
+            let frequency = SWEEP_START_FREQUENCY
+                * (SWEEP_END_FREQUENCY / SWEEP_START_FREQUENCY).powf(progress);
+
+            self.phase = (self.phase + frequency / self.sample_rate).fract();
+            (self.phase * core::f32::consts::TAU).sin() * LEVEL
+        } else if position < noise_end {
+            let burst = (BURST_SECONDS * self.sample_rate) as u32;
+
+            if (position - sweep_end) % (3 * burst) < burst {
+                self.next_noise() * LEVEL
+            } else {
+                0.0
+            }
+        } else {
+            0.0
+        }
+    }
+
+    /// Xorshift noise between `-1.0` and `1.0`.
+    fn next_noise(&mut self) -> f32 {
+        self.noise ^= self.noise << 13;
+        self.noise ^= self.noise >> 17;
+        self.noise ^= self.noise << 5;
+        self.noise as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+/// Fires transport and playback commands on a fixed schedule.
+///
+/// The intervals are prime numbers of control cycles, so the commands keep meeting each other in
+/// different states over the course of hours.
+pub struct SoakSchedule {
+    slice: u8,
+}
+
+impl SoakSchedule {
+    pub fn new() -> Self {
+        SoakSchedule { slice: 0 }
+    }
+
+    /// Returns the command which is due in the given control cycle.
+    pub fn tick(&mut self, tick: u32) -> Option<Command> {
+        if tick.is_multiple_of(211) {
+            Some(Command::ToggleRecording)
+        } else if tick.is_multiple_of(53) {
+            self.slice = (self.slice + 5) % 16;
+            Some(Command::JumpToSlice(self.slice))
+        } else if tick.is_multiple_of(29) {
+            Some(Command::NextSlice)
+        } else if tick.is_multiple_of(13) {
+            Some(Command::RotateBuffer)
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for SoakSchedule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Counters of everything which went wrong during the soak test.
+///
+/// Written by the audio and the control task without locking.
+pub struct SoakMonitor {
+    overruns: AtomicU32,
+    invalid_samples: AtomicU32,
+    meter_anomalies: AtomicU32,
+    clipping_ticks: AtomicU32,
+}
+
+impl SoakMonitor {
+    pub const fn new() -> Self {
+        SoakMonitor {
+            overruns: AtomicU32::new(0),
+            invalid_samples: AtomicU32::new(0),
+            meter_anomalies: AtomicU32::new(0),
+            clipping_ticks: AtomicU32::new(0),
+        }
+    }
+
+    /// Counts an audio callback which took longer than its block period.
+    pub fn check_callback(&self, elapsed_cycles: u32, budget_cycles: u32) {
+        if elapsed_cycles > budget_cycles {
+            self.overruns.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts NaN and infinite output samples.
+    pub fn check_sample(&self, sample: f32) {
+        if !sample.is_finite() {
+            self.invalid_samples.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Checks the meters once per control cycle. Invalid levels and an output which keeps
+    /// clipping count as anomaly.
+    pub fn check_meters(&self, input_peak: f32, output_peak: f32) {
+        if !input_peak.is_finite() || !output_peak.is_finite() {
+            self.meter_anomalies.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if output_peak >= crate::meter::CLIP_LEVEL {
+            let ticks = self.clipping_ticks.fetch_add(1, Ordering::Relaxed) + 1;
+
+            if ticks == MAX_CLIPPING_TICKS {
+                self.meter_anomalies.fetch_add(1, Ordering::Relaxed);
+            }
+        } else {
+            self.clipping_ticks.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub fn get_overruns(&self) -> u32 {
+        self.overruns.load(Ordering::Relaxed)
+    }
+
+    pub fn get_invalid_samples(&self) -> u32 {
+        self.invalid_samples.load(Ordering::Relaxed)
+    }
+
+    pub fn get_meter_anomalies(&self) -> u32 {
+        self.meter_anomalies.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for SoakMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub static SOAK_MONITOR: SoakMonitor = SoakMonitor::new();
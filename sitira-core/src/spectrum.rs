@@ -0,0 +1,296 @@
+use core::f32::consts::PI;
+use core::ops::Range;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+
+use micromath::F32Ext;
+
+use crate::grains::Window;
+
+/// Samples of the decimated output one analysis takes, a power of two
+pub const SPECTRUM_SIZE: usize = 512;
+/// Bars of the spectrum, spaced logarithmically from the lowest bin to half the sample rate
+pub const SPECTRUM_BANDS: usize = 64;
+/// Samples of the output averaged into one sample of the analysis
+pub const SPECTRUM_DECIMATION: usize = 2;
+/// Level at the bottom of the bars
+const FLOOR_DB: f32 = -72.0;
+/// How far a bar falls per analysis, as share of its height
+const FALL: f32 = 0.05;
+
+/// Tap of the output and the spectrum computed from it, shared between the audio task, the
+/// analysis in the idle task and the display.
+///
+/// The audio task fills a frame of decimated samples while the spectrum is shown and then leaves
+/// it alone until the analysis has taken it. Samples and bands are stored as raw `f32` bits in
+/// atomics, like the level meters.
+pub struct Spectrum {
+    enabled: AtomicBool,
+    samples: [AtomicU32; SPECTRUM_SIZE],
+    /// Samples in the frame, which belongs to the analysis once it is full
+    written: AtomicUsize,
+    bands: [AtomicU32; SPECTRUM_BANDS],
+    changed: AtomicBool,
+}
+
+impl Spectrum {
+    pub const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicU32 = AtomicU32::new(0);
+
+        Spectrum {
+            enabled: AtomicBool::new(false),
+            samples: [ZERO; SPECTRUM_SIZE],
+            written: AtomicUsize::new(0),
+            bands: [ZERO; SPECTRUM_BANDS],
+            changed: AtomicBool::new(false),
+        }
+    }
+
+    /// Starts or stops the tap, the output only gets copied while the spectrum is shown.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Takes a block of the output. Samples which do not fit into the frame get dropped, so the
+    /// next frame starts where the analysis took the last one.
+    pub fn push(&self, block: &[f32]) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let mut written = self.written.load(Ordering::Acquire);
+
+        for samples in block.chunks_exact(SPECTRUM_DECIMATION) {
+            if written >= SPECTRUM_SIZE {
+                break;
+            }
+
+            let sample = samples.iter().sum::<f32>() / SPECTRUM_DECIMATION as f32;
+
+            self.samples[written].store(sample.to_bits(), Ordering::Relaxed);
+            written += 1;
+        }
+
+        self.written.store(written, Ordering::Release);
+    }
+
+    /// Copies a full frame and lets the tap start the next one, `false` while it is not full.
+    fn take_frame(&self, frame: &mut [f32; SPECTRUM_SIZE]) -> bool {
+        if self.written.load(Ordering::Acquire) < SPECTRUM_SIZE {
+            return false;
+        }
+
+        for (sample, stored) in frame.iter_mut().zip(self.samples.iter()) {
+            *sample = f32::from_bits(stored.load(Ordering::Relaxed));
+        }
+
+        self.written.store(0, Ordering::Release);
+
+        true
+    }
+
+    fn publish(&self, bands: &[f32; SPECTRUM_BANDS]) {
+        for (stored, band) in self.bands.iter().zip(bands) {
+            stored.store(band.to_bits(), Ordering::Relaxed);
+        }
+
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns the height of every bar from `0.0` to `1.0`, the lowest band first.
+    pub fn get_bands(&self) -> [f32; SPECTRUM_BANDS] {
+        let mut bands = [0.0; SPECTRUM_BANDS];
+
+        for (band, stored) in bands.iter_mut().zip(self.bands.iter()) {
+            *band = f32::from_bits(stored.load(Ordering::Relaxed));
+        }
+
+        bands
+    }
+
+    /// Returns `true` once after a new spectrum got published.
+    pub fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::Relaxed)
+    }
+}
+
+impl Default for Spectrum {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Turns the frames of the tap into bands with a Hann windowed FFT, meant to be stepped by the
+/// idle task.
+///
+/// The bars jump up to a louder level right away and fall slowly, so short peaks stay visible.
+pub struct SpectrumAnalyzer {
+    real: [f32; SPECTRUM_SIZE],
+    imaginary: [f32; SPECTRUM_SIZE],
+    /// Cosine and sine of the first half turn, in steps of one bin
+    twiddles: [(f32, f32); SPECTRUM_SIZE / 2],
+    bands: [f32; SPECTRUM_BANDS],
+}
+
+impl SpectrumAnalyzer {
+    pub fn new() -> Self {
+        let mut twiddles = [(0.0, 0.0); SPECTRUM_SIZE / 2];
+
+        for (index, twiddle) in twiddles.iter_mut().enumerate() {
+            let angle = 2.0 * PI * index as f32 / SPECTRUM_SIZE as f32;
+            *twiddle = (angle.cos(), angle.sin());
+        }
+
+        SpectrumAnalyzer {
+            real: [0.0; SPECTRUM_SIZE],
+            imaginary: [0.0; SPECTRUM_SIZE],
+            twiddles,
+            bands: [0.0; SPECTRUM_BANDS],
+        }
+    }
+
+    /// Analyzes the frame of `spectrum` once it is full and publishes the bands. Returns `false`
+    /// while the frame is still being filled.
+    pub fn step(&mut self, spectrum: &Spectrum) -> bool {
+        if !spectrum.take_frame(&mut self.real) {
+            return false;
+        }
+
+        for (index, sample) in self.real.iter_mut().enumerate() {
+            *sample *= Window::Hann.gain(index as f32 / SPECTRUM_SIZE as f32, 0.0);
+        }
+
+        self.imaginary = [0.0; SPECTRUM_SIZE];
+        self.transform();
+
+        // a full scale sine on a bin reaches a quarter of the size through the Hann window
+        let full_scale = (SPECTRUM_SIZE / 4) as f32;
+
+        for (band, bar) in self.bands.iter_mut().enumerate() {
+            let power = band_bins(band)
+                .map(|bin| {
+                    self.real[bin] * self.real[bin] + self.imaginary[bin] * self.imaginary[bin]
+                })
+                .fold(0.0, f32::max);
+            let db = 10.0 * (power / (full_scale * full_scale)).max(1e-12).log10();
+            let level = ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0);
+
+            *bar = level.max(*bar - FALL);
+        }
+
+        spectrum.publish(&self.bands);
+
+        true
+    }
+
+    /// Transforms the frame in place, a radix-2 FFT with decimation in time.
+    fn transform(&mut self) {
+        let bits = SPECTRUM_SIZE.trailing_zeros();
+
+        for index in 0..SPECTRUM_SIZE {
+            let reversed = index.reverse_bits() >> (usize::BITS - bits);
+
+            if reversed > index {
+                self.real.swap(index, reversed);
+                self.imaginary.swap(index, reversed);
+            }
+        }
+
+        let mut size = 2;
+
+        while size <= SPECTRUM_SIZE {
+            let half = size / 2;
+            let stride = SPECTRUM_SIZE / size;
+
+            for start in (0..SPECTRUM_SIZE).step_by(size) {
+                for offset in 0..half {
+                    let (cos, sin) = self.twiddles[offset * stride];
+                    let (even, odd) = (start + offset, start + offset + half);
+
+                    // the odd half gets turned by e^(-i * angle)
+                    let real = self.real[odd] * cos + self.imaginary[odd] * sin;
+                    let imaginary = self.imaginary[odd] * cos - self.real[odd] * sin;
+
+                    self.real[odd] = self.real[even] - real;
+                    self.imaginary[odd] = self.imaginary[even] - imaginary;
+                    self.real[even] += real;
+                    self.imaginary[even] += imaginary;
+                }
+            }
+
+            size *= 2;
+        }
+    }
+}
+
+impl Default for SpectrumAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Bins of a band, every band spans the same share of octaves and at least one bin. The bin of
+/// the DC offset is left out.
+fn band_bins(band: usize) -> Range<usize> {
+    let octaves = (SPECTRUM_SIZE / 2).trailing_zeros() as f32;
+    let edge =
+        |band: usize| (2.0.powf(octaves * band as f32 / SPECTRUM_BANDS as f32) + 0.5) as usize;
+    let start = edge(band);
+
+    start..edge(band + 1).max(start + 1)
+}
+
+/// Spectrum of the output, shown on the display
+pub static SPECTRUM: Spectrum = Spectrum::new();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sine_lights_up_its_band() {
+        let spectrum = Spectrum::new();
+        let mut analyzer = SpectrumAnalyzer::new();
+
+        // 32 periods per frame put the sine right on bin 32
+        let block: Vec<f32> = (0..SPECTRUM_SIZE * SPECTRUM_DECIMATION)
+            .map(|n| {
+                let frame = (n / SPECTRUM_DECIMATION) as f32;
+                (2.0 * PI * 32.0 * frame / SPECTRUM_SIZE as f32).sin()
+            })
+            .collect();
+
+        spectrum.push(&block);
+        assert!(!analyzer.step(&spectrum));
+
+        spectrum.set_enabled(true);
+        spectrum.push(&block[..100]);
+        spectrum.push(&block[100..]);
+
+        assert!(analyzer.step(&spectrum));
+        assert!(spectrum.take_changed());
+
+        let bands = spectrum.get_bands();
+        let band = (0..SPECTRUM_BANDS)
+            .find(|band| band_bins(*band).contains(&32))
+            .unwrap();
+
+        assert!(bands[band] > 0.95, "{}", bands[band]);
+        assert!(bands[band / 2] < 0.1, "{}", bands[band / 2]);
+        assert!(bands[SPECTRUM_BANDS - 1] < 0.1);
+
+        // the frame has been taken, the next one needs new samples
+        assert!(!analyzer.step(&spectrum));
+    }
+
+    #[test]
+    fn bands_cover_all_bins() {
+        assert_eq!(band_bins(0).start, 1);
+        assert_eq!(band_bins(SPECTRUM_BANDS - 1).end, SPECTRUM_SIZE / 2);
+
+        for band in 1..SPECTRUM_BANDS {
+            assert!(band_bins(band).start >= band_bins(band - 1).start);
+            assert!(!band_bins(band).is_empty());
+        }
+    }
+}
@@ -0,0 +1,195 @@
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Lock-free queue from one producer to one consumer, meant to live in a static.
+///
+/// Holds up to `N - 1` items. Pushing never blocks, an item gets dropped when the queue is full.
+/// The queue itself can not be used, it hands out one `Producer` and one `Consumer` once, which
+/// then get owned by the context on either side, e.g. a task on one and a task on the other.
+pub struct SpscQueue<T, const N: usize> {
+    items: UnsafeCell<[MaybeUninit<T>; N]>,
+    /// Next item to pop, only written by the consumer
+    head: AtomicUsize,
+    /// Next free place, only written by the producer
+    tail: AtomicUsize,
+    /// Set once both ends have been handed out
+    split: AtomicBool,
+}
+
+// SAFETY: there is only one producer and one consumer, each touches its own end of the queue and
+// the indices hand the items over with release and acquire ordering
+unsafe impl<T: Copy + Send, const N: usize> Sync for SpscQueue<T, N> {}
+
+impl<T: Copy, const N: usize> SpscQueue<T, N> {
+    pub const fn new() -> Self {
+        SpscQueue {
+            items: UnsafeCell::new([MaybeUninit::uninit(); N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            split: AtomicBool::new(false),
+        }
+    }
+
+    /// Hands out both ends of the queue, `None` if that has happened before.
+    pub fn split(&self) -> Option<(Producer<'_, T, N>, Consumer<'_, T, N>)> {
+        if self.split.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+
+        Some((Producer { queue: self }, Consumer { queue: self }))
+    }
+}
+
+impl<T: Copy, const N: usize> Default for SpscQueue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The end of a `SpscQueue` which pushes, there is only one of it.
+pub struct Producer<'a, T, const N: usize> {
+    queue: &'a SpscQueue<T, N>,
+}
+
+impl<T: Copy, const N: usize> Producer<'_, T, N> {
+    /// Appends an item, returns `false` if the queue was full.
+    pub fn push(&mut self, item: T) -> bool {
+        let queue = self.queue;
+        let tail = queue.tail.load(Ordering::Relaxed);
+        let next = (tail + 1) % N;
+
+        if next == queue.head.load(Ordering::Acquire) {
+            return false;
+        }
+
+        // SAFETY: the consumer does not read the place behind the tail
+        unsafe { (*queue.items.get())[tail] = MaybeUninit::new(item) };
+        queue.tail.store(next, Ordering::Release);
+
+        true
+    }
+}
+
+/// The end of a `SpscQueue` which pops, there is only one of it.
+pub struct Consumer<'a, T, const N: usize> {
+    queue: &'a SpscQueue<T, N>,
+}
+
+impl<T: Copy, const N: usize> Consumer<'_, T, N> {
+    /// Returns how many items are waiting, the producer may add more meanwhile.
+    pub fn len(&self) -> usize {
+        let queue = self.queue;
+        let head = queue.head.load(Ordering::Relaxed);
+        let tail = queue.tail.load(Ordering::Acquire);
+
+        (tail + N - head) % N
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes the oldest item.
+    pub fn pop(&mut self) -> Option<T> {
+        let queue = self.queue;
+        let head = queue.head.load(Ordering::Relaxed);
+
+        if head == queue.tail.load(Ordering::Acquire) {
+            return None;
+        }
+
+        // SAFETY: the place has been written before the tail moved past it
+        let item = unsafe { (*queue.items.get())[head].assume_init() };
+        queue.head.store((head + 1) % N, Ordering::Release);
+
+        Some(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_only_once() {
+        let queue: SpscQueue<u32, 4> = SpscQueue::new();
+
+        assert!(queue.split().is_some());
+        assert!(queue.split().is_none());
+    }
+
+    #[test]
+    fn pops_in_the_order_of_pushing() {
+        let queue: SpscQueue<u32, 4> = SpscQueue::new();
+        let (mut producer, mut consumer) = queue.split().unwrap();
+
+        assert_eq!(consumer.pop(), None);
+        assert!(producer.push(1));
+        assert!(producer.push(2));
+        assert_eq!(consumer.pop(), Some(1));
+        assert_eq!(consumer.pop(), Some(2));
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn drops_items_when_full() {
+        let queue: SpscQueue<u32, 4> = SpscQueue::new();
+        let (mut producer, mut consumer) = queue.split().unwrap();
+
+        assert!(producer.push(1));
+        assert!(producer.push(2));
+        assert!(producer.push(3));
+        assert!(!producer.push(4));
+        assert_eq!(consumer.len(), 3);
+
+        assert_eq!(consumer.pop(), Some(1));
+        assert!(producer.push(5));
+        assert_eq!(consumer.pop(), Some(2));
+        assert_eq!(consumer.pop(), Some(3));
+        assert_eq!(consumer.pop(), Some(5));
+    }
+
+    #[test]
+    fn indices_wrap_around() {
+        let queue: SpscQueue<u32, 4> = SpscQueue::new();
+        let (mut producer, mut consumer) = queue.split().unwrap();
+
+        for item in 0..20 {
+            assert!(producer.push(item));
+            assert_eq!(consumer.len(), 1);
+            assert_eq!(consumer.pop(), Some(item));
+        }
+
+        assert!(consumer.is_empty());
+        assert_eq!(consumer.pop(), None);
+    }
+
+    #[test]
+    fn hands_items_between_threads() {
+        static QUEUE: SpscQueue<u32, 8> = SpscQueue::new();
+        let (mut producer, mut consumer) = QUEUE.split().unwrap();
+
+        let sender = std::thread::spawn(move || {
+            for item in 0..10_000 {
+                while !producer.push(item) {
+                    std::thread::yield_now();
+                }
+            }
+        });
+
+        let mut expected = 0;
+
+        while expected < 10_000 {
+            match consumer.pop() {
+                Some(item) => {
+                    assert_eq!(item, expected);
+                    expected += 1;
+                }
+                None => std::thread::yield_now(),
+            }
+        }
+
+        sender.join().unwrap();
+    }
+}
@@ -0,0 +1,362 @@
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, AtomicUsize, Ordering};
+
+use crate::resample::ResampleQuality;
+
+/// Pages of the window, one is played while the ones ahead of it get loaded
+pub const STREAM_PAGES: usize = 8;
+
+/// Marks a page of the window which holds no page of the file
+const EMPTY: usize = usize::MAX;
+
+/// A page of the window which needs to be filled with a page of the file.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PageRequest {
+    /// Page of the window, which starts at `index * page_length`
+    pub index: usize,
+    /// Page of the file, which starts at `page * page_length`
+    pub page: usize,
+}
+
+/// Window of `STREAM_PAGES` pages in memory onto a file which is too long to fit into it.
+///
+/// Page `n` of the file always lives in page `n % STREAM_PAGES` of the window, so the window is
+/// a ring which the file rotates through. The control task publishes the read position, the
+/// loading task asks for the pages it needs next and fills them one after another: the page under
+/// the position first, then the ones ahead of it and the one behind. Everything is stored in
+/// atomics, so the audio task can check without locking if the page under the read position
+/// holds the right samples.
+pub struct PageTable {
+    page_length: usize,
+    /// Page of the file held by every page of the window, `EMPTY` while it gets loaded
+    pages: [AtomicUsize; STREAM_PAGES],
+    /// Length of the file in samples, 0 if nothing is streamed
+    length: AtomicUsize,
+    position: AtomicUsize,
+}
+
+impl PageTable {
+    pub const fn new(page_length: usize) -> Self {
+        PageTable {
+            page_length,
+            pages: [
+                AtomicUsize::new(EMPTY),
+                AtomicUsize::new(EMPTY),
+                AtomicUsize::new(EMPTY),
+                AtomicUsize::new(EMPTY),
+                AtomicUsize::new(EMPTY),
+                AtomicUsize::new(EMPTY),
+                AtomicUsize::new(EMPTY),
+                AtomicUsize::new(EMPTY),
+            ],
+            length: AtomicUsize::new(0),
+            position: AtomicUsize::new(0),
+        }
+    }
+
+    /// Starts streaming a file of `length` samples into an empty window.
+    pub fn start(&self, length: usize) {
+        for page in self.pages.iter() {
+            page.store(EMPTY, Ordering::Relaxed);
+        }
+
+        self.position.store(0, Ordering::Relaxed);
+        self.length.store(length, Ordering::Release);
+    }
+
+    pub fn stop(&self) {
+        self.length.store(0, Ordering::Relaxed);
+    }
+
+    pub fn is_streaming(&self) -> bool {
+        !self.is_empty()
+    }
+
+    /// Returns the length of the streamed file in samples.
+    pub fn len(&self) -> usize {
+        self.length.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get_page_length(&self) -> usize {
+        self.page_length
+    }
+
+    /// Returns the samples of the window, which is what the engines get to play from.
+    pub fn get_window_length(&self) -> usize {
+        STREAM_PAGES * self.page_length
+    }
+
+    /// Sets the read position in samples of the file, which decides the pages to load next.
+    pub fn set_position(&self, position: usize) {
+        self.position.store(position, Ordering::Relaxed);
+    }
+
+    /// Maps a normalized offset into the file onto the window. Returns `None` while the page under
+    /// the offset has not been loaded.
+    pub fn map_offset(&self, offset: f32) -> Option<f32> {
+        let length = self.len();
+
+        if length == 0 {
+            return None;
+        }
+
+        let position = ((offset.clamp(0.0, 1.0) * length as f32) as usize).min(length - 1);
+        let page = position / self.page_length;
+
+        if self.pages[page % STREAM_PAGES].load(Ordering::Acquire) != page {
+            return None;
+        }
+
+        let window = self.get_window_length();
+
+        Some((position % window) as f32 / window as f32)
+    }
+
+    /// Returns the next page to load and marks its place in the window as empty, `None` if all
+    /// pages around the read position are loaded.
+    pub fn next_request(&self) -> Option<PageRequest> {
+        let page_count = self.get_page_count();
+        let current = self.position.load(Ordering::Relaxed) / self.page_length;
+
+        // the pages ahead come first, grains mostly move forward
+        let wanted = (0..STREAM_PAGES - 1)
+            .map(|ahead| current + ahead)
+            .chain(current.checked_sub(1));
+
+        for page in wanted.filter(|page| *page < page_count) {
+            let index = page % STREAM_PAGES;
+
+            if self.pages[index].load(Ordering::Relaxed) != page {
+                self.pages[index].store(EMPTY, Ordering::Relaxed);
+                return Some(PageRequest { index, page });
+            }
+        }
+
+        None
+    }
+
+    /// Marks a requested page as loaded, once all of its samples have been written.
+    pub fn complete(&self, request: PageRequest) {
+        self.pages[request.index].store(request.page, Ordering::Release);
+    }
+
+    /// Returns `true` if the whole file fits into the window and has been loaded.
+    pub fn is_complete(&self) -> bool {
+        self.get_page_count() <= STREAM_PAGES && self.get_loaded_pages() == self.get_page_count()
+    }
+
+    /// Returns how far the window has been filled the first time in percent, counting `written`
+    /// samples of the page being loaded as well.
+    pub fn get_progress(&self, written: usize) -> u32 {
+        let needed = self.get_page_count().min(STREAM_PAGES) * self.page_length;
+        let loaded = self.get_loaded_pages() * self.page_length + written;
+
+        match needed {
+            0 => 0,
+            _ => (loaded.min(needed) * 100 / needed) as u32,
+        }
+    }
+
+    fn get_page_count(&self) -> usize {
+        self.len().div_ceil(self.page_length)
+    }
+
+    fn get_loaded_pages(&self) -> usize {
+        self.pages
+            .iter()
+            .filter(|page| page.load(Ordering::Relaxed) != EMPTY)
+            .count()
+    }
+}
+
+/// Request and state of loading a sample file, shared between the control, the display and the
+/// idle task.
+///
+/// A load reads a WAV file from the SD card into a slot, converted to the engine rate with the
+/// requested quality. Files which are longer than the slot are streamed, the load counts as
+/// finished once the window has been filled the first time.
+pub struct SampleLoad {
+    requested: AtomicBool,
+    slot: AtomicUsize,
+    number: AtomicU32,
+    quality: AtomicU8,
+    progress: AtomicU32,
+    running: AtomicBool,
+}
+
+impl SampleLoad {
+    pub const fn new() -> Self {
+        SampleLoad {
+            requested: AtomicBool::new(false),
+            slot: AtomicUsize::new(0),
+            number: AtomicU32::new(0),
+            quality: AtomicU8::new(0),
+            progress: AtomicU32::new(0),
+            running: AtomicBool::new(false),
+        }
+    }
+
+    /// Requests loading the sample file with `number` into `slot`. Ignored while loading.
+    pub fn request(&self, slot: usize, number: u32, quality: ResampleQuality) -> bool {
+        if self.is_running() {
+            return false;
+        }
+
+        self.slot.store(slot, Ordering::Relaxed);
+        self.number.store(number, Ordering::Relaxed);
+        self.quality.store(quality as u8, Ordering::Relaxed);
+        self.progress.store(0, Ordering::Relaxed);
+        self.requested.store(true, Ordering::Release);
+
+        true
+    }
+
+    /// Takes a pending request and marks the load as running. Returns slot, file number and
+    /// quality of the conversion.
+    pub fn take_request(&self) -> Option<(usize, u32, ResampleQuality)> {
+        if self.requested.swap(false, Ordering::Acquire) {
+            self.running.store(true, Ordering::Relaxed);
+
+            Some((
+                self.slot.load(Ordering::Relaxed),
+                self.number.load(Ordering::Relaxed),
+                ResampleQuality::from_u8(self.quality.load(Ordering::Relaxed)),
+            ))
+        } else {
+            None
+        }
+    }
+
+    pub fn set_progress(&self, percentage: u32) {
+        self.progress.store(percentage, Ordering::Relaxed);
+    }
+
+    /// Returns the progress in percent.
+    pub fn get_progress(&self) -> u32 {
+        self.progress.load(Ordering::Relaxed)
+    }
+
+    pub fn finish(&self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed) || self.requested.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for SampleLoad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub static LOAD: SampleLoad = SampleLoad::new();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loads every page which gets requested.
+    fn load_all(table: &PageTable) -> Vec<PageRequest> {
+        core::iter::from_fn(|| table.next_request())
+            .inspect(|request| table.complete(*request))
+            .collect()
+    }
+
+    #[test]
+    fn unloaded_pages_map_to_nothing() {
+        let table = PageTable::new(100);
+        table.start(1000);
+
+        assert_eq!(table.map_offset(0.0), None);
+        assert_eq!(table.get_progress(0), 0);
+    }
+
+    #[test]
+    fn loads_the_pages_ahead_first() {
+        let table = PageTable::new(100);
+        table.start(2000);
+        table.set_position(350);
+
+        let requests = load_all(&table);
+        let pages: Vec<usize> = requests.iter().map(|request| request.page).collect();
+
+        assert_eq!(pages, [3, 4, 5, 6, 7, 8, 9, 2]);
+        assert!(requests
+            .iter()
+            .all(|request| request.index == request.page % STREAM_PAGES));
+        assert_eq!(table.get_progress(0), 100);
+    }
+
+    #[test]
+    fn maps_the_file_onto_the_window() {
+        let table = PageTable::new(100);
+        table.start(2000);
+        table.set_position(1000);
+        load_all(&table);
+
+        // sample 1050 of the file lives in page 2 of the window
+        assert_eq!(table.map_offset(1050.0 / 2000.0), Some(250.0 / 800.0));
+        // the start of the file has been rotated out of the window
+        assert_eq!(table.map_offset(0.0), None);
+    }
+
+    #[test]
+    fn moving_on_replaces_the_pages_behind() {
+        let table = PageTable::new(100);
+        table.start(2000);
+        table.set_position(100);
+        load_all(&table);
+        assert!(table.map_offset(0.0).is_some());
+
+        table.set_position(300);
+        let request = table.next_request().unwrap();
+
+        assert_eq!(request, PageRequest { index: 0, page: 8 });
+        // the page gets marked as empty until it is loaded
+        assert_eq!(table.map_offset(0.0), None);
+    }
+
+    #[test]
+    fn short_file_completes() {
+        let table = PageTable::new(100);
+        table.start(250);
+
+        assert!(!table.is_complete());
+        assert_eq!(load_all(&table).len(), 3);
+        assert!(table.is_complete());
+        assert_eq!(table.get_progress(0), 100);
+    }
+
+    #[test]
+    fn progress_counts_the_page_being_loaded() {
+        let table = PageTable::new(100);
+        table.start(400);
+
+        let first = table.next_request().unwrap();
+        table.complete(first);
+
+        assert_eq!(table.get_progress(50), 37);
+    }
+
+    #[test]
+    fn load_is_requested_once() {
+        let load = SampleLoad::new();
+
+        assert!(load.request(2, 7, ResampleQuality::Polyphase));
+        assert!(!load.request(3, 8, ResampleQuality::Linear));
+        assert_eq!(
+            load.take_request(),
+            Some((2, 7, ResampleQuality::Polyphase))
+        );
+        assert_eq!(load.take_request(), None);
+        assert!(load.is_running());
+
+        load.finish();
+        assert!(!load.is_running());
+    }
+}
@@ -0,0 +1,236 @@
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+
+use micromath::F32Ext;
+
+use crate::tempo::{NoteValue, NOTE_VALUES};
+use crate::timecode::TimeText;
+
+/// Change of a normalized parameter which counts as turning it, smaller ones are ADC noise
+const CHANGE_THRESHOLD: f32 = 0.005;
+/// Stored before the first update, so start up does not count as a change
+const UNSET: u32 = 0x7fc0_0000;
+/// Stored while grain size and delay are not given in note values
+const NO_NOTE_VALUES: u8 = u8::MAX;
+
+/// How the grain engine maps its normalized grain size and pitch, also to show them in real units.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct StretchRanges {
+    pub min_grain_in_ms: f32,
+    pub max_grain_in_ms: f32,
+    /// Shift at either end of the pitch parameter
+    pub pitch_range_in_semitones: f32,
+}
+
+impl StretchRanges {
+    /// Grain length in ms, spread exponentially between the shortest and the longest grain.
+    pub fn grain_in_ms(&self, grain_size: f32) -> f32 {
+        let ratio = self.max_grain_in_ms / self.min_grain_in_ms;
+
+        self.min_grain_in_ms * ratio.powf(grain_size.clamp(0.0, 1.0))
+    }
+
+    /// Normalized grain size of a grain `ms` long, the inverse of `grain_in_ms`.
+    pub fn grain_size(&self, ms: f32) -> f32 {
+        let ratio = self.max_grain_in_ms / self.min_grain_in_ms;
+
+        ((ms / self.min_grain_in_ms).ln() / ratio.ln()).clamp(0.0, 1.0)
+    }
+
+    /// Pitch shift in semitones, the middle position leaves the pitch alone.
+    pub fn semitones(&self, pitch: f32) -> f32 {
+        (pitch.clamp(0.0, 1.0) * 2.0 - 1.0) * self.pitch_range_in_semitones
+    }
+
+    /// Normalized pitch which shifts by `semitones`, the inverse of `semitones`.
+    pub fn pitch(&self, semitones: f32) -> f32 {
+        ((semitones / self.pitch_range_in_semitones + 1.0) * 0.5).clamp(0.0, 1.0)
+    }
+}
+
+/// Speed at which grains play back their audio when shifted by `semitones`.
+pub fn speed_from_semitones(semitones: f32) -> f32 {
+    2.0.powf(semitones / 12.0)
+}
+
+/// Grain size and pitch as last set by the control task, so the display can preview them while
+/// they are being turned. Also holds the note values of grain size and delay while they are given
+/// in note values.
+pub struct StretchPreview {
+    grain_size: AtomicU32,
+    pitch: AtomicU32,
+    /// Indices of the grain and the delay note value in one byte, so they are read together
+    note_values: AtomicU8,
+    changed: AtomicBool,
+}
+
+impl StretchPreview {
+    pub const fn new() -> Self {
+        StretchPreview {
+            grain_size: AtomicU32::new(UNSET),
+            pitch: AtomicU32::new(UNSET),
+            note_values: AtomicU8::new(NO_NOTE_VALUES),
+            changed: AtomicBool::new(false),
+        }
+    }
+
+    pub fn get_grain_size(&self) -> f32 {
+        f32::from_bits(self.grain_size.load(Ordering::Relaxed))
+    }
+
+    pub fn get_pitch(&self) -> f32 {
+        f32::from_bits(self.pitch.load(Ordering::Relaxed))
+    }
+
+    /// Takes the latest values and marks them changed if either one got turned.
+    pub fn publish(&self, grain_size: f32, pitch: f32) {
+        // the first values only set the reference
+        let first = self.get_grain_size().is_nan();
+
+        if first
+            || (grain_size - self.get_grain_size()).abs() > CHANGE_THRESHOLD
+            || (pitch - self.get_pitch()).abs() > CHANGE_THRESHOLD
+        {
+            self.changed.store(!first, Ordering::Relaxed);
+
+            self.grain_size
+                .store(grain_size.to_bits(), Ordering::Relaxed);
+            self.pitch.store(pitch.to_bits(), Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the note values of grain size and delay, `None` while they are set in ms.
+    pub fn get_note_values(&self) -> Option<(NoteValue, NoteValue)> {
+        match self.note_values.load(Ordering::Relaxed) {
+            NO_NOTE_VALUES => None,
+            packed => Some((
+                NOTE_VALUES[(packed >> 4) as usize],
+                NOTE_VALUES[(packed & 0x0f) as usize],
+            )),
+        }
+    }
+
+    /// Takes the note values of grain size and delay and marks them changed if either one did.
+    pub fn publish_note_values(&self, note_values: Option<(NoteValue, NoteValue)>) {
+        let packed = match note_values {
+            Some((grain, delay)) => ((grain as u8) << 4) | delay as u8,
+            None => NO_NOTE_VALUES,
+        };
+
+        if self.note_values.swap(packed, Ordering::Relaxed) != packed {
+            self.changed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns `true` once after grain size, pitch or a note value have been changed.
+    pub fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::Relaxed)
+    }
+}
+
+impl Default for StretchPreview {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Writes `value` rounded to `decimals` places, with a leading `+` for positive values if `sign`
+/// is set. Works on integers, which keeps the float formatting of `core` out of the firmware.
+pub fn write_fixed(text: &mut TimeText, value: f32, decimals: u32, sign: bool) {
+    let scale = 10u32.pow(decimals);
+    let scaled = (value.abs() * scale as f32).round() as u32;
+
+    // the texts always fit
+    if value < 0.0 && scaled > 0 {
+        let _ = text.write_str("-");
+    } else if sign {
+        let _ = text.write_str("+");
+    }
+
+    let _ = write!(text, "{}", scaled / scale);
+
+    if decimals > 0 {
+        let _ = write!(
+            text,
+            ".{:0width$}",
+            scaled % scale,
+            width = decimals as usize
+        );
+    }
+}
+
+/// Formats a grain length, e.g. `120.5 ms`.
+pub fn format_grain(ms: f32) -> TimeText {
+    let mut text = TimeText::new();
+
+    write_fixed(&mut text, ms, 1, false);
+    let _ = text.write_str(" ms");
+
+    text
+}
+
+/// Formats a pitch shift, e.g. `+7.0 st`.
+pub fn format_semitones(semitones: f32) -> TimeText {
+    let mut text = TimeText::new();
+
+    write_fixed(&mut text, semitones, 1, true);
+    let _ = text.write_str(" st");
+
+    text
+}
+
+/// Formats a playback speed, e.g. `1.50x`.
+pub fn format_speed(speed: f32) -> TimeText {
+    let mut text = TimeText::new();
+
+    write_fixed(&mut text, speed, 2, false);
+    let _ = text.write_str("x");
+
+    text
+}
+
+/// Grain size and pitch of the granulator which plays the active buffer
+pub static STRETCH_PREVIEW: StretchPreview = StretchPreview::new();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RANGES: StretchRanges = StretchRanges {
+        min_grain_in_ms: 10.0,
+        max_grain_in_ms: 1000.0,
+        pitch_range_in_semitones: 24.0,
+    };
+
+    #[test]
+    fn grain_size_inverts_the_grain_length() {
+        for grain_size in [0.0, 0.25, 0.5, 1.0] {
+            let ms = RANGES.grain_in_ms(grain_size);
+
+            assert!((RANGES.grain_size(ms) - grain_size).abs() < 1e-3);
+        }
+
+        assert_eq!(RANGES.grain_size(1.0), 0.0);
+        assert_eq!(RANGES.grain_size(5000.0), 1.0);
+    }
+
+    #[test]
+    fn note_values_get_published_together() {
+        let preview = StretchPreview::new();
+
+        assert_eq!(preview.get_note_values(), None);
+
+        preview.publish_note_values(Some((NoteValue::Half, NoteValue::Sixteenth)));
+
+        assert!(preview.take_changed());
+        assert_eq!(
+            preview.get_note_values(),
+            Some((NoteValue::Half, NoteValue::Sixteenth))
+        );
+
+        // the same note values again are no change
+        preview.publish_note_values(Some((NoteValue::Half, NoteValue::Sixteenth)));
+
+        assert!(!preview.take_changed());
+    }
+}
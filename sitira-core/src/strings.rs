@@ -0,0 +1,255 @@
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::mapping::Parameter;
+use crate::menu::MenuItem;
+
+/// Languages the interface can be shown in.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Language {
+    English,
+}
+
+/// Every language, indexed by its discriminant
+const LANGUAGES: [Language; 1] = [Language::English];
+
+/// Every text of the interface which is not a value.
+///
+/// The screen and the menu only refer to these keys, the words come from the table of the
+/// selected language. Every table is an exhaustive match, so a new key can not be forgotten in
+/// any translation.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UiText {
+    Splash,
+    Menu(MenuItem),
+    Parameter(Parameter),
+    GrainsPage,
+    ShapePage,
+    SpreadPage,
+    EnginePage,
+    SecondStreamPage,
+    Curve,
+    Kit,
+    KitOn,
+    KitOff,
+    PadLabel,
+    SlotLabel,
+    NoteLabel,
+    GateLabel,
+    BurstLabel,
+    PositionLabel,
+    LengthLabel,
+    GainLabel,
+    DensityLabel,
+    CloudPitchLabel,
+    LoadLabel,
+    GrainLabel,
+    PitchLabel,
+    SpeedLabel,
+    DelayLabel,
+    ShiftLabel,
+    CardErrorLabel,
+    InputMeter,
+    RecordMeter,
+    OutputMeter,
+    CalibrationCenter,
+    CalibrationRange,
+    EraseConfirmation,
+    Bouncing,
+    Exporting,
+    Erasing,
+    Loading,
+    LearningCc,
+    NoCc,
+    DarkTheme,
+    LightTheme,
+    HighContrastTheme,
+}
+
+/// Returns the text of a key in the selected language.
+pub fn get(text: UiText) -> &'static str {
+    match get_language() {
+        Language::English => english(text),
+    }
+}
+
+pub fn get_language() -> Language {
+    LANGUAGES
+        .get(LANGUAGE.load(Ordering::Relaxed) as usize)
+        .copied()
+        .unwrap_or(Language::English)
+}
+
+/// Selects the language of every text, the display shows it once it gets redrawn.
+pub fn set_language(language: Language) {
+    LANGUAGE.store(language as u8, Ordering::Relaxed);
+}
+
+fn english(text: UiText) -> &'static str {
+    match text {
+        UiText::Splash => "Sitira Synth\nby Max Genson\n\nWritten in Rust",
+        UiText::Menu(item) => match item {
+            MenuItem::OffsetFine => "Offset Scrub",
+            MenuItem::Parameters => "Parameters",
+            MenuItem::GrainView => "Grain View",
+            MenuItem::Spectrum => "Spectrum",
+            MenuItem::GrainCount => "Grains",
+            MenuItem::RotationDivision => "Rotation",
+            MenuItem::Slot => "Slot",
+            MenuItem::EraseSlot => "Erase Slot",
+            MenuItem::SecondSlot => "Stream 2 Slot",
+            MenuItem::EditZoom => "Zoom",
+            MenuItem::EditScroll => "Scroll",
+            MenuItem::TrimStart => "Trim Start",
+            MenuItem::TrimEnd => "Trim End",
+            MenuItem::TrimReset => "Reset Trim",
+            MenuItem::BounceLength => "Bounce Length",
+            MenuItem::Bounce => "Bounce",
+            MenuItem::CurveChannel => "Curve Channel",
+            MenuItem::CurvePoints => "Curve Points",
+            MenuItem::CurvePoint => "Curve Point",
+            MenuItem::CurveInput => "Point Input",
+            MenuItem::CurveOutput => "Point Output",
+            MenuItem::CurveReset => "Curve Reset",
+            MenuItem::Scene => "Scene",
+            MenuItem::SceneStore => "Store Scene",
+            MenuItem::MorphSceneA => "Morph A",
+            MenuItem::MorphSceneB => "Morph B",
+            MenuItem::MorphSource => "Morph CV",
+            MenuItem::SceneGates => "Scene Gates",
+            MenuItem::SceneGlide => "Scene Glide",
+            MenuItem::Automate => "Automate",
+            MenuItem::AutomationBlend => "Auto Blend",
+            MenuItem::AutomationClear => "Clear Auto",
+            MenuItem::InputTrim => "Input Trim",
+            MenuItem::RecordSync => "Record Sync",
+            MenuItem::LoopQuantize => "Loop Quantize",
+            MenuItem::Metronome => "Metronome",
+            MenuItem::LiveGranulation => "Live Mode",
+            MenuItem::PreRoll => "Pre-Roll",
+            MenuItem::Looper => "Looper",
+            MenuItem::LoopDecay => "Loop Decay",
+            MenuItem::PitchShift => "Pitch Shift",
+            MenuItem::Scale => "Scale",
+            MenuItem::Mode => "Mode",
+            MenuItem::Root => "Root",
+            MenuItem::FineTune => "Fine Tune",
+            MenuItem::GrainSeed => "Seed",
+            MenuItem::Reroll => "Re-roll",
+            MenuItem::Swing => "Swing",
+            MenuItem::Humanize => "Humanize",
+            MenuItem::GrainAttack => "Attack",
+            MenuItem::GrainDecay => "Decay",
+            MenuItem::AttackSpread => "Attack Spread",
+            MenuItem::DecaySpread => "Decay Spread",
+            MenuItem::DelaySync => "Delay Sync",
+            MenuItem::NoteValues => "Note Values",
+            MenuItem::EchoTime => "Echo Time",
+            MenuItem::EchoSync => "Echo Sync",
+            MenuItem::EchoFeedback => "Echo Feedback",
+            MenuItem::EchoMix => "Echo Mix",
+            MenuItem::ReverbSize => "Reverb Size",
+            MenuItem::ReverbMix => "Reverb Mix",
+            MenuItem::TextureCrush => "Crush",
+            MenuItem::TextureDownsample => "Downsample",
+            MenuItem::MacroRoute => "Macro Route",
+            MenuItem::MacroDepth => "Macro Depth",
+            MenuItem::SlewParameter => "Slew Param",
+            MenuItem::SlewRise => "Slew Rise",
+            MenuItem::SlewFall => "Slew Fall",
+            MenuItem::MidiLearn => "MIDI Learn",
+            MenuItem::MidiClear => "Clear CC",
+            MenuItem::ExportFormat => "Export Format",
+            MenuItem::Export => "Export",
+            MenuItem::Sample => "Sample",
+            MenuItem::LoadQuality => "Load Quality",
+            MenuItem::LoadSample => "Load Sample",
+            MenuItem::KitMode => "Kit Mode",
+            MenuItem::KitPad => "Kit Pad",
+            MenuItem::KitSlot => "Pad Slot",
+            MenuItem::KitNote => "Pad Note",
+            MenuItem::KitGate => "Pad Gate",
+            MenuItem::KitBurst => "Pad Burst",
+            MenuItem::KitStore => "Store Pad",
+            MenuItem::SaveSession => "Save Session",
+            MenuItem::LoadSession => "Load Session",
+            MenuItem::RetryCard => "Retry SD",
+            MenuItem::Theme => "Theme",
+        },
+        UiText::Parameter(parameter) => match parameter {
+            Parameter::Offset => "Offset",
+            Parameter::GrainSize => "Grain Size",
+            Parameter::Pitch => "Pitch",
+            Parameter::VarispeedSpeed => "Varispeed",
+            Parameter::PitchSpread => "Pitch Spread",
+            Parameter::OffsetSpread => "Offset Spread",
+            Parameter::EngineBlend => "Engine Blend",
+            Parameter::GrainSizeSpread => "Size Spread",
+            Parameter::Delay => "Delay",
+            Parameter::ActiveGrains => "Grains",
+            Parameter::Envelope => "Envelope",
+            Parameter::Velocity => "Velocity",
+            Parameter::DelaySpread => "Delay Spread",
+            Parameter::WaveSelect => "Shape",
+            Parameter::VelocitySpread => "Velo Spread",
+            Parameter::EchoTime => "Echo Time",
+            Parameter::EchoFeedback => "Echo Feedback",
+            Parameter::EchoMix => "Echo Mix",
+            Parameter::ReverbSize => "Reverb Size",
+            Parameter::ReverbMix => "Reverb Mix",
+            Parameter::TextureCrush => "Crush",
+            Parameter::TextureDownsample => "Downsample",
+            Parameter::SecondOffset => "Offset 2",
+            Parameter::SecondGrainSize => "Grain Size 2",
+            Parameter::SecondPitch => "Pitch 2",
+            Parameter::SecondGrains => "Grains 2",
+            Parameter::DirectCv1 => "CV 1",
+            Parameter::DirectCv2 => "CV 2",
+            Parameter::Macro => "Macro",
+            Parameter::None => "",
+        },
+        UiText::GrainsPage => "Grains",
+        UiText::ShapePage => "Shape",
+        UiText::SpreadPage => "Spread",
+        UiText::EnginePage => "Engine",
+        UiText::SecondStreamPage => "Stream 2",
+        UiText::Curve => "Curve",
+        UiText::Kit => "Kit",
+        UiText::KitOn => "on",
+        UiText::KitOff => "off",
+        UiText::PadLabel => "PAD",
+        UiText::SlotLabel => "SLOT",
+        UiText::NoteLabel => "NOTE",
+        UiText::GateLabel => "GATE",
+        UiText::BurstLabel => "BURST",
+        UiText::PositionLabel => "POS",
+        UiText::LengthLabel => "LEN",
+        UiText::GainLabel => "GAIN",
+        UiText::DensityLabel => "GRN",
+        UiText::CloudPitchLabel => "PIT",
+        UiText::LoadLabel => "CPU",
+        UiText::GrainLabel => "GRAIN",
+        UiText::PitchLabel => "PITCH",
+        UiText::SpeedLabel => "SPEED",
+        UiText::DelayLabel => "DELAY",
+        UiText::ShiftLabel => "SHIFT",
+        UiText::CardErrorLabel => "SD!",
+        UiText::InputMeter => "IN",
+        UiText::RecordMeter => "REC",
+        UiText::OutputMeter => "OUT",
+        UiText::CalibrationCenter => "Center all knobs\nunplug all CVs\n\nclick to continue",
+        UiText::CalibrationRange => "Turn every knob\nfully left and right\n\nclick to finish",
+        UiText::EraseConfirmation => "Erase this slot?\n\nclick to confirm\nturn to cancel",
+        UiText::Bouncing => "Bouncing",
+        UiText::Exporting => "Exporting",
+        UiText::Erasing => "Erasing",
+        UiText::Loading => "Loading",
+        UiText::LearningCc => "Send a CC",
+        UiText::NoCc => "No CC",
+        UiText::DarkTheme => "Dark",
+        UiText::LightTheme => "Light",
+        UiText::HighContrastTheme => "High Contrast",
+    }
+}
+
+/// Language of the interface
+static LANGUAGE: AtomicU8 = AtomicU8::new(Language::English as u8);
@@ -0,0 +1,244 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::clock;
+
+/// Shortest interval between taps which is accepted, in ms (300 BPM)
+const MIN_TAP_INTERVAL_IN_MS: u32 = 200;
+/// Longest interval between taps, in ms. A longer pause starts a new tempo.
+const MAX_TAP_INTERVAL_IN_MS: u32 = 2000;
+/// Number of the latest tap intervals which get averaged
+const TAP_HISTORY: usize = 3;
+/// Relative deviation from the average above which a tap starts over with its own interval
+const TAP_TOLERANCE: f32 = 0.25;
+
+/// Note values times can snap to, in beats: from sixteenths to whole notes with the triplets
+/// and dotted values in between
+const DIVISIONS_IN_BEATS: [f32; 10] = [
+    0.25,
+    1.0 / 3.0,
+    0.5,
+    2.0 / 3.0,
+    0.75,
+    1.0,
+    4.0 / 3.0,
+    1.5,
+    2.0,
+    4.0,
+];
+
+/// Note values grain size and delay can be set in, a quarter note lasts one beat.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NoteValue {
+    ThirtySecond,
+    Sixteenth,
+    Eighth,
+    Quarter,
+    Half,
+}
+
+/// Every note value from the shortest to the longest, indexed by its discriminant
+pub const NOTE_VALUES: [NoteValue; 5] = [
+    NoteValue::ThirtySecond,
+    NoteValue::Sixteenth,
+    NoteValue::Eighth,
+    NoteValue::Quarter,
+    NoteValue::Half,
+];
+
+impl NoteValue {
+    /// Note value a normalized parameter picks, the note values split its range evenly.
+    pub fn from_parameter(value: f32) -> Self {
+        let index = (value.clamp(0.0, 1.0) * NOTE_VALUES.len() as f32) as usize;
+
+        NOTE_VALUES[index.min(NOTE_VALUES.len() - 1)]
+    }
+
+    pub fn get_beats(&self) -> f32 {
+        match self {
+            NoteValue::ThirtySecond => 0.125,
+            NoteValue::Sixteenth => 0.25,
+            NoteValue::Eighth => 0.5,
+            NoteValue::Quarter => 1.0,
+            NoteValue::Half => 2.0,
+        }
+    }
+
+    /// Length of the note value at the beat `period`, in the same unit.
+    pub fn get_length(&self, period: f32) -> f32 {
+        self.get_beats() * period
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NoteValue::ThirtySecond => "1/32",
+            NoteValue::Sixteenth => "1/16",
+            NoteValue::Eighth => "1/8",
+            NoteValue::Quarter => "1/4",
+            NoteValue::Half => "1/2",
+        }
+    }
+}
+
+/// Where the beat period comes from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TempoSource {
+    /// The external clock, its edges mark the beats
+    Clock,
+    /// Tapped on the panel, the beats are counted out internally
+    Tap,
+}
+
+/// Turns taps into a beat period.
+///
+/// The latest intervals get averaged, so the tempo settles while tapping on. A tap far off the
+/// average replaces the history, which lets the tempo change without waiting for a pause.
+pub struct TapTempo {
+    last_tap: Option<u32>,
+    intervals: [u32; TAP_HISTORY],
+    count: usize,
+}
+
+impl TapTempo {
+    pub fn new() -> Self {
+        TapTempo {
+            last_tap: None,
+            intervals: [0; TAP_HISTORY],
+            count: 0,
+        }
+    }
+
+    /// Registers a tap at `time_in_ms` and returns the averaged beat period in ms, `None` until
+    /// two taps were close enough.
+    pub fn tap(&mut self, time_in_ms: u32) -> Option<u32> {
+        let interval = match self.last_tap {
+            Some(last) => time_in_ms.wrapping_sub(last),
+            None => {
+                self.last_tap = Some(time_in_ms);
+                return None;
+            }
+        };
+
+        // a tap right after the last one is bounce
+        if interval < MIN_TAP_INTERVAL_IN_MS {
+            return None;
+        }
+
+        self.last_tap = Some(time_in_ms);
+
+        if interval > MAX_TAP_INTERVAL_IN_MS {
+            self.count = 0;
+            return None;
+        }
+
+        if self.count > 0 {
+            let average = self.average();
+
+            if interval.abs_diff(average) as f32 > average as f32 * TAP_TOLERANCE {
+                self.count = 0;
+            }
+        }
+
+        self.intervals.copy_within(0..TAP_HISTORY - 1, 1);
+        self.intervals[0] = interval;
+        self.count = (self.count + 1).min(TAP_HISTORY);
+
+        Some(self.average())
+    }
+
+    fn average(&self) -> u32 {
+        self.intervals[..self.count].iter().sum::<u32>() / self.count as u32
+    }
+}
+
+impl Default for TapTempo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Sets the tapped beat period in samples, `0` forgets it.
+pub fn set_tapped_period(period: usize) {
+    TAPPED_PERIOD.store(period, Ordering::Relaxed);
+}
+
+/// Returns the beat period in samples and its source, the external clock wins over tapping.
+pub fn get() -> Option<(usize, TempoSource)> {
+    match (clock::get_period(), TAPPED_PERIOD.load(Ordering::Relaxed)) {
+        (Some(period), _) => Some((period, TempoSource::Clock)),
+        (None, 0) => None,
+        (None, period) => Some((period, TempoSource::Tap)),
+    }
+}
+
+/// Returns the beat period in samples, `None` if there is no tempo.
+pub fn get_period() -> Option<usize> {
+    get().map(|(period, _)| period)
+}
+
+/// Snaps a time to the nearest note value of the beat `period`, in the same unit. Only note
+/// values up to `max` are considered, without any the time is returned unchanged.
+///
+/// Nearest is meant musically, so a time in between two note values goes to the one it differs
+/// less from by ratio.
+pub fn snap_to_division(time: f32, period: f32, max: f32) -> f32 {
+    if time <= 0.0 {
+        return time;
+    }
+
+    let mut snapped = time;
+    let mut nearest_ratio = f32::MAX;
+
+    for beats in DIVISIONS_IN_BEATS {
+        let division = beats * period;
+
+        if division > max {
+            break;
+        }
+
+        let ratio = if division > time {
+            division / time
+        } else {
+            time / division
+        };
+
+        if ratio < nearest_ratio {
+            nearest_ratio = ratio;
+            snapped = division;
+        }
+    }
+
+    snapped
+}
+
+/// Beat period which was tapped last
+static TAPPED_PERIOD: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parameters_pick_note_values_evenly() {
+        assert_eq!(NoteValue::from_parameter(0.0), NoteValue::ThirtySecond);
+        assert_eq!(NoteValue::from_parameter(0.3), NoteValue::Sixteenth);
+        assert_eq!(NoteValue::from_parameter(0.5), NoteValue::Eighth);
+        assert_eq!(NoteValue::from_parameter(0.7), NoteValue::Quarter);
+        assert_eq!(NoteValue::from_parameter(1.0), NoteValue::Half);
+    }
+
+    #[test]
+    fn note_values_last_their_share_of_the_beat() {
+        // 120 BPM
+        assert_eq!(NoteValue::ThirtySecond.get_length(500.0), 62.5);
+        assert_eq!(NoteValue::Quarter.get_length(500.0), 500.0);
+        assert_eq!(NoteValue::Half.get_length(500.0), 1000.0);
+    }
+
+    #[test]
+    fn times_snap_to_the_nearest_division() {
+        assert_eq!(snap_to_division(480.0, 500.0, 1000.0), 500.0);
+        assert_eq!(snap_to_division(130.0, 500.0, 1000.0), 125.0);
+        // longer divisions than the maximum are left out
+        assert_eq!(snap_to_division(1900.0, 500.0, 1000.0), 1000.0);
+    }
+}
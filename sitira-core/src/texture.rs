@@ -0,0 +1,72 @@
+use micromath::F32Ext;
+
+/// Bit depth at the lowest crush setting
+const MAX_BITS: f32 = 16.0;
+/// Bit depth at the highest crush setting
+const MIN_BITS: f32 = 2.0;
+/// Sample rate reduction at the highest downsample setting
+const MAX_DOWNSAMPLE: f32 = 32.0;
+
+/// Lo-fi stage with bit crushing and sample rate reduction on the master bus.
+///
+/// With both controls at zero the stage is bypassed and costs nothing.
+pub struct Texture {
+    /// Quantization steps per unit, `0.0` when crushing is off
+    levels: f32,
+    /// Advance of the hold phase per sample, `1.0` when downsampling is off
+    rate: f32,
+    phase: f32,
+    held: f32,
+}
+
+impl Texture {
+    pub fn new() -> Self {
+        Texture {
+            levels: 0.0,
+            rate: 1.0,
+            phase: 0.0,
+            held: 0.0,
+        }
+    }
+
+    /// Sets the crush amount between `0.0` (off) and `1.0` (two bits).
+    pub fn set_crush(&mut self, crush: f32) {
+        self.levels = if crush > 0.0 {
+            let bits = MAX_BITS - (MAX_BITS - MIN_BITS) * crush.min(1.0);
+            2.0_f32.powf(bits - 1.0)
+        } else {
+            0.0
+        };
+    }
+
+    /// Sets the sample rate reduction between `0.0` (off) and `1.0`.
+    pub fn set_downsample(&mut self, downsample: f32) {
+        self.rate = 1.0 / (1.0 + (MAX_DOWNSAMPLE - 1.0) * downsample.clamp(0.0, 1.0));
+    }
+
+    pub fn is_bypassed(&self) -> bool {
+        self.levels == 0.0 && self.rate >= 1.0
+    }
+
+    pub fn process(&mut self, sample: f32) -> f32 {
+        self.phase += self.rate;
+
+        // the input is only sampled once the phase wraps around
+        if self.phase >= 1.0 {
+            self.phase -= 1.0;
+            self.held = if self.levels > 0.0 {
+                (sample * self.levels).round() / self.levels
+            } else {
+                sample
+            };
+        }
+
+        self.held
+    }
+}
+
+impl Default for Texture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
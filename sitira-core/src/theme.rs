@@ -0,0 +1,144 @@
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use embedded_graphics::pixelcolor::{Rgb565, RgbColor, WebColors};
+
+use crate::strings::{self, UiText};
+
+/// File on the SD card which keeps the selected theme
+pub const THEME_NAME: &str = "THEME.CFG";
+
+/// Colors the interface is drawn with.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Palette {
+    pub background: Rgb565,
+    /// Text and values
+    pub foreground: Rgb565,
+    /// Secondary text, grid lines and unselected markers
+    pub muted: Rgb565,
+    /// Waveform and curves
+    pub accent: Rgb565,
+    /// Whatever is selected or being changed
+    pub highlight: Rgb565,
+}
+
+/// Color schemes of the display.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Theme {
+    Dark,
+    Light,
+    /// Pure colors on black, for bright light or weak eyes
+    HighContrast,
+}
+
+/// Every theme, indexed by its discriminant
+const THEMES: [Theme; 3] = [Theme::Dark, Theme::Light, Theme::HighContrast];
+
+impl Theme {
+    pub fn name(&self) -> &'static str {
+        strings::get(match self {
+            Theme::Dark => UiText::DarkTheme,
+            Theme::Light => UiText::LightTheme,
+            Theme::HighContrast => UiText::HighContrastTheme,
+        })
+    }
+
+    pub fn get_palette(&self) -> Palette {
+        match self {
+            Theme::Dark => Palette {
+                background: Rgb565::BLACK,
+                foreground: Rgb565::WHITE,
+                muted: Rgb565::CSS_DARK_GRAY,
+                accent: Rgb565::CSS_VIOLET,
+                highlight: Rgb565::CSS_ORANGE,
+            },
+            Theme::Light => Palette {
+                background: Rgb565::WHITE,
+                foreground: Rgb565::BLACK,
+                muted: Rgb565::CSS_GRAY,
+                accent: Rgb565::CSS_DARK_VIOLET,
+                highlight: Rgb565::CSS_DARK_ORANGE,
+            },
+            Theme::HighContrast => Palette {
+                background: Rgb565::BLACK,
+                foreground: Rgb565::WHITE,
+                muted: Rgb565::CSS_LIGHT_GRAY,
+                accent: Rgb565::CYAN,
+                highlight: Rgb565::YELLOW,
+            },
+        }
+    }
+
+    /// Steps through the themes, wrapping around at either end.
+    pub fn step(&self, steps: i32) -> Self {
+        THEMES[(*self as i32 + steps).rem_euclid(THEMES.len() as i32) as usize]
+    }
+
+    /// Returns the theme of a byte of the theme file, `None` if it is no theme.
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        THEMES.get(byte as usize).copied()
+    }
+
+    pub fn to_byte(&self) -> u8 {
+        *self as u8
+    }
+}
+
+/// The selected theme, shared between the control, the display and the idle task.
+///
+/// A theme selected in the menu marks the screen for a repaint and the file for saving, a theme
+/// restored from the file only does the former.
+pub struct ThemeSelection {
+    theme: AtomicU8,
+    changed: AtomicBool,
+    unsaved: AtomicBool,
+}
+
+impl ThemeSelection {
+    pub const fn new() -> Self {
+        ThemeSelection {
+            theme: AtomicU8::new(Theme::Dark as u8),
+            changed: AtomicBool::new(false),
+            unsaved: AtomicBool::new(false),
+        }
+    }
+
+    pub fn get(&self) -> Theme {
+        Theme::from_byte(self.theme.load(Ordering::Relaxed)).unwrap_or(Theme::Dark)
+    }
+
+    pub fn get_palette(&self) -> Palette {
+        self.get().get_palette()
+    }
+
+    /// Selects a theme, which gets saved.
+    pub fn select(&self, theme: Theme) {
+        self.restore(theme);
+        self.unsaved.store(true, Ordering::Relaxed);
+    }
+
+    /// Selects a theme which was read from the SD card.
+    pub fn restore(&self, theme: Theme) {
+        self.theme.store(theme.to_byte(), Ordering::Relaxed);
+        self.changed.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns `true` once after the theme changed, the screen needs a repaint then.
+    pub fn take_changed(&self) -> bool {
+        self.changed.swap(false, Ordering::Relaxed)
+    }
+
+    /// Returns the selected theme once after it changed in the menu.
+    pub fn take_unsaved(&self) -> Option<Theme> {
+        self.unsaved
+            .swap(false, Ordering::Relaxed)
+            .then(|| self.get())
+    }
+}
+
+impl Default for ThemeSelection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub static THEME: ThemeSelection = ThemeSelection::new();
@@ -0,0 +1,95 @@
+use core::fmt::Write;
+
+/// Longest text a position can be formatted to
+const MAX_TEXT_LENGTH: usize = 16;
+
+/// How buffer positions are presented to the user.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TimeFormat {
+    /// `s.mmm s` below a minute, `m:ss.mmm` above
+    Seconds,
+    /// `bar.beat.sixteenth`, all counted from 1
+    Bars { bpm: f32, beats_per_bar: u8 },
+}
+
+/// Formatted position which lives on the stack.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TimeText {
+    bytes: [u8; MAX_TEXT_LENGTH],
+    len: usize,
+}
+
+impl TimeText {
+    pub const fn new() -> Self {
+        TimeText {
+            bytes: [0; MAX_TEXT_LENGTH],
+            len: 0,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only whole `str`s get written
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+impl Default for TimeText {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for TimeText {
+    fn write_str(&mut self, text: &str) -> core::fmt::Result {
+        let end = self.len + text.len();
+
+        if end > MAX_TEXT_LENGTH {
+            return Err(core::fmt::Error);
+        }
+
+        self.bytes[self.len..end].copy_from_slice(text.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Converts a position in samples into something users can reason about.
+pub fn format_position(samples: usize, sample_rate: u32, format: TimeFormat) -> TimeText {
+    let mut text = TimeText::new();
+
+    // the texts always fit, so errors are impossible
+    let _ = match format {
+        TimeFormat::Seconds => {
+            let millis = samples as u64 * 1000 / sample_rate as u64;
+            let seconds = millis / 1000;
+
+            if seconds < 60 {
+                write!(text, "{}.{:03} s", seconds, millis % 1000)
+            } else {
+                write!(
+                    text,
+                    "{}:{:02}.{:03}",
+                    seconds / 60,
+                    seconds % 60,
+                    millis % 1000
+                )
+            }
+        }
+        TimeFormat::Bars { bpm, beats_per_bar } => {
+            let samples_per_sixteenth = sample_rate as f32 * 60.0 / bpm.max(1.0) / 4.0;
+            let sixteenths = (samples as f32 / samples_per_sixteenth) as u32;
+            let beats = sixteenths / 4;
+            let beats_per_bar = beats_per_bar.max(1) as u32;
+
+            write!(
+                text,
+                "{}.{}.{}",
+                beats / beats_per_bar + 1,
+                beats % beats_per_bar + 1,
+                sixteenths % 4 + 1
+            )
+        }
+    };
+
+    text
+}
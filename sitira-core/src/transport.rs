@@ -0,0 +1,185 @@
+use crate::event::{Command, Event, Input};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TransportState {
+    Recording,
+    Playing,
+}
+
+/// Reported whenever the transport switches its state.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TransportChange {
+    StartedRecording,
+    StoppedRecording,
+    /// A take which a press started got cancelled, as the press turned into another gesture
+    DiscardedTake,
+}
+
+/// Decides whether incoming audio gets recorded or the recorded buffer gets played back.
+///
+/// Pressing the button toggles between both states right on the press, commands can set them
+/// explicitly. With sync enabled, toggling only arms the transport and the toggle gets executed
+/// on the next gate edge by the audio task. Pressing again before that disarms it.
+///
+/// A press which started a take can still turn into a double click or a hold, which cancel the
+/// take again. The second press of a double click does not toggle.
+pub struct Transport {
+    state: TransportState,
+    sync: bool,
+    armed: bool,
+    /// State and arming before the last press, as long as its gesture is not known yet
+    settling: Option<(TransportState, bool)>,
+}
+
+impl Transport {
+    pub fn new(state: TransportState) -> Self {
+        Transport {
+            state,
+            sync: false,
+            armed: false,
+            settling: None,
+        }
+    }
+
+    pub fn is_sync_enabled(&self) -> bool {
+        self.sync
+    }
+
+    pub fn set_sync(&mut self, sync: bool) {
+        self.sync = sync;
+        self.armed = false;
+        self.settling = None;
+    }
+
+    pub fn is_armed(&self) -> bool {
+        self.armed
+    }
+
+    /// Catches up with a toggle which has been executed on a gate edge.
+    pub fn complete_sync(&mut self) -> Option<TransportChange> {
+        self.armed = false;
+        self.toggle()
+    }
+
+    pub fn get_state(&self) -> TransportState {
+        self.state
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.state == TransportState::Recording
+    }
+
+    /// Consumes an event and returns the resulting state change, if there is any.
+    pub fn handle(&mut self, event: &Event) -> Option<TransportChange> {
+        match event {
+            // the press of the gesture toggled already
+            Event::Pressed(Input::Button) if self.settling.is_some() => None,
+            Event::Pressed(Input::Button) => {
+                self.settling = Some((self.state, self.armed));
+                self.toggle_or_arm()
+            }
+            Event::Click(Input::Button) => {
+                self.settling = None;
+                None
+            }
+            Event::DoubleClick(Input::Button) | Event::Hold(Input::Button) => self.revert_press(),
+            Event::Command(Command::ToggleRecording) => self.toggle_or_arm(),
+            Event::Command(Command::StartRecording) => self.set_state(TransportState::Recording),
+            Event::Command(Command::StopRecording) => self.set_state(TransportState::Playing),
+            _ => None,
+        }
+    }
+
+    fn toggle_or_arm(&mut self) -> Option<TransportChange> {
+        if self.sync {
+            self.armed = !self.armed;
+            None
+        } else {
+            self.toggle()
+        }
+    }
+
+    /// Takes back what the press of a double click or hold did, except for a stopped take.
+    fn revert_press(&mut self) -> Option<TransportChange> {
+        let (state, armed) = self.settling.take()?;
+
+        self.armed = armed;
+
+        if state == TransportState::Playing && self.is_recording() {
+            self.state = TransportState::Playing;
+            Some(TransportChange::DiscardedTake)
+        } else {
+            None
+        }
+    }
+
+    fn toggle(&mut self) -> Option<TransportChange> {
+        match self.state {
+            TransportState::Recording => self.set_state(TransportState::Playing),
+            TransportState::Playing => self.set_state(TransportState::Recording),
+        }
+    }
+
+    fn set_state(&mut self, state: TransportState) -> Option<TransportChange> {
+        if self.state == state {
+            return None;
+        }
+
+        self.state = state;
+
+        match state {
+            TransportState::Recording => Some(TransportChange::StartedRecording),
+            TransportState::Playing => Some(TransportChange::StoppedRecording),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn press_toggles_right_away() {
+        let mut transport = Transport::new(TransportState::Playing);
+
+        let change = transport.handle(&Event::Pressed(Input::Button));
+        assert_eq!(change, Some(TransportChange::StartedRecording));
+        assert_eq!(transport.handle(&Event::Click(Input::Button)), None);
+
+        let change = transport.handle(&Event::Pressed(Input::Button));
+        assert_eq!(change, Some(TransportChange::StoppedRecording));
+    }
+
+    #[test]
+    fn double_click_discards_the_take_of_its_first_press() {
+        let mut transport = Transport::new(TransportState::Playing);
+
+        transport.handle(&Event::Pressed(Input::Button));
+        assert_eq!(transport.handle(&Event::Pressed(Input::Button)), None);
+
+        let change = transport.handle(&Event::DoubleClick(Input::Button));
+        assert_eq!(change, Some(TransportChange::DiscardedTake));
+        assert!(!transport.is_recording());
+    }
+
+    #[test]
+    fn hold_keeps_a_stopped_take() {
+        let mut transport = Transport::new(TransportState::Recording);
+
+        transport.handle(&Event::Pressed(Input::Button));
+        assert_eq!(transport.handle(&Event::Hold(Input::Button)), None);
+        assert!(!transport.is_recording());
+    }
+
+    #[test]
+    fn hold_disarms_a_synced_press() {
+        let mut transport = Transport::new(TransportState::Playing);
+        transport.set_sync(true);
+
+        transport.handle(&Event::Pressed(Input::Button));
+        assert!(transport.is_armed());
+
+        transport.handle(&Event::Hold(Input::Button));
+        assert!(!transport.is_armed());
+    }
+}
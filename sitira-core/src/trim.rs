@@ -0,0 +1,47 @@
+use core::fmt::Write;
+use core::sync::atomic::{AtomicI32, AtomicU32, Ordering};
+
+use micromath::F32Ext;
+
+use crate::timecode::TimeText;
+
+/// Lowest and highest trim, hot modular signals need the attenuation
+pub const TRIM_RANGE_IN_DB: (i32, i32) = (-24, 12);
+
+/// Trim in whole decibels
+static TRIM_DB: AtomicI32 = AtomicI32::new(0);
+/// Gain applied to the input, stored as `f32` bits
+static TRIM_GAIN: AtomicU32 = AtomicU32::new(0x3F80_0000);
+
+/// Returns the trim in decibels.
+pub fn get_db() -> i32 {
+    TRIM_DB.load(Ordering::Relaxed)
+}
+
+/// Returns the gain the input gets trimmed with before it is monitored or recorded.
+pub fn get_gain() -> f32 {
+    f32::from_bits(TRIM_GAIN.load(Ordering::Relaxed))
+}
+
+/// Sets the trim, it is kept within `TRIM_RANGE_IN_DB`.
+pub fn set_db(db: i32) {
+    let db = db.clamp(TRIM_RANGE_IN_DB.0, TRIM_RANGE_IN_DB.1);
+
+    TRIM_DB.store(db, Ordering::Relaxed);
+    TRIM_GAIN.store(10.0f32.powf(db as f32 / 20.0).to_bits(), Ordering::Relaxed);
+}
+
+/// Steps the trim by whole decibels.
+pub fn step(steps: i32) {
+    set_db(get_db() + steps);
+}
+
+/// Formats a trim, e.g. `-6 dB`.
+pub fn format_trim(db: i32) -> TimeText {
+    let mut text = TimeText::new();
+
+    // the text always fits
+    let _ = write!(text, "{:+} dB", db);
+
+    text
+}
@@ -0,0 +1,87 @@
+/// Sizes the packets of the USB audio stream so they follow the clock of the codec.
+///
+/// The host asks for a packet every millisecond by the clock of the USB, while the codec fills
+/// the queue in between by its own clock, and the two drift apart. The queue is kept around its
+/// target: a packet carries one frame more than a millisecond holds while the queue is above it
+/// and one less while it is below. Until the queue reaches the target, and again after it ran
+/// dry, the packets are silent.
+pub struct RateMatcher {
+    /// Frames of one millisecond
+    nominal: usize,
+    target: usize,
+    primed: bool,
+}
+
+impl RateMatcher {
+    pub fn new(sample_rate: usize, target: usize) -> Self {
+        RateMatcher {
+            nominal: sample_rate / 1000,
+            target,
+            primed: false,
+        }
+    }
+
+    /// Returns the most frames a packet carries.
+    pub fn max_frames(&self) -> usize {
+        self.nominal + 1
+    }
+
+    /// Plans the next packet with `queued` frames waiting, returns how many of them it takes and
+    /// how many frames it carries in all, the rest of them silent.
+    pub fn plan(&mut self, queued: usize) -> (usize, usize) {
+        if !self.primed {
+            self.primed = queued >= self.target;
+
+            return (0, self.nominal);
+        }
+
+        let frames = if queued > self.target + self.nominal {
+            self.nominal + 1
+        } else if queued + self.nominal < self.target {
+            self.nominal - 1
+        } else {
+            self.nominal
+        };
+
+        // a queue which runs dry fills up to the target again
+        if queued < frames {
+            self.primed = false;
+        }
+
+        (frames.min(queued), frames)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waits_for_the_target() {
+        let mut matcher = RateMatcher::new(48000, 96);
+
+        assert_eq!(matcher.plan(50), (0, 48));
+        assert_eq!(matcher.plan(100), (0, 48));
+        assert_eq!(matcher.plan(100), (48, 48));
+
+        // running dry pads the packet and waits again
+        assert_eq!(matcher.plan(30), (30, 47));
+        assert_eq!(matcher.plan(60), (0, 48));
+    }
+
+    #[test]
+    fn follows_a_faster_codec() {
+        let mut matcher = RateMatcher::new(48000, 480);
+        let mut queued = 480;
+
+        matcher.plan(queued);
+
+        // the codec runs 0.1 % fast, 48.048 frames per millisecond
+        for millisecond in 0..100_000 {
+            queued += 48 + usize::from(millisecond % 1000 < 48);
+            queued -= matcher.plan(queued).0;
+
+            assert!(queued.abs_diff(480) <= 2 * 48);
+        }
+    }
+}
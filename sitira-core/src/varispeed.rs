@@ -0,0 +1,118 @@
+use micromath::F32Ext;
+
+/// Fastest playback speed in both directions
+pub const MAX_SPEED: f32 = 2.0;
+
+/// Continuous tape-style loop player.
+///
+/// Reads the same audio buffer as the granulator, but plays it back as one endless loop with
+/// a variable speed between `-MAX_SPEED` and `MAX_SPEED`. Samples in between two buffer
+/// positions are linearly interpolated.
+///
+/// The play head is kept as a sample index and the phase in between it and the next sample, an
+/// `f32` position would lose its fraction far into a long buffer.
+pub struct Varispeed {
+    index: usize,
+    /// Fraction of the way to the next sample, `0.0` to below `1.0`
+    phase: f32,
+    wrapped: bool,
+}
+
+impl Varispeed {
+    pub fn new() -> Self {
+        Varispeed {
+            index: 0,
+            phase: 0.0,
+            wrapped: false,
+        }
+    }
+
+    /// Moves the play head back to the start of the buffer.
+    pub fn reset(&mut self) {
+        self.index = 0;
+        self.phase = 0.0;
+    }
+
+    /// Returns the play head position as sample index and the fraction towards the next one.
+    pub fn get_position(&self) -> (usize, f32) {
+        (self.index, self.phase)
+    }
+
+    /// Returns `true` once after the play head wrapped around either end of the loop.
+    pub fn take_wrapped(&mut self) -> bool {
+        core::mem::replace(&mut self.wrapped, false)
+    }
+
+    /// Returns the next sample of the loop and advances the play head by `speed` samples.
+    pub fn get_next_sample(&mut self, buffer: &[f32], speed: f32) -> f32 {
+        let length = buffer.len();
+
+        if length < 2 {
+            return 0.0;
+        }
+
+        // buffer might have shrunk since the last call
+        if self.index >= length {
+            self.reset();
+        }
+
+        let index = self.index;
+        let next_index = if index + 1 < length { index + 1 } else { 0 };
+
+        let sample = buffer[index] + (buffer[next_index] - buffer[index]) * self.phase;
+
+        self.advance(length, speed);
+
+        sample
+    }
+
+    /// Moves the play head by `speed` samples through a loop of `length` samples without reading
+    /// it, so the loop keeps its place while it is not heard.
+    pub fn advance(&mut self, length: usize, speed: f32) {
+        if length < 2 {
+            return;
+        }
+
+        if self.index >= length {
+            self.reset();
+        }
+
+        // whole samples move the index, the phase keeps the rest
+        let step = self.phase + speed.clamp(-MAX_SPEED, MAX_SPEED);
+        let whole = step.floor();
+
+        self.phase = step - whole;
+
+        // the phase may round up to a whole sample for tiny negative steps
+        let whole = if self.phase >= 1.0 {
+            self.phase = 0.0;
+            whole as isize + 1
+        } else {
+            whole as isize
+        };
+
+        // advance and wrap around in both directions
+        let index = self.index as isize + whole;
+
+        if index >= length as isize {
+            self.index = (index - length as isize) as usize;
+            self.wrapped = true;
+        } else if index < 0 {
+            self.index = (index + length as isize) as usize;
+            self.wrapped = true;
+        } else {
+            self.index = index as usize;
+        }
+    }
+}
+
+impl Default for Varispeed {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Maps a normalized control value (`0.0` to `1.0`) to a playback speed (`-MAX_SPEED` to `MAX_SPEED`).
+pub fn speed_from_normalized(value: f32) -> f32 {
+    (value.clamp(0.0, 1.0) * 2.0 - 1.0) * MAX_SPEED
+}
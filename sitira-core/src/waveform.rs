@@ -0,0 +1,170 @@
+use core::ops::Range;
+
+/// Samples summarized by an entry of the lowest level
+pub const BLOCK_LENGTH: usize = 256;
+/// Entries of a level which get merged into one entry of the level above
+const FAN_OUT: usize = 4;
+/// Levels of a pyramid, an entry of the top one summarizes about 5 s at 48 kHz
+const LEVELS: usize = 6;
+
+/// Lowest and highest sample of a stretch of audio.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Peak {
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Peak {
+    /// Peak of no samples at all, which merges with any other without changing it
+    pub const EMPTY: Peak = Peak {
+        min: f32::MAX,
+        max: f32::MIN,
+    };
+
+    pub fn of(samples: &[f32]) -> Self {
+        samples.iter().fold(Peak::EMPTY, |peak, sample| Peak {
+            min: peak.min.min(*sample),
+            max: peak.max.max(*sample),
+        })
+    }
+
+    pub fn merge(self, other: Peak) -> Self {
+        Peak {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min > self.max
+    }
+
+    /// Returns the larger magnitude of both, 0 for an empty peak.
+    pub fn get_amplitude(&self) -> f32 {
+        if self.is_empty() {
+            0.0
+        } else {
+            self.max.max(-self.min)
+        }
+    }
+}
+
+/// Layout of a min/max pyramid over a buffer of up to `capacity` samples, which lives in memory
+/// handed in by the caller.
+///
+/// Level 0 holds the peak of every `BLOCK_LENGTH` samples, every level above merges `FAN_OUT`
+/// entries of the one below. The levels lie one after another in a single slice of `len()`
+/// entries. A waveform then gets drawn from a few entries of the fitting level per column, no
+/// matter how many samples it shows.
+#[derive(Clone, Copy)]
+pub struct PeakPyramid {
+    capacity: usize,
+}
+
+impl PeakPyramid {
+    pub const fn new(capacity: usize) -> Self {
+        PeakPyramid { capacity }
+    }
+
+    /// Returns the entries of all levels together.
+    pub const fn len(&self) -> usize {
+        self.get_level_start(LEVELS)
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.capacity == 0
+    }
+
+    /// Summarizes the blocks of `range` into the pyramid, `samples` are the valid samples of the
+    /// buffer from its start. Entries beyond them are left out of the levels above, so whatever
+    /// they held before does not show up.
+    pub fn update(&self, peaks: &mut [Peak], samples: &[f32], range: Range<usize>) {
+        let valid = samples.len().min(self.capacity);
+        let mut first = range.start / BLOCK_LENGTH;
+        let mut last = range.end.min(valid).div_ceil(BLOCK_LENGTH);
+
+        for (block, peak) in peaks.iter_mut().enumerate().take(last).skip(first) {
+            let start = block * BLOCK_LENGTH;
+            let end = (start + BLOCK_LENGTH).min(valid);
+
+            *peak = Peak::of(&samples[start..end]);
+        }
+
+        // every level above merges the entries below which changed
+        for level in 1..LEVELS {
+            let below = self.get_level_start(level - 1);
+            let start = self.get_level_start(level);
+            let valid_below = valid.div_ceil(get_entry_length(level - 1));
+
+            first /= FAN_OUT;
+            last = last.div_ceil(FAN_OUT);
+
+            for entry in first..last {
+                let children = entry * FAN_OUT..((entry + 1) * FAN_OUT).min(valid_below);
+
+                peaks[start + entry] = peaks[below + children.start..below + children.end]
+                    .iter()
+                    .fold(Peak::EMPTY, |peak, child| peak.merge(*child));
+            }
+        }
+    }
+
+    /// Fills `columns` with the peaks of `range` split evenly among them. Every column is read
+    /// from the highest level whose entries are not wider than it, each entry counts for the
+    /// column it starts in. Columns narrower than a block get read from `samples`.
+    pub fn query(
+        &self,
+        peaks: &[Peak],
+        samples: &[f32],
+        range: Range<usize>,
+        columns: &mut [Peak],
+    ) {
+        let count = columns.len();
+        let span = range.len();
+
+        for (index, column) in columns.iter_mut().enumerate() {
+            let start = range.start + span * index / count;
+            let end = (range.start + span * (index + 1) / count).max(start + 1);
+
+            let level = (0..LEVELS)
+                .rev()
+                .find(|level| get_entry_length(*level) <= end - start);
+
+            *column = match level {
+                Some(level) => {
+                    let length = get_entry_length(level);
+                    let offset = self.get_level_start(level);
+                    let last = end.div_ceil(length).min(self.get_level_len(level));
+                    let first = start.div_ceil(length).min(last);
+
+                    peaks[offset + first..offset + last]
+                        .iter()
+                        .fold(Peak::EMPTY, |peak, entry| peak.merge(*entry))
+                }
+                None => Peak::of(&samples[start.min(samples.len())..end.min(samples.len())]),
+            };
+        }
+    }
+
+    const fn get_level_len(&self, level: usize) -> usize {
+        self.capacity.div_ceil(get_entry_length(level))
+    }
+
+    /// Returns the first entry of `level`, which is where the levels below it end.
+    const fn get_level_start(&self, level: usize) -> usize {
+        let mut start = 0;
+        let mut below = 0;
+
+        while below < level {
+            start += self.get_level_len(below);
+            below += 1;
+        }
+
+        start
+    }
+}
+
+/// Returns the samples summarized by an entry of `level`.
+const fn get_entry_length(level: usize) -> usize {
+    BLOCK_LENGTH * FAN_OUT.pow(level as u32)
+}
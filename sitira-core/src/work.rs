@@ -0,0 +1,125 @@
+/// How urgent a job is. Jobs of a higher priority get stepped first, lower ones only get a turn
+/// while none of them has any work.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Priority {
+    Low,
+    Normal,
+    High,
+}
+
+/// What a job reports after a step.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Progress {
+    /// Did some work and has more to do
+    Working,
+    /// Has nothing to do right now, so the next job gets the turn
+    Waiting,
+    Done,
+}
+
+/// Background work which is done a small piece at a time, with access to a `context` like the SD
+/// card or shared resources.
+pub trait Job<C> {
+    /// Does a bounded piece of the work, so the loop running the queue stays responsive.
+    fn step(&mut self, context: &mut C) -> Progress;
+
+    /// Called once the job is done or cancelled, e.g. to close its file.
+    fn finish(self, _context: &mut C)
+    where
+        Self: Sized,
+    {
+    }
+}
+
+struct Entry<J> {
+    priority: Priority,
+    job: J,
+}
+
+/// Fixed number of jobs which get worked through cooperatively, meant to be run by the idle task.
+///
+/// Every `run()` steps a single job: the first one of the highest priority which has work, the
+/// jobs of one priority take turns. Jobs are plain values, usually an enum of all kinds of
+/// background work, so the queue needs no allocation.
+pub struct WorkQueue<J, const N: usize> {
+    entries: [Option<Entry<J>>; N],
+    /// Entry stepped last, the turn goes on after it
+    last: usize,
+}
+
+impl<J, const N: usize> WorkQueue<J, N> {
+    pub fn new() -> Self {
+        WorkQueue {
+            entries: [(); N].map(|_| None),
+            last: 0,
+        }
+    }
+
+    /// Queues a job. Returns it back if the queue is full.
+    pub fn push(&mut self, priority: Priority, job: J) -> Result<(), J> {
+        match self.entries.iter_mut().find(|entry| entry.is_none()) {
+            Some(entry) => {
+                *entry = Some(Entry { priority, job });
+                Ok(())
+            }
+            None => Err(job),
+        }
+    }
+
+    /// Steps the job of the highest priority which has work. Returns `false` if none had any.
+    pub fn run<C>(&mut self, context: &mut C) -> bool
+    where
+        J: Job<C>,
+    {
+        for priority in [Priority::High, Priority::Normal, Priority::Low] {
+            for offset in 1..=N {
+                let index = (self.last + offset) % N;
+
+                let progress = match self.entries[index].as_mut() {
+                    Some(entry) if entry.priority == priority => entry.job.step(context),
+                    _ => continue,
+                };
+
+                match progress {
+                    Progress::Waiting => continue,
+                    Progress::Working => (),
+                    Progress::Done => {
+                        if let Some(entry) = self.entries[index].take() {
+                            entry.job.finish(context);
+                        }
+                    }
+                }
+
+                self.last = index;
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// Cancels all jobs `predicate` returns `true` for. Returns how many were cancelled.
+    pub fn cancel<C>(&mut self, context: &mut C, mut predicate: impl FnMut(&J) -> bool) -> usize
+    where
+        J: Job<C>,
+    {
+        let mut cancelled = 0;
+
+        for entry in self.entries.iter_mut() {
+            if matches!(entry, Some(queued) if predicate(&queued.job)) {
+                if let Some(queued) = entry.take() {
+                    queued.job.finish(context);
+                    cancelled += 1;
+                }
+            }
+        }
+
+        cancelled
+    }
+}
+
+impl<J, const N: usize> Default for WorkQueue<J, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,639 @@
+use core::sync::atomic::Ordering;
+
+use rtic::Mutex;
+
+use sitira_core::{
+    bounce::{BounceJob, BOUNCE, BOUNCE_BLOCKS_PER_CALLBACK},
+    event::{Event, Input},
+    grain_stats::{GRAIN_SNAPSHOT, GRAIN_STATS},
+    grains::GrainCloud,
+    kit::KIT_PADS,
+    meter::{INPUT_METER, OUTPUT_METER, RECORD_METER},
+    normalize,
+    pre_roll::SPLICE_FRAMES_PER_CALLBACK,
+    pulse::PulseOutput,
+    record_sync::RECORD_SYNC,
+    rng::GRAIN_SEED,
+    settings::UserSettings,
+    soak::SOAK_MONITOR,
+    spectrum::SPECTRUM,
+    stretch, tempo, trim, varispeed,
+};
+
+use crate::{
+    app::audio_handler,
+    config::{
+        AUDIO_BLOCK_SIZE, AUDIO_SAMPLE_RATE, CLOCK_GATE, CONTROL_RATE_IN_MS, CPU_FREQUENCY_IN_HZ,
+        CV_OUTPUT_GAIN, GATE_PULSE_IN_MS, GRAIN_SIZE_RANGE_IN_MS, LIVE_GAP_IN_MS, METRONOME_LEVEL,
+        RECORD_SYNC_GATE, SOAK_TEST,
+    },
+    control::STRETCH_RANGES,
+    playback::{
+        self, IS_RECORDING, LIVE_GRANULATION, LOOPER, METRONOME, OVERDUB, PITCH_SHIFT, PRE_ROLL,
+        SOURCE,
+    },
+    sample_file::STREAM,
+    sdram::ECHO_MAX_FRAMES,
+    slots::{self, SLOTS, SLOT_LENGTH},
+    watchdog::{self, Task},
+    waveform_cache::WAVEFORMS,
+};
+
+const AUDIO_CALLBACK_INTERVAL: f32 = AUDIO_BLOCK_SIZE as f32 * (1.0 / (AUDIO_SAMPLE_RATE as f32));
+const AUDIO_CALLBACK_CYCLES: u32 = (AUDIO_CALLBACK_INTERVAL * CPU_FREQUENCY_IN_HZ as f32) as u32;
+/// Audio blocks the settings glide over from one control update to the next
+pub const BLOCKS_PER_CONTROL_CYCLE: u32 =
+    (CONTROL_RATE_IN_MS as f32 / 1000.0 / AUDIO_CALLBACK_INTERVAL) as u32;
+const CYCLES_PER_FRAME: u32 = CPU_FREQUENCY_IN_HZ / AUDIO_SAMPLE_RATE as u32;
+const LIVE_GAP_FRAMES: usize = (LIVE_GAP_IN_MS * AUDIO_SAMPLE_RATE as f32 / 1000.0) as usize;
+/// Frames the grains of a kit burst may ring on after it, the longest grain
+pub const KIT_TAIL_FRAMES: usize =
+    (GRAIN_SIZE_RANGE_IN_MS.1 * AUDIO_SAMPLE_RATE as f32 / 1000.0) as usize;
+pub const GATE_PULSE_FRAMES: usize =
+    (GATE_PULSE_IN_MS * AUDIO_SAMPLE_RATE as f32 / 1000.0) as usize;
+
+/// Records and plays one block of audio, renders a running bounce besides.
+pub fn process(mut ctx: audio_handler::Context) {
+    let audio = &mut ctx.local.ar.audio;
+    let mut buffer = ctx.local.ar.buffer;
+    let granulator = ctx.local.granulator;
+    let second_granulator = ctx.local.second_granulator;
+    let kit_voices = ctx.local.kit_voices;
+    let varispeed = ctx.local.varispeed;
+    let mixer = ctx.local.mixer;
+    let output = ctx.local.output;
+    let sdram = ctx.local.sdram;
+    let input_meter = ctx.local.input_meter;
+    let record_meter = ctx.local.record_meter;
+    let output_meter = ctx.local.output_meter;
+    let texture = ctx.local.texture;
+    let echo = ctx.local.echo;
+    let reverb = ctx.local.reverb;
+
+    let callback_start = cortex_m::peripheral::DWT::cycle_count();
+
+    // the samples of this block arrived since the last callback
+    let block_start = core::mem::replace(ctx.local.last_callback_start, callback_start);
+
+    watchdog::beat(Task::Audio);
+
+    audio.get_stereo(&mut buffer);
+
+    if SOAK_TEST {
+        ctx.local.soak_generator.fill(&mut buffer);
+    }
+
+    // meter incoming audio before and after the trim, everything after works on the trimmed
+    // input. The envelope of the recorded channel drives the CV output.
+    let follower = ctx.local.follower;
+    let trim_gain = trim::get_gain();
+
+    for (right, left) in buffer.iter_mut() {
+        input_meter.accumulate(*right);
+        input_meter.accumulate(*left);
+
+        *right *= trim_gain;
+        *left *= trim_gain;
+
+        record_meter.accumulate(*right);
+        record_meter.accumulate(*left);
+        follower.process(*right);
+    }
+    input_meter.publish(&INPUT_METER);
+    record_meter.publish(&RECORD_METER);
+
+    ctx.local
+        .ar
+        .cv_output
+        .set(follower.get_level() * CV_OUTPUT_GAIN);
+
+    // gate edges get placed at the frame they happened at
+    let mut sync_edge = None;
+    let mut clock_edge = None;
+
+    while let Some(timed) = ctx.local.ar.gate_events.pop() {
+        let frame = timed.get_frame(block_start, CYCLES_PER_FRAME, buffer.len());
+
+        if let Event::Pressed(Input::Gate(gate)) = timed.event {
+            if gate == RECORD_SYNC_GATE {
+                sync_edge = sync_edge.or(Some(frame));
+            }
+
+            if gate == CLOCK_GATE {
+                clock_edge = clock_edge.or(Some(frame));
+            }
+        }
+    }
+
+    // the output stays muted as long as the kill gate is high
+    while let Some(timed) = ctx.local.ar.panel_events.pop() {
+        match timed.event {
+            Event::Pressed(Input::KillGate) => *ctx.local.killed = true,
+            Event::Released(Input::KillGate) => *ctx.local.killed = false,
+            _ => (),
+        }
+    }
+
+    ctx.local.clock_follower.process(clock_edge, buffer.len());
+
+    // an armed recording toggle gets executed right on the frame of the gate edge
+    let toggle_frame = match sync_edge {
+        Some(frame) if RECORD_SYNC.fire() => {
+            if IS_RECORDING.load(Ordering::Relaxed) {
+                IS_RECORDING.store(false, Ordering::Relaxed);
+            } else {
+                playback::begin_recording();
+            }
+
+            Some(frame)
+        }
+        _ => None,
+    };
+
+    let is_recording = IS_RECORDING.load(Ordering::Relaxed);
+
+    // the input is monitored while recording, unless it gets granulated live, overdubbed or
+    // shifted in pitch
+    let live = is_recording && LIVE_GRANULATION.load(Ordering::Relaxed);
+    let overdub = OVERDUB.load(Ordering::Relaxed);
+    let shifting = PITCH_SHIFT.load(Ordering::Relaxed);
+    let monitor_input = is_recording && !live && !overdub && !shifting;
+
+    // the input and the output are crossfaded when the monitoring starts or stops
+    let crossfade = ctx.local.crossfade;
+    crossfade.set_monitoring(monitor_input);
+
+    output.set_muted(*ctx.local.killed);
+    output.set_volume(
+        ctx.shared
+            .engine_settings
+            .lock(|settings| settings.master_volume),
+    );
+
+    let active_slot = SLOTS.get_active();
+
+    // frames of this block which belong to the take
+    let take_frames = match (toggle_frame, is_recording) {
+        (Some(frame), true) => frame..buffer.len(),
+        (Some(frame), false) => 0..frame,
+        (None, true) => 0..buffer.len(),
+        (None, false) => 0..0,
+    };
+
+    let take_started = is_recording && !*ctx.local.was_recording;
+
+    // the input around the take is kept for the next one
+    let pre_roll = ctx.local.pre_roll;
+    let keep_pre_roll = PRE_ROLL.load(Ordering::Relaxed);
+
+    if keep_pre_roll {
+        for (right, _) in buffer[..take_frames.start].iter() {
+            pre_roll.push(*right);
+        }
+    } else {
+        pre_roll.clear();
+    }
+
+    // a new take begins with the kept input, which gets copied in over the next callbacks. The
+    // buffer is remembered, a discarded take leaves its slot for the spare buffer.
+    if take_started && keep_pre_roll && !overdub {
+        SOURCE.set_len(pre_roll.begin_splice());
+        *ctx.local.splice_start = slots::get_start(active_slot);
+    }
+
+    if pre_roll.is_splicing() {
+        let start = *ctx.local.splice_start;
+        let take = &mut sdram[start..start + SLOT_LENGTH];
+
+        pre_roll.splice(take, SPLICE_FRAMES_PER_CALLBACK);
+    }
+
+    // store incoming audio in memory, wrapping around the slot when overflowing
+    if !take_frames.is_empty() && !overdub {
+        let mut writer = SOURCE.writer(&mut sdram[slots::get_range(active_slot, SLOT_LENGTH)]);
+
+        for (right, _) in buffer[take_frames.clone()].iter() {
+            writer.push(*right);
+        }
+    }
+
+    if keep_pre_roll {
+        for (right, _) in buffer[take_frames.end..].iter() {
+            pre_roll.push(*right);
+        }
+    }
+
+    // the click starts with the take, so its first beat lands on the start of the loop
+    let metronome = ctx.local.metronome;
+    let clicking = is_recording && METRONOME.load(Ordering::Relaxed);
+    let tempo = tempo::get();
+
+    if take_started {
+        metronome.restart();
+    }
+
+    *ctx.local.was_recording = is_recording;
+
+    // the click is only mixed to the output, it never gets recorded
+    let mut next_click = |frame: usize| {
+        if clicking {
+            metronome.process(tempo, clock_edge == Some(frame)) * METRONOME_LEVEL
+        } else {
+            0.0
+        }
+    };
+
+    // played frames, mixed with the input while it is monitored
+    let mut played = [(0.0, 0.0); AUDIO_BLOCK_SIZE];
+
+    // when playing, for as long as the output can be heard
+    let granulating = crossfade.is_output_heard();
+
+    // the looper plays the take in place of the grains, an overdub adds the input where it plays
+    let looper = ctx.local.looper;
+    let pitch_shifter = ctx.local.pitch_shifter;
+    let looping = LOOPER.load(Ordering::Relaxed);
+    let mut loop_block = [0.0; AUDIO_BLOCK_SIZE];
+
+    if granulating && looping {
+        let mut input = [0.0; AUDIO_BLOCK_SIZE];
+
+        for frame in take_frames.clone() {
+            input[frame] = buffer[frame].0;
+        }
+
+        let overdub = (overdub && !take_frames.is_empty()).then_some(&input[..buffer.len()]);
+        let (speed, decay) = ctx.shared.engine_settings.lock(|settings| {
+            (
+                varispeed::speed_from_normalized(settings.varispeed_speed),
+                settings.loop_decay,
+            )
+        });
+        let take = &mut sdram[slots::get_range(active_slot, SOURCE.len())];
+
+        looper.set_feedback(1.0 - decay);
+        looper.render(take, speed, overdub, &mut loop_block[..buffer.len()]);
+    }
+
+    if granulating {
+        // live grains stay behind the write head
+        let memory = &sdram[slots::get_range(active_slot, SLOT_LENGTH)];
+        let source = if live {
+            SOURCE.reader_behind(memory, LIVE_GAP_FRAMES)
+        } else {
+            SOURCE.reader(memory)
+        };
+
+        // update user settings, gliding from the last control update to the latest
+        let interpolator = &mut ctx.local.interpolator;
+        let granular_settings = &mut ctx.local.granular_settings;
+
+        ctx.shared
+            .user_settings
+            .lock(|settings| interpolator.process(settings, granular_settings));
+
+        // grains of the active slot only play what can be read of it
+        let confine = |settings: &mut UserSettings| {
+            if live && source.is_empty() {
+                settings.active_grains = 0.0;
+            } else if live {
+                settings.offset = source.map_offset(settings.offset);
+            } else if STREAM.is_streaming() {
+                // grains wait until the page under the offset has been read from the SD card
+                match STREAM.map_offset(settings.offset) {
+                    Some(offset) => settings.offset = offset,
+                    None => settings.active_grains = 0.0,
+                }
+            }
+        };
+
+        confine(granular_settings);
+        granulator.set_settings(granular_settings);
+
+        // the second stream shares the settings of the first one but its offset, size, pitch and
+        // grains, and plays the active slot or another one
+        let mut second_settings = *granular_settings;
+        let second_slot = ctx.shared.engine_settings.lock(|settings| {
+            second_settings.offset = settings.second_offset;
+            second_settings.grain_size = settings.second_grain_size;
+            second_settings.pitch = settings.second_pitch;
+            second_settings.active_grains = settings.second_grains;
+            settings.second_slot.unwrap_or(active_slot)
+        });
+        let second_samples = if second_slot == active_slot {
+            confine(&mut second_settings);
+            source.as_slice()
+        } else {
+            &sdram[slots::get_range(second_slot, SLOTS.get_length(second_slot))]
+        };
+
+        second_granulator.set_settings(&second_settings);
+
+        // a new seed starts the scattering of all grains over, every pad from a seed of its own
+        if GRAIN_SEED.take_changed() {
+            let seed = GRAIN_SEED.get();
+
+            granulator.reseed(seed);
+            second_granulator.reseed(seed.wrapping_add(KIT_PADS as u32 + 1));
+
+            for (index, voice) in kit_voices.iter_mut().enumerate() {
+                voice.reseed(seed.wrapping_add(index as u32 + 1));
+            }
+        }
+
+        // swing and humanize shift the starts of all grains and the envelope shapes them, the
+        // ones of the pads included
+        let (swing, humanize, envelope, spread) = ctx.shared.engine_settings.lock(|settings| {
+            (
+                settings.grain_swing,
+                settings.grain_humanize,
+                (settings.grain_attack, settings.grain_decay),
+                (settings.grain_attack_spread, settings.grain_decay_spread),
+            )
+        });
+
+        for cloud in [&mut *granulator, &mut *second_granulator] {
+            cloud.set_timing(swing, humanize);
+            cloud.set_envelope(envelope.0, envelope.1);
+            cloud.set_envelope_spread(spread.0, spread.1);
+        }
+
+        for voice in kit_voices.iter_mut() {
+            voice.set_timing(swing, humanize);
+            voice.set_envelope(envelope.0, envelope.1);
+            voice.set_envelope_spread(spread.0, spread.1);
+        }
+
+        // triggered pads start a burst of grains from their own slot
+
+        ctx.shared.kit.lock(|kit| {
+            let triggered = kit.take_triggered();
+
+            for (index, voice) in kit_voices.iter_mut().enumerate() {
+                if triggered & 1 << index != 0 {
+                    voice.trigger(&kit.get_pads()[index], AUDIO_SAMPLE_RATE);
+                }
+            }
+        });
+
+        // the pads are rendered ahead, they play from slots of their own
+        let mut kit_block = [0.0; AUDIO_BLOCK_SIZE];
+        let kit_block = &mut kit_block[..buffer.len()];
+
+        for voice in kit_voices.iter_mut().filter(|voice| voice.is_active()) {
+            let slot = voice.get_slot();
+            let memory = &sdram[slots::get_range(slot, SLOT_LENGTH)];
+
+            // the take of the active slot lives in the ring
+            let samples = if slot == active_slot {
+                SOURCE.reader(memory).as_slice()
+            } else {
+                &memory[..SLOTS.get_length(slot)]
+            };
+
+            // a slot which is empty or recorded into can not be played
+            if samples.is_empty() || (is_recording && slot == active_slot) {
+                voice.stop();
+            } else {
+                voice.render(samples, kit_block);
+            }
+        }
+
+        // update engine settings
+        let speed = ctx.shared.engine_settings.lock(|settings| {
+            mixer.set_blend(settings.engine_blend);
+            texture.set_crush(settings.texture_crush);
+            texture.set_downsample(settings.texture_downsample);
+            echo.set_time(settings.echo_time * ECHO_MAX_FRAMES as f32);
+            echo.set_feedback(settings.echo_feedback);
+            echo.set_mix(settings.echo_mix);
+            reverb.set_size(settings.reverb_size);
+            reverb.set_mix(settings.reverb_mix);
+            varispeed::speed_from_normalized(settings.varispeed_speed)
+        });
+
+        // the grains are mixed a block at a time, which keeps their loops tight
+        let mut granular_block = [0.0; AUDIO_BLOCK_SIZE];
+        let granular_block = &mut granular_block[..buffer.len()];
+
+        // the grains rest while the pitch shifter or the looper plays
+        if !looping && !shifting {
+            granulator.render(source.as_slice(), granular_block);
+            second_granulator.render(second_samples, granular_block);
+        }
+
+        // the peak of a take is only known once it is finished
+        let gain = if live || shifting {
+            1.0
+        } else {
+            normalize::get_gain()
+        };
+
+        // the pitch knob with its CV transposes the input
+        let shift_speed =
+            stretch::speed_from_semitones(STRETCH_RANGES.semitones(granular_settings.pitch));
+
+        for frame in 0..buffer.len() {
+            let granular_sample = granular_block[frame];
+
+            // the loop keeps running while it is not heard, so its gate output stays in time
+            let varispeed_sample = if mixer.is_varispeed_active() {
+                varispeed.get_next_sample(source.as_slice(), speed)
+            } else {
+                varispeed.advance(source.as_slice().len(), speed);
+                0.0
+            };
+
+            let played_sample = if shifting {
+                pitch_shifter.process(buffer[frame].0, shift_speed)
+            } else if looping {
+                loop_block[frame]
+            } else {
+                mixer.process(granular_sample, varispeed_sample)
+            };
+            let mono_sample = played_sample * gain + kit_block[frame];
+
+            if SOAK_TEST {
+                SOAK_MONITOR.check_sample(mono_sample);
+            }
+
+            let mono_sample = if texture.is_bypassed() {
+                mono_sample
+            } else {
+                texture.process(mono_sample)
+            };
+
+            let (left, right) = echo.process(mono_sample);
+            played[frame] = reverb.process(left, right);
+        }
+
+        if looping || shifting {
+            GRAIN_SNAPSHOT.clear();
+        } else {
+            GRAIN_SNAPSHOT.publish(granulator.get_dots(source.as_slice().len()));
+        }
+    } else {
+        GRAIN_SNAPSHOT.clear();
+    }
+
+    // mono copy of the output for the spectrum
+    let mut tap = [0.0; AUDIO_BLOCK_SIZE];
+    let monitor_heard = crossfade.is_monitor_heard();
+
+    for (frame, input) in buffer.iter().enumerate() {
+        let (monitor_gain, played_gain) = crossfade.next_gains();
+        let (mut left, mut right) = played[frame];
+
+        // the input is only mixed in while it is monitored
+        if monitor_heard {
+            left = input.0 * monitor_gain + left * played_gain;
+            right = input.1 * monitor_gain + right * played_gain;
+        }
+
+        // an overdub is heard on top of the loop it goes into
+        if looping && overdub && take_frames.contains(&frame) {
+            left += input.0;
+            right += input.1;
+        }
+
+        let click = next_click(frame);
+        let (left, right) = output.process(left + click, right + click);
+        tap[frame] = (left + right) * 0.5;
+
+        audio.push_stereo((left, right)).unwrap();
+        ctx.local.ar.usb_output.push(left, right);
+        output_meter.accumulate(left);
+        output_meter.accumulate(right);
+    }
+
+    output_meter.publish(&OUTPUT_METER);
+    SPECTRUM.push(&tap[..buffer.len()]);
+
+    // ----------------------------------
+    // GATE OUTPUTS
+    // ----------------------------------
+
+    let pulses = ctx.local.pulses;
+
+    if granulator.take_finished() > 0 {
+        pulses.trigger(PulseOutput::Grain);
+    }
+
+    // the loop gate follows the engine which plays the take
+    let looper_wrapped = looper.take_wrapped();
+    let varispeed_wrapped = varispeed.take_wrapped();
+
+    if (looping && looper_wrapped) || (!looping && varispeed_wrapped) {
+        pulses.trigger(PulseOutput::Loop);
+    }
+
+    ctx.local
+        .ar
+        .gate_outputs
+        .write(pulses.advance(buffer.len()));
+
+    // ----------------------------------
+    // BOUNCE
+    // ----------------------------------
+
+    render_bounce(
+        ctx.local.bouncer,
+        ctx.local.bounce_job,
+        sdram,
+        &mut ctx.shared.user_settings,
+        buffer.len(),
+        is_recording,
+    );
+
+    let elapsed = cortex_m::peripheral::DWT::cycle_count().wrapping_sub(callback_start);
+    let load = elapsed as f32 / AUDIO_CALLBACK_CYCLES as f32;
+
+    if granulating && !looping && !shifting {
+        GRAIN_STATS.publish(Some(ctx.local.granular_settings), load);
+    } else {
+        GRAIN_STATS.publish(None, load);
+    }
+
+    // the clouds start fewer grains once the callback runs out of time
+    let bouncer = ctx.local.bouncer;
+    let playing = kit_voices
+        .iter()
+        .map(|voice| voice.get_playing())
+        .chain([
+            granulator.get_playing(),
+            second_granulator.get_playing(),
+            bouncer.get_playing(),
+        ])
+        .max()
+        .unwrap_or(0);
+    let limit = ctx.local.grain_limit.update(load, playing);
+
+    granulator.set_limit(limit);
+    second_granulator.set_limit(limit);
+    bouncer.set_limit(limit);
+
+    for voice in kit_voices.iter_mut() {
+        voice.set_limit(limit);
+    }
+
+    GRAIN_STATS.publish_counts(granulator.get_requested(), granulator.get_playing());
+
+    if SOAK_TEST {
+        SOAK_MONITOR.check_callback(elapsed, AUDIO_CALLBACK_CYCLES);
+    }
+}
+
+/// Renders the next blocks of a running bounce of the active slot into its target slot.
+fn render_bounce(
+    bouncer: &mut GrainCloud,
+    bounce_job: &mut Option<BounceJob>,
+    sdram: &mut [f32],
+    user_settings: &mut impl rtic::Mutex<T = UserSettings>,
+    frames: usize,
+    is_recording: bool,
+) {
+    if let Some((target_slot, length)) = BOUNCE.take_request() {
+        *bounce_job = Some(BounceJob::new(target_slot, length.min(SLOT_LENGTH)));
+    }
+
+    let mut bounce_finished = false;
+
+    if let Some(job) = bounce_job.as_mut() {
+        // the source slot has to stay untouched while rendering
+        if !is_recording {
+            let source_range = slots::get_range(SLOTS.get_active(), SLOT_LENGTH);
+
+            user_settings.lock(|settings| bouncer.set_settings(settings));
+
+            let target_start = slots::get_start(job.target_slot);
+
+            // render several blocks per callback to be faster than real time, every block goes
+            // through a copy as source and target share the SDRAM
+            for _ in 0..BOUNCE_BLOCKS_PER_CALLBACK {
+                let mut block = [0.0; AUDIO_BLOCK_SIZE];
+                let block = &mut block[..frames.min(job.length - job.rendered)];
+                let source = SOURCE.reader(&sdram[source_range.clone()]);
+
+                bouncer.render(source.as_slice(), block);
+
+                let start = target_start + job.rendered;
+                sdram[start..start + block.len()].copy_from_slice(block);
+                job.rendered += block.len();
+            }
+
+            BOUNCE.set_rendered(job.rendered);
+        }
+
+        bounce_finished = job.is_finished();
+
+        if bounce_finished {
+            WAVEFORMS.invalidate(SLOTS.get_buffer(job.target_slot), 0);
+            SLOTS.set_length(job.target_slot, job.length);
+        }
+    }
+
+    if bounce_finished {
+        *bounce_job = None;
+        BOUNCE.finish();
+    }
+}
@@ -0,0 +1,56 @@
+use crate::export::{self, Export, WavFormat, EXPORT_SAMPLES_PER_STEP};
+use crate::storage::{Error, Storage};
+
+/// File which holds the last recording
+pub const AUTOSAVE_NAME: [u8; 12] = *b"AUTOSAVE.WAV";
+
+/// Requests and progress of the autosave, which works like an export to a fixed file.
+pub static AUTOSAVE: Export = Export::new();
+
+/// Reads the autosaved recording into `buffer`. Returns the number of restored samples.
+///
+/// A file cut short by a power loss is restored as far as it got written.
+pub fn restore(
+    storage: &mut Storage,
+    buffer: &mut [f32],
+    sample_rate: u32,
+) -> Result<usize, Error> {
+    let name = core::str::from_utf8(&AUTOSAVE_NAME).unwrap_or("");
+    let mut file = storage.open(name)?;
+
+    let samples = match export::read_wav_header(storage, &mut file) {
+        Ok(info) if info.format == WavFormat::Float32 && info.sample_rate == sample_rate => {
+            info.samples.min(buffer.len())
+        }
+        _ => {
+            storage.close(file)?;
+            return Err(Error::FormatError("Not an autosave"));
+        }
+    };
+
+    let mut bytes = [0; EXPORT_SAMPLES_PER_STEP * 4];
+    let mut restored = 0;
+
+    while restored < samples {
+        let chunk = (samples - restored).min(EXPORT_SAMPLES_PER_STEP);
+        let read = storage.read(&mut file, &mut bytes[..chunk * 4])?;
+
+        if read == 0 {
+            break;
+        }
+
+        // partially read samples get dropped at the end of the file
+        for (sample, value) in buffer[restored..]
+            .iter_mut()
+            .zip(bytes[..read].chunks_exact(4))
+        {
+            *sample = f32::from_le_bytes([value[0], value[1], value[2], value[3]]);
+        }
+
+        restored += read / 4;
+    }
+
+    storage.close(file)?;
+
+    Ok(restored)
+}
@@ -0,0 +1,653 @@
+use core::sync::atomic::Ordering;
+
+use sitira_core::{
+    card::CARD,
+    erase::{EraseJob, ERASE},
+    normalize::{self, PeakScanner},
+    onset::OnsetDetector,
+    resample::ResampleQuality,
+    session::{Session, SESSION},
+    slices::SliceMarkers,
+    spectrum::{SpectrumAnalyzer, SPECTRUM},
+    stream::LOAD,
+    theme::{Theme, THEME},
+    work::{Job, Priority, Progress, WorkQueue},
+};
+
+use crate::{
+    autosave::{AUTOSAVE, AUTOSAVE_NAME},
+    config::{AUDIO_SAMPLE_RATE, NORMALIZE_MAX_GAIN, NORMALIZE_TARGET_LEVEL},
+    export::{Export, ExportJob, EXPORT},
+    playback::{ANALYSIS_REQUESTED, IS_RECORDING, SOURCE},
+    rprintln,
+    sample_file::{StreamJob, STREAM},
+    session_file,
+    sitira::SdCard,
+    slots::{self, SLOTS},
+    storage::Storage,
+    theme_file,
+    waveform_cache::WAVEFORMS,
+};
+
+/// Runs the background work of the idle task for good. The `card` is only there while it is not
+/// mounted, so mounting it can be tried again.
+pub fn run<L, S>(
+    storage: &mut Option<Storage>,
+    card: &mut Option<SdCard>,
+    slices: L,
+    session: S,
+) -> !
+where
+    L: rtic::Mutex<T = SliceMarkers>,
+    S: rtic::Mutex<T = Session>,
+{
+    let mut queue: BackgroundQueue = WorkQueue::new();
+    let mut background = Background {
+        storage,
+        slices,
+        session,
+        spectrum: SpectrumAnalyzer::new(),
+    };
+
+    // the waveform keeps following the slots for good
+    queue_job(
+        &mut queue,
+        &mut background,
+        Priority::Low,
+        IdleJob::Waveform,
+    );
+
+    // as does the spectrum, which only gets samples while it is shown
+    queue_job(
+        &mut queue,
+        &mut background,
+        Priority::Low,
+        IdleJob::Spectrum,
+    );
+
+    loop {
+        // a new recording invalidates any running analysis
+        if IS_RECORDING.load(Ordering::Relaxed) {
+            queue.cancel(&mut background, IdleJob::is_analysis);
+        }
+
+        // SD CARD
+
+        if CARD.take_retry() {
+            // whatever still works on the failed card ends with it
+            if queue.cancel(&mut background, IdleJob::uses_card) > 0 {
+                EXPORT.finish();
+                AUTOSAVE.finish();
+                LOAD.finish();
+            }
+
+            remount(background.storage, card);
+        }
+
+        // EXPORT AND AUTOSAVE
+
+        if let Some((slot, length, format)) = EXPORT.take_request() {
+            let job = match background.storage.as_mut() {
+                Some(storage) => {
+                    match ExportJob::start(storage, slot, length, format, AUDIO_SAMPLE_RATE as u32)
+                    {
+                        Ok(job) => {
+                            rprintln!(
+                                "Exporting slot {} to {} as {}!",
+                                slot,
+                                job.get_name(),
+                                format.name()
+                            );
+                            Some(job)
+                        }
+                        Err(error) => {
+                            rprintln!("Failed to create the export: {:?}", error);
+                            None
+                        }
+                    }
+                }
+                None => {
+                    rprintln!("No SD card to export to!");
+                    None
+                }
+            };
+
+            let queued = job.map_or(false, |job| {
+                queue_job(
+                    &mut queue,
+                    &mut background,
+                    Priority::Normal,
+                    IdleJob::Export(job),
+                )
+            });
+
+            if !queued {
+                EXPORT.finish();
+            }
+        }
+
+        if let Some((slot, length, format)) = AUTOSAVE.take_request() {
+            // without an SD card there is nothing to save to
+            let job = background.storage.as_mut().and_then(|storage| {
+                match ExportJob::create(
+                    storage,
+                    AUTOSAVE_NAME,
+                    slot,
+                    length,
+                    format,
+                    AUDIO_SAMPLE_RATE as u32,
+                ) {
+                    Ok(job) => Some(job),
+                    Err(error) => {
+                        rprintln!("Failed to create the autosave: {:?}", error);
+                        None
+                    }
+                }
+            });
+
+            let queued = job.map_or(false, |job| {
+                queue_job(
+                    &mut queue,
+                    &mut background,
+                    Priority::Normal,
+                    IdleJob::Autosave(job),
+                )
+            });
+
+            if !queued {
+                AUTOSAVE.finish();
+            }
+        }
+
+        // SAMPLE LOADING AND STREAMING
+
+        if let Some((slot, number, quality)) = LOAD.take_request() {
+            // only one file gets streamed at a time
+            queue.cancel(&mut background, |job| matches!(job, IdleJob::Stream(_)));
+
+            let job = match background.storage.as_mut() {
+                Some(storage) => start_stream(storage, slot, number, quality),
+                None => {
+                    rprintln!("No SD card to load from!");
+                    None
+                }
+            };
+
+            let queued = job.map_or(false, |job| {
+                queue_job(
+                    &mut queue,
+                    &mut background,
+                    Priority::High,
+                    IdleJob::Stream(job),
+                )
+            });
+
+            if queued {
+                // the slices and the gain belong to what the slot held before
+                background.slices.lock(|slices| slices.clear(SOURCE.len()));
+                normalize::set_gain(1.0);
+            } else {
+                LOAD.finish();
+            }
+        }
+
+        // SESSION AND THEME
+
+        if SESSION.take_save() {
+            if background.storage.is_some() {
+                queue_job(
+                    &mut queue,
+                    &mut background,
+                    Priority::Normal,
+                    IdleJob::SaveSession,
+                );
+            } else {
+                rprintln!("No SD card to save to!");
+            }
+        }
+
+        if SESSION.take_load() {
+            if background.storage.is_some() {
+                queue_job(
+                    &mut queue,
+                    &mut background,
+                    Priority::Normal,
+                    IdleJob::LoadSession,
+                );
+            } else {
+                rprintln!("No SD card to load from!");
+            }
+        }
+
+        if let Some(theme) = THEME.take_unsaved() {
+            if background.storage.is_some() {
+                queue_job(
+                    &mut queue,
+                    &mut background,
+                    Priority::Normal,
+                    IdleJob::SaveTheme(theme),
+                );
+            }
+        }
+
+        // ERASE
+
+        if let Some((buffer, length)) = ERASE.take_request() {
+            let job = IdleJob::Erase(EraseJob::new(buffer, length));
+
+            if !queue_job(&mut queue, &mut background, Priority::Normal, job) {
+                ERASE.finish();
+            }
+        }
+
+        // ANALYSIS
+
+        if ANALYSIS_REQUESTED.swap(false, Ordering::Relaxed) {
+            let source_length = SOURCE.len();
+            let mut peak_scanner = PeakScanner::new();
+            let mut onset_detector = OnsetDetector::new();
+
+            peak_scanner.start(source_length);
+            background
+                .slices
+                .lock(|slices| onset_detector.start(source_length, slices));
+
+            queue.cancel(&mut background, IdleJob::is_analysis);
+            queue_job(
+                &mut queue,
+                &mut background,
+                Priority::Low,
+                IdleJob::Normalize(peak_scanner),
+            );
+            queue_job(
+                &mut queue,
+                &mut background,
+                Priority::Low,
+                IdleJob::Onsets(onset_detector),
+            );
+        }
+
+        if !queue.run(&mut background) {
+            cortex_m::asm::nop();
+        }
+    }
+}
+
+/// Jobs the idle task can have queued at once
+const BACKGROUND_JOBS: usize = 8;
+
+type BackgroundQueue = WorkQueue<IdleJob, BACKGROUND_JOBS>;
+
+/// Work the idle task does in the background, a small piece per step. Everything which reads
+/// or writes the SD card or walks through a whole slot runs as one of these.
+enum IdleJob {
+    Export(ExportJob),
+    Autosave(ExportJob),
+    Stream(StreamJob),
+    Erase(EraseJob),
+    Normalize(PeakScanner),
+    Onsets(OnsetDetector),
+    SaveTheme(Theme),
+    SaveSession,
+    LoadSession,
+    /// Summarizes the peaks of the slots for the waveform, never done
+    Waveform,
+    /// Analyzes the frames of the output spectrum, never done
+    Spectrum,
+}
+
+impl IdleJob {
+    /// Returns `true` for the analyses of the active slot, which a new take makes obsolete.
+    fn is_analysis(&self) -> bool {
+        matches!(self, IdleJob::Normalize(_) | IdleJob::Onsets(_))
+    }
+
+    /// Returns `true` for the jobs which read or write files.
+    fn uses_card(&self) -> bool {
+        matches!(
+            self,
+            IdleJob::Export(_)
+                | IdleJob::Autosave(_)
+                | IdleJob::Stream(_)
+                | IdleJob::SaveTheme(_)
+                | IdleJob::SaveSession
+                | IdleJob::LoadSession
+        )
+    }
+}
+
+/// What the background jobs work with, the resources of the idle task.
+struct Background<'a, L, S> {
+    storage: &'a mut Option<Storage>,
+    slices: L,
+    session: S,
+    spectrum: SpectrumAnalyzer,
+}
+
+impl<L, S> Job<Background<'_, L, S>> for IdleJob
+where
+    L: rtic::Mutex<T = SliceMarkers>,
+    S: rtic::Mutex<T = Session>,
+{
+    fn step(&mut self, background: &mut Background<'_, L, S>) -> Progress {
+        // frames analyzed per lock, keeps the control task responsive
+        const ONSET_FRAMES_PER_STEP: usize = 16;
+        // samples scanned for the peak per step
+        const PEAK_SAMPLES_PER_STEP: usize = 4096;
+        // samples zeroed per step while erasing
+        const ERASE_SAMPLES_PER_STEP: usize = 16384;
+
+        let recording = IS_RECORDING.load(Ordering::Relaxed);
+
+        match (self, background.storage.as_mut()) {
+            (IdleJob::Export(job), Some(storage)) => {
+                // recording overwrites the exported slot
+                let aborted = recording && job.slot == SLOTS.get_active();
+
+                step_export(job, storage, &EXPORT, aborted)
+            }
+            // any new take replaces the autosave
+            (IdleJob::Autosave(job), Some(storage)) => {
+                step_export(job, storage, &AUTOSAVE, recording)
+            }
+            (IdleJob::Stream(job), Some(storage)) => step_stream(job, storage),
+            (IdleJob::SaveTheme(theme), Some(storage)) => {
+                if let Err(error) = theme_file::save(storage, *theme) {
+                    rprintln!("Failed to save the theme: {:?}", error);
+                }
+
+                Progress::Done
+            }
+            (IdleJob::SaveSession, Some(storage)) => {
+                let session = background.session.lock(|session| *session);
+
+                match session_file::save(storage, &session) {
+                    Ok(()) => {
+                        rprintln!("Saved the session!");
+                    }
+                    Err(error) => {
+                        rprintln!("Failed to save the session: {:?}", error);
+                    }
+                }
+
+                Progress::Done
+            }
+            (IdleJob::LoadSession, Some(storage)) => {
+                match session_file::load(storage) {
+                    Ok(session) => {
+                        background.session.lock(|shared| *shared = session);
+                        SESSION.set_loaded();
+                    }
+                    Err(error) => {
+                        rprintln!("Failed to load the session: {:?}", error);
+                    }
+                }
+
+                Progress::Done
+            }
+            (IdleJob::Waveform, _) => {
+                if WAVEFORMS.build(SOURCE.len()) {
+                    Progress::Working
+                } else {
+                    Progress::Waiting
+                }
+            }
+            (IdleJob::Spectrum, _) => {
+                if background.spectrum.step(&SPECTRUM) {
+                    Progress::Working
+                } else {
+                    Progress::Waiting
+                }
+            }
+            (IdleJob::Erase(job), _) => {
+                let end = (job.erased + ERASE_SAMPLES_PER_STEP).min(job.length);
+
+                // a new take may record into the erased buffer, which overwrites it anyway
+                let recorded = recording && SLOTS.get_buffer(SLOTS.get_active()) == job.buffer;
+
+                if !recorded {
+                    // SAFETY: the slot of the buffer was emptied, so nothing plays it back, and
+                    // takes are not undone while erasing
+                    unsafe { slots::erase(job.buffer, job.erased..end) };
+                }
+
+                job.erased = if recorded { job.length } else { end };
+                ERASE.set_erased(job.erased);
+
+                if job.is_finished() {
+                    rprintln!("Erased buffer {}!", job.buffer);
+                    ERASE.finish();
+                    Progress::Done
+                } else {
+                    Progress::Working
+                }
+            }
+            (IdleJob::Normalize(peak_scanner), _) => {
+                let buffer = match slots::get_slice(SLOTS.get_active(), SOURCE.len()) {
+                    Some(buffer) => buffer,
+                    None => return Progress::Waiting,
+                };
+
+                match peak_scanner.process(buffer, PEAK_SAMPLES_PER_STEP) {
+                    Some(peak) => {
+                        let gain = normalize::gain_for_peak(
+                            peak,
+                            NORMALIZE_TARGET_LEVEL,
+                            NORMALIZE_MAX_GAIN,
+                        );
+
+                        normalize::set_gain(gain);
+                        rprintln!(
+                            "Normalizing playback by {}!",
+                            normalize::format_gain(gain).as_str()
+                        );
+                        Progress::Done
+                    }
+                    None => Progress::Working,
+                }
+            }
+            (IdleJob::Onsets(onset_detector), _) => {
+                let buffer = match slots::get_slice(SLOTS.get_active(), SOURCE.len()) {
+                    Some(buffer) => buffer,
+                    None => return Progress::Waiting,
+                };
+
+                let finished = background
+                    .slices
+                    .lock(|slices| onset_detector.process(buffer, slices, ONSET_FRAMES_PER_STEP));
+
+                if finished {
+                    rprintln!(
+                        "Onset analysis found {} slices!",
+                        background.slices.lock(|slices| slices.len())
+                    );
+                    Progress::Done
+                } else {
+                    Progress::Working
+                }
+            }
+            // jobs on the SD card only get queued if there is one
+            (_, None) => Progress::Done,
+        }
+    }
+
+    fn finish(self, background: &mut Background<'_, L, S>) {
+        let storage = match background.storage.as_mut() {
+            Some(storage) => storage,
+            None => return,
+        };
+
+        match self {
+            IdleJob::Export(job) | IdleJob::Autosave(job) => {
+                if let Err(error) = job.close(storage) {
+                    rprintln!("Failed to close the export: {:?}", error);
+                }
+            }
+            // the samples in memory stay where they are
+            IdleJob::Stream(job) => {
+                STREAM.stop();
+
+                if let Err(error) = job.close(storage) {
+                    rprintln!("Failed to close the sample file: {:?}", error);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Mounts the SD card again, a failed one gets unmounted first.
+fn remount(storage: &mut Option<Storage>, card: &mut Option<SdCard>) {
+    if let Some(failed) = storage.take() {
+        *card = Some(failed.unmount());
+    }
+
+    if let Some(unmounted) = card.take() {
+        match Storage::mount(unmounted) {
+            Ok(mounted) => {
+                rprintln!("Mounted the SD card!");
+                *storage = Some(mounted);
+            }
+            Err((error, unmounted)) => {
+                rprintln!("Failed to mount the SD card: {}!", error.description());
+                *card = Some(unmounted);
+            }
+        }
+    }
+}
+
+/// Queues a background job. If the queue is full, the job gets dropped and `false` returned.
+fn queue_job<L, S>(
+    queue: &mut BackgroundQueue,
+    background: &mut Background<'_, L, S>,
+    priority: Priority,
+    job: IdleJob,
+) -> bool
+where
+    L: rtic::Mutex<T = SliceMarkers>,
+    S: rtic::Mutex<T = Session>,
+{
+    match queue.push(priority, job) {
+        Ok(()) => true,
+        Err(job) => {
+            rprintln!("Too much background work, a job got dropped!");
+            job.finish(background);
+            false
+        }
+    }
+}
+
+/// Writes the next chunk of an export, which is done once it is complete or `aborted`.
+fn step_export(
+    job: &mut ExportJob,
+    storage: &mut Storage,
+    progress: &Export,
+    aborted: bool,
+) -> Progress {
+    let done = if aborted {
+        rprintln!("Writing {} aborted by a recording!", job.get_name());
+        true
+    } else {
+        match slots::get_slice(job.slot, job.length) {
+            Some(buffer) => match job.process(storage, buffer) {
+                Ok(()) => {
+                    progress.set_written(job.written);
+
+                    if job.is_finished() {
+                        rprintln!("Wrote {}!", job.get_name());
+                    }
+
+                    job.is_finished()
+                }
+                Err(error) => {
+                    rprintln!("Failed to write {}: {:?}", job.get_name(), error);
+                    true
+                }
+            },
+            None => true,
+        }
+    };
+
+    if done {
+        progress.finish();
+        Progress::Done
+    } else {
+        Progress::Working
+    }
+}
+
+/// Opens sample file `number` and starts streaming it into `slot`, converted with `quality`
+/// if it has another rate than the engine.
+fn start_stream(
+    storage: &mut Storage,
+    slot: usize,
+    number: u32,
+    quality: ResampleQuality,
+) -> Option<StreamJob> {
+    match StreamJob::open(storage, number, slot, AUDIO_SAMPLE_RATE as u32, quality) {
+        Ok((job, fitting)) => {
+            if slot == SLOTS.get_active() {
+                SOURCE.set_len(fitting);
+            }
+
+            rprintln!(
+                "Reading {} at {} Hz into slot {}!",
+                job.get_name(),
+                job.get_rate(),
+                slot
+            );
+            Some(job)
+        }
+        Err(error) => {
+            rprintln!("Failed to open sample file {:02}: {:?}", number, error);
+            None
+        }
+    }
+}
+
+/// Reads the next samples of the streamed file. A file which fits into the slot is played like
+/// a take once it has been read, a longer one keeps streaming until the slot is taken over and
+/// waits while all pages around the read position are loaded.
+fn step_stream(job: &mut StreamJob, storage: &mut Storage) -> Progress {
+    // switching the slot, a new take, an undo or an erase end the stream
+    if SLOTS.get_active() != job.slot
+        || SLOTS.get_buffer(job.slot) != job.buffer
+        || SLOTS.get_length(job.slot) == 0
+    {
+        rprintln!("Stopped streaming {}!", job.get_name());
+        LOAD.finish();
+        return Progress::Done;
+    }
+
+    match job.process(storage) {
+        Ok(_) if STREAM.is_complete() => {
+            SLOTS.set_length(job.slot, STREAM.len());
+            SOURCE.set_len(STREAM.len());
+            ANALYSIS_REQUESTED.store(true, Ordering::Relaxed);
+
+            rprintln!("Loaded {}!", job.get_name());
+            LOAD.finish();
+            Progress::Done
+        }
+        Ok(read) => {
+            LOAD.set_progress(job.get_progress());
+
+            // the load is done once the window has been filled, the rest follows the offset
+            if LOAD.is_running() && job.get_progress() >= 100 {
+                rprintln!("Streaming {}!", job.get_name());
+                LOAD.finish();
+            }
+
+            if read {
+                Progress::Working
+            } else {
+                Progress::Waiting
+            }
+        }
+        Err(error) => {
+            rprintln!("Failed to read {}: {:?}", job.get_name(), error);
+            LOAD.finish();
+            Progress::Done
+        }
+    }
+}
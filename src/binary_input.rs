@@ -18,6 +18,7 @@ pub struct BinaryInput<P> {
     input_type: InputType,
     state: bool,
     transition: bool,
+    release: bool,
 }
 
 impl<P> BinaryInput<P>
@@ -32,6 +33,7 @@ where
             input_type,
             state: false,
             transition: false,
+            release: false,
         }
     }
 
@@ -67,6 +69,10 @@ where
         } else {
             self.transition = false;
         }
+
+        // checks if state has transition from high to low
+        self.release = self.get_saved_state() && self.is_input_low();
+
         self.state = self.is_input_high();
     }
 
@@ -90,6 +96,11 @@ where
         self.transition
     }
 
+    /// Returns `true` if the state changed from high to low (for one polling cycle).
+    pub fn is_released(&self) -> bool {
+        self.release
+    }
+
     // Returns `true` if the input is high, depending on the `InputType`.
     pub fn is_pressed(&self) -> bool {
         self.state
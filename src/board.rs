@@ -0,0 +1,94 @@
+use core::fmt::Debug;
+use stm32h7xx_hal::hal::digital::v2::OutputPin;
+
+#[cfg(all(feature = "hw_rev_a", feature = "hw_rev_b"))]
+compile_error!("Only one hardware revision can be selected, either `hw_rev_a` or `hw_rev_b`");
+
+/// Known revisions of the Sitira panel PCB.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum HardwareRevision {
+    RevA,
+    RevB,
+}
+
+/// Electrical level which lights up an LED.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LedPolarity {
+    ActiveHigh,
+    ActiveLow,
+}
+
+/// Everything that differs between panel revisions.
+pub struct BoardConfig {
+    pub revision: HardwareRevision,
+    /// Physical mux channel of every logical input (indexed by `AdcMuxInputs`)
+    pub mux_channels: [u8; 16],
+    /// Logical gate number of the physical gate inputs 1 to 4
+    pub gate_order: [u8; 4],
+    pub led_polarity: LedPolarity,
+}
+
+impl BoardConfig {
+    /// Switches an LED on or off according to the board's LED polarity.
+    pub fn set_led<P>(&self, led: &mut P, on: bool)
+    where
+        P: OutputPin,
+        <P as OutputPin>::Error: Debug,
+    {
+        let high = match self.led_polarity {
+            LedPolarity::ActiveHigh => on,
+            LedPolarity::ActiveLow => !on,
+        };
+
+        if high {
+            led.set_high().unwrap();
+        } else {
+            led.set_low().unwrap();
+        }
+    }
+}
+
+/// Revision A, the original panel without strap resistors.
+pub const REV_A: BoardConfig = BoardConfig {
+    revision: HardwareRevision::RevA,
+    mux_channels: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    gate_order: [0, 1, 2, 3],
+    led_polarity: LedPolarity::ActiveHigh,
+};
+
+/// Revision B, routes both 4051 in reverse channel order, swaps the gate rows and sinks the LEDs.
+pub const REV_B: BoardConfig = BoardConfig {
+    revision: HardwareRevision::RevB,
+    mux_channels: [7, 6, 5, 4, 3, 2, 1, 0, 15, 14, 13, 12, 11, 10, 9, 8],
+    gate_order: [2, 3, 0, 1],
+    led_polarity: LedPolarity::ActiveLow,
+};
+
+/// Selects the board configuration from the strap pin ID.
+///
+/// The strap pins are read with internal pull-ups, so a missing strap reads as `1` and a strap
+/// resistor to ground as `0`. Unknown IDs fall back to revision A.
+pub fn from_strap_id(id: u8) -> &'static BoardConfig {
+    match id {
+        0b10 => &REV_B,
+        _ => &REV_A,
+    }
+}
+
+/// Board configuration selected by the `hw_rev_a` or the `hw_rev_b` feature, if any.
+pub const FORCED: Option<&BoardConfig> = if cfg!(feature = "hw_rev_b") {
+    Some(&REV_B)
+} else if cfg!(feature = "hw_rev_a") {
+    Some(&REV_A)
+} else {
+    None
+};
+
+/// Selects the board configuration of the build, from the features if one of them names a
+/// revision and from the strap pin ID otherwise.
+///
+/// Panels with missing or misplaced strap resistors need the feature, the straps would pick the
+/// wrong mux channel order, gate rows and LED polarity for them.
+pub fn select(strap_id: u8) -> &'static BoardConfig {
+    FORCED.unwrap_or_else(|| from_strap_id(strap_id))
+}
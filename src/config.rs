@@ -1,5 +1,205 @@
+use sitira_core::expander::EXPANDER_BUTTONS;
+use sitira_core::routing::TriggerAction;
+
 /// Internal update rate for scheduler and other various tasks
 pub const CONTROL_RATE_IN_MS: u32 = 30;
 
 /// LCD frames per second
 pub const LCD_REFRESH_RATE_IN_MS: u32 = 20;
+
+/// Seconds without encoder or button activity until the display goes dark
+pub const SCREENSAVER_TIMEOUT_IN_S: u32 = 120;
+
+/// Seconds over which the display backlight fades out before going dark
+pub const SCREENSAVER_FADE_IN_S: u32 = 5;
+
+/// Put the display controller into sleep mode once it is dark
+pub const SCREENSAVER_SLEEP: bool = true;
+
+/// Flickers of the seed LED at start up on an SDRAM fault without a display, 5 per second
+#[cfg(feature = "headless")]
+pub const SDRAM_FAULT_BLINKS: u32 = 15;
+
+/// Control cycles after boot in which holding the encoder enters the calibration
+pub const CALIBRATION_BOOT_TICKS: u32 = 30;
+
+/// Core clock as configured by libdaisy
+pub const CPU_FREQUENCY_IN_HZ: u32 = 480_000_000;
+
+/// Audio sample rate of the codec as libdaisy sets it up
+pub const AUDIO_SAMPLE_RATE: usize = libdaisy::AUDIO_SAMPLE_RATE;
+
+/// Frames per audio callback as libdaisy sets up the DMA
+pub const AUDIO_BLOCK_SIZE: usize = libdaisy::AUDIO_BLOCK_SIZE as usize;
+
+/// Replaces the audio input with synthetic signals and exercises the transport on a schedule,
+/// for validating long-term stability on real hardware. Results are logged with the `log`
+/// feature.
+pub const SOAK_TEST: bool = false;
+
+/// Bus clock of the SD card
+pub const SD_CARD_FREQUENCY_IN_MHZ: u32 = 50;
+
+/// Interval in which the soak test logs its counters
+pub const SOAK_REPORT_INTERVAL_IN_S: u32 = 60;
+
+/// Resets the device if the audio, control or display task stalls for this long. Set to `0` to
+/// leave the watchdog off.
+pub const WATCHDOG_TIMEOUT_IN_MS: u32 = 1000;
+
+/// Saves the first seconds of every recording to the SD card and restores them at start up. Set to
+/// `0` to turn the autosave off.
+pub const AUTOSAVE_IN_S: u32 = 30;
+
+/// CPU cycles to wait for the strap pin pull-ups to settle before reading them
+pub const STRAP_SETTLE_CYCLES: u32 = 4800;
+
+/// Words at the start of the SDRAM whose cells get tested at start up, besides the data and
+/// address lines. Set to `0` to skip the test.
+pub const SDRAM_TEST_WORDS: usize = 0x100000;
+
+/// PWM frequency of the RGB status LED
+pub const RGB_LED_PWM_FREQUENCY_IN_KHZ: u32 = 1;
+
+/// PWM frequency of the status LED timer with the `cv-output` feature, high enough for a simple
+/// RC filter to smooth the CV
+pub const CV_OUTPUT_PWM_FREQUENCY_IN_KHZ: u32 = 50;
+
+/// Times the envelope follower of the CV output rises and falls with
+pub const ENVELOPE_ATTACK_IN_MS: f32 = 5.0;
+pub const ENVELOPE_RELEASE_IN_MS: f32 = 150.0;
+
+/// Gain from the input envelope to the CV output, `2.0` reaches the full CV at half the input
+/// range
+pub const CV_OUTPUT_GAIN: f32 = 2.0;
+
+/// Time after an accepted gate edge in which further edges of that gate count as bounce
+pub const GATE_DEBOUNCE_IN_US: u32 = 250;
+
+/// Musical division of the buffer by which a gate trigger rotates the start point
+pub const ROTATION_DIVISION: u8 = 8;
+
+/// Default length of a bounce into a free slot
+pub const DEFAULT_BOUNCE_SECONDS: u32 = 10;
+
+/// Gate (zero indexed) on whose rising edge a synced recording toggle gets executed
+pub const RECORD_SYNC_GATE: u8 = 3;
+
+/// Gate (zero indexed) whose clock recorded loop lengths can be quantized to
+pub const CLOCK_GATE: u8 = 2;
+
+/// Distance live grains keep to the write head while the slot they play is being recorded into
+pub const LIVE_GAP_IN_MS: f32 = 100.0;
+
+/// Input kept in front of a take while `Pre-Roll` is on
+pub const PRE_ROLL_IN_S: usize = 2;
+
+/// Length of the grains which shift the pitch of the input while `Pitch Shift` is on
+pub const PITCH_SHIFT_GRAIN_IN_MS: usize = 40;
+
+/// Level of the metronome click which is mixed to the output while recording
+pub const METRONOME_LEVEL: f32 = 0.3;
+
+/// Beats per bar of the metronome, the first one gets accented
+pub const METRONOME_BEATS_PER_BAR: usize = 4;
+
+/// Width of the pulses on the gate outputs of the `gate-outputs` feature
+pub const GATE_PULSE_IN_MS: f32 = 5.0;
+
+/// MIDI channel (zero indexed) the `midi` feature listens to, `None` listens to all channels
+pub const MIDI_CHANNEL: Option<u8> = None;
+
+/// Audio the `usb-audio` feature holds back for the host, so the drift of the clocks evens out
+pub const USB_AUDIO_LATENCY_IN_MS: usize = 8;
+
+/// Vendor and product ID of the USB audio device, the shared test IDs of pid.codes
+pub const USB_VID: u16 = 0x1209;
+pub const USB_PID: u16 = 0x0001;
+
+/// Clock of the I2C bus of the expansion panel of the `expander` feature
+pub const EXPANDER_I2C_FREQUENCY_IN_KHZ: u32 = 400;
+
+/// Actions of the buttons of an expansion panel, unassigned buttons do nothing
+pub const EXPANDER_BUTTON_ACTIONS: [TriggerAction; EXPANDER_BUTTONS] = [
+    TriggerAction::ToggleRecording,
+    TriggerAction::NextSlice,
+    TriggerAction::RotateBuffer,
+    TriggerAction::None,
+    TriggerAction::None,
+    TriggerAction::None,
+    TriggerAction::None,
+    TriggerAction::None,
+    TriggerAction::None,
+    TriggerAction::None,
+    TriggerAction::None,
+    TriggerAction::None,
+    TriggerAction::None,
+    TriggerAction::None,
+    TriggerAction::None,
+    TriggerAction::None,
+];
+
+/// Change of the echo, reverb and texture parameters per encoder detent
+pub const FX_PARAMETER_STEP: f32 = 0.01;
+
+/// Change of the depth of a modulation route per encoder detent
+pub const MOD_DEPTH_STEP: f32 = 0.05;
+
+/// Change of the rise or fall time of a parameter slew per encoder detent
+pub const SLEW_STEP_IN_MS: i32 = 10;
+
+/// Conversions averaged into one reading of every multiplexed input, indexed by
+/// `AdcMuxInputs`. Pitch gets the most, so noisy CVs do not make it flutter. At most
+/// `MAX_OVERSAMPLING`, 1 turns the filtering off.
+pub const MUX_OVERSAMPLING: [usize; 16] = [4, 4, 16, 8, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4];
+
+/// Level to which the peak of a recording gets normalized
+pub const NORMALIZE_TARGET_LEVEL: f32 = 0.9;
+
+/// Largest gain the normalization applies (about 18dB)
+pub const NORMALIZE_MAX_GAIN: f32 = 8.0;
+
+/// Offset change per encoder detent when scrubbing
+pub const OFFSET_SCRUB_STEP_IN_MS: f32 = 1.0;
+
+/// Maximum deviation of the scrubbed offset from the offset knob
+pub const OFFSET_SCRUB_RANGE_IN_S: f32 = 2.0;
+
+/// Knob movement after which the knob picks the offset up again and the scrubbing is dropped
+pub const OFFSET_PICKUP_THRESHOLD: f32 = 0.02;
+
+/// Distance to its held value at which a knob picks a parameter up after a bank switch
+pub const KNOB_PICKUP_THRESHOLD: f32 = 0.02;
+
+/// Fixed level of the granulator, leaves headroom for overlapping grains
+pub const GRANULATOR_LEVEL: f32 = 0.5;
+
+/// Grain lengths and pitch shift the grain engine spans with its grain size and pitch parameters
+pub const GRAIN_SIZE_RANGE_IN_MS: (f32, f32) = (10.0, 1000.0);
+pub const PITCH_RANGE_IN_SEMITONES: f32 = 24.0;
+
+/// Share of the block time the audio task should need at most. Above the ceiling fewer grains
+/// may start, until the load is back at the target.
+pub const GRAIN_LOAD_TARGET: f32 = 0.75;
+pub const GRAIN_LOAD_CEILING: f32 = 0.9;
+
+/// Time between grains the grain engine spans linearly with its delay parameter
+pub const GRAIN_DELAY_RANGE_IN_MS: (f32, f32) = (0.0, 1000.0);
+
+/// Time the time-stretch preview stays on the display after grain size or pitch were turned
+pub const STRETCH_PREVIEW_IN_MS: u32 = 2000;
+
+/// Time a parameter stays focused on the display after it was changed
+pub const PARAMETER_POPUP_IN_MS: u32 = 1000;
+
+/// Output gain at the lowest and the highest position of the master volume knob, the very lowest
+/// position mutes
+pub const OUTPUT_MIN_DB: f32 = -60.0;
+pub const OUTPUT_MAX_DB: f32 = 0.0;
+
+/// Duration of the ramp when the output gets muted or unmuted
+pub const MUTE_RAMP_IN_MS: f32 = 10.0;
+/// Duration of the crossfade between the monitored input and the played output
+pub const MONITOR_CROSSFADE_IN_MS: f32 = 20.0;
+/// Duration of the crossfade at the seam of the looper, which its loop is shorter by
+pub const LOOPER_CROSSFADE_IN_MS: f32 = 10.0;
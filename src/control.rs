@@ -0,0 +1,1155 @@
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+use rtic::Mutex;
+use stm32h7xx_hal::prelude::_embedded_hal_adc_OneShot;
+
+use sitira_core::{
+    bounce::BOUNCE,
+    calibration::{CALIBRATION_CHANNELS, DIRECT_CV_CHANNEL, MASTER_VOLUME_CHANNEL},
+    card::CARD,
+    erase::ERASE,
+    event::{Command, Event, Input, TimedEvent},
+    gesture::{DOUBLE_CLICK_TICKS, HOLD_TICKS},
+    grains::{GrainCloud, Window},
+    mapping::{
+        ControlMaps, Parameter, DIRECT_CV_COUNT, DIRECT_CV_PARAMETERS, EXPANDER_CHANNEL,
+        FX_PARAMETERS, MAPPED_CHANNELS,
+    },
+    menu::{MenuAction, MenuItem},
+    meter::{ClipIndicator, INPUT_METER, OUTPUT_METER, RECORD_METER},
+    midi_learn::{MidiLearn, LEARN_VIEW},
+    modulation::ModMatrix,
+    pages::PARAMETER_VIEW,
+    quantizer::{self, Quantizer},
+    record_sync::RECORD_SYNC,
+    resample::ResampleQuality,
+    rng::{Rng, GRAIN_SEED},
+    session::{Session, SESSION},
+    settings::UserSettings,
+    shift::ShiftLayer,
+    slew::SlewLimiter,
+    soak::SOAK_MONITOR,
+    stream::LOAD,
+    stretch::{StretchRanges, STRETCH_PREVIEW},
+    tempo::{self, NoteValue, TapTempo},
+    theme::THEME,
+    trim,
+};
+
+use crate::{
+    app::update_handler,
+    config::{
+        AUDIO_SAMPLE_RATE, CALIBRATION_BOOT_TICKS, CONTROL_RATE_IN_MS, FX_PARAMETER_STEP,
+        GRAIN_DELAY_RANGE_IN_MS, GRAIN_SIZE_RANGE_IN_MS, GRANULATOR_LEVEL, MOD_DEPTH_STEP,
+        PARAMETER_POPUP_IN_MS, PITCH_RANGE_IN_SEMITONES, SLEW_STEP_IN_MS,
+        SOAK_REPORT_INTERVAL_IN_S, SOAK_TEST,
+    },
+    export::EXPORT,
+    playback::{
+        apply_transport_change, erase_active_slot, format_time, get_playback_length, switch_slot,
+        undo_last_take, IS_RECORDING, LIVE_GRANULATION, LOOPER, METRONOME, OFFSET_POSITION,
+        PITCH_SHIFT, PRE_ROLL, SOURCE,
+    },
+    rgbled::Status,
+    rprintln,
+    sample_file::{MAX_SAMPLE_FILES, STREAM},
+    sdram::{CONTROL_CYCLE_FRAMES, ECHO_MAX_FRAMES},
+    sitira::ControlRate,
+    slots::{SLOTS, SLOT_COUNT},
+    watchdog::{self, Task},
+};
+
+/// Control cycles since start up
+pub static CONTROL_TICKS: AtomicU32 = AtomicU32::new(0);
+/// Control cycle of the last encoder or button activity
+pub static LAST_ACTIVITY: AtomicU32 = AtomicU32::new(0);
+/// Set while the knobs control the shift bank
+pub static SHIFT_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Control cycles after a tap in which the button can still report a gesture of it
+const TAP_GESTURE_TICKS: u32 = HOLD_TICKS + DOUBLE_CLICK_TICKS + 1;
+const PARAMETER_POPUP_TICKS: u32 = PARAMETER_POPUP_IN_MS / CONTROL_RATE_IN_MS;
+pub const STRETCH_RANGES: StretchRanges = StretchRanges {
+    min_grain_in_ms: GRAIN_SIZE_RANGE_IN_MS.0,
+    max_grain_in_ms: GRAIN_SIZE_RANGE_IN_MS.1,
+    pitch_range_in_semitones: PITCH_RANGE_IN_SEMITONES,
+};
+
+/// What the events of a cycle asked for which needs the panel readings of the same cycle.
+#[derive(Default)]
+struct PanelRequests {
+    store_scene: bool,
+    store_pad: bool,
+}
+
+/// Handles the input events of the last cycle, reads the panel and updates the settings.
+pub fn update(mut ctx: update_handler::Context) {
+    // clear TIM2 interrupt flag
+    ctx.local.cr.timer2.clear_irq();
+
+    watchdog::beat(Task::Control);
+
+    if let Some(watchdog) = ctx.local.watchdog {
+        watchdog.service();
+    }
+
+    // ----------------------------------
+    // INPUT EVENTS
+    // ----------------------------------
+
+    ctx.local.cr.poll_events(ctx.local.events);
+
+    let tick = CONTROL_TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+
+    // a synced toggle has already been executed by the audio task
+    if RECORD_SYNC.take_fired() {
+        if let Some(change) = ctx.local.transport.complete_sync() {
+            apply_transport_change(
+                change,
+                true,
+                *ctx.local.loop_quantize,
+                ctx.local.rotation,
+                &mut ctx.shared.slices,
+            );
+        }
+    }
+
+    load_session(&mut ctx);
+
+    if SOAK_TEST {
+        if let Some(command) = ctx.local.soak_schedule.tick(tick) {
+            ctx.local.events.push(TimedEvent {
+                event: Event::Command(command),
+                timestamp: cortex_m::peripheral::DWT::cycle_count(),
+            });
+        }
+
+        SOAK_MONITOR.check_meters(INPUT_METER.get_peak(), OUTPUT_METER.get_peak());
+
+        if tick.is_multiple_of(SOAK_REPORT_INTERVAL_IN_S * 1000 / CONTROL_RATE_IN_MS) {
+            rprintln!(
+                "Soak test at {} s: {} overruns, {} invalid samples, {} meter anomalies",
+                tick * CONTROL_RATE_IN_MS / 1000,
+                SOAK_MONITOR.get_overruns(),
+                SOAK_MONITOR.get_invalid_samples(),
+                SOAK_MONITOR.get_meter_anomalies()
+            );
+        }
+    }
+
+    let mut requests = PanelRequests::default();
+
+    while let Some(timed) = ctx.local.events.pop() {
+        handle_event(&mut ctx, timed, tick, &mut requests);
+    }
+
+    update_leds(
+        ctx.local.cr,
+        ctx.local.clip_indicator,
+        ctx.local.transport.is_recording(),
+    );
+
+    update_settings(&mut ctx, &requests);
+}
+
+/// Takes over a session loaded by the idle task and loads its samples one after another.
+fn load_session(ctx: &mut update_handler::Context) {
+    // a session loaded by the idle task takes over the kit, the scenes and the mappings, its
+    // samples then get loaded one slot after another
+    if SESSION.take_loaded() {
+        let session = ctx.shared.session.lock(|session| *session);
+
+        ctx.shared
+            .kit
+            .lock(|kit| session.apply(kit, ctx.local.scenes, ctx.local.midi_learn));
+
+        if let Some(maps) = session.maps {
+            ctx.local.shift_layer.set_maps(maps);
+            *ctx.local.mod_matrix = maps.matrix;
+        }
+
+        *ctx.local.session_queue = Some(session);
+        rprintln!("Loaded the session!");
+    }
+
+    if !ctx.local.transport.is_recording() && !LOAD.is_running() {
+        if let Some(session) = ctx.local.session_queue.as_mut() {
+            match session.take_next_sample() {
+                Some((slot, number)) if slot < SLOT_COUNT => {
+                    if slot != SLOTS.get_active() {
+                        switch_slot(slot as i32 - SLOTS.get_active() as i32);
+                    }
+
+                    LOAD.request(slot, number, ResampleQuality::Polyphase);
+                }
+                Some(_) => (),
+                None => {
+                    let active = session.active_slot.min(SLOT_COUNT - 1);
+
+                    if active != SLOTS.get_active() {
+                        switch_slot(active as i32 - SLOTS.get_active() as i32);
+                    }
+
+                    *ctx.local.session_queue = None;
+                }
+            }
+        }
+    }
+}
+
+/// Passes an event to everything which handles input, in the order they take it.
+fn handle_event(
+    ctx: &mut update_handler::Context,
+    timed: TimedEvent,
+    tick: u32,
+    requests: &mut PanelRequests,
+) {
+    let event = timed.event;
+
+    if event.is_user_activity() {
+        LAST_ACTIVITY.store(tick, Ordering::Relaxed);
+    }
+
+    // holding the encoder at boot enters the calibration, which then takes over the panel
+    let calibrating = ctx.shared.calibration.lock(|calibration| {
+        match event {
+            Event::Hold(Input::EncoderSwitch) if tick <= CALIBRATION_BOOT_TICKS => {
+                calibration.start()
+            }
+            Event::Click(Input::EncoderSwitch) if calibration.is_active() => calibration.advance(),
+            _ => (),
+        }
+
+        calibration.is_active()
+    });
+
+    // the calibration swallows the release of the encoder, which would leave shift on
+    if calibrating {
+        set_shift(ctx.local.shift_layer, false);
+        return;
+    }
+
+    // while an erase asks for confirmation, a click confirms and other input cancels
+    if ERASE.is_confirming() {
+        match event {
+            Event::Click(Input::EncoderSwitch) => {
+                if !ctx.local.transport.is_recording() {
+                    erase_active_slot();
+                } else {
+                    ERASE.cancel();
+                }
+
+                return;
+            }
+            Event::Pressed(Input::Button)
+            | Event::DoubleClick(_)
+            | Event::Hold(_)
+            | Event::EncoderTurned { .. } => {
+                ERASE.cancel();
+                return;
+            }
+            _ => (),
+        }
+    }
+
+    // while the encoder is held, the button taps the tempo, the gestures which trail the
+    // last tap get swallowed as well
+    let since_tap = ctx.local.last_tap.map(|last| tick.wrapping_sub(last));
+    let tapping = ctx.local.shift_layer.is_shifted()
+        || matches!(since_tap, Some(ticks) if ticks <= TAP_GESTURE_TICKS);
+
+    match event {
+        Event::Pressed(Input::Button) if ctx.local.shift_layer.is_shifted() => {
+            tap_tempo(ctx.local.tap_tempo, tick);
+            ctx.local.shift_layer.mark_used();
+            *ctx.local.last_tap = Some(tick);
+            return;
+        }
+        Event::Released(Input::Button)
+        | Event::Click(Input::Button)
+        | Event::DoubleClick(Input::Button)
+        | Event::Hold(Input::Button)
+            if tapping =>
+        {
+            return
+        }
+        _ => (),
+    }
+
+    // in kit mode the pads take their gates and notes before anything else
+    if ctx.shared.kit.lock(|kit| kit.claim(&event)) {
+        return;
+    }
+
+    // gates are translated into commands before anything else
+    let event = ctx.local.routing.route(event);
+
+    let was_armed = ctx.local.transport.is_armed();
+
+    if let Some(change) = ctx.local.transport.handle(&event) {
+        apply_transport_change(
+            change,
+            false,
+            *ctx.local.loop_quantize,
+            ctx.local.rotation,
+            &mut ctx.shared.slices,
+        );
+    }
+
+    if ctx.local.transport.is_armed() != was_armed {
+        if ctx.local.transport.is_armed() {
+            RECORD_SYNC.arm();
+        } else {
+            RECORD_SYNC.disarm();
+        }
+    }
+
+    let menu_action = ctx.shared.menu.lock(|menu| menu.handle(&event));
+
+    if let Some(action) = menu_action {
+        handle_menu_action(ctx, action, requests);
+    }
+
+    match event {
+        Event::Command(Command::RotateBuffer) => ctx.local.rotation.trigger(),
+        Event::Command(Command::NextSlice) => ctx.shared.slices.lock(|slices| slices.select_next()),
+        Event::Command(Command::JumpToSlice(index)) => ctx
+            .shared
+            .slices
+            .lock(|slices| slices.select(index as usize)),
+        Event::Command(Command::RecallScene(index)) => ctx.local.scenes.recall(index as usize),
+        // the knobs control the shift bank while the encoder is held, where the effects
+        // start from their current settings
+        Event::Pressed(Input::EncoderSwitch) => {
+            ctx.shared.engine_settings.lock(|settings| {
+                for parameter in FX_PARAMETERS {
+                    if let Some(value) = settings.get(parameter) {
+                        ctx.local.shift_layer.set(parameter, value);
+                    }
+                }
+            });
+
+            set_shift(ctx.local.shift_layer, true);
+            *ctx.local.undo_armed = false;
+        }
+        // holding it on MIDI Learn listens for a controller instead
+        Event::Hold(Input::EncoderSwitch) => {
+            if ctx.shared.menu.lock(|menu| menu.get_selected_item()) == MenuItem::MidiLearn {
+                ctx.local.midi_learn.set_learning(true);
+                rprintln!(
+                    "Learning a controller for {:?}",
+                    ctx.local.midi_learn.get_selected()
+                );
+            } else {
+                *ctx.local.undo_armed = true;
+            }
+        }
+        // the audio task mutes the output as long as the kill gate is high
+        Event::Pressed(Input::KillGate) | Event::Released(Input::KillGate) => {
+            ctx.local.cr.audio_events.push(timed);
+        }
+        // holding the encoder discards the last take, unless the shift bank got used
+        Event::Released(Input::EncoderSwitch) => {
+            let undo = *ctx.local.undo_armed && !ctx.local.shift_layer.is_used();
+
+            if undo && !ctx.local.transport.is_recording() && !ERASE.is_running() {
+                undo_last_take();
+            }
+
+            set_shift(ctx.local.shift_layer, false);
+            *ctx.local.undo_armed = false;
+        }
+        Event::Command(Command::UndoTake)
+            if !ctx.local.transport.is_recording() && !ERASE.is_running() =>
+        {
+            undo_last_take()
+        }
+        // a press on the button toggles the recording, see the transport
+        Event::DoubleClick(Input::Button) if !ctx.local.transport.is_recording() => switch_slot(1),
+        Event::Hold(Input::Button) if !ctx.local.transport.is_recording() => {
+            ERASE.ask();
+        }
+        Event::ControlChange { .. } => {
+            ctx.local.midi_learn.handle(&event);
+        }
+        // notes above the slice notes got routed already, the rest transpose the grains
+        Event::NoteOn { note, .. } => {
+            *ctx.local.midi_transpose = quantizer::note_to_semitones(note)
+        }
+        _ => (),
+    }
+}
+
+/// Carries out what the menu made of an event.
+fn handle_menu_action(
+    ctx: &mut update_handler::Context,
+    action: MenuAction,
+    requests: &mut PanelRequests,
+) {
+    match action {
+        MenuAction::Adjust(MenuItem::OffsetFine, steps) => ctx.local.scrub.scrub(steps),
+        MenuAction::Adjust(MenuItem::Parameters, steps) => PARAMETER_VIEW.step_page(steps),
+        MenuAction::Adjust(MenuItem::RotationDivision, steps) => {
+            ctx.local.rotation.step_division(steps)
+        }
+        MenuAction::Adjust(MenuItem::Slot, steps) => {
+            // slots can only be switched while playing back
+            if !ctx.local.transport.is_recording() {
+                switch_slot(steps);
+            }
+        }
+        MenuAction::Execute(MenuItem::EraseSlot) if !ctx.local.transport.is_recording() => {
+            ERASE.ask();
+        }
+        MenuAction::Adjust(MenuItem::SecondSlot, steps) => {
+            ctx.shared.engine_settings.lock(|settings| {
+                settings.second_slot = step_second_slot(settings.second_slot, steps);
+                rprintln!("Second stream plays slot {:?}!", settings.second_slot);
+            });
+        }
+        MenuAction::Adjust(MenuItem::BounceLength, steps) => {
+            *ctx.local.bounce_seconds =
+                (*ctx.local.bounce_seconds as i32 + steps).clamp(1, 60) as u32;
+        }
+        MenuAction::Execute(MenuItem::Bounce) => match SLOTS.find_free() {
+            Some(slot) => {
+                let length = *ctx.local.bounce_seconds as usize * AUDIO_SAMPLE_RATE as usize;
+
+                if BOUNCE.request(slot, length) {
+                    rprintln!(
+                        "Bouncing {} into slot {}!",
+                        format_time(length).as_str(),
+                        slot
+                    );
+                }
+            }
+            None => {
+                rprintln!("No free slot to bounce into!");
+            }
+        },
+        MenuAction::Adjust(MenuItem::CurveChannel, steps) => ctx
+            .shared
+            .curves
+            .lock(|curves| curves.select_channel(steps)),
+        MenuAction::Adjust(MenuItem::CurvePoints, steps) => ctx
+            .shared
+            .curves
+            .lock(|curves| curves.change_point_count(steps)),
+        MenuAction::Adjust(MenuItem::CurvePoint, steps) => {
+            ctx.shared.curves.lock(|curves| curves.select_point(steps))
+        }
+        MenuAction::Adjust(MenuItem::CurveInput, steps) => {
+            ctx.shared.curves.lock(|curves| curves.move_point(steps, 0))
+        }
+        MenuAction::Adjust(MenuItem::CurveOutput, steps) => {
+            ctx.shared.curves.lock(|curves| curves.move_point(0, steps))
+        }
+        MenuAction::Execute(MenuItem::CurveReset) => {
+            ctx.shared.curves.lock(|curves| curves.reset())
+        }
+        MenuAction::Adjust(MenuItem::Scene, steps) => ctx.local.scenes.select(steps),
+        // the values are only known after the ADCs have been read
+        MenuAction::Execute(MenuItem::SceneStore) => requests.store_scene = true,
+        MenuAction::Adjust(MenuItem::MorphSceneA, steps) => ctx.local.scenes.step_scene_a(steps),
+        MenuAction::Adjust(MenuItem::MorphSceneB, steps) => ctx.local.scenes.step_scene_b(steps),
+        MenuAction::Adjust(MenuItem::MorphSource, steps) => ctx.local.scenes.step_source(steps),
+        MenuAction::Adjust(MenuItem::SceneGates, _) => {
+            let scene_gates = !ctx.local.routing.is_scene_gates();
+            ctx.local.routing.set_scene_gates(scene_gates);
+            rprintln!("Scene gates {}!", if scene_gates { "on" } else { "off" });
+        }
+        MenuAction::Adjust(MenuItem::SceneGlide, steps) => {
+            ctx.local.scenes.step_glide(steps);
+            rprintln!("Scene glide {} ms!", ctx.local.scenes.get_glide());
+        }
+        MenuAction::Adjust(MenuItem::Automate, _) => {
+            let recording = !ctx.local.automation.is_recording();
+            ctx.local.automation.set_recording(recording);
+            rprintln!(
+                "Automation {}!",
+                if recording { "recording" } else { "playing" }
+            );
+        }
+        MenuAction::Adjust(MenuItem::AutomationBlend, steps) => {
+            ctx.local.automation.step_blend(steps);
+            rprintln!(
+                "Automation blend {}%!",
+                (ctx.local.automation.get_blend() * 100.0) as u32
+            );
+        }
+        MenuAction::Execute(MenuItem::AutomationClear) => {
+            ctx.local.automation.clear();
+            rprintln!("Cleared the automation!");
+        }
+        MenuAction::Adjust(MenuItem::InputTrim, steps) => {
+            trim::step(steps);
+            rprintln!("Input trim {}!", trim::format_trim(trim::get_db()).as_str());
+        }
+        MenuAction::Adjust(MenuItem::RecordSync, _) => {
+            ctx.local
+                .transport
+                .set_sync(!ctx.local.transport.is_sync_enabled());
+            RECORD_SYNC.disarm();
+        }
+        MenuAction::Adjust(MenuItem::LoopQuantize, _) => {
+            *ctx.local.loop_quantize = !*ctx.local.loop_quantize
+        }
+        MenuAction::Adjust(MenuItem::Metronome, _) => {
+            let metronome = !METRONOME.load(Ordering::Relaxed);
+            METRONOME.store(metronome, Ordering::Relaxed);
+            rprintln!("Metronome {}!", if metronome { "on" } else { "off" });
+        }
+        MenuAction::Adjust(MenuItem::LiveGranulation, _) => {
+            let live = !LIVE_GRANULATION.load(Ordering::Relaxed);
+            LIVE_GRANULATION.store(live, Ordering::Relaxed);
+            rprintln!("Live granulation {}!", if live { "on" } else { "off" });
+        }
+        MenuAction::Adjust(MenuItem::PreRoll, _) => {
+            let pre_roll = !PRE_ROLL.load(Ordering::Relaxed);
+            PRE_ROLL.store(pre_roll, Ordering::Relaxed);
+            rprintln!("Pre-roll {}!", if pre_roll { "on" } else { "off" });
+        }
+        MenuAction::Adjust(MenuItem::Looper, _) => {
+            let looper = !LOOPER.load(Ordering::Relaxed);
+            LOOPER.store(looper, Ordering::Relaxed);
+            rprintln!("Looper {}!", if looper { "on" } else { "off" });
+        }
+        MenuAction::Adjust(MenuItem::LoopDecay, steps) => ctx
+            .shared
+            .engine_settings
+            .lock(|settings| settings.loop_decay = step_fx_parameter(settings.loop_decay, steps)),
+        MenuAction::Adjust(MenuItem::PitchShift, _) => {
+            let shift = !PITCH_SHIFT.load(Ordering::Relaxed);
+            PITCH_SHIFT.store(shift, Ordering::Relaxed);
+            rprintln!("Pitch shift {}!", if shift { "on" } else { "off" });
+        }
+        MenuAction::Adjust(MenuItem::Scale, steps) => {
+            ctx.local.quantizer.step_scale(steps);
+            rprintln!("Scale {}!", ctx.local.quantizer.get_scale().name());
+        }
+        MenuAction::Adjust(MenuItem::Mode, steps) => {
+            ctx.local.quantizer.step_mode(steps);
+            rprintln!("Mode {}!", ctx.local.quantizer.get_mode().name());
+        }
+        MenuAction::Adjust(MenuItem::Root, steps) => {
+            ctx.local.quantizer.step_root(steps);
+            rprintln!("Root {}!", ctx.local.quantizer.get_root_name());
+        }
+        MenuAction::Adjust(MenuItem::FineTune, steps) => {
+            ctx.local.quantizer.step_fine_tune(steps);
+            rprintln!("Fine tune {} cents!", ctx.local.quantizer.get_fine_tune());
+        }
+        MenuAction::Adjust(MenuItem::EchoTime, steps) => ctx
+            .shared
+            .engine_settings
+            .lock(|settings| settings.echo_time = step_fx_parameter(settings.echo_time, steps)),
+        MenuAction::Adjust(MenuItem::GrainSeed, steps) => {
+            GRAIN_SEED.step(steps);
+            rprintln!("Seed {:08X}!", GRAIN_SEED.get());
+        }
+        // the moment of the click is as good as random
+        MenuAction::Execute(MenuItem::Reroll) => {
+            GRAIN_SEED.set(Rng::new(cortex_m::peripheral::DWT::cycle_count()).next_u32());
+            rprintln!("Seed {:08X}!", GRAIN_SEED.get());
+        }
+        MenuAction::Adjust(MenuItem::Swing, steps) => ctx
+            .shared
+            .engine_settings
+            .lock(|settings| settings.grain_swing = step_fx_parameter(settings.grain_swing, steps)),
+        MenuAction::Adjust(MenuItem::Humanize, steps) => {
+            ctx.shared.engine_settings.lock(|settings| {
+                settings.grain_humanize = step_fx_parameter(settings.grain_humanize, steps)
+            })
+        }
+        MenuAction::Adjust(MenuItem::GrainAttack, steps) => {
+            ctx.shared.engine_settings.lock(|settings| {
+                settings.grain_attack = step_fx_parameter(settings.grain_attack, steps)
+            })
+        }
+        MenuAction::Adjust(MenuItem::GrainDecay, steps) => ctx
+            .shared
+            .engine_settings
+            .lock(|settings| settings.grain_decay = step_fx_parameter(settings.grain_decay, steps)),
+        MenuAction::Adjust(MenuItem::AttackSpread, steps) => {
+            ctx.shared.engine_settings.lock(|settings| {
+                settings.grain_attack_spread =
+                    step_fx_parameter(settings.grain_attack_spread, steps)
+            })
+        }
+        MenuAction::Adjust(MenuItem::DecaySpread, steps) => {
+            ctx.shared.engine_settings.lock(|settings| {
+                settings.grain_decay_spread = step_fx_parameter(settings.grain_decay_spread, steps)
+            })
+        }
+        MenuAction::Adjust(MenuItem::DelaySync, _) => {
+            *ctx.local.delay_sync = !*ctx.local.delay_sync
+        }
+        MenuAction::Adjust(MenuItem::NoteValues, _) => {
+            *ctx.local.note_values = !*ctx.local.note_values
+        }
+        MenuAction::Adjust(MenuItem::EchoSync, _) => *ctx.local.echo_sync = !*ctx.local.echo_sync,
+        MenuAction::Adjust(MenuItem::EchoFeedback, steps) => {
+            ctx.shared.engine_settings.lock(|settings| {
+                settings.echo_feedback = step_fx_parameter(settings.echo_feedback, steps)
+            })
+        }
+        MenuAction::Adjust(MenuItem::EchoMix, steps) => ctx
+            .shared
+            .engine_settings
+            .lock(|settings| settings.echo_mix = step_fx_parameter(settings.echo_mix, steps)),
+        MenuAction::Adjust(MenuItem::ReverbSize, steps) => ctx
+            .shared
+            .engine_settings
+            .lock(|settings| settings.reverb_size = step_fx_parameter(settings.reverb_size, steps)),
+        MenuAction::Adjust(MenuItem::ReverbMix, steps) => ctx
+            .shared
+            .engine_settings
+            .lock(|settings| settings.reverb_mix = step_fx_parameter(settings.reverb_mix, steps)),
+        MenuAction::Adjust(MenuItem::TextureCrush, steps) => {
+            ctx.shared.engine_settings.lock(|settings| {
+                settings.texture_crush = step_fx_parameter(settings.texture_crush, steps)
+            })
+        }
+        MenuAction::Adjust(MenuItem::TextureDownsample, steps) => {
+            ctx.shared.engine_settings.lock(|settings| {
+                settings.texture_downsample = step_fx_parameter(settings.texture_downsample, steps)
+            })
+        }
+        MenuAction::Adjust(MenuItem::MacroRoute, steps) => {
+            ctx.local.mod_matrix.select(steps);
+            log_mod_route(ctx.local.mod_matrix);
+        }
+        MenuAction::Adjust(MenuItem::MacroDepth, steps) => {
+            ctx.local
+                .mod_matrix
+                .adjust_depth(steps as f32 * MOD_DEPTH_STEP);
+            log_mod_route(ctx.local.mod_matrix);
+        }
+        MenuAction::Adjust(MenuItem::SlewParameter, steps) => {
+            ctx.local.slew.select(steps);
+            log_slew(ctx.local.slew);
+        }
+        MenuAction::Adjust(MenuItem::SlewRise, steps) => {
+            ctx.local.slew.adjust_rise(steps * SLEW_STEP_IN_MS);
+            log_slew(ctx.local.slew);
+        }
+        MenuAction::Adjust(MenuItem::SlewFall, steps) => {
+            ctx.local.slew.adjust_fall(steps * SLEW_STEP_IN_MS);
+            log_slew(ctx.local.slew);
+        }
+        MenuAction::Adjust(MenuItem::MidiLearn, steps) => {
+            ctx.local.midi_learn.select(steps);
+            log_midi_learn(ctx.local.midi_learn);
+        }
+        MenuAction::Execute(MenuItem::MidiClear) => {
+            ctx.local.midi_learn.clear();
+            log_midi_learn(ctx.local.midi_learn);
+        }
+        MenuAction::Adjust(MenuItem::ExportFormat, _) => {
+            *ctx.local.export_format = ctx.local.export_format.toggle();
+            rprintln!("Exporting as {}!", ctx.local.export_format.name());
+        }
+        MenuAction::Adjust(MenuItem::EditZoom, steps) => {
+            ctx.shared.editor.lock(|editor| editor.zoom(steps))
+        }
+        MenuAction::Adjust(MenuItem::EditScroll, steps) => {
+            ctx.shared.editor.lock(|editor| editor.scroll(steps))
+        }
+        MenuAction::Adjust(MenuItem::TrimStart, steps) => {
+            ctx.shared.editor.lock(|editor| editor.step_start(steps))
+        }
+        MenuAction::Adjust(MenuItem::TrimEnd, steps) => {
+            ctx.shared.editor.lock(|editor| editor.step_end(steps))
+        }
+        MenuAction::Execute(MenuItem::TrimReset) => {
+            ctx.shared.editor.lock(|editor| editor.reset_trim())
+        }
+        MenuAction::Adjust(MenuItem::KitMode, _) => ctx.shared.kit.lock(|kit| {
+            kit.set_enabled(!kit.is_enabled());
+            rprintln!("Kit mode {}!", if kit.is_enabled() { "on" } else { "off" });
+        }),
+        MenuAction::Adjust(MenuItem::KitPad, steps) => ctx.shared.kit.lock(|kit| kit.select(steps)),
+        MenuAction::Adjust(MenuItem::KitSlot, steps) => {
+            ctx.shared.kit.lock(|kit| kit.step_slot(steps, SLOT_COUNT))
+        }
+        MenuAction::Adjust(MenuItem::KitNote, steps) => {
+            ctx.shared.kit.lock(|kit| kit.step_note(steps))
+        }
+        MenuAction::Adjust(MenuItem::KitGate, steps) => {
+            ctx.shared.kit.lock(|kit| kit.step_gate(steps))
+        }
+        MenuAction::Adjust(MenuItem::KitBurst, steps) => {
+            ctx.shared.kit.lock(|kit| kit.step_burst(steps))
+        }
+        // the settings are only known after the ADCs have been read
+        MenuAction::Execute(MenuItem::KitStore) => requests.store_pad = true,
+        // the file actions need a usable SD card, the display shows that there is none
+        MenuAction::Execute(
+            item @ (MenuItem::Export
+            | MenuItem::LoadSample
+            | MenuItem::SaveSession
+            | MenuItem::LoadSession),
+        ) if !CARD.is_ready() => {
+            if let Err(error) = CARD.check() {
+                rprintln!(
+                    "{} needs the SD card: {}!",
+                    item.name(),
+                    error.description()
+                );
+            }
+        }
+        MenuAction::Execute(MenuItem::RetryCard) => {
+            if CARD.request_retry() {
+                rprintln!("Mounting the SD card again!");
+            }
+        }
+        MenuAction::Execute(MenuItem::SaveSession) => {
+            let maps = ControlMaps {
+                matrix: *ctx.local.mod_matrix,
+                ..*ctx.local.shift_layer.get_maps()
+            };
+            let mut session = ctx
+                .shared
+                .kit
+                .lock(|kit| Session::capture(kit, ctx.local.scenes, ctx.local.midi_learn, &maps));
+
+            session.active_slot = SLOTS.get_active();
+
+            for (slot, sample) in session.samples.iter_mut().take(SLOT_COUNT).enumerate() {
+                *sample = SLOTS
+                    .get_sample(slot)
+                    .filter(|_| SLOTS.get_length(slot) > 0);
+            }
+
+            ctx.shared.session.lock(|shared| *shared = session);
+            SESSION.request_save();
+        }
+        // the session replaces the samples of the slots, like loading a sample does
+        MenuAction::Execute(MenuItem::LoadSession) if !ctx.local.transport.is_recording() => {
+            SESSION.request_load();
+        }
+        MenuAction::Adjust(MenuItem::Theme, steps) => {
+            THEME.select(THEME.get().step(steps));
+            rprintln!("Theme {}!", THEME.get().name());
+        }
+        // only a finished recording can be exported
+        MenuAction::Execute(MenuItem::Export) if !ctx.local.transport.is_recording() => {
+            let slot = SLOTS.get_active();
+            let length = SOURCE.len();
+
+            if length > 0 && EXPORT.request(slot, length, *ctx.local.export_format) {
+                rprintln!(
+                    "Exporting {} of slot {}!",
+                    format_time(length).as_str(),
+                    slot
+                );
+            }
+        }
+        MenuAction::Adjust(MenuItem::Sample, steps) => {
+            let number = *ctx.local.sample_number as i32 + steps;
+            *ctx.local.sample_number = number.rem_euclid(MAX_SAMPLE_FILES as i32) as u32;
+            rprintln!("Sample file {:02}!", *ctx.local.sample_number);
+        }
+        MenuAction::Adjust(MenuItem::LoadQuality, _) => {
+            *ctx.local.load_quality = ctx.local.load_quality.toggle();
+            rprintln!("Converting rates {}!", ctx.local.load_quality.name());
+        }
+        // a sample replaces the active slot, which can not happen while recording into it
+        MenuAction::Execute(MenuItem::LoadSample) if !ctx.local.transport.is_recording() => {
+            let slot = SLOTS.get_active();
+
+            if LOAD.request(slot, *ctx.local.sample_number, *ctx.local.load_quality) {
+                rprintln!(
+                    "Loading sample file {:02} into slot {}!",
+                    *ctx.local.sample_number,
+                    slot
+                );
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Shows the gates, clipping and the recording state on the LEDs.
+fn update_leds(cr: &mut ControlRate, clip_indicator: &mut ClipIndicator, recording: bool) {
+    let board = cr.board;
+
+    // LED1 and LED2 mirror the gate inputs, unless their pins are gate outputs, the MIDI input or
+    // the bus of an expansion panel
+    #[cfg(not(any(feature = "gate-outputs", feature = "expander")))]
+    board.set_led(
+        &mut cr.led1,
+        cr.gate1.is_saved_state_high() || cr.gate3.is_saved_state_high(),
+    );
+    #[cfg(not(any(feature = "gate-outputs", feature = "midi", feature = "expander")))]
+    board.set_led(
+        &mut cr.led2,
+        cr.gate2.is_saved_state_high() || cr.is_gate4_high(),
+    );
+
+    // LED3 flashes on clipping and shows the recording state otherwise
+    if INPUT_METER.take_clipped() || RECORD_METER.take_clipped() || OUTPUT_METER.take_clipped() {
+        clip_indicator.trigger();
+    }
+
+    let clip_flash = clip_indicator.tick();
+    let led3_state = clip_flash.unwrap_or(recording);
+
+    board.set_led(&mut cr.led3, led3_state);
+
+    // status LED mirrors the same information in color
+    let status = match clip_flash {
+        Some(lit) => Status::Clipping(lit),
+        None if recording => Status::Recording,
+        None => Status::Playing,
+    };
+
+    cr.status_led.show_status(status);
+}
+
+/// Reads the knobs and CV inputs and turns them into the settings of the engines.
+fn update_settings(ctx: &mut update_handler::Context, requests: &PanelRequests) {
+    let adc_values = &mut ctx.local.cr.muxed_parameters;
+    let adc2 = &mut ctx.local.cr.adc2;
+    let master_volume = &mut ctx.local.cr.master_volume;
+    let direct_cvs = &mut ctx.local.cr.direct_cvs;
+
+    // read from ADC1, the inputs keep their last values if the multiplexers can not be switched
+    for i in 0..16 {
+        if adc_values.read_value(i).is_err() {
+            break;
+        }
+    }
+
+    // read from ADC2
+    if let Ok(data) = adc2.read(master_volume.get_pin()) {
+        master_volume.update(data);
+    }
+
+    direct_cvs.read(adc2);
+
+    let mut raw = [0.0; CALIBRATION_CHANNELS];
+
+    for (channel, value) in raw.iter_mut().take(16).enumerate() {
+        *value = adc_values.get_value(channel);
+    }
+
+    raw[MASTER_VOLUME_CHANNEL] = master_volume.get_value();
+    raw[DIRECT_CV_CHANNEL..][..DIRECT_CV_COUNT].copy_from_slice(&direct_cvs.get_values());
+
+    // readings are calibrated first
+    let calibrated = ctx.shared.calibration.lock(|calibration| {
+        calibration.measure(&raw);
+
+        let mut calibrated = raw;
+        for (channel, value) in calibrated.iter_mut().enumerate() {
+            *value = calibration.apply(channel, *value);
+        }
+
+        calibrated
+    });
+
+    // then every channel is shaped by its response curve
+    let mut values = [0.0; 16];
+
+    ctx.shared.curves.lock(|curves| {
+        for (channel, value) in values.iter_mut().enumerate() {
+            *value = curves.apply(channel, calibrated[channel]);
+        }
+    });
+
+    let scenes = &mut ctx.local.scenes;
+
+    if requests.store_scene {
+        scenes.store(&values);
+    }
+
+    // the morph source moves linearly from scene A (0V) to scene B (5V)
+    if let Some(source) = scenes.get_source() {
+        scenes.morph(calibrated[source], &mut values);
+    }
+
+    // a recalled scene glides in and holds until the knobs get turned
+    scenes.apply_recall(&mut values, CONTROL_RATE_IN_MS);
+
+    // the knobs of an expansion panel follow the ones of the panel
+    let mut channels = [0.0; MAPPED_CHANNELS];
+    channels[..EXPANDER_CHANNEL].copy_from_slice(&values);
+    channels[EXPANDER_CHANNEL..].copy_from_slice(&ctx.local.cr.expander.get_knobs());
+
+    // the mapping of the active bank decides which channel controls which parameter
+    let shift_layer = &ctx.local.shift_layer;
+    let mut parameters = shift_layer.apply(&channels);
+
+    // the direct CVs are no knobs, they only take part as sources of the matrix
+    for (channel, parameter) in DIRECT_CV_PARAMETERS.iter().enumerate() {
+        parameters.set(*parameter, calibrated[DIRECT_CV_CHANNEL + channel]);
+    }
+
+    // learned MIDI controllers move their parameters like a knob would
+    ctx.local.midi_learn.apply(&mut parameters);
+    LEARN_VIEW.publish(ctx.local.midi_learn);
+
+    let source_length = get_playback_length();
+
+    // recorded knob movements loop along with the take and play over the knobs
+    let automation = &mut ctx.local.automation;
+    automation.sync(IS_RECORDING.load(Ordering::Relaxed));
+    automation.set_length(source_length / CONTROL_CYCLE_FRAMES);
+
+    let parameters = automation.apply(&parameters);
+    PARAMETER_VIEW.set_automated(automation.get_automated());
+
+    // the macro and other routes move several parameters at once
+    let parameters = ctx.local.mod_matrix.apply(&parameters);
+
+    // stepped CVs glide to their next value as slow as the slew of their parameter allows
+    let parameters = ctx.local.slew.apply(&parameters);
+
+    // the parameter being changed gets focused on the display for a while
+    if let Some(parameter) = ctx.local.change_detector.detect(&parameters) {
+        PARAMETER_VIEW.focus(parameter, PARAMETER_POPUP_TICKS);
+    }
+
+    PARAMETER_VIEW.publish(&parameters);
+
+    // offset gets scrubbed and rotated first and then confined to the selected slice, or to
+    // the trim while none is selected
+    let offset = ctx
+        .local
+        .scrub
+        .apply(parameters.get(Parameter::Offset), source_length);
+    let offset = ctx.local.rotation.apply(offset);
+    let slices = &mut ctx.shared.slices;
+    let offset = ctx.shared.editor.lock(|editor| {
+        editor.follow(SLOTS.get_active(), source_length);
+
+        slices.lock(|slices| match slices.get_selected() {
+            Some(_) => slices.apply(offset),
+            None => editor.apply(offset),
+        })
+    });
+    let position = (offset * source_length as f32) as usize;
+
+    OFFSET_POSITION.store(position, Ordering::Relaxed);
+    STREAM.set_position(position);
+
+    // the pitch knob with its CV input and the MIDI transpose meet in semitones, so the
+    // quantizer snaps them all to the same tuning, shifted by root and fine tune
+    let semitones =
+        STRETCH_RANGES.semitones(parameters.get(Parameter::Pitch)) + *ctx.local.midi_transpose;
+    let pitch = STRETCH_RANGES.pitch(ctx.local.quantizer.tune(semitones));
+
+    let grain_size = parameters.get(Parameter::GrainSize);
+    let delay = parameters.get(Parameter::Delay);
+    let beat_in_ms =
+        tempo::get_period().map(|period| period as f32 * 1000.0 / AUDIO_SAMPLE_RATE as f32);
+    let (min_delay, max_delay) = GRAIN_DELAY_RANGE_IN_MS;
+    let delay_from_ms = |ms: f32| ((ms - min_delay) / (max_delay - min_delay)).clamp(0.0, 1.0);
+
+    // in note values the grain size and delay knobs pick note values of the tempo, otherwise a
+    // synced grain delay snaps to the nearest note value
+    let (grain_size, delay, note_values) = match beat_in_ms {
+        Some(beat_in_ms) if *ctx.local.note_values => {
+            let grain_note = NoteValue::from_parameter(grain_size);
+            let delay_note = NoteValue::from_parameter(delay);
+
+            (
+                STRETCH_RANGES.grain_size(grain_note.get_length(beat_in_ms)),
+                delay_from_ms(delay_note.get_length(beat_in_ms)),
+                Some((grain_note, delay_note)),
+            )
+        }
+        Some(beat_in_ms) if *ctx.local.delay_sync => {
+            let delay_in_ms = min_delay + delay * (max_delay - min_delay);
+            let snapped = tempo::snap_to_division(delay_in_ms, beat_in_ms, max_delay);
+
+            (grain_size, delay_from_ms(snapped), None)
+        }
+        _ => (grain_size, delay, None),
+    };
+
+    // the second stream is tuned and sized like the first one, its offset spans the whole take
+    let second_semitones = STRETCH_RANGES.semitones(parameters.get(Parameter::SecondPitch))
+        + *ctx.local.midi_transpose;
+    let second_pitch = STRETCH_RANGES.pitch(ctx.local.quantizer.tune(second_semitones));
+    let second_grain_size = match beat_in_ms {
+        Some(beat_in_ms) if note_values.is_some() => STRETCH_RANGES.grain_size(
+            NoteValue::from_parameter(parameters.get(Parameter::SecondGrainSize))
+                .get_length(beat_in_ms),
+        ),
+        _ => parameters.get(Parameter::SecondGrainSize),
+    };
+
+    STRETCH_PREVIEW.publish_note_values(note_values);
+
+    let mut kit_enabled = false;
+
+    // update user settings
+    ctx.shared.user_settings.lock(|settings| {
+        settings.master_volume = GRANULATOR_LEVEL;
+        settings.active_grains = parameters.get(Parameter::ActiveGrains);
+        settings.offset = offset;
+        settings.grain_size = grain_size;
+        settings.pitch = pitch;
+        settings.delay = delay;
+        settings.velocity = parameters.get(Parameter::Velocity);
+        settings.sp_offset = parameters.get(Parameter::OffsetSpread);
+        settings.sp_grain_size = parameters.get(Parameter::GrainSizeSpread);
+        settings.sp_pitch = parameters.get(Parameter::PitchSpread);
+        settings.sp_velocity = parameters.get(Parameter::VelocitySpread);
+        settings.sp_delay = parameters.get(Parameter::DelaySpread);
+        settings.window_function =
+            Window::from_parameter(parameters.get(Parameter::Envelope)) as u8;
+        settings.window_param = parameters.get(Parameter::WaveSelect);
+        settings.tuning = *ctx.local.quantizer;
+
+        STRETCH_PREVIEW.publish(settings.grain_size, settings.pitch);
+
+        // a pad stores what the knobs are set to, the cloud itself is muted in kit mode
+        ctx.shared.kit.lock(|kit| {
+            kit.set_tuning(settings.tuning);
+
+            if requests.store_pad {
+                kit.store(settings);
+            }
+
+            kit_enabled = kit.is_enabled();
+
+            if kit_enabled {
+                settings.active_grains = 0.0;
+            }
+        });
+    });
+
+    ctx.shared.engine_settings.lock(|settings| {
+        settings.varispeed_speed = parameters.get(Parameter::VarispeedSpeed);
+        settings.engine_blend = parameters.get(Parameter::EngineBlend);
+        settings.master_volume = calibrated[MASTER_VOLUME_CHANNEL];
+        settings.second_offset = parameters.get(Parameter::SecondOffset);
+        settings.second_grain_size = second_grain_size;
+        settings.second_pitch = second_pitch;
+
+        // like the first stream, the second one is muted in kit mode
+        settings.second_grains = if kit_enabled {
+            0.0
+        } else {
+            parameters.get(Parameter::SecondGrains)
+        };
+
+        // effects follow the knobs only while a knob controls them, the menu sets them otherwise,
+        // so routes to an effect only modulate it while it is on a knob
+        for parameter in FX_PARAMETERS {
+            if shift_layer.is_mapped(parameter) {
+                settings.set(parameter, parameters.get(parameter));
+            }
+        }
+
+        // a synced echo repeats once per beat, halved until it fits
+        if *ctx.local.echo_sync {
+            if let Some(mut period) = tempo::get_period() {
+                while period > ECHO_MAX_FRAMES {
+                    period /= 2;
+                }
+
+                settings.echo_time = period as f32 / ECHO_MAX_FRAMES as f32;
+            }
+        }
+    });
+}
+
+/// Sets the tempo from a tap of the button at control cycle `tick`.
+fn tap_tempo(tap_tempo: &mut TapTempo, tick: u32) {
+    if let Some(period_in_ms) = tap_tempo.tap(tick.wrapping_mul(CONTROL_RATE_IN_MS)) {
+        tempo::set_tapped_period(
+            (period_in_ms as f32 * AUDIO_SAMPLE_RATE as f32 / 1000.0) as usize,
+        );
+        rprintln!("Tapped {} BPM!", 60_000 / period_in_ms);
+    }
+}
+
+/// Prints the route selected in the menu.
+fn log_mod_route(matrix: &ModMatrix) {
+    if let Some(route) = matrix.get_selected() {
+        rprintln!(
+            "Route {:?} to {:?} with depth {}",
+            route.source,
+            route.destination,
+            route.depth
+        );
+    }
+}
+
+fn log_slew(slew: &SlewLimiter) {
+    let parameter = slew.get_selected();
+    let times = slew.get_slew(parameter);
+
+    rprintln!(
+        "Slew {:?} rising in {} ms and falling in {} ms",
+        parameter,
+        times.rise_in_ms,
+        times.fall_in_ms
+    );
+}
+
+fn log_midi_learn(learn: &MidiLearn) {
+    let parameter = learn.get_selected();
+
+    if let Some(controller) = learn.get_controller(parameter) {
+        rprintln!("{:?} follows CC {}", parameter, controller);
+    } else {
+        rprintln!("{:?} follows no CC", parameter);
+    }
+}
+
+/// Switches the knobs between the panel and the shift bank.
+fn set_shift(shift_layer: &mut ShiftLayer, shifted: bool) {
+    shift_layer.set_shifted(shifted);
+    SHIFT_ACTIVE.store(shifted, Ordering::Relaxed);
+}
+
+/// Steps a normalized FX parameter by encoder detents.
+fn step_fx_parameter(value: f32, steps: i32) -> f32 {
+    (value + steps as f32 * FX_PARAMETER_STEP).clamp(0.0, 1.0)
+}
+
+/// Steps the slot of the second stream through the active slot, `None`, and every slot.
+fn step_second_slot(slot: Option<usize>, steps: i32) -> Option<usize> {
+    let index = slot.map_or(0, |slot| slot as i32 + 1);
+
+    match (index + steps).rem_euclid(SLOT_COUNT as i32 + 1) {
+        0 => None,
+        index => Some(index as usize - 1),
+    }
+}
+
+/// Granulator settings until the control task has read the panel.
+pub fn initial_user_settings() -> UserSettings {
+    UserSettings {
+        master_volume: GRANULATOR_LEVEL,
+        active_grains: 0.1,
+        offset: 0.5,
+        grain_size: 0.5,
+        pitch: 0.5,
+        delay: 0.0,
+        velocity: 1.0,
+        sp_offset: 0.0,
+        sp_grain_size: 0.0,
+        sp_pitch: 0.0,
+        sp_delay: 0.0,
+        sp_velocity: 0.0,
+        window_function: Window::Sine as u8,
+        window_param: 0.5,
+        tuning: Quantizer::default(),
+    }
+}
+
+/// Grain cloud with the ranges of the firmware, starting from the initial settings.
+pub fn new_grain_cloud() -> GrainCloud {
+    GrainCloud::new(
+        AUDIO_SAMPLE_RATE,
+        STRETCH_RANGES,
+        GRAIN_DELAY_RANGE_IN_MS,
+        &initial_user_settings(),
+    )
+}
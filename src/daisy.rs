@@ -0,0 +1,213 @@
+use libdaisy::gpio::*;
+use stm32h7xx_hal::gpio::{self, ExtiPin};
+use stm32h7xx_hal::hal::adc::Channel;
+use stm32h7xx_hal::hal::digital::v2::{InputPin, OutputPin};
+use stm32h7xx_hal::stm32;
+
+use crate::error::SitiraError;
+
+/// Daisy module the panel is wired to, which hands out the pins of the knobs, jacks, buttons and
+/// LEDs in the modes their drivers take them in.
+///
+/// The pins of the display, the SD card, the status LED, MIDI and the expander use alternate
+/// functions of the STM32 and are still taken where they are set up.
+pub trait Board {
+    /// Pins which libdaisy leaves after `System::init()`
+    type Gpio;
+
+    type MuxInput1: Channel<stm32::ADC1, ID = u8>;
+    type MuxInput2: Channel<stm32::ADC1, ID = u8>;
+    type MuxSelect0: OutputPin;
+    type MuxSelect1: OutputPin;
+    type MuxSelect2: OutputPin;
+
+    type MasterVolume: Channel<stm32::ADC2, ID = u8>;
+    type DirectCv1: Channel<stm32::ADC2, ID = u8>;
+    type DirectCv2: Channel<stm32::ADC2, ID = u8>;
+
+    type RotarySwitch: InputPin;
+    type RotaryClock: InputPin;
+    type RotaryData: InputPin;
+
+    type Gate1: InputPin + ExtiPin;
+    type Gate2: InputPin + ExtiPin;
+    type Gate3: InputPin + ExtiPin;
+    type Gate4: InputPin + ExtiPin;
+    type KillGate: InputPin;
+
+    type Led1: OutputPin;
+    type Led2: OutputPin;
+    type Led3: OutputPin;
+
+    type Button: InputPin;
+
+    /// GPIOA pins of gate 1 to 4 in hardware order, the gate tasks bind their EXTI lines
+    const GATE_PINS: [u32; 4];
+
+    fn mux_inputs(gpio: &mut Self::Gpio)
+        -> Result<(Self::MuxInput1, Self::MuxInput2), SitiraError>;
+
+    fn mux_selects(
+        gpio: &mut Self::Gpio,
+    ) -> Result<(Self::MuxSelect0, Self::MuxSelect1, Self::MuxSelect2), SitiraError>;
+
+    fn master_volume(gpio: &mut Self::Gpio) -> Result<Self::MasterVolume, SitiraError>;
+
+    /// Takes the jacks of gate 4 and the kill gate as analog inputs.
+    fn direct_cvs(gpio: &mut Self::Gpio)
+        -> Result<(Self::DirectCv1, Self::DirectCv2), SitiraError>;
+
+    fn encoder(
+        gpio: &mut Self::Gpio,
+    ) -> Result<(Self::RotarySwitch, Self::RotaryClock, Self::RotaryData), SitiraError>;
+
+    fn gates(gpio: &mut Self::Gpio)
+        -> Result<(Self::Gate1, Self::Gate2, Self::Gate3), SitiraError>;
+
+    fn gate4(gpio: &mut Self::Gpio) -> Result<Self::Gate4, SitiraError>;
+
+    fn kill_gate(gpio: &mut Self::Gpio) -> Result<Self::KillGate, SitiraError>;
+
+    fn led1(gpio: &mut Self::Gpio) -> Result<Self::Led1, SitiraError>;
+
+    fn led2(gpio: &mut Self::Gpio) -> Result<Self::Led2, SitiraError>;
+
+    fn led3(gpio: &mut Self::Gpio) -> Result<Self::Led3, SitiraError>;
+
+    fn button(gpio: &mut Self::Gpio) -> Result<Self::Button, SitiraError>;
+}
+
+/// Daisy Seed, the module of all Sitira panels so far.
+pub struct DaisySeed;
+
+impl Board for DaisySeed {
+    type Gpio = GPIO;
+
+    /// MUX A+B
+    type MuxInput1 = Daisy15<Analog>;
+    /// MUX C+D
+    type MuxInput2 = Daisy16<Analog>;
+    type MuxSelect0 = Daisy17<Output<PushPull>>;
+    type MuxSelect1 = Daisy18<Output<PushPull>>;
+    type MuxSelect2 = Daisy19<Output<PushPull>>;
+
+    /// Not multiplexed
+    type MasterVolume = Daisy21<Analog>;
+    type DirectCv1 = Daisy23<Analog>;
+    type DirectCv2 = Daisy20<Analog>;
+
+    type RotarySwitch = Daisy28<Input<gpio::Floating>>;
+    type RotaryClock = Daisy26<Input<PullUp>>;
+    type RotaryData = Daisy27<Input<PullUp>>;
+
+    type Gate1 = Daisy24<Input<gpio::Floating>>;
+    type Gate2 = Daisy25<Input<gpio::Floating>>;
+    type Gate3 = Daisy22<Input<gpio::Floating>>;
+    type Gate4 = Daisy23<Input<gpio::Floating>>;
+    type KillGate = Daisy20<Input<gpio::Floating>>;
+
+    type Led1 = Daisy13<Output<PushPull>>;
+    type Led2 = Daisy14<Output<PushPull>>;
+    type Led3 = Daisy0<Output<PushPull>>;
+
+    type Button = Daisy9<Input<PullDown>>;
+
+    /// D24, D25, D22, D23
+    const GATE_PINS: [u32; 4] = [1, 0, 5, 4];
+
+    fn mux_inputs(gpio: &mut GPIO) -> Result<(Self::MuxInput1, Self::MuxInput2), SitiraError> {
+        let mux1 = gpio.daisy15.take().ok_or(SitiraError::PinTaken(15))?;
+        let mux2 = gpio.daisy16.take().ok_or(SitiraError::PinTaken(16))?;
+
+        Ok((mux1.into_analog(), mux2.into_analog()))
+    }
+
+    fn mux_selects(
+        gpio: &mut GPIO,
+    ) -> Result<(Self::MuxSelect0, Self::MuxSelect1, Self::MuxSelect2), SitiraError> {
+        let select0 = gpio.daisy17.take().ok_or(SitiraError::PinTaken(17))?;
+        let select1 = gpio.daisy18.take().ok_or(SitiraError::PinTaken(18))?;
+        let select2 = gpio.daisy19.take().ok_or(SitiraError::PinTaken(19))?;
+
+        Ok((
+            select0.into_push_pull_output(),
+            select1.into_push_pull_output(),
+            select2.into_push_pull_output(),
+        ))
+    }
+
+    fn master_volume(gpio: &mut GPIO) -> Result<Self::MasterVolume, SitiraError> {
+        let pin = gpio.daisy21.take().ok_or(SitiraError::PinTaken(21))?;
+
+        Ok(pin.into_analog())
+    }
+
+    fn direct_cvs(gpio: &mut GPIO) -> Result<(Self::DirectCv1, Self::DirectCv2), SitiraError> {
+        let cv1 = gpio.daisy23.take().ok_or(SitiraError::PinTaken(23))?;
+        let cv2 = gpio.daisy20.take().ok_or(SitiraError::PinTaken(20))?;
+
+        Ok((cv1.into_analog(), cv2.into_analog()))
+    }
+
+    fn encoder(
+        gpio: &mut GPIO,
+    ) -> Result<(Self::RotarySwitch, Self::RotaryClock, Self::RotaryData), SitiraError> {
+        let switch = gpio.daisy28.take().ok_or(SitiraError::PinTaken(28))?;
+        let clock = gpio.daisy26.take().ok_or(SitiraError::PinTaken(26))?;
+        let data = gpio.daisy27.take().ok_or(SitiraError::PinTaken(27))?;
+
+        Ok((
+            switch.into_floating_input(),
+            clock.into_pull_up_input(),
+            data.into_pull_up_input(),
+        ))
+    }
+
+    fn gates(gpio: &mut GPIO) -> Result<(Self::Gate1, Self::Gate2, Self::Gate3), SitiraError> {
+        let gate1 = gpio.daisy24.take().ok_or(SitiraError::PinTaken(24))?;
+        let gate2 = gpio.daisy25.take().ok_or(SitiraError::PinTaken(25))?;
+        let gate3 = gpio.daisy22.take().ok_or(SitiraError::PinTaken(22))?;
+
+        Ok((
+            gate1.into_floating_input(),
+            gate2.into_floating_input(),
+            gate3.into_floating_input(),
+        ))
+    }
+
+    fn gate4(gpio: &mut GPIO) -> Result<Self::Gate4, SitiraError> {
+        let pin = gpio.daisy23.take().ok_or(SitiraError::PinTaken(23))?;
+
+        Ok(pin.into_floating_input())
+    }
+
+    fn kill_gate(gpio: &mut GPIO) -> Result<Self::KillGate, SitiraError> {
+        let pin = gpio.daisy20.take().ok_or(SitiraError::PinTaken(20))?;
+
+        Ok(pin.into_floating_input())
+    }
+
+    fn led1(gpio: &mut GPIO) -> Result<Self::Led1, SitiraError> {
+        let pin = gpio.daisy13.take().ok_or(SitiraError::PinTaken(13))?;
+
+        Ok(pin.into_push_pull_output())
+    }
+
+    fn led2(gpio: &mut GPIO) -> Result<Self::Led2, SitiraError> {
+        let pin = gpio.daisy14.take().ok_or(SitiraError::PinTaken(14))?;
+
+        Ok(pin.into_push_pull_output())
+    }
+
+    fn led3(gpio: &mut GPIO) -> Result<Self::Led3, SitiraError> {
+        let pin = gpio.daisy0.take().ok_or(SitiraError::PinTaken(0))?;
+
+        Ok(pin.into_push_pull_output())
+    }
+
+    fn button(gpio: &mut GPIO) -> Result<Self::Button, SitiraError> {
+        let pin = gpio.daisy9.take().ok_or(SitiraError::PinTaken(9))?;
+
+        Ok(pin.into_pull_down_input())
+    }
+}
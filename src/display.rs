@@ -0,0 +1,534 @@
+use core::fmt::Write;
+use core::sync::atomic::Ordering;
+
+use rtic::Mutex;
+
+use sitira_core::{
+    bounce::BOUNCE,
+    calibration::CalibrationStage,
+    card::CARD,
+    erase::ERASE,
+    grain_stats::{self, GRAIN_SNAPSHOT, GRAIN_STATS},
+    grains::Window,
+    mapping::Parameter,
+    menu::MenuItem,
+    meter::{INPUT_METER, OUTPUT_METER, RECORD_METER},
+    midi_learn::LEARN_VIEW,
+    normalize,
+    pages::{self, MAX_PAGE_ROWS, PAGES, PARAMETER_VIEW},
+    rng::GRAIN_SEED,
+    spectrum::SPECTRUM,
+    stream::LOAD,
+    stretch::{self, STRETCH_PREVIEW},
+    strings::{self, UiText},
+    theme::THEME,
+    timecode::TimeText,
+};
+
+use crate::{
+    app::display_handler,
+    config::{
+        CONTROL_RATE_IN_MS, LCD_REFRESH_RATE_IN_MS, SCREENSAVER_FADE_IN_S, SCREENSAVER_SLEEP,
+        SCREENSAVER_TIMEOUT_IN_S, STRETCH_PREVIEW_IN_MS,
+    },
+    control::{CONTROL_TICKS, LAST_ACTIVITY, SHIFT_ACTIVE, STRETCH_RANGES},
+    export::EXPORT,
+    panic,
+    playback::{format_time, get_playback_length, IS_RECORDING, OFFSET_POSITION},
+    sitira::Display,
+    slots::SLOTS,
+    watchdog::{self, Task},
+    waveform_cache::WAVEFORMS,
+};
+
+/// Columns of the waveform, one per pixel of the screen width
+pub const WAVE_COLUMNS: usize = sitira_core::screen::WIDTH;
+
+/// Redraws the parts of the screen which have changed since the last refresh.
+pub fn refresh(mut ctx: display_handler::Context) {
+    // clear TIM4 interrupt flag
+    ctx.local.vr.timer4.clear_irq();
+
+    watchdog::beat(Task::Display);
+
+    let lcd = ctx.shared.lcd;
+
+    // the display only reaches its final place once the tasks run
+    panic::register_display(lcd);
+
+    // the framebuffer is left alone until the last frame has been sent
+    if lcd.is_busy() {
+        return;
+    }
+
+    // the screensaver dims and then sleeps the display until the panel is used again
+    if !wake(lcd) {
+        return;
+    }
+
+    // set when something else has taken the place of the waveform
+    let overlay_shown = &mut ctx.local.overlay_shown;
+    let calibration_shown = &mut ctx.local.calibration_shown;
+    let mut cleared = false;
+
+    let calibration_stage = ctx
+        .shared
+        .calibration
+        .lock(|calibration| calibration.get_stage());
+    let (
+        curve_editing,
+        kit_editing,
+        trim_editing,
+        pages_shown,
+        grains_shown,
+        spectrum_shown,
+        seed_editing,
+        counts_shown,
+        learn_shown,
+        card_retrying,
+    ) = ctx.shared.menu.lock(|menu| {
+        let item = menu.get_selected_item();
+        (
+            item.is_curve(),
+            item.is_kit(),
+            item.is_editor(),
+            item == MenuItem::Parameters,
+            item == MenuItem::GrainView,
+            item == MenuItem::Spectrum,
+            matches!(item, MenuItem::GrainSeed | MenuItem::Reroll),
+            item == MenuItem::GrainCount,
+            matches!(item, MenuItem::MidiLearn | MenuItem::MidiClear),
+            item == MenuItem::RetryCard,
+        )
+    });
+    // the output only gets analyzed while its spectrum is shown
+    SPECTRUM.set_enabled(spectrum_shown);
+
+    // the editor is drawn over the waveform as well, so it needs to know when it shows up
+    let editor_shown = core::mem::replace(ctx.local.editor_shown, trim_editing);
+    // the seed gets redrawn whenever it shows up again
+    let seed_shown = ctx.local.seed_shown;
+
+    if !seed_editing {
+        *seed_shown = None;
+    }
+    // as do the grain counts
+    let shown_counts = ctx.local.counts_shown;
+
+    if !counts_shown {
+        *shown_counts = None;
+    }
+    // and the controller of the parameter to learn
+    let shown_learn = ctx.local.learn_shown;
+
+    if !learn_shown {
+        *shown_learn = None;
+    }
+    // and the state of the SD card
+    let card_shown = ctx.local.card_shown;
+
+    if !card_retrying {
+        *card_shown = None;
+    }
+    let view_changed = PARAMETER_VIEW.take_changed();
+
+    // a new theme repaints everything
+    let theme_changed = THEME.take_changed();
+
+    if theme_changed {
+        lcd.clear();
+        cleared = true;
+        **calibration_shown = CalibrationStage::Inactive;
+        *ctx.local.confirmation_shown = false;
+        *ctx.local.progress_shown = false;
+        *ctx.local.popup_shown = false;
+    }
+
+    if calibration_stage != CalibrationStage::Inactive {
+        if calibration_stage != **calibration_shown {
+            let message = match calibration_stage {
+                CalibrationStage::Center => UiText::CalibrationCenter,
+                _ => UiText::CalibrationRange,
+            };
+
+            lcd.draw_message(strings::get(message));
+            **overlay_shown = true;
+        }
+    } else if ERASE.is_confirming() {
+        if !*ctx.local.confirmation_shown {
+            lcd.draw_message(strings::get(UiText::EraseConfirmation));
+            **overlay_shown = true;
+        }
+    } else if pages_shown {
+        // the parameter pages take the place of the waveform
+        if view_changed || theme_changed || !**overlay_shown {
+            draw_parameter_page(lcd);
+            **overlay_shown = true;
+        }
+    } else if grains_shown {
+        // the grains move all the time, so they get plotted at every refresh
+        lcd.draw_grain_view(GRAIN_SNAPSHOT.get_dots());
+        **overlay_shown = true;
+    } else if spectrum_shown {
+        // the idle task publishes a new spectrum whenever a frame of the output is analyzed
+        if SPECTRUM.take_changed() || theme_changed || !**overlay_shown {
+            lcd.draw_spectrum(&SPECTRUM.get_bands());
+            **overlay_shown = true;
+        }
+    } else if curve_editing {
+        // the curve editor takes the place of the waveform
+        ctx.shared.curves.lock(|curves| {
+            if curves.take_changed() || theme_changed || !**overlay_shown {
+                lcd.draw_curve(curves.get_curve(), curves.get_point(), curves.get_channel());
+                **overlay_shown = true;
+            }
+        });
+    } else if kit_editing {
+        // the kit editor takes the place of the waveform
+        ctx.shared.kit.lock(|kit| {
+            if kit.take_changed() || theme_changed || !**overlay_shown {
+                lcd.draw_kit(kit);
+                **overlay_shown = true;
+            }
+        });
+    } else if seed_editing {
+        // the seed takes the place of the waveform, so it can be noted down
+        let seed = GRAIN_SEED.get();
+
+        if *seed_shown != Some(seed) || theme_changed {
+            let mut text = TimeText::new();
+            let _ = write!(text, "{}\n{:08X}", MenuItem::GrainSeed.name(), seed);
+
+            lcd.draw_message(text.as_str());
+            *seed_shown = Some(seed);
+            **overlay_shown = true;
+        }
+    } else if counts_shown {
+        // the grains which play fall behind the requested ones while the load limit holds them back
+        let (requested, playing) = GRAIN_STATS.get_counts();
+
+        if *shown_counts != Some((requested, playing)) || theme_changed {
+            let mut text = TimeText::new();
+            let _ = write!(
+                text,
+                "{}\n{}/{}",
+                MenuItem::GrainCount.name(),
+                playing,
+                requested
+            );
+
+            lcd.draw_message(text.as_str());
+            *shown_counts = Some((requested, playing));
+            **overlay_shown = true;
+        }
+    } else if learn_shown {
+        let state = LEARN_VIEW.get();
+
+        if *shown_learn != Some(state) || theme_changed {
+            let (parameter, controller, learning) = state;
+            let mut text = TimeText::new();
+            let _ = match (learning, controller) {
+                (true, _) => write!(
+                    text,
+                    "{}\n{}",
+                    parameter.name(),
+                    strings::get(UiText::LearningCc)
+                ),
+                (false, Some(controller)) => {
+                    write!(text, "{}\nCC {}", parameter.name(), controller)
+                }
+                (false, None) => {
+                    write!(text, "{}\n{}", parameter.name(), strings::get(UiText::NoCc))
+                }
+            };
+
+            lcd.draw_message(text.as_str());
+            *shown_learn = Some(state);
+            **overlay_shown = true;
+        }
+    } else if card_retrying {
+        // tells why the card can not be used, the retry shows up here once it is done
+        let state = CARD.check();
+
+        if *card_shown != Some(state) || theme_changed {
+            let message = match state {
+                Ok(()) => "SD card ready",
+                Err(error) => error.description(),
+            };
+
+            lcd.draw_message(message);
+            *card_shown = Some(state);
+            **overlay_shown = true;
+        }
+    } else if trim_editing {
+        let columns = ctx.local.columns;
+        let waveform_pending = ctx.local.waveform_pending;
+
+        // the editor shows the zoomed waveform with the trim, it waits for the peaks like the
+        // waveform does
+        ctx.shared.editor.lock(|editor| {
+            let changed = editor.take_changed() || theme_changed || !editor_shown;
+
+            if (changed || *waveform_pending) && !IS_RECORDING.load(Ordering::Relaxed) {
+                let view = editor.get_view();
+                let drawn = view.len() < WAVE_COLUMNS
+                    || WAVEFORMS.get_peaks(SLOTS.get_active(), view.clone(), columns);
+
+                *waveform_pending = !drawn;
+
+                if drawn {
+                    lcd.clear_subsection(lcd.get_layout().wave_area());
+
+                    if view.len() >= WAVE_COLUMNS {
+                        lcd.draw_waveform(columns);
+                    }
+
+                    lcd.draw_trim(view, editor.get_trim(), editor.get_buffer_length());
+                    **overlay_shown = true;
+                }
+            }
+        });
+    } else {
+        let columns = ctx.local.columns;
+        let waveform_pending = ctx.local.waveform_pending;
+
+        // redraw the waveform whenever slices have been analyzed or selected, it waits for
+        // the peaks of new samples to be summarized
+        ctx.shared.slices.lock(|slices| {
+            let changed = slices.take_changed() || theme_changed || **overlay_shown;
+
+            if (changed || *waveform_pending) && !IS_RECORDING.load(Ordering::Relaxed) {
+                let buffer_length = slices.get_buffer_length();
+
+                // not enough samples to fill the screen width
+                let drawn = buffer_length < WAVE_COLUMNS
+                    || WAVEFORMS.get_peaks(SLOTS.get_active(), 0..buffer_length, columns);
+
+                *waveform_pending = !drawn;
+
+                if drawn {
+                    lcd.clear();
+
+                    if buffer_length >= WAVE_COLUMNS {
+                        lcd.draw_waveform(columns);
+                    }
+
+                    lcd.draw_slice_markers(slices.as_slice(), buffer_length, slices.get_selected());
+                    **overlay_shown = false;
+                    cleared = true;
+                }
+            }
+        });
+    }
+
+    // bounce, export, erase and load progress
+    let progress = draw_progress(lcd, ctx.local.progress_shown);
+
+    // positions are shown as time, redrawn only when the text changes
+    let readout = (
+        format_time(OFFSET_POSITION.load(Ordering::Relaxed)),
+        format_time(get_playback_length()),
+        normalize::format_gain(normalize::get_gain()),
+    );
+
+    **calibration_shown = calibration_stage;
+    *ctx.local.confirmation_shown = ERASE.is_confirming();
+
+    if cleared || readout != *ctx.local.readout {
+        lcd.draw_time_readout(readout.0.as_str(), readout.1.as_str(), readout.2.as_str());
+        *ctx.local.readout = readout;
+    }
+
+    // time-stretch preview, shown for a while after grain size or pitch were turned
+    let preview_ticks = &mut ctx.local.preview_ticks;
+
+    if progress {
+        **preview_ticks = 0;
+    } else if STRETCH_PREVIEW.take_changed() || (cleared && **preview_ticks > 0) {
+        let semitones = STRETCH_RANGES.semitones(STRETCH_PREVIEW.get_pitch());
+        let grain =
+            stretch::format_grain(STRETCH_RANGES.grain_in_ms(STRETCH_PREVIEW.get_grain_size()));
+
+        // in note values the grain shows its note value and the delay replaces the speed
+        let note_values = STRETCH_PREVIEW.get_note_values();
+        let grain = note_values.map_or(grain.as_str(), |(grain, _)| grain.name());
+
+        lcd.draw_stretch_preview(
+            grain,
+            stretch::format_semitones(semitones).as_str(),
+            stretch::format_speed(stretch::speed_from_semitones(semitones)).as_str(),
+            note_values.map(|(_, delay)| delay.name()),
+        );
+        **preview_ticks = STRETCH_PREVIEW_IN_MS / LCD_REFRESH_RATE_IN_MS;
+    } else if **preview_ticks > 0 {
+        **preview_ticks -= 1;
+
+        if **preview_ticks == 0 {
+            lcd.clear_stretch_preview();
+        }
+    }
+
+    // grain cloud, redrawn only when the text changes
+    let cloud = (
+        grain_stats::format_percent(GRAIN_STATS.get_density()),
+        grain_stats::format_percent(GRAIN_STATS.get_pitch()),
+        grain_stats::format_percent(GRAIN_STATS.get_load()),
+    );
+
+    if cleared || cloud != *ctx.local.cloud {
+        lcd.draw_cloud_readout(cloud.0.as_str(), cloud.1.as_str(), cloud.2.as_str());
+        *ctx.local.cloud = cloud;
+    }
+
+    let shifted = SHIFT_ACTIVE.load(Ordering::Relaxed);
+
+    if cleared || shifted != *ctx.local.shift_shown {
+        lcd.draw_shift_indicator(shifted);
+        *ctx.local.shift_shown = shifted;
+    }
+
+    let card_failed = !CARD.is_ready();
+
+    if cleared || card_failed != *ctx.local.card_error_shown {
+        lcd.draw_card_error(card_failed);
+        *ctx.local.card_error_shown = card_failed;
+    }
+
+    // the parameter being changed pops up below the readout, unless its page is shown anyway
+    let focus = if pages_shown {
+        None
+    } else {
+        PARAMETER_VIEW.get_focus()
+    };
+
+    if let Some((page, row)) = focus {
+        if cleared || view_changed || !*ctx.local.popup_shown {
+            let parameter = PAGES[page].parameters[row];
+            let label = pages::label(parameter);
+
+            // the window and its shape show how the grains fade
+            if matches!(parameter, Parameter::Envelope | Parameter::WaveSelect) {
+                lcd.draw_window_popup(
+                    label,
+                    Window::from_parameter(PARAMETER_VIEW.get_value(Parameter::Envelope)),
+                    PARAMETER_VIEW.get_value(Parameter::WaveSelect),
+                );
+            } else {
+                lcd.draw_parameter_popup(label, PARAMETER_VIEW.get_value(parameter));
+            }
+
+            *ctx.local.popup_shown = true;
+        }
+    } else if *ctx.local.popup_shown {
+        lcd.clear_parameter_popup();
+        *ctx.local.popup_shown = false;
+    }
+
+    draw_meters(lcd);
+
+    // only the changed parts of the frame get transferred
+    lcd.flush();
+}
+
+/// Draws the selected parameter page, which is the one of the focused parameter if any.
+fn draw_parameter_page(lcd: &mut Display) {
+    let page = &PAGES[PARAMETER_VIEW.get_page()];
+    let mut rows = [("", 0.0, false); MAX_PAGE_ROWS];
+
+    for (row, parameter) in rows.iter_mut().zip(page.parameters) {
+        *row = (
+            pages::label(*parameter),
+            PARAMETER_VIEW.get_value(*parameter),
+            PARAMETER_VIEW.is_automated(*parameter),
+        );
+    }
+
+    lcd.draw_parameter_page(
+        strings::get(page.title),
+        &rows[..page.parameters.len()],
+        PARAMETER_VIEW.get_focus().map(|(_, row)| row),
+    );
+}
+
+/// Dims the display after a while without activity and sleeps it, `false` while it sleeps.
+fn wake(lcd: &mut Display) -> bool {
+    let idle_ticks = CONTROL_TICKS
+        .load(Ordering::Relaxed)
+        .wrapping_sub(LAST_ACTIVITY.load(Ordering::Relaxed));
+    let timeout_ticks = SCREENSAVER_TIMEOUT_IN_S * 1000 / CONTROL_RATE_IN_MS;
+    let fade_ticks = SCREENSAVER_FADE_IN_S * 1000 / CONTROL_RATE_IN_MS;
+
+    if idle_ticks >= timeout_ticks {
+        if !lcd.is_sleeping() {
+            lcd.sleep(SCREENSAVER_SLEEP);
+        }
+
+        return false;
+    }
+
+    if lcd.is_sleeping() {
+        lcd.wake();
+    } else if idle_ticks + fade_ticks > timeout_ticks {
+        let remaining = timeout_ticks - idle_ticks;
+        lcd.set_brightness((remaining * u8::MAX as u32 / fade_ticks) as u8);
+    } else {
+        lcd.set_brightness(u8::MAX);
+    }
+
+    true
+}
+
+/// Shows the progress of a running job, `true` while there is one.
+fn draw_progress(lcd: &mut Display, shown: &mut bool) -> bool {
+    let progress = if BOUNCE.is_running() {
+        Some((BOUNCE.get_progress(), UiText::Bouncing))
+    } else if EXPORT.is_running() {
+        Some((EXPORT.get_progress(), UiText::Exporting))
+    } else if ERASE.is_running() {
+        Some((ERASE.get_progress(), UiText::Erasing))
+    } else if LOAD.is_running() {
+        Some((LOAD.get_progress(), UiText::Loading))
+    } else {
+        None
+    };
+
+    if let Some((percentage, label)) = progress {
+        let label = strings::get(label);
+
+        if !*shown {
+            lcd.draw_loading_bar(0, label);
+            *shown = true;
+        }
+
+        lcd.draw_loading_bar(percentage, label);
+    } else if *shown {
+        lcd.clear_subsection(lcd.get_layout().progress_area());
+        *shown = false;
+    }
+
+    progress.is_some()
+}
+
+/// Draws the input, record and output level meters.
+fn draw_meters(lcd: &mut Display) {
+    let layout = lcd.get_layout();
+
+    lcd.draw_meter(
+        layout.meter_position(0),
+        strings::get(UiText::InputMeter),
+        INPUT_METER.get_rms(),
+        INPUT_METER.get_peak(),
+    );
+    lcd.draw_meter(
+        layout.meter_position(1),
+        strings::get(UiText::RecordMeter),
+        RECORD_METER.get_rms(),
+        RECORD_METER.get_peak(),
+    );
+    lcd.draw_meter(
+        layout.meter_position(2),
+        strings::get(UiText::OutputMeter),
+        OUTPUT_METER.get_rms(),
+        OUTPUT_METER.get_peak(),
+    );
+}
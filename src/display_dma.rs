@@ -0,0 +1,151 @@
+use stm32h7xx_hal::pac;
+
+/// DMAMUX1 request line of the SPI1 transmitter
+const SPI1_TX_DMA_REQUEST: u8 = 38;
+/// Stream of DMA1 used for the display, streams 0 and 1 belong to the audio interface
+const STREAM: usize = 2;
+
+/// Largest amount of bytes a single transfer can move
+pub const MAX_TRANSFER_SIZE: usize = u16::MAX as usize;
+
+/// Feeds the SPI1 transmitter from memory with DMA1 stream 2.
+///
+/// Only the pixel data goes through DMA, commands are still written blocking by the display
+/// driver. While a transfer runs, the SPI must not be used otherwise. Completion raises the
+/// `DMA1_STR2` interrupt, which has to call `finish()` before the SPI is usable again.
+pub struct DisplayDma {
+    busy: bool,
+}
+
+impl DisplayDma {
+    /// Routes the SPI1 TX request to the stream and configures it. DMA1 is already clocked by
+    /// the audio interface.
+    pub fn new() -> Self {
+        let dma = unsafe { &*pac::DMA1::ptr() };
+        let dmamux = unsafe { &*pac::DMAMUX1::ptr() };
+        let spi = unsafe { &*pac::SPI1::ptr() };
+
+        dma.st[STREAM].cr.modify(|_, w| w.en().disabled());
+        while dma.st[STREAM].cr.read().en().is_enabled() {}
+
+        dmamux.ccr[STREAM].modify(|_, w| unsafe { w.dmareq_id().bits(SPI1_TX_DMA_REQUEST) });
+
+        dma.st[STREAM]
+            .par
+            .write(|w| unsafe { w.pa().bits(&spi.txdr as *const _ as u32) });
+
+        dma.st[STREAM].cr.write(|w| {
+            w.dir()
+                .memory_to_peripheral()
+                .minc()
+                .incremented()
+                .pinc()
+                .fixed()
+                .msize()
+                .bits8()
+                .psize()
+                .bits8()
+                .pl()
+                .medium()
+                .tcie()
+                .enabled()
+        });
+
+        DisplayDma { busy: false }
+    }
+
+    pub fn is_busy(&self) -> bool {
+        self.busy
+    }
+
+    /// Starts sending `data` to the SPI transmitter.
+    ///
+    /// ## Safety
+    /// `data` must stay valid and unchanged until `finish()` has been called.
+    pub unsafe fn start(&mut self, data: &[u8]) {
+        debug_assert!(data.len() <= MAX_TRANSFER_SIZE);
+
+        let dma = &*pac::DMA1::ptr();
+        let spi = &*pac::SPI1::ptr();
+
+        // the data might still sit in the cache
+        cortex_m::Peripherals::steal()
+            .SCB
+            .clean_dcache_by_slice(data);
+
+        clear_stream_flags(dma);
+
+        dma.st[STREAM]
+            .m0ar
+            .write(|w| w.m0a().bits(data.as_ptr() as u32));
+        dma.st[STREAM]
+            .ndtr
+            .write(|w| w.ndt().bits(data.len() as u16));
+
+        // DMA requests can only be enabled while the SPI is disabled
+        spi.cr1.modify(|_, w| w.spe().disabled());
+        spi.cfg1.modify(|_, w| w.txdmaen().enabled());
+        spi.cr1.modify(|_, w| w.spe().enabled());
+
+        dma.st[STREAM].cr.modify(|_, w| w.en().enabled());
+        spi.cr1.modify(|_, w| w.cstart().started());
+
+        self.busy = true;
+    }
+
+    /// Waits for the last byte to leave the SPI and hands the SPI back to blocking use. Must be
+    /// called from the transfer complete interrupt.
+    pub fn finish(&mut self) {
+        let dma = unsafe { &*pac::DMA1::ptr() };
+        let spi = unsafe { &*pac::SPI1::ptr() };
+
+        clear_stream_flags(dma);
+
+        while spi.sr.read().txc().bit_is_clear() {}
+
+        // disabling the SPI flushes the received bytes nobody is interested in
+        spi.cr1.modify(|_, w| w.spe().disabled());
+        spi.cfg1.modify(|_, w| w.txdmaen().disabled());
+        spi.ifcr
+            .write(|w| w.ovrc().set_bit().eotc().set_bit().txtfc().set_bit());
+        spi.cr1.modify(|_, w| w.spe().enabled());
+        spi.cr1.modify(|_, w| w.cstart().started());
+
+        self.busy = false;
+    }
+
+    /// Blocks until the running transfer is complete and finishes it. Only meant for contexts in
+    /// which the transfer complete interrupt cannot run anymore.
+    pub fn wait(&mut self) {
+        if !self.busy {
+            return;
+        }
+
+        let dma = unsafe { &*pac::DMA1::ptr() };
+
+        while dma.lisr.read().tcif2().bit_is_clear() {}
+
+        self.finish();
+    }
+}
+
+impl Default for DisplayDma {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn clear_stream_flags(dma: &pac::dma1::RegisterBlock) {
+    dma.lifcr.write(|w| {
+        w.ctcif2()
+            .set_bit()
+            .chtif2()
+            .set_bit()
+            .cteif2()
+            .set_bit()
+            .cdmeif2()
+            .set_bit()
+            .cfeif2()
+            .set_bit()
+    });
+}
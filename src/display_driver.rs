@@ -0,0 +1,165 @@
+use core::ops::Range;
+
+use display_interface_spi::SPIInterfaceNoCS;
+use ili9341::{DisplaySize240x320, Ili9341, ModeState, Orientation};
+use stm32h7xx_hal::hal;
+
+use crate::error::SitiraError;
+
+#[cfg(all(feature = "st7789", feature = "ssd1306"))]
+compile_error!("Only one display panel can be selected, either `st7789` or `ssd1306`");
+
+#[cfg(all(feature = "headless", any(feature = "st7789", feature = "ssd1306")))]
+compile_error!("The `headless` build has no display panel, drop `st7789` or `ssd1306`");
+
+/// How the rows of a flush get to the panel.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RowTransfer {
+    /// The panel waits for the framebuffer bytes, which get streamed by DMA
+    Dma,
+    /// The driver has written the rows on its own, e.g. in another pixel format
+    Written,
+}
+
+/// Controller of a display panel on SPI1, which gets its pixels from the framebuffer.
+///
+/// The framebuffer is drawn in RGB565 whatever the panel is, so the interface looks the same on
+/// all of them. Panels which take RGB565 only set their window and let the DMA stream the
+/// framebuffer, others convert the rows on their own.
+pub trait DisplayDriver {
+    /// Width of the panel in the orientation it is driven in
+    const WIDTH: usize;
+    /// Height of the panel in the orientation it is driven in, a multiple of 16
+    const HEIGHT: usize;
+    /// Fastest SPI clock the controller accepts
+    const SPI_CLOCK_IN_MHZ: u32;
+
+    /// Sets the backlight brightness or the contrast, if the panel supports it.
+    fn set_brightness(&mut self, brightness: u8) -> Result<(), SitiraError>;
+
+    /// Enters or leaves the sleep mode of the controller.
+    fn set_sleep_mode(&mut self, sleep: bool) -> Result<(), SitiraError>;
+
+    /// Writes `rows` of the frame, `pixels` holds them as big endian RGB565.
+    fn write_rows(&mut self, rows: Range<usize>, pixels: &[u8])
+        -> Result<RowTransfer, SitiraError>;
+}
+
+/// 2.2" ILI9341 panel with 320x240 pixels, the one of the standard build.
+///
+/// The display is the only device on its SPI bus, so its chip select stays low all the time.
+/// This lets the driver set the drawing window and the DMA stream the pixels right after.
+pub struct Ili9341Panel<SPI, DC, CS, RESET> {
+    driver: Ili9341<SPIInterfaceNoCS<SPI, DC>, RESET>,
+    _cs: CS,
+}
+
+impl<SPI, DC, CS, RESET> Ili9341Panel<SPI, DC, CS, RESET>
+where
+    SPI: hal::blocking::spi::Write<u8>,
+    DC: hal::digital::v2::OutputPin,
+    CS: hal::digital::v2::OutputPin,
+    RESET: hal::digital::v2::OutputPin,
+{
+    pub fn new<DELAY>(
+        spi: SPI,
+        dc: DC,
+        mut cs: CS,
+        reset: RESET,
+        mut delay: DELAY,
+    ) -> Result<Self, SitiraError>
+    where
+        DELAY: libdaisy::prelude::_embedded_hal_blocking_delay_DelayMs<u16>,
+    {
+        cs.set_low().ok();
+
+        let interface = SPIInterfaceNoCS::new(spi, dc);
+
+        let driver = Ili9341::new(
+            interface,
+            reset,
+            &mut delay,
+            Orientation::Landscape,
+            DisplaySize240x320,
+        )
+        .map_err(|_| SitiraError::Display)?;
+
+        Ok(Ili9341Panel { driver, _cs: cs })
+    }
+}
+
+impl<SPI, DC, CS, RESET> DisplayDriver for Ili9341Panel<SPI, DC, CS, RESET>
+where
+    SPI: hal::blocking::spi::Write<u8>,
+    DC: hal::digital::v2::OutputPin,
+    CS: hal::digital::v2::OutputPin,
+    RESET: hal::digital::v2::OutputPin,
+{
+    const WIDTH: usize = 320;
+    const HEIGHT: usize = 240;
+    const SPI_CLOCK_IN_MHZ: u32 = 25;
+
+    fn set_brightness(&mut self, brightness: u8) -> Result<(), SitiraError> {
+        self.driver
+            .brightness(brightness)
+            .map_err(|_| SitiraError::Display)
+    }
+
+    fn set_sleep_mode(&mut self, sleep: bool) -> Result<(), SitiraError> {
+        let state = if sleep { ModeState::On } else { ModeState::Off };
+
+        self.driver
+            .sleep_mode(state)
+            .map_err(|_| SitiraError::Display)
+    }
+
+    fn write_rows(
+        &mut self,
+        rows: Range<usize>,
+        _pixels: &[u8],
+    ) -> Result<RowTransfer, SitiraError> {
+        // sets the window and starts the memory write without any data
+        self.driver
+            .draw_raw_iter(
+                0,
+                rows.start as u16,
+                Self::WIDTH as u16 - 1,
+                rows.end as u16 - 1,
+                core::iter::empty::<u16>(),
+            )
+            .map_err(|_| SitiraError::Display)?;
+
+        Ok(RowTransfer::Dma)
+    }
+}
+
+/// Stands in for the panel of a build without a display, everything drawn goes nowhere.
+///
+/// The SPI1 pins stay unused and the display task never runs, so the framebuffer only gets drawn
+/// to by the few screens of the start up, like the splash and the self test.
+#[cfg(feature = "headless")]
+pub struct NoPanel;
+
+#[cfg(feature = "headless")]
+impl DisplayDriver for NoPanel {
+    // the layout of the standard build, for whatever still draws on the framebuffer
+    const WIDTH: usize = 320;
+    const HEIGHT: usize = 240;
+    const SPI_CLOCK_IN_MHZ: u32 = 0;
+
+    fn set_brightness(&mut self, _brightness: u8) -> Result<(), SitiraError> {
+        Ok(())
+    }
+
+    fn set_sleep_mode(&mut self, _sleep: bool) -> Result<(), SitiraError> {
+        Ok(())
+    }
+
+    fn write_rows(
+        &mut self,
+        _rows: Range<usize>,
+        _pixels: &[u8],
+    ) -> Result<RowTransfer, SitiraError> {
+        Ok(RowTransfer::Written)
+    }
+}
@@ -1,10 +1,13 @@
-use core::fmt::Debug;
 use nb::block;
 use stm32h7xx_hal::adc::{Adc, AdcSampleTime, Disabled, Enabled, Resolution};
 use stm32h7xx_hal::hal::adc::Channel;
 use stm32h7xx_hal::hal::digital::v2::OutputPin;
 use stm32h7xx_hal::stm32;
 
+use sitira_core::oversample::{decimate, MAX_OVERSAMPLING};
+
+use crate::error::SitiraError;
+
 const MUX_INPUTS: usize = 8;
 
 const ONE_BIT_MASK: u8 = 0b1;
@@ -23,6 +26,12 @@ pub struct DualMux<M1, M2, S0, S1, S2> {
     // two 4051 Multiplexer
     value: [f32; MUX_INPUTS * 2],
 
+    // physical channel of every logical input
+    channel_map: [u8; MUX_INPUTS * 2],
+
+    // conversions per reading of every physical channel
+    oversampling: [usize; MUX_INPUTS * 2],
+
     // helper
     conversion_value: f32,
 }
@@ -32,11 +41,8 @@ where
     M1: Channel<stm32::ADC1, ID = u8>,
     M2: Channel<stm32::ADC1, ID = u8>,
     S0: OutputPin,
-    <S0 as OutputPin>::Error: Debug,
     S1: OutputPin,
-    <S1 as OutputPin>::Error: Debug,
     S2: OutputPin,
-    <S2 as OutputPin>::Error: Debug,
 {
     pub fn new(
         adc: Adc<stm32::ADC1, Disabled>,
@@ -63,54 +69,84 @@ where
 
             value: [0.0; MUX_INPUTS * 2],
 
+            channel_map: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+
+            oversampling: [1; MUX_INPUTS * 2],
+
             conversion_value,
         }
     }
 
-    fn set_select_pins(&mut self, input_number: usize) {
+    fn set_select_pins(&mut self, input_number: usize) -> Result<(), SitiraError> {
         let input_number = input_number.clamp(0, 15) as u8;
         let first_bit = input_number & ONE_BIT_MASK;
         let second_bit = (input_number >> 1) & ONE_BIT_MASK;
         let third_bit = (input_number >> 2) & ONE_BIT_MASK;
 
-        match first_bit {
-            0b0 => self.select0_pin.set_low().unwrap(),
-            0b1 => self.select0_pin.set_high().unwrap(),
-            _ => (),
-        }
+        let first = match first_bit {
+            0b0 => self.select0_pin.set_low(),
+            _ => self.select0_pin.set_high(),
+        };
 
-        match second_bit {
-            0b0 => self.select1_pin.set_low().unwrap(),
-            0b1 => self.select1_pin.set_high().unwrap(),
-            _ => (),
-        }
+        let second = match second_bit {
+            0b0 => self.select1_pin.set_low(),
+            _ => self.select1_pin.set_high(),
+        };
+
+        let third = match third_bit {
+            0b0 => self.select2_pin.set_low(),
+            _ => self.select2_pin.set_high(),
+        };
 
-        match third_bit {
-            0b0 => self.select2_pin.set_low().unwrap(),
-            0b1 => self.select2_pin.set_high().unwrap(),
-            _ => (),
+        match (first, second, third) {
+            (Ok(()), Ok(()), Ok(())) => Ok(()),
+            _ => Err(SitiraError::Pin),
         }
     }
 
-    pub fn read_value(&mut self, input_number: usize) {
-        match input_number {
-            0..=8 => {
-                self.set_select_pins(input_number);
-                self.adc.start_conversion(&mut self.mux1_pin);
+    /// Reads one input with its oversampling. If its channel can not be selected, the input
+    /// keeps its last value.
+    pub fn read_value(&mut self, input_number: usize) -> Result<(), SitiraError> {
+        self.set_select_pins(input_number)?;
+
+        let mut samples = [0; MAX_OVERSAMPLING];
+        let mut count = 0;
+
+        for _ in 0..self.oversampling[input_number] {
+            match input_number {
+                0..=8 => self.adc.start_conversion(&mut self.mux1_pin),
+                9..=16 => self.adc.start_conversion(&mut self.mux2_pin),
+                _ => continue,
             }
-            9..=16 => {
-                self.set_select_pins(input_number);
-                self.adc.start_conversion(&mut self.mux2_pin);
+
+            if let Ok(data) = block!(self.adc.read_sample()) {
+                samples[count] = data;
+                count += 1;
             }
-            _ => (),
         }
 
-        if let Ok(data) = block!(self.adc.read_sample()) {
-            self.value[input_number] = data as f32 * self.conversion_value;
+        if count > 0 {
+            self.value[input_number] = decimate(&mut samples[..count]) * self.conversion_value;
+        }
+
+        Ok(())
+    }
+
+    /// Sets the physical channel of every logical input, for panels with different wiring.
+    pub fn set_channel_map(&mut self, channel_map: [u8; MUX_INPUTS * 2]) {
+        self.channel_map = channel_map;
+    }
+
+    /// Sets the conversions per reading of every logical input, after the channel map.
+    pub fn set_oversampling(&mut self, oversampling: [usize; MUX_INPUTS * 2]) {
+        for (input, count) in oversampling.iter().enumerate() {
+            let channel = self.channel_map[input] as usize;
+            self.oversampling[channel] = (*count).clamp(1, MAX_OVERSAMPLING);
         }
     }
 
+    /// Returns the value of a logical input.
     pub fn get_value(&self, input_number: usize) -> f32 {
-        self.value[input_number]
+        self.value[self.channel_map[input_number] as usize]
     }
 }
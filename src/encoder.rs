@@ -1,12 +1,37 @@
 use libdaisy::hid::{Switch, SwitchType};
 use stm32h7xx_hal::hal::digital::v2::InputPin;
 
+use sitira_core::gesture::{Gesture, GestureDetector};
+
+/// Smoothing of the measured turning velocity
+const VELOCITY_SMOOTHING: f32 = 0.6;
+/// Velocity (detents per control cycle) above which acceleration kicks in
+const ACCELERATION_THRESHOLD: f32 = 1.5;
+/// Additional multiplier per detent per control cycle above the threshold
+const ACCELERATION_FACTOR: f32 = 2.0;
+/// Upper bound of the acceleration multiplier
+const MAX_ACCELERATION: f32 = 16.0;
+
+/// Result of one control cycle.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct EncoderTick {
+    /// Signed amount of detents moved since the last tick
+    pub detents: i32,
+    /// Detents scaled by the turning velocity, for scrolling through long ranges
+    pub accelerated: i32,
+    pub gesture: Option<Gesture>,
+}
+
 pub struct RotaryEncoder<S, C, D> {
     pub switch: Switch<S>, // gives access to the underlying Switch functions
     clock: Switch<C>,
     data: Switch<D>,
     pub current_value: i32,
     clock_state: bool,
+    steps_per_detent: i32,
+    last_value: i32,
+    velocity: f32,
+    gestures: GestureDetector,
 }
 
 impl<S, C, D> RotaryEncoder<S, C, D>
@@ -32,9 +57,19 @@ where
             data,
             current_value,
             clock_state,
+            steps_per_detent: 1,
+            last_value: current_value,
+            velocity: 0.0,
+            gestures: GestureDetector::default(),
         }
     }
 
+    /// Sets how many counted steps make up one mechanical detent.
+    pub fn set_steps_per_detent(&mut self, steps: i32) {
+        self.steps_per_detent = steps.max(1);
+    }
+
+    /// Samples the encoder pins. Should be called as often as possible.
     pub fn update(&mut self) {
         self.clock.update();
         self.data.update();
@@ -68,4 +103,44 @@ where
 
         self.clock_state = current_clock_state;
     }
+
+    /// Returns the signed amount of whole detents since the last call. Steps of an unfinished
+    /// detent are kept for the next call.
+    pub fn get_delta(&mut self) -> i32 {
+        let detents = (self.current_value - self.last_value) / self.steps_per_detent;
+        self.last_value += detents * self.steps_per_detent;
+        detents
+    }
+
+    /// Evaluates movement and switch gestures, must be called once per control cycle.
+    pub fn tick(&mut self) -> EncoderTick {
+        let detents = self.get_delta();
+
+        EncoderTick {
+            detents,
+            accelerated: self.accelerate(detents),
+            gesture: self
+                .gestures
+                .tick(self.switch.is_rising(), self.switch.is_falling()),
+        }
+    }
+
+    /// Returns `true` while the switch is held down long enough to count as hold.
+    pub fn is_held(&self) -> bool {
+        self.gestures.is_held()
+    }
+
+    fn accelerate(&mut self, detents: i32) -> i32 {
+        let speed = detents.abs() as f32;
+        self.velocity = self.velocity * VELOCITY_SMOOTHING + speed * (1.0 - VELOCITY_SMOOTHING);
+
+        let multiplier = if self.velocity > ACCELERATION_THRESHOLD {
+            (1.0 + (self.velocity - ACCELERATION_THRESHOLD) * ACCELERATION_FACTOR)
+                .min(MAX_ACCELERATION)
+        } else {
+            1.0
+        };
+
+        (detents as f32 * multiplier) as i32
+    }
 }
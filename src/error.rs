@@ -0,0 +1,36 @@
+/// Failures of the hardware, returned instead of panicking so they can be reported over RTT or
+/// on the display.
+///
+/// Parts which are not needed to make sound, like the display, get left out when they fail.
+/// Everything else ends the start up with a report.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SitiraError {
+    /// A pin of the Daisy, by its number, got handed out twice
+    PinTaken(u8),
+    /// A pin could not be read or driven
+    Pin,
+    /// The display controller did not take a command
+    Display,
+    /// The framebuffer region lies outside of the SDRAM
+    Framebuffer,
+    /// An event queue between the tasks got split twice
+    Queue,
+    /// The UART of the MIDI input could not be set up
+    Midi,
+    /// The USB audio device could not be set up
+    Usb,
+}
+
+impl SitiraError {
+    pub fn description(&self) -> &'static str {
+        match self {
+            SitiraError::PinTaken(_) => "Pin of the Daisy taken twice",
+            SitiraError::Pin => "Pin could not be driven",
+            SitiraError::Display => "Display does not answer",
+            SitiraError::Framebuffer => "Framebuffer outside of the SDRAM",
+            SitiraError::Queue => "Event queue split twice",
+            SitiraError::Midi => "MIDI input could not be set up",
+            SitiraError::Usb => "USB audio could not be set up",
+        }
+    }
+}
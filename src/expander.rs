@@ -0,0 +1,102 @@
+use sitira_core::event::EventQueue;
+use sitira_core::expander::EXPANDER_KNOBS;
+
+#[cfg(feature = "expander")]
+use sitira_core::event::TimedEvent;
+#[cfg(feature = "expander")]
+use sitira_core::expander::{
+    ads1115_start, ExpansionPanel, ADS1115_ADDRESS, ADS1115_CONVERSION, MCP23017_ADDRESS,
+    MCP23017_GPIO, MCP23017_PULL_UPS,
+};
+#[cfg(feature = "expander")]
+use stm32h7xx_hal::hal::blocking::i2c::{Write, WriteRead};
+#[cfg(feature = "expander")]
+use stm32h7xx_hal::{i2c::I2c, stm32};
+
+#[cfg(feature = "expander")]
+use crate::rprintln;
+
+#[cfg(all(feature = "expander", feature = "gate-outputs"))]
+compile_error!("Pins 13 and 14 can either be `gate-outputs` or the I2C bus of the `expander`");
+#[cfg(all(feature = "expander", feature = "midi"))]
+compile_error!("Only one use of pin 14 can be selected, either `midi` or `expander`");
+
+/// Expansion panel on I2C1, with buttons on an MCP23017 and knobs on an ADS1115.
+///
+/// Either expander may be missing, the ones which do not answer at the start are left out.
+#[cfg(feature = "expander")]
+pub struct Expander {
+    i2c: I2c<stm32::I2C1>,
+    has_buttons: bool,
+    has_knobs: bool,
+    panel: ExpansionPanel,
+}
+
+#[cfg(feature = "expander")]
+impl Expander {
+    /// Looks for the expanders on the bus, sets the buttons up and starts the first conversion.
+    pub fn detect(mut i2c: I2c<stm32::I2C1>) -> Self {
+        let has_buttons = i2c.write(MCP23017_ADDRESS, &MCP23017_PULL_UPS).is_ok();
+        let has_knobs = i2c.write(ADS1115_ADDRESS, &ads1115_start(0)).is_ok();
+
+        rprintln!(
+            "Expansion panel: buttons {}, knobs {}",
+            if has_buttons { "found" } else { "missing" },
+            if has_knobs { "found" } else { "missing" }
+        );
+
+        Expander {
+            i2c,
+            has_buttons,
+            has_knobs,
+            panel: ExpansionPanel::new(),
+        }
+    }
+
+    /// Queues the edges of the buttons, takes the finished conversion and starts the next one. A
+    /// transfer which fails is tried again at the next poll.
+    pub fn poll(&mut self, timestamp: u32, events: &mut EventQueue) {
+        if self.has_buttons {
+            let mut levels = [0; 2];
+            let read = self
+                .i2c
+                .write_read(MCP23017_ADDRESS, &[MCP23017_GPIO], &mut levels);
+
+            if read.is_ok() {
+                self.panel
+                    .update_buttons(u16::from_le_bytes(levels), |event| {
+                        events.push(TimedEvent { event, timestamp });
+                    });
+            }
+        }
+
+        if self.has_knobs {
+            let mut conversion = [0; 2];
+            let read = self
+                .i2c
+                .write_read(ADS1115_ADDRESS, &[ADS1115_CONVERSION], &mut conversion);
+
+            if read.is_ok() {
+                let knob = self.panel.update_knob(conversion);
+                self.i2c.write(ADS1115_ADDRESS, &ads1115_start(knob)).ok();
+            }
+        }
+    }
+
+    pub fn get_knobs(&self) -> [f32; EXPANDER_KNOBS] {
+        self.panel.get_knobs()
+    }
+}
+
+/// Stands in for the expansion panel while pins 13 and 14 are no I2C bus.
+#[cfg(not(feature = "expander"))]
+pub struct Expander;
+
+#[cfg(not(feature = "expander"))]
+impl Expander {
+    pub fn poll(&mut self, _timestamp: u32, _events: &mut EventQueue) {}
+
+    pub fn get_knobs(&self) -> [f32; EXPANDER_KNOBS] {
+        [0.0; EXPANDER_KNOBS]
+    }
+}
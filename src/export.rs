@@ -0,0 +1,396 @@
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+
+use embedded_sdmmc::File;
+
+use crate::storage::{Error, Storage};
+
+/// Samples converted and written per step
+pub const EXPORT_SAMPLES_PER_STEP: usize = 1024;
+/// Length of the RIFF header written in front of the samples
+pub const HEADER_LENGTH: usize = 44;
+/// Longest `fmt ` chunk which gets read, the one of an extensible format
+const FMT_CHUNK_LENGTH: usize = 40;
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+/// Exports are numbered upwards, `TAKE0000.WAV` to `TAKE9999.WAV`
+const MAX_FILES: u32 = 10_000;
+
+/// Sample format of an exported WAV file.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WavFormat {
+    Float32,
+    Pcm16,
+}
+
+impl WavFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            WavFormat::Float32 => "32 bit float",
+            WavFormat::Pcm16 => "16 bit PCM",
+        }
+    }
+
+    pub fn toggle(&self) -> Self {
+        match self {
+            WavFormat::Float32 => WavFormat::Pcm16,
+            WavFormat::Pcm16 => WavFormat::Float32,
+        }
+    }
+
+    pub fn bytes_per_sample(&self) -> usize {
+        match self {
+            WavFormat::Float32 => 4,
+            WavFormat::Pcm16 => 2,
+        }
+    }
+
+    fn format_tag(&self) -> u16 {
+        match self {
+            WavFormat::Float32 => FORMAT_FLOAT,
+            WavFormat::Pcm16 => FORMAT_PCM,
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => WavFormat::Pcm16,
+            _ => WavFormat::Float32,
+        }
+    }
+}
+
+/// What the header of a WAV file tells about its samples.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WavInfo {
+    pub format: WavFormat,
+    pub sample_rate: u32,
+    pub samples: usize,
+    /// Bytes in front of the first sample
+    pub data_offset: usize,
+}
+
+/// Returns the header of a mono WAV file with `samples` samples.
+pub fn wav_header(format: WavFormat, sample_rate: u32, samples: usize) -> [u8; HEADER_LENGTH] {
+    let block_align = format.bytes_per_sample() as u32;
+    let data_length = samples as u32 * block_align;
+
+    let mut header = [0; HEADER_LENGTH];
+    let mut put = |offset: usize, bytes: &[u8]| {
+        header[offset..offset + bytes.len()].copy_from_slice(bytes);
+    };
+
+    put(0, b"RIFF");
+    put(4, &(data_length + HEADER_LENGTH as u32 - 8).to_le_bytes());
+    put(8, b"WAVE");
+    put(12, b"fmt ");
+    put(16, &16_u32.to_le_bytes());
+    put(20, &format.format_tag().to_le_bytes());
+    put(22, &1_u16.to_le_bytes());
+    put(24, &sample_rate.to_le_bytes());
+    put(28, &(sample_rate * block_align).to_le_bytes());
+    put(32, &(block_align as u16).to_le_bytes());
+    put(34, &(block_align as u16 * 8).to_le_bytes());
+    put(36, b"data");
+    put(40, &data_length.to_le_bytes());
+
+    header
+}
+
+/// Walks the RIFF chunks of `file` up to its samples and leaves the file positioned at them.
+///
+/// Chunks other than `fmt ` and `data`, like `fact`, `LIST` or `bext`, get skipped. Only mono
+/// files in one of the export formats are accepted, also when their format is extensible.
+pub fn read_wav_header(storage: &mut Storage, file: &mut File) -> Result<WavInfo, Error> {
+    let mut riff = [0; 12];
+    let read = storage.read(file, &mut riff)?;
+
+    if read < riff.len() || &riff[0..4] != b"RIFF" || &riff[8..12] != b"WAVE" {
+        return Err(Error::FormatError("Not a WAV file"));
+    }
+
+    let mut position = riff.len();
+    let mut format = None;
+
+    loop {
+        let mut chunk = [0; 8];
+
+        if storage.read(file, &mut chunk)? < chunk.len() {
+            return Err(Error::FormatError("No samples in the WAV file"));
+        }
+
+        let length = u32_at(&chunk, 4) as usize;
+        position += chunk.len();
+
+        match &chunk[0..4] {
+            b"fmt " => {
+                let mut fmt = [0; FMT_CHUNK_LENGTH];
+                let read = storage.read(file, &mut fmt[..length.min(FMT_CHUNK_LENGTH)])?;
+
+                format = Some(
+                    parse_fmt_chunk(&fmt[..read])
+                        .ok_or(Error::FormatError("Not a mono WAV file"))?,
+                );
+            }
+            b"data" => {
+                let (format, sample_rate) =
+                    format.ok_or(Error::FormatError("No format in front of the samples"))?;
+                // a file cut short holds fewer samples than its header tells
+                let length = length.min((file.length() as usize).saturating_sub(position));
+
+                return Ok(WavInfo {
+                    format,
+                    sample_rate,
+                    samples: length / format.bytes_per_sample(),
+                    data_offset: position,
+                });
+            }
+            _ => (),
+        }
+
+        // chunks are padded to an even length
+        position += length + length % 2;
+        storage.seek(file, position as u32)?;
+    }
+}
+
+/// Reads a `fmt ` chunk, returns format and sample rate. `None` for anything but mono samples of
+/// an export format.
+fn parse_fmt_chunk(fmt: &[u8]) -> Option<(WavFormat, u32)> {
+    if fmt.len() < 16 || u16_at(fmt, 2) != 1 {
+        return None;
+    }
+
+    // an extensible format has the actual tag at the start of its sub format
+    let tag = match u16_at(fmt, 0) {
+        FORMAT_EXTENSIBLE if fmt.len() >= FMT_CHUNK_LENGTH => u16_at(fmt, 24),
+        tag => tag,
+    };
+
+    let format = match (tag, u16_at(fmt, 14)) {
+        (FORMAT_FLOAT, 32) => WavFormat::Float32,
+        (FORMAT_PCM, 16) => WavFormat::Pcm16,
+        _ => return None,
+    };
+
+    Some((format, u32_at(fmt, 4)))
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+/// Encodes `samples` into `bytes`, returns the number of bytes used.
+pub fn encode_samples(format: WavFormat, samples: &[f32], bytes: &mut [u8]) -> usize {
+    let size = format.bytes_per_sample();
+
+    for (sample, chunk) in samples.iter().zip(bytes.chunks_exact_mut(size)) {
+        match format {
+            WavFormat::Float32 => chunk.copy_from_slice(&sample.to_le_bytes()),
+            WavFormat::Pcm16 => {
+                let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+                chunk.copy_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+
+    samples.len().min(bytes.len() / size) * size
+}
+
+/// Decodes `bytes` into `samples`, returns the number of samples filled. Partial samples at the
+/// end of `bytes` are dropped.
+pub fn decode_samples(format: WavFormat, bytes: &[u8], samples: &mut [f32]) -> usize {
+    let size = format.bytes_per_sample();
+
+    for (sample, chunk) in samples.iter_mut().zip(bytes.chunks_exact(size)) {
+        *sample = match format {
+            WavFormat::Float32 => f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]),
+            WavFormat::Pcm16 => i16::from_le_bytes([chunk[0], chunk[1]]) as f32 / i16::MAX as f32,
+        };
+    }
+
+    samples.len().min(bytes.len() / size)
+}
+
+/// Returns the name of the export with the given number.
+fn file_name(number: u32) -> [u8; 12] {
+    let mut name = *b"TAKE0000.WAV";
+
+    for (index, digit) in name[4..8].iter_mut().rev().enumerate() {
+        *digit = b'0' + (number / 10_u32.pow(index as u32) % 10) as u8;
+    }
+
+    name
+}
+
+/// Requests and progress of an export, shared between the control and the idle task.
+///
+/// An export writes the recording of a slot to the SD card as WAV file.
+pub struct Export {
+    requested: AtomicBool,
+    slot: AtomicUsize,
+    length: AtomicUsize,
+    format: AtomicU8,
+    written: AtomicUsize,
+    running: AtomicBool,
+}
+
+impl Export {
+    pub const fn new() -> Self {
+        Export {
+            requested: AtomicBool::new(false),
+            slot: AtomicUsize::new(0),
+            length: AtomicUsize::new(0),
+            format: AtomicU8::new(0),
+            written: AtomicUsize::new(0),
+            running: AtomicBool::new(false),
+        }
+    }
+
+    /// Requests writing `length` samples of `slot`. Ignored while an export is running.
+    pub fn request(&self, slot: usize, length: usize, format: WavFormat) -> bool {
+        if self.is_running() {
+            return false;
+        }
+
+        self.slot.store(slot, Ordering::Relaxed);
+        self.length.store(length, Ordering::Relaxed);
+        self.format.store(format as u8, Ordering::Relaxed);
+        self.written.store(0, Ordering::Relaxed);
+        self.requested.store(true, Ordering::Release);
+
+        true
+    }
+
+    /// Takes a pending request and marks the export as running. Returns slot, length and format.
+    pub fn take_request(&self) -> Option<(usize, usize, WavFormat)> {
+        if self.requested.swap(false, Ordering::Acquire) {
+            self.running.store(true, Ordering::Relaxed);
+
+            Some((
+                self.slot.load(Ordering::Relaxed),
+                self.length.load(Ordering::Relaxed),
+                WavFormat::from_u8(self.format.load(Ordering::Relaxed)),
+            ))
+        } else {
+            None
+        }
+    }
+
+    pub fn set_written(&self, written: usize) {
+        self.written.store(written, Ordering::Relaxed);
+    }
+
+    pub fn finish(&self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed) || self.requested.load(Ordering::Relaxed)
+    }
+
+    /// Returns the progress in percent.
+    pub fn get_progress(&self) -> u32 {
+        let length = self.length.load(Ordering::Relaxed);
+
+        if length == 0 {
+            return 0;
+        }
+
+        ((self.written.load(Ordering::Relaxed) * 100) / length) as u32
+    }
+}
+
+/// State of the export which is currently written by the idle task.
+pub struct ExportJob {
+    file: File,
+    name: [u8; 12],
+    pub slot: usize,
+    pub length: usize,
+    pub written: usize,
+    format: WavFormat,
+}
+
+impl ExportJob {
+    /// Creates the next free `TAKEnnnn.WAV` and writes its header.
+    pub fn start(
+        storage: &mut Storage,
+        slot: usize,
+        length: usize,
+        format: WavFormat,
+        sample_rate: u32,
+    ) -> Result<Self, Error> {
+        let name = (0..MAX_FILES)
+            .map(file_name)
+            .find(|name| !storage.exists(as_str(name)))
+            .ok_or(Error::NotEnoughSpace)?;
+
+        Self::create(storage, name, slot, length, format, sample_rate)
+    }
+
+    /// Creates the file `name`, replacing an existing one, and writes its header.
+    pub fn create(
+        storage: &mut Storage,
+        name: [u8; 12],
+        slot: usize,
+        length: usize,
+        format: WavFormat,
+        sample_rate: u32,
+    ) -> Result<Self, Error> {
+        let mut file = storage.create(as_str(&name))?;
+        storage.write(&mut file, &wav_header(format, sample_rate, length))?;
+
+        Ok(ExportJob {
+            file,
+            name,
+            slot,
+            length,
+            written: 0,
+            format,
+        })
+    }
+
+    pub fn get_name(&self) -> &str {
+        as_str(&self.name)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.written >= self.length
+    }
+
+    /// Writes the next samples of `buffer`, which holds the whole recording of `length` samples.
+    pub fn process(&mut self, storage: &mut Storage, buffer: &[f32]) -> Result<(), Error> {
+        let mut bytes = [0; EXPORT_SAMPLES_PER_STEP * 4];
+
+        let end = (self.written + EXPORT_SAMPLES_PER_STEP)
+            .min(self.length)
+            .min(buffer.len());
+        let used = encode_samples(self.format, &buffer[self.written..end], &mut bytes);
+
+        storage.write(&mut self.file, &bytes[..used])?;
+        self.written = end;
+
+        Ok(())
+    }
+
+    /// Closes the file, which is incomplete if the job has not finished.
+    pub fn close(self, storage: &mut Storage) -> Result<(), Error> {
+        storage.close(self.file)
+    }
+}
+
+fn as_str(name: &[u8; 12]) -> &str {
+    // names only consist of ASCII
+    core::str::from_utf8(name).unwrap_or("")
+}
+
+pub static EXPORT: Export = Export::new();
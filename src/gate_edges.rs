@@ -0,0 +1,165 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use cortex_m::peripheral::DWT;
+use stm32h7xx_hal::pac;
+
+use sitira_core::event::{
+    Event, EventConsumer, EventProducer, Input, TimedEvent, AUDIO_EVENTS, EVENT_QUEUE_SIZE,
+};
+use sitira_core::spsc::SpscQueue;
+
+use crate::daisy::Board;
+use crate::error::SitiraError;
+use crate::sitira::Daisy;
+
+/// GPIOA pins of the gate inputs in hardware order
+const GATE_PINS: [u32; 4] = Daisy::GATE_PINS;
+/// Number of gate inputs which raise interrupts
+pub const GATE_COUNT: usize = GATE_PINS.len();
+/// EXTI lines of all gate pins
+const GATE_LINES: u32 =
+    (1 << GATE_PINS[0]) | (1 << GATE_PINS[1]) | (1 << GATE_PINS[2]) | (1 << GATE_PINS[3]);
+
+/// Gate edges from the interrupts to the control task.
+static GATE_EVENTS: SpscQueue<TimedEvent, EVENT_QUEUE_SIZE> = SpscQueue::new();
+
+/// Logical gate numbers of the hardware gates, one per byte
+static GATE_ORDER: AtomicU32 = AtomicU32::new(u32::from_le_bytes([0, 1, 2, 3]));
+
+/// Sets the logical gate numbers of the hardware gates, before the interrupts get enabled.
+pub fn set_gate_order(order: [u8; GATE_COUNT]) {
+    GATE_ORDER.store(u32::from_le_bytes(order), Ordering::Relaxed);
+}
+
+/// Reads the level of a gate input in hardware order from the input data register.
+fn is_gate_high(idr: u32, gate: usize) -> bool {
+    // gate inputs are inverted by their input stage
+    idr & (1 << GATE_PINS[gate]) == 0
+}
+
+fn read_idr() -> u32 {
+    // SAFETY: the input data register is read only
+    unsafe { (*pac::GPIOA::ptr()).idr.read().bits() }
+}
+
+/// Returns the event of a hardware gate changing to `high`.
+fn gate_event(gate: usize, high: bool, timestamp: u32) -> TimedEvent {
+    let order = GATE_ORDER.load(Ordering::Relaxed).to_le_bytes();
+    let input = Input::Gate(order[gate]);
+
+    TimedEvent {
+        event: if high {
+            Event::Pressed(input)
+        } else {
+            Event::Released(input)
+        },
+        timestamp,
+    }
+}
+
+/// The ends of the queues the gate interrupts push their edges into.
+pub struct GateEdges {
+    control: EventProducer,
+    audio: EventProducer,
+}
+
+/// Splits the gate edge queues, returns the producing ends for the interrupts, the edges for the
+/// debouncer of the control task and the edges for the audio task. Can only be called once.
+pub fn split() -> Result<(GateEdges, EventConsumer, EventConsumer), SitiraError> {
+    let (control, control_edges) = GATE_EVENTS.split().ok_or(SitiraError::Queue)?;
+    let (audio, audio_edges) = AUDIO_EVENTS.split().ok_or(SitiraError::Queue)?;
+
+    Ok((GateEdges { control, audio }, control_edges, audio_edges))
+}
+
+/// Queues the edges of all gates with a pending interrupt for the control and the audio task.
+///
+/// Gets called by the interrupts of all gate lines, which have to run at the same priority and
+/// share the `edges` without a lock.
+pub fn on_interrupt(edges: &mut GateEdges) {
+    // SAFETY: only the pending bits of the gate lines get cleared
+    let exti = unsafe { &*pac::EXTI::ptr() };
+    let pending = exti.cpupr1.read().bits() & GATE_LINES;
+
+    exti.cpupr1.write(|w| unsafe { w.bits(pending) });
+
+    let idr = read_idr();
+    let timestamp = DWT::cycle_count();
+
+    for (gate, pin) in GATE_PINS.iter().enumerate() {
+        if pending & (1 << pin) != 0 {
+            let event = gate_event(gate, is_gate_high(idr, gate), timestamp);
+
+            edges.control.push(event);
+            edges.audio.push(event);
+        }
+    }
+}
+
+/// Turns the queued gate edges into debounced events for the control task.
+///
+/// The interrupts catch every edge, even of triggers much shorter than a control cycle. An edge
+/// which follows the last accepted edge of its gate too closely is taken as bounce and dropped.
+/// Since that might have been the final edge, the levels of the gates get compared once all
+/// edges have been handled.
+pub struct GateDebouncer {
+    edges: EventConsumer,
+    states: [bool; GATE_COUNT],
+    last_edges: [u32; GATE_COUNT],
+    debounce_cycles: u32,
+}
+
+impl GateDebouncer {
+    pub fn new(edges: EventConsumer, debounce_cycles: u32) -> Self {
+        GateDebouncer {
+            edges,
+            states: [false; GATE_COUNT],
+            last_edges: [0; GATE_COUNT],
+            debounce_cycles,
+        }
+    }
+
+    /// Handles all queued edges and calls `on_event` for every accepted one.
+    pub fn drain<F>(&mut self, mut on_event: F)
+    where
+        F: FnMut(TimedEvent),
+    {
+        while let Some(event) = self.edges.pop() {
+            self.accept(event, &mut on_event);
+        }
+
+        // the levels are read after the queue is empty, so no edge can get lost in between
+        let idr = read_idr();
+        let timestamp = DWT::cycle_count();
+
+        for gate in 0..GATE_COUNT {
+            self.accept(
+                gate_event(gate, is_gate_high(idr, gate), timestamp),
+                &mut on_event,
+            );
+        }
+    }
+
+    fn accept<F>(&mut self, timed: TimedEvent, on_event: &mut F)
+    where
+        F: FnMut(TimedEvent),
+    {
+        let (gate, high) = match timed.event {
+            Event::Pressed(Input::Gate(gate)) => (gate as usize, true),
+            Event::Released(Input::Gate(gate)) => (gate as usize, false),
+            _ => return,
+        };
+
+        let state = &mut self.states[gate];
+        let last_edge = &mut self.last_edges[gate];
+
+        if high == *state || timed.timestamp.wrapping_sub(*last_edge) < self.debounce_cycles {
+            return;
+        }
+
+        *state = high;
+        *last_edge = timed.timestamp;
+
+        on_event(timed);
+    }
+}
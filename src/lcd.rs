@@ -1,72 +1,277 @@
-use core::ops::Neg;
-
-use display_interface_spi::SPIInterface;
-use ili9341::{DisplaySize240x320, Ili9341, Orientation};
-use stm32h7xx_hal::hal;
+use core::ops::Range;
 
 use embedded_graphics::{
-    mono_font::{ascii, MonoTextStyle},
-    pixelcolor::Rgb565,
+    pixelcolor::{IntoStorage, Rgb565},
     prelude::*,
-    primitives::{Polyline, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
-    text::{Alignment, Text},
+    primitives::{PrimitiveStyle, Rectangle},
 };
 
-use micromath::F32Ext;
+use sitira_core::curve::ResponseCurve;
+use sitira_core::grains::{GrainDot, Window};
+use sitira_core::kit::Kit;
+use sitira_core::screen::{Layout, Screen};
+use sitira_core::theme::THEME;
+use sitira_core::waveform::Peak;
+
+use crate::display_dma::{DisplayDma, MAX_TRANSFER_SIZE};
+use crate::display_driver::{DisplayDriver, RowTransfer};
+use crate::error::SitiraError;
+use crate::rprintln;
+
+/// Number of patterns `Lcd::show_test_pattern()` cycles through
+pub const TEST_PATTERNS: usize = 3;
+
+/// Height of the horizontal bands which are tracked for changes
+const BAND_HEIGHT: usize = 16;
+
+/// Off-screen copy of the display content which keeps track of changed bands.
+///
+/// Drawing only touches the buffer. A pixel marks its band dirty only if its color actually
+/// changes, so redrawing unchanged content costs no SPI bandwidth at all. Bands span the whole
+/// width, so that every run of dirty bands is one contiguous block of memory which can be sent
+/// with a single DMA transfer. Pixels are stored big endian, as the display expects them.
+pub struct FrameBuffer {
+    pixels: &'static mut [u16],
+    width: usize,
+    height: usize,
+    dirty: u32,
+}
+
+impl FrameBuffer {
+    /// Creates a framebuffer on top of `pixels`, which needs to hold `width * height` values.
+    /// The height needs to be a multiple of the band height.
+    pub fn new(pixels: &'static mut [u16], width: usize, height: usize) -> Self {
+        assert!(pixels.len() >= width * height);
+        assert!(height % BAND_HEIGHT == 0 && height / BAND_HEIGHT <= u32::BITS as usize);
+
+        let mut frame = FrameBuffer {
+            pixels,
+            width,
+            height,
+            dirty: 0,
+        };
+
+        // the display content is unknown at the beginning
+        frame.mark_all_dirty();
+        frame
+    }
+
+    /// Number of bands, one bit of the dirty mask each
+    fn get_band_count(&self) -> usize {
+        self.height / BAND_HEIGHT
+    }
+
+    /// Bytes of one band
+    fn get_band_size(&self) -> usize {
+        self.width * BAND_HEIGHT * 2
+    }
+
+    fn mark_all_dirty(&mut self) {
+        self.dirty = u32::MAX >> (u32::BITS as usize - self.get_band_count());
+    }
+
+    fn set_pixel(&mut self, x: usize, y: usize, raw: u16) {
+        let index = y * self.width + x;
+        let raw = raw.to_be();
+
+        if self.pixels[index] != raw {
+            self.pixels[index] = raw;
+            self.dirty |= 1 << (y / BAND_HEIGHT);
+        }
+    }
+
+    /// Returns the dirty bands as bitmask and marks everything clean.
+    fn take_dirty(&mut self) -> u32 {
+        let dirty = self.dirty;
+        self.dirty = 0;
+        dirty
+    }
+
+    /// Raw bytes of the given range of bands.
+    fn get_band_bytes(&self, first: usize, count: usize) -> &[u8] {
+        let start = first * self.width * BAND_HEIGHT;
+        let pixels = &self.pixels[start..start + count * self.width * BAND_HEIGHT];
 
-pub struct Lcd<SPI, DC, CS, RESET> {
-    driver: Ili9341<SPIInterface<SPI, DC, CS>, RESET>,
+        // SAFETY: every u16 is made of two initialized bytes
+        unsafe { core::slice::from_raw_parts(pixels.as_ptr() as *const u8, pixels.len() * 2) }
+    }
 }
 
-impl<SPI, DC, CS, RESET> Lcd<SPI, DC, CS, RESET>
-where
-    SPI: hal::blocking::spi::Write<u8>,
-    DC: hal::digital::v2::OutputPin,
-    CS: hal::digital::v2::OutputPin,
-    RESET: hal::digital::v2::OutputPin,
-{
-    pub fn new<DELAY>(spi: SPI, dc: DC, cs: CS, reset: RESET, mut delay: DELAY) -> Self
+impl OriginDimensions for FrameBuffer {
+    fn size(&self) -> Size {
+        Size::new(self.width as u32, self.height as u32)
+    }
+}
+
+impl DrawTarget for FrameBuffer {
+    type Color = Rgb565;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
     where
-        DELAY: libdaisy::prelude::_embedded_hal_blocking_delay_DelayMs<u16>,
+        I: IntoIterator<Item = Pixel<Self::Color>>,
     {
-        let interface = SPIInterface::new(spi, dc, cs);
+        for Pixel(point, color) in pixels {
+            if point.x >= 0
+                && point.y >= 0
+                && (point.x as usize) < self.width
+                && (point.y as usize) < self.height
+            {
+                self.set_pixel(point.x as usize, point.y as usize, color.into_storage());
+            }
+        }
 
-        let driver = Ili9341::new(
-            interface,
-            reset,
-            &mut delay,
-            Orientation::Landscape,
-            DisplaySize240x320,
-        )
-        .unwrap();
+        Ok(())
+    }
+}
 
-        Self { driver }
+/// Display which is drawn through a framebuffer, on whichever panel the driver controls.
+///
+/// A panel which does not answer gets dropped, everything is drawn to the framebuffer as before
+/// but goes nowhere.
+pub struct Lcd<D> {
+    driver: Option<D>,
+    frame: FrameBuffer,
+    dma: DisplayDma,
+    /// Bands which still need to be sent by the running flush
+    pending: u32,
+    brightness: u8,
+    sleeping: bool,
+}
+
+impl<D: DisplayDriver> Lcd<D> {
+    /// Draws on a framebuffer on top of `pixels`, which needs to hold every pixel of the panel.
+    /// Without a `driver` nothing is shown.
+    pub fn new(driver: Option<D>, pixels: &'static mut [u16]) -> Self {
+        Self {
+            driver,
+            frame: FrameBuffer::new(pixels, D::WIDTH, D::HEIGHT),
+            dma: DisplayDma::new(),
+            pending: 0,
+            brightness: u8::MAX,
+            sleeping: false,
+        }
     }
 
-    pub fn clear(&mut self) {
-        self.driver.clear(Rgb565::BLACK).unwrap();
+    /// Sets the backlight brightness, if the panel supports it. Must not be called while busy.
+    pub fn set_brightness(&mut self, brightness: u8) {
+        if brightness != self.brightness {
+            self.command(|driver| driver.set_brightness(brightness));
+            self.brightness = brightness;
+        }
     }
 
-    pub fn setup(&mut self) {
-        self.driver.clear(Rgb565::BLACK).unwrap();
+    /// Sends a command to the panel, a panel which fails gets dropped.
+    fn command(&mut self, command: impl FnOnce(&mut D) -> Result<(), SitiraError>) {
+        if let Some(Err(error)) = self.driver.as_mut().map(command) {
+            self.drop_driver(error);
+        }
+    }
+
+    fn drop_driver(&mut self, error: SitiraError) {
+        rprintln!("{}, the display is left out!", error.description());
+        self.driver = None;
+    }
+
+    pub fn is_sleeping(&self) -> bool {
+        self.sleeping
+    }
+
+    /// Turns the display dark. With `deep` set, the controller enters its sleep mode as well.
+    /// Must not be called while busy.
+    pub fn sleep(&mut self, deep: bool) {
+        self.set_brightness(0);
+
+        if deep {
+            self.command(|driver| driver.set_sleep_mode(true));
+        }
+
+        self.sleeping = true;
+    }
+
+    /// Leaves the sleep mode and restores full brightness. Must not be called while busy.
+    pub fn wake(&mut self) {
+        // leaving the sleep mode is harmless if it has not been entered
+        self.command(|driver| driver.set_sleep_mode(false));
+        self.set_brightness(u8::MAX);
+        self.sleeping = false;
+    }
+
+    /// Returns `true` while a flush is still being transferred. The framebuffer should not be
+    /// drawn to in the meantime.
+    pub fn is_busy(&self) -> bool {
+        self.dma.is_busy()
+    }
+
+    /// Starts transferring all changed bands of the framebuffer to the display. Does nothing
+    /// while the previous flush is still running.
+    pub fn flush(&mut self) {
+        if self.is_busy() {
+            return;
+        }
+
+        self.pending = self.frame.take_dirty();
+        self.start_next_transfer();
+    }
+
+    /// Continues the running flush, must be called from the DMA transfer complete interrupt.
+    pub fn on_transfer_complete(&mut self) {
+        self.dma.finish();
+        self.start_next_transfer();
+    }
 
-        let character_style = MonoTextStyle::new(&ascii::FONT_10X20, Rgb565::WHITE);
+    /// Sends the next run of pending bands. Runs the driver writes on its own follow right away,
+    /// the ones streamed by DMA continue in the transfer complete interrupt.
+    fn start_next_transfer(&mut self) {
+        while self.pending != 0 {
+            let first = self.pending.trailing_zeros() as usize;
+            let mut count = 0;
+
+            // merge consecutive bands as long as they fit into one transfer
+            while first + count < self.frame.get_band_count()
+                && self.pending & (1 << (first + count)) != 0
+                && (count + 1) * self.frame.get_band_size() <= MAX_TRANSFER_SIZE
+            {
+                self.pending &= !(1 << (first + count));
+                count += 1;
+            }
+
+            let rows = first * BAND_HEIGHT..(first + count) * BAND_HEIGHT;
+            let bytes = self.frame.get_band_bytes(first, count);
+
+            // without a panel the bands are done right away
+            match self
+                .driver
+                .as_mut()
+                .map(|driver| driver.write_rows(rows, bytes))
+            {
+                Some(Ok(RowTransfer::Dma)) => {
+                    // SAFETY: the framebuffer is not drawn to while the display is busy
+                    unsafe { self.dma.start(bytes) };
+                    return;
+                }
+                Some(Ok(RowTransfer::Written)) | None => (),
+                Some(Err(error)) => self.drop_driver(error),
+            }
+        }
+    }
 
-        let middle_x: i32 = (self.driver.width() / 2) as i32;
-        let middle_y: i32 = (self.driver.height() / 2) as i32;
+    pub fn clear(&mut self) {
+        let Ok(()) = self.frame.clear(THEME.get_palette().background);
+    }
 
-        let start_text = "Sitira Synth\nby Max Genson\n\nWritten in Rust";
-        let position = Point::new(middle_x, middle_y - ((4 * 22) / 2));
+    pub fn setup(&mut self) {
+        self.frame.draw_splash();
+        self.flush();
+    }
 
-        Text::with_alignment(start_text, position, character_style, Alignment::Center)
-            .draw(&mut self.driver)
-            .unwrap();
+    /// Regions of the interface for the size of the panel.
+    pub fn get_layout(&self) -> Layout {
+        self.frame.get_layout()
     }
 
     pub fn clear_subsection(&mut self, area: Rectangle) {
-        area.into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
-            .draw(&mut self.driver)
-            .unwrap();
+        self.frame.clear_subsection(area);
     }
 
     pub fn fill_subsection_with_corners(
@@ -75,151 +280,211 @@ where
         bottom_right: Point,
         color: Rgb565,
     ) {
-        Rectangle::with_corners(top_left, bottom_right)
-            .into_styled(PrimitiveStyle::with_fill(color))
-            .draw(&mut self.driver)
-            .unwrap();
+        self.frame
+            .fill_subsection_with_corners(top_left, bottom_right, color);
     }
 
-    pub fn draw_waveform(&mut self, audio_slice: &[f32]) {
-        const WAVE_WIDTH: usize = 320;
-        const WAVE_Y_OFFSET: i32 = 120;
-        const WAVE_HEIGHT: i32 = 60;
+    pub fn draw_waveform(&mut self, columns: &[Peak]) {
+        self.frame.draw_waveform(columns);
+    }
+
+    /// Draws slice markers as vertical lines across the waveform. The selected slice is highlighted.
+    pub fn draw_slice_markers(
+        &mut self,
+        markers: &[usize],
+        buffer_length: usize,
+        selected: Option<usize>,
+    ) {
+        self.frame
+            .draw_slice_markers(markers, buffer_length, selected);
+    }
 
-        const X_SCALER: usize = 1;
+    /// Draws the trim points within the view of a zoomed waveform and where both lie in the buffer.
+    pub fn draw_trim(&mut self, view: Range<usize>, trim: Range<usize>, buffer_length: usize) {
+        self.frame.draw_trim(view, trim, buffer_length);
+    }
 
-        let buffer_length = audio_slice.len();
-        let step = buffer_length / 320;
+    /// Draws a response curve with its breakpoints in place of the waveform. The selected
+    /// breakpoint is highlighted.
+    pub fn draw_curve(&mut self, curve: &ResponseCurve, selected: usize, channel: usize) {
+        self.frame.draw_curve(curve, selected, channel);
+    }
 
-        let mut points_iter =
-            audio_slice
-                .iter()
-                .enumerate()
-                .step_by(step / X_SCALER)
-                .map(|(i, sample)| {
-                    let x = (i as f32 / buffer_length as f32) * (WAVE_WIDTH * X_SCALER) as f32;
-                    let y = log_scale(log_scale(log_scale(sample.abs()))) * WAVE_HEIGHT as f32;
+    /// Draws the pads of the kit in place of the waveform, the selected pad is highlighted.
+    pub fn draw_kit(&mut self, kit: &Kit) {
+        self.frame.draw_kit(kit);
+    }
 
-                    Point::new(
-                        x as i32,
-                        y.clamp(WAVE_HEIGHT.neg() as f32, WAVE_HEIGHT as f32) as i32,
-                    )
-                });
+    /// Plots the playing grains in place of the waveform.
+    pub fn draw_grain_view(&mut self, dots: impl Iterator<Item = GrainDot>) {
+        self.frame.draw_grain_view(dots);
+    }
 
-        let mut inversed_points_iter = points_iter.clone();
+    /// Draws the output spectrum in place of the waveform.
+    pub fn draw_spectrum(&mut self, bands: &[f32]) {
+        self.frame.draw_spectrum(bands);
+    }
 
-        let mut points: [Point; WAVE_WIDTH] = [Point::new(0, 0); WAVE_WIDTH];
+    /// Shows a centered message in place of the waveform.
+    pub fn draw_message(&mut self, message: &str) {
+        self.frame.draw_message(message);
+    }
 
-        for i in 0..WAVE_WIDTH {
-            points[i] = points_iter.next().unwrap();
-            points[i].y = points[i].y + WAVE_Y_OFFSET;
-        }
+    /// Shows a fault report and transfers the whole frame without relying on interrupts.
+    pub fn show_fault(&mut self, title: &str, lines: &[&str]) {
+        self.show_screen(Rgb565::RED, title, lines);
+    }
 
-        let line_style = PrimitiveStyle::with_stroke(Rgb565::CSS_VIOLET, 1);
+    /// Shows a status screen while the tasks are not running, e.g. during start up.
+    pub fn show_status(&mut self, title: &str, lines: &[&str]) {
+        self.show_screen(Rgb565::BLACK, title, lines);
+    }
 
-        Polyline::new(&points)
-            .into_styled(line_style)
-            .draw(&mut self.driver)
-            .unwrap();
+    fn show_screen(&mut self, background: Rgb565, title: &str, lines: &[&str]) {
+        self.dma.wait();
 
-        for i in 0..WAVE_WIDTH {
-            points[i] = inversed_points_iter.next().unwrap();
-            points[i].y = -points[i].y + WAVE_Y_OFFSET;
+        if self.sleeping {
+            self.wake();
         }
 
-        Polyline::new(&points)
-            .into_styled(line_style)
-            .draw(&mut self.driver)
-            .unwrap();
+        self.frame.draw_report(background, title, lines);
+        self.transfer_frame();
+    }
 
-        let upper_bound = [
-            Point::new(0, WAVE_Y_OFFSET - WAVE_HEIGHT),
-            Point::new(320, WAVE_Y_OFFSET - WAVE_HEIGHT),
+    /// Shows one of `TEST_PATTERNS` full screen, without relying on interrupts.
+    pub fn show_test_pattern(&mut self, pattern: usize) {
+        const BAR_COLORS: [Rgb565; 8] = [
+            Rgb565::WHITE,
+            Rgb565::YELLOW,
+            Rgb565::CYAN,
+            Rgb565::GREEN,
+            Rgb565::MAGENTA,
+            Rgb565::RED,
+            Rgb565::BLUE,
+            Rgb565::BLACK,
         ];
+        const GRID_SPACING: usize = 16;
 
-        let lower_bound = [
-            Point::new(0, WAVE_Y_OFFSET + WAVE_HEIGHT),
-            Point::new(320, WAVE_Y_OFFSET + WAVE_HEIGHT),
-        ];
+        self.dma.wait();
 
-        let line_style = PrimitiveStyle::with_stroke(Rgb565::new(16, 32, 16), 1);
+        if self.sleeping {
+            self.wake();
+        }
 
-        Polyline::new(&lower_bound)
-            .into_styled(line_style)
-            .draw(&mut self.driver)
-            .unwrap();
+        match pattern % TEST_PATTERNS {
+            // color bars show swapped or missing color bits
+            0 => {
+                let width = (self.frame.width / BAR_COLORS.len()) as u32;
+
+                for (index, color) in BAR_COLORS.iter().enumerate() {
+                    let bar = Rectangle::new(
+                        Point::new(index as i32 * width as i32, 0),
+                        Size::new(width, self.frame.height as u32),
+                    );
+
+                    // drawing on the framebuffer can not fail
+                    let Ok(()) = bar
+                        .into_styled(PrimitiveStyle::with_fill(*color))
+                        .draw(&mut self.frame);
+                }
+            }
+            // a white screen shows dead pixels and an uneven backlight
+            1 => {
+                let Ok(()) = self.frame.clear(Rgb565::WHITE);
+            }
+            // the grid shows offsets and missing rows or columns
+            _ => {
+                let Ok(()) = self.frame.clear(Rgb565::BLACK);
+
+                for x in 0..self.frame.width {
+                    for y in 0..self.frame.height {
+                        if x % GRID_SPACING == 0 || y % GRID_SPACING == 0 {
+                            self.frame.set_pixel(x, y, Rgb565::WHITE.into_storage());
+                        }
+                    }
+                }
+            }
+        }
 
-        Polyline::new(&upper_bound)
-            .into_styled(line_style)
-            .draw(&mut self.driver)
-            .unwrap();
+        self.transfer_frame();
     }
 
-    pub fn draw_loading_bar(&mut self, percentage: u32, filename: &str) {
-        if percentage == 0 {
-            let border_style = PrimitiveStyleBuilder::new()
-                .stroke_color(Rgb565::WHITE)
-                .stroke_width(3)
-                .build();
-
-            let position = Point::new(40, 200);
-
-            // border
-            Rectangle::new(
-                position,
-                Size {
-                    width: 240,
-                    height: 20,
-                },
-            )
-            .into_styled(border_style)
-            .draw(&mut self.driver)
-            .unwrap();
-
-            let character_style = MonoTextStyle::new(&ascii::FONT_6X9, Rgb565::WHITE);
-
-            let position = Point::new((self.driver.width() / 2) as i32, 190);
-
-            Text::with_alignment(filename, position, character_style, Alignment::Center)
-                .draw(&mut self.driver)
-                .unwrap();
-        }
+    /// Transfers the whole frame and waits until it is done.
+    fn transfer_frame(&mut self) {
+        self.frame.mark_all_dirty();
+        self.pending = self.frame.take_dirty();
 
-        let loading_bar_style = PrimitiveStyleBuilder::new()
-            .fill_color(Rgb565::WHITE)
-            .build();
-
-        if percentage <= 100 {
-            let position = Point::new(46, 206);
-
-            Rectangle::new(
-                position,
-                Size {
-                    width: (231 * percentage) / 100,
-                    height: 8,
-                },
-            )
-            .into_styled(loading_bar_style)
-            .draw(&mut self.driver)
-            .unwrap();
+        while self.pending != 0 {
+            self.start_next_transfer();
+            self.dma.wait();
         }
     }
 
-    pub fn print_on_screen(&mut self, x: usize, y: usize, message: &str) -> Rectangle {
-        let character_style = MonoTextStyle::new(&ascii::FONT_6X9, Rgb565::WHITE);
+    /// Draws a page of parameters in place of the waveform, every value as a bar. The focused
+    /// row is highlighted, the automated ones get the accent color.
+    pub fn draw_parameter_page(
+        &mut self,
+        title: &str,
+        parameters: &[(&str, f32, bool)],
+        focused: Option<usize>,
+    ) {
+        self.frame.draw_parameter_page(title, parameters, focused);
+    }
 
-        let position = Point::new(x as i32, y as i32);
+    /// Shows the parameter which is being changed below the readout, with its value as a bar.
+    pub fn draw_parameter_popup(&mut self, label: &str, value: f32) {
+        self.frame.draw_parameter_popup(label, value);
+    }
 
-        let text = Text::new(message, position, character_style);
+    /// Shows the window of the grains in place of the bar of the popup.
+    pub fn draw_window_popup(&mut self, label: &str, window: Window, shape: f32) {
+        self.frame.draw_window_popup(label, window, shape);
+    }
 
-        let bounding_box = text.bounding_box();
+    pub fn clear_parameter_popup(&mut self) {
+        self.frame.clear_parameter_popup();
+    }
 
-        text.draw(&mut self.driver).unwrap();
+    /// Draws the playback offset, the buffer length and the normalization gain above the
+    /// waveform.
+    pub fn draw_time_readout(&mut self, offset: &str, length: &str, gain: &str) {
+        self.frame.draw_time_readout(offset, length, gain);
+    }
 
-        bounding_box
+    /// Draws the density, the pitch and the load of the grain cloud below the time readout.
+    pub fn draw_cloud_readout(&mut self, density: &str, pitch: &str, load: &str) {
+        self.frame.draw_cloud_readout(density, pitch, load);
     }
-}
 
-fn log_scale(value: f32) -> f32 {
-    (value + 1.0).log10() * (1.0 / 2.0.log10())
+    /// Previews below the waveform how grain size and pitch stretch the buffer.
+    pub fn draw_stretch_preview(&mut self, grain: &str, pitch: &str, speed: &str) {
+        self.frame.draw_stretch_preview(grain, pitch, speed);
+    }
+
+    pub fn clear_stretch_preview(&mut self) {
+        self.frame.clear_stretch_preview();
+    }
+
+    /// Shows below the readout that the knobs control the shift bank.
+    pub fn draw_shift_indicator(&mut self, shifted: bool) {
+        self.frame.draw_shift_indicator(shifted);
+    }
+
+    pub fn draw_card_error(&mut self, failed: bool) {
+        self.frame.draw_card_error(failed);
+    }
+
+    /// Draws a horizontal level meter with a label on the left. The RMS level is drawn as a bar,
+    /// the peak level as a thin marker. The bar turns red when the peak is clipping.
+    pub fn draw_meter(&mut self, position: Point, label: &str, rms: f32, peak: f32) {
+        self.frame.draw_meter(position, label, rms, peak);
+    }
+
+    pub fn draw_loading_bar(&mut self, percentage: u32, filename: &str) {
+        self.frame.draw_loading_bar(percentage, filename);
+    }
+
+    pub fn print_on_screen(&mut self, x: usize, y: usize, message: &str) -> Rectangle {
+        self.frame.print_on_screen(x, y, message)
+    }
 }
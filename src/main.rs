@@ -1,14 +1,44 @@
 #![no_main]
 #![no_std]
 
+pub mod audio;
+pub mod autosave;
+pub mod background;
 pub mod binary_input;
+pub mod board;
 pub mod config;
+pub mod control;
+pub mod daisy;
+pub mod display;
+pub mod display_dma;
+pub mod display_driver;
 pub mod dual_mux_4051;
 pub mod encoder;
+pub mod error;
+pub mod expander;
+pub mod export;
+pub mod gate_edges;
 pub mod lcd;
+pub mod mapping_file;
+pub mod midi_input;
+pub mod panic;
+pub mod playback;
+pub mod pwm_cv;
 pub mod rgbled;
+pub mod sample_file;
 pub mod sdram;
+pub mod selftest;
+pub mod session_file;
 pub mod sitira;
+pub mod slots;
+pub mod ssd1306;
+pub mod st7789;
+pub mod storage;
+pub mod theme_file;
+pub mod update;
+pub mod usb_output;
+pub mod watchdog;
+pub mod waveform_cache;
 
 #[rtic::app(
     device = stm32h7xx_hal::stm32,
@@ -16,27 +46,105 @@ pub mod sitira;
 )]
 mod app {
     use crate::{
-        sdram,
-        sitira::{AdcMuxInputs, AudioRate, ControlRate, Sitira, VisualRate},
+        audio::{BLOCKS_PER_CONTROL_CYCLE, GATE_PULSE_FRAMES, KIT_TAIL_FRAMES},
+        autosave, background,
+        config::{
+            AUDIO_SAMPLE_RATE, AUTOSAVE_IN_S, CONTROL_RATE_IN_MS, CPU_FREQUENCY_IN_HZ,
+            DEFAULT_BOUNCE_SECONDS, ENVELOPE_ATTACK_IN_MS, ENVELOPE_RELEASE_IN_MS,
+            EXPANDER_BUTTON_ACTIONS, GRAIN_LOAD_CEILING, GRAIN_LOAD_TARGET, KNOB_PICKUP_THRESHOLD,
+            LOOPER_CROSSFADE_IN_MS, METRONOME_BEATS_PER_BAR, MONITOR_CROSSFADE_IN_MS,
+            MUTE_RAMP_IN_MS, OFFSET_PICKUP_THRESHOLD, OFFSET_SCRUB_RANGE_IN_S,
+            OFFSET_SCRUB_STEP_IN_MS, OUTPUT_MAX_DB, OUTPUT_MIN_DB, PITCH_SHIFT_GRAIN_IN_MS,
+            ROTATION_DIVISION, WATCHDOG_TIMEOUT_IN_MS,
+        },
+        control::{self, initial_user_settings, new_grain_cloud},
+        display,
+        export::WavFormat,
+        gate_edges::{self, GateEdges},
+        mapping_file,
+        midi_input::MidiInput,
+        panic,
+        playback::{format_time, ANALYSIS_REQUESTED, IS_RECORDING, SOURCE},
+        sdram, selftest,
+        sitira::{AudioRate, ControlRate, Display, SdCard, Sitira, VisualRate},
+        slots::{self, SLOTS, SLOT_LENGTH},
+        storage::Storage,
+        theme_file, update,
+        usb_output::UsbAudio,
+        watchdog::{self, Watchdog},
     };
-
-    use granulator::{Granulator, ModeType, ScaleType, UserSettings, WindowFunction};
-    use stm32h7xx_hal::prelude::_embedded_hal_adc_OneShot;
-
-    use libdaisy::prelude::OutputPin;
-
-    use core::{
-        sync::atomic::{AtomicBool, AtomicUsize, Ordering},
-        time::Duration,
+    use sitira_core::{
+        automation::Automation,
+        bounce::BounceJob,
+        calibration::{Calibration, CalibrationStage},
+        card::CardError,
+        clock::ClockFollower,
+        crossfade::Crossfade,
+        curve::CurveSet,
+        echo::Echo,
+        editor::WaveformEditor,
+        event::EventQueue,
+        follower::EnvelopeFollower,
+        grain_limit::GrainLimit,
+        grains::GrainCloud,
+        interpolation::SettingsInterpolator,
+        kit::{Kit, KitVoice, KIT_PADS},
+        looper::Looper,
+        mapping::{ControlMaps, Parameter},
+        menu::Menu,
+        meter::{BlockMeter, ClipIndicator},
+        metronome::Metronome,
+        midi_learn::MidiLearn,
+        mixer::Mixer,
+        modulation::ModMatrix,
+        output::OutputStage,
+        pages::ChangeDetector,
+        pitch_shift::PitchShifter,
+        pre_roll::PreRoll,
+        pulse::PulseScheduler,
+        quantizer::Quantizer,
+        resample::ResampleQuality,
+        reverb::Reverb,
+        rotation::{BufferRotation, RotationAmount},
+        routing::{TriggerAction, TriggerRouting},
+        scene::SceneMorph,
+        scrub::OffsetScrub,
+        session::Session,
+        settings::{EngineSettings, UserSettings},
+        shift::ShiftLayer,
+        slew::SlewLimiter,
+        slices::SliceMarkers,
+        soak::{SignalGenerator, SoakSchedule},
+        tempo::TapTempo,
+        texture::Texture,
+        theme::THEME,
+        timecode::TimeText,
+        transport::{Transport, TransportState},
+        varispeed::Varispeed,
+        waveform::Peak,
     };
 
+    use core::sync::atomic::Ordering;
+
     #[allow(unused_imports)]
     use crate::rprintln;
 
     #[shared]
     struct Shared {
         audio_buffer: &'static [f32],
-        user_settings: granulator::UserSettings,
+        user_settings: UserSettings,
+        engine_settings: EngineSettings,
+        menu: Menu,
+        slices: SliceMarkers,
+        editor: WaveformEditor,
+        curves: CurveSet,
+        calibration: Calibration,
+        kit: Kit,
+        session: Session,
+        #[lock_free]
+        lcd: Display,
+        #[lock_free]
+        gate_edges: GateEdges,
     }
 
     #[local]
@@ -45,55 +153,302 @@ mod app {
         cr: ControlRate,
         vr: VisualRate,
         sdram: &'static mut [f32],
-        granulator: Granulator,
+        midi_input: MidiInput,
+        usb_audio: UsbAudio,
+        granulator: GrainCloud,
+        second_granulator: GrainCloud,
+        kit_voices: [KitVoice; KIT_PADS],
+        interpolator: SettingsInterpolator,
+        granular_settings: UserSettings,
+        varispeed: Varispeed,
+        mixer: Mixer,
+        output: OutputStage,
+        crossfade: Crossfade,
+        input_meter: BlockMeter,
+        record_meter: BlockMeter,
+        output_meter: BlockMeter,
+        bouncer: GrainCloud,
+        bounce_job: Option<BounceJob>,
+        events: EventQueue,
+        routing: TriggerRouting,
+        transport: Transport,
+        rotation: BufferRotation,
+        clip_indicator: ClipIndicator,
+        bounce_seconds: u32,
+        scrub: OffsetScrub,
+        scenes: SceneMorph,
+        automation: Automation,
+        soak_generator: SignalGenerator,
+        soak_schedule: SoakSchedule,
+        clock_follower: ClockFollower,
+        loop_quantize: bool,
+        watchdog: Option<Watchdog>,
+        echo: Echo,
+        pre_roll: PreRoll,
+        looper: Looper,
+        pitch_shifter: PitchShifter,
+        echo_sync: bool,
+        delay_sync: bool,
+        note_values: bool,
+        reverb: Reverb,
+        texture: Texture,
+        pulses: PulseScheduler,
+        follower: EnvelopeFollower,
+        metronome: Metronome,
+        storage: Option<Storage>,
+        card: Option<SdCard>,
+        export_format: WavFormat,
+        shift_layer: ShiftLayer,
+        undo_armed: bool,
+        mod_matrix: ModMatrix,
+        slew: SlewLimiter,
+        quantizer: Quantizer,
+        midi_transpose: f32,
+        tap_tempo: TapTempo,
+        change_detector: ChangeDetector,
     }
 
-    static SOURCE_LENGTH: AtomicUsize = AtomicUsize::new(0);
-    static IS_RECORDING: AtomicBool = AtomicBool::new(true);
-    const AUDIO_CALLBACK_INTERVAL: f32 =
-        libdaisy::AUDIO_BLOCK_SIZE as f32 * (1.0 / (libdaisy::AUDIO_SAMPLE_RATE as f32));
-
     #[init]
     fn init(ctx: init::Context) -> (Shared, Local, init::Monotonics) {
+        // the reset flags are only valid until they get cleared
+        if let Some(stalled) = watchdog::take_reset_reason() {
+            rprintln!("Reset by the watchdog, stalled tasks: {}", stalled);
+        }
+
         // initiate system
-        let sitira = Sitira::init(ctx.core, ctx.device);
+        // without the display registered, a failure is reported over RTT and by the seed LED
+        let mut sitira = match Sitira::init(ctx.core, ctx.device) {
+            Ok(sitira) => sitira,
+            Err(error) => panic::halt(error),
+        };
 
-        // create the granulator object
-        let granulator = Granulator::new(libdaisy::AUDIO_SAMPLE_RATE);
+        // create the grain cloud of the active slot
+        let granulator = new_grain_cloud();
 
         // activate timer 4 interrupt
+        #[cfg(not(feature = "headless"))]
         rtic::pend(stm32h7xx_hal::interrupt::TIM4);
 
+        // the soak test measures the duration of every audio callback with the cycle counter, gate
+        // edges get timestamped with it
+        {
+            let mut core = unsafe { cortex_m::Peripherals::steal() };
+            core.DCB.enable_trace();
+            core.DWT.enable_cycle_counter();
+        }
+
+        // without a usable SD card Sitira only records and plays, until the card gets mounted
+        // from the menu
+        let (mut storage, card) = match Storage::mount(sitira.sd_card) {
+            Ok(storage) => (Some(storage), None),
+            Err((error, card)) => {
+                rprintln!("Failed to mount the SD card: {}!", error.description());
+                (None, Some(card))
+            }
+        };
+
+        // holding the button and the encoder at start up enters the self test
+        if selftest::is_requested(&mut sitira.control_rate) {
+            selftest::run(
+                &mut sitira.control_rate,
+                &mut sitira.display,
+                sitira.sdram,
+                storage.is_some(),
+            );
+        }
+
+        // holding the button at start up installs the firmware image on the SD card
+        if let (true, Some(storage)) =
+            (sitira.control_rate.button.is_input_high(), storage.as_mut())
+        {
+            update_firmware(storage, &mut sitira.display);
+        }
+
+        // a mapping on the SD card replaces the one of the panel
+        let control_maps = match storage.as_mut().map(mapping_file::load) {
+            Some(Ok(control_maps)) => {
+                rprintln!("Loaded the control mapping from the SD card!");
+                control_maps
+            }
+            Some(Err(error)) => {
+                rprintln!("Using the panel control mapping: {:?}", error);
+                ControlMaps::new()
+            }
+            None => ControlMaps::new(),
+        };
+
+        // the theme selected last comes back, the display gets repainted with it
+        match storage.as_mut().map(theme_file::load) {
+            Some(Ok(theme)) => THEME.restore(theme),
+            Some(Err(error)) => {
+                rprintln!("Using the default theme: {:?}", error);
+            }
+            None => (),
+        }
+
+        // the envelope selects a window function, a blend of two makes no sense
+        let scenes = match control_maps.panel.get_channel(Parameter::Envelope) {
+            Some(channel) => SceneMorph::new(&[channel]),
+            None => SceneMorph::new(&[]),
+        };
+
+        // the autosaved recording comes back into the first slot and gets played back right away
+        let mut transport_state = TransportState::Recording;
+
+        if let (true, Some(storage)) = (AUTOSAVE_IN_S > 0, storage.as_mut()) {
+            let active = SLOTS.get_active();
+
+            // SAFETY: the tasks which access the slots have not started yet
+            let restored = unsafe { sdram::get_slice_mut(slots::get_start(active), SLOT_LENGTH) }
+                .map(|buffer| autosave::restore(storage, buffer, AUDIO_SAMPLE_RATE as u32));
+
+            match restored {
+                Some(Ok(length)) if length > 0 => {
+                    SLOTS.set_length(active, length);
+                    SOURCE.set_len(length);
+                    IS_RECORDING.store(false, Ordering::Relaxed);
+                    ANALYSIS_REQUESTED.store(true, Ordering::Relaxed);
+                    transport_state = TransportState::Playing;
+
+                    rprintln!(
+                        "Restored {} from the autosave!",
+                        format_time(length).as_str()
+                    );
+                }
+                Some(Err(error)) => {
+                    rprintln!("No autosave restored: {:?}", error);
+                }
+                _ => (),
+            }
+        }
+
+        // started last, so the long initialization does not count against the timeout
+        let watchdog = if WATCHDOG_TIMEOUT_IN_MS > 0 {
+            Some(Watchdog::start(WATCHDOG_TIMEOUT_IN_MS))
+        } else {
+            None
+        };
+
         rprintln!("I am here!");
 
         (
             Shared {
                 audio_buffer: sdram::get_slice(0, 1).unwrap(), // mock slice
-                user_settings: UserSettings {
-                    master_volume: 1.0,
-                    active_grains: 0.1,
-                    offset: 0.5,
-                    grain_size: 0.5,
-                    pitch: 0.5,
-                    delay: 0.0,
-                    velocity: 1.0,
-                    sp_offset: 0.0,
-                    sp_grain_size: 0.0,
-                    sp_pitch: 0.0,
-                    sp_delay: 0.0,
-                    sp_velocity: 0.0,
-                    window_function: WindowFunction::Sine as u8,
-                    window_param: 0.5,
-                    scale: ScaleType::Diatonic as u8,
-                    mode: ModeType::Ionian as u8,
-                },
+                user_settings: initial_user_settings(),
+                engine_settings: EngineSettings::default(),
+                menu: Menu::new(),
+                slices: SliceMarkers::new(),
+                editor: WaveformEditor::new(),
+                curves: CurveSet::new(),
+                calibration: Calibration::new(),
+                kit: Kit::new(&initial_user_settings()),
+                session: Session::new(),
+                lcd: sitira.display,
+                gate_edges: sitira.gate_edges,
             },
             Local {
                 ar: sitira.audio_rate,
                 cr: sitira.control_rate,
                 vr: sitira.visual_rate,
                 sdram: sitira.sdram,
+                midi_input: sitira.midi_input,
+                usb_audio: sitira.usb_audio,
                 granulator,
+                second_granulator: new_grain_cloud(),
+                kit_voices: [
+                    KitVoice::new(new_grain_cloud(), &initial_user_settings(), KIT_TAIL_FRAMES),
+                    KitVoice::new(new_grain_cloud(), &initial_user_settings(), KIT_TAIL_FRAMES),
+                    KitVoice::new(new_grain_cloud(), &initial_user_settings(), KIT_TAIL_FRAMES),
+                    KitVoice::new(new_grain_cloud(), &initial_user_settings(), KIT_TAIL_FRAMES),
+                ],
+                interpolator: SettingsInterpolator::new(
+                    &initial_user_settings(),
+                    BLOCKS_PER_CONTROL_CYCLE,
+                ),
+                granular_settings: initial_user_settings(),
+                varispeed: Varispeed::new(),
+                mixer: Mixer::new(),
+                output: OutputStage::new(
+                    AUDIO_SAMPLE_RATE as f32,
+                    MUTE_RAMP_IN_MS,
+                    OUTPUT_MIN_DB,
+                    OUTPUT_MAX_DB,
+                ),
+                crossfade: Crossfade::new(AUDIO_SAMPLE_RATE as f32, MONITOR_CROSSFADE_IN_MS),
+                input_meter: BlockMeter::new(),
+                record_meter: BlockMeter::new(),
+                output_meter: BlockMeter::new(),
+                bouncer: new_grain_cloud(),
+                bounce_job: None,
+                events: EventQueue::new(),
+                routing: TriggerRouting::new(
+                    [
+                        TriggerAction::NextSlice,
+                        TriggerAction::RotateBuffer,
+                        TriggerAction::None,
+                        TriggerAction::None,
+                    ],
+                    EXPANDER_BUTTON_ACTIONS,
+                ),
+                transport: Transport::new(transport_state),
+                rotation: BufferRotation::new(RotationAmount::Division(ROTATION_DIVISION)),
+                clip_indicator: ClipIndicator::new(),
+                bounce_seconds: DEFAULT_BOUNCE_SECONDS,
+                scrub: OffsetScrub::new(
+                    AUDIO_SAMPLE_RATE as f32,
+                    OFFSET_SCRUB_STEP_IN_MS,
+                    OFFSET_SCRUB_RANGE_IN_S,
+                    OFFSET_PICKUP_THRESHOLD,
+                ),
+                scenes,
+                // SAFETY: same as for the echo region
+                automation: Automation::new(unsafe {
+                    sdram::AUTOMATION_BUFFER.get_slice_mut().unwrap()
+                }),
+                soak_generator: SignalGenerator::new(AUDIO_SAMPLE_RATE as f32),
+                soak_schedule: SoakSchedule::new(),
+                clock_follower: ClockFollower::new(AUDIO_SAMPLE_RATE as f32),
+                loop_quantize: false,
+                watchdog,
+                // SAFETY: the echo region is handed out only here and lies outside of the audio
+                // region
+                echo: Echo::new(unsafe { sdram::ECHO_BUFFER.get_slice_mut().unwrap() }),
+                pre_roll: PreRoll::new(unsafe { sdram::PRE_ROLL_BUFFER.get_slice_mut().unwrap() }),
+                looper: Looper::new(
+                    (LOOPER_CROSSFADE_IN_MS * AUDIO_SAMPLE_RATE as f32 / 1000.0) as usize,
+                ),
+                // SAFETY: same as for the echo region
+                pitch_shifter: PitchShifter::new(
+                    unsafe { sdram::PITCH_SHIFT_BUFFER.get_slice_mut().unwrap() },
+                    PITCH_SHIFT_GRAIN_IN_MS * AUDIO_SAMPLE_RATE / 1000,
+                ),
+                echo_sync: false,
+                delay_sync: false,
+                note_values: false,
+                // SAFETY: same as for the echo region
+                reverb: Reverb::new(
+                    unsafe { sdram::REVERB_BUFFER.get_slice_mut().unwrap() },
+                    AUDIO_SAMPLE_RATE as f32,
+                ),
+                texture: Texture::new(),
+                pulses: PulseScheduler::new(GATE_PULSE_FRAMES),
+                follower: EnvelopeFollower::new(
+                    AUDIO_SAMPLE_RATE as f32,
+                    ENVELOPE_ATTACK_IN_MS,
+                    ENVELOPE_RELEASE_IN_MS,
+                ),
+                metronome: Metronome::new(AUDIO_SAMPLE_RATE as f32, METRONOME_BEATS_PER_BAR),
+                storage,
+                card,
+                export_format: WavFormat::Float32,
+                mod_matrix: control_maps.matrix,
+                slew: SlewLimiter::new(CONTROL_RATE_IN_MS),
+                shift_layer: ShiftLayer::new(control_maps, KNOB_PICKUP_THRESHOLD),
+                undo_armed: false,
+                quantizer: Quantizer::default(),
+                midi_transpose: 0.0,
+                tap_tempo: TapTempo::new(),
+                change_detector: ChangeDetector::new(),
             },
             init::Monotonics(),
         )
@@ -101,186 +456,101 @@ mod app {
 
     // Non-default idle ensures chip doesn't go to sleep which causes issues for
     // probe.rs currently
-    #[idle]
-    fn idle(_ctx: idle::Context) -> ! {
-        loop {
-            cortex_m::asm::nop();
-        }
+    #[idle(local = [storage, card], shared = [slices, session])]
+    fn idle(ctx: idle::Context) -> ! {
+        background::run(
+            ctx.local.storage,
+            ctx.local.card,
+            ctx.shared.slices,
+            ctx.shared.session,
+        )
     }
 
-    // Interrupt handler for audio
-    #[task(binds = DMA1_STR1, local = [ar, sdram, granulator], shared = [user_settings, audio_buffer], priority = 8)]
-    fn audio_handler(mut ctx: audio_handler::Context) {
-        let audio = &mut ctx.local.ar.audio;
-        let mut buffer = ctx.local.ar.buffer;
-        let granulator = ctx.local.granulator;
-        let sdram = ctx.local.sdram;
-
-        audio.get_stereo(&mut buffer);
-
-        // update scheduler
-        granulator.update_scheduler(Duration::from_secs_f32(AUDIO_CALLBACK_INTERVAL));
+    #[task(binds = DMA1_STR1, local = [ar, sdram, granulator, second_granulator, kit_voices, interpolator, granular_settings, varispeed, mixer, output, crossfade, input_meter, record_meter, output_meter, bouncer, bounce_job, soak_generator, clock_follower, texture, echo, reverb, pre_roll, looper, pitch_shifter, pulses, follower, metronome, grain_limit: GrainLimit = GrainLimit::new(GRAIN_LOAD_TARGET, GRAIN_LOAD_CEILING), last_callback_start: u32 = 0, splice_start: usize = 0, was_recording: bool = false, killed: bool = false], shared = [user_settings, engine_settings, audio_buffer, kit], priority = 8)]
+    fn audio_handler(ctx: audio_handler::Context) {
+        audio::process(ctx);
+    }
 
-        let is_recording = IS_RECORDING.load(Ordering::Relaxed);
+    #[task(binds = TIM2, local = [cr, events, routing, transport, rotation, clip_indicator, bounce_seconds, scrub, scenes, automation, soak_schedule, loop_quantize, watchdog, echo_sync, delay_sync, note_values, export_format, shift_layer, undo_armed, mod_matrix, slew, quantizer, midi_transpose, tap_tempo, last_tap: Option<u32> = None, sample_number: u32 = 0, load_quality: ResampleQuality = ResampleQuality::Polyphase, session_queue: Option<Session> = None, change_detector, midi_learn: MidiLearn = MidiLearn::new()], shared = [user_settings, engine_settings, menu, slices, editor, curves, calibration, kit, session], priority = 3)]
+    fn update_handler(ctx: update_handler::Context) {
+        control::update(ctx);
+    }
 
-        // when recording
-        if is_recording {
-            let source_length = SOURCE_LENGTH.load(Ordering::Relaxed);
+    #[task(binds = TIM4, local = [vr, progress_shown: bool = false, shift_shown: bool = false, popup_shown: bool = false, overlay_shown: bool = false, confirmation_shown: bool = false, calibration_shown: CalibrationStage = CalibrationStage::Inactive, readout: (TimeText, TimeText, TimeText) = (TimeText::new(), TimeText::new(), TimeText::new()), cloud: (TimeText, TimeText, TimeText) = (TimeText::new(), TimeText::new(), TimeText::new()), preview_ticks: u32 = 0, columns: [Peak; display::WAVE_COLUMNS] = [Peak::EMPTY; display::WAVE_COLUMNS], waveform_pending: bool = false, editor_shown: bool = false, seed_shown: Option<u32> = None, counts_shown: Option<(usize, usize)> = None, learn_shown: Option<(Parameter, Option<u8>, bool)> = None, card_shown: Option<Result<(), CardError>> = None, card_error_shown: bool = false], shared = [menu, slices, editor, curves, calibration, kit, lcd])]
+    fn display_handler(ctx: display_handler::Context) {
+        display::refresh(ctx);
+    }
 
-            if source_length < sdram::SDRAM_SIZE {
-                // store incomong audio in memory
-                for (index, (right, left)) in buffer.iter().enumerate() {
-                    sdram[source_length + index] = *right;
-                    audio.push_stereo((*right, *left)).unwrap();
-                }
+    #[task(binds = DMA1_STR2, shared = [lcd])]
+    fn display_dma_handler(ctx: display_dma_handler::Context) {
+        ctx.shared.lcd.on_transfer_complete();
+    }
 
-                // update source length by buffer size of one channel
-                SOURCE_LENGTH.fetch_add(buffer.len(), Ordering::Relaxed);
-            } else {
-                // wrap around the SDRAM when overflowing
-                SOURCE_LENGTH.store(0, Ordering::Relaxed);
+    // all gate interrupts share a priority, so they share the producing ends of the gate edge
+    // queues without a lock
 
-                // store incomong audio in memory
-                for (index, (right, left)) in buffer.iter().enumerate() {
-                    sdram[source_length + index] = *right;
-                    audio.push_stereo((*right, *left)).unwrap();
-                }
-                SOURCE_LENGTH.fetch_add(buffer.len(), Ordering::Relaxed);
-            }
-        }
+    #[task(binds = EXTI0, shared = [gate_edges], priority = 4)]
+    fn gate_exti0_handler(ctx: gate_exti0_handler::Context) {
+        gate_edges::on_interrupt(ctx.shared.gate_edges);
+    }
 
-        // when playing
-        if !is_recording {
-            // set audio buffer
-            let source_length = SOURCE_LENGTH.load(Ordering::Relaxed);
-            granulator.set_audio_buffer(&sdram[0..source_length]);
-
-            // update user settings
-            ctx.shared
-                .user_settings
-                .lock(|settings| granulator.update_all_user_settings(settings));
-
-            for _ in buffer {
-                // get next sample
-                let mono_sample = granulator.get_next_sample();
-                audio.push_stereo((mono_sample, mono_sample)).unwrap();
-            }
-        }
+    #[task(binds = EXTI1, shared = [gate_edges], priority = 4)]
+    fn gate_exti1_handler(ctx: gate_exti1_handler::Context) {
+        gate_edges::on_interrupt(ctx.shared.gate_edges);
     }
 
-    #[task(binds = TIM2, local = [cr], shared = [user_settings], priority = 3)]
-    fn update_handler(mut ctx: update_handler::Context) {
-        // clear TIM2 interrupt flag
-        ctx.local.cr.timer2.clear_irq();
-
-        // ----------------------------------
-        // BUTTON, GATE INs AND LEDs
-        // ----------------------------------
-
-        // LEDs
-        let led1 = &mut ctx.local.cr.led1;
-        let led2 = &mut ctx.local.cr.led2;
-        let led3 = &mut ctx.local.cr.led3;
-
-        // binary devices
-        let button = &mut ctx.local.cr.button;
-        let gate1 = &mut ctx.local.cr.gate1;
-        let gate2 = &mut ctx.local.cr.gate2;
-        let gate3 = &mut ctx.local.cr.gate3;
-        let gate4 = &mut ctx.local.cr.gate4;
-
-        // save all binary inputs at the beginning
-        button.save_state();
-        gate1.save_state();
-        gate2.save_state();
-        gate3.save_state();
-        gate4.save_state();
-
-        if gate1.is_saved_state_high() || gate3.is_saved_state_high() {
-            led1.set_high().unwrap();
-        } else {
-            led1.set_low().unwrap();
-        }
+    #[task(binds = EXTI4, shared = [gate_edges], priority = 4)]
+    fn gate_exti4_handler(ctx: gate_exti4_handler::Context) {
+        gate_edges::on_interrupt(ctx.shared.gate_edges);
+    }
 
-        if gate2.is_saved_state_high() || gate4.is_saved_state_high() {
-            led2.set_high().unwrap();
-        } else {
-            led2.set_low().unwrap();
-        }
+    #[task(binds = EXTI9_5, shared = [gate_edges], priority = 4)]
+    fn gate_exti9_5_handler(ctx: gate_exti9_5_handler::Context) {
+        gate_edges::on_interrupt(ctx.shared.gate_edges);
+    }
 
-        if button.is_triggered() {
-            IS_RECORDING.fetch_xor(true, Ordering::Relaxed); // invert boolean
-        }
+    #[task(binds = USART1, local = [midi_input], priority = 4)]
+    fn midi_handler(ctx: midi_handler::Context) {
+        ctx.local.midi_input.on_interrupt();
+    }
 
-        match IS_RECORDING.load(Ordering::Relaxed) {
-            true => {
-                if button.is_triggered() {
-                    rprintln!("Started recording incoming audio!");
-                    SOURCE_LENGTH.store(0, Ordering::Relaxed);
-                }
+    #[task(binds = OTG_FS, local = [usb_audio], priority = 4)]
+    fn usb_handler(ctx: usb_handler::Context) {
+        ctx.local.usb_audio.on_interrupt();
+    }
 
-                led3.set_high().unwrap();
+    /// Installs a verified firmware image from the SD card and resets, returns if there is none.
+    fn update_firmware(storage: &mut Storage, display: &mut Display) {
+        // SAFETY: the slots are not in use yet and the first one gets restored afterwards
+        let buffer = unsafe {
+            sdram::get_slice_mut::<u8>(
+                slots::get_start(SLOTS.get_active()) * core::mem::size_of::<f32>(),
+                update::MAX_FILE_LENGTH,
+            )
+        };
+
+        match buffer.map(|buffer| update::stage(storage, buffer)) {
+            Some(Ok(image)) if update::is_installed(image) => {
+                rprintln!("Firmware is already up to date!")
             }
+            Some(Ok(image)) => {
+                rprintln!("Installing {}!", update::FIRMWARE_NAME);
+                display.show_status("Updating", &["Installing SITIRA.BIN", "Do not power off!"]);
 
-            false => {
-                if button.is_triggered() {
-                    rprintln!("Stopped recording incoming audio!");
-                    rprintln!(
-                        "Audio buffer gets set with length of {} samples!",
-                        SOURCE_LENGTH.load(Ordering::Relaxed)
-                    );
-                }
-
-                led3.set_low().unwrap();
+                // SAFETY: the image has been verified
+                unsafe { update::install(image) }
             }
-        }
-
-        // can probably be spilt into two different task, since reading the ADCs needs more fine tuning
-
-        // ----------------------------------
-        // USER SETTINGS
-        // ----------------------------------
-
-        let adc_values = &mut ctx.local.cr.muxed_parameters;
-        let adc2 = &mut ctx.local.cr.adc2;
-        let master_volume = &mut ctx.local.cr.master_volume;
-
-        // read from ADC2
-        for i in 0..16 {
-            adc_values.read_value(i);
-        }
+            Some(Err(update::UpdateError::Storage(error))) => {
+                rprintln!("No firmware update: {:?}", error)
+            }
+            Some(Err(error)) => {
+                rprintln!("Firmware update failed: {:?}", error);
+                display.show_status("Update failed", &[error.description()]);
 
-        // read from ADC1
-        if let Ok(data) = adc2.read(master_volume.get_pin()) {
-            master_volume.update(data);
+                // gives some time to read the message
+                cortex_m::asm::delay(2 * CPU_FREQUENCY_IN_HZ);
+            }
+            None => (),
         }
-
-        // update user settings
-        ctx.shared.user_settings.lock(|settings| {
-            settings.master_volume = master_volume.get_value() * 0.5;
-            settings.active_grains = adc_values.get_value(AdcMuxInputs::ActiveGrains as usize);
-            settings.offset = adc_values.get_value(AdcMuxInputs::Offset as usize);
-            settings.grain_size = adc_values.get_value(AdcMuxInputs::GrainSize as usize);
-            settings.pitch = adc_values.get_value(AdcMuxInputs::Pitch as usize);
-            settings.delay = adc_values.get_value(AdcMuxInputs::Delay as usize);
-            settings.velocity = adc_values.get_value(AdcMuxInputs::Velocity as usize);
-            settings.sp_offset = adc_values.get_value(AdcMuxInputs::OffsetSpread as usize);
-            settings.sp_grain_size = adc_values.get_value(AdcMuxInputs::GrainSizeSpread as usize);
-            settings.sp_pitch = adc_values.get_value(AdcMuxInputs::PitchSpread as usize);
-            settings.sp_velocity = adc_values.get_value(AdcMuxInputs::VelocitySpread as usize);
-            settings.sp_delay = adc_values.get_value(AdcMuxInputs::DelaySpread as usize);
-            settings.window_function =
-                (adc_values.get_value(AdcMuxInputs::Envelope as usize) * 6.0) as u8;
-            // settings.window_param = adc_values.get_value(AdcMuxInputs::WaveSelect as usize);
-        });
-    }
-
-    #[task(binds = TIM4, local = [vr], shared = [])]
-    fn display_handler(ctx: display_handler::Context) {
-        // clear TIM2 interrupt flag
-        ctx.local.vr.timer4.clear_irq();
-
-        // activate timer 4 interrupt
-        rtic::pend(stm32h7xx_hal::interrupt::TIM4);
     }
 }
@@ -0,0 +1,38 @@
+use sitira_core::mapping::{ControlMaps, MappingError, MAPPING_NAME, MAX_FILE_LENGTH};
+
+use crate::storage::{self, Storage};
+
+#[derive(Debug)]
+pub enum LoadError {
+    Storage(storage::Error),
+    Mapping(MappingError),
+}
+
+impl From<storage::Error> for LoadError {
+    fn from(error: storage::Error) -> Self {
+        LoadError::Storage(error)
+    }
+}
+
+impl From<MappingError> for LoadError {
+    fn from(error: MappingError) -> Self {
+        LoadError::Mapping(error)
+    }
+}
+
+/// Reads the mapping file from the SD card.
+pub fn load(storage: &mut Storage) -> Result<ControlMaps, LoadError> {
+    let mut file = storage.open(MAPPING_NAME)?;
+    let mut buffer = [0; MAX_FILE_LENGTH];
+    let length = file.length() as usize;
+
+    if length > MAX_FILE_LENGTH {
+        storage.close(file)?;
+        return Err(MappingError::TooLong.into());
+    }
+
+    let read = storage.read(&mut file, &mut buffer[..length])?;
+    storage.close(file)?;
+
+    Ok(ControlMaps::from_bytes(&buffer[..read])?)
+}
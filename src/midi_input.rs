@@ -0,0 +1,70 @@
+use sitira_core::event::{EventConsumer, EventProducer, TimedEvent, EVENT_QUEUE_SIZE};
+use sitira_core::spsc::SpscQueue;
+
+use crate::error::SitiraError;
+
+#[cfg(feature = "midi")]
+use cortex_m::peripheral::DWT;
+#[cfg(feature = "midi")]
+use sitira_core::midi::MidiParser;
+#[cfg(feature = "midi")]
+use stm32h7xx_hal::{hal::serial::Read, serial, stm32};
+
+#[cfg(all(feature = "midi", feature = "gate-outputs"))]
+compile_error!("Only one use of pin 14 can be selected, either `midi` or `gate-outputs`");
+
+/// Notes and control changes from the MIDI interrupt to the control task.
+static MIDI_EVENTS: SpscQueue<TimedEvent, EVENT_QUEUE_SIZE> = SpscQueue::new();
+
+/// Splits the MIDI event queue, returns the producing end for the interrupt and the events for
+/// the control task. Can only be called once.
+pub fn split() -> Result<(EventProducer, EventConsumer), SitiraError> {
+    MIDI_EVENTS.split().ok_or(SitiraError::Queue)
+}
+
+/// Receiving end of the UART on pin 14, which turns the incoming MIDI into events.
+#[cfg(feature = "midi")]
+pub struct MidiInput {
+    rx: serial::Rx<stm32::USART1>,
+    parser: MidiParser,
+    events: EventProducer,
+}
+
+#[cfg(feature = "midi")]
+impl MidiInput {
+    pub fn new(rx: serial::Rx<stm32::USART1>, channel: Option<u8>, events: EventProducer) -> Self {
+        MidiInput {
+            rx,
+            parser: MidiParser::new(channel),
+            events,
+        }
+    }
+
+    /// Parses all received bytes and queues the events of the completed messages, stamped with
+    /// the time they arrived at.
+    pub fn on_interrupt(&mut self) {
+        let timestamp = DWT::cycle_count();
+
+        loop {
+            match self.rx.read() {
+                Ok(byte) => {
+                    if let Some(event) = self.parser.parse(byte) {
+                        self.events.push(TimedEvent { event, timestamp });
+                    }
+                }
+                Err(nb::Error::WouldBlock) => break,
+                // an overrun lost bytes, the parser picks up again at the next status byte
+                Err(nb::Error::Other(_)) => (),
+            }
+        }
+    }
+}
+
+/// Stands in for the MIDI input while pin 14 drives LED 2, its interrupt is never enabled.
+#[cfg(not(feature = "midi"))]
+pub struct MidiInput;
+
+#[cfg(not(feature = "midi"))]
+impl MidiInput {
+    pub fn on_interrupt(&mut self) {}
+}
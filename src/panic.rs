@@ -0,0 +1,160 @@
+use core::fmt::Write;
+use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+
+use cortex_m_rt::{exception, ExceptionFrame};
+use stm32h7xx_hal::pac;
+
+use crate::config::CPU_FREQUENCY_IN_HZ;
+use crate::error::SitiraError;
+use crate::rprintln;
+use crate::sitira::Display;
+
+/// Longest report which is kept, everything behind gets cut off
+const REPORT_LENGTH: usize = 512;
+/// Characters of the 6x10 font which fit into one line of the display
+const LINE_LENGTH: usize = 52;
+/// Lines which fit below the title
+const MAX_LINES: usize = 16;
+
+/// The seed LED sits on PC7
+const SEED_LED_PIN: u32 = 7;
+const BLINK_ON_CYCLES: u32 = CPU_FREQUENCY_IN_HZ / 8;
+const BLINK_OFF_CYCLES: u32 = CPU_FREQUENCY_IN_HZ / 4;
+const BLINK_PAUSE_CYCLES: u32 = CPU_FREQUENCY_IN_HZ;
+/// Flashes between two pauses, so the faults can be told apart without a display
+const PANIC_BLINKS: u32 = 2;
+const HARD_FAULT_BLINKS: u32 = 3;
+const INIT_FAULT_BLINKS: u32 = 4;
+
+/// Display the report gets drawn on, null until the display task has registered it
+static DISPLAY: AtomicPtr<Display> = AtomicPtr::new(core::ptr::null_mut());
+/// Set once the first fault is being reported
+static REPORTING: AtomicBool = AtomicBool::new(false);
+
+/// Makes the display available to the fault handlers. Needs to be called with the display at
+/// its final location, i.e. from a task and not from `init`.
+pub fn register_display(display: &mut Display) {
+    DISPLAY.store(display, Ordering::Relaxed);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    cortex_m::interrupt::disable();
+
+    let mut report = Report::new();
+    // a report which does not fit gets cut off
+    let _ = write!(report, "{}", info);
+
+    report_fault("PANIC", report.as_str(), PANIC_BLINKS)
+}
+
+#[exception]
+fn HardFault(frame: &ExceptionFrame) -> ! {
+    cortex_m::interrupt::disable();
+
+    let mut report = Report::new();
+    let _ = write!(
+        report,
+        "PC 0x{:08x} LR 0x{:08x} XPSR 0x{:08x}",
+        frame.pc(),
+        frame.lr(),
+        frame.xpsr()
+    );
+
+    report_fault("HARD FAULT", report.as_str(), HARD_FAULT_BLINKS)
+}
+
+/// Reports hardware which could not be set up and stops there. Only shows up on the display if
+/// it has been registered.
+pub fn halt(error: SitiraError) -> ! {
+    cortex_m::interrupt::disable();
+
+    let mut report = Report::new();
+    let _ = write!(report, "{:?}: {}", error, error.description());
+
+    report_fault("INIT FAILED", report.as_str(), INIT_FAULT_BLINKS)
+}
+
+fn report_fault(title: &str, details: &str, blinks: u32) -> ! {
+    rprintln!("{}: {}", title, details);
+
+    // a fault while reporting would otherwise end in an endless chain of reports
+    if !REPORTING.swap(true, Ordering::Relaxed) {
+        show_on_display(title, details);
+    }
+
+    blink(blinks)
+}
+
+fn show_on_display(title: &str, details: &str) {
+    let display = DISPLAY.load(Ordering::Relaxed);
+
+    if display.is_null() {
+        return;
+    }
+
+    let mut lines = [""; MAX_LINES];
+    let mut count = 0;
+
+    for (line, chunk) in lines.iter_mut().zip(details.as_bytes().chunks(LINE_LENGTH)) {
+        // chunks may split a multi byte character, which is not worth any effort here
+        *line = core::str::from_utf8(chunk).unwrap_or("?");
+        count += 1;
+    }
+
+    // SAFETY: interrupts are disabled, so whoever used the display before never continues
+    unsafe { (*display).show_fault(title, &lines[..count]) };
+}
+
+/// Blinks the seed LED in groups of `count` forever.
+fn blink(count: u32) -> ! {
+    let gpioc = unsafe { &*pac::GPIOC::ptr() };
+
+    loop {
+        for _ in 0..count {
+            gpioc.bsrr.write(|w| unsafe { w.bits(1 << SEED_LED_PIN) });
+            cortex_m::asm::delay(BLINK_ON_CYCLES);
+            gpioc
+                .bsrr
+                .write(|w| unsafe { w.bits(1 << (SEED_LED_PIN + 16)) });
+            cortex_m::asm::delay(BLINK_OFF_CYCLES);
+        }
+
+        cortex_m::asm::delay(BLINK_PAUSE_CYCLES);
+    }
+}
+
+/// Fault report which lives on the stack.
+struct Report {
+    bytes: [u8; REPORT_LENGTH],
+    len: usize,
+}
+
+impl Report {
+    fn new() -> Self {
+        Report {
+            bytes: [0; REPORT_LENGTH],
+            len: 0,
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        // only whole `str`s get written
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+impl Write for Report {
+    fn write_str(&mut self, text: &str) -> core::fmt::Result {
+        let end = self.len + text.len();
+
+        if end > REPORT_LENGTH {
+            return Err(core::fmt::Error);
+        }
+
+        self.bytes[self.len..end].copy_from_slice(text.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
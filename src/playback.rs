@@ -0,0 +1,184 @@
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use sitira_core::{
+    clock,
+    erase::ERASE,
+    ring::AudioRing,
+    rotation::BufferRotation,
+    slices::SliceMarkers,
+    tempo,
+    timecode::{self, TimeFormat, TimeText},
+    transport::TransportChange,
+};
+
+use crate::{
+    autosave::AUTOSAVE,
+    config::{AUDIO_SAMPLE_RATE, AUTOSAVE_IN_S},
+    export::WavFormat,
+    rprintln,
+    sample_file::STREAM,
+    slots::{self, SLOTS, SLOT_COUNT, SLOT_LENGTH},
+    waveform_cache::WAVEFORMS,
+};
+
+/// Take of the active slot
+pub static SOURCE: AudioRing = AudioRing::new();
+pub static IS_RECORDING: AtomicBool = AtomicBool::new(true);
+pub static ANALYSIS_REQUESTED: AtomicBool = AtomicBool::new(false);
+/// Playback offset in samples, as set by the controls
+pub static OFFSET_POSITION: AtomicUsize = AtomicUsize::new(0);
+/// Set if the granulator keeps playing from the slot while it is being recorded into
+pub static LIVE_GRANULATION: AtomicBool = AtomicBool::new(false);
+/// Set if a click is mixed to the output while recording
+pub static METRONOME: AtomicBool = AtomicBool::new(false);
+/// Set if a take starts with the input of the moments before it
+pub static PRE_ROLL: AtomicBool = AtomicBool::new(false);
+/// Set if the looper plays the slot instead of the granulator
+pub static LOOPER: AtomicBool = AtomicBool::new(false);
+/// Set if the output plays the input shifted in pitch instead of the granulator
+pub static PITCH_SHIFT: AtomicBool = AtomicBool::new(false);
+/// Set while a recording adds to the loop of the looper instead of replacing it
+pub static OVERDUB: AtomicBool = AtomicBool::new(false);
+
+/// Starts recording a new take into the active slot, or an overdub while the looper plays one.
+pub fn begin_recording() {
+    let overdub = LOOPER.load(Ordering::Relaxed) && !SOURCE.is_empty();
+
+    if !overdub {
+        SOURCE.clear();
+        SLOTS.begin_take(SLOTS.get_active());
+    }
+
+    OVERDUB.store(overdub, Ordering::Relaxed);
+    IS_RECORDING.store(true, Ordering::Relaxed);
+}
+
+/// Applies a transport state change. A synced change has already been executed by the audio
+/// task, so only the bookkeeping is left.
+pub fn apply_transport_change(
+    change: TransportChange,
+    synced: bool,
+    quantize: bool,
+    rotation: &mut BufferRotation,
+    slices: &mut impl rtic::Mutex<T = SliceMarkers>,
+) {
+    match change {
+        TransportChange::StartedRecording => {
+            rprintln!("Started recording incoming audio!");
+
+            if !synced {
+                begin_recording();
+            }
+
+            rotation.reset();
+            slices.lock(|slices| slices.clear(0));
+        }
+        TransportChange::StoppedRecording => {
+            if !synced {
+                IS_RECORDING.store(false, Ordering::Relaxed);
+            }
+
+            let active = SLOTS.get_active();
+            let recorded = SOURCE.len();
+            let overdub = OVERDUB.swap(false, Ordering::Relaxed);
+
+            // an overdub keeps the length of the loop and only changes what it holds
+            if overdub {
+                WAVEFORMS.invalidate(SLOTS.get_buffer(active), 0);
+            }
+
+            // round to whole beats, so the loop stays in time with the clock or tapped tempo
+            if let (true, Some(period)) = (quantize && !overdub, tempo::get_period()) {
+                let length = clock::quantize_length(recorded, period, SLOT_LENGTH);
+
+                if length != recorded {
+                    unsafe { slots::adjust_loop(active, recorded, length) };
+                    SOURCE.set_len(length);
+                }
+            }
+
+            SLOTS.set_length(active, SOURCE.len());
+            ANALYSIS_REQUESTED.store(true, Ordering::Relaxed);
+            request_autosave(active, SOURCE.len());
+            rprintln!("Stopped recording incoming audio!");
+            rprintln!(
+                "Audio buffer gets set with a length of {}!",
+                format_time(SOURCE.len()).as_str()
+            );
+        }
+        // the press turned into a double click or hold, the slot gets back what it held. An
+        // overdub has already been mixed into the loop and stays.
+        TransportChange::DiscardedTake => {
+            IS_RECORDING.store(false, Ordering::Relaxed);
+
+            if !OVERDUB.swap(false, Ordering::Relaxed) {
+                undo_last_take();
+            }
+        }
+    }
+}
+
+/// Discards the last take of any slot.
+pub fn undo_last_take() {
+    if let Some((slot, length)) = SLOTS.undo() {
+        if slot == SLOTS.get_active() {
+            SOURCE.set_len(length);
+            ANALYSIS_REQUESTED.store(true, Ordering::Relaxed);
+            request_autosave(slot, length);
+        }
+
+        rprintln!("Undid the last take of slot {}!", slot);
+    }
+}
+
+/// Makes the slot `steps` further the active one. Must not be called while recording.
+pub fn switch_slot(steps: i32) {
+    let slot = (SLOTS.get_active() as i32 + steps).rem_euclid(SLOT_COUNT as i32) as usize;
+
+    SLOTS.set_active(slot);
+    SOURCE.set_len(SLOTS.get_length(slot));
+    ANALYSIS_REQUESTED.store(true, Ordering::Relaxed);
+
+    rprintln!("Switched to slot {}!", slot);
+}
+
+/// Empties the active slot once the erase got confirmed, its buffer is zeroed by the idle
+/// task. Must not be called while recording.
+pub fn erase_active_slot() {
+    let slot = SLOTS.get_active();
+
+    // playback stops before the buffer gets zeroed
+    SOURCE.clear();
+    SLOTS.set_length(slot, 0);
+    ANALYSIS_REQUESTED.store(true, Ordering::Relaxed);
+
+    if ERASE.confirm(SLOTS.get_buffer(slot), SLOT_LENGTH) {
+        rprintln!("Erasing slot {}!", slot);
+    }
+}
+
+/// Returns the samples the offset scans, which is the whole file while streaming and not just
+/// the part of it in memory.
+pub fn get_playback_length() -> usize {
+    if STREAM.is_streaming() {
+        STREAM.len()
+    } else {
+        SOURCE.len()
+    }
+}
+
+/// Requests saving the first seconds of a finished take.
+pub fn request_autosave(slot: usize, length: usize) {
+    if AUTOSAVE_IN_S == 0 || length == 0 {
+        return;
+    }
+
+    let length = length.min(AUTOSAVE_IN_S as usize * AUDIO_SAMPLE_RATE as usize);
+
+    AUTOSAVE.request(slot, length, WavFormat::Float32);
+}
+
+/// Formats a position in samples in the format used throughout the interface.
+pub fn format_time(samples: usize) -> TimeText {
+    timecode::format_position(samples, AUDIO_SAMPLE_RATE as u32, TimeFormat::Seconds)
+}
@@ -0,0 +1,26 @@
+use stm32h7xx_hal::hal::PwmPin;
+
+/// Control voltage output on a PWM channel, smoothed into a voltage by an external RC filter.
+pub struct PwmCv<P> {
+    channel: P,
+}
+
+impl<P> PwmCv<P>
+where
+    P: PwmPin<Duty = u16>,
+{
+    pub fn new(mut channel: P) -> Self {
+        channel.set_duty(0);
+        channel.enable();
+
+        PwmCv { channel }
+    }
+
+    /// Sets the output between `0.0` (ground) and `1.0` (the pin voltage).
+    pub fn set(&mut self, value: f32) {
+        let max_duty = self.channel.get_max_duty();
+
+        self.channel
+            .set_duty((value.clamp(0.0, 1.0) * max_duty as f32) as u16);
+    }
+}
@@ -1,22 +1,33 @@
-use libdaisy::hid::Led;
+use micromath::F32Ext;
 use LEDConfig::*;
 use RGBColors::*;
 
-use core::fmt::Debug;
-use stm32h7xx_hal::hal::digital::v2::OutputPin;
+use stm32h7xx_hal::hal::PwmPin;
+
+/// Exponent of the perceptual brightness curve
+const GAMMA: f32 = 2.2;
 
 pub enum LEDConfig {
     ActiveLow,
     ActiveHigh,
 }
 
+/// Driver for an RGB LED on three hardware PWM channels.
+///
+/// Colors and brightness are set in perceived (linear) values between `0.0` and `1.0`, which get
+/// gamma corrected before being written to the timer compare registers. Since the timers do the
+/// modulation, no periodic update call is needed.
 pub struct RGBLed<R, G, B> {
-    red: Led<R>,
-    green: Led<G>,
-    blue: Led<B>,
+    red: R,
+    green: G,
+    blue: B,
+    invert: bool,
+    brightness: f32,
+    rgb: (f32, f32, f32),
     pub color: RGBColors,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub enum RGBColors {
     Black,
     Blue,
@@ -29,96 +40,81 @@ pub enum RGBColors {
     Complex,
 }
 
+/// What the status LED is currently indicating.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Recording,
+    Playing,
+    /// Flashing, `true` is the lit phase
+    Clipping(bool),
+}
+
 impl<R, G, B> RGBLed<R, G, B>
 where
-    R: OutputPin,
-    <R as OutputPin>::Error: Debug,
-    G: OutputPin,
-    <G as OutputPin>::Error: Debug,
-    B: OutputPin,
-    <B as OutputPin>::Error: Debug,
+    R: PwmPin<Duty = u16>,
+    G: PwmPin<Duty = u16>,
+    B: PwmPin<Duty = u16>,
 {
-    pub fn new(red: R, green: G, blue: B, config: LEDConfig, resolution: u32) -> Self {
-        let invert;
+    pub fn new(mut red: R, mut green: G, mut blue: B, config: LEDConfig) -> Self {
+        let invert = match config {
+            ActiveHigh => false,
+            ActiveLow => true,
+        };
 
-        match config {
-            ActiveHigh => invert = false,
-            ActiveLow => invert = true,
-        }
+        red.enable();
+        green.enable();
+        blue.enable();
 
-        RGBLed {
-            red: Led::new(red, invert, resolution),
-            green: Led::new(green, invert, resolution),
-            blue: Led::new(blue, invert, resolution),
+        let mut led = RGBLed {
+            red,
+            green,
+            blue,
+            invert,
+            brightness: 1.0,
+            rgb: (0.0, 0.0, 0.0),
             color: Black,
-        }
-    }
+        };
 
-    pub fn update(&mut self) {
-        self.red.update();
-        self.green.update();
-        self.blue.update();
+        led.write();
+        led
     }
 
-    pub fn set_simple_color(&mut self, color: RGBColors) {
-        let mut rgb = 0b000;
-        match color {
-            Black => self.color = Black,
-            Blue => {
-                rgb = 0b001;
-                self.color = Blue;
-            }
-            Green => {
-                rgb = 0b010;
-                self.color = Green;
-            }
-            Cyan => {
-                rgb = 0b011;
-                self.color = Cyan;
-            }
-            Red => {
-                rgb = 0b100;
-                self.color = Red;
-            }
-            Magenta => {
-                rgb = 0b101;
-                self.color = Magenta;
-            }
-            Yellow => {
-                rgb = 0b110;
-                self.color = Yellow;
-            }
-            White => {
-                rgb = 0b111;
-                self.color = White;
-            }
-            Complex => {}
-        }
+    /// Sets the overall brightness which scales every color.
+    pub fn set_brightness(&mut self, brightness: f32) {
+        self.brightness = brightness.clamp(0.0, 1.0);
+        self.write();
+    }
 
-        match (rgb & 0b100) >> 2 {
-            0 => self.red.set_brightness(0.0),
-            1 => self.red.set_brightness(1.0),
-            _ => {}
-        }
+    pub fn get_brightness(&self) -> f32 {
+        self.brightness
+    }
 
-        match (rgb & 0b010) >> 1 {
-            0 => self.green.set_brightness(0.0),
-            1 => self.green.set_brightness(1.0),
-            _ => {}
-        }
+    pub fn set_simple_color(&mut self, color: RGBColors) {
+        let rgb: u8 = match color {
+            Black => 0b000,
+            Blue => 0b001,
+            Green => 0b010,
+            Cyan => 0b011,
+            Red => 0b100,
+            Magenta => 0b101,
+            Yellow => 0b110,
+            White => 0b111,
+            Complex => return,
+        };
 
-        match (rgb & 0b001) >> 0 {
-            0 => self.blue.set_brightness(0.0),
-            1 => self.blue.set_brightness(1.0),
-            _ => {}
-        }
+        self.rgb = (
+            ((rgb & 0b100) >> 2) as f32,
+            ((rgb & 0b010) >> 1) as f32,
+            (rgb & 0b001) as f32,
+        );
+        self.color = color;
+        self.write();
     }
 
     pub fn set_color(&mut self, r: f32, g: f32, b: f32) {
-        self.red.set_brightness(r);
-        self.green.set_brightness(g);
-        self.blue.set_brightness(b);
+        self.rgb = (r.clamp(0.0, 1.0), g.clamp(0.0, 1.0), b.clamp(0.0, 1.0));
         self.color = Complex;
+        self.write();
     }
 
     pub fn cycle_color(&mut self) {
@@ -133,4 +129,65 @@ where
             _ => self.set_simple_color(White),
         }
     }
+
+    /// Maps a device status to a color:
+    /// - recording is red
+    /// - playing is green
+    /// - clipping flashes yellow
+    pub fn show_status(&mut self, status: Status) {
+        let color = match status {
+            Status::Recording => Red,
+            Status::Playing => Green,
+            Status::Clipping(true) => Yellow,
+            Status::Clipping(false) => Black,
+        };
+
+        if color != self.color {
+            self.set_simple_color(color);
+        }
+    }
+
+    fn write(&mut self) {
+        let (r, g, b) = self.rgb;
+
+        let red_duty = self.duty(r, self.red.get_max_duty());
+        let green_duty = self.duty(g, self.green.get_max_duty());
+        let blue_duty = self.duty(b, self.blue.get_max_duty());
+
+        self.red.set_duty(red_duty);
+        self.green.set_duty(green_duty);
+        self.blue.set_duty(blue_duty);
+    }
+
+    fn duty(&self, value: f32, max_duty: u16) -> u16 {
+        let corrected = (value * self.brightness).powf(GAMMA);
+        let duty = (corrected * max_duty as f32) as u16;
+
+        if self.invert {
+            max_duty - duty
+        } else {
+            duty
+        }
+    }
+}
+
+/// Placeholder for a color channel which is not wired to a PWM capable pin.
+pub struct NoChannel;
+
+impl PwmPin for NoChannel {
+    type Duty = u16;
+
+    fn disable(&mut self) {}
+
+    fn enable(&mut self) {}
+
+    fn get_duty(&self) -> Self::Duty {
+        0
+    }
+
+    fn get_max_duty(&self) -> Self::Duty {
+        u16::MAX
+    }
+
+    fn set_duty(&mut self, _duty: Self::Duty) {}
 }
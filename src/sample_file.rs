@@ -0,0 +1,228 @@
+use core::ops::Range;
+
+use embedded_sdmmc::File;
+
+use sitira_core::resample::{ResampleQuality, Resampler};
+use sitira_core::stream::{PageRequest, PageTable, STREAM_PAGES};
+
+use crate::export::{self, WavFormat, EXPORT_SAMPLES_PER_STEP};
+use crate::sdram;
+use crate::slots::{SLOTS, SLOT_LENGTH};
+use crate::storage::{Error, Storage};
+use crate::waveform_cache::WAVEFORMS;
+
+/// Samples of one page of the stream window, which spans a whole slot
+pub const STREAM_PAGE_LENGTH: usize = SLOT_LENGTH / STREAM_PAGES;
+/// Samples files are numbered upwards, `SAMPLE00.WAV` to `SAMPLE99.WAV`
+pub const MAX_SAMPLE_FILES: u32 = 100;
+/// Samples read per step, keeps the idle task responsive
+const LOAD_SAMPLES_PER_STEP: usize = EXPORT_SAMPLES_PER_STEP;
+
+/// Window of the slot which is streamed into
+pub static STREAM: PageTable = PageTable::new(STREAM_PAGE_LENGTH);
+
+/// Returns the name of the sample file with the given number.
+pub fn file_name(number: u32) -> [u8; 12] {
+    let mut name = *b"SAMPLE00.WAV";
+
+    name[6] = b'0' + (number / 10 % 10) as u8;
+    name[7] = b'0' + (number % 10) as u8;
+
+    name
+}
+
+/// State of the sample file which is currently loaded or streamed by the idle task.
+///
+/// The pages of the file are read in the order `STREAM` asks for them, a few samples per step.
+/// Files at another rate than the engine get converted on the way, the pages then hold samples
+/// at the engine rate. The SDMMC reads block, so they are only ever done by the idle task.
+pub struct StreamJob {
+    file: File,
+    name: [u8; 12],
+    format: WavFormat,
+    rate: u32,
+    /// Bytes in front of the samples of the file
+    data_offset: usize,
+    /// Samples of the file, before any conversion
+    input_length: usize,
+    /// Next sample of the file a read continues at
+    read_position: usize,
+    /// Converts the samples to the engine rate, `None` if the file has that rate already
+    resampler: Option<Resampler>,
+    pub slot: usize,
+    /// SDRAM buffer of the slot, the stream ends when the slot gets mapped to another one
+    pub buffer: usize,
+    /// Page being read and the samples of it which have been written
+    current: Option<(PageRequest, usize)>,
+}
+
+impl StreamJob {
+    /// Opens the sample file with `number` and starts streaming it into `slot`, converted to
+    /// `sample_rate` with `quality`. Returns the job and the samples which fit into the slot.
+    ///
+    /// The slot is moved onto the spare buffer like for a new take, so an undo brings back what
+    /// it held before.
+    pub fn open(
+        storage: &mut Storage,
+        number: u32,
+        slot: usize,
+        sample_rate: u32,
+        quality: ResampleQuality,
+    ) -> Result<(Self, usize), Error> {
+        let name = file_name(number);
+        let mut file = storage.open(as_str(&name))?;
+
+        let parsed = match export::read_wav_header(storage, &mut file) {
+            Ok(info) if info.samples == 0 => Err(Error::FormatError("No samples in the WAV file")),
+            Ok(info) if info.sample_rate == sample_rate => Ok((info, None)),
+            Ok(info) => Resampler::new(info.sample_rate, sample_rate, quality)
+                .map(|resampler| (info, Some(resampler)))
+                .ok_or(Error::FormatError("Unsupported sample rate")),
+            Err(error) => Err(error),
+        };
+
+        let (info, resampler) = match parsed {
+            Ok(parsed) => parsed,
+            Err(error) => {
+                storage.close(file)?;
+                return Err(error);
+            }
+        };
+        let input_length = info.samples;
+
+        let length = resampler.as_ref().map_or(input_length, |resampler| {
+            resampler.get_output_length(input_length)
+        });
+        let fitting = length.min(STREAM.get_window_length());
+
+        SLOTS.begin_take(slot);
+        SLOTS.set_length(slot, fitting);
+        SLOTS.set_sample(slot, number);
+        STREAM.start(length);
+
+        let job = StreamJob {
+            file,
+            name,
+            format: info.format,
+            rate: info.sample_rate,
+            data_offset: info.data_offset,
+            input_length,
+            read_position: 0,
+            resampler,
+            slot,
+            buffer: SLOTS.get_buffer(slot),
+            current: None,
+        };
+
+        Ok((job, fitting))
+    }
+
+    pub fn get_name(&self) -> &str {
+        as_str(&self.name)
+    }
+
+    /// Returns the sample rate of the file.
+    pub fn get_rate(&self) -> u32 {
+        self.rate
+    }
+
+    /// Returns how far the window has been filled the first time in percent.
+    pub fn get_progress(&self) -> u32 {
+        STREAM.get_progress(self.current.map_or(0, |(_, written)| written))
+    }
+
+    /// Reads the next samples of the page `STREAM` needs next, if there is any. Returns `false` if
+    /// all pages around the read position are loaded.
+    pub fn process(&mut self, storage: &mut Storage) -> Result<bool, Error> {
+        let (request, written) = match self.current {
+            Some(current) => current,
+            None => match STREAM.next_request() {
+                Some(request) => (request, 0),
+                None => return Ok(false),
+            },
+        };
+
+        let chunk = match self.resampler.as_ref() {
+            Some(resampler) => resampler.get_output_chunk(LOAD_SAMPLES_PER_STEP),
+            None => LOAD_SAMPLES_PER_STEP,
+        };
+        let chunk = chunk.min(STREAM_PAGE_LENGTH - written);
+        let output = request.page * STREAM_PAGE_LENGTH + written;
+        let start = self.buffer * SLOT_LENGTH + request.index * STREAM_PAGE_LENGTH + written;
+
+        // SAFETY: the page is marked as empty, so no grains start in it until it is complete
+        let samples = unsafe { sdram::get_slice_mut::<f32>(start, chunk) }
+            .ok_or(Error::FormatError("Page outside of the SDRAM"))?;
+
+        // samples of the file the chunk is made of
+        let input = match self.resampler.as_ref() {
+            Some(resampler) => resampler.get_input_range(output..output + chunk),
+            None => output..output + chunk,
+        };
+        let input = input.start.min(self.input_length)..input.end.min(self.input_length);
+
+        let mut source = [0.0; LOAD_SAMPLES_PER_STEP];
+        let read = self.read(storage, input.clone(), &mut source)?;
+
+        let valid = match self.resampler.as_ref() {
+            Some(resampler) => {
+                resampler.process(&source[..read], input.start, output, samples);
+                STREAM.len().saturating_sub(output).min(chunk)
+            }
+            None => {
+                samples[..read].copy_from_slice(&source[..read]);
+                read
+            }
+        };
+
+        // the last page of the file gets filled up with silence, as does a file cut short
+        samples[valid..].fill(0.0);
+        WAVEFORMS.invalidate(self.buffer, request.index * STREAM_PAGE_LENGTH + written);
+
+        let written = written + chunk;
+
+        if written >= STREAM_PAGE_LENGTH {
+            STREAM.complete(request);
+            self.current = None;
+        } else {
+            self.current = Some((request, written));
+        }
+
+        Ok(true)
+    }
+
+    pub fn close(self, storage: &mut Storage) -> Result<(), Error> {
+        storage.close(self.file)
+    }
+
+    /// Reads the samples `range` of the file into `samples`, returns how many could be read.
+    fn read(
+        &mut self,
+        storage: &mut Storage,
+        range: Range<usize>,
+        samples: &mut [f32],
+    ) -> Result<usize, Error> {
+        let size = self.format.bytes_per_sample();
+
+        // the input of converted chunks overlaps, so the file gets rewound a bit for every one
+        if range.start != self.read_position {
+            storage.seek(
+                &mut self.file,
+                (self.data_offset + range.start * size) as u32,
+            )?;
+        }
+
+        let mut bytes = [0; LOAD_SAMPLES_PER_STEP * 4];
+        let count = range.len().min(samples.len());
+        let read = storage.read(&mut self.file, &mut bytes[..count * size])?;
+
+        self.read_position = range.start + read / size;
+
+        Ok(export::decode_samples(self.format, &bytes[..read], samples))
+    }
+}
+
+fn as_str(name: &[u8; 12]) -> &str {
+    // names only consist of ASCII
+    core::str::from_utf8(name).unwrap_or("")
+}
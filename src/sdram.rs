@@ -1,9 +1,107 @@
-use core::ptr::slice_from_raw_parts;
+use core::ptr::{slice_from_raw_parts, slice_from_raw_parts_mut};
+
+use sitira_core::mapping::PARAMETER_COUNT;
+use sitira_core::reverb;
+use sitira_core::waveform::Peak;
+
+use crate::config::{
+    AUDIO_SAMPLE_RATE, CONTROL_RATE_IN_MS, PITCH_SHIFT_GRAIN_IN_MS, PRE_ROLL_IN_S,
+};
+use crate::slots::{BUFFER_COUNT, SLOT_LENGTH};
+use crate::waveform_cache::PYRAMID;
 
 /// Physical memory represented in bytes which is 64MB
 pub const SDRAM_SIZE: usize = 0x4000000;
 const SDRAM_BASE_ADDRESS: usize = 0xC0000000;
 
+/// Memory at the end of the SDRAM which is reserved for everything except recorded audio (8MB)
+pub const RESERVED_SIZE: usize = 0x800000;
+
+/// Memory at the beginning of the SDRAM for recorded audio in bytes
+pub const AUDIO_REGION_SIZE: usize = SDRAM_SIZE - RESERVED_SIZE;
+
+/// A fixed area inside the reserved part of the SDRAM. Offset and size are given in bytes.
+pub struct Region {
+    pub offset: usize,
+    pub size: usize,
+}
+
+impl Region {
+    /// Returns the first byte behind the region, which is where the next region can start.
+    pub const fn end(&self) -> usize {
+        self.offset + self.size
+    }
+
+    /// Returns a reading reference to the whole region in type `T`.
+    pub fn get_slice<T>(&self) -> Option<&'static [T]> {
+        get_slice(self.offset / sized::<T>(1), self.size / sized::<T>(1))
+    }
+
+    /// Returns a writing reference to the whole region in type `T`.
+    ///
+    /// ## Safety
+    /// Same as `get_slice_mut()`, the region must be handed out only once.
+    pub unsafe fn get_slice_mut<T>(&self) -> Option<&'static mut [T]> {
+        get_slice_mut(self.offset / sized::<T>(1), self.size / sized::<T>(1))
+    }
+}
+
+/// Off-screen framebuffer of the display in RGB565, sized for the largest panel of 320x240
+pub const FRAMEBUFFER: Region = Region {
+    offset: AUDIO_REGION_SIZE,
+    size: 320 * 240 * 2,
+};
+
+/// Both lines of the echo, two seconds each
+pub const ECHO_BUFFER: Region = Region {
+    offset: FRAMEBUFFER.end(),
+    size: 2 * 2 * AUDIO_SAMPLE_RATE * 4,
+};
+
+/// Longest echo time in frames, given by the size of the echo region
+pub const ECHO_MAX_FRAMES: usize = ECHO_BUFFER.size / (2 * core::mem::size_of::<f32>());
+
+/// Lines of the reverb for both channels
+pub const REVERB_BUFFER: Region = Region {
+    offset: ECHO_BUFFER.end(),
+    size: reverb::get_buffer_length(AUDIO_SAMPLE_RATE) * 4,
+};
+
+/// Min/max pyramids of every audio buffer, the waveform gets drawn from them
+pub const WAVEFORM_BUFFER: Region = Region {
+    // whole peaks, so the region can be read as a slice of them
+    offset: (REVERB_BUFFER.end() + PEAK_SIZE - 1) / PEAK_SIZE * PEAK_SIZE,
+    size: BUFFER_COUNT * PYRAMID.len() * PEAK_SIZE,
+};
+
+const PEAK_SIZE: usize = core::mem::size_of::<Peak>();
+
+/// Input in front of a take
+pub const PRE_ROLL_BUFFER: Region = Region {
+    offset: WAVEFORM_BUFFER.end(),
+    size: PRE_ROLL_IN_S * AUDIO_SAMPLE_RATE * 4,
+};
+
+/// Ring of the pitch shifter, two grains long
+pub const PITCH_SHIFT_BUFFER: Region = Region {
+    offset: PRE_ROLL_BUFFER.end(),
+    size: 2 * PITCH_SHIFT_GRAIN_IN_MS * AUDIO_SAMPLE_RATE / 1000 * 4,
+};
+
+/// Frames of audio played during one control cycle
+pub const CONTROL_CYCLE_FRAMES: usize = AUDIO_SAMPLE_RATE * CONTROL_RATE_IN_MS as usize / 1000;
+
+/// Automation lanes of all parameters, a value per control cycle of the longest take
+pub const AUTOMATION_BUFFER: Region = Region {
+    offset: PITCH_SHIFT_BUFFER.end(),
+    size: SLOT_LENGTH / CONTROL_CYCLE_FRAMES * PARAMETER_COUNT * 4,
+};
+
+const _: () = assert!(
+    AUTOMATION_BUFFER.end() <= SDRAM_SIZE,
+    "the reserved regions do not fit into the SDRAM"
+);
+
 /// Returns a reference to a slice of `len` elements with a given `offset` in type `T` if it fits into the SDRAM
 /// of the Daisy Seed Rev. 5 (which is 64MB).
 ///
@@ -27,6 +125,20 @@ pub fn get_slice<T>(offset: usize, len: usize) -> Option<&'static [T]> {
     }
 }
 
+/// Returns a mutable reference to a slice of `len` elements with a given `offset` in type `T` if it fits into
+/// the SDRAM.
+///
+/// ## Safety
+/// The caller has to guarantee that no other reference to the same area exists for as long as the returned
+/// slice is alive. This is only sound when every area is handed out once, e.g. during initialization.
+pub unsafe fn get_slice_mut<T>(offset: usize, len: usize) -> Option<&'static mut [T]> {
+    if sized::<T>(offset + len) <= SDRAM_SIZE {
+        slice_from_raw_parts_mut((SDRAM_BASE_ADDRESS + sized::<T>(offset)) as *mut T, len).as_mut()
+    } else {
+        None
+    }
+}
+
 fn sized<T>(value: usize) -> usize {
     value * core::mem::size_of::<T>()
 }
@@ -0,0 +1,213 @@
+use core::fmt::Write;
+
+use stm32h7xx_hal::hal::digital::v2::OutputPin;
+use stm32h7xx_hal::prelude::_embedded_hal_adc_OneShot;
+
+use sitira_core::memtest;
+
+use crate::config::CPU_FREQUENCY_IN_HZ;
+use crate::lcd::TEST_PATTERNS;
+use crate::rprintln;
+use crate::sitira::{ControlRate, Display};
+
+/// Longest line of the self test screen
+const MAX_LINE_LENGTH: usize = 48;
+/// Time every LCD test pattern is shown
+const PATTERN_DURATION_IN_MS: u32 = 800;
+/// Interval in which the encoder gets polled
+const POLL_INTERVAL_IN_MS: u32 = 1;
+/// Encoder polls per screen update
+const POLLS_PER_UPDATE: u32 = 30;
+/// Screen updates until the next LED lights up
+const UPDATES_PER_LED: u32 = 8;
+
+/// Line of text which lives on the stack.
+struct Line {
+    bytes: [u8; MAX_LINE_LENGTH],
+    len: usize,
+}
+
+impl Line {
+    fn new() -> Self {
+        Line {
+            bytes: [0; MAX_LINE_LENGTH],
+            len: 0,
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        // only whole `str`s get written
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("")
+    }
+}
+
+impl Write for Line {
+    fn write_str(&mut self, text: &str) -> core::fmt::Result {
+        let end = self.len + text.len();
+
+        if end > MAX_LINE_LENGTH {
+            return Err(core::fmt::Error);
+        }
+
+        self.bytes[self.len..end].copy_from_slice(text.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Returns `true` if the button and the encoder are both held, which enters the self test.
+pub fn is_requested(cr: &mut ControlRate) -> bool {
+    // the switch of the encoder is debounced over several reads
+    for _ in 0..8 {
+        cr.encoder.update();
+        delay_ms(POLL_INTERVAL_IN_MS);
+    }
+
+    cr.button.is_input_high() && cr.encoder.switch.is_high()
+}
+
+/// Exercises every peripheral of the panel, to verify newly built hardware.
+///
+/// Cycles through the LCD test patterns first, then tests all of the SDRAM and shows the live state of
+/// all inputs while the LEDs light up one after the other. Only a power cycle leaves the self
+/// test.
+pub fn run(cr: &mut ControlRate, display: &mut Display, sdram: &mut [f32], sd_card: bool) -> ! {
+    rprintln!("Entering the self test!");
+
+    for pattern in 0..TEST_PATTERNS {
+        display.show_test_pattern(pattern);
+        delay_ms(PATTERN_DURATION_IN_MS);
+    }
+
+    let words = sdram.len();
+    let mut sdram_line = Line::new();
+    let mut sd_card_line = Line::new();
+
+    // the lines always fit, so errors are impossible
+    let _ = match memtest::test(sdram, words) {
+        Ok(words) => write!(sdram_line, "SDRAM: ok, {} words", words),
+        Err(fault) => write!(sdram_line, "SDRAM: {}", fault),
+    };
+
+    rprintln!("{}", sdram_line.as_str());
+
+    let _ = write!(
+        sd_card_line,
+        "SD card: {}",
+        if sd_card { "mounted" } else { "not found" }
+    );
+
+    let mut update = 0;
+
+    loop {
+        for _ in 0..POLLS_PER_UPDATE {
+            cr.encoder.update();
+            delay_ms(POLL_INTERVAL_IN_MS);
+        }
+
+        let led = update / UPDATES_PER_LED % 4;
+
+        if update % UPDATES_PER_LED == 0 {
+            // the pins of LED 1 and 2 belong to the audio task when they are gate outputs, pin 14
+            // receives MIDI with the `midi` feature and both are the I2C bus of the `expander`
+            #[cfg(not(any(feature = "gate-outputs", feature = "expander")))]
+            cr.board.set_led(&mut cr.led1, led == 0);
+            #[cfg(not(any(feature = "gate-outputs", feature = "midi", feature = "expander")))]
+            cr.board.set_led(&mut cr.led2, led == 1);
+            cr.board.set_led(&mut cr.led3, led == 2);
+            cr.status_led.cycle_color();
+
+            if led == 3 {
+                cr.seed_led.set_high().unwrap();
+            } else {
+                cr.seed_led.set_low().unwrap();
+            }
+        }
+
+        let mut channels = [Line::new(), Line::new(), Line::new(), Line::new()];
+
+        for channel in 0..16 {
+            // a channel which can not be selected shows its last value
+            let _ = cr.muxed_parameters.read_value(channel);
+
+            let _ = write!(
+                channels[channel / 4],
+                "{:2}: {:.3}  ",
+                channel,
+                cr.muxed_parameters.get_value(channel)
+            );
+        }
+
+        if let Ok(data) = cr.adc2.read(cr.master_volume.get_pin()) {
+            cr.master_volume.update(data);
+        }
+
+        cr.direct_cvs.read(&mut cr.adc2);
+
+        let mut volume = Line::new();
+        let mut gates = Line::new();
+        let mut switches = Line::new();
+        let mut leds = Line::new();
+
+        let _ = write!(volume, "Volume: {:.3}", cr.master_volume.get_value());
+        #[cfg(not(feature = "direct-cv"))]
+        let _ = write!(
+            gates,
+            "Gates: {} {} {} {}  Kill: {}",
+            cr.gate1.is_input_high() as u8,
+            cr.gate2.is_input_high() as u8,
+            cr.gate3.is_input_high() as u8,
+            cr.gate4.is_input_high() as u8,
+            cr.kill_gate.is_input_high() as u8
+        );
+        #[cfg(feature = "direct-cv")]
+        {
+            let [cv1, cv2] = cr.direct_cvs.get_values();
+
+            let _ = write!(volume, "  CV: {:.3} {:.3}", cv1, cv2);
+            let _ = write!(
+                gates,
+                "Gates: {} {} {}",
+                cr.gate1.is_input_high() as u8,
+                cr.gate2.is_input_high() as u8,
+                cr.gate3.is_input_high() as u8
+            );
+        }
+        let _ = write!(
+            switches,
+            "Encoder: {}  Switch: {}  Button: {}",
+            cr.encoder.current_value,
+            cr.encoder.switch.is_high() as u8,
+            cr.button.is_input_high() as u8
+        );
+        let _ = match led {
+            3 => write!(leds, "LED: Seed"),
+            _ => write!(leds, "LED: {}", led + 1),
+        };
+
+        display.show_status(
+            "Self test",
+            &[
+                sdram_line.as_str(),
+                sd_card_line.as_str(),
+                "",
+                channels[0].as_str(),
+                channels[1].as_str(),
+                channels[2].as_str(),
+                channels[3].as_str(),
+                volume.as_str(),
+                gates.as_str(),
+                switches.as_str(),
+                leds.as_str(),
+                "",
+                "Power cycle to leave",
+            ],
+        );
+
+        update += 1;
+    }
+}
+
+fn delay_ms(ms: u32) {
+    cortex_m::asm::delay(CPU_FREQUENCY_IN_HZ / 1000 * ms);
+}
@@ -0,0 +1,53 @@
+use sitira_core::session::{Session, SessionError, SessionText, MAX_SESSION_LENGTH, SESSION_NAME};
+
+use crate::storage::{self, Storage};
+
+#[derive(Debug)]
+pub enum LoadError {
+    Storage(storage::Error),
+    Session(SessionError),
+}
+
+impl From<storage::Error> for LoadError {
+    fn from(error: storage::Error) -> Self {
+        LoadError::Storage(error)
+    }
+}
+
+impl From<SessionError> for LoadError {
+    fn from(error: SessionError) -> Self {
+        LoadError::Session(error)
+    }
+}
+
+/// Reads the session file from the SD card.
+pub fn load(storage: &mut Storage) -> Result<Session, LoadError> {
+    let mut file = storage.open(SESSION_NAME)?;
+    let mut buffer = [0; MAX_SESSION_LENGTH];
+    let length = file.length() as usize;
+
+    if length > MAX_SESSION_LENGTH {
+        storage.close(file)?;
+        return Err(SessionError::TooLong.into());
+    }
+
+    let read = storage.read(&mut file, &mut buffer[..length])?;
+    storage.close(file)?;
+
+    Ok(Session::from_bytes(&buffer[..read])?)
+}
+
+/// Writes the session to the SD card, replacing the last one.
+pub fn save(storage: &mut Storage, session: &Session) -> Result<(), storage::Error> {
+    let mut text = SessionText::new();
+
+    session
+        .write(&mut text)
+        .map_err(|_| storage::Error::FormatError("Session too long"))?;
+
+    let mut file = storage.create(SESSION_NAME)?;
+    let written = storage.write(&mut file, text.as_bytes());
+    storage.close(file)?;
+
+    written.map(|_| ())
+}
@@ -1,86 +1,243 @@
 use libdaisy::prelude::*;
 use libdaisy::{audio, gpio::*, hid, system::System};
 
-use stm32h7xx_hal::{adc, gpio, pac, spi, stm32, timer};
+use stm32h7xx_hal::gpio::{Edge, ExtiPin};
+use stm32h7xx_hal::hal::digital::v2::InputPin;
+#[cfg(feature = "direct-cv")]
+use stm32h7xx_hal::prelude::_embedded_hal_adc_OneShot;
+#[cfg(feature = "midi")]
+use stm32h7xx_hal::serial;
+#[cfg(not(feature = "headless"))]
+use stm32h7xx_hal::spi;
+use stm32h7xx_hal::{adc, gpio, pac, pwm, sdmmc, stm32, timer};
+#[cfg(feature = "usb-audio")]
+use stm32h7xx_hal::{rcc::rec::UsbClkSel, usb_hs};
+
+use sitira_core::event::{
+    Event, EventConsumer, EventProducer, EventQueue, Input, TimedEvent, PANEL_EVENTS,
+};
+use sitira_core::gesture::GestureDetector;
+use sitira_core::mapping::DIRECT_CV_COUNT;
+use sitira_core::memtest;
+#[cfg(feature = "midi")]
+use sitira_core::midi::MIDI_BAUD_RATE;
+use sitira_core::pulse::PULSE_OUTPUT_COUNT;
 
 use crate::binary_input::*;
+use crate::board::{self, BoardConfig, LedPolarity};
 use crate::config::*;
+use crate::daisy::Board;
+#[cfg(not(feature = "headless"))]
+use crate::display_driver::DisplayDriver;
 use crate::dual_mux_4051;
 use crate::encoder;
+use crate::error::SitiraError;
+use crate::expander::Expander;
+use crate::gate_edges::{self, GateDebouncer, GateEdges};
 use crate::lcd;
+use crate::midi_input::{self, MidiInput};
+use crate::rgbled;
 use crate::rprintln;
+use crate::sdram;
+use crate::usb_output::{self, UsbAudio, UsbOutput};
 
 #[macro_export]
 macro_rules! rprintln {
-    ($($rest:tt)*) => {
+    ($($rest:tt)*) => {{
         #[cfg(feature = "log")]
-        rtt_target::rprintln!($($rest)*)
-    }
+        rtt_target::rprintln!($($rest)*);
+        // without the log the arguments still count as used
+        #[cfg(not(feature = "log"))]
+        if false {
+            let _ = format_args!($($rest)*);
+        }
+    }};
 }
 
 // ===================
 // PIN TYPE DEFINITION
 // ===================
 
+/// Daisy module of the panel
+pub type Daisy = crate::daisy::DaisySeed;
+
 /// Not multiplexed
-pub type MasterVolume = hid::AnalogControl<Daisy21<Analog>>;
+pub type MasterVolume = hid::AnalogControl<<Daisy as Board>::MasterVolume>;
 /// MUX A+B
-pub type MuxInput1 = Daisy15<Analog>;
+pub type MuxInput1 = <Daisy as Board>::MuxInput1;
 /// MUX C+D
-pub type MuxInput2 = Daisy16<Analog>;
+pub type MuxInput2 = <Daisy as Board>::MuxInput2;
 
-pub type MuxSelect0 = Daisy17<Output<PushPull>>;
-pub type MuxSelect1 = Daisy18<Output<PushPull>>;
-pub type MuxSelect2 = Daisy19<Output<PushPull>>;
+pub type MuxSelect0 = <Daisy as Board>::MuxSelect0;
+pub type MuxSelect1 = <Daisy as Board>::MuxSelect1;
+pub type MuxSelect2 = <Daisy as Board>::MuxSelect2;
 
 pub type AnalogRead =
     dual_mux_4051::DualMux<MuxInput1, MuxInput2, MuxSelect0, MuxSelect1, MuxSelect2>;
 
-pub type Gate1 = BinaryInput<Daisy24<Input<gpio::Floating>>>;
-pub type Gate2 = BinaryInput<Daisy25<Input<gpio::Floating>>>;
-pub type Gate3 = BinaryInput<Daisy22<Input<gpio::Floating>>>;
-pub type Gate4 = BinaryInput<Daisy23<Input<gpio::Floating>>>;
-
-pub type KillGate = BinaryInput<Daisy20<Input<gpio::Floating>>>;
-
-pub type Led1 = Daisy13<Output<PushPull>>;
-pub type Led2 = Daisy14<Output<PushPull>>;
-pub type Led3 = Daisy0<Output<PushPull>>;
+pub type Gate1 = BinaryInput<<Daisy as Board>::Gate1>;
+pub type Gate2 = BinaryInput<<Daisy as Board>::Gate2>;
+pub type Gate3 = BinaryInput<<Daisy as Board>::Gate3>;
+#[cfg(not(feature = "direct-cv"))]
+pub type Gate4 = BinaryInput<<Daisy as Board>::Gate4>;
+
+#[cfg(not(feature = "direct-cv"))]
+pub type KillGate = BinaryInput<<Daisy as Board>::KillGate>;
+
+/// Not multiplexed, on the jacks of gate 4 and the kill gate
+#[cfg(feature = "direct-cv")]
+pub type DirectCv1 = hid::AnalogControl<<Daisy as Board>::DirectCv1>;
+#[cfg(feature = "direct-cv")]
+pub type DirectCv2 = hid::AnalogControl<<Daisy as Board>::DirectCv2>;
+
+pub type Led1 = <Daisy as Board>::Led1;
+pub type Led2 = <Daisy as Board>::Led2;
+pub type Led3 = <Daisy as Board>::Led3;
+
+pub type StatusRed = pwm::Pwm<stm32::TIM12, 0, pwm::ComplementaryImpossible>;
+pub type StatusGreen = pwm::Pwm<stm32::TIM12, 1, pwm::ComplementaryImpossible>;
+
+#[cfg(not(feature = "cv-output"))]
+pub type StatusLed = rgbled::RGBLed<StatusRed, StatusGreen, rgbled::NoChannel>;
+/// Only red is left when the green channel is the CV output
+#[cfg(feature = "cv-output")]
+pub type StatusLed = rgbled::RGBLed<StatusRed, rgbled::NoChannel, rgbled::NoChannel>;
+
+/// Green channel of the status LED, driven by the audio task as CV output
+#[cfg(feature = "cv-output")]
+pub type CvOutput = crate::pwm_cv::PwmCv<StatusGreen>;
+
+/// Stands in for the CV output while the green channel belongs to the status LED.
+#[cfg(not(feature = "cv-output"))]
+pub struct CvOutput;
+
+#[cfg(not(feature = "cv-output"))]
+impl CvOutput {
+    pub fn set(&mut self, _value: f32) {}
+}
 
-pub type ButtonSwitch = BinaryInput<Daisy9<Input<PullDown>>>;
+pub type ButtonSwitch = BinaryInput<<Daisy as Board>::Button>;
 
 pub type Encoder = encoder::RotaryEncoder<
-    Daisy28<Input<gpio::Floating>>,
-    Daisy26<Input<PullUp>>,
-    Daisy27<Input<PullUp>>,
+    <Daisy as Board>::RotarySwitch,
+    <Daisy as Board>::RotaryClock,
+    <Daisy as Board>::RotaryData,
 >;
 
-pub type Display = lcd::Lcd<
+/// Panel of the display, an ILI9341 unless the `st7789` or the `ssd1306` feature selects another.
+/// The `headless` build has none.
+#[cfg(not(any(feature = "st7789", feature = "ssd1306", feature = "headless")))]
+pub type Panel = crate::display_driver::Ili9341Panel<
+    spi::Spi<stm32::SPI1, spi::Enabled>,
+    Daisy11<Output<PushPull>>,
+    Daisy12<Output<PushPull>>,
+    Daisy7<Output<PushPull>>,
+>;
+#[cfg(feature = "st7789")]
+pub type Panel = crate::st7789::St7789<
     spi::Spi<stm32::SPI1, spi::Enabled>,
     Daisy11<Output<PushPull>>,
     Daisy12<Output<PushPull>>,
     Daisy7<Output<PushPull>>,
 >;
+#[cfg(feature = "ssd1306")]
+pub type Panel = crate::ssd1306::Ssd1306<
+    spi::Spi<stm32::SPI1, spi::Enabled>,
+    Daisy11<Output<PushPull>>,
+    Daisy12<Output<PushPull>>,
+    Daisy7<Output<PushPull>>,
+>;
+#[cfg(feature = "headless")]
+pub type Panel = crate::display_driver::NoPanel;
 
-pub enum AdcMuxInputs {
-    Offset = 0,
-    GrainSize = 1,
-    Pitch = 2,
-    PitchSpread = 4,
-    OffsetSpread = 5,
-    GrainSizeSpread = 7,
-    Delay = 8,
-    ActiveGrains = 9,
-    Envelope = 10,
-    Velocity = 12,
-    DelaySpread = 13,
-    WaveSelect = 14,
-    VelocitySpread = 15,
-}
+pub type Display = lcd::Lcd<Panel>;
+
+/// SD card on SDMMC1 with a 4 bit bus on pins 1 to 6
+pub type SdCard = sdmmc::Sdmmc<stm32::SDMMC1>;
 
 pub struct AudioRate {
     pub audio: audio::Audio,
     pub buffer: audio::AudioBuffer,
+    pub gate_outputs: GateOutputs,
+    pub cv_output: CvOutput,
+    /// Edges of the gate inputs, pushed by their interrupts
+    pub gate_events: EventConsumer,
+    /// Panel events the control task hands on, like the kill gate
+    pub panel_events: EventConsumer,
+    /// Output on its way to the USB audio device
+    pub usb_output: UsbOutput,
+}
+
+/// Pins of LED 1 and 2, driven by the audio task as grain and loop gate outputs.
+#[cfg(feature = "gate-outputs")]
+pub struct GateOutputs {
+    grain: Led1,
+    loop_wrap: Led2,
+}
+
+#[cfg(feature = "gate-outputs")]
+impl GateOutputs {
+    pub fn new(mut grain: Led1, mut loop_wrap: Led2) -> Result<Self, SitiraError> {
+        grain.set_low().map_err(|_| SitiraError::Pin)?;
+        loop_wrap.set_low().map_err(|_| SitiraError::Pin)?;
+
+        Ok(GateOutputs { grain, loop_wrap })
+    }
+
+    /// Sets the outputs to the levels of the pulse scheduler, indexed by `PulseOutput`. A pulse
+    /// which could not be sent is not worth holding up the audio for.
+    pub fn write(&mut self, levels: [bool; PULSE_OUTPUT_COUNT]) {
+        self.grain.set_state(levels[0].into()).ok();
+        self.loop_wrap.set_state(levels[1].into()).ok();
+    }
+}
+
+/// Stands in for the gate outputs while LED 1 and 2 show the gate inputs.
+#[cfg(not(feature = "gate-outputs"))]
+pub struct GateOutputs;
+
+#[cfg(not(feature = "gate-outputs"))]
+impl GateOutputs {
+    pub fn write(&mut self, _levels: [bool; PULSE_OUTPUT_COUNT]) {}
+}
+
+/// CV inputs which ADC2 reads next to the master volume, without the multiplexers.
+#[cfg(feature = "direct-cv")]
+pub struct DirectCvs {
+    cv1: DirectCv1,
+    cv2: DirectCv2,
+}
+
+#[cfg(feature = "direct-cv")]
+impl DirectCvs {
+    /// Reads all inputs, one which could not be converted keeps its last value.
+    pub fn read(&mut self, adc2: &mut adc::Adc<stm32::ADC2, adc::Enabled>) {
+        if let Ok(data) = adc2.read(self.cv1.get_pin()) {
+            self.cv1.update(data);
+        }
+
+        if let Ok(data) = adc2.read(self.cv2.get_pin()) {
+            self.cv2.update(data);
+        }
+    }
+
+    /// Returns the smoothed values, indexed like `DIRECT_CV_PARAMETERS`.
+    pub fn get_values(&self) -> [f32; DIRECT_CV_COUNT] {
+        [self.cv1.get_value(), self.cv2.get_value()]
+    }
+}
+
+/// Stands in for the direct CV inputs while their jacks are gate 4 and the kill gate.
+#[cfg(not(feature = "direct-cv"))]
+pub struct DirectCvs;
+
+#[cfg(not(feature = "direct-cv"))]
+impl DirectCvs {
+    pub fn read(&mut self, _adc2: &mut adc::Adc<stm32::ADC2, adc::Enabled>) {}
+
+    pub fn get_values(&self) -> [f32; DIRECT_CV_COUNT] {
+        [0.0; DIRECT_CV_COUNT]
+    }
 }
 
 pub struct ControlRate {
@@ -91,27 +248,141 @@ pub struct ControlRate {
     // Analog inputs
     pub master_volume: MasterVolume,
     pub muxed_parameters: AnalogRead,
+    pub direct_cvs: DirectCvs,
+    pub expander: Expander,
 
     // Gates
     pub gate1: Gate1,
     pub gate2: Gate2,
     pub gate3: Gate3,
+    #[cfg(not(feature = "direct-cv"))]
     pub gate4: Gate4,
+    #[cfg(not(feature = "direct-cv"))]
     pub kill_gate: KillGate,
+    pub gate_debouncer: GateDebouncer,
+    /// Notes and control changes, pushed by the MIDI interrupt
+    pub midi_events: EventConsumer,
+    /// Panel events for the audio task
+    pub audio_events: EventProducer,
 
     // LEDs
+    #[cfg(not(any(feature = "gate-outputs", feature = "expander")))]
     pub led1: Led1,
+    #[cfg(not(any(feature = "gate-outputs", feature = "midi", feature = "expander")))]
     pub led2: Led2,
     pub led3: Led3,
     pub seed_led: SeedLed,
+    pub status_led: StatusLed,
 
     // Switches
     pub button: ButtonSwitch,
+    pub button_gestures: GestureDetector,
     pub encoder: Encoder,
+
+    // Panel revision
+    pub board: &'static BoardConfig,
+}
+
+impl ControlRate {
+    /// Returns the saved state of gate 4.
+    #[cfg(not(feature = "direct-cv"))]
+    pub fn is_gate4_high(&self) -> bool {
+        self.gate4.is_saved_state_high()
+    }
+
+    /// Gate 4 stays low while its jack is a direct CV input.
+    #[cfg(feature = "direct-cv")]
+    pub fn is_gate4_high(&self) -> bool {
+        false
+    }
+
+    /// Polls all binary inputs and the encoder and translates their state changes into events,
+    /// which get the time of the poll. Gate edges and MIDI keep the time they got received at.
+    pub fn poll_events(&mut self, events: &mut EventQueue) {
+        let timestamp = cortex_m::peripheral::DWT::cycle_count();
+
+        // save all binary inputs at the beginning
+        self.button.save_state();
+        self.gate1.save_state();
+        self.gate2.save_state();
+        self.gate3.save_state();
+        #[cfg(not(feature = "direct-cv"))]
+        self.gate4.save_state();
+        #[cfg(not(feature = "direct-cv"))]
+        self.kill_gate.save_state();
+        self.encoder.update();
+
+        push_edge_events(&self.button, Input::Button, timestamp, events);
+
+        let gesture = self
+            .button_gestures
+            .tick(self.button.is_triggered(), self.button.is_released());
+
+        if let Some(gesture) = gesture {
+            push_event(gesture.to_event(Input::Button), timestamp, events);
+        }
+        #[cfg(not(feature = "direct-cv"))]
+        push_edge_events(&self.kill_gate, Input::KillGate, timestamp, events);
+
+        self.expander.poll(timestamp, events);
+
+        // edges of the gates have been caught by their interrupts, so short triggers are not lost
+        self.gate_debouncer.drain(|event| {
+            events.push(event);
+        });
+
+        while let Some(event) = self.midi_events.pop() {
+            events.push(event);
+        }
+
+        if self.encoder.switch.is_rising() {
+            push_event(Event::Pressed(Input::EncoderSwitch), timestamp, events);
+        }
+
+        if self.encoder.switch.is_falling() {
+            push_event(Event::Released(Input::EncoderSwitch), timestamp, events);
+        }
+
+        let tick = self.encoder.tick();
+
+        if let Some(gesture) = tick.gesture {
+            push_event(gesture.to_event(Input::EncoderSwitch), timestamp, events);
+        }
+
+        if tick.detents != 0 {
+            let event = Event::EncoderTurned {
+                detents: tick.detents,
+                accelerated: tick.accelerated,
+            };
+
+            push_event(event, timestamp, events);
+        }
+    }
+}
+
+fn push_event(event: Event, timestamp: u32, events: &mut EventQueue) {
+    events.push(TimedEvent { event, timestamp });
+}
+
+fn push_edge_events<P>(
+    input: &BinaryInput<P>,
+    source: Input,
+    timestamp: u32,
+    events: &mut EventQueue,
+) where
+    P: InputPin,
+    <P as InputPin>::Error: core::fmt::Debug,
+{
+    if input.is_triggered() {
+        push_event(Event::Pressed(source), timestamp, events);
+    }
+
+    if input.is_released() {
+        push_event(Event::Released(source), timestamp, events);
+    }
 }
 
 pub struct VisualRate {
-    pub lcd: Display,
     pub timer4: timer::Timer<stm32::TIM4>,
 }
 
@@ -119,8 +390,81 @@ pub struct Sitira {
     pub audio_rate: AudioRate,
     pub control_rate: ControlRate,
     pub visual_rate: VisualRate,
+    pub display: Display,
     pub sdram: &'static mut [f32],
-    // pub sd_card: Option<SdCard>,
+    /// SD card interface, the card itself may be missing
+    pub sd_card: SdCard,
+    /// Producing ends of the gate edge queues, shared by the gate interrupts
+    pub gate_edges: GateEdges,
+    /// UART the MIDI interrupt reads from
+    pub midi_input: MidiInput,
+    /// USB device the USB interrupt serves
+    pub usb_audio: UsbAudio,
+}
+
+/// Device peripherals the platform sets up besides the ones of libdaisy.
+///
+/// `System::init()` consumes all device peripherals, but leaves these alone apart from the clock
+/// registers. They are taken once right after it and moved out one by one where they get set up,
+/// so further peripherals like a UART or USB just need another field here.
+struct DevicePeripherals {
+    rcc: pac::RCC,
+    pwr: pac::PWR,
+    syscfg: pac::SYSCFG,
+    exti: pac::EXTI,
+    tim3: pac::TIM3,
+    tim4: pac::TIM4,
+    tim12: pac::TIM12,
+    spi1: pac::SPI1,
+    sdmmc1: pac::SDMMC1,
+    #[cfg(feature = "midi")]
+    usart1: pac::USART1,
+    #[cfg(feature = "expander")]
+    i2c1: pac::I2C1,
+    #[cfg(feature = "usb-audio")]
+    gpioa: pac::GPIOA,
+    #[cfg(feature = "usb-audio")]
+    otg2_global: pac::OTG2_HS_GLOBAL,
+    #[cfg(feature = "usb-audio")]
+    otg2_device: pac::OTG2_HS_DEVICE,
+    #[cfg(feature = "usb-audio")]
+    otg2_pwrclk: pac::OTG2_HS_PWRCLK,
+}
+
+impl DevicePeripherals {
+    /// Takes the peripherals which `System::init()` has left.
+    ///
+    /// # Safety
+    ///
+    /// Must be called only once, after `System::init()`, and nothing else may steal the device
+    /// peripherals.
+    unsafe fn take() -> Self {
+        let device = pac::Peripherals::steal();
+
+        DevicePeripherals {
+            rcc: device.RCC,
+            pwr: device.PWR,
+            syscfg: device.SYSCFG,
+            exti: device.EXTI,
+            tim3: device.TIM3,
+            tim4: device.TIM4,
+            tim12: device.TIM12,
+            spi1: device.SPI1,
+            sdmmc1: device.SDMMC1,
+            #[cfg(feature = "midi")]
+            usart1: device.USART1,
+            #[cfg(feature = "expander")]
+            i2c1: device.I2C1,
+            #[cfg(feature = "usb-audio")]
+            gpioa: device.GPIOA,
+            #[cfg(feature = "usb-audio")]
+            otg2_global: device.OTG2_HS_GLOBAL,
+            #[cfg(feature = "usb-audio")]
+            otg2_device: device.OTG2_HS_DEVICE,
+            #[cfg(feature = "usb-audio")]
+            otg2_pwrclk: device.OTG2_HS_PWRCLK,
+        }
+    }
 }
 
 impl Sitira {
@@ -132,19 +476,27 @@ impl Sitira {
     - ADC1 (Analog Input Reading)
     - SPI1 (LCD Driver)
     - SDMMC1 (SD Card Controller)
+    - USART1 (MIDI Input, with the `midi` feature)
+    - I2C1 (Expansion Panel, with the `expander` feature)
+    - USB2 (USB Audio on the micro USB port, with the `usb-audio` feature)
+
+    Fails if a part which is needed to make sound can not be set up, a display which does not
+    answer gets left out.
     */
-    pub fn init(core: rtic::export::Peripherals, device: stm32::Peripherals) -> Self {
+    pub fn init(
+        core: rtic::export::Peripherals,
+        device: stm32::Peripherals,
+    ) -> Result<Self, SitiraError> {
         // ===========
         // SYSTEM INIT
         // ===========
 
         let mut system = System::init(core, device);
 
-        let rcc_p = unsafe { pac::Peripherals::steal().RCC };
-        let pwr_p = unsafe { pac::Peripherals::steal().PWR };
-        let syscfg_p = unsafe { pac::Peripherals::steal().SYSCFG };
+        // SAFETY: called once right after the system init, which is the only other owner
+        let mut device = unsafe { DevicePeripherals::take() };
 
-        let mut ccdr = System::init_clocks(pwr_p, rcc_p, &syscfg_p);
+        let mut ccdr = System::init_clocks(device.pwr, device.rcc, &device.syscfg);
 
         // enable logger
         libdaisy::logger::init();
@@ -152,16 +504,71 @@ impl Sitira {
 
         // set high for system config
         let mut seed_led = system.gpio.led;
-        seed_led.set_high().unwrap();
+        seed_led.set_high().map_err(|_| SitiraError::Pin)?;
 
         // ============
         // CONFIG SDRAM
         // ============
 
-        let sdram = system.sdram;
+        // the upper part of the memory is reserved for the framebuffer
+        let (sdram, _) = system
+            .sdram
+            .split_at_mut(sdram::AUDIO_REGION_SIZE / core::mem::size_of::<f32>());
+
+        // soldering faults of DIY builds would otherwise only show up as corrupted audio
+        let sdram_test = match SDRAM_TEST_WORDS {
+            0 => Ok(0),
+            words => memtest::test(sdram, words),
+        };
+
+        match sdram_test {
+            Ok(words) => {
+                rprintln!("SDRAM passed the test of {} words!", words);
+            }
+            Err(fault) => {
+                rprintln!("SDRAM failed the test: {}", fault);
+            }
+        }
+
         sdram.fill(0.0);
         rprintln!("SDRAM initiated!");
 
+        // ===========================
+        // DETECT HARDWARE REVISION
+        // ===========================
+
+        // strap resistors on the status LED pins pull them low against the internal pull-ups
+        let strap0_pin = system
+            .gpio
+            .daisy29
+            .take()
+            .ok_or(SitiraError::PinTaken(29))?
+            .into_pull_up_input();
+
+        let strap1_pin = system
+            .gpio
+            .daisy30
+            .take()
+            .ok_or(SitiraError::PinTaken(30))?
+            .into_pull_up_input();
+
+        // let the pull-ups settle
+        cortex_m::asm::delay(STRAP_SETTLE_CYCLES);
+
+        let strap1 = strap1_pin.is_high().map_err(|_| SitiraError::Pin)?;
+        let strap0 = strap0_pin.is_high().map_err(|_| SitiraError::Pin)?;
+        let strap_id = (strap1 as u8) << 1 | strap0 as u8;
+        let board = board::select(strap_id);
+
+        if board::FORCED.is_some() {
+            rprintln!(
+                "Selected hardware revision {:?} by feature!",
+                board.revision
+            );
+        } else {
+            rprintln!("Detected hardware revision {:?}!", board.revision);
+        }
+
         // =============
         // CONFIG TIMERS
         // =============
@@ -169,117 +576,192 @@ impl Sitira {
         system.timer2.set_freq(CONTROL_RATE_IN_MS.ms());
         rprintln!("Set control rate timer to {} ms!", CONTROL_RATE_IN_MS);
 
-        // Delay Timer
-        let timer3 = unsafe { pac::Peripherals::steal().TIM3 }.timer(
-            1.ms(),
-            ccdr.peripheral.TIM3,
-            &ccdr.clocks,
-        );
-        let delay = stm32h7xx_hal::delay::DelayFromCountDownTimer::new(timer3);
-
-        let timer4_p = unsafe { pac::Peripherals::steal().TIM4 };
-        let mut timer4 = timer::Timer::tim4(timer4_p, ccdr.peripheral.TIM4, &mut ccdr.clocks);
+        let mut timer4 = timer::Timer::tim4(device.tim4, ccdr.peripheral.TIM4, &mut ccdr.clocks);
 
         timer4.set_freq(LCD_REFRESH_RATE_IN_MS.ms());
-        timer4.listen(stm32h7xx_hal::timer::Event::TimeOut);
-        rprintln!("Set visual rate timer to {} ms!", LCD_REFRESH_RATE_IN_MS);
-
-        // ===========================
-        // CONFIG LCD DRIVER (ILI9431)
-        // ===========================
 
-        let lcd_clk = system
-            .gpio
-            .daisy8
-            .expect("Failed to get pin 8 of the daisy!")
-            .into_alternate_af5();
-
-        let lcd_miso = stm32h7xx_hal::spi::NoMiso {};
-
-        let lcd_mosi = system
-            .gpio
-            .daisy10
-            .expect("Failed to get pin 10 of the daisy!")
-            .into_alternate_af5()
-            .internal_pull_up(true);
-
-        let lcd_dc = system
-            .gpio
-            .daisy11
-            .expect("Failed to get pin 11 of the daisy!")
-            .into_push_pull_output();
-        let lcd_cs = system
-            .gpio
-            .daisy12
-            .expect("Failed to get pin 12 of the daisy!")
-            .into_push_pull_output();
-
-        // dummy pin
-        let lcd_reset = system
-            .gpio
-            .daisy7
-            .expect("Failed to get pin 7 of the daisy!")
-            .into_push_pull_output();
+        // without a display the display task never runs
+        #[cfg(not(feature = "headless"))]
+        {
+            timer4.listen(stm32h7xx_hal::timer::Event::TimeOut);
+            rprintln!("Set visual rate timer to {} ms!", LCD_REFRESH_RATE_IN_MS);
+        }
 
-        let mode = spi::Mode {
-            polarity: stm32h7xx_hal::spi::Polarity::IdleLow,
-            phase: stm32h7xx_hal::spi::Phase::CaptureOnFirstTransition,
+        // ===================
+        // CONFIG LCD DRIVER
+        // ===================
+
+        // SAFETY: the framebuffer region is handed out only here and lies outside of the audio
+        // region
+        let pixels =
+            unsafe { sdram::FRAMEBUFFER.get_slice_mut() }.ok_or(SitiraError::Framebuffer)?;
+
+        #[cfg(feature = "headless")]
+        let panel = Some(Panel);
+
+        #[cfg(not(feature = "headless"))]
+        let panel = {
+            // Delay Timer
+            let timer3 = device
+                .tim3
+                .timer(1.ms(), ccdr.peripheral.TIM3, &ccdr.clocks);
+            let delay = stm32h7xx_hal::delay::DelayFromCountDownTimer::new(timer3);
+
+            let lcd_clk = system
+                .gpio
+                .daisy8
+                .ok_or(SitiraError::PinTaken(8))?
+                .into_alternate_af5();
+
+            let lcd_miso = stm32h7xx_hal::spi::NoMiso {};
+
+            let lcd_mosi = system
+                .gpio
+                .daisy10
+                .ok_or(SitiraError::PinTaken(10))?
+                .into_alternate_af5()
+                .internal_pull_up(true);
+
+            let lcd_dc = system
+                .gpio
+                .daisy11
+                .ok_or(SitiraError::PinTaken(11))?
+                .into_push_pull_output();
+            let lcd_cs = system
+                .gpio
+                .daisy12
+                .ok_or(SitiraError::PinTaken(12))?
+                .into_push_pull_output();
+
+            // not connected on the ILI9341 board, the other panels need their reset wired to it
+            let lcd_reset = system
+                .gpio
+                .daisy7
+                .ok_or(SitiraError::PinTaken(7))?
+                .into_push_pull_output();
+
+            let mode = spi::Mode {
+                polarity: stm32h7xx_hal::spi::Polarity::IdleLow,
+                phase: stm32h7xx_hal::spi::Phase::CaptureOnFirstTransition,
+            };
+
+            let lcd_spi = device.spi1.spi(
+                (lcd_clk, lcd_miso, lcd_mosi),
+                mode,
+                Panel::SPI_CLOCK_IN_MHZ.mhz(),
+                ccdr.peripheral.SPI1,
+                &ccdr.clocks,
+            );
+
+            // Sitira keeps playing without a display, like a headless build
+            match Panel::new(lcd_spi, lcd_dc, lcd_cs, lcd_reset, delay) {
+                Ok(panel) => Some(panel),
+                Err(error) => {
+                    rprintln!("{}, the display is left out!", error.description());
+                    None
+                }
+            }
         };
 
-        let lcd_spi = unsafe { pac::Peripherals::steal().SPI1 }.spi(
-            (lcd_clk, lcd_miso, lcd_mosi),
-            mode,
-            25.mhz(),
-            ccdr.peripheral.SPI1,
-            &ccdr.clocks,
-        );
-
-        let mut lcd = lcd::Lcd::new(lcd_spi, lcd_dc, lcd_cs, lcd_reset, delay);
+        let mut lcd = lcd::Lcd::new(panel, pixels);
 
         lcd.setup();
 
         rprintln!("Initiated LCD screen!");
 
-        // =====================
-        // CONFIG ANALOG READING
-        // =====================
+        // without a display the seed LED flickers instead, the fault itself is only logged
+        #[cfg(feature = "headless")]
+        if sdram_test.is_err() {
+            for _ in 0..SDRAM_FAULT_BLINKS {
+                seed_led.set_low().map_err(|_| SitiraError::Pin)?;
+                cortex_m::asm::delay(CPU_FREQUENCY_IN_HZ / 10);
+                seed_led.set_high().map_err(|_| SitiraError::Pin)?;
+                cortex_m::asm::delay(CPU_FREQUENCY_IN_HZ / 10);
+            }
+        }
+
+        // the framebuffer lives in the SDRAM as well, so the report might be garbled
+        #[cfg(not(feature = "headless"))]
+        if let Err(fault) = sdram_test {
+            lcd.show_fault(
+                "SDRAM fault",
+                &[fault.description(), "Check the SDRAM solder joints"],
+            );
 
-        let mux1_pin = system
+            // gives some time to read the message
+            cortex_m::asm::delay(3 * CPU_FREQUENCY_IN_HZ);
+        }
+
+        // ==============
+        // CONFIG SD CARD
+        // ==============
+
+        let sd_d3 = system
             .gpio
-            .daisy15
+            .daisy1
             .take()
-            .expect("Failed to get pin 15 of the daisy!")
-            .into_analog();
-
-        let mux2_pin = system
+            .ok_or(SitiraError::PinTaken(1))?
+            .into_alternate_af12()
+            .internal_pull_up(true)
+            .set_speed(gpio::Speed::VeryHigh);
+        let sd_d2 = system
             .gpio
-            .daisy16
+            .daisy2
             .take()
-            .expect("Failed to get pin 16 of the daisy!")
-            .into_analog();
-
-        let select0_pin = system
+            .ok_or(SitiraError::PinTaken(2))?
+            .into_alternate_af12()
+            .internal_pull_up(true)
+            .set_speed(gpio::Speed::VeryHigh);
+        let sd_d1 = system
             .gpio
-            .daisy17
+            .daisy3
             .take()
-            .expect("Failed to get pin 17 of the daisy!")
-            .into_push_pull_output();
-
-        let select1_pin = system
+            .ok_or(SitiraError::PinTaken(3))?
+            .into_alternate_af12()
+            .internal_pull_up(true)
+            .set_speed(gpio::Speed::VeryHigh);
+        let sd_d0 = system
             .gpio
-            .daisy18
+            .daisy4
             .take()
-            .expect("Failed to get pin 18 of the daisy!")
-            .into_push_pull_output();
-
-        let select2_pin = system
+            .ok_or(SitiraError::PinTaken(4))?
+            .into_alternate_af12()
+            .internal_pull_up(true)
+            .set_speed(gpio::Speed::VeryHigh);
+        let sd_cmd = system
             .gpio
-            .daisy19
+            .daisy5
             .take()
-            .expect("Failed to get pin 19 of the daisy!")
-            .into_push_pull_output();
+            .ok_or(SitiraError::PinTaken(5))?
+            .into_alternate_af12()
+            .internal_pull_up(true)
+            .set_speed(gpio::Speed::VeryHigh);
+        let sd_clk = system
+            .gpio
+            .daisy6
+            .take()
+            .ok_or(SitiraError::PinTaken(6))?
+            .into_alternate_af12()
+            .internal_pull_up(false)
+            .set_speed(gpio::Speed::VeryHigh);
+
+        // the card gets initialized when it is mounted, which can be tried again without one
+        let sd_card = device.sdmmc1.sdmmc(
+            (sd_clk, sd_cmd, sd_d0, sd_d1, sd_d2, sd_d3),
+            ccdr.peripheral.SDMMC1,
+            &ccdr.clocks,
+        );
 
-        let muxed_parameters = dual_mux_4051::DualMux::new(
+        rprintln!("Initiated SD card interface!");
+
+        // =====================
+        // CONFIG ANALOG READING
+        // =====================
+
+        let (mux1_pin, mux2_pin) = Daisy::mux_inputs(&mut system.gpio)?;
+        let (select0_pin, select1_pin, select2_pin) = Daisy::mux_selects(&mut system.gpio)?;
+
+        let mut muxed_parameters = dual_mux_4051::DualMux::new(
             system.adc1,
             mux1_pin,
             mux2_pin,
@@ -288,6 +770,9 @@ impl Sitira {
             select2_pin,
         );
 
+        muxed_parameters.set_channel_map(board.mux_channels);
+        muxed_parameters.set_oversampling(MUX_OVERSAMPLING);
+
         rprintln!("Initiated ADC1 reading (dual 4051 mux)!");
 
         let mut adc2 = system.adc2.enable();
@@ -295,40 +780,29 @@ impl Sitira {
         adc2.set_sample_time(adc::AdcSampleTime::T_387);
         let adc2_max_value = adc2.max_sample() as f32;
 
-        let master_volume_pin = system
-            .gpio
-            .daisy21
-            .take()
-            .expect("Failed to get pin 13 of the daisy!")
-            .into_analog();
+        let master_volume_pin = Daisy::master_volume(&mut system.gpio)?;
         let master_volume = hid::AnalogControl::new(master_volume_pin, adc2_max_value);
 
+        #[cfg(feature = "direct-cv")]
+        let direct_cvs = {
+            let (cv1_pin, cv2_pin) = Daisy::direct_cvs(&mut system.gpio)?;
+
+            DirectCvs {
+                cv1: hid::AnalogControl::new(cv1_pin, adc2_max_value),
+                cv2: hid::AnalogControl::new(cv2_pin, adc2_max_value),
+            }
+        };
+        #[cfg(not(feature = "direct-cv"))]
+        let direct_cvs = DirectCvs;
+
         rprintln!("Initiated ADC2 reading!");
 
         // ==============
         // CONFIG ENCODER
         // ==============
 
-        let rotary_switch_pin = system
-            .gpio
-            .daisy28
-            .take()
-            .expect("Failed to get pin 28 of the daisy!")
-            .into_floating_input();
-
-        let rotary_clock_pin = system
-            .gpio
-            .daisy26
-            .take()
-            .expect("Failed to get pin 26 of the daisy!")
-            .into_pull_up_input();
-
-        let rotary_data_pin = system
-            .gpio
-            .daisy27
-            .take()
-            .expect("Failed to get pin 27 of the daisy!")
-            .into_pull_up_input();
+        let (rotary_switch_pin, rotary_clock_pin, rotary_data_pin) =
+            Daisy::encoder(&mut system.gpio)?;
 
         let mut encoder =
             encoder::RotaryEncoder::new(rotary_switch_pin, rotary_clock_pin, rotary_data_pin);
@@ -340,47 +814,51 @@ impl Sitira {
         // CONFIG GATE INPUTS
         // ==================
 
-        let gate1_pin = system
-            .gpio
-            .daisy24
-            .take()
-            .expect("Failed to get pin 24 of the daisy!")
-            .into_floating_input();
+        // gate 1 to 4 raise an interrupt on both edges, the kill gate shares its line with gate 1
+        // and gets polled
+
+        gate_edges::set_gate_order(board.gate_order);
+
+        // the selection of the EXTI ports lives in SYSCFG, which needs its clock
+        unsafe { &*pac::RCC::ptr() }
+            .apb4enr
+            .modify(|_, w| w.syscfgen().set_bit());
+
+        let (mut gate1_pin, mut gate2_pin, mut gate3_pin) = Daisy::gates(&mut system.gpio)?;
+
+        enable_gate_interrupt(&mut gate1_pin, &mut device.syscfg, &mut device.exti);
         let gate1 = BinaryInput::new(gate1_pin, InputType::ActiveLow);
 
-        let gate2_pin = system
-            .gpio
-            .daisy25
-            .take()
-            .expect("Failed to get pin 25 of the daisy!")
-            .into_floating_input();
+        enable_gate_interrupt(&mut gate2_pin, &mut device.syscfg, &mut device.exti);
         let gate2 = BinaryInput::new(gate2_pin, InputType::ActiveLow);
 
-        let gate3_pin = system
-            .gpio
-            .daisy22
-            .take()
-            .expect("Failed to get pin 22 of the daisy!")
-            .into_floating_input();
+        enable_gate_interrupt(&mut gate3_pin, &mut device.syscfg, &mut device.exti);
         let gate3 = BinaryInput::new(gate3_pin, InputType::ActiveLow);
 
-        let gate4_pin = system
-            .gpio
-            .daisy23
-            .take()
-            .expect("Failed to get pin 23 of the daisy!")
-            .into_floating_input();
+        // with direct CVs the jacks of gate 4 and the kill gate are read by ADC2
+        #[cfg(not(feature = "direct-cv"))]
+        let (gate4, kill_gate) = {
+            let mut gate4_pin = Daisy::gate4(&mut system.gpio)?;
+            enable_gate_interrupt(&mut gate4_pin, &mut device.syscfg, &mut device.exti);
 
-        let gate4 = BinaryInput::new(gate4_pin, InputType::ActiveLow);
+            let gate4 = BinaryInput::new(gate4_pin, InputType::ActiveLow);
 
-        let kill_gate_pin = system
-            .gpio
-            .daisy20
-            .take()
-            .expect("Failed to get pin 20 of the daisy!")
-            .into_floating_input();
+            let kill_gate_pin = Daisy::kill_gate(&mut system.gpio)?;
+
+            let kill_gate = BinaryInput::new(kill_gate_pin, InputType::ActiveLow);
+
+            (gate4, kill_gate)
+        };
 
-        let kill_gate = BinaryInput::new(kill_gate_pin, InputType::ActiveLow);
+        // the interrupts queue every edge for the control and for the audio task, the control
+        // task hands panel events on to the audio task through a queue of its own
+        let (gate_edges, control_edges, audio_edges) = gate_edges::split()?;
+        let (audio_events, panel_events) = PANEL_EVENTS.split().ok_or(SitiraError::Queue)?;
+
+        let gate_debouncer = GateDebouncer::new(
+            control_edges,
+            GATE_DEBOUNCE_IN_US * (CPU_FREQUENCY_IN_HZ / 1_000_000),
+        );
 
         rprintln!("Initiated gate inputs!");
 
@@ -388,29 +866,63 @@ impl Sitira {
         // CONFIG LEDs
         // ===========
 
-        let mut led1 = system
-            .gpio
-            .daisy13
-            .take()
-            .expect("Failed to get pin 13 of the daisy!")
-            .into_push_pull_output();
-        led1.set_low().unwrap();
+        // with the `expander` feature the pins of LED 1 and 2 are the I2C bus
+        #[cfg(not(feature = "expander"))]
+        let mut led1 = Daisy::led1(&mut system.gpio)?;
+        #[cfg(not(feature = "expander"))]
+        board.set_led(&mut led1, false);
+
+        // with the `midi` feature the pin of LED 2 receives MIDI
+        #[cfg(not(any(feature = "midi", feature = "expander")))]
+        let mut led2 = Daisy::led2(&mut system.gpio)?;
+        #[cfg(not(any(feature = "midi", feature = "expander")))]
+        board.set_led(&mut led2, false);
+
+        let mut led3 = Daisy::led3(&mut system.gpio)?;
+        board.set_led(&mut led3, false);
+
+        #[cfg(feature = "gate-outputs")]
+        let gate_outputs = GateOutputs::new(led1, led2)?;
+        #[cfg(not(feature = "gate-outputs"))]
+        let gate_outputs = GateOutputs;
+
+        // status LED on TIM12, only red and green are routed to PWM capable pins. The CV output
+        // needs a higher frequency to be smoothed, the LED does not mind.
+        let pwm_frequency = if cfg!(feature = "cv-output") {
+            CV_OUTPUT_PWM_FREQUENCY_IN_KHZ
+        } else {
+            RGB_LED_PWM_FREQUENCY_IN_KHZ
+        };
+        let rgb_red_pin = strap0_pin.into_alternate_af2();
+        let rgb_green_pin = strap1_pin.into_alternate_af2();
 
-        let mut led2 = system
-            .gpio
-            .daisy14
-            .take()
-            .expect("Failed to get pin 14 of the daisy!")
-            .into_push_pull_output();
-        led2.set_low().unwrap();
+        let (rgb_red, rgb_green) = device.tim12.pwm(
+            (rgb_red_pin, rgb_green_pin),
+            pwm_frequency.khz(),
+            ccdr.peripheral.TIM12,
+            &ccdr.clocks,
+        );
 
-        let mut led3 = system
-            .gpio
-            .daisy0
-            .take()
-            .expect("Failed to get pin 0 of the daisy!")
-            .into_push_pull_output();
-        led3.set_low().unwrap();
+        let status_led_config = match board.led_polarity {
+            LedPolarity::ActiveHigh => rgbled::LEDConfig::ActiveHigh,
+            LedPolarity::ActiveLow => rgbled::LEDConfig::ActiveLow,
+        };
+
+        #[cfg(not(feature = "cv-output"))]
+        let (status_led, cv_output) = (
+            rgbled::RGBLed::new(rgb_red, rgb_green, rgbled::NoChannel, status_led_config),
+            CvOutput,
+        );
+        #[cfg(feature = "cv-output")]
+        let (status_led, cv_output) = (
+            rgbled::RGBLed::new(
+                rgb_red,
+                rgbled::NoChannel,
+                rgbled::NoChannel,
+                status_led_config,
+            ),
+            crate::pwm_cv::PwmCv::new(rgb_green),
+        );
 
         rprintln!("Initiated LEDs!");
 
@@ -418,49 +930,201 @@ impl Sitira {
         // CONFIG BUTTON
         // =============
 
-        let button_pin = system
-            .gpio
-            .daisy9
-            .take()
-            .expect("Failed to get pin 9 of the daisy!")
-            .into_pull_down_input();
+        let button_pin = Daisy::button(&mut system.gpio)?;
 
         let button = BinaryInput::new(button_pin, InputType::ActiveHigh);
 
         rprintln!("Initiated button input!");
 
+        // ===========
+        // CONFIG MIDI
+        // ===========
+
+        // with the `midi` feature the pin of LED 2 receives MIDI, whose interrupt pushes the
+        // events for the control task
+        let (midi_producer, midi_events) = midi_input::split()?;
+
+        #[cfg(feature = "midi")]
+        let midi_input = {
+            let midi_pin = system
+                .gpio
+                .daisy14
+                .take()
+                .ok_or(SitiraError::PinTaken(14))?;
+
+            let mut serial = device
+                .usart1
+                .serial(
+                    (serial::NoTx, midi_pin.into_alternate_af7()),
+                    MIDI_BAUD_RATE.bps(),
+                    ccdr.peripheral.USART1,
+                    &ccdr.clocks,
+                )
+                .map_err(|_| SitiraError::Midi)?;
+
+            serial.listen(serial::Event::Rxne);
+
+            let (_, rx) = serial.split();
+
+            MidiInput::new(rx, MIDI_CHANNEL, midi_producer)
+        };
+        #[cfg(not(feature = "midi"))]
+        let midi_input = {
+            // the queue stays empty while pin 14 drives LED 2
+            let _ = midi_producer;
+            MidiInput
+        };
+
+        rprintln!("Initiated MIDI input!");
+
+        // ===============
+        // CONFIG EXPANDER
+        // ===============
+
+        // with the `expander` feature the pins of LED 1 and 2 are the I2C bus of an expansion
+        // panel, which gets polled by the control task
+        #[cfg(feature = "expander")]
+        let expander = {
+            let scl = system
+                .gpio
+                .daisy13
+                .take()
+                .ok_or(SitiraError::PinTaken(13))?
+                .into_alternate_af4()
+                .set_open_drain();
+
+            let sda = system
+                .gpio
+                .daisy14
+                .take()
+                .ok_or(SitiraError::PinTaken(14))?
+                .into_alternate_af4()
+                .set_open_drain();
+
+            let i2c = device.i2c1.i2c(
+                (scl, sda),
+                EXPANDER_I2C_FREQUENCY_IN_KHZ.khz(),
+                ccdr.peripheral.I2C1,
+                &ccdr.clocks,
+            );
+
+            Expander::detect(i2c)
+        };
+        #[cfg(not(feature = "expander"))]
+        let expander = Expander;
+
+        rprintln!("Initiated expansion panel!");
+
+        // ================
+        // CONFIG USB AUDIO
+        // ================
+
+        // with the `usb-audio` feature the micro USB port streams the output to a computer, the
+        // audio task queues it for the USB interrupt
+        let (usb_producer, usb_frames) = usb_output::split()?;
+
+        #[cfg(feature = "usb-audio")]
+        let (usb_output, usb_audio) = {
+            // libdaisy does not hand out the pins of the port, the other pins of the bank keep
+            // their configuration as long as it is not reset
+            let gpioa = device.gpioa.split_without_reset(ccdr.peripheral.GPIOA);
+
+            let usb = usb_hs::USB2::new(
+                device.otg2_global,
+                device.otg2_device,
+                device.otg2_pwrclk,
+                gpioa.pa11.into_alternate_af10(),
+                gpioa.pa12.into_alternate_af10(),
+                ccdr.peripheral.USB2OTG.kernel_clk_mux(UsbClkSel::HSI48),
+                &ccdr.clocks,
+            );
+
+            let endpoint_memory =
+                cortex_m::singleton!(: [u32; 1024] = [0; 1024]).ok_or(SitiraError::Usb)?;
+            let bus = cortex_m::singleton!(
+                : usb_device::bus::UsbBusAllocator<usb_hs::UsbBus<usb_hs::USB2>> =
+                    usb_hs::UsbBus::new(usb, endpoint_memory)
+            )
+            .ok_or(SitiraError::Usb)?;
+
+            (
+                UsbOutput::new(usb_producer),
+                UsbAudio::new(bus, usb_frames)?,
+            )
+        };
+        #[cfg(not(feature = "usb-audio"))]
+        let (usb_output, usb_audio) = {
+            // nothing takes the output without the port
+            let _ = (usb_producer, usb_frames);
+            (UsbOutput, UsbAudio)
+        };
+
+        rprintln!("Initiated USB audio!");
+
         // ===============
         // CONFIG FINISHED
         // ===============
 
-        seed_led.set_low().unwrap();
+        seed_led.set_low().map_err(|_| SitiraError::Pin)?;
         rprintln!("Sitira hardware platform is now fully setup!");
 
-        Self {
+        Ok(Self {
             audio_rate: AudioRate {
                 audio: system.audio,
                 buffer: [(0.0, 0.0); audio::BLOCK_SIZE_MAX],
+                gate_outputs,
+                cv_output,
+                gate_events: audio_edges,
+                panel_events,
+                usb_output,
             },
             control_rate: ControlRate {
                 timer2: system.timer2,
                 adc2,
                 master_volume,
                 muxed_parameters,
+                direct_cvs,
+                expander,
                 gate1,
                 gate2,
                 gate3,
+                #[cfg(not(feature = "direct-cv"))]
                 gate4,
+                #[cfg(not(feature = "direct-cv"))]
                 kill_gate,
+                gate_debouncer,
+                midi_events,
+                audio_events,
+                #[cfg(not(any(feature = "gate-outputs", feature = "expander")))]
                 led1,
+                #[cfg(not(any(
+                    feature = "gate-outputs",
+                    feature = "midi",
+                    feature = "expander"
+                )))]
                 led2,
                 led3,
                 seed_led,
+                status_led,
                 button,
+                button_gestures: GestureDetector::default(),
                 encoder,
+                board,
             },
-            visual_rate: VisualRate { lcd, timer4 },
+            visual_rate: VisualRate { timer4 },
+            display: lcd,
             sdram,
-            // sd_card,
-        }
+            sd_card,
+            gate_edges,
+            midi_input,
+            usb_audio,
+        })
     }
 }
+
+/// Lets both edges of a gate pin raise its EXTI interrupt.
+fn enable_gate_interrupt<P: ExtiPin>(pin: &mut P, syscfg: &mut pac::SYSCFG, exti: &mut pac::EXTI) {
+    pin.make_interrupt_source(syscfg);
+    pin.trigger_on_edge(exti, Edge::RisingFalling);
+    pin.enable_interrupt(exti);
+}
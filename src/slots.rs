@@ -0,0 +1,217 @@
+use core::ops::Range;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use crate::sdram::{self, AUDIO_REGION_SIZE};
+use crate::waveform_cache::WAVEFORMS;
+
+/// Number of independent audio buffers in SDRAM
+pub const SLOT_COUNT: usize = 4;
+
+/// Buffers in SDRAM, one more than slots, which keeps the previous take for undo
+pub const BUFFER_COUNT: usize = SLOT_COUNT + 1;
+
+/// Capacity of one slot in samples
+pub const SLOT_LENGTH: usize = AUDIO_REGION_SIZE / core::mem::size_of::<f32>() / BUFFER_COUNT;
+
+/// Marks that there is no take to undo
+const NO_UNDO: usize = usize::MAX;
+
+/// Marks a buffer which was not loaded from a sample file
+const NO_SAMPLE: u32 = u32::MAX;
+
+/// Samples over which the loop boundary gets crossfaded when the length changes
+const LOOP_CROSSFADE: usize = 480;
+
+/// Divides the SDRAM into equally sized slots and keeps track of their content.
+///
+/// Every slot is mapped to one of the SDRAM buffers, the remaining spare buffer holds the
+/// content a slot had before its last take. Undoing the take maps the spare buffer back, so
+/// nothing has to be copied. Every buffer remembers the sample file it was loaded from, so it
+/// follows the content through an undo. Everything is stored in atomics, so the audio task can
+/// query it without locking.
+pub struct SlotManager {
+    lengths: [AtomicUsize; SLOT_COUNT],
+    buffers: [AtomicUsize; SLOT_COUNT],
+    samples: [AtomicU32; BUFFER_COUNT],
+    active: AtomicUsize,
+    spare: AtomicUsize,
+    undo_slot: AtomicUsize,
+    undo_length: AtomicUsize,
+}
+
+impl SlotManager {
+    pub const fn new() -> Self {
+        SlotManager {
+            lengths: [
+                AtomicUsize::new(0),
+                AtomicUsize::new(0),
+                AtomicUsize::new(0),
+                AtomicUsize::new(0),
+            ],
+            buffers: [
+                AtomicUsize::new(0),
+                AtomicUsize::new(1),
+                AtomicUsize::new(2),
+                AtomicUsize::new(3),
+            ],
+            samples: [
+                AtomicU32::new(NO_SAMPLE),
+                AtomicU32::new(NO_SAMPLE),
+                AtomicU32::new(NO_SAMPLE),
+                AtomicU32::new(NO_SAMPLE),
+                AtomicU32::new(NO_SAMPLE),
+            ],
+            active: AtomicUsize::new(0),
+            spare: AtomicUsize::new(SLOT_COUNT),
+            undo_slot: AtomicUsize::new(NO_UNDO),
+            undo_length: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the slot which is currently recorded into or played back.
+    pub fn get_active(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    pub fn set_active(&self, slot: usize) {
+        self.active.store(slot % SLOT_COUNT, Ordering::Relaxed);
+    }
+
+    /// Returns the amount of valid samples in a slot.
+    pub fn get_length(&self, slot: usize) -> usize {
+        self.lengths[slot % SLOT_COUNT].load(Ordering::Relaxed)
+    }
+
+    pub fn set_length(&self, slot: usize, length: usize) {
+        self.lengths[slot % SLOT_COUNT].store(length.min(SLOT_LENGTH), Ordering::Relaxed);
+    }
+
+    /// Returns the first empty slot which is not the active one.
+    pub fn find_free(&self) -> Option<usize> {
+        let active = self.get_active();
+
+        (0..SLOT_COUNT).find(|slot| *slot != active && self.get_length(*slot) == 0)
+    }
+
+    /// Returns the SDRAM buffer a slot is currently mapped to.
+    pub fn get_buffer(&self, slot: usize) -> usize {
+        self.buffers[slot % SLOT_COUNT].load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of the sample file the content of a slot was loaded from, `None` for
+    /// recordings.
+    pub fn get_sample(&self, slot: usize) -> Option<u32> {
+        match self.samples[self.get_buffer(slot)].load(Ordering::Relaxed) {
+            NO_SAMPLE => None,
+            number => Some(number),
+        }
+    }
+
+    /// Marks the content of a slot as loaded from sample file `number`.
+    pub fn set_sample(&self, slot: usize, number: u32) {
+        self.samples[self.get_buffer(slot)].store(number, Ordering::Relaxed);
+    }
+
+    /// Moves a slot onto the spare buffer before a new take gets recorded into it. The previous
+    /// content stays in the old buffer until the next take, so it can be restored by `undo()`.
+    pub fn begin_take(&self, slot: usize) {
+        let slot = slot % SLOT_COUNT;
+        let previous =
+            self.buffers[slot].swap(self.spare.load(Ordering::Relaxed), Ordering::Relaxed);
+
+        self.spare.store(previous, Ordering::Relaxed);
+        self.samples[self.get_buffer(slot)].store(NO_SAMPLE, Ordering::Relaxed);
+        WAVEFORMS.invalidate(self.get_buffer(slot), 0);
+        self.undo_length
+            .store(self.get_length(slot), Ordering::Relaxed);
+        self.undo_slot.store(slot, Ordering::Relaxed);
+    }
+
+    /// Discards the last take and restores what the slot contained before. Returns the slot and
+    /// its restored length, `None` if there is nothing to undo.
+    ///
+    /// Must not be called while recording.
+    pub fn undo(&self) -> Option<(usize, usize)> {
+        let slot = self.undo_slot.swap(NO_UNDO, Ordering::Relaxed);
+
+        if slot == NO_UNDO {
+            return None;
+        }
+
+        let take = self.buffers[slot].swap(self.spare.load(Ordering::Relaxed), Ordering::Relaxed);
+        let length = self.undo_length.load(Ordering::Relaxed);
+
+        self.spare.store(take, Ordering::Relaxed);
+        self.set_length(slot, length);
+
+        Some((slot, length))
+    }
+}
+
+/// Returns the first sample index of a slot in SDRAM.
+pub fn get_start(slot: usize) -> usize {
+    SLOTS.get_buffer(slot) * SLOT_LENGTH
+}
+
+/// Returns the SDRAM index range of the first `length` samples of a slot.
+pub fn get_range(slot: usize, length: usize) -> Range<usize> {
+    let start = get_start(slot);
+    start..start + length.min(SLOT_LENGTH)
+}
+
+/// Returns a reading reference to the first `length` samples of a slot.
+pub fn get_slice(slot: usize, length: usize) -> Option<&'static [f32]> {
+    sdram::get_slice(get_start(slot), length.min(SLOT_LENGTH))
+}
+
+/// Changes the length of a recorded loop without clicking at the new boundary.
+///
+/// When shortening, the cut off material gets crossfaded into the start of the loop. When
+/// lengthening, the recording fades out and the rest is filled with silence.
+///
+/// ## Safety
+/// Writes into the slot, the audio task must not read beyond `recorded` meanwhile.
+pub unsafe fn adjust_loop(slot: usize, recorded: usize, length: usize) {
+    let length = length.min(SLOT_LENGTH);
+
+    WAVEFORMS.invalidate(SLOTS.get_buffer(slot), 0);
+
+    let buffer = match sdram::get_slice_mut::<f32>(get_start(slot), recorded.max(length)) {
+        Some(buffer) => buffer,
+        None => return,
+    };
+
+    if length < recorded {
+        let fade = (recorded - length).min(LOOP_CROSSFADE).min(length);
+
+        for i in 0..fade {
+            let gain = i as f32 / fade as f32;
+            buffer[i] = buffer[i] * gain + buffer[length + i] * (1.0 - gain);
+        }
+    } else if length > recorded {
+        let fade = LOOP_CROSSFADE.min(recorded);
+
+        for i in 0..fade {
+            buffer[recorded - fade + i] *= 1.0 - i as f32 / fade as f32;
+        }
+
+        buffer[recorded..length].fill(0.0);
+    }
+}
+
+/// Fills a range of an SDRAM buffer with silence, no matter which slot it is mapped to.
+///
+/// ## Safety
+/// Writes into the buffer, nothing may read or record the range meanwhile.
+pub unsafe fn erase(buffer: usize, range: Range<usize>) {
+    let start = buffer * SLOT_LENGTH + range.start.min(SLOT_LENGTH);
+    let length = range.end.min(SLOT_LENGTH).saturating_sub(range.start);
+
+    if let Some(samples) = sdram::get_slice_mut::<f32>(start, length) {
+        samples.fill(0.0);
+        WAVEFORMS.invalidate(buffer, range.start);
+    }
+}
+
+/// Content of all slots
+pub static SLOTS: SlotManager = SlotManager::new();
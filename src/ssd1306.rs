@@ -0,0 +1,178 @@
+use core::ops::Range;
+
+use display_interface::{DataFormat, WriteOnlyDataCommand};
+use display_interface_spi::SPIInterfaceNoCS;
+use stm32h7xx_hal::hal;
+
+use crate::display_driver::{DisplayDriver, RowTransfer};
+use crate::error::SitiraError;
+
+// commands of the controller
+const CONTRAST: u8 = 0x81;
+const DISPLAY_OFF: u8 = 0xAE;
+const DISPLAY_ON: u8 = 0xAF;
+const COLUMN_RANGE: u8 = 0x21;
+const PAGE_RANGE: u8 = 0x22;
+
+/// Sets the panel up for 128x64 pixels with the charge pump on and horizontal addressing, so a
+/// range of pages is written in one go
+const INIT_SEQUENCE: [&[u8]; 15] = [
+    &[DISPLAY_OFF],
+    // clock divider
+    &[0xD5, 0x80],
+    // 64 rows, starting at the first one
+    &[0xA8, 0x3F],
+    &[0xD3, 0x00],
+    &[0x40],
+    // charge pump
+    &[0x8D, 0x14],
+    // horizontal addressing
+    &[0x20, 0x00],
+    // mirrored columns and rows, as the panels are mounted
+    &[0xA1],
+    &[0xC8],
+    // wiring of the rows
+    &[0xDA, 0x12],
+    &[CONTRAST, 0xCF],
+    // precharge and deselect level
+    &[0xD9, 0xF1],
+    &[0xDB, 0x40],
+    // shows the memory, not inverted
+    &[0xA4, 0xA6],
+    &[DISPLAY_ON],
+];
+
+/// Columns of the panel
+const WIDTH: usize = 128;
+/// Rows of one page, every byte holds a column of them
+const PAGE_HEIGHT: usize = 8;
+/// Luma from 0 to 255 above which a pixel lights up
+const LIT_LUMA: u32 = 64;
+
+/// Monochrome SSD1306 OLED with 128x64 pixels on SPI.
+///
+/// Rows get converted from the framebuffer into pages of lit pixels, so they are written by the
+/// driver instead of the DMA. A frame is just 1 kB, which takes about a millisecond.
+pub struct Ssd1306<SPI, DC, CS, RESET> {
+    interface: SPIInterfaceNoCS<SPI, DC>,
+    _cs: CS,
+    _reset: RESET,
+    sleeping: bool,
+}
+
+impl<SPI, DC, CS, RESET> Ssd1306<SPI, DC, CS, RESET>
+where
+    SPI: hal::blocking::spi::Write<u8>,
+    DC: hal::digital::v2::OutputPin,
+    CS: hal::digital::v2::OutputPin,
+    RESET: hal::digital::v2::OutputPin,
+{
+    pub fn new<DELAY>(
+        spi: SPI,
+        dc: DC,
+        mut cs: CS,
+        mut reset: RESET,
+        mut delay: DELAY,
+    ) -> Result<Self, SitiraError>
+    where
+        DELAY: libdaisy::prelude::_embedded_hal_blocking_delay_DelayMs<u16>,
+    {
+        cs.set_low().ok();
+
+        reset.set_low().ok();
+        delay.delay_ms(1);
+        reset.set_high().ok();
+        delay.delay_ms(1);
+
+        let mut panel = Ssd1306 {
+            interface: SPIInterfaceNoCS::new(spi, dc),
+            _cs: cs,
+            _reset: reset,
+            sleeping: false,
+        };
+
+        for commands in INIT_SEQUENCE {
+            panel.commands(commands)?;
+        }
+
+        Ok(panel)
+    }
+
+    fn commands(&mut self, commands: &[u8]) -> Result<(), SitiraError> {
+        self.interface
+            .send_commands(DataFormat::U8(commands))
+            .map_err(|_| SitiraError::Display)
+    }
+}
+
+impl<SPI, DC, CS, RESET> DisplayDriver for Ssd1306<SPI, DC, CS, RESET>
+where
+    SPI: hal::blocking::spi::Write<u8>,
+    DC: hal::digital::v2::OutputPin,
+    CS: hal::digital::v2::OutputPin,
+    RESET: hal::digital::v2::OutputPin,
+{
+    const WIDTH: usize = WIDTH;
+    const HEIGHT: usize = 64;
+    const SPI_CLOCK_IN_MHZ: u32 = 10;
+
+    /// An OLED has no backlight, the contrast dims it and darkness switches it off.
+    fn set_brightness(&mut self, brightness: u8) -> Result<(), SitiraError> {
+        match brightness {
+            0 => self.commands(&[DISPLAY_OFF]),
+            _ if self.sleeping => self.commands(&[CONTRAST, brightness]),
+            _ => self.commands(&[CONTRAST, brightness, DISPLAY_ON]),
+        }
+    }
+
+    fn set_sleep_mode(&mut self, sleep: bool) -> Result<(), SitiraError> {
+        self.sleeping = sleep;
+        self.commands(&[if sleep { DISPLAY_OFF } else { DISPLAY_ON }])
+    }
+
+    fn write_rows(
+        &mut self,
+        rows: Range<usize>,
+        pixels: &[u8],
+    ) -> Result<RowTransfer, SitiraError> {
+        let first_page = rows.start / PAGE_HEIGHT;
+        let last_page = (rows.end - 1) / PAGE_HEIGHT;
+
+        self.commands(&[
+            COLUMN_RANGE,
+            0,
+            WIDTH as u8 - 1,
+            PAGE_RANGE,
+            first_page as u8,
+            last_page as u8,
+        ])?;
+
+        let mut page = [0; WIDTH];
+
+        for rows in pixels.chunks_exact(WIDTH * PAGE_HEIGHT * 2) {
+            for (x, column) in page.iter_mut().enumerate() {
+                *column = (0..PAGE_HEIGHT).fold(0, |bits, y| {
+                    let index = (y * WIDTH + x) * 2;
+                    let raw = u16::from_be_bytes([rows[index], rows[index + 1]]);
+
+                    bits | ((is_lit(raw) as u8) << y)
+                });
+            }
+
+            self.interface
+                .send_data(DataFormat::U8(&page))
+                .map_err(|_| SitiraError::Display)?;
+        }
+
+        Ok(RowTransfer::Written)
+    }
+}
+
+/// Returns `true` if an RGB565 pixel is bright enough to light up.
+fn is_lit(raw: u16) -> bool {
+    let red = ((raw >> 11) & 0x1F) as u32 * 255 / 31;
+    let green = ((raw >> 5) & 0x3F) as u32 * 255 / 63;
+    let blue = (raw & 0x1F) as u32 * 255 / 31;
+
+    (red * 299 + green * 587 + blue * 114) / 1000 > LIT_LUMA
+}
@@ -0,0 +1,130 @@
+use core::ops::Range;
+
+use display_interface::{DataFormat, WriteOnlyDataCommand};
+use display_interface_spi::SPIInterfaceNoCS;
+use stm32h7xx_hal::hal;
+
+use crate::display_driver::{DisplayDriver, RowTransfer};
+use crate::error::SitiraError;
+
+// commands of the controller
+const SOFTWARE_RESET: u8 = 0x01;
+const SLEEP_IN: u8 = 0x10;
+const SLEEP_OUT: u8 = 0x11;
+const NORMAL_MODE: u8 = 0x13;
+const INVERSION_ON: u8 = 0x21;
+const DISPLAY_ON: u8 = 0x29;
+const COLUMN_ADDRESS: u8 = 0x2A;
+const ROW_ADDRESS: u8 = 0x2B;
+const MEMORY_WRITE: u8 = 0x2C;
+const MEMORY_ACCESS: u8 = 0x36;
+const PIXEL_FORMAT: u8 = 0x3A;
+const BRIGHTNESS: u8 = 0x51;
+const DISPLAY_CONTROL: u8 = 0x53;
+
+/// Row and column exchange plus column mirroring turn the panel to landscape
+const LANDSCAPE: u8 = 0x60;
+/// 16 bits per pixel on the RGB and the MCU interface
+const RGB565: u8 = 0x55;
+/// Enables the brightness register and the backlight control
+const BRIGHTNESS_CONTROL: u8 = 0x24;
+
+/// ST7789 panel with 320x240 pixels, driven in landscape orientation like the ILI9341.
+///
+/// Both controllers share the MIPI command set for the drawing window and the memory write, so
+/// the framebuffer gets streamed the same way. IPS panels with this controller show inverted
+/// colors unless the inversion is switched on.
+pub struct St7789<SPI, DC, CS, RESET> {
+    interface: SPIInterfaceNoCS<SPI, DC>,
+    _cs: CS,
+    _reset: RESET,
+}
+
+impl<SPI, DC, CS, RESET> St7789<SPI, DC, CS, RESET>
+where
+    SPI: hal::blocking::spi::Write<u8>,
+    DC: hal::digital::v2::OutputPin,
+    CS: hal::digital::v2::OutputPin,
+    RESET: hal::digital::v2::OutputPin,
+{
+    pub fn new<DELAY>(
+        spi: SPI,
+        dc: DC,
+        mut cs: CS,
+        mut reset: RESET,
+        mut delay: DELAY,
+    ) -> Result<Self, SitiraError>
+    where
+        DELAY: libdaisy::prelude::_embedded_hal_blocking_delay_DelayMs<u16>,
+    {
+        cs.set_low().ok();
+
+        reset.set_low().ok();
+        delay.delay_ms(10);
+        reset.set_high().ok();
+        delay.delay_ms(120);
+
+        let mut panel = St7789 {
+            interface: SPIInterfaceNoCS::new(spi, dc),
+            _cs: cs,
+            _reset: reset,
+        };
+
+        panel.command(SOFTWARE_RESET, &[])?;
+        delay.delay_ms(150);
+        panel.command(SLEEP_OUT, &[])?;
+        delay.delay_ms(120);
+        panel.command(PIXEL_FORMAT, &[RGB565])?;
+        panel.command(MEMORY_ACCESS, &[LANDSCAPE])?;
+        panel.command(INVERSION_ON, &[])?;
+        panel.command(NORMAL_MODE, &[])?;
+        panel.command(DISPLAY_CONTROL, &[BRIGHTNESS_CONTROL])?;
+        panel.command(DISPLAY_ON, &[])?;
+
+        Ok(panel)
+    }
+
+    fn command(&mut self, command: u8, parameters: &[u8]) -> Result<(), SitiraError> {
+        self.interface
+            .send_commands(DataFormat::U8(&[command]))
+            .and_then(|_| self.interface.send_data(DataFormat::U8(parameters)))
+            .map_err(|_| SitiraError::Display)
+    }
+}
+
+impl<SPI, DC, CS, RESET> DisplayDriver for St7789<SPI, DC, CS, RESET>
+where
+    SPI: hal::blocking::spi::Write<u8>,
+    DC: hal::digital::v2::OutputPin,
+    CS: hal::digital::v2::OutputPin,
+    RESET: hal::digital::v2::OutputPin,
+{
+    const WIDTH: usize = 320;
+    const HEIGHT: usize = 240;
+    const SPI_CLOCK_IN_MHZ: u32 = 50;
+
+    fn set_brightness(&mut self, brightness: u8) -> Result<(), SitiraError> {
+        self.command(BRIGHTNESS, &[brightness])
+    }
+
+    fn set_sleep_mode(&mut self, sleep: bool) -> Result<(), SitiraError> {
+        self.command(if sleep { SLEEP_IN } else { SLEEP_OUT }, &[])
+    }
+
+    fn write_rows(
+        &mut self,
+        rows: Range<usize>,
+        _pixels: &[u8],
+    ) -> Result<RowTransfer, SitiraError> {
+        let [x1_high, x1_low] = (Self::WIDTH as u16 - 1).to_be_bytes();
+        let [y0_high, y0_low] = (rows.start as u16).to_be_bytes();
+        let [y1_high, y1_low] = (rows.end as u16 - 1).to_be_bytes();
+
+        self.command(COLUMN_ADDRESS, &[0, 0, x1_high, x1_low])?;
+        self.command(ROW_ADDRESS, &[y0_high, y0_low, y1_high, y1_low])?;
+        // the data line stays high after the empty parameters, so the pixels can follow
+        self.command(MEMORY_WRITE, &[])?;
+
+        Ok(RowTransfer::Dma)
+    }
+}
@@ -0,0 +1,190 @@
+use core::cell::RefCell;
+
+use embedded_sdmmc::{
+    Block, BlockCount, BlockDevice, BlockIdx, Controller, DirEntry, Directory, File, Mode,
+    TimeSource, Timestamp, Volume, VolumeIdx,
+};
+use sitira_core::card::{CardError, CARD};
+use stm32h7xx_hal::{prelude::*, sdmmc};
+
+use crate::config::SD_CARD_FREQUENCY_IN_MHZ;
+use crate::sitira::SdCard;
+
+/// Errors of the file system and the card below it
+pub type Error = embedded_sdmmc::Error<sdmmc::Error>;
+
+/// Adapts the SDMMC peripheral to the block interface of the file system.
+pub struct SdBlockDevice {
+    // the file system only hands out shared references
+    card: RefCell<SdCard>,
+}
+
+impl BlockDevice for SdBlockDevice {
+    type Error = sdmmc::Error;
+
+    fn read(
+        &self,
+        blocks: &mut [Block],
+        start_block_idx: BlockIdx,
+        _reason: &str,
+    ) -> Result<(), Self::Error> {
+        let mut card = self.card.borrow_mut();
+
+        for (index, block) in blocks.iter_mut().enumerate() {
+            card.read_block(start_block_idx.0 + index as u32, &mut block.contents)?;
+        }
+
+        Ok(())
+    }
+
+    fn write(&self, blocks: &[Block], start_block_idx: BlockIdx) -> Result<(), Self::Error> {
+        let mut card = self.card.borrow_mut();
+
+        for (index, block) in blocks.iter().enumerate() {
+            card.write_block(start_block_idx.0 + index as u32, &block.contents)?;
+        }
+
+        Ok(())
+    }
+
+    fn num_blocks(&self) -> Result<BlockCount, Self::Error> {
+        let size = self.card.borrow().card()?.size();
+
+        Ok(BlockCount((size / Block::LEN as u64) as u32))
+    }
+}
+
+/// There is no real time clock, so every file gets the same date.
+pub struct FixedTime;
+
+impl TimeSource for FixedTime {
+    fn get_timestamp(&self) -> Timestamp {
+        Timestamp {
+            year_since_1970: 52,
+            zero_indexed_month: 0,
+            zero_indexed_day: 0,
+            hours: 0,
+            minutes: 0,
+            seconds: 0,
+        }
+    }
+}
+
+/// FAT file system in the root directory of the first partition of the SD card.
+///
+/// Files are addressed by their 8.3 name.
+pub struct Storage {
+    controller: Controller<SdBlockDevice, FixedTime>,
+    volume: Volume,
+    root: Directory,
+}
+
+impl Storage {
+    /// Initializes `card` and mounts its first partition. Hands the card back on failure, so it
+    /// can be tried again, and sets the state of the card either way.
+    pub fn mount(mut card: SdCard) -> Result<Self, (CardError, SdCard)> {
+        if card.init_card(SD_CARD_FREQUENCY_IN_MHZ.mhz()).is_err() {
+            CARD.set(Err(CardError::Missing));
+            return Err((CardError::Missing, card));
+        }
+
+        let mut controller = Controller::new(
+            SdBlockDevice {
+                card: RefCell::new(card),
+            },
+            FixedTime,
+        );
+
+        let mounted = controller
+            .get_volume(VolumeIdx(0))
+            .and_then(|volume| Ok((controller.open_root_dir(&volume)?, volume)));
+
+        match mounted {
+            Ok((root, volume)) => {
+                CARD.set(Ok(()));
+
+                Ok(Storage {
+                    controller,
+                    volume,
+                    root,
+                })
+            }
+            Err(_) => {
+                CARD.set(Err(CardError::Unmountable));
+
+                let (device, _) = controller.free();
+                Err((CardError::Unmountable, device.card.into_inner()))
+            }
+        }
+    }
+
+    /// Gives up the file system and hands the card back, e.g. to mount it again after it failed.
+    pub fn unmount(self) -> SdCard {
+        let (device, _) = self.controller.free();
+
+        device.card.into_inner()
+    }
+
+    /// Looks up a file, fails if it does not exist.
+    pub fn find(&mut self, name: &str) -> Result<DirEntry, Error> {
+        let result = self
+            .controller
+            .find_directory_entry(&self.volume, &self.root, name);
+
+        check(result)
+    }
+
+    pub fn exists(&mut self, name: &str) -> bool {
+        self.find(name).is_ok()
+    }
+
+    /// Opens a file for reading.
+    pub fn open(&mut self, name: &str) -> Result<File, Error> {
+        let result =
+            self.controller
+                .open_file_in_dir(&mut self.volume, &self.root, name, Mode::ReadOnly);
+
+        check(result)
+    }
+
+    /// Creates a file for writing, an existing file gets truncated.
+    pub fn create(&mut self, name: &str) -> Result<File, Error> {
+        let result = self.controller.open_file_in_dir(
+            &mut self.volume,
+            &self.root,
+            name,
+            Mode::ReadWriteCreateOrTruncate,
+        );
+
+        check(result)
+    }
+
+    /// Reads up to `buffer.len()` bytes, returns how many were read.
+    pub fn read(&mut self, file: &mut File, buffer: &mut [u8]) -> Result<usize, Error> {
+        check(self.controller.read(&self.volume, file, buffer))
+    }
+
+    /// Moves the read position to `offset` bytes from the start of the file.
+    pub fn seek(&mut self, file: &mut File, offset: u32) -> Result<(), Error> {
+        file.seek_from_start(offset)
+            .map_err(|_| Error::FormatError("Seek beyond the end of the file"))
+    }
+
+    pub fn write(&mut self, file: &mut File, buffer: &[u8]) -> Result<usize, Error> {
+        check(self.controller.write(&mut self.volume, file, buffer))
+    }
+
+    /// Closes a file, which also updates its directory entry.
+    pub fn close(&mut self, file: File) -> Result<(), Error> {
+        check(self.controller.close_file(&self.volume, file))
+    }
+}
+
+/// Marks the card as failed if it did not answer, missing files and the like leave it usable.
+fn check<T>(result: Result<T, Error>) -> Result<T, Error> {
+    if let Err(Error::DeviceError(_)) = result {
+        CARD.set(Err(CardError::Failed));
+    }
+
+    result
+}
@@ -0,0 +1,25 @@
+use sitira_core::theme::{Theme, THEME_NAME};
+
+use crate::storage::{Error, Storage};
+
+/// Reads the theme which was selected last from the SD card.
+pub fn load(storage: &mut Storage) -> Result<Theme, Error> {
+    let mut file = storage.open(THEME_NAME)?;
+    let mut byte = [0; 1];
+    let read = storage.read(&mut file, &mut byte)?;
+    storage.close(file)?;
+
+    match read {
+        1 => Theme::from_byte(byte[0]).ok_or(Error::FormatError("Not a theme")),
+        _ => Err(Error::FormatError("Empty theme file")),
+    }
+}
+
+/// Writes the selected theme to the SD card, replacing the last one.
+pub fn save(storage: &mut Storage, theme: Theme) -> Result<(), Error> {
+    let mut file = storage.create(THEME_NAME)?;
+    let written = storage.write(&mut file, &[theme.to_byte()]);
+    storage.close(file)?;
+
+    written.map(|_| ())
+}
@@ -0,0 +1,234 @@
+use core::arch::asm;
+use core::ptr::{addr_of, read_volatile, write_volatile};
+
+use crate::storage::{self, Storage};
+
+/// File on the SD card which holds a new firmware image, followed by the CRC-32 of the image
+pub const FIRMWARE_NAME: &str = "SITIRA.BIN";
+
+const FLASH_START: usize = 0x0800_0000;
+/// The whole internal flash is a single sector
+const FLASH_SIZE: usize = 128 * 1024;
+/// Smallest unit the flash gets programmed in
+const FLASH_WORD_LENGTH: usize = 32;
+const CHECKSUM_LENGTH: usize = 4;
+/// Largest file which can hold an image
+pub const MAX_FILE_LENGTH: usize = FLASH_SIZE + CHECKSUM_LENGTH;
+/// Where the flashing code runs from, ITCM behind the null pointer
+const ITCM_FLASHER: usize = 0x0000_0100;
+
+// registers of the flash bank
+const FLASH_KEYR: *mut u32 = 0x5200_2004 as *mut u32;
+const FLASH_CR: *mut u32 = 0x5200_200C as *mut u32;
+const FLASH_SR: *mut u32 = 0x5200_2010 as *mut u32;
+const FLASH_CCR: *mut u32 = 0x5200_2014 as *mut u32;
+const FLASH_KEYS: [u32; 2] = [0x4567_0123, 0xCDEF_89AB];
+const CR_LOCK: u32 = 1 << 0;
+const CR_PG: u32 = 1 << 1;
+const CR_SER: u32 = 1 << 2;
+const CR_PSIZE_X64: u32 = 0b11 << 4;
+const CR_START: u32 = 1 << 7;
+const SR_BSY: u32 = 1 << 0;
+const SR_QW: u32 = 1 << 2;
+const CCR_ALL: u32 = 0x0FEF_0000;
+const SCB_AIRCR: *mut u32 = 0xE000_ED0C as *mut u32;
+const AIRCR_SYSRESETREQ: u32 = 0x05FA_0004;
+
+#[derive(Debug)]
+pub enum UpdateError {
+    Storage(storage::Error),
+    /// The file is empty or larger than the flash
+    InvalidLength,
+    ChecksumMismatch,
+    /// The vector table does not point into RAM and flash, so it is no image for this device
+    InvalidImage,
+}
+
+impl UpdateError {
+    pub fn description(&self) -> &'static str {
+        match self {
+            UpdateError::Storage(_) => "Could not read SITIRA.BIN",
+            UpdateError::InvalidLength => "SITIRA.BIN has a wrong size",
+            UpdateError::ChecksumMismatch => "SITIRA.BIN is corrupted",
+            UpdateError::InvalidImage => "SITIRA.BIN is no firmware",
+        }
+    }
+}
+
+impl From<storage::Error> for UpdateError {
+    fn from(error: storage::Error) -> Self {
+        UpdateError::Storage(error)
+    }
+}
+
+/// Bitwise CRC-32 as used by zip and PNG.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0_u32;
+
+    for byte in data {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+/// Reads the firmware file into `buffer` and verifies it. Returns the image, padded to whole
+/// flash words.
+pub fn stage<'a>(storage: &mut Storage, buffer: &'a mut [u8]) -> Result<&'a [u8], UpdateError> {
+    let mut file = storage.open(FIRMWARE_NAME)?;
+    let length = file.length() as usize;
+
+    if length <= CHECKSUM_LENGTH || length > MAX_FILE_LENGTH || length > buffer.len() {
+        storage.close(file)?;
+        return Err(UpdateError::InvalidLength);
+    }
+
+    let mut read = 0;
+
+    while read < length {
+        match storage.read(&mut file, &mut buffer[read..length])? {
+            0 => break,
+            bytes => read += bytes,
+        }
+    }
+
+    storage.close(file)?;
+
+    if read != length {
+        return Err(UpdateError::InvalidLength);
+    }
+
+    let image_length = length - CHECKSUM_LENGTH;
+    let mut checksum = [0; CHECKSUM_LENGTH];
+    checksum.copy_from_slice(&buffer[image_length..length]);
+
+    if crc32(&buffer[..image_length]) != u32::from_le_bytes(checksum) {
+        return Err(UpdateError::ChecksumMismatch);
+    }
+
+    if !is_vector_table_valid(&buffer[..image_length]) {
+        return Err(UpdateError::InvalidImage);
+    }
+
+    // erased flash reads as 0xFF, so padding with it leaves no trace
+    let padded = (image_length + FLASH_WORD_LENGTH - 1) / FLASH_WORD_LENGTH * FLASH_WORD_LENGTH;
+    buffer[image_length..padded].fill(0xFF);
+
+    Ok(&buffer[..padded])
+}
+
+/// Checks that the initial stack pointer lies in RAM and the reset handler in flash.
+fn is_vector_table_valid(image: &[u8]) -> bool {
+    if image.len() < 8 {
+        return false;
+    }
+
+    let word = |index: usize| {
+        u32::from_le_bytes([
+            image[index * 4],
+            image[index * 4 + 1],
+            image[index * 4 + 2],
+            image[index * 4 + 3],
+        ]) as usize
+    };
+
+    let stack = word(0);
+    let reset = word(1);
+
+    let stack_valid = (0x2000_0000..=0x2002_0000).contains(&stack)
+        || (0x2400_0000..=0x2408_0000).contains(&stack);
+    let reset_valid = (FLASH_START..FLASH_START + image.len()).contains(&reset) && reset & 1 == 1;
+
+    stack_valid && reset_valid
+}
+
+/// Returns `true` if the flash already holds `image`, so an update is not needed.
+pub fn is_installed(image: &[u8]) -> bool {
+    // SAFETY: the image is never larger than the flash
+    let flash = unsafe { core::slice::from_raw_parts(FLASH_START as *const u8, image.len()) };
+
+    flash == image
+}
+
+/// Replaces the running firmware with `image` and resets the device.
+///
+/// ## Safety
+/// `image` has to be verified by `stage()`. A power loss while flashing leaves the device without
+/// firmware, it then needs a debug probe again.
+pub unsafe fn install(image: &[u8]) -> ! {
+    // provided by the linker for the section of `flash_and_reset()`
+    extern "C" {
+        static __start_flasher: u8;
+        static __stop_flasher: u8;
+    }
+
+    cortex_m::interrupt::disable();
+
+    let mut core = cortex_m::Peripherals::steal();
+    core.SCB.disable_dcache(&mut core.CPUID);
+
+    // the code which erases the flash can not run from it, so it gets copied to ITCM
+    let start = addr_of!(__start_flasher);
+    let length = addr_of!(__stop_flasher) as usize - start as usize;
+    core::ptr::copy_nonoverlapping(start, ITCM_FLASHER as *mut u8, length);
+
+    asm!("dsb", "isb");
+
+    // keeps the thumb bit of the function address
+    let entry = ITCM_FLASHER + (flash_and_reset as usize - start as usize);
+    let flasher: unsafe extern "C" fn(*const u32, usize) -> ! = core::mem::transmute(entry);
+
+    flasher(image.as_ptr() as *const u32, image.len() / 4)
+}
+
+/// Erases the flash, programs `words` words from `image` and resets.
+///
+/// Gets copied to ITCM and runs from there while the flash is gone, so it has to be position
+/// independent: everything in here has to be inlined and must not panic.
+#[link_section = "flasher"]
+#[inline(never)]
+unsafe extern "C" fn flash_and_reset(image: *const u32, words: usize) -> ! {
+    write_volatile(FLASH_KEYR, FLASH_KEYS[0]);
+    write_volatile(FLASH_KEYR, FLASH_KEYS[1]);
+    write_volatile(FLASH_CCR, CCR_ALL);
+
+    write_volatile(FLASH_CR, CR_SER | CR_PSIZE_X64);
+    write_volatile(FLASH_CR, CR_SER | CR_PSIZE_X64 | CR_START);
+
+    while read_volatile(FLASH_SR) & (SR_BSY | SR_QW) != 0 {}
+
+    write_volatile(FLASH_CR, CR_PG | CR_PSIZE_X64);
+
+    let mut index = 0_usize;
+
+    // every flash word gets programmed once its last word has been written
+    while index < words {
+        write_volatile(
+            (FLASH_START as *mut u32).wrapping_add(index),
+            read_volatile(image.wrapping_add(index)),
+        );
+        index = index.wrapping_add(1);
+
+        if index % (FLASH_WORD_LENGTH / 4) == 0 {
+            asm!("dsb");
+
+            while read_volatile(FLASH_SR) & (SR_BSY | SR_QW) != 0 {}
+        }
+    }
+
+    write_volatile(FLASH_CR, CR_LOCK);
+
+    asm!("dsb");
+    write_volatile(SCB_AIRCR, AIRCR_SYSRESETREQ);
+    asm!("dsb");
+
+    loop {}
+}
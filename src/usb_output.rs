@@ -0,0 +1,169 @@
+use sitira_core::spsc::{Consumer, Producer, SpscQueue};
+
+use crate::error::SitiraError;
+
+#[cfg(feature = "usb-audio")]
+use sitira_core::usb_audio::RateMatcher;
+#[cfg(feature = "usb-audio")]
+use stm32h7xx_hal::usb_hs::{UsbBus, USB2};
+#[cfg(feature = "usb-audio")]
+use usb_device::{
+    bus::UsbBusAllocator,
+    device::{UsbDevice, UsbDeviceBuilder, UsbDeviceState, UsbVidPid},
+};
+#[cfg(feature = "usb-audio")]
+use usbd_audio::{AudioClass, AudioClassBuilder, Format, StreamConfig, TerminalType};
+
+#[cfg(feature = "usb-audio")]
+use crate::config::{AUDIO_SAMPLE_RATE, USB_AUDIO_LATENCY_IN_MS, USB_PID, USB_VID};
+
+/// Frames the queue from the audio task to the USB interrupt holds, more than the latency and
+/// the largest block together
+pub const USB_QUEUE_FRAMES: usize = 2048;
+
+/// Stereo frames of the output, as 16 bit samples
+type UsbFrame = (i16, i16);
+
+pub type UsbProducer = Producer<'static, UsbFrame, USB_QUEUE_FRAMES>;
+pub type UsbConsumer = Consumer<'static, UsbFrame, USB_QUEUE_FRAMES>;
+
+/// Output of the audio task on its way to the host.
+static USB_FRAMES: SpscQueue<UsbFrame, USB_QUEUE_FRAMES> = SpscQueue::new();
+
+/// Splits the queue of the output, returns the producing end for the audio task and the frames
+/// for the USB interrupt. Can only be called once.
+pub fn split() -> Result<(UsbProducer, UsbConsumer), SitiraError> {
+    USB_FRAMES.split().ok_or(SitiraError::Queue)
+}
+
+/// Sample rates the stream offers, only the one of the codec
+#[cfg(feature = "usb-audio")]
+const SAMPLE_RATES: [u32; 1] = [AUDIO_SAMPLE_RATE as u32];
+/// Bytes of the largest packet, a millisecond and one frame of two 16 bit samples
+#[cfg(feature = "usb-audio")]
+const MAX_PACKET_BYTES: usize = (AUDIO_SAMPLE_RATE / 1000 + 1) * 4;
+
+/// Hands the output of the audio task on to the USB interrupt.
+#[cfg(feature = "usb-audio")]
+pub struct UsbOutput {
+    frames: UsbProducer,
+}
+
+#[cfg(feature = "usb-audio")]
+impl UsbOutput {
+    pub fn new(frames: UsbProducer) -> Self {
+        UsbOutput { frames }
+    }
+
+    /// Queues a frame of the output, it gets dropped while no host takes the stream.
+    pub fn push(&mut self, left: f32, right: f32) {
+        let left = (left.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        let right = (right.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+
+        self.frames.push((left, right));
+    }
+}
+
+/// Stands in for the USB output without the `usb-audio` feature.
+#[cfg(not(feature = "usb-audio"))]
+pub struct UsbOutput;
+
+#[cfg(not(feature = "usb-audio"))]
+impl UsbOutput {
+    pub fn push(&mut self, _left: f32, _right: f32) {}
+}
+
+/// Class compliant USB audio device on the micro USB port, which streams the output to the host.
+#[cfg(feature = "usb-audio")]
+pub struct UsbAudio {
+    device: UsbDevice<'static, UsbBus<USB2>>,
+    class: AudioClass<'static, UsbBus<USB2>>,
+    frames: UsbConsumer,
+    matcher: RateMatcher,
+    packet: [u8; MAX_PACKET_BYTES],
+    /// Bytes of the packet which waits for the endpoint, none once it has been sent
+    pending: usize,
+}
+
+#[cfg(feature = "usb-audio")]
+impl UsbAudio {
+    pub fn new(
+        bus: &'static UsbBusAllocator<UsbBus<USB2>>,
+        frames: UsbConsumer,
+    ) -> Result<Self, SitiraError> {
+        let stream =
+            StreamConfig::new_discrete(Format::S16le, 2, &SAMPLE_RATES, TerminalType::InMicrophone)
+                .map_err(|_| SitiraError::Usb)?;
+
+        let class = AudioClassBuilder::new()
+            .input(stream)
+            .build(bus)
+            .map_err(|_| SitiraError::Usb)?;
+
+        let device = UsbDeviceBuilder::new(bus, UsbVidPid(USB_VID, USB_PID))
+            .manufacturer("Backtail")
+            .product("Sitira")
+            .serial_number("1")
+            .max_packet_size_0(64)
+            .build();
+
+        Ok(UsbAudio {
+            device,
+            class,
+            frames,
+            matcher: RateMatcher::new(
+                AUDIO_SAMPLE_RATE,
+                USB_AUDIO_LATENCY_IN_MS * AUDIO_SAMPLE_RATE / 1000,
+            ),
+            packet: [0; MAX_PACKET_BYTES],
+            pending: 0,
+        })
+    }
+
+    /// Answers the host and sends the next packet of the output once the last one has gone out.
+    pub fn on_interrupt(&mut self) {
+        self.device.poll(&mut [&mut self.class]);
+
+        if self.device.state() != UsbDeviceState::Configured {
+            return;
+        }
+
+        if self.pending == 0 {
+            self.pending = self.fill_packet();
+        }
+
+        // the endpoint is busy until the host has taken the last packet
+        if self.class.write(&self.packet[..self.pending]).is_ok() {
+            self.pending = 0;
+        }
+    }
+
+    /// Takes as many frames as the drift of the clocks asks for, returns the bytes of the packet.
+    fn fill_packet(&mut self) -> usize {
+        let (queued, length) = self.matcher.plan(self.frames.len());
+        let length = length.min(self.matcher.max_frames());
+
+        for (index, bytes) in self.packet.chunks_exact_mut(4).take(length).enumerate() {
+            let (left, right) = if index < queued {
+                self.frames.pop().unwrap_or((0, 0))
+            } else {
+                (0, 0)
+            };
+
+            bytes[..2].copy_from_slice(&left.to_le_bytes());
+            bytes[2..].copy_from_slice(&right.to_le_bytes());
+        }
+
+        length * 4
+    }
+}
+
+/// Stands in for the USB audio device without the `usb-audio` feature, its interrupt is never
+/// enabled.
+#[cfg(not(feature = "usb-audio"))]
+pub struct UsbAudio;
+
+#[cfg(not(feature = "usb-audio"))]
+impl UsbAudio {
+    pub fn on_interrupt(&mut self) {}
+}
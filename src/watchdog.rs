@@ -0,0 +1,174 @@
+use core::fmt;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use stm32h7xx_hal::pac;
+
+/// Start of the backup SRAM, which keeps its content through a reset
+const BACKUP_RAM_ADDRESS: usize = 0x3880_0000;
+/// Marks a valid record in backup RAM
+const RECORD_MAGIC: u32 = 0x5717_D06D;
+
+const KEY_START: u32 = 0xCCCC;
+const KEY_UNLOCK: u32 = 0x5555;
+const KEY_REFRESH: u32 = 0xAAAA;
+/// LSI divided by 64 counts at 500 Hz
+const PRESCALER_DIV_64: u32 = 4;
+const TICKS_PER_MS: f32 = 0.5;
+const MAX_RELOAD: u32 = 0xFFF;
+
+/// Reset flags in RCC_RSR
+const RSR_IWDG1RSTF: u32 = 1 << 26;
+const RSR_RMVF: u32 = 1 << 16;
+/// Freezes IWDG1 while the core is halted by a debugger
+const DBGMCU_APB4FZ1_IWDG1: u32 = 1 << 18;
+
+/// Tasks which have to prove that they are still alive.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Task {
+    Audio = 1,
+    Control = 2,
+    Display = 4,
+}
+
+const TASKS: [Task; 3] = [Task::Audio, Task::Control, Task::Display];
+const ALL_TASKS: u8 = Task::Audio as u8 | Task::Control as u8 | Task::Display as u8;
+
+/// Tasks which have beaten since the watchdog was fed the last time
+static HEARTBEATS: AtomicU8 = AtomicU8::new(0);
+
+/// Posts a heartbeat of a task.
+pub fn beat(task: Task) {
+    HEARTBEATS.fetch_or(task as u8, Ordering::Relaxed);
+}
+
+/// Independent watchdog which is only fed while every task keeps beating.
+///
+/// Tasks which did not beat since the last feed are recorded in backup RAM, so the reason of a
+/// watchdog reset can be reported after the reboot.
+pub struct Watchdog;
+
+impl Watchdog {
+    /// Starts the IWDG. Once started, it cannot be stopped anymore.
+    pub fn start(timeout_in_ms: u32) -> Self {
+        let iwdg = unsafe { &*pac::IWDG::ptr() };
+        let dbgmcu = unsafe { &*pac::DBGMCU::ptr() };
+
+        enable_backup_ram();
+
+        dbgmcu
+            .apb4fz1
+            .modify(|r, w| unsafe { w.bits(r.bits() | DBGMCU_APB4FZ1_IWDG1) });
+
+        let reload = ((timeout_in_ms as f32 * TICKS_PER_MS) as u32).clamp(1, MAX_RELOAD);
+
+        iwdg.kr.write(|w| unsafe { w.bits(KEY_START) });
+        iwdg.kr.write(|w| unsafe { w.bits(KEY_UNLOCK) });
+        iwdg.pr.write(|w| unsafe { w.bits(PRESCALER_DIV_64) });
+        iwdg.rlr.write(|w| unsafe { w.bits(reload) });
+
+        // the new values are only taken over after some LSI cycles
+        while iwdg.sr.read().bits() != 0 {}
+
+        iwdg.kr.write(|w| unsafe { w.bits(KEY_REFRESH) });
+
+        Watchdog
+    }
+
+    /// Feeds the watchdog if every task has beaten since the last feed. Otherwise the missing
+    /// tasks get recorded, in case the watchdog resets before they recover.
+    pub fn service(&mut self) {
+        if HEARTBEATS
+            .compare_exchange(ALL_TASKS, 0, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            let iwdg = unsafe { &*pac::IWDG::ptr() };
+            iwdg.kr.write(|w| unsafe { w.bits(KEY_REFRESH) });
+
+            write_record(0);
+        } else {
+            write_record(ALL_TASKS & !HEARTBEATS.load(Ordering::Relaxed));
+        }
+    }
+}
+
+/// Tasks which stalled before a watchdog reset.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StalledTasks(u8);
+
+impl StalledTasks {
+    pub fn contains(&self, task: Task) -> bool {
+        self.0 & task as u8 != 0
+    }
+}
+
+impl fmt::Display for StalledTasks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut separator = "";
+
+        for task in TASKS.iter().filter(|task| self.contains(**task)) {
+            write!(f, "{}{:?}", separator, task)?;
+            separator = ", ";
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the stalled tasks if the last reset was caused by the watchdog, and clears the reset
+/// flags. Has to be called once during start up, before the watchdog gets started.
+pub fn take_reset_reason() -> Option<StalledTasks> {
+    let rcc = unsafe { &*pac::RCC::ptr() };
+
+    enable_backup_ram();
+
+    let watchdog_reset = rcc.rsr.read().bits() & RSR_IWDG1RSTF != 0;
+    rcc.rsr
+        .modify(|r, w| unsafe { w.bits(r.bits() | RSR_RMVF) });
+
+    let record = read_record();
+    write_record(0);
+
+    if !watchdog_reset {
+        return None;
+    }
+
+    // without a record, the control task stalled before it could write one
+    Some(StalledTasks(match record {
+        Some(stalled) if stalled != 0 => stalled,
+        _ => Task::Control as u8,
+    }))
+}
+
+fn enable_backup_ram() {
+    let rcc = unsafe { &*pac::RCC::ptr() };
+    let pwr = unsafe { &*pac::PWR::ptr() };
+
+    rcc.ahb4enr.modify(|_, w| w.bkpramen().set_bit());
+    pwr.cr1.modify(|_, w| w.dbp().set_bit());
+}
+
+fn write_record(stalled: u8) {
+    let record = BACKUP_RAM_ADDRESS as *mut u32;
+
+    unsafe {
+        record.write_volatile(RECORD_MAGIC);
+        record.add(1).write_volatile(stalled as u32);
+
+        // a reset drops whatever still sits in the cache
+        cortex_m::Peripherals::steal()
+            .SCB
+            .clean_dcache_by_address(BACKUP_RAM_ADDRESS, 8);
+    }
+}
+
+fn read_record() -> Option<u8> {
+    let record = BACKUP_RAM_ADDRESS as *const u32;
+
+    unsafe {
+        if record.read_volatile() == RECORD_MAGIC {
+            Some(record.add(1).read_volatile() as u8)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use core::ops::Range;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use sitira_core::waveform::{Peak, PeakPyramid, BLOCK_LENGTH};
+
+use crate::sdram::{self, WAVEFORM_BUFFER};
+use crate::slots::{self, BUFFER_COUNT, SLOTS, SLOT_COUNT, SLOT_LENGTH};
+
+/// Layout of the pyramid of every buffer
+pub const PYRAMID: PeakPyramid = PeakPyramid::new(SLOT_LENGTH);
+/// Samples summarized per step, keeps the idle task responsive
+const SAMPLES_PER_STEP: usize = 64 * BLOCK_LENGTH;
+/// Bits of a mark which hold the samples its pyramid covers, enough for a slot of 4M samples. The
+/// bits above count how often the pyramid has been outdated.
+const COVERED_BITS: u32 = 22;
+const COVERED_MASK: usize = (1 << COVERED_BITS) - 1;
+
+/// Min/max pyramids of all SDRAM buffers, which the waveform gets drawn from.
+///
+/// Every buffer has a pyramid of its own, so it stays valid when an undo maps a slot to another
+/// buffer. The idle task builds them up to the length of their slots and follows a recording as
+/// it grows. Everything else which writes into a buffer marks its pyramid as outdated from there
+/// on. The marks are stored in atomics, so every task can do that without locking, and a build
+/// step which got outdated meanwhile does not count.
+pub struct WaveformCache {
+    /// Samples of every buffer which its pyramid covers, together with a count of invalidations
+    marks: [AtomicUsize; BUFFER_COUNT],
+}
+
+impl WaveformCache {
+    pub const fn new() -> Self {
+        WaveformCache {
+            marks: [
+                AtomicUsize::new(0),
+                AtomicUsize::new(0),
+                AtomicUsize::new(0),
+                AtomicUsize::new(0),
+                AtomicUsize::new(0),
+            ],
+        }
+    }
+
+    /// Marks the pyramid of `buffer` as outdated from sample `position` on.
+    pub fn invalidate(&self, buffer: usize, position: usize) {
+        let _ = self.marks[buffer % BUFFER_COUNT].fetch_update(
+            Ordering::AcqRel,
+            Ordering::Relaxed,
+            |mark| {
+                let count = (mark >> COVERED_BITS).wrapping_add(1);
+
+                Some(count << COVERED_BITS | (mark & COVERED_MASK).min(position))
+            },
+        );
+    }
+
+    /// Summarizes the next samples of a slot whose pyramid does not cover it, `active_length`
+    /// is the length of the active slot. Returns `false` if all pyramids are up to date.
+    pub fn build(&self, active_length: usize) -> bool {
+        for slot in 0..SLOT_COUNT {
+            let buffer = SLOTS.get_buffer(slot);
+            let length = if slot == SLOTS.get_active() {
+                active_length
+            } else {
+                SLOTS.get_length(slot)
+            }
+            .min(SLOT_LENGTH);
+
+            let mark = self.marks[buffer].load(Ordering::Acquire);
+            let built = mark & COVERED_MASK;
+
+            if built == length {
+                continue;
+            }
+
+            // a new take or a shorter loop start over in the block the slot ends in
+            let start = built.min(length) / BLOCK_LENGTH * BLOCK_LENGTH;
+            let end = (start + SAMPLES_PER_STEP).min(length);
+
+            // SAFETY: the pyramids are only written here. The display may read the levels above
+            // while they get merged, which at worst draws a column off for a frame.
+            let (peaks, samples) = match (
+                unsafe { get_pyramid_mut(buffer) },
+                sdram::get_slice::<f32>(buffer * SLOT_LENGTH, end),
+            ) {
+                (Some(peaks), Some(samples)) => (peaks, samples),
+                _ => return false,
+            };
+
+            PYRAMID.update(peaks, samples, start..end);
+
+            // a write meanwhile has outdated the pyramid, the step then has to be done again
+            let _ = self.marks[buffer].compare_exchange(
+                mark,
+                mark & !COVERED_MASK | end,
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            );
+
+            return true;
+        }
+
+        false
+    }
+
+    /// Fills `columns` with the peaks of `range` of a slot. Returns `false` while its pyramid does
+    /// not cover the range yet.
+    pub fn get_peaks(&self, slot: usize, range: Range<usize>, columns: &mut [Peak]) -> bool {
+        let buffer = SLOTS.get_buffer(slot);
+
+        if self.marks[buffer].load(Ordering::Acquire) & COVERED_MASK < range.end {
+            return false;
+        }
+
+        match (get_pyramid(buffer), slots::get_slice(slot, range.end)) {
+            (Some(peaks), Some(samples)) => {
+                PYRAMID.query(peaks, samples, range, columns);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+fn get_pyramid(buffer: usize) -> Option<&'static [Peak]> {
+    WAVEFORM_BUFFER
+        .get_slice::<Peak>()
+        .and_then(|peaks| peaks.get(buffer * PYRAMID.len()..(buffer + 1) * PYRAMID.len()))
+}
+
+/// ## Safety
+/// Same as `sdram::get_slice_mut()`, only the idle task may write the pyramids.
+unsafe fn get_pyramid_mut(buffer: usize) -> Option<&'static mut [Peak]> {
+    sdram::get_slice_mut::<Peak>(
+        WAVEFORM_BUFFER.offset / core::mem::size_of::<Peak>() + buffer * PYRAMID.len(),
+        PYRAMID.len(),
+    )
+}
+
+/// Pyramids of all buffers
+pub static WAVEFORMS: WaveformCache = WaveformCache::new();